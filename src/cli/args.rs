@@ -1,5 +1,18 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
 use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::compressors::zip::MAX_COMMENT_LEN;
+use crate::compressors::CommandRegistry;
+use crate::core::config::{Durability, LevelPreset, XzTuning};
+use crate::operations::{format_extension, recovery, DEFAULT_TOP};
+use crate::utils::fs::validate_name_template;
+use crate::utils::timestamp::{parse_time_bound, validate_timestamp_format};
+use crate::utils::{
+    is_download_url, is_remote_url, is_sha256_hex, parse_log_level, parse_size, BackupMode,
+    FileFilter, IoPriority, PathTransform, RateLimit, RemoteTarget, LOG_LEVELS,
+};
 
 const LONG_ABOUT: &str = concat!(
     "Just Compress Zip - A unified compression utility\n\n",
@@ -16,7 +29,7 @@ const LONG_ABOUT: &str = concat!(
     env!("CARGO_PKG_REPOSITORY"),
     "\n\n",
     "A command-line tool that provides a consistent interface for multiple\n",
-    "compression formats including GZIP, BZIP2, XZ, ZIP, TAR, and compound\n",
+    "compression formats including GZIP, BZIP2, XZ, ZIP, TAR, CPIO, AR, and compound\n",
     "formats (TGZ, TBZ2, TXZ)."
 );
 
@@ -27,9 +40,35 @@ COMPRESSION COMMANDS:
   xz      XZ compression (.xz)
   zip     ZIP compression (.zip)
   tar     TAR archive (.tar)
+  cpio    CPIO archive, newc format (.cpio)
+  ar      AR archive (.ar, extracts .a too)
   tgz     TAR + GZIP (.tar.gz)
   tbz2    TAR + BZIP2 (.tar.bz2)
   txz     TAR + XZ (.tar.xz)
+  dedup   Deduplicating archive: identical chunks stored once (.jczd)
+  auto    Per input: xz, gzip, or nothing for already-compressed data
+          (directories: txz, tgz or tar)
+  Others registered in ~/.config/jcz/compressors.toml (or --compressors)
+
+SUBCOMMANDS:
+  identify      Report what jcz thinks a file is (reads only the header)
+  tier          Compress rarely-accessed files in place (age rules from TOML)
+  restore-file  Bring a tiered file back to its original path
+  watch         Compress new files in a directory as they appear
+  merge         Combine archives and directories into one archive
+  rotate        Archive rotated logs into a dated archive, verify it, delete them
+  cleanup       Remove temp directories left behind by crashed or killed runs
+  grep          Search the files inside archives without extracting them
+  tree          Show an archive's contents as a tree with directory sizes
+  verify        Check archives: every layer, CRCs, authentication and integrity tags
+  benchmark     Compare ratio and speed of each format/level on a sample
+  estimate      Predict the compressed size of inputs without writing output
+  analyze       Report duplicate files, the largest files and sizes per extension
+  keygen        Generate an RSA or X25519 key pair for --encrypt-key
+  gen-keyfile   Create a random keyfile for --encrypt-keyfile
+  encrypt       Encrypt files to .jcze without compressing them
+  decrypt       Decrypt .jcze files without decompressing them
+  reencrypt     Rotate the key or password of .jcze files without recompressing
 
 EXAMPLES:
   # Compress a file with GZIP
@@ -38,18 +77,101 @@ EXAMPLES:
   # Compress with BZIP2 at level 9
   jcz -c bzip2 -l 9 file.txt
 
+  # Strongest level of the format, or xz's extreme mode
+  jcz -c txz -l best directory/
+  jcz -c xz -l 9e file.txt
+
+  # Tune xz for archival: extreme mode and a larger dictionary
+  jcz -c txz --extreme --lzma2 dict=256M,lc=4 dataset/
+
   # Create compressed archive
   jcz -c tgz directory/
 
   # Compress with timestamp
   jcz -c gzip -t 2 file.txt
 
+  # Compress with a custom UTC timestamp (file_2024-05-01_1230.gz)
+  jcz -c gzip --timestamp-format '%Y-%m-%d_%H%M' --utc file.txt
+
+  # Name outputs after a template (file-myhost-20240501.gz)
+  jcz -c gzip --name-template '{stem}-{hostname}-{date}.{ext}' file.txt
+
   # Compress and move to directory
   jcz -c gzip -C /backups/ file.txt
 
+  # Compress straight to S3, keeping no local copy (--upload keeps it)
+  jcz -c txz -C s3://backups/nightly/ home/
+
+  # Download and extract a release, checking its published hash
+  jcz -d --sha256 9f86d08...0a08 -C /opt/ https://example.com/tool-1.2.tar.gz
+
+  # Let jcz pick per input (skips JPEGs, videos and existing archives)
+  jcz -c auto logs/ photos/ report.csv
+
   # Collect multiple files into archive
   jcz -c tgz -a myarchive file1.txt file2.txt dir/
 
+  # Collect files under their relative paths (src/a/main.c, src/b/main.c)
+  jcz -c tgz -A sources --keep-paths src/a/main.c src/b/main.c
+
+  # Add new and changed files to an existing backup.tar.gz
+  jcz -c tgz -a backup -u file1 file2
+
+  # Store /var/www as site/ and /etc/nginx as config/nginx/
+  jcz -c tgz -A release --transform 's#^/var/www#site#' --transform '/etc=config' /var/www /etc/nginx
+
+  # One archive per project folder (proj1.tar.gz, proj2.tar.gz)
+  jcz -c tgz --group-by-dir proj1/* proj2/*
+
+  # Show sizes, compression ratio and throughput per file and in total
+  jcz -c xz --stats logs/*.log
+
+  # List each member as it is archived or extracted, like tar -v
+  jcz -c tgz -v project/
+
+  # Errors only, or a debug log kept in a file
+  jcz -q -d backups/*.tar.gz
+  jcz --log-level debug --log-file jcz.log -c xz data.bin
+
+  # Record the outcome of every file for a wrapper script
+  jcz -c xz --report report.json logs/*.log
+
+  # Upload each archive once it is written
+  jcz -c txz --post-cmd 'rclone copy \"$JCZ_OUTPUT\" remote:backups' projects/*
+
+  # Abort a large batch at the first broken archive
+  jcz -d --fail-fast incoming/*.tar.gz
+
+  # Ride out NFS hiccups: retry a failing file up to 3 times (1s, 2s, 4s apart)
+  jcz -c txz --retries 3 -C /mnt/nfs/backups projects/*
+
+  # Leave cores free on a shared build server
+  jcz -c txz -j 2 build-logs/*
+
+  # pigz, pbzip2/lbzip2 and pixz are used when installed; opt out with
+  jcz -c tgz --no-parallel-tools project/
+
+  # Unpack a large tar straight into place, without a scratch copy
+  jcz -d --direct -C /srv/data dataset.tar
+
+  # Weekly full backup, then daily archives of what changed since
+  jcz -c tgz --listed-incremental home.snar -t 1 home/
+  # Restore: the full archive, then each incremental in order
+  jcz -d --incremental -C /restore home_20260105.tar.gz home_20260106.tar.gz
+
+  # Refuse .xz files that would need more than 256 MiB to decompress
+  jcz -d --memory-limit 256M untrusted.tar.xz
+
+  # Unpack a large archive with scratch space on a roomy disk
+  jcz -d --tempdir /data/scratch dataset.tar.xz
+
+  # Background backup that stays out of the way of interactive work
+  jcz -c txz --nice --ionice --limit-rate 50M -a home-backup ~/
+
+  # Use an external tool registered as [compressor.lrzip] in compressors.toml
+  jcz -c lrzip big.iso
+  jcz -d big.iso.lrz
+
   # Decompress any supported format
   jcz -d archive.tar.gz
 
@@ -59,9 +181,118 @@ EXAMPLES:
   # Decompress multiple files
   jcz -d file1.gz file2.bz2 file3.xz
 
+  # Decompress a file whose name does not say what it is (gives data)
+  jcz -d --format xz data.bin
+
+  # Keep what would be replaced as app.conf~, or app.conf.~1~, app.conf.~2~, ...
+  jcz -d --backup config.tar.gz
+  jcz -d --backup=numbered config.tar.gz
+
+  # Always extract into a directory of its own: out/release/, or out/v2/
+  jcz -d release.zip -C out/ --extract-dir
+  jcz -d release.zip -C out/ --extract-dir=v2
+
+  # Unpack a .deb or .rpm package into a directory
+  jcz -d hello_1.0_amd64.deb
+
+  # Japanese names from a zip made on Windows (needs bsdtar)
+  jcz -d --zip-encoding cp932 legacy.zip
+
+  # Stamp build metadata into the zip comment (shown by identify and tree)
+  jcz -c zip --comment 'nightly build 2024-05-01' dist/
+
   # Force overwrite without prompting
   jcz -d -f archive.tar.gz
 
+  # Keep hashes and a manifest in archive.tar.gz.jcz.json (archive stays plain)
+  jcz -c tgz --sidecar-metadata project/
+
+  # Long-term backup with a MANIFEST.sha256 inside, checked by jcz verify
+  jcz -c tgz --manifest -a photos-2024 2024/
+  jcz verify photos-2024.tar.gz
+
+  # Back up a system directory with xattrs (file capabilities) and ACLs
+  jcz -c txz --xattrs --acls /usr/local/bin
+  jcz -d --xattrs --acls bin.tar.xz -C /usr/local/
+
+  # Archive the files symlinks point to instead of the links themselves
+  jcz -c tgz --dereference deploy/
+
+  # Skip files too small to be worth compressing and leave huge ones to another job
+  jcz -c xz --min-size 4K --max-size 2G /var/log/*
+
+  # Archive last year's reports, leaving anything touched in the last 30 days
+  jcz -c txz -A reports-2024 --newer-mtime 2024-01-01 --older-than 30d reports/
+
+  # Keep the original name out of the gzip header
+  jcz -c gzip --no-name secret-plans.txt
+
+  # Backups that rsync can delta-transfer
+  jcz -c tgz --rsyncable home/
+
+  # Bit-identical archive for a reproducible build
+  SOURCE_DATE_EPOCH=1700000000 jcz -c txz --reproducible dist/
+
+  # Guarantee extracted files are on disk before exiting (backup restores)
+  jcz -d --durability full backup.tar.gz
+
+  # Same for compressed outputs (encrypted ones are synced by default)
+  jcz -c txz --fsync db-dump.sql
+
+  # Identify a mystery file without decompressing it
+  jcz identify unknown.bin
+
+  # Compress (and encrypt) logs as they are rotated into a directory
+  jcz watch --on-create -c xz --pattern '*.log.1' --encrypt-key ops.pub.pem /var/log/myapp/
+
+  # Combine archives and a directory into one, failing on path collisions
+  jcz merge all.tar.xz part1.tar.gz part2.zip extra/
+
+  # Convert a zip to a tar.gz, keeping permissions, mtimes and symlinks
+  jcz merge release.tar.gz release.zip
+
+  # Move rotated logs into this month's archive once it checks out
+  jcz rotate --pattern 'app.log.*' --into '/archive/logs-%Y%m.tar.xz' /var/log/myapp/
+
+  # See what crashed runs left in /data and the temp dir, then remove it
+  jcz cleanup --dry-run /data
+  jcz cleanup /data
+
+  # Search the logs inside an archive without extracting it (member:line)
+  jcz grep -n --member '*.log' 'timeout' logs.tar.xz
+
+  # Make a tgz that jcz cat reads single files from without decompressing it all
+  jcz -c tgz --indexed -a dataset dataset/
+  jcz cat dataset.tar.gz dataset/labels.csv
+
+  # See what an archive holds, and where the bulk of it is
+  jcz tree backup.tgz
+
+  # Check every layer of an encrypted backup down to the original files
+  jcz verify --decrypt-key private.pem backup.tar.gz.jcze
+
+  # Nightly check of a whole backup directory: a pass/fail table, exit 0 only if all pass
+  jcz verify -j 4 --decrypt-keyfile backup.key /backups/*
+
+  # Pick -c/-l for your data: ratio vs. speed of every format and level
+  jcz benchmark sample.log
+
+  # Predict the size of a txz at level 9 from a 64 MiB sample, writing nothing
+  jcz estimate -c txz -l 9 dataset/
+
+  # Find duplicate and oversized files to prune before archiving
+  jcz analyze --top 20 photos/
+
+  # Keep 5% recovery data beside a cold-storage archive, and repair it later
+  jcz -c txz --recovery 5% -a backup photos/
+  jcz repair backup.tar.xz
+
+  # Extract what is still readable from a damaged archive, listing what was lost
+  jcz -d --salvage old-backup.tar.gz
+
+  # Skip CRC32 checks of gzip and zip data, for speed or to keep mismatched members
+  jcz -d --no-verify logs.tar.gz
+
 ENCRYPTION:
   # Encrypt with password
   jcz -c gzip -e file.txt
@@ -75,11 +306,66 @@ ENCRYPTION:
   # Decrypt with RSA private key
   jcz -d --decrypt-key private.pem file.txt.gz.jcze
 
+  # Smaller, faster X25519 keys work with the same options
+  jcz keygen --type x25519 backup
+  jcz -c tgz --encrypt-key backup.pub.pem project/
+  jcz -d --decrypt-key backup.pem project.tar.gz.jcze
+
   # Decrypt and remove encrypted file
   jcz -d --remove-encrypted file.txt.gz.jcze
 
+  # Same, keeping the encrypted file in the trash
+  jcz -d --remove-encrypted --trash file.txt.gz.jcze
+
+  # Unattended encryption with a keyfile instead of a password
+  jcz gen-keyfile secret.bin
+  jcz -c txz --encrypt-keyfile secret.bin logs/
+  jcz -d --decrypt-keyfile secret.bin logs.tar.xz.jcze
+
+  # Password from the OS keychain (asked and stored on first use)
+  jcz -c tgz -e --use-keyring backups project/
+  jcz -d --use-keyring backups project.tar.gz.jcze
+
+  # Standard .gpg for recipients without jcz (gpg -d opens it; needs gpg)
+  jcz -c tgz --encrypt-gpg alice@example.com project/
+  jcz -d project.tar.gz.gpg
+
+  # Hide exact file sizes by padding the payload to a multiple of 4 KiB
+  jcz -c gzip -e --pad-to 4K report.pdf
+
+  # Encrypt a huge image with larger I/O buffers (memory use stays flat)
+  jcz encrypt --buffer-size 8M --encrypt-keyfile secret.bin disk.img
+
+  # Split the key among 5 people, any 3 of whom can decrypt
+  jcz -c tgz --split-key 3/5 project/
+  jcz -d --share a.share --share b.share --share c.share project.tar.gz.jcze
+
+  # Only the crypto: encrypt any file as is, and decrypt it again
+  jcz encrypt disk.img
+  jcz decrypt disk.img.jcze
+
+  # Decrypt an encrypted archive but keep it compressed
+  jcz -d --no-decompress backup.tar.gz.jcze
+
+  # Rotate backups to a new RSA key (only the wrapped key is rewritten)
+  jcz reencrypt --decrypt-key old.pem --encrypt-key new.pem backups/*.jcze
+
+  # Change the password of password-encrypted files
+  jcz reencrypt backups/*.jcze
+
+  # Password-protected zip that 7-Zip/WinZip can open (AES-256)
+  jcz -c zip --zip-encrypt report.pdf
+  jcz -d --zip-encrypt report.pdf.zip
+
 ENVIRONMENT VARIABLES:
-  JCDBG    Control logging verbosity (error, warn, info, debug)
+  JCDBG    Control logging verbosity (error, warn, info, debug); -q and --log-level override it
+  NO_COLOR Disable colors on a terminal (same as --no-color)
+  TMPDIR   Directory for extraction scratch space (overridden by --tempdir)
+
+EXIT CODES:
+  0  Success                  3  Every input failed
+  1  Usage error              4  Wrong password or key
+  2  Some inputs failed       5  Integrity check failed
 
 For more information, visit: https://github.com/saimizi/jc";
 
@@ -90,7 +376,12 @@ For more information, visit: https://github.com/saimizi/jc";
 #[command(about = "Just Compress Zip - A unified compression utility")]
 #[command(long_about = LONG_ABOUT)]
 #[command(after_help = AFTER_HELP)]
+#[command(subcommand_negates_reqs = true)]
 pub struct CliArgs {
+    /// Subcommand (when given, the compression options below are ignored)
+    #[command(subcommand)]
+    pub subcommand: Option<Commands>,
+
     /// Decompress mode
     #[arg(short = 'd', long)]
     pub decompress: bool,
@@ -99,18 +390,146 @@ pub struct CliArgs {
     #[arg(short = 'f', long)]
     pub force: bool,
 
+    /// Rename outputs that would be replaced to NAME~ instead; --backup=numbered for NAME.~N~, or --backup=SUFFIX
+    #[arg(long, value_name = "CONTROL", num_args = 0..=1, require_equals = true,
+          default_missing_value = "")]
+    pub backup: Option<String>,
+
+    /// Print sizes, ratio, throughput and time per file and in total
+    #[arg(long)]
+    pub stats: bool,
+
+    /// List each tar and zip member as it is added or extracted
+    #[arg(short = 'v', long)]
+    pub verbose: bool,
+
+    /// Only log errors (same as --log-level error)
+    #[arg(short = 'q', long, global = true, conflicts_with = "log_level")]
+    pub quiet: bool,
+
+    /// Log verbosity: off, error, warn, info, debug or trace (overrides JCDBG)
+    #[arg(long, global = true, value_name = "LEVEL", value_parser = LOG_LEVELS)]
+    pub log_level: Option<String>,
+
+    /// Append log messages to FILE instead of printing them to stderr
+    #[arg(long, global = true, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Plain output: no colors even on a terminal (also set by NO_COLOR)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Size of read and write buffers, 4K to 1G [default: 1M]
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_buffer_size)]
+    pub buffer_size: Option<usize>,
+
+    /// Shell command run before each file, with JCZ_INPUT set
+    #[arg(long, value_name = "CMD")]
+    pub pre_cmd: Option<String>,
+
+    /// Shell command run after each file, with JCZ_INPUT, JCZ_OUTPUT and JCZ_STATUS set
+    #[arg(long, value_name = "CMD")]
+    pub post_cmd: Option<String>,
+
+    /// Shell command run before the batch, with JCZ_INPUTS and JCZ_TOTAL set
+    #[arg(long, value_name = "CMD")]
+    pub pre_batch_cmd: Option<String>,
+
+    /// Shell command run after the batch, with JCZ_STATUS, JCZ_TOTAL and JCZ_FAILED set
+    #[arg(long, value_name = "CMD")]
+    pub post_batch_cmd: Option<String>,
+
+    /// TOML file with [hooks] entries (default: ~/.config/jcz/hooks.toml)
+    #[arg(long, value_name = "FILE")]
+    pub hooks: Option<PathBuf>,
+
+    /// Write a JSON record of every input (status, output, sizes, time, sha256, error) to FILE
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Stop starting new files after the first failure
+    #[arg(long, conflicts_with = "keep_going")]
+    pub fail_fast: bool,
+
+    /// Process every file even when some fail [default]
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Retry a file, upload or download up to N times after transient I/O or network failures
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retries: u32,
+
+    /// Seconds to wait before the first retry, doubled before each further one
+    #[arg(long, value_name = "SECS", default_value_t = 1.0)]
+    pub retry_delay: f64,
+
+    /// Use at most N threads: files in parallel plus threads inside xz, multi-stream bzip2 and zip extraction [default: all cores]
+    #[arg(short = 'j', long, value_name = "N")]
+    pub threads: Option<usize>,
+
+    /// Run at lower CPU priority; --nice=N for a niceness other than 10
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true,
+          default_missing_value = "10", allow_negative_numbers = true)]
+    pub nice: Option<i32>,
+
+    /// Only use the disk when it is otherwise idle; --ionice=best-effort[:0-7] for another class (Linux)
+    #[arg(long, value_name = "CLASS", num_args = 0..=1, require_equals = true,
+          default_missing_value = "idle")]
+    pub ionice: Option<String>,
+
+    /// Extract plain tar and zip archives straight into the destination, skipping scratch space
+    #[arg(long)]
+    pub direct: bool,
+
+    /// Run gzip, bzip2 and xz even when pigz, pbzip2/lbzip2 or pixz is installed
+    #[arg(long)]
+    pub no_parallel_tools: bool,
+
+    /// Refuse to decompress .xz inputs needing more than SIZE of memory (K/M/G suffixes)
+    #[arg(long, value_name = "SIZE")]
+    pub memory_limit: Option<String>,
+
+    /// Directory for extraction scratch space [default: $TMPDIR, else next to the output]
+    #[arg(long, value_name = "DIR")]
+    pub tempdir: Option<PathBuf>,
+
+    /// Cap the data compressors read and write, in bytes per second (K/M/G suffixes, e.g. 50M)
+    #[arg(long, value_name = "RATE")]
+    pub limit_rate: Option<String>,
+
     /// Compression command (see COMPRESSION COMMANDS below)
     #[arg(short = 'c', long, default_value = "tgz")]
     pub command: String,
 
-    /// Compression level (1-9)
-    #[arg(short = 'l', long, default_value = "6")]
-    pub level: u8,
+    /// Compression level: a number in the format's range (gzip 1-9, xz 0-9, ...), or fast, default or best; append e for xz's extreme mode (9e)
+    #[arg(short = 'l', long, value_name = "LEVEL", default_value = "default")]
+    pub level: String,
+
+    /// xz extreme mode: slower, for a little more compression (same as an e level suffix)
+    #[arg(long)]
+    pub extreme: bool,
 
-    /// Move output to specified directory (works for both compression and decompression)
+    /// xz LZMA2 options over the level's preset, e.g. dict=64M,lc=4,lp=0,pb=0
+    #[arg(long, value_name = "OPTIONS")]
+    pub lzma2: Option<String>,
+
+    /// Move output to specified directory (works for both compression and decompression),
+    /// or upload it to a URL as --upload does, keeping no local copy
     #[arg(short = 'C', long)]
     pub move_to: Option<PathBuf>,
 
+    /// Upload each output to URL (s3://bucket/prefix/, sftp://host/dir/ or http(s)://) and keep it
+    #[arg(long, value_name = "URL")]
+    pub upload: Option<String>,
+
+    /// Write Reed-Solomon recovery data (<output>.jczr) able to rebuild PCT of each output, e.g. 5%
+    #[arg(long, value_name = "PCT")]
+    pub recovery: Option<String>,
+
+    /// Expected SHA-256 of a downloaded URL input (-d); repeat once per URL, in order
+    #[arg(long, value_name = "HEX")]
+    pub sha256: Vec<String>,
+
     /// Collect files into archive (with parent directory)
     #[arg(short = 'a', long)]
     pub collect: Option<String>,
@@ -119,10 +538,35 @@ pub struct CliArgs {
     #[arg(short = 'A', long)]
     pub collect_flat: Option<String>,
 
+    /// Collect files into one archive per input subdirectory, named after it
+    #[arg(long)]
+    pub group_by_dir: bool,
+
+    /// Store collected inputs under their relative paths instead of their basenames
+    #[arg(long)]
+    pub keep_paths: bool,
+
+    /// Add inputs newer than their copies in an existing -a/-A archive, keeping the rest
+    #[arg(short = 'u', long)]
+    pub update: bool,
+
     /// Timestamp option: 0=none, 1=date, 2=datetime, 3=nanoseconds
     #[arg(short = 't', long, default_value = "0")]
     pub timestamp: u8,
 
+    /// Timestamp output filenames with a strftime pattern, e.g. '%Y-%m-%d_%H%M'
+    #[arg(long, value_name = "PATTERN", conflicts_with = "timestamp")]
+    pub timestamp_format: Option<String>,
+
+    /// Format timestamps in UTC instead of local time
+    #[arg(long)]
+    pub utc: bool,
+
+    /// Output filename template, e.g. '{stem}-{hostname}-{date}.{ext}'
+    /// (variables: stem, name, ext, hostname, date, timestamp, level)
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+
     /// Input files or directories
     #[arg(required = true)]
     pub inputs: Vec<PathBuf>,
@@ -131,90 +575,1180 @@ pub struct CliArgs {
     #[arg(long = "encrypt-password", short = 'e')]
     pub encrypt_password: bool,
 
-    /// RSA public key file for encryption (encrypts the symmetric key)
+    /// RSA or X25519 public key file for encryption
     #[arg(long = "encrypt-key")]
     pub encrypt_key: Option<PathBuf>,
 
-    /// RSA private key file for decryption (decrypts the symmetric key)
+    /// RSA or X25519 private key file for decryption
     #[arg(long = "decrypt-key")]
     pub decrypt_key: Option<PathBuf>,
 
+    /// Split the key into N share files, any K of which decrypt (e.g. 3/5)
+    #[arg(long, value_name = "K/N")]
+    pub split_key: Option<String>,
+
+    /// Key share file of a split-key archive (repeat for each share)
+    #[arg(long = "share", value_name = "FILE")]
+    pub shares: Vec<PathBuf>,
+
+    /// Encrypt with the 32-byte key in this file (see `jcz gen-keyfile`)
+    #[arg(long = "encrypt-keyfile", value_name = "FILE")]
+    pub encrypt_keyfile: Option<PathBuf>,
+
+    /// Decrypt with the 32-byte key in this file
+    #[arg(long = "decrypt-keyfile", value_name = "FILE")]
+    pub decrypt_keyfile: Option<PathBuf>,
+
+    /// Pad encrypted payloads to a multiple of SIZE so sizes leak less (K/M/G suffixes)
+    #[arg(long, value_name = "SIZE")]
+    pub pad_to: Option<String>,
+
+    /// Encrypt to this GnuPG key ID as a standard .gpg file (repeat for more recipients)
+    #[arg(long = "encrypt-gpg", value_name = "KEYID")]
+    pub encrypt_gpg: Vec<String>,
+
+    /// Take the -e / -d password from the OS keychain entry NAME (stored on first -e)
+    #[arg(long, value_name = "NAME")]
+    pub use_keyring: Option<String>,
+
+    /// Write or read standard AES-256 encrypted zips (prompts for the password)
+    #[arg(long)]
+    pub zip_encrypt: bool,
+
+    /// Archive comment to write to zips, e.g. build metadata (up to 64 KiB)
+    #[arg(long, value_name = "TEXT")]
+    pub comment: Option<String>,
+
+    /// Decode names in legacy zips (no UTF-8 flag) from this charset, e.g. cp932
+    #[arg(long, value_name = "CHARSET")]
+    pub zip_encoding: Option<String>,
+
     /// Remove encrypted file after successful decryption
     #[arg(long = "remove-encrypted")]
     pub remove_encrypted: bool,
+
+    /// With --remove-encrypted, move encrypted files to the OS trash instead of deleting them
+    #[arg(long, requires = "remove_encrypted")]
+    pub trash: bool,
+
+    /// Only decrypt .jcze inputs, leaving what they contain compressed
+    #[arg(long)]
+    pub no_decompress: bool,
+
+    /// Write a JSON metadata sidecar (<archive>.jcz.json) next to each archive
+    #[arg(long)]
+    pub sidecar_metadata: bool,
+
+    /// Embed a MANIFEST.sha256 (hashes and sizes) in -a/-A collection archives
+    #[arg(long)]
+    pub manifest: bool,
+
+    /// Write tgz archives in seekable blocks with an index (<archive>.jcz.idx) for jcz cat
+    #[arg(long)]
+    pub indexed: bool,
+
+    /// Archive only what changed since the snapshot FILE was written, then update it (tar formats)
+    #[arg(long, value_name = "FILE")]
+    pub listed_incremental: Option<PathBuf>,
+
+    /// Rewrite the paths inputs are stored under, e.g. 's#^/var/www#site#' or '/var/www=site' (repeatable; tar formats)
+    #[arg(long, value_name = "EXPR")]
+    pub transform: Vec<String>,
+
+    /// Apply tar archives as incrementals, in order: merge into the destination and delete what they record as removed
+    #[arg(long)]
+    pub incremental: bool,
+
+    /// With -d, skip damaged gzip members and tar entries, extract the rest and report what was lost
+    #[arg(long)]
+    pub salvage: bool,
+
+    /// With -d, skip the CRC32 checks of gzip and zip data, keeping members that fail them
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// With -d, take inputs to be in FORMAT whatever their names say: gzip, xz, tgz, zip, deb, ... or a registered compressor
+    #[arg(long, value_name = "FORMAT")]
+    pub format: Option<String>,
+
+    /// With -d, extract each archive into a new directory named after it, or --extract-dir=NAME
+    #[arg(long, visible_alias = "mkdir", value_name = "NAME", num_args = 0..=1,
+          require_equals = true, default_missing_value = "")]
+    pub extract_dir: Option<String>,
+
+    /// Store/restore extended attributes (incl. security.capability) in tar archives
+    #[arg(long)]
+    pub xattrs: bool,
+
+    /// Store/restore POSIX ACLs in tar archives
+    #[arg(long)]
+    pub acls: bool,
+
+    /// Archive the files and directories symlinks point to
+    #[arg(long, overrides_with = "no_dereference")]
+    pub dereference: bool,
+
+    /// Archive symlinks as links (default)
+    #[arg(long, overrides_with = "dereference")]
+    pub no_dereference: bool,
+
+    /// Skip files smaller than SIZE (e.g. 4K), as inputs and inside directories
+    #[arg(long, value_name = "SIZE")]
+    pub min_size: Option<String>,
+
+    /// Skip files larger than SIZE (e.g. 2G), as inputs and inside directories
+    #[arg(long, value_name = "SIZE")]
+    pub max_size: Option<String>,
+
+    /// Skip files last modified before DATE (2024-01-01, RFC 3339, @EPOCH) or more than an age (30d) ago
+    #[arg(long, value_name = "DATE")]
+    pub newer_mtime: Option<String>,
+
+    /// Skip files modified less than AGE ago (30d, 12h, 2w) or at or after a DATE
+    #[arg(long, value_name = "AGE")]
+    pub older_than: Option<String>,
+
+    /// Store the original name and mtime in gzip headers and restore the name (default)
+    #[arg(long, overrides_with = "no_name")]
+    pub name: bool,
+
+    /// Neither store nor restore the original name in gzip headers
+    #[arg(long, overrides_with = "name")]
+    pub no_name: bool,
+
+    /// Make gzip (or a registered compressor that supports it) output rsync-friendly
+    #[arg(long)]
+    pub rsyncable: bool,
+
+    /// Bit-identical archives for identical inputs (honors SOURCE_DATE_EPOCH)
+    #[arg(long)]
+    pub reproducible: bool,
+
+    /// Fsync policy for extracted files: full, default, or lazy
+    #[arg(long, default_value = "default", value_name = "MODE")]
+    pub durability: String,
+
+    /// Fsync compressed outputs and their directories before reporting
+    /// success (the default for encrypted outputs)
+    #[arg(long, overrides_with = "no_fsync")]
+    pub fsync: bool,
+
+    /// Don't fsync compressed or encrypted outputs
+    #[arg(long, overrides_with = "fsync")]
+    pub no_fsync: bool,
+
+    /// TOML file registering external compressors as commands
+    /// (default: ~/.config/jcz/compressors.toml)
+    #[arg(long, value_name = "FILE")]
+    pub compressors: Option<PathBuf>,
+}
+
+/// Subcommands that operate outside the compress/decompress workflow
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Identify files by reading only their first few KB
+    Identify {
+        /// Files to identify
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Compress rarely-accessed files in place according to age rules
+    Tier {
+        /// TOML file with [[rule]] tables
+        #[arg(long, value_name = "FILE")]
+        rules: PathBuf,
+
+        /// Show what would be tiered without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Directories to scan
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+    },
+
+    /// Bring tiered files back to their original paths
+    RestoreFile {
+        /// Original paths (or their compressed forms)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Compress files as they appear in a directory (runs until stopped)
+    Watch {
+        /// Compress files created in or moved into DIR
+        #[arg(long, required = true)]
+        on_create: bool,
+
+        /// Compression command
+        #[arg(short = 'c', long, default_value = "gzip")]
+        command: String,
+
+        /// Compression level: a number, or fast, default or best
+        #[arg(short = 'l', long, value_name = "LEVEL", default_value = "default")]
+        level: String,
+
+        /// Only compress files whose name matches GLOB (e.g. '*.log')
+        #[arg(long, value_name = "GLOB")]
+        pattern: Option<String>,
+
+        /// Seconds a file must stay unchanged before it is compressed
+        #[arg(long, value_name = "SECONDS", default_value = "2")]
+        settle: u64,
+
+        /// Move outputs to this directory
+        #[arg(short = 'C', long)]
+        move_to: Option<PathBuf>,
+
+        /// RSA or X25519 public key file for encryption
+        #[arg(long = "encrypt-key")]
+        encrypt_key: Option<PathBuf>,
+
+        /// Encrypt with the 32-byte key in this file
+        #[arg(
+            long = "encrypt-keyfile",
+            value_name = "FILE",
+            conflicts_with = "encrypt_key"
+        )]
+        encrypt_keyfile: Option<PathBuf>,
+
+        /// Delete each file once it is compressed
+        #[arg(long)]
+        remove_source: bool,
+
+        /// With --remove-source, move each file to the OS trash instead of deleting it
+        #[arg(long, requires = "remove_source")]
+        trash: bool,
+
+        /// Directory to watch
+        dir: PathBuf,
+    },
+
+    /// Combine archives and directories into one archive, reporting path collisions
+    Merge {
+        /// Compression level: a number, or fast, default or best
+        #[arg(short = 'l', long, value_name = "LEVEL", default_value = "default")]
+        level: String,
+
+        /// When inputs differ at one path: error (list them all), first or last input wins
+        #[arg(long, value_name = "POLICY", default_value = "error")]
+        on_collision: String,
+
+        /// Replace OUTPUT if it exists
+        #[arg(short = 'f', long)]
+        force: bool,
+
+        /// Archive to write: .tar, .tar.gz, .tar.bz2, .tar.xz (or .tgz, .tbz2, .txz) or .zip
+        output: PathBuf,
+
+        /// Archives (tar, tgz, tbz2, txz, zip) and directories, in order
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+
+    /// Archive rotated logs matching a glob into a dated archive, verify it, and delete them
+    Rotate {
+        /// Only take files whose name matches GLOB (e.g. 'app.log.*')
+        #[arg(long, value_name = "GLOB", required = true)]
+        pattern: String,
+
+        /// Archive to add them to, with strftime fields (e.g. logs-%Y%m.tar.xz)
+        #[arg(long, value_name = "ARCHIVE", required = true)]
+        into: String,
+
+        /// Compression level: a number, or fast, default or best
+        #[arg(short = 'l', long, value_name = "LEVEL", default_value = "default")]
+        level: String,
+
+        /// Keep the files once archived
+        #[arg(long)]
+        keep: bool,
+
+        /// Move the files to the OS trash instead of deleting them
+        #[arg(long, conflicts_with = "keep")]
+        trash: bool,
+
+        /// Show what would be archived without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Directory holding the logs
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Remove temp directories left behind by crashed or killed runs
+    Cleanup {
+        /// Only take unrecorded ones last modified more than AGE ago (30m, 12h, 2d)
+        #[arg(long, value_name = "AGE", default_value = "1d")]
+        older_than: String,
+
+        /// Show what would be removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Directories to scan, besides the system temp dir
+        #[arg(default_value = ".")]
+        dirs: Vec<PathBuf>,
+    },
+
+    /// Search the files inside archives, printing member:line for each match
+    Grep {
+        /// Ignore case
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+
+        /// Treat PATTERN as a literal string, not a regular expression
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Print the line number of each match (member:N:line)
+        #[arg(short = 'n', long)]
+        line_number: bool,
+
+        /// Only search members whose path matches GLOB (e.g. '*.log')
+        #[arg(long, value_name = "GLOB")]
+        member: Option<String>,
+
+        /// Regular expression to look for
+        pattern: String,
+
+        /// Archives to search: tar, tgz, tbz2, txz, or single gz, bz2 and xz files
+        #[arg(required = true)]
+        archives: Vec<PathBuf>,
+    },
+
+    /// Print members of an archive to stdout, seeking with the index of an --indexed tgz
+    Cat {
+        /// Archive to read: tar, tgz, tbz2 or txz
+        archive: PathBuf,
+
+        /// Paths of the members to print, in order
+        #[arg(required = true)]
+        members: Vec<String>,
+    },
+
+    /// Check archives against their recovery data (<archive>.jczr) and rebuild damaged blocks
+    Repair {
+        /// Archives to check and repair
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Show the members of an archive as a tree, with cumulative directory sizes
+    Tree {
+        /// Archive to show: tar, tgz, tbz2, txz or zip
+        archive: PathBuf,
+    },
+
+    /// Unpack archives in scratch space, in parallel, and check every layer, authentication tag, integrity tag and manifest
+    Verify {
+        /// RSA or X25519 private key file for encrypted archives
+        #[arg(long = "decrypt-key")]
+        decrypt_key: Option<PathBuf>,
+
+        /// Key share file of split-key archives (repeat for each share)
+        #[arg(long = "share", value_name = "FILE", conflicts_with = "decrypt_key")]
+        shares: Vec<PathBuf>,
+
+        /// Keyfile of keyfile-encrypted archives
+        #[arg(
+            long = "decrypt-keyfile",
+            value_name = "FILE",
+            conflicts_with_all = ["decrypt_key", "shares"]
+        )]
+        decrypt_keyfile: Option<PathBuf>,
+
+        /// Fail archives with neither an integrity tag nor a manifest
+        #[arg(long)]
+        require_tag: bool,
+
+        /// Verify at most N archives at once [default: all cores]
+        #[arg(short = 'j', long, value_name = "N")]
+        threads: Option<usize>,
+
+        /// Archives and .jcze containers to verify
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Compress a sample with every format and level and compare ratio and speed
+    Benchmark {
+        /// Representative file or directory
+        sample: PathBuf,
+    },
+
+    /// Predict the compressed size of each input without writing any output
+    Estimate {
+        /// Compression command: gzip, bzip2, xz, tar, tgz, tbz2 or txz
+        #[arg(short = 'c', long, default_value = "gzip")]
+        command: String,
+
+        /// Compression level: a number, or fast, default or best
+        #[arg(short = 'l', long, value_name = "LEVEL", default_value = "default")]
+        level: String,
+
+        /// Compress evenly spaced blocks adding up to SIZE of larger inputs (default 64M)
+        #[arg(long, value_name = "SIZE", conflicts_with = "full")]
+        sample: Option<String>,
+
+        /// Compress every input in full for an exact size
+        #[arg(long)]
+        full: bool,
+
+        /// Files and directories (directories are measured as tar)
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+
+    /// Report duplicate files, the largest files and the size per extension of directories or archives
+    Analyze {
+        /// List the N largest files
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_TOP)]
+        top: usize,
+
+        /// Directories, or archives: tar, tgz, tbz2 or txz
+        #[arg(required = true)]
+        targets: Vec<PathBuf>,
+    },
+
+    /// Generate a key pair as NAME.pem (private) and NAME.pub.pem (public)
+    Keygen {
+        /// Key type: rsa or x25519
+        #[arg(long = "type", default_value = "rsa", value_name = "TYPE")]
+        key_type: String,
+
+        /// RSA key size in bits
+        #[arg(long, default_value = "4096")]
+        bits: usize,
+
+        /// Base name of the key files (never overwritten)
+        name: PathBuf,
+    },
+
+    /// Write a new random 32-byte keyfile for --encrypt-keyfile
+    GenKeyfile {
+        /// Keyfile to create (never overwritten)
+        path: PathBuf,
+    },
+
+    /// Wrap files in the .jcze container as they are, without compressing them
+    Encrypt {
+        /// RSA or X25519 public key file (default: prompt for a password)
+        #[arg(long = "encrypt-key")]
+        encrypt_key: Option<PathBuf>,
+
+        /// Split the key into N share files, any K of which decrypt
+        #[arg(long, value_name = "K/N", conflicts_with = "encrypt_key")]
+        split_key: Option<String>,
+
+        /// Keyfile holding a 32-byte key
+        #[arg(
+            long = "encrypt-keyfile",
+            value_name = "FILE",
+            conflicts_with_all = ["encrypt_key", "split_key"]
+        )]
+        encrypt_keyfile: Option<PathBuf>,
+
+        /// Pad each payload to a multiple of SIZE (K/M/G suffixes)
+        #[arg(long, value_name = "SIZE")]
+        pad_to: Option<String>,
+
+        /// Files to encrypt
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Re-encrypt .jcze files in place for a new key or password (payload untouched)
+    Reencrypt {
+        /// Current RSA or X25519 private key (default: prompt for the current password)
+        #[arg(long = "decrypt-key")]
+        decrypt_key: Option<PathBuf>,
+
+        /// New RSA or X25519 public key (default: prompt for a new password)
+        #[arg(long = "encrypt-key")]
+        encrypt_key: Option<PathBuf>,
+
+        /// Files to re-encrypt
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Decrypt .jcze files without decompressing what they contain
+    Decrypt {
+        /// RSA or X25519 private key file for public-key encrypted files
+        #[arg(long = "decrypt-key")]
+        decrypt_key: Option<PathBuf>,
+
+        /// Key share file of split-key files (repeat for each share)
+        #[arg(long = "share", value_name = "FILE", conflicts_with = "decrypt_key")]
+        shares: Vec<PathBuf>,
+
+        /// Keyfile of keyfile-encrypted files
+        #[arg(
+            long = "decrypt-keyfile",
+            value_name = "FILE",
+            conflicts_with_all = ["decrypt_key", "shares"]
+        )]
+        decrypt_keyfile: Option<PathBuf>,
+
+        /// Remove each .jcze file once decrypted
+        #[arg(long = "remove-encrypted")]
+        remove_encrypted: bool,
+
+        /// With --remove-encrypted, move .jcze files to the OS trash instead of deleting them
+        #[arg(long, requires = "remove_encrypted")]
+        trash: bool,
+
+        /// Files to decrypt
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
 }
 
 impl CliArgs {
-    /// Validate arguments
-    pub fn validate(&self) -> Result<(), String> {
+    /// Files selected by `--min-size`, `--max-size`, `--newer-mtime` and
+    /// `--older-than`, ages counting back from now
+    pub fn file_filter(&self) -> FileFilter {
+        let now = SystemTime::now();
+        let time =
+            |text: &Option<String>| text.as_deref().and_then(|text| parse_time_bound(text, now));
+        FileFilter::default()
+            .with_sizes(
+                self.min_size.as_deref().and_then(parse_size),
+                self.max_size.as_deref().and_then(parse_size),
+            )
+            .with_mtimes(time(&self.newer_mtime), time(&self.older_than))
+    }
+
+    /// `--fsync` or `--no-fsync`, whichever came last, if any
+    pub fn fsync_policy(&self) -> Option<bool> {
+        match (self.fsync, self.no_fsync) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Log level chosen with `-q` or `--log-level`, if any
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        if self.quiet {
+            return Some(LevelFilter::Error);
+        }
+        self.log_level.as_deref().and_then(parse_log_level)
+    }
+
+    /// Validate arguments; commands in `external` are valid too
+    pub fn validate(&self, external: Option<&CommandRegistry>) -> Result<(), String> {
         // Validate timestamp option
         if self.timestamp > 3 {
             return Err(format!("Invalid timestamp option: {}", self.timestamp));
         }
+        if let Some(ref pattern) = self.timestamp_format {
+            validate_timestamp_format(pattern).map_err(|e| e.to_string())?;
+        }
+        if let Some(ref template) = self.name_template {
+            validate_name_template(template).map_err(|e| e.to_string())?;
+        }
 
         // Validate compression command
-        let valid_commands = ["gzip", "bzip2", "xz", "tar", "zip", "tgz", "tbz2", "txz"];
-        if !valid_commands.contains(&self.command.as_str()) {
+        let valid_commands = [
+            "gzip", "bzip2", "xz", "tar", "zip", "cpio", "ar", "tgz", "tbz2", "txz", "dedup",
+            "auto",
+        ];
+        let registered = external.is_some_and(|registry| registry.get(&self.command).is_some());
+        if !valid_commands.contains(&self.command.as_str()) && !registered {
             return Err(format!("Invalid compression command: {}", self.command));
         }
 
-        // Check that collect and collect_flat are not both specified
-        if self.collect.is_some() && self.collect_flat.is_some() {
-            return Err("Cannot specify both -a and -A".to_string());
+        let (_, extreme) = parse_level(&self.level)?;
+        if let Some(ref options) = self.lzma2 {
+            parse_lzma2(options)?;
         }
-
-        // Check that password and RSA encryption are not both specified
-        if self.encrypt_password && self.encrypt_key.is_some() {
-            return Err("Cannot specify both --encrypt-password and --encrypt-key".to_string());
+        let xz_only = extreme || self.extreme || self.lzma2.is_some();
+        if xz_only && (self.decompress || !matches!(self.command.as_str(), "xz" | "txz")) {
+            return Err(
+                "--extreme, --lzma2 and the e level suffix only apply to -c xz and txz".to_string(),
+            );
         }
 
-        // Check that encryption options are only used in compression mode
-        if self.decompress {
-            if self.encrypt_password {
-                return Err("--encrypt-password can only be used in compression mode".to_string());
-            }
-            if self.encrypt_key.is_some() {
-                return Err("--encrypt-key can only be used in compression mode".to_string());
+        if self.rsyncable {
+            let supported = matches!(self.command.as_str(), "gzip" | "tgz")
+                || external
+                    .and_then(|registry| registry.get(&self.command))
+                    .is_some_and(|compressor| compressor.supports_rsyncable());
+            if self.decompress || !supported {
+                return Err(
+                    "--rsyncable only applies to -c gzip and tgz, and to registered \
+                     compressors whose entry sets rsyncable"
+                        .to_string(),
+                );
             }
         }
 
-        // Check that decryption key is only used in decompression mode
-        if !self.decompress && self.decrypt_key.is_some() {
-            return Err("--decrypt-key can only be used in decompression mode".to_string());
+        if self.threads == Some(0) {
+            return Err("--threads must be at least 1".to_string());
         }
 
-        // Check that remove-encrypted is only used in decompression mode
-        if !self.decompress && self.remove_encrypted {
-            return Err("--remove-encrypted can only be used in decompression mode".to_string());
+        // Validate priority and bandwidth options
+        if let Some(niceness) = self.nice {
+            if !(-20..=19).contains(&niceness) {
+                return Err(format!(
+                    "Invalid niceness: {} (expected -20 to 19)",
+                    niceness
+                ));
+            }
+        }
+        if let Some(ref class) = self.ionice {
+            if IoPriority::from_name(class).is_none() {
+                return Err(format!(
+                    "Invalid I/O class: {} (expected idle, best-effort or best-effort:0-7)",
+                    class
+                ));
+            }
+        }
+        if let Some(ref rate) = self.limit_rate {
+            if RateLimit::parse(rate).is_none() {
+                return Err(format!(
+                    "Invalid rate: {} (expected e.g. 500K or 50M)",
+                    rate
+                ));
+            }
+        }
+
+        // Uploads: -C URL or --upload, when compressing
+        let remote_move_to = self
+            .move_to
+            .as_deref()
+            .and_then(std::path::Path::to_str)
+            .filter(|path| is_remote_url(path));
+        if let Some(url) = remote_move_to.or(self.upload.as_deref()) {
+            if self.decompress {
+                return Err(
+                    "Uploading (-C URL or --upload) applies to compression only".to_string()
+                );
+            }
+            if remote_move_to.is_some() && self.upload.is_some() {
+                return Err("Cannot specify both -C URL and --upload".to_string());
+            }
+            if remote_move_to.is_some() && self.update {
+                return Err("Cannot specify --update with -C URL".to_string());
+            }
+            RemoteTarget::parse(url).map_err(|e| e.to_string())?;
+        }
+
+        // URL inputs: downloaded, so only ever decompressed
+        let url_inputs = self
+            .inputs
+            .iter()
+            .filter(|input| input.to_str().is_some_and(is_download_url))
+            .count();
+        if url_inputs > 0 && !self.decompress {
+            return Err("URL inputs can only be decompressed (-d)".to_string());
+        }
+        if !self.sha256.is_empty() {
+            if let Some(hex) = self.sha256.iter().find(|hex| !is_sha256_hex(hex)) {
+                return Err(format!(
+                    "Invalid --sha256 value: {} (expected 64 hex digits)",
+                    hex
+                ));
+            }
+            if self.sha256.len() != url_inputs {
+                return Err(format!(
+                    "--sha256 given {} times for {} URL input(s)",
+                    self.sha256.len(),
+                    url_inputs
+                ));
+            }
+        }
+
+        if let Some(ref percent) = self.recovery {
+            if self.decompress {
+                return Err("--recovery applies to compression only".to_string());
+            }
+            if recovery::parse_percent(percent).is_none() {
+                return Err(format!(
+                    "Invalid --recovery: {} (expected a percentage from 1% to 100%)",
+                    percent
+                ));
+            }
+        }
+
+        if !(self.retry_delay.is_finite() && (0.0..=3600.0).contains(&self.retry_delay)) {
+            return Err(format!(
+                "Invalid --retry-delay: {} (expected 0 to 3600 seconds)",
+                self.retry_delay
+            ));
+        }
+
+        // Validate durability policy
+        if Durability::from_name(&self.durability).is_none() {
+            return Err(format!(
+                "Invalid durability mode: {} (expected full, default, or lazy)",
+                self.durability
+            ));
+        }
+
+        // Check that collect and collect_flat are not both specified
+        if self.collect.is_some() && self.collect_flat.is_some() {
+            return Err("Cannot specify both -a and -A".to_string());
+        }
+        if self.keep_paths && self.collect.is_none() && self.collect_flat.is_none() {
+            return Err("--keep-paths requires -a or -A".to_string());
+        }
+        if self.update {
+            if self.decompress || (self.collect.is_none() && self.collect_flat.is_none()) {
+                return Err("--update requires -a or -A".to_string());
+            }
+            // A timestamped name never matches the archive to update
+            if self.timestamp != 0 || self.timestamp_format.is_some() {
+                return Err("Cannot specify --update with a timestamp".to_string());
+            }
+        }
+
+        // Grouping builds collection archives, so it needs a compound format
+        if self.group_by_dir {
+            if self.decompress {
+                return Err("--group-by-dir can only be used in compression mode".to_string());
+            }
+            if self.collect.is_some() || self.collect_flat.is_some() {
+                return Err("Cannot specify --group-by-dir with -a or -A".to_string());
+            }
+            if !["tgz", "tbz2", "txz"].contains(&self.command.as_str()) {
+                return Err("--group-by-dir requires tgz, tbz2, or txz".to_string());
+            }
+        }
+
+        // A collection is a single archive, so there is nothing to choose per input
+        if self.command == "auto"
+            && !self.decompress
+            && (self.collect.is_some() || self.collect_flat.is_some())
+        {
+            return Err("-c auto cannot be used with -a or -A".to_string());
+        }
+
+        // Check that password and RSA encryption are not both specified
+        if self.encrypt_password && self.encrypt_key.is_some() {
+            return Err("Cannot specify both --encrypt-password and --encrypt-key".to_string());
+        }
+
+        // Check that encryption options are only used in compression mode
+        if self.decompress {
+            if self.encrypt_password {
+                return Err("--encrypt-password can only be used in compression mode".to_string());
+            }
+            if self.encrypt_key.is_some() {
+                return Err("--encrypt-key can only be used in compression mode".to_string());
+            }
+        }
+
+        // Check that decryption key is only used in decompression mode
+        if !self.decompress && self.decrypt_key.is_some() {
+            return Err("--decrypt-key can only be used in decompression mode".to_string());
+        }
+
+        if let Some(ref split) = self.split_key {
+            if self.decompress {
+                return Err("--split-key can only be used in compression mode".to_string());
+            }
+            if self.encrypt_password || self.encrypt_key.is_some() {
+                return Err(
+                    "--split-key cannot be combined with --encrypt-password or --encrypt-key"
+                        .to_string(),
+                );
+            }
+            if parse_split_key(split).is_none() {
+                return Err(format!(
+                    "Invalid --split-key: {} (expected K/N with 2 <= K <= N <= 255)",
+                    split
+                ));
+            }
+        }
+
+        if !self.shares.is_empty() {
+            if !self.decompress {
+                return Err("--share can only be used in decompression mode".to_string());
+            }
+            if self.decrypt_key.is_some() {
+                return Err("Cannot specify both --decrypt-key and --share".to_string());
+            }
+        }
+
+        // Only password encryption has a password to keep
+        if self.use_keyring.is_some() && !self.decompress && !self.encrypt_password {
+            return Err("--use-keyring requires -e (or -d)".to_string());
+        }
+
+        if self.encrypt_keyfile.is_some() {
+            if self.decompress {
+                return Err("--encrypt-keyfile can only be used in compression mode".to_string());
+            }
+            if self.encrypt_password || self.encrypt_key.is_some() || self.split_key.is_some() {
+                return Err(
+                    "--encrypt-keyfile cannot be combined with other encryption options"
+                        .to_string(),
+                );
+            }
+        }
+
+        if !self.encrypt_gpg.is_empty() {
+            if self.decompress {
+                return Err("--encrypt-gpg can only be used in compression mode".to_string());
+            }
+            if self.encrypt_password
+                || self.encrypt_key.is_some()
+                || self.split_key.is_some()
+                || self.encrypt_keyfile.is_some()
+            {
+                return Err(
+                    "--encrypt-gpg cannot be combined with other encryption options".to_string(),
+                );
+            }
+        }
+
+        if let Some(ref size) = self.pad_to {
+            let encrypted = self.encrypt_password
+                || self.encrypt_key.is_some()
+                || self.split_key.is_some()
+                || self.encrypt_keyfile.is_some();
+            if self.decompress || !encrypted {
+                return Err(
+                    "--pad-to requires -e, --encrypt-key, --split-key or --encrypt-keyfile"
+                        .to_string(),
+                );
+            }
+            if parse_pad_to(size).is_none() {
+                return Err(format!("Invalid --pad-to: {} (expected e.g. 4K)", size));
+            }
+        }
+
+        if self.decrypt_keyfile.is_some() {
+            if !self.decompress {
+                return Err("--decrypt-keyfile can only be used in decompression mode".to_string());
+            }
+            if self.decrypt_key.is_some() || !self.shares.is_empty() {
+                return Err(
+                    "--decrypt-keyfile cannot be combined with --decrypt-key or --share"
+                        .to_string(),
+                );
+            }
+        }
+
+        for (flag, size) in [
+            ("--min-size", &self.min_size),
+            ("--max-size", &self.max_size),
+        ] {
+            if let Some(size) = size {
+                if parse_size(size).is_none() {
+                    return Err(format!("Invalid {}: {} (expected e.g. 4K)", flag, size));
+                }
+            }
+        }
+        for (flag, time) in [
+            ("--newer-mtime", &self.newer_mtime),
+            ("--older-than", &self.older_than),
+        ] {
+            if let Some(time) = time {
+                if parse_time_bound(time, SystemTime::now()).is_none() {
+                    return Err(format!(
+                        "Invalid {}: {} (expected an age such as 30d or a date such as 2024-01-01)",
+                        flag, time
+                    ));
+                }
+            }
+        }
+        let filter = self.file_filter();
+        if let (Some(min), Some(max)) = (filter.min_size, filter.max_size) {
+            if min > max {
+                return Err("--min-size cannot be larger than --max-size".to_string());
+            }
+        }
+        if let (Some(newer), Some(older)) = (filter.newer_than, filter.older_than) {
+            if newer >= older {
+                return Err("--newer-mtime and --older-than select no time at all".to_string());
+            }
+        }
+
+        if self.direct && !self.decompress {
+            return Err("--direct can only be used in decompression mode".to_string());
+        }
+
+        if let Some(ref limit) = self.memory_limit {
+            if !self.decompress {
+                return Err("--memory-limit can only be used in decompression mode".to_string());
+            }
+            if parse_size(limit).is_none() {
+                return Err(format!(
+                    "Invalid memory limit: {} (expected e.g. 512M)",
+                    limit
+                ));
+            }
+        }
+
+        if self.no_decompress && !self.decompress {
+            return Err("--no-decompress can only be used in decompression mode".to_string());
+        }
+
+        // Sidecars describe archives being written
+        if self.decompress && self.sidecar_metadata {
+            return Err("--sidecar-metadata can only be used in compression mode".to_string());
+        }
+
+        // Only collections have a top level to put the manifest in
+        if self.manifest
+            && (self.decompress
+                || (self.collect.is_none() && self.collect_flat.is_none() && !self.group_by_dir))
+        {
+            return Err("--manifest requires -a, -A or --group-by-dir".to_string());
+        }
+
+        // Attributes travel in PAX headers, so only tar-based archives carry them
+        if !self.decompress
+            && (self.xattrs || self.acls)
+            && !["tar", "tgz", "tbz2", "txz"].contains(&self.command.as_str())
+        {
+            return Err("--xattrs and --acls require tar, tgz, tbz2, or txz".to_string());
+        }
+
+        if self.listed_incremental.is_some() {
+            if self.decompress {
+                return Err(
+                    "--listed-incremental can only be used in compression mode (use --incremental)"
+                        .to_string(),
+                );
+            }
+            if !["tar", "tgz", "tbz2", "txz"].contains(&self.command.as_str()) {
+                return Err("--listed-incremental requires tar, tgz, tbz2, or txz".to_string());
+            }
+            // One snapshot describes one tree
+            if self.inputs.len() != 1
+                || self.collect.is_some()
+                || self.collect_flat.is_some()
+                || self.group_by_dir
+            {
+                return Err("--listed-incremental takes exactly one input".to_string());
+            }
+        }
+
+        // The index records offsets into the gzip stream itself
+        if self.indexed {
+            if self.decompress {
+                return Err("--indexed can only be used in compression mode".to_string());
+            }
+            if self.command != "tgz" {
+                return Err("--indexed requires tgz".to_string());
+            }
+            if self.encrypt_password
+                || self.encrypt_key.is_some()
+                || self.split_key.is_some()
+                || self.encrypt_keyfile.is_some()
+                || !self.encrypt_gpg.is_empty()
+            {
+                return Err("--indexed cannot be used with encryption".to_string());
+            }
+            if self.rsyncable {
+                return Err("Cannot specify --indexed with --rsyncable".to_string());
+            }
+        }
+
+        if !self.transform.is_empty() {
+            if self.decompress {
+                return Err("--transform can only be used in compression mode".to_string());
+            }
+            if !["tar", "tgz", "tbz2", "txz"].contains(&self.command.as_str()) {
+                return Err("--transform requires tar, tgz, tbz2, or txz".to_string());
+            }
+            if self.group_by_dir {
+                return Err("Cannot specify --transform with --group-by-dir".to_string());
+            }
+            PathTransform::parse(&self.transform).map_err(|e| e.to_string())?;
+        }
+
+        if self.incremental && (!self.decompress || self.no_decompress) {
+            return Err("--incremental can only be used when decompressing".to_string());
+        }
+        if self.salvage && (!self.decompress || self.no_decompress) {
+            return Err("--salvage can only be used when decompressing".to_string());
+        }
+        if self.no_verify && (!self.decompress || self.no_decompress) {
+            return Err("--no-verify can only be used when decompressing".to_string());
+        }
+        if let Some(ref format) = self.format {
+            if !self.decompress || self.no_decompress {
+                return Err("--format can only be used when decompressing".to_string());
+            }
+            if self.incremental {
+                return Err("Cannot specify --format with --incremental".to_string());
+            }
+            if format_extension(format, external).is_none() {
+                return Err(format!("Unknown format for --format: {}", format));
+            }
+        }
+        if let Some(ref backup) = self.backup {
+            BackupMode::parse(Some(backup.as_str()).filter(|b| !b.is_empty()))?;
+        }
+        if let Some(ref name) = self.extract_dir {
+            if !self.decompress || self.no_decompress {
+                return Err("--extract-dir can only be used when decompressing".to_string());
+            }
+            if self.incremental {
+                return Err("Cannot specify --extract-dir with --incremental".to_string());
+            }
+            if !name.is_empty() && self.inputs.len() != 1 {
+                return Err("--extract-dir=NAME takes exactly one input".to_string());
+            }
+        }
+
+        // Extraction never follows links out of the destination
+        if self.decompress && self.dereference {
+            return Err("--dereference can only be used in compression mode".to_string());
+        }
+
+        // Only gzip headers carry a name
+        if !self.decompress
+            && (self.name || self.no_name)
+            && !["gzip", "tgz", "auto"].contains(&self.command.as_str())
+        {
+            return Err("--name and --no-name only apply to gzip and tgz".to_string());
+        }
+
+        // zip always records each file's own mtime
+        if self.reproducible {
+            if self.decompress {
+                return Err("--reproducible can only be used in compression mode".to_string());
+            }
+            if self.command == "zip" {
+                return Err("--reproducible is not supported for zip".to_string());
+            }
+            if self.name {
+                return Err("--reproducible never stores a gzip name; drop --name".to_string());
+            }
+        }
+
+        // Other formats have no password support of their own; use -e there
+        if !self.decompress && self.zip_encrypt && self.command != "zip" {
+            return Err("--zip-encrypt requires -c zip".to_string());
+        }
+
+        if let Some(ref comment) = self.comment {
+            if self.decompress {
+                return Err("--comment can only be used in compression mode".to_string());
+            }
+            if self.command != "zip" {
+                return Err("--comment requires -c zip".to_string());
+            }
+            if comment.len() > MAX_COMMENT_LEN {
+                return Err(format!("--comment is limited to {} bytes", MAX_COMMENT_LEN));
+            }
+        }
+
+        // Names are always written as flagged UTF-8
+        if !self.decompress && self.zip_encoding.is_some() {
+            return Err("--zip-encoding can only be used in decompression mode".to_string());
+        }
+
+        // Check that remove-encrypted is only used in decompression mode
+        if !self.decompress && self.remove_encrypted {
+            return Err("--remove-encrypted can only be used in decompression mode".to_string());
         }
 
         Ok(())
     }
 }
 
+/// Parse `--split-key K/N`: N shares, any K of them needed, 2 <= K <= N
+pub fn parse_split_key(text: &str) -> Option<(u8, u8)> {
+    let (threshold, shares) = text.split_once('/')?;
+    let threshold: u8 = threshold.trim().parse().ok()?;
+    let shares: u8 = shares.trim().parse().ok()?;
+    (threshold >= 2 && threshold <= shares).then_some((threshold, shares))
+}
+
+/// Parsed `-l` value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Number(u8),
+    Preset(LevelPreset),
+}
+
+/// Parse `-l`: a number or fast, default or best, optionally followed by
+/// `e` for xz's extreme mode (`9e`, `beste`); whether the number is in
+/// range is up to the compressor
+pub fn parse_level(text: &str) -> Result<(Level, bool), String> {
+    let parse = |text: &str| {
+        text.parse()
+            .ok()
+            .map(Level::Number)
+            .or_else(|| LevelPreset::from_name(text).map(Level::Preset))
+    };
+    parse(text)
+        .map(|level| (level, false))
+        .or_else(|| Some((parse(text.strip_suffix('e')?)?, true)))
+        .ok_or_else(|| {
+            format!(
+                "Invalid level: {} (expected a number, fast, default or best, \
+                 optionally followed by e)",
+                text
+            )
+        })
+}
+
+/// Parse `--lzma2 OPTIONS`: comma-separated `dict=SIZE` (4K to 1536M) and
+/// `lc`, `lp` and `pb` (0 to 4, with lc + lp at most 4), as xz takes them
+pub fn parse_lzma2(text: &str) -> Result<XzTuning, String> {
+    let mut tuning = XzTuning::default();
+    for option in text.split(',') {
+        let invalid = || format!("Invalid --lzma2 option: {}", option);
+        let (name, value) = option.split_once('=').ok_or_else(invalid)?;
+        let bits = || value.parse::<u32>().ok().filter(|bits| *bits <= 4);
+        match name {
+            "dict" => {
+                let size = parse_size(value).filter(|size| (4 << 10..=1536 << 20).contains(size));
+                tuning.dict_size = Some(size.ok_or_else(invalid)? as u32);
+            }
+            "lc" => tuning.lc = Some(bits().ok_or_else(invalid)?),
+            "lp" => tuning.lp = Some(bits().ok_or_else(invalid)?),
+            "pb" => tuning.pb = Some(bits().ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        }
+    }
+    if tuning.lc.unwrap_or(3) + tuning.lp.unwrap_or(0) > 4 {
+        return Err(format!(
+            "Invalid --lzma2 options: {} (lc + lp must be at most 4)",
+            text
+        ));
+    }
+    Ok(tuning)
+}
+
+/// Parse `--pad-to SIZE`: a block size from 1 byte to 1 GiB
+pub fn parse_pad_to(text: &str) -> Option<u64> {
+    parse_size(text).filter(|size| (1..=1 << 30).contains(size))
+}
+
+/// Parse `--buffer-size SIZE`: from 4 KiB to 1 GiB
+fn parse_buffer_size(text: &str) -> Result<usize, String> {
+    parse_size(text)
+        .filter(|size| (4 << 10..=1 << 30).contains(size))
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or_else(|| "expected a size from 4K to 1G, e.g. 4M".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Parse arguments the same way the binary does
+    fn parse(args: &[&str]) -> CliArgs {
+        CliArgs::parse_from(std::iter::once("jcz").chain(args.iter().copied()))
+    }
+
     #[test]
     fn test_validate_mutual_exclusivity_password_and_rsa() {
-        let args = CliArgs {
-            decompress: false,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt")],
-            encrypt_password: true,
-            encrypt_key: Some(PathBuf::from("key.pem")),
-            decrypt_key: None,
-            remove_encrypted: false,
-        };
-
-        let result = args.validate();
+        let args = parse(&["-c", "gzip", "-e", "--encrypt-key", "key.pem", "file.txt"]);
+
+        let result = args.validate(None);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -223,23 +1757,9 @@ mod tests {
 
     #[test]
     fn test_validate_encrypt_password_only_in_compression() {
-        let args = CliArgs {
-            decompress: true,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt.gz")],
-            encrypt_password: true,
-            encrypt_key: None,
-            decrypt_key: None,
-            remove_encrypted: false,
-        };
-
-        let result = args.validate();
+        let args = parse(&["-d", "-c", "gzip", "-e", "file.txt.gz"]);
+
+        let result = args.validate(None);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -248,23 +1768,16 @@ mod tests {
 
     #[test]
     fn test_validate_encrypt_key_only_in_compression() {
-        let args = CliArgs {
-            decompress: true,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt.gz")],
-            encrypt_password: false,
-            encrypt_key: Some(PathBuf::from("key.pem")),
-            decrypt_key: None,
-            remove_encrypted: false,
-        };
-
-        let result = args.validate();
+        let args = parse(&[
+            "-d",
+            "-c",
+            "gzip",
+            "--encrypt-key",
+            "key.pem",
+            "file.txt.gz",
+        ]);
+
+        let result = args.validate(None);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -273,89 +1786,533 @@ mod tests {
 
     #[test]
     fn test_validate_decrypt_key_only_in_decompression() {
-        let args = CliArgs {
-            decompress: false,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt")],
-            encrypt_password: false,
-            encrypt_key: None,
-            decrypt_key: Some(PathBuf::from("key.pem")),
-            remove_encrypted: false,
-        };
-
-        let result = args.validate();
+        let args = parse(&["-c", "gzip", "--decrypt-key", "key.pem", "file.txt"]);
+
+        let result = args.validate(None);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
             .contains("--decrypt-key can only be used in decompression mode"));
     }
 
+    #[test]
+    fn test_validate_no_decompress_only_in_decompression() {
+        let args = parse(&["--no-decompress", "file.txt.gz.jcze"]);
+        assert!(args
+            .validate(None)
+            .unwrap_err()
+            .contains("--no-decompress can only be used in decompression mode"));
+
+        let args = parse(&["-d", "--no-decompress", "file.txt.gz.jcze"]);
+        assert!(args.validate(None).is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_subcommand_parses_key() {
+        let args = parse(&["encrypt", "--encrypt-key", "public.pem", "disk.img"]);
+        match args.subcommand {
+            Some(Commands::Encrypt {
+                encrypt_key, files, ..
+            }) => {
+                assert_eq!(encrypt_key, Some(PathBuf::from("public.pem")));
+                assert_eq!(files, vec![PathBuf::from("disk.img")]);
+            }
+            other => panic!("Expected encrypt subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_split_key() {
+        assert_eq!(parse_split_key("3/5"), Some((3, 5)));
+        assert_eq!(parse_split_key("2/2"), Some((2, 2)));
+        for bad in ["1/5", "4/3", "3", "3/256", "a/b"] {
+            assert_eq!(parse_split_key(bad), None, "{}", bad);
+        }
+
+        assert!(parse(&["-c", "tgz", "--split-key", "3/5", "dir"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-c", "tgz", "--split-key", "6/5", "dir"])
+            .validate(None)
+            .is_err());
+        assert!(parse(&["-c", "tgz", "-e", "--split-key", "3/5", "dir"])
+            .validate(None)
+            .is_err());
+        assert!(parse(&["-c", "tgz", "--share", "a.share", "dir"])
+            .validate(None)
+            .is_err());
+        assert!(parse(&["-d", "--share", "a.share", "x.tar.gz.jcze"])
+            .validate(None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_validate_use_keyring() {
+        assert!(
+            parse(&["-c", "gzip", "-e", "--use-keyring", "backups", "f"])
+                .validate(None)
+                .is_ok()
+        );
+        assert!(parse(&["-d", "--use-keyring", "backups", "f.gz.jcze"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-c", "gzip", "--use-keyring", "backups", "f"])
+            .validate(None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_keyfile_options() {
+        assert!(parse(&["-c", "gzip", "--encrypt-keyfile", "k.bin", "f"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-d", "--decrypt-keyfile", "k.bin", "f.gz.jcze"])
+            .validate(None)
+            .is_ok());
+        assert!(
+            parse(&["-c", "gzip", "-e", "--encrypt-keyfile", "k.bin", "f"])
+                .validate(None)
+                .is_err()
+        );
+        assert!(parse(&["-d", "--encrypt-keyfile", "k.bin", "f.gz.jcze"])
+            .validate(None)
+            .is_err());
+        assert!(parse(&["-c", "gzip", "--decrypt-keyfile", "k.bin", "f"])
+            .validate(None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_pad_to() {
+        assert!(parse(&["-c", "gzip", "-e", "--pad-to", "4K", "f"])
+            .validate(None)
+            .is_ok());
+        let result = parse(&["-c", "gzip", "--pad-to", "4K", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("requires"));
+        let result = parse(&["-c", "gzip", "-e", "--pad-to", "0", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("Invalid --pad-to"));
+        assert!(parse(&["-d", "-e", "--pad-to", "4K", "f.gz.jcze"])
+            .validate(None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_gpg_options() {
+        let args = parse(&[
+            "-c",
+            "tgz",
+            "--encrypt-gpg",
+            "a@x",
+            "--encrypt-gpg",
+            "b@x",
+            "d",
+        ]);
+        assert_eq!(args.encrypt_gpg, vec!["a@x", "b@x"]);
+        assert!(args.validate(None).is_ok());
+        assert!(parse(&["-c", "tgz", "-e", "--encrypt-gpg", "a@x", "d"])
+            .validate(None)
+            .is_err());
+        assert!(parse(&["-d", "--encrypt-gpg", "a@x", "d.tar.gz.gpg"])
+            .validate(None)
+            .is_err());
+    }
+
     #[test]
     fn test_validate_valid_password_encryption() {
-        let args = CliArgs {
-            decompress: false,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt")],
-            encrypt_password: true,
-            encrypt_key: None,
-            decrypt_key: None,
-            remove_encrypted: false,
-        };
-
-        assert!(args.validate().is_ok());
+        let args = parse(&["-c", "gzip", "-e", "file.txt"]);
+
+        assert!(args.validate(None).is_ok());
     }
 
     #[test]
     fn test_validate_valid_rsa_encryption() {
-        let args = CliArgs {
-            decompress: false,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt")],
-            encrypt_password: false,
-            encrypt_key: Some(PathBuf::from("public.pem")),
-            decrypt_key: None,
-            remove_encrypted: false,
-        };
-
-        assert!(args.validate().is_ok());
+        let args = parse(&["-c", "gzip", "--encrypt-key", "public.pem", "file.txt"]);
+
+        assert!(args.validate(None).is_ok());
     }
 
     #[test]
     fn test_validate_valid_rsa_decryption() {
-        let args = CliArgs {
-            decompress: true,
-            force: false,
-            command: "gzip".to_string(),
-            level: 6,
-            move_to: None,
-            collect: None,
-            collect_flat: None,
-            timestamp: 0,
-            inputs: vec![PathBuf::from("file.txt.gz.jcze")],
-            encrypt_password: false,
-            encrypt_key: None,
-            decrypt_key: Some(PathBuf::from("private.pem")),
-            remove_encrypted: false,
-        };
-
-        assert!(args.validate().is_ok());
+        let args = parse(&[
+            "-d",
+            "-c",
+            "gzip",
+            "--decrypt-key",
+            "private.pem",
+            "file.txt.gz.jcze",
+        ]);
+
+        assert!(args.validate(None).is_ok());
+    }
+
+    #[test]
+    fn test_identify_subcommand_does_not_require_inputs() {
+        let args = parse(&["identify", "mystery.bin"]);
+
+        assert!(args.inputs.is_empty());
+        match args.subcommand {
+            Some(Commands::Identify { files }) => {
+                assert_eq!(files, vec![PathBuf::from("mystery.bin")]);
+            }
+            other => panic!("Expected identify subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_durability_mode() {
+        assert!(parse(&["-d", "--durability", "full", "a.tar.gz"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-d", "--durability", "lazy", "a.tar.gz"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-d", "--durability", "eventually", "a.tar.gz"]).validate(None);
+        assert!(result.unwrap_err().contains("Invalid durability mode"));
+    }
+
+    #[test]
+    fn test_fsync_policy() {
+        assert_eq!(parse(&["-c", "gzip", "f"]).fsync_policy(), None);
+        assert_eq!(
+            parse(&["-c", "gzip", "--fsync", "f"]).fsync_policy(),
+            Some(true)
+        );
+        assert_eq!(
+            parse(&["-c", "gzip", "--fsync", "--no-fsync", "f"]).fsync_policy(),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_dereference_flags_override_each_other() {
+        assert!(!parse(&["-c", "tgz", "dir"]).dereference);
+        assert!(parse(&["-c", "tgz", "--dereference", "dir"]).dereference);
+        assert!(!parse(&["--dereference", "--no-dereference", "dir"]).dereference);
+
+        let result = parse(&["-d", "--dereference", "a.tar"]).validate(None);
+        assert!(result.unwrap_err().contains("compression mode"));
+    }
+
+    #[test]
+    fn test_validate_manifest_needs_collection() {
+        assert!(parse(&["-c", "tgz", "--manifest", "-a", "pkg", "f"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-c", "tgz", "--manifest", "--group-by-dir", "d/f"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-c", "tgz", "--manifest", "f"]).validate(None);
+        assert!(result
+            .unwrap_err()
+            .contains("requires -a, -A or --group-by-dir"));
+    }
+
+    #[test]
+    fn test_validate_auto_command() {
+        assert!(parse(&["-c", "auto", "f", "dir"]).validate(None).is_ok());
+        assert!(parse(&["-c", "auto", "--no-name", "f"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-c", "auto", "-a", "pkg", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("-c auto cannot be used"));
+        let result = parse(&["-c", "auto", "--group-by-dir", "d/f"]).validate(None);
+        assert!(result.unwrap_err().contains("requires tgz, tbz2, or txz"));
+    }
+
+    #[test]
+    fn test_validate_priority_and_rate() {
+        let args = parse(&["--nice", "--ionice", "--limit-rate", "50M", "f"]);
+        assert_eq!(args.nice, Some(10));
+        assert_eq!(args.ionice.as_deref(), Some("idle"));
+        assert!(args.validate(None).is_ok());
+        assert_eq!(args.inputs, vec![PathBuf::from("f")]);
+
+        let args = parse(&["--nice=-5", "--ionice=best-effort:4", "f"]);
+        assert_eq!(args.nice, Some(-5));
+        assert!(args.validate(None).is_ok());
+
+        assert!(parse(&["--nice=20", "f"]).validate(None).is_err());
+        assert!(parse(&["--ionice=realtime", "f"]).validate(None).is_err());
+        let result = parse(&["--limit-rate", "fast", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("Invalid rate"));
+    }
+
+    #[test]
+    fn test_validate_direct() {
+        assert!(parse(&["-d", "--direct", "f.tar"]).validate(None).is_ok());
+        let result = parse(&["--direct", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("decompression mode"));
+    }
+
+    #[test]
+    fn test_fail_fast_conflicts_with_keep_going() {
+        assert!(parse(&["--fail-fast", "f"]).fail_fast);
+        assert!(!parse(&["--keep-going", "f"]).fail_fast);
+        let result = CliArgs::try_parse_from(["jcz", "--fail-fast", "--keep-going", "f"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let args = parse(&["--buffer-size", "4M", "-c", "gzip", "f"]);
+        assert_eq!(args.buffer_size, Some(4 << 20));
+        let args = parse(&["encrypt", "--buffer-size", "64K", "f"]);
+        assert_eq!(args.buffer_size, Some(64 << 10));
+
+        let jcz = std::iter::once("jcz");
+        for size in ["1K", "2G", "lots"] {
+            let args = jcz.clone().chain(["--buffer-size", size, "f"]);
+            assert!(CliArgs::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_memory_limit() {
+        assert!(parse(&["-d", "--memory-limit", "512M", "f.xz"])
+            .validate(None)
+            .is_ok());
+        let result = parse(&["-d", "--memory-limit", "lots", "f.xz"]).validate(None);
+        assert!(result.unwrap_err().contains("Invalid memory limit"));
+        let result = parse(&["--memory-limit", "512M", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("decompression mode"));
+    }
+
+    #[test]
+    fn test_validate_sizes() {
+        let args = parse(&["--min-size", "4K", "--max-size", "1G", "d"]);
+        assert!(args.validate(None).is_ok());
+        let filter = args.file_filter();
+        assert_eq!(
+            (filter.min_size, filter.max_size),
+            (Some(4096), Some(1 << 30))
+        );
+        let result = parse(&["--min-size", "big", "d"]).validate(None);
+        assert!(result.unwrap_err().contains("Invalid --min-size"));
+        let result = parse(&["--min-size", "2M", "--max-size", "1M", "d"]).validate(None);
+        assert!(result.unwrap_err().contains("cannot be larger"));
+    }
+
+    #[test]
+    fn test_validate_mtimes() {
+        let args = parse(&["--newer-mtime", "2024-01-01", "--older-than", "30d", "d"]);
+        assert!(args.validate(None).is_ok());
+        assert!(args.file_filter().newer_than < args.file_filter().older_than);
+        let result = parse(&["--older-than", "soon", "d"]).validate(None);
+        assert!(result.unwrap_err().contains("Invalid --older-than"));
+        let result = parse(&["--newer-mtime", "1d", "--older-than", "2d", "d"]).validate(None);
+        assert!(result.unwrap_err().contains("no time at all"));
+    }
+
+    #[test]
+    fn test_validate_threads() {
+        assert_eq!(parse(&["-j", "4", "f"]).threads, Some(4));
+        assert!(parse(&["--threads", "1", "f"]).validate(None).is_ok());
+
+        let result = parse(&["-j", "0", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("at least 1"));
+    }
+
+    #[test]
+    fn test_validate_zip_flags() {
+        assert!(parse(&["-c", "zip", "--zip-encrypt", "f"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-d", "--zip-encrypt", "f.zip"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-c", "tgz", "--zip-encrypt", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("requires -c zip"));
+
+        assert!(parse(&["-d", "--zip-encoding", "cp932", "f.zip"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-c", "zip", "--comment", "nightly build", "f"])
+            .validate(None)
+            .is_ok());
+        let result = parse(&["-c", "tgz", "--comment", "nightly build", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("requires -c zip"));
+        let result = parse(&["-d", "--comment", "nightly build", "f.zip"]).validate(None);
+        assert!(result.unwrap_err().contains("compression mode"));
+        let long = "x".repeat(MAX_COMMENT_LEN + 1);
+        let result = parse(&["-c", "zip", "--comment", &long, "f"]).validate(None);
+        assert!(result.unwrap_err().contains("limited to 65535 bytes"));
+
+        let result = parse(&["-c", "zip", "--zip-encoding", "cp932", "f"]).validate(None);
+        assert!(result
+            .unwrap_err()
+            .contains("only be used in decompression mode"));
+    }
+
+    #[test]
+    fn test_validate_reproducible() {
+        assert!(parse(&["-c", "tgz", "--reproducible", "dir"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-c", "zip", "--reproducible", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("not supported for zip"));
+
+        let result = parse(&["-d", "--reproducible", "a.tar.gz"]).validate(None);
+        assert!(result.unwrap_err().contains("compression mode"));
+    }
+
+    #[test]
+    fn test_validate_gzip_name_flags() {
+        assert!(parse(&["-c", "gzip", "--no-name", "f"]).no_name);
+        assert!(!parse(&["-c", "gzip", "--no-name", "--name", "f"]).no_name);
+        assert!(parse(&["-d", "--no-name", "f.gz"]).validate(None).is_ok());
+
+        let result = parse(&["-c", "xz", "--no-name", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("only apply to gzip"));
+
+        let result = parse(&["-c", "tgz", "--reproducible", "--name", "d"]).validate(None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_group_by_dir() {
+        assert!(parse(&["-c", "txz", "--group-by-dir", "a/x", "b/y"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-c", "gzip", "--group-by-dir", "a/x"]).validate(None);
+        assert!(result.unwrap_err().contains("requires tgz"));
+
+        let result = parse(&["--group-by-dir", "-a", "pkg", "a/x"]).validate(None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_xattrs_needs_tar() {
+        assert!(parse(&["-c", "tgz", "--xattrs", "--acls", "dir"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-d", "--acls", "a.zip"]).validate(None).is_ok());
+
+        let result = parse(&["-c", "zip", "--xattrs", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("require tar"));
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("7"), Ok((Level::Number(7), false)));
+        assert_eq!(parse_level("9e"), Ok((Level::Number(9), true)));
+        assert_eq!(
+            parse_level("best"),
+            Ok((Level::Preset(LevelPreset::Best), false))
+        );
+        assert_eq!(
+            parse_level("defaulte"),
+            Ok((Level::Preset(LevelPreset::Default), true))
+        );
+        for text in ["", "e", "ee", "max", "-1", "9ee"] {
+            assert!(parse_level(text).is_err(), "{}", text);
+        }
+
+        assert!(parse(&["-c", "txz", "-l", "9e", "dir"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-c", "tgz", "-l", "9e", "dir"])
+            .validate(None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_lzma2() {
+        let tuning = parse_lzma2("dict=64M,lc=4,lp=0,pb=0").unwrap();
+        assert_eq!(tuning.dict_size, Some(64 << 20));
+        assert_eq!(
+            (tuning.lc, tuning.lp, tuning.pb),
+            (Some(4), Some(0), Some(0))
+        );
+        assert!(!tuning.extreme);
+
+        for text in [
+            "", "dict", "dict=1K", "dict=2G", "lc=5", "pb=x", "mf=bt4", "lp=2",
+        ] {
+            assert!(parse_lzma2(text).is_err(), "{}", text);
+        }
+
+        assert!(parse(&["-c", "xz", "--extreme", "--lzma2", "dict=8M", "f"])
+            .validate(None)
+            .is_ok());
+        assert!(parse(&["-c", "gzip", "--extreme", "f"])
+            .validate(None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_incremental() {
+        assert!(
+            parse(&["-c", "txz", "--listed-incremental", "s.snar", "dir"])
+                .validate(None)
+                .is_ok()
+        );
+        assert!(parse(&["-d", "--incremental", "full.tgz", "inc.tgz"])
+            .validate(None)
+            .is_ok());
+
+        let result = parse(&["-c", "zip", "--listed-incremental", "s.snar", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("requires tar"));
+        let result = parse(&["--listed-incremental", "s.snar", "a", "b"]).validate(None);
+        assert!(result.unwrap_err().contains("exactly one input"));
+        let result = parse(&["-d", "--listed-incremental", "s.snar", "a.tgz"]).validate(None);
+        assert!(result.is_err());
+        let result = parse(&["--incremental", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("decompressing"));
+    }
+
+    #[test]
+    fn test_validate_format() {
+        for format in ["xz", "gz", "tgz", "tar.gz", "deb", "ZIP"] {
+            assert!(
+                parse(&["-d", "--format", format, "data.bin"])
+                    .validate(None)
+                    .is_ok(),
+                "{}",
+                format
+            );
+        }
+
+        let result = parse(&["-d", "--format", "lz4", "data.bin"]).validate(None);
+        assert!(result.unwrap_err().contains("Unknown format"));
+        let result = parse(&["--format", "xz", "data.bin"]).validate(None);
+        assert!(result.unwrap_err().contains("decompressing"));
+        let result = parse(&["-d", "--format", "tgz", "--incremental", "a.bin"]).validate(None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_extract_dir() {
+        // Without a value the flag does not swallow the archive
+        let args = parse(&["-d", "--extract-dir", "a.zip", "b.tgz"]);
+        assert_eq!(args.extract_dir.as_deref(), Some(""));
+        assert_eq!(args.inputs.len(), 2);
+        assert!(args.validate(None).is_ok());
+        let args = parse(&["-d", "--mkdir=out", "a.zip"]);
+        assert_eq!(args.extract_dir.as_deref(), Some("out"));
+        assert!(args.validate(None).is_ok());
+
+        let result = parse(&["-d", "--extract-dir=out", "a.zip", "b.zip"]).validate(None);
+        assert!(result.unwrap_err().contains("exactly one input"));
+        let result = parse(&["--extract-dir", "dir"]).validate(None);
+        assert!(result.unwrap_err().contains("decompressing"));
+        let result = parse(&["-d", "--extract-dir", "--incremental", "a.tgz"]).validate(None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_backup() {
+        let args = parse(&["-d", "--backup", "a.tgz"]);
+        assert_eq!(args.backup.as_deref(), Some(""));
+        assert_eq!(args.inputs.len(), 1);
+        assert!(args.validate(None).is_ok());
+        assert!(parse(&["--backup=.bak", "f"]).validate(None).is_ok());
+        let result = parse(&["--backup=x/y", "f"]).validate(None);
+        assert!(result.unwrap_err().contains("backup suffix"));
     }
 }
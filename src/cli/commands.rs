@@ -1,30 +1,227 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use crate::cli::args::CliArgs;
+use crate::cli::args::{
+    parse_level, parse_lzma2, parse_pad_to, parse_split_key, CliArgs, Commands, Level,
+};
+use crate::cli::hooks::Hooks;
+use crate::cli::interrupt;
+use crate::cli::keyring::keyring_secrets;
+use crate::cli::output::{print_stats, print_verify_table, styled, Tone};
+use crate::cli::prompt::{prompt_overwrite, prompt_password, prompt_zip_password};
+use crate::compressors::{
+    create_compressor, default_registry_path, detect_format, require_tools, zip, CommandRegistry,
+};
+use crate::core::compressor::Compressor;
 use crate::core::config::{
-    CollectionConfig, CollectionMode, CompressionConfig, DecryptionMethod, EncryptionMethod,
-    TimestampOption,
+    CollectionConfig, CollectionMode, CommandTuning, CompressionConfig, DecryptionMethod,
+    Durability, EncryptionMethod, EntryListener, ExtractDir, FormatOptions, GzipTuning,
+    OverwritePolicy, Secrets, TimestampOption, XzTuning,
 };
 use crate::core::error::{JcError, JcResult};
-use crate::core::types::{CompoundFormat, CompressionFormat};
-use crate::operations::{collect_and_compress, compound, compress, decompress};
-use crate::utils::{error, validate_input_files, validate_move_to};
+use crate::core::heuristics::choose_format;
+use crate::core::report::{Operation, Report};
+use crate::core::types::{ArchiveEntry, CompoundFormat, CompressionFormat, EntryKind};
+use crate::crypto::keys::{generate_keyfile, generate_rsa_key_pair, write_new_private_file};
+use crate::crypto::X25519Encryption;
+use crate::operations::benchmark::BENCHMARK_HEADER;
+use crate::operations::recovery;
+use crate::operations::stats::{format_size, timed};
+use crate::operations::{
+    analyze, benchmark, build_pattern, cat_member, clean_orphans, clean_recorded_orphans,
+    collect_and_compress, collect_by_directory, compound, compress, decompress, encrypt_only,
+    estimate, grep_archive, identify_file, list_archive, merge_archives, reencrypt_files,
+    render_tree, repair, restore_tiered_file, rotate_logs, tier_directory, verify_archive,
+    watch_directory, write_recovery, CollisionPolicy, FileStats, GrepOptions, RunStats, TierRules,
+    Verification, WatchOptions, DEFAULT_SAMPLE,
+};
+use crate::utils::bufio::set_buffer_size;
+use crate::utils::timestamp::parse_time_bound;
+use crate::utils::{
+    create_scratch_dir, download, error, info, is_download_url, is_remote_url, parse_size,
+    remove_original, require_download_tool, run_limited, run_unless_failed, set_io_priority,
+    set_niceness, sync_output, validate_input_files, validate_move_to, BackupMode, IoPriority,
+    PathTransform, RateLimit, RemoteTarget, RetryPolicy, Upload,
+};
+use rayon::prelude::*;
 
 /// Execute the appropriate command based on CLI arguments
 pub fn execute(args: CliArgs) -> JcResult<()> {
-    // Validate arguments
-    args.validate().map_err(JcError::Other)?;
+    if let Some(size) = args.buffer_size {
+        set_buffer_size(size);
+    }
+
+    // Scratch space of runs that crashed or were killed since
+    if !matches!(args.subcommand, Some(Commands::Cleanup { .. })) {
+        clean_recorded_orphans();
+    }
+
+    // Subcommands bypass the compression options entirely
+    if let Some(subcommand) = args.subcommand {
+        return execute_subcommand(subcommand);
+    }
+
+    // Registered compressors extend the valid -c commands
+    let external = load_external(args.compressors.as_deref())?;
+    args.validate(external.as_deref()).map_err(JcError::Usage)?;
+    let hooks = Hooks::load(args.hooks.as_deref())?.with_overrides(Hooks {
+        pre_batch: args.pre_batch_cmd.clone(),
+        pre_file: args.pre_cmd.clone(),
+        post_file: args.post_cmd.clone(),
+        post_batch: args.post_batch_cmd.clone(),
+    });
+
+    // -C URL uploads each output without keeping it; --upload keeps it
+    let remote_move_to = args
+        .move_to
+        .as_deref()
+        .and_then(Path::to_str)
+        .filter(|path| is_remote_url(path));
+    let retry = RetryPolicy::new(args.retries, Duration::from_secs_f64(args.retry_delay));
+    let upload = match (remote_move_to, args.upload.as_deref()) {
+        (Some(url), _) => Some(Upload {
+            target: RemoteTarget::parse(url)?,
+            remove_local: true,
+            retry,
+        }),
+        (None, Some(url)) => Some(Upload {
+            target: RemoteTarget::parse(url)?,
+            remove_local: false,
+            retry,
+        }),
+        (None, None) => None,
+    };
+    if let Some(ref upload) = upload {
+        upload.target.require_tool()?;
+    }
+
+    // Before any worker threads start, so they and the tools inherit it
+    if let Some(niceness) = args.nice {
+        set_niceness(niceness)?;
+    }
+    if let Some(priority) = args.ionice.as_deref().and_then(IoPriority::from_name) {
+        set_io_priority(priority)?;
+    }
 
     // Build configuration
-    let timestamp = TimestampOption::from_u8(args.timestamp)
-        .ok_or(JcError::InvalidTimestampOption(args.timestamp))?;
+    let timestamp = match args.timestamp_format {
+        Some(ref pattern) => TimestampOption::Format {
+            pattern: pattern.clone(),
+            utc: false,
+        },
+        None => TimestampOption::from_u8(args.timestamp)
+            .ok_or(JcError::InvalidTimestampOption(args.timestamp))?,
+    };
+    let unique_names = timestamp != TimestampOption::None;
+    let timestamp = if args.utc {
+        // {timestamp} in a name template needs a layout to carry UTC
+        match timestamp {
+            TimestampOption::None if args.name_template.is_some() => {
+                TimestampOption::DateTime.in_utc()
+            }
+            timestamp => timestamp.in_utc(),
+        }
+    } else {
+        timestamp
+    };
 
+    let durability = Durability::from_name(&args.durability)
+        .ok_or_else(|| JcError::Other(format!("Invalid durability mode: {}", args.durability)))?;
+
+    let reproducible = if args.reproducible {
+        Some(source_date_epoch()?)
+    } else {
+        None
+    };
+
+    let secrets = match args.use_keyring {
+        Some(ref name) => keyring_secrets(name)?,
+        None => Secrets::new(prompt_password),
+    };
+
+    let xz_tuning = XzTuning {
+        extreme: args.extreme,
+        ..match args.lzma2 {
+            Some(ref options) => parse_lzma2(options).map_err(JcError::Usage)?,
+            None => XzTuning::default(),
+        }
+    };
+    let transform = if args.transform.is_empty() {
+        None
+    } else {
+        Some(PathTransform::parse(&args.transform)?)
+    };
     let config = CompressionConfig::new()
-        .with_level(args.level)
+        .with_format_options(CompressionFormat::Xz.name(), FormatOptions::Xz(xz_tuning));
+    let config = with_level(config, &args.level)?
+        .with_unique_names(unique_names)
         .with_timestamp(timestamp)
-        .with_force(args.force);
+        .with_name_template(args.name_template.clone())
+        .with_force(args.force)
+        .with_overwrite(if args.force {
+            OverwritePolicy::Overwrite
+        } else {
+            OverwritePolicy::Ask(Arc::new(prompt_overwrite))
+        })
+        .with_secrets(Some(secrets))
+        .with_show_output_size(args.stats)
+        .with_entry_listener(args.verbose.then(|| EntryListener::new(print_entry)))
+        .with_threads(args.threads)
+        .with_fail_fast(args.fail_fast)
+        .with_retry(retry)
+        .with_limit_rate(args.limit_rate.as_deref().and_then(RateLimit::parse))
+        .with_temp_dir(args.tempdir.clone())
+        .with_direct(args.direct)
+        .with_serial_tools(args.no_parallel_tools)
+        .with_memory_limit(args.memory_limit.as_deref().and_then(parse_size))
+        .with_pad_to(args.pad_to.as_deref().and_then(parse_pad_to))
+        .with_durability(durability)
+        .with_fsync(args.fsync_policy())
+        .with_sidecar_metadata(args.sidecar_metadata)
+        .with_xattrs(args.xattrs)
+        .with_acls(args.acls)
+        .with_dereference(args.dereference)
+        .with_filter(args.file_filter())
+        .with_gzip_no_name(args.no_name)
+        .with_reproducible(reproducible)
+        .with_zip_encoding(args.zip_encoding.clone())
+        .with_zip_comment(args.comment.clone())
+        .with_manifest(args.manifest)
+        .with_indexed(args.indexed)
+        .with_listed_incremental(args.listed_incremental.clone())
+        .with_transform(transform)
+        .with_incremental(args.incremental)
+        .with_extract_dir(args.extract_dir.as_deref().map(|name| match name {
+            "" => ExtractDir::FromArchive,
+            name => ExtractDir::Named(name.to_string()),
+        }))
+        .with_backup(match args.backup.as_deref() {
+            Some(control) => BackupMode::parse(Some(control).filter(|c| !c.is_empty()))
+                .map_err(JcError::Other)?,
+            None => None,
+        })
+        .with_trash(args.trash)
+        .with_salvage(args.salvage)
+        .with_no_verify(args.no_verify)
+        .with_decode_as(args.format.clone())
+        .with_external(external);
+    let config = if args.rsyncable {
+        with_rsyncable(config, &args.command)
+    } else {
+        config
+    };
 
-    let config = if let Some(ref move_to) = args.move_to {
+    if let Some(ref tempdir) = args.tempdir {
+        if !tempdir.is_dir() {
+            return Err(JcError::NotADirectory(tempdir.clone()));
+        }
+    }
+
+    let config = if let Some(move_to) = args.move_to.as_ref().filter(|_| remote_move_to.is_none()) {
         validate_move_to(move_to)?;
         config.with_move_to(move_to.clone())
     } else {
@@ -35,31 +232,80 @@ pub fn execute(args: CliArgs) -> JcResult<()> {
     let config = if args.encrypt_password {
         config.with_encryption(Some(EncryptionMethod::Password))
     } else if let Some(ref public_key_path) = args.encrypt_key {
-        config.with_encryption(Some(EncryptionMethod::Rsa {
-            public_key_path: public_key_path.clone(),
+        config.with_encryption(Some(public_key_method(public_key_path.clone())))
+    } else if let Some(ref keyfile_path) = args.encrypt_keyfile {
+        config.with_encryption(Some(EncryptionMethod::Keyfile {
+            keyfile_path: keyfile_path.clone(),
         }))
+    } else if let Some((threshold, shares)) = args.split_key.as_deref().and_then(parse_split_key) {
+        config.with_encryption(Some(EncryptionMethod::Threshold { threshold, shares }))
+    } else if !args.encrypt_gpg.is_empty() {
+        config.with_encryption(Some(EncryptionMethod::Gpg {
+            recipients: args.encrypt_gpg.clone(),
+        }))
+    } else {
+        config
+    };
+
+    // Asked once up front, since files are processed in parallel
+    let config = if args.zip_encrypt {
+        config.with_zip_password(Some(prompt_zip_password()?))
     } else {
         config
     };
 
-    // Validate input files
-    let inputs = validate_input_files(args.inputs)?;
-    let input_paths: Vec<PathBuf> = inputs.iter().map(|f| f.real_path.clone()).collect();
+    // A missing tool fails the run here rather than every input
+    if !args.decompress {
+        validate_command_level(&args.command, &config)?;
+        require_command_tools(&args.command, &config)?;
+    }
 
-    if args.decompress {
+    // Validate input files; URLs are downloaded when their turn comes
+    let (urls, local): (Vec<PathBuf>, Vec<PathBuf>) = args
+        .inputs
+        .into_iter()
+        .partition(|input| args.decompress && input.to_str().is_some_and(is_download_url));
+    let mut input_paths = Vec::new();
+    if !local.is_empty() || urls.is_empty() {
+        let inputs = validate_input_files(local, args.dereference, config.filter)?;
+        if inputs.is_empty() && urls.is_empty() {
+            info!("No input is within the --min-size/--max-size range, nothing to do");
+            return Ok(());
+        }
+        input_paths.extend(inputs.into_iter().map(|f| f.original_path));
+    }
+    if !urls.is_empty() {
+        require_download_tool()?;
+    }
+    input_paths.extend(urls);
+
+    let followup = Followup {
+        report: args.report.as_deref(),
+        hooks: &hooks,
+        upload: upload.as_ref(),
+        recovery: args.recovery.as_deref().and_then(recovery::parse_percent),
+        // Extracted outputs are synced by `--durability` already
+        fsync: !args.decompress && config.syncs_outputs(),
+    };
+    // Once any prompts are done, which Ctrl-C should still kill outright
+    let interrupt = interrupt::install()?;
+    let config = config.with_interrupt(Some(interrupt.clone()));
+
+    let result = if args.decompress {
         // Decompression mode
-        let decryption_method = if let Some(ref private_key_path) = args.decrypt_key {
-            Some(DecryptionMethod::Rsa {
-                private_key_path: private_key_path.clone(),
-            })
-        } else {
-            None
-        };
+        let config = config.with_decrypt_only(args.no_decompress);
+        let decryption_method = decryption_method(
+            args.decrypt_key.clone(),
+            args.decrypt_keyfile.clone(),
+            args.shares.clone(),
+        );
         handle_decompress(
             input_paths,
             config,
             decryption_method,
             args.remove_encrypted,
+            &args.sha256,
+            &followup,
         )
     } else if args.collect.is_some() || args.collect_flat.is_some() {
         // Collection mode
@@ -69,12 +315,107 @@ pub fn execute(args: CliArgs) -> JcResult<()> {
             CollectionMode::Flat
         };
 
-        let package_name = args.collect.or(args.collect_flat).unwrap();
+        let collection_config = CollectionConfig {
+            base: config,
+            package_name: args.collect.or(args.collect_flat).unwrap(),
+            mode,
+            keep_paths: args.keep_paths,
+            update: args.update,
+        };
 
-        handle_collection(input_paths, &args.command, package_name, mode, config)
+        handle_collection(input_paths, &args.command, collection_config, &followup)
+    } else if args.group_by_dir {
+        // One archive per input subdirectory
+        handle_group_by_dir(input_paths, &args.command, config, &followup)
     } else {
         // Standard compression mode
-        handle_compress(input_paths, &args.command, config)
+        handle_compress(input_paths, &args.command, config, &followup)
+    };
+    // Interrupted even if the inputs in flight went on to finish
+    if interrupt.is_cancelled() {
+        result.and(Err(JcError::Aborted))
+    } else {
+        result
+    }
+}
+
+/// Fail when a tool that `command` needs is not installed
+///
+/// `auto` and registered commands are left to fail per input.
+fn require_command_tools(command: &str, config: &CompressionConfig) -> JcResult<()> {
+    if CompoundFormat::from_str(command).is_none()
+        && CompressionFormat::from_name(command).is_none()
+    {
+        return Ok(());
+    }
+    require_tools(config)
+}
+
+/// Fail with a usage error when the level is outside the range of the
+/// compressor behind `command`, before any input is touched
+///
+/// `auto` is left to each input.
+fn validate_command_level(command: &str, config: &CompressionConfig) -> JcResult<()> {
+    let builtin;
+    let compressor: &dyn Compressor = match config
+        .external
+        .as_ref()
+        .and_then(|registry| registry.get(command))
+    {
+        Some(registered) => registered,
+        None => {
+            let format = match CompoundFormat::from_str(command) {
+                Some(compound) => compound.secondary(),
+                None => match CompressionFormat::from_name(command) {
+                    Some(format) => format,
+                    None => return Ok(()),
+                },
+            };
+            builtin = create_compressor(format);
+            &*builtin
+        }
+    };
+
+    let level = config.level_for(compressor);
+    if !compressor.supports_levels() || compressor.validate_level(level) {
+        return Ok(());
+    }
+    let valid: Vec<u8> = (0..=u8::MAX)
+        .filter(|&level| compressor.validate_level(level))
+        .collect();
+    Err(JcError::Usage(format!(
+        "Invalid compression level {} for {} (valid levels are {} to {})",
+        level,
+        compressor.name(),
+        valid.first().copied().unwrap_or(0),
+        valid.last().copied().unwrap_or(0)
+    )))
+}
+
+/// Load the compressor registry: `path` if given, else the default file
+/// if there is one
+fn load_external(path: Option<&Path>) -> JcResult<Option<Arc<CommandRegistry>>> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match default_registry_path().filter(|p| p.is_file()) {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(Arc::new(CommandRegistry::load(&path)?)))
+}
+
+/// Epoch that reproducible archives clamp mtimes to
+///
+/// Follows the reproducible-builds.org SOURCE_DATE_EPOCH convention;
+/// without it every mtime is zeroed.
+fn source_date_epoch() -> JcResult<u64> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| JcError::Other(format!("Invalid SOURCE_DATE_EPOCH: {}", value))),
+        Err(_) => Ok(0),
     }
 }
 
@@ -83,86 +424,1171 @@ fn handle_decompress(
     config: CompressionConfig,
     decryption_method: Option<DecryptionMethod>,
     remove_encrypted: bool,
+    checksums: &[String],
+    followup: &Followup,
 ) -> JcResult<()> {
-    let results = decompress::decompress_files(inputs, config, decryption_method, remove_encrypted);
+    followup.hooks.pre_batch(Operation::Decompress, &inputs)?;
+    let order = inputs.clone();
+    let (inputs, mut results) = followup.hooks.pre_files(Operation::Decompress, inputs);
+    let (urls, inputs): (Vec<PathBuf>, Vec<PathBuf>) = inputs
+        .into_iter()
+        .partition(|input| input.to_str().is_some_and(is_download_url));
 
-    // Check for errors
-    let mut had_errors = false;
-    for result in results {
-        if let Err(e) = result {
-            error!("Decompression failed: {}", e);
-            had_errors = true;
+    let show_stats = config.show_output_size;
+    let (decompressed, wall_time) = timed(|| -> JcResult<_> {
+        let mut decompressed = Vec::new();
+        if !inputs.is_empty() || urls.is_empty() {
+            let local = decompress::decompress_files(
+                inputs.clone(),
+                config.clone(),
+                decryption_method.clone(),
+                remove_encrypted,
+            );
+            decompressed.extend(inputs.into_iter().zip(local));
+        }
+        if !urls.is_empty() {
+            decompressed.extend(decompress_urls(urls, checksums, config, decryption_method)?);
+        }
+        Ok(decompressed)
+    });
+    results.extend(decompressed?);
+
+    finish_batch(
+        Operation::Decompress,
+        in_input_order(&order, results),
+        wall_time,
+        show_stats,
+        followup,
+        "Some files failed to decompress",
+    )
+}
+
+/// Download each URL, checking it against its `--sha256`, then extract it
+/// to where a local archive would go with the same `-C`
+fn decompress_urls(
+    urls: Vec<PathBuf>,
+    checksums: &[String],
+    config: CompressionConfig,
+    decryption_method: Option<DecryptionMethod>,
+) -> JcResult<Vec<(PathBuf, JcResult<FileStats>)>> {
+    let dest = config.move_to.clone().unwrap_or_else(|| PathBuf::from("."));
+    let scratch = create_scratch_dir(config.temp_dir.as_deref(), Some(&dest))?;
+
+    let mut results = Vec::new();
+    let mut fetched = Vec::new();
+    for (i, url) in urls.into_iter().enumerate() {
+        // One directory per URL, as two may end in the same name
+        let dir = scratch.path().join(i.to_string());
+        let sha256 = checksums.get(i).map(String::as_str);
+        let downloaded = fs::create_dir(&dir).map_err(JcError::from).and_then(|()| {
+            let url = url.to_string_lossy();
+            config.retry.run(&url, || download(&url, &dir, sha256))
+        });
+        match downloaded {
+            Ok(path) => fetched.push((url, path)),
+            Err(e) => results.push((url, Err(e))),
         }
     }
 
-    if had_errors {
-        Err(JcError::Other(
-            "Some files failed to decompress".to_string(),
-        ))
-    } else {
-        Ok(())
+    let paths = fetched.iter().map(|(_, path)| path.clone()).collect();
+    let config = config.with_move_to(dest);
+    let decompressed = decompress::decompress_files(paths, config, decryption_method, false);
+    for ((url, _), result) in fetched.into_iter().zip(decompressed) {
+        let result = result.map(|mut file| {
+            file.input = url.clone();
+            file
+        });
+        results.push((url, result));
     }
+    Ok(results)
 }
 
-fn handle_compress(inputs: Vec<PathBuf>, command: &str, config: CompressionConfig) -> JcResult<()> {
-    // Determine if simple or compound format
-    if let Some(compound) = CompoundFormat::from_str(command) {
-        // Compound format (tgz, tbz2, txz)
-        let results = compound::compress_compound_batch(inputs, compound, config);
+fn handle_compress(
+    inputs: Vec<PathBuf>,
+    command: &str,
+    config: CompressionConfig,
+    followup: &Followup,
+) -> JcResult<()> {
+    followup.hooks.pre_batch(Operation::Compress, &inputs)?;
+    let order = inputs.clone();
+    let (inputs, mut results) = followup.hooks.pre_files(Operation::Compress, inputs);
 
-        let mut had_errors = false;
-        for result in results {
-            if let Err(e) = result {
-                error!("Compression failed: {}", e);
-                had_errors = true;
-            }
-        }
+    let show_stats = config.show_output_size;
 
-        if had_errors {
-            Err(JcError::Other("Some files failed to compress".to_string()))
+    let (compressed, wall_time) = timed(|| {
+        if command == "auto" {
+            compress_auto(inputs, config)
         } else {
-            Ok(())
+            compress_batch(inputs, command, config)
         }
-    } else {
+    });
+    results.extend(compressed?);
+
+    finish_batch(
+        Operation::Compress,
+        in_input_order(&order, results),
+        wall_time,
+        show_stats,
+        followup,
+        "Some files failed to compress",
+    )
+}
+
+/// Compress every input with one `-c` command, pairing each with its result
+fn compress_batch(
+    inputs: Vec<PathBuf>,
+    command: &str,
+    config: CompressionConfig,
+) -> JcResult<Vec<(PathBuf, JcResult<FileStats>)>> {
+    // Determine if simple or compound format
+    let results = if let Some(compound) = CompoundFormat::from_str(command) {
+        // Compound format (tgz, tbz2, txz)
+        compound::compress_compound_batch(inputs.clone(), compound, config)
+    } else if let Some(format) = CompressionFormat::from_name(command) {
         // Simple format (gzip, bzip2, xz, tar)
-        let format = CompressionFormat::from_name(command)
+        compress::compress_files(inputs.clone(), format, config)
+    } else {
+        // Registered in the compressor registry
+        let external = config.external.clone();
+        let compressor = external
+            .as_ref()
+            .and_then(|registry| registry.get(command))
             .ok_or_else(|| JcError::InvalidCommand(command.to_string()))?;
+        compress::compress_files_with(inputs.clone(), compressor, config)
+    };
+    Ok(inputs.into_iter().zip(results).collect())
+}
 
-        let results = compress::compress_files(inputs, format, config);
+/// `-c auto`: pick a command per input, then compress each group as a batch
+///
+/// Files that are already compressed are left as they are, unless they are
+/// to be encrypted, in which case they are stored in a tar.
+fn compress_auto(
+    inputs: Vec<PathBuf>,
+    config: CompressionConfig,
+) -> JcResult<Vec<(PathBuf, JcResult<FileStats>)>> {
+    let mut results = Vec::new();
+    let mut groups: Vec<(&str, Vec<PathBuf>)> = Vec::new();
 
-        let mut had_errors = false;
-        for result in results {
-            if let Err(e) = result {
-                error!("Compression failed: {}", e);
-                had_errors = true;
+    for input in inputs {
+        let decision = match run_unless_failed(config.fail_fast.as_ref(), || choose_format(&input))
+        {
+            Ok(decision) => decision,
+            Err(JcError::Cancelled) => {
+                results.push((input, Err(JcError::Cancelled)));
+                continue;
             }
-        }
+            Err(e) => {
+                error!("Failed to inspect {}: {}", input.display(), e);
+                results.push((input, Err(e)));
+                continue;
+            }
+        };
+        let command = decision
+            .choice
+            .command(input.is_dir())
+            .or(config.encryption.is_some().then_some("tar"));
 
-        if had_errors {
-            Err(JcError::Other("Some files failed to compress".to_string()))
-        } else {
-            Ok(())
+        match command {
+            Some(command) => {
+                info!("{}: {} -> {}", input.display(), decision, command);
+                match groups.iter_mut().find(|(c, _)| *c == command) {
+                    Some((_, group)) => group.push(input),
+                    None => groups.push((command, vec![input])),
+                }
+            }
+            None => info!("{}: {}, left as is", input.display(), decision),
         }
     }
+
+    for (command, group) in groups {
+        results.extend(compress_batch(group, command, config.clone())?);
+    }
+    Ok(results)
 }
 
 fn handle_collection(
     inputs: Vec<PathBuf>,
     command: &str,
-    package_name: String,
-    mode: CollectionMode,
+    collection_config: CollectionConfig,
+    followup: &Followup,
+) -> JcResult<()> {
+    let compound = CompoundFormat::from_str(command)
+        .ok_or_else(|| JcError::InvalidCommand(command.to_string()))?;
+
+    // An input left out would leave the archive incomplete, so any
+    // failing hook stops the collection
+    followup.hooks.pre_batch(Operation::Compress, &inputs)?;
+    for input in &inputs {
+        followup.hooks.pre_file(Operation::Compress, input)?;
+    }
+
+    let show_stats = collection_config.base.show_output_size;
+
+    let package = PathBuf::from(&collection_config.package_name);
+    let (result, wall_time) = timed(|| collect_and_compress(inputs, compound, collection_config));
+    let result = followup.finish_file(Operation::Compress, &package, result);
+    let batch_hook = followup
+        .hooks
+        .post_batch(Operation::Compress, 1, result.is_err() as usize);
+    if let Some(path) = followup.report {
+        let mut run = Report::new(Operation::Compress);
+        run.add(&package, &result);
+        run.write(path)?;
+    }
+    if show_stats {
+        print_stats(&RunStats::new(vec![result?], wall_time));
+    } else {
+        result?;
+    }
+
+    batch_hook
+}
+
+fn handle_group_by_dir(
+    inputs: Vec<PathBuf>,
+    command: &str,
     config: CompressionConfig,
+    followup: &Followup,
 ) -> JcResult<()> {
     let compound = CompoundFormat::from_str(command)
         .ok_or_else(|| JcError::InvalidCommand(command.to_string()))?;
 
-    let collection_config = CollectionConfig {
-        base: config,
-        package_name,
-        mode,
+    followup.hooks.pre_batch(Operation::Compress, &inputs)?;
+    let (inputs, mut results) = followup.hooks.pre_files(Operation::Compress, inputs);
+
+    let show_stats = config.show_output_size;
+    let (collected, wall_time) = timed(|| collect_by_directory(inputs, compound, config));
+    results.extend(collected?);
+
+    finish_batch(
+        Operation::Compress,
+        results,
+        wall_time,
+        show_stats,
+        followup,
+        "Some directories failed to compress",
+    )
+}
+
+/// `results` in the order their inputs were given, however the batch split
+/// them up (inputs skipped by a hook, `-c auto` groups, URLs); results of
+/// an input given twice keep their relative order
+fn in_input_order(
+    inputs: &[PathBuf],
+    mut results: Vec<(PathBuf, JcResult<FileStats>)>,
+) -> Vec<(PathBuf, JcResult<FileStats>)> {
+    let mut positions = HashMap::new();
+    for (i, input) in inputs.iter().enumerate() {
+        positions.entry(input).or_insert(i);
+    }
+    results.sort_by_key(|(input, _)| positions.get(input).copied().unwrap_or(usize::MAX));
+    results
+}
+
+/// Upload the outputs and run the post-file hooks, log the failures of a
+/// batch, print `--stats` and write `--report`, then run the post-batch
+/// hook and summarize any failures as `message`
+fn finish_batch(
+    operation: Operation,
+    results: Vec<(PathBuf, JcResult<FileStats>)>,
+    wall_time: Duration,
+    show_stats: bool,
+    followup: &Followup,
+    message: &str,
+) -> JcResult<()> {
+    let total = results.len();
+    let mut report = Report::new(operation);
+    let mut failures = Vec::new();
+    let mut stats = Vec::new();
+    for (input, result) in results {
+        let result = followup.finish_file(operation, &input, result);
+        if followup.report.is_some() {
+            report.add(&input, &result);
+        }
+        match result {
+            Ok(file) => stats.push(file),
+            // Cancelled inputs only count towards the summary
+            Err(e) if e.is_cancellation() => failures.push(e),
+            Err(e) => {
+                error!("{} failed: {}", operation.noun(), e);
+                failures.push(e);
+            }
+        }
+    }
+    if show_stats {
+        print_stats(&RunStats::new(stats, wall_time));
+    }
+    if let Some(path) = followup.report {
+        report.write(path)?;
+    }
+
+    let batch_hook = followup.hooks.post_batch(operation, total, failures.len());
+    batch_result(message, failures, total).and(batch_hook)
+}
+
+/// What happens to the results of a batch besides logging them
+struct Followup<'a> {
+    report: Option<&'a Path>,
+    hooks: &'a Hooks,
+    upload: Option<&'a Upload>,
+    /// `--recovery` percentage
+    recovery: Option<u8>,
+    /// Fsync outputs, and their recovery data, before anything else
+    fsync: bool,
+}
+
+impl Followup<'_> {
+    /// Write recovery data for a successful output and upload both, then
+    /// run the post-file hook
+    fn finish_file(
+        &self,
+        operation: Operation,
+        input: &Path,
+        result: JcResult<FileStats>,
+    ) -> JcResult<FileStats> {
+        let result = match result {
+            Ok(file) if self.fsync => sync_output(&file.output, Durability::Default).map(|_| file),
+            result => result,
+        };
+        let result = match (result, self.recovery) {
+            (Ok(file), Some(percent)) => {
+                let written = write_recovery(&file.output, percent).and_then(|path| {
+                    if self.fsync {
+                        sync_output(&path, Durability::Default)?;
+                    }
+                    Ok(path)
+                });
+                written.and_then(|path| match self.upload {
+                    Some(upload) => upload.send(&path).map(|_| file),
+                    None => Ok(file),
+                })
+            }
+            (result, _) => result,
+        };
+        let result = match (result, self.upload) {
+            (Ok(mut file), Some(upload)) => upload.send(&file.output).map(|output| {
+                file.output = output;
+                file
+            }),
+            (result, _) => result,
+        };
+        self.hooks.post_file(operation, input, result)
+    }
+}
+
+/// Ok when no input failed; otherwise `message` as a `BatchFailed` error,
+/// each failure having been reported already
+fn batch_result(message: &str, failures: Vec<JcError>, total: usize) -> JcResult<()> {
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(JcError::batch_failed(message, &failures, total))
+    }
+}
+
+/// Print `--stats` output: one line per file, then the totals
+/// `-v` output: one member per line, directories with a trailing `/`
+fn print_entry(entry: &ArchiveEntry) {
+    let path = entry.path.to_string_lossy();
+    // zip directory names already end in a slash
+    let slash = if entry.kind == EntryKind::Directory && !path.ends_with('/') {
+        "/"
+    } else {
+        ""
+    };
+    // Nothing to do about a closed stdout; the archive is still written
+    let _ = writeln!(io::stdout().lock(), "{}{}", path, slash);
+}
+
+fn execute_subcommand(subcommand: Commands) -> JcResult<()> {
+    match subcommand {
+        Commands::Identify { files } => handle_identify(files),
+        Commands::Tier {
+            rules,
+            dry_run,
+            dirs,
+        } => handle_tier(&rules, dry_run, dirs),
+        Commands::RestoreFile { files } => handle_restore_file(files),
+        Commands::Watch {
+            on_create: _,
+            command,
+            level,
+            pattern,
+            settle,
+            move_to,
+            encrypt_key,
+            encrypt_keyfile,
+            remove_source,
+            trash,
+            dir,
+        } => {
+            let encryption = match (encrypt_key, encrypt_keyfile) {
+                (Some(public_key_path), _) => Some(public_key_method(public_key_path)),
+                (None, Some(keyfile_path)) => Some(EncryptionMethod::Keyfile { keyfile_path }),
+                (None, None) => None,
+            };
+            // Ctrl-C ends the watch once the files in hand are done
+            let interrupt = interrupt::install()?;
+            let options = WatchOptions {
+                pattern,
+                settle: Duration::from_secs(settle),
+                cancel: Some(interrupt.clone()),
+            };
+            let config = CompressionConfig::new()
+                .with_encryption(encryption)
+                .with_trash(trash)
+                .with_interrupt(Some(interrupt));
+            handle_watch(
+                &dir,
+                &command,
+                &level,
+                move_to,
+                config,
+                remove_source,
+                &options,
+            )
+        }
+        Commands::Merge {
+            level,
+            on_collision,
+            force,
+            output,
+            inputs,
+        } => handle_merge(&level, &on_collision, force, &output, &inputs),
+        Commands::Rotate {
+            pattern,
+            into,
+            level,
+            keep,
+            trash,
+            dry_run,
+            dir,
+        } => {
+            let config = with_level(CompressionConfig::new(), &level)?
+                .with_external(load_external(None)?)
+                .with_trash(trash);
+            handle_rotate(&dir, &pattern, &into, keep, dry_run, &config)
+        }
+        Commands::Cleanup {
+            older_than,
+            dry_run,
+            dirs,
+        } => {
+            let cutoff = parse_time_bound(&older_than, SystemTime::now()).ok_or_else(|| {
+                JcError::Usage(format!(
+                    "Invalid --older-than: {} (expected an age such as 1d)",
+                    older_than
+                ))
+            })?;
+            handle_cleanup(&dirs, cutoff, dry_run)
+        }
+        Commands::Grep {
+            ignore_case,
+            fixed_strings,
+            line_number,
+            member,
+            pattern,
+            archives,
+        } => {
+            let options = GrepOptions {
+                pattern: build_pattern(&pattern, ignore_case, fixed_strings)?,
+                members: member,
+            };
+            handle_grep(&options, line_number, &archives)
+        }
+        Commands::Cat { archive, members } => handle_cat(&archive, &members),
+        Commands::Tree { archive } => {
+            let entries = list_archive(&archive, &CompressionConfig::new())?;
+            print!("{}", render_tree(&entries));
+            if detect_format(&archive) == Some(CompressionFormat::Zip) {
+                if let Some(comment) = zip::read_comment(&archive)? {
+                    println!("\nComment: {}", comment);
+                }
+            }
+            Ok(())
+        }
+        Commands::Verify {
+            decrypt_key,
+            shares,
+            decrypt_keyfile,
+            require_tag,
+            threads,
+            files,
+        } => handle_verify(
+            decryption_method(decrypt_key, decrypt_keyfile, shares),
+            require_tag,
+            threads,
+            files,
+        ),
+        Commands::Repair { files } => handle_repair(files),
+        Commands::Benchmark { sample } => handle_benchmark(&sample),
+        Commands::Estimate {
+            command,
+            level,
+            sample,
+            full,
+            inputs,
+        } => handle_estimate(&command, &level, sample.as_deref(), full, &inputs),
+        Commands::Analyze { top, targets } => handle_analyze(top, &targets),
+        Commands::Keygen {
+            key_type,
+            bits,
+            name,
+        } => handle_keygen(&key_type, bits, &name),
+        Commands::GenKeyfile { path } => handle_gen_keyfile(&path),
+        Commands::Encrypt {
+            encrypt_key,
+            split_key,
+            encrypt_keyfile,
+            pad_to,
+            files,
+        } => handle_encrypt(encrypt_key, split_key, encrypt_keyfile, pad_to, files),
+        Commands::Reencrypt {
+            decrypt_key,
+            encrypt_key,
+            files,
+        } => handle_reencrypt(decrypt_key, encrypt_key, files),
+        Commands::Decrypt {
+            decrypt_key,
+            shares,
+            decrypt_keyfile,
+            remove_encrypted,
+            trash,
+            files,
+        } => handle_decrypt(
+            decrypt_key,
+            shares,
+            decrypt_keyfile,
+            remove_encrypted,
+            trash,
+            files,
+        ),
+    }
+}
+
+/// `config` with the `-l` value applied; presets are resolved by each
+/// compressor
+fn with_level(config: CompressionConfig, level: &str) -> JcResult<CompressionConfig> {
+    let (level, extreme) = parse_level(level).map_err(JcError::Usage)?;
+    let config = match level {
+        Level::Number(level) => config.with_level(level),
+        Level::Preset(preset) => config.with_level_preset(Some(preset)),
+    };
+    if !extreme {
+        return Ok(config);
+    }
+    let tuning = XzTuning {
+        extreme,
+        ..config.xz_tuning()
+    };
+    Ok(config.with_format_options(CompressionFormat::Xz.name(), FormatOptions::Xz(tuning)))
+}
+
+/// `config` asking the compressor behind `command` for rsync-friendly
+/// output: a registered compressor, else gzip (`-c gzip` or `tgz`)
+fn with_rsyncable(config: CompressionConfig, command: &str) -> CompressionConfig {
+    let registered = config
+        .external
+        .as_ref()
+        .and_then(|registry| registry.get(command))
+        .map(|compressor| compressor.name());
+    match registered {
+        Some(name) => config.with_format_options(
+            name,
+            FormatOptions::Command(CommandTuning { rsyncable: true }),
+        ),
+        None => config.with_format_options(
+            CompressionFormat::Gzip.name(),
+            FormatOptions::Gzip(GzipTuning { rsyncable: true }),
+        ),
+    }
+}
+
+/// Combine `inputs` into the archive `output`
+fn handle_merge(
+    level: &str,
+    on_collision: &str,
+    force: bool,
+    output: &Path,
+    inputs: &[PathBuf],
+) -> JcResult<()> {
+    let policy = CollisionPolicy::from_name(on_collision).ok_or_else(|| {
+        JcError::Usage(format!(
+            "Invalid --on-collision: {} (expected error, first or last)",
+            on_collision
+        ))
+    })?;
+    let config = with_level(CompressionConfig::new(), level)?
+        .with_external(load_external(None)?)
+        .with_force(force)
+        .with_overwrite(if force {
+            OverwritePolicy::Overwrite
+        } else {
+            OverwritePolicy::Keep
+        });
+
+    let collisions = merge_archives(output, inputs, policy, &config)?;
+    if !collisions.is_empty() {
+        info!(
+            "{} colliding paths resolved in favour of the {} input",
+            collisions.len(),
+            on_collision
+        );
+    }
+    Ok(())
+}
+
+/// Print each file `rotate_logs` archived (or would archive, with
+/// `dry_run`) and where
+fn handle_rotate(
+    dir: &Path,
+    pattern: &str,
+    into: &str,
+    keep: bool,
+    dry_run: bool,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    let Some(rotation) = rotate_logs(dir, pattern, into, keep, dry_run, config)? else {
+        return Ok(());
+    };
+    for file in &rotation.files {
+        println!(
+            "{}{} -> {}",
+            if dry_run { "would rotate " } else { "" },
+            file.display(),
+            rotation.archive.display()
+        );
+    }
+    Ok(())
+}
+
+fn handle_cleanup(dirs: &[PathBuf], cutoff: SystemTime, dry_run: bool) -> JcResult<()> {
+    let orphans = clean_orphans(dirs, cutoff, dry_run)?;
+    if orphans.is_empty() {
+        info!("No temp directories left behind");
+    }
+    for orphan in &orphans {
+        let verb = if dry_run { "would remove" } else { "removed" };
+        println!("{} {}", verb, orphan.display());
+    }
+    Ok(())
+}
+
+/// Print `member:line` for every match in `archives`, prefixed with the
+/// archive when there are several
+fn handle_grep(options: &GrepOptions, line_number: bool, archives: &[PathBuf]) -> JcResult<()> {
+    let config = CompressionConfig::new();
+    let mut stdout = io::stdout().lock();
+    let mut matches = 0;
+    let mut failures = Vec::new();
+    for archive in archives {
+        let prefix = if archives.len() > 1 {
+            format!("{}:", archive.display())
+        } else {
+            String::new()
+        };
+        let searched = grep_archive(archive, options, &config, |m| {
+            if m.binary {
+                writeln!(stdout, "{}{}: binary file matches", prefix, m.member)
+            } else if line_number {
+                writeln!(
+                    stdout,
+                    "{}{}:{}:{}",
+                    prefix, m.member, m.line_number, m.line
+                )
+            } else {
+                writeln!(stdout, "{}{}:{}", prefix, m.member, m.line)
+            }
+        });
+        match searched {
+            Ok(count) => matches += count,
+            // Output piped into `head` and closed: the reader has what it wanted
+            Err(JcError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => {
+                error!("Failed to search {}: {}", archive.display(), e);
+                failures.push(e);
+            }
+        }
+    }
+
+    if matches == 0 {
+        info!("No matches");
+    }
+    batch_result(
+        "Some archives could not be searched",
+        failures,
+        archives.len(),
+    )
+}
+
+fn handle_cat(archive: &Path, members: &[String]) -> JcResult<()> {
+    let config = CompressionConfig::new();
+    let mut stdout = io::stdout().lock();
+    for member in members {
+        match cat_member(archive, member, &config, &mut stdout) {
+            Ok(_) => {}
+            // Output piped into `head` and closed: the reader has what it wanted
+            Err(JcError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Compress each batch of settled files in `dir` with `command`
+fn handle_watch(
+    dir: &Path,
+    command: &str,
+    level: &str,
+    move_to: Option<PathBuf>,
+    config: CompressionConfig,
+    remove_source: bool,
+    options: &WatchOptions,
+) -> JcResult<()> {
+    let config = with_level(config, level)?.with_external(load_external(None)?);
+    let config = match move_to {
+        Some(move_to) => {
+            validate_move_to(&move_to)?;
+            config.with_move_to(move_to)
+        }
+        None => config,
+    };
+    // Unknown commands fail now rather than on the first file
+    let registered = config
+        .external
+        .as_ref()
+        .is_some_and(|registry| registry.get(command).is_some());
+    if CompoundFormat::from_str(command).is_none()
+        && CompressionFormat::from_name(command).is_none()
+        && !registered
+    {
+        return Err(JcError::InvalidCommand(command.to_string()));
+    }
+    validate_command_level(command, &config)?;
+    require_command_tools(command, &config)?;
+
+    watch_directory(dir, options, |files| {
+        let results = match compress_batch(files, command, config.clone()) {
+            Ok(results) => results,
+            Err(e) => {
+                error!("Failed to compress: {}", e);
+                return;
+            }
+        };
+        for (file, result) in results {
+            match result {
+                Ok(stats) => {
+                    info!("{} -> {}", file.display(), stats.output.display());
+                    if remove_source {
+                        // The output must survive a crash the source doesn't
+                        let removed = sync_output(&stats.output, Durability::Default)
+                            .and_then(|_| remove_original(&file, config.trash));
+                        if let Err(e) = removed {
+                            error!("Failed to remove {}: {}", file.display(), e);
+                        }
+                    }
+                }
+                Err(e) => error!("Failed to compress {}: {}", file.display(), e),
+            }
+        }
+    })
+}
+
+fn handle_tier(rules_path: &Path, dry_run: bool, dirs: Vec<PathBuf>) -> JcResult<()> {
+    let rules = TierRules::load(rules_path)?;
+
+    let total = dirs.len();
+    let mut failures = Vec::new();
+    for dir in dirs {
+        match tier_directory(&dir, &rules, dry_run) {
+            Ok(entries) => {
+                let before: u64 = entries.iter().map(|e| e.original_size).sum();
+                let after: u64 = entries.iter().map(|e| e.archive_size).sum();
+                for entry in &entries {
+                    println!(
+                        "{}{} -> {}",
+                        if dry_run { "would tier " } else { "" },
+                        entry.original.display(),
+                        entry.archive.display()
+                    );
+                }
+                if dry_run {
+                    println!(
+                        "{}: {} files, {} bytes",
+                        dir.display(),
+                        entries.len(),
+                        before
+                    );
+                } else {
+                    println!(
+                        "{}: tiered {} files, {} -> {} bytes",
+                        dir.display(),
+                        entries.len(),
+                        before,
+                        after
+                    );
+                }
+            }
+            Err(e) => {
+                error!("Failed to tier {}: {}", dir.display(), e);
+                failures.push(e);
+            }
+        }
+    }
+
+    batch_result("Some directories failed to tier", failures, total)
+}
+
+fn handle_restore_file(files: Vec<PathBuf>) -> JcResult<()> {
+    let total = files.len();
+    let mut failures = Vec::new();
+    for file in files {
+        if let Err(e) = restore_tiered_file(&file) {
+            error!("Failed to restore {}: {}", file.display(), e);
+            failures.push(e);
+        }
+    }
+
+    batch_result("Some files could not be restored", failures, total)
+}
+
+/// Verify `files` in parallel and print a table of the results
+fn handle_verify(
+    decryption: Option<DecryptionMethod>,
+    require_tag: bool,
+    threads: Option<usize>,
+    files: Vec<PathBuf>,
+) -> JcResult<()> {
+    if threads == Some(0) {
+        return Err(JcError::Usage("--threads must be at least 1".to_string()));
+    }
+    let secrets = Secrets::new(prompt_password);
+
+    let results: Vec<JcResult<Verification>> = run_limited(threads, || {
+        files
+            .par_iter()
+            .map(|file| {
+                let result = verify_archive(file, decryption.as_ref(), Some(&secrets))
+                    .and_then(|verification| {
+                        if require_tag && !verification.is_tagged() {
+                            return Err(JcError::Other(format!(
+                                "{} has no integrity tag or manifest (create it with encryption, --sidecar-metadata or --manifest)",
+                                file.display()
+                            )));
+                        }
+                        Ok(verification)
+                    });
+                if let Err(ref e) = result {
+                    error!("Failed to verify {}: {}", file.display(), e);
+                }
+                result
+            })
+            .collect()
+    });
+    print_verify_table(&files, &results);
+
+    let total = files.len();
+    let failures = results.into_iter().filter_map(Result::err).collect();
+    batch_result("Some files failed verification", failures, total)
+}
+
+fn handle_repair(files: Vec<PathBuf>) -> JcResult<()> {
+    let total = files.len();
+    let mut failures = Vec::new();
+    for file in files {
+        match repair(&file) {
+            Ok(0) => println!("{}: {} (intact)", file.display(), styled("OK", Tone::Good)),
+            Ok(blocks) => println!(
+                "{}: {} ({} damaged blocks rebuilt)",
+                file.display(),
+                styled("REPAIRED", Tone::Good),
+                blocks
+            ),
+            Err(e) => {
+                error!("Failed to repair {}: {}", file.display(), e);
+                failures.push(e);
+            }
+        }
+    }
+
+    batch_result("Some files could not be repaired", failures, total)
+}
+
+fn handle_encrypt(
+    encrypt_key: Option<PathBuf>,
+    split_key: Option<String>,
+    encrypt_keyfile: Option<PathBuf>,
+    pad_to: Option<String>,
+    files: Vec<PathBuf>,
+) -> JcResult<()> {
+    let pad_to = match pad_to {
+        Some(size) => Some(parse_pad_to(&size).ok_or_else(|| {
+            JcError::Other(format!("Invalid --pad-to: {} (expected e.g. 4K)", size))
+        })?),
+        None => None,
+    };
+    let method = if let Some(public_key_path) = encrypt_key {
+        public_key_method(public_key_path)
+    } else if let Some(keyfile_path) = encrypt_keyfile {
+        EncryptionMethod::Keyfile { keyfile_path }
+    } else if let Some(split) = split_key {
+        let (threshold, shares) = parse_split_key(&split).ok_or_else(|| {
+            JcError::Other(format!(
+                "Invalid --split-key: {} (expected K/N with 2 <= K <= N <= 255)",
+                split
+            ))
+        })?;
+        EncryptionMethod::Threshold { threshold, shares }
+    } else {
+        EncryptionMethod::Password
+    };
+    let secrets = Secrets::new(prompt_password);
+
+    let total = files.len();
+    let results = encrypt_only(files.clone(), &method, Some(&secrets), pad_to);
+    let mut failures = Vec::new();
+    for (file, result) in files.iter().zip(results) {
+        if let Err(e) = result {
+            error!("Failed to encrypt {}: {}", file.display(), e);
+            failures.push(e);
+        }
+    }
+
+    batch_result("Some files failed to encrypt", failures, total)
+}
+
+fn handle_reencrypt(
+    decrypt_key: Option<PathBuf>,
+    encrypt_key: Option<PathBuf>,
+    files: Vec<PathBuf>,
+) -> JcResult<()> {
+    let decryption = decrypt_key.map(private_key_method);
+    let encryption = match encrypt_key {
+        Some(public_key_path) => public_key_method(public_key_path),
+        None => EncryptionMethod::Password,
+    };
+    let secrets = Secrets::new(prompt_password);
+
+    let total = files.len();
+    let failures: Vec<JcError> =
+        reencrypt_files(files, decryption.as_ref(), &encryption, Some(&secrets))
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+
+    batch_result("Some files failed to re-encrypt", failures, total)
+}
+
+/// `--encrypt-key` method for the kind of key in the file (RSA unless it
+/// is an X25519 key)
+fn public_key_method(public_key_path: PathBuf) -> EncryptionMethod {
+    if X25519Encryption::is_x25519_key_file(&public_key_path) {
+        EncryptionMethod::X25519 { public_key_path }
+    } else {
+        EncryptionMethod::Rsa { public_key_path }
+    }
+}
+
+/// `--decrypt-key` method for the kind of key in the file
+fn private_key_method(private_key_path: PathBuf) -> DecryptionMethod {
+    if X25519Encryption::is_x25519_key_file(&private_key_path) {
+        DecryptionMethod::X25519 { private_key_path }
+    } else {
+        DecryptionMethod::Rsa { private_key_path }
+    }
+}
+
+/// Private or public key, keyfile or share files given for decryption
+fn decryption_method(
+    decrypt_key: Option<PathBuf>,
+    decrypt_keyfile: Option<PathBuf>,
+    shares: Vec<PathBuf>,
+) -> Option<DecryptionMethod> {
+    if let Some(private_key_path) = decrypt_key {
+        Some(private_key_method(private_key_path))
+    } else if let Some(keyfile_path) = decrypt_keyfile {
+        Some(DecryptionMethod::Keyfile { keyfile_path })
+    } else if !shares.is_empty() {
+        Some(DecryptionMethod::Shares {
+            share_paths: shares,
+        })
+    } else {
+        None
+    }
+}
+
+/// `jcz decrypt` is `jcz -d --no-decompress` without the other options
+fn handle_decrypt(
+    decrypt_key: Option<PathBuf>,
+    shares: Vec<PathBuf>,
+    decrypt_keyfile: Option<PathBuf>,
+    remove_encrypted: bool,
+    trash: bool,
+    files: Vec<PathBuf>,
+) -> JcResult<()> {
+    let decryption = decryption_method(decrypt_key, decrypt_keyfile, shares);
+    let config = CompressionConfig::new()
+        .with_secrets(Some(Secrets::new(prompt_password)))
+        .with_decrypt_only(true)
+        .with_trash(trash);
+
+    handle_decompress(
+        files,
+        config,
+        decryption,
+        remove_encrypted,
+        &[],
+        &Followup {
+            report: None,
+            hooks: &Hooks::default(),
+            upload: None,
+            recovery: None,
+            fsync: false,
+        },
+    )
+}
+
+fn handle_keygen(key_type: &str, bits: usize, name: &Path) -> JcResult<()> {
+    let with_suffix = |suffix: &str| {
+        let mut path = name.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
     };
+    let private_path = with_suffix(".pem");
+    let public_path = with_suffix(".pub.pem");
+    for path in [&private_path, &public_path] {
+        if path.exists() {
+            return Err(JcError::Other(format!(
+                "Refusing to overwrite existing file: {}",
+                path.display()
+            )));
+        }
+    }
 
-    collect_and_compress(inputs, compound, collection_config)?;
+    let (private_pem, public_pem) = match key_type {
+        "rsa" => generate_rsa_key_pair(bits)?,
+        "x25519" => {
+            let (private_key, public_key) = X25519Encryption::generate_key_pair()?;
+            (
+                X25519Encryption::private_key_to_pem(&private_key),
+                X25519Encryption::public_key_to_pem(&public_key),
+            )
+        }
+        other => {
+            return Err(JcError::Other(format!(
+                "Unknown key type: {} (expected rsa or x25519)",
+                other
+            )))
+        }
+    };
 
+    write_new_private_file(&private_path, private_pem.as_bytes())?;
+    fs::write(&public_path, public_pem)?;
+    println!("Private key: {}", private_path.display());
+    println!("Public key:  {}", public_path.display());
     Ok(())
 }
+
+fn handle_gen_keyfile(path: &Path) -> JcResult<()> {
+    if path.exists() {
+        return Err(JcError::Other(format!(
+            "Refusing to overwrite existing file: {}",
+            path.display()
+        )));
+    }
+    generate_keyfile(path)?;
+    println!("Keyfile written: {}", path.display());
+    println!("Anyone who can read it can decrypt files encrypted with it; keep a backup.");
+    Ok(())
+}
+
+fn handle_benchmark(sample: &Path) -> JcResult<()> {
+    let mut results = benchmark(sample)?;
+    if results.is_empty() {
+        return Err(JcError::Other(format!(
+            "No format could compress {}",
+            sample.display()
+        )));
+    }
+    results.sort_by_key(|r| (r.compressed_size, r.elapsed));
+
+    println!(
+        "{}: {}, smallest first",
+        sample.display(),
+        format_size(results[0].original_size)
+    );
+    println!("{}", BENCHMARK_HEADER);
+    for result in &results {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+/// Print the predicted output size of each of `inputs` with `command`
+fn handle_estimate(
+    command: &str,
+    level: &str,
+    sample: Option<&str>,
+    full: bool,
+    inputs: &[PathBuf],
+) -> JcResult<()> {
+    let sample = match sample {
+        Some(text) => Some(parse_size(text).filter(|size| *size > 0).ok_or_else(|| {
+            JcError::Usage(format!("Invalid --sample: {} (expected e.g. 64M)", text))
+        })?),
+        None if full => None,
+        None => Some(DEFAULT_SAMPLE),
+    };
+    let config = with_level(CompressionConfig::new(), level)?;
+
+    let total = inputs.len();
+    let mut failures = Vec::new();
+    for input in inputs {
+        match estimate(input, command, sample, &config) {
+            Ok(estimate) => println!("{}", estimate),
+            // An unusable command fails for every input alike
+            Err(e @ JcError::Usage(_)) => return Err(e),
+            Err(e) => {
+                error!("Failed to estimate {}: {}", input.display(), e);
+                failures.push(e);
+            }
+        }
+    }
+
+    batch_result("Some inputs could not be estimated", failures, total)
+}
+
+fn handle_analyze(top: usize, targets: &[PathBuf]) -> JcResult<()> {
+    let config = CompressionConfig::new();
+    let total = targets.len();
+    let mut failures = Vec::new();
+    for target in targets {
+        match analyze(target, top, &config) {
+            Ok(analysis) => println!("{}", analysis),
+            Err(e) => {
+                error!("Failed to analyze {}: {}", target.display(), e);
+                failures.push(e);
+            }
+        }
+    }
+
+    batch_result("Some targets could not be analyzed", failures, total)
+}
+
+fn handle_identify(files: Vec<PathBuf>) -> JcResult<()> {
+    let total = files.len();
+    let mut failures = Vec::new();
+    for file in files {
+        match identify_file(&file) {
+            Ok(ident) => println!("{}", ident),
+            Err(e) => {
+                error!("Failed to identify {}: {}", file.display(), e);
+                failures.push(e);
+            }
+        }
+    }
+
+    batch_result("Some files could not be identified", failures, total)
+}
@@ -0,0 +1,220 @@
+//! Commands run around a batch and each of its files
+//!
+//! Hooks come from `--pre-cmd`, `--post-cmd`, `--pre-batch-cmd` and
+//! `--post-batch-cmd`, or from a TOML file (`--hooks`, by default
+//! `~/.config/jcz/hooks.toml`):
+//!
+//! ```toml
+//! [hooks]
+//! pre_batch = "logger jcz starting"
+//! post_file = "rclone copy \"$JCZ_OUTPUT\" remote:backups"
+//! ```
+//!
+//! Each hook runs through the shell (`sh -c`, or `cmd /C` on Windows) with
+//! `JCZ_HOOK`, `JCZ_OPERATION` and, depending on the hook, `JCZ_INPUT`,
+//! `JCZ_OUTPUT`, `JCZ_STATUS`, `JCZ_ERROR`, `JCZ_INPUTS`, `JCZ_TOTAL` and
+//! `JCZ_FAILED` set. Files are processed in parallel, so every `pre_file`
+//! hook runs before the batch starts and every `post_file` hook after it
+//! ends, in input order.
+
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::core::error::{JcError, JcResult};
+use crate::core::report::Operation;
+use crate::operations::FileStats;
+use crate::utils::{debug, user_config_file};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HookFile {
+    #[serde(default)]
+    hooks: Hooks,
+}
+
+/// Shell commands to run around a batch
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    pub pre_batch: Option<String>,
+    pub pre_file: Option<String>,
+    pub post_file: Option<String>,
+    pub post_batch: Option<String>,
+}
+
+impl Hooks {
+    /// Hooks from `path` if given, else from the default file if there is
+    /// one
+    pub fn load(path: Option<&Path>) -> JcResult<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match user_config_file("hooks.toml").filter(|p| p.is_file()) {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        let text =
+            fs::read_to_string(&path).map_err(|_| JcError::FileNotFound(path.to_path_buf()))?;
+        let file: HookFile = toml::from_str(&text).map_err(|e| {
+            JcError::Other(format!(
+                "Invalid hooks file {}: {}",
+                path.display(),
+                e.message()
+            ))
+        })?;
+        Ok(file.hooks)
+    }
+
+    /// Replace the hooks given on the command line
+    pub fn with_overrides(self, overrides: Hooks) -> Self {
+        Hooks {
+            pre_batch: overrides.pre_batch.or(self.pre_batch),
+            pre_file: overrides.pre_file.or(self.pre_file),
+            post_file: overrides.post_file.or(self.post_file),
+            post_batch: overrides.post_batch.or(self.post_batch),
+        }
+    }
+
+    /// Run the `pre_batch` hook; when it fails, nothing is processed
+    pub fn pre_batch(&self, operation: Operation, inputs: &[PathBuf]) -> JcResult<()> {
+        let mut names = OsString::new();
+        for (i, input) in inputs.iter().enumerate() {
+            if i > 0 {
+                names.push("\n");
+            }
+            names.push(input);
+        }
+        run(
+            "pre_batch",
+            self.pre_batch.as_deref(),
+            operation,
+            &[("JCZ_INPUTS", names), ("JCZ_TOTAL", count(inputs.len()))],
+        )
+    }
+
+    /// Run the `pre_file` hook for `input`; when it fails, the input is
+    /// skipped
+    pub fn pre_file(&self, operation: Operation, input: &Path) -> JcResult<()> {
+        run(
+            "pre_file",
+            self.pre_file.as_deref(),
+            operation,
+            &[("JCZ_INPUT", input.into())],
+        )
+        .map_err(|e| for_input(input, e))
+    }
+
+    /// Run `pre_file` for every input, pairing those whose hook failed
+    /// with the error
+    pub fn pre_files(
+        &self,
+        operation: Operation,
+        inputs: Vec<PathBuf>,
+    ) -> (Vec<PathBuf>, Vec<(PathBuf, JcResult<FileStats>)>) {
+        let mut kept = Vec::new();
+        let mut skipped = Vec::new();
+        for input in inputs {
+            match self.pre_file(operation, &input) {
+                Ok(()) => kept.push(input),
+                Err(e) => skipped.push((input, Err(e))),
+            }
+        }
+        (kept, skipped)
+    }
+
+    /// Run the `post_file` hook for `input` and its outcome; a failing
+    /// hook turns the outcome into a failure
+    pub fn post_file(
+        &self,
+        operation: Operation,
+        input: &Path,
+        result: JcResult<FileStats>,
+    ) -> JcResult<FileStats> {
+        let mut env = vec![("JCZ_INPUT", input.into())];
+        match &result {
+            Ok(file) => {
+                env.push(("JCZ_STATUS", "ok".into()));
+                env.push(("JCZ_OUTPUT", file.output.clone().into()));
+            }
+            Err(e) => {
+                let status = if e.is_cancellation() {
+                    "cancelled"
+                } else {
+                    "failed"
+                };
+                env.push(("JCZ_STATUS", status.into()));
+                env.push(("JCZ_ERROR", e.to_string().into()));
+            }
+        }
+        let hook = run("post_file", self.post_file.as_deref(), operation, &env)
+            .map_err(|e| for_input(input, e));
+        result.and_then(|file| hook.map(|()| file))
+    }
+
+    /// Run the `post_batch` hook once the batch is done, successful or not
+    pub fn post_batch(&self, operation: Operation, total: usize, failed: usize) -> JcResult<()> {
+        let status = if failed == 0 { "ok" } else { "failed" };
+        run(
+            "post_batch",
+            self.post_batch.as_deref(),
+            operation,
+            &[
+                ("JCZ_STATUS", status.into()),
+                ("JCZ_TOTAL", count(total)),
+                ("JCZ_FAILED", count(failed)),
+            ],
+        )
+    }
+}
+
+fn for_input(input: &Path, e: JcError) -> JcError {
+    JcError::Other(format!("{}: {}", input.display(), e))
+}
+
+fn count(n: usize) -> OsString {
+    n.to_string().into()
+}
+
+/// Run `command` through the shell as the `hook` hook, failing unless it
+/// exits successfully
+fn run(
+    hook: &str,
+    command: Option<&str>,
+    operation: Operation,
+    env: &[(&str, OsString)],
+) -> JcResult<()> {
+    let Some(command) = command else {
+        return Ok(());
+    };
+    debug!("Running {} hook: {}", hook, command);
+
+    let status = shell(command)
+        .env("JCZ_HOOK", hook)
+        .env("JCZ_OPERATION", operation.name())
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .status()
+        .map_err(|e| JcError::Other(format!("Failed to run {} hook: {}", hook, e)))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(JcError::Other(format!("{} hook failed ({})", hook, status)))
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
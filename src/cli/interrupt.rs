@@ -0,0 +1,32 @@
+//! Ctrl-C handling for compression and decompression runs
+//!
+//! The first SIGINT or SIGTERM stops the batch from starting further
+//! inputs (see `CompressionConfig::interrupt`). Inputs in progress run to
+//! their end: the external tools they run get the terminal's SIGINT too
+//! and exit, their partial outputs are removed as for any failure, and
+//! scratch space is cleaned up as the batch returns. The run then exits
+//! with `EXIT_INTERRUPTED`. A second signal exits at once.
+
+use crate::core::error::{JcError, JcResult, EXIT_INTERRUPTED};
+use crate::utils::CancellationToken;
+
+/// Install the handler, returning the token it cancels
+pub fn install() -> JcResult<CancellationToken> {
+    let token = CancellationToken::new();
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let token = token.clone();
+        // SAFETY: the action only touches an atomic flag and calls
+        // `_exit`, both async-signal-safe
+        let registered = unsafe {
+            signal_hook_registry::register(signal, move || {
+                if token.is_cancelled() {
+                    libc::_exit(EXIT_INTERRUPTED);
+                }
+                token.cancel();
+            })
+        };
+        registered
+            .map_err(|e| JcError::Other(format!("Failed to install the Ctrl-C handler: {}", e)))?;
+    }
+    Ok(token)
+}
@@ -0,0 +1,59 @@
+//! OS keychain passwords (`--use-keyring NAME`)
+//!
+//! Entries live in the Secret Service (Linux), Keychain (macOS) or
+//! Credential Manager (Windows) under the service `jcz`, with NAME as the
+//! account. Encryption stores the password on first use; decryption only
+//! reads it. Needs the `keyring` feature.
+
+use crate::core::config::Secrets;
+use crate::core::error::{JcError, JcResult};
+
+/// Keychain service the entries are filed under
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "jcz";
+
+/// Password provider backed by the keychain entry `name`
+#[cfg(feature = "keyring")]
+pub fn keyring_secrets(name: &str) -> JcResult<Secrets> {
+    use crate::cli::prompt::prompt_password;
+    use crate::core::config::SecretPurpose;
+    use crate::utils::info;
+    use zeroize::Zeroizing;
+
+    let keyring_error = |e: keyring::Error| JcError::Other(format!("Keyring error: {}", e));
+    let entry = keyring::Entry::new(KEYRING_SERVICE, name).map_err(keyring_error)?;
+    let name = name.to_string();
+
+    Ok(Secrets::new(move |purpose: SecretPurpose| {
+        match entry.get_password() {
+            Ok(password) => return Ok(Zeroizing::new(password)),
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(keyring_error(e)),
+        }
+
+        match purpose {
+            SecretPurpose::Encrypt => {
+                let password = prompt_password(purpose)?;
+                if password.is_empty() {
+                    return Err(JcError::Other("Password cannot be empty".to_string()));
+                }
+                entry.set_password(&password).map_err(keyring_error)?;
+                info!("Stored the password in keyring entry '{}'", name);
+                Ok(password)
+            }
+            // A mistyped password must not end up in the keychain
+            SecretPurpose::Decrypt => Err(JcError::Other(format!(
+                "No password stored in the keyring under '{}'",
+                name
+            ))),
+        }
+    }))
+}
+
+/// Without the `keyring` feature there is no keychain to talk to
+#[cfg(not(feature = "keyring"))]
+pub fn keyring_secrets(_name: &str) -> JcResult<Secrets> {
+    Err(JcError::Other(
+        "--use-keyring needs jcz built with the `keyring` feature".to_string(),
+    ))
+}
@@ -1,5 +1,10 @@
 pub mod args;
 pub mod commands;
+mod hooks;
+mod interrupt;
+mod keyring;
+pub mod output;
+mod prompt;
 
 pub use args::CliArgs;
 pub use commands::execute;
@@ -0,0 +1,297 @@
+//! Styled terminal output
+//!
+//! When stdout is a terminal, results are colored and `--stats` is printed
+//! as an aligned table. Pipes and files get the plain lines scripts rely
+//! on, and `--no-color` (or a non-empty `NO_COLOR`) keeps the layout but
+//! drops the colors.
+
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::core::error::JcResult;
+use crate::operations::stats::format_size;
+use crate::operations::{FileStats, RunStats, Verification};
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+/// How a status fragment is highlighted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Good,
+    Warning,
+    Error,
+    Strong,
+}
+
+impl Tone {
+    fn code(self) -> &'static str {
+        match self {
+            Tone::Good => "1;32",
+            Tone::Warning => "1;33",
+            Tone::Error => "1;31",
+            Tone::Strong => "1",
+        }
+    }
+}
+
+/// What the terminal on stdout and stderr can show
+#[derive(Debug, Clone, Copy, Default)]
+struct Output {
+    /// stdout is a terminal: tables are aligned
+    interactive: bool,
+
+    /// stdout may be colored
+    color: bool,
+
+    /// stderr may be colored
+    color_stderr: bool,
+}
+
+/// Whether colors are allowed at all: not with `--no-color` or `NO_COLOR`
+pub fn colors_allowed(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Detect the terminals, once, before anything is printed
+pub fn init(no_color: bool) {
+    let allowed = colors_allowed(no_color);
+    let interactive = io::stdout().is_terminal();
+    OUTPUT.get_or_init(|| Output {
+        interactive,
+        color: allowed && interactive,
+        color_stderr: allowed && io::stderr().is_terminal(),
+    });
+}
+
+fn output() -> Output {
+    OUTPUT.get().copied().unwrap_or_default()
+}
+
+fn paint(text: &str, tone: Tone, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", tone.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// `text` highlighted for stdout
+pub fn styled(text: &str, tone: Tone) -> String {
+    paint(text, tone, output().color)
+}
+
+/// `text` highlighted for stderr
+pub fn styled_stderr(text: &str, tone: Tone) -> String {
+    paint(text, tone, output().color_stderr)
+}
+
+/// Print `--stats`: one line per file and the total
+pub fn print_stats(run: &RunStats) {
+    if !output().interactive {
+        for file in &run.files {
+            println!("{}", file);
+        }
+        println!("{}", run);
+        return;
+    }
+    for line in stats_table(run) {
+        println!("{}", line);
+    }
+}
+
+/// Print `jcz verify` results: a table of each archive, OK or FAILED, and
+/// what was checked or why it failed
+pub fn print_verify_table(files: &[PathBuf], results: &[JcResult<Verification>]) {
+    for line in verify_table(files, results) {
+        println!("{}", line);
+    }
+}
+
+/// `jcz verify` results as aligned columns under a header
+fn verify_table(files: &[PathBuf], results: &[JcResult<Verification>]) -> Vec<String> {
+    let rows: Vec<(String, bool, String)> = files
+        .iter()
+        .zip(results)
+        .map(|(file, result)| match result {
+            Ok(verification) => (
+                file.display().to_string(),
+                true,
+                verification.checks().join("; "),
+            ),
+            Err(e) => (file.display().to_string(), false, e.to_string()),
+        })
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(name, _, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("ARCHIVE".len());
+
+    let mut lines = vec![styled(
+        &format!("{:<width$}  {:<6}  DETAILS", "ARCHIVE", "RESULT"),
+        Tone::Strong,
+    )];
+    lines.extend(rows.iter().map(|(name, passed, details)| {
+        let result = if *passed {
+            styled(&format!("{:<6}", "OK"), Tone::Good)
+        } else {
+            styled("FAILED", Tone::Error)
+        };
+        format!("{:<width$}  {}  {}", name, result, details)
+    }));
+    lines
+}
+
+/// Size before and after, in the direction of the run
+fn sizes(file: &FileStats) -> (u64, u64) {
+    if file.decompressed {
+        (file.compressed_size, file.original_size)
+    } else {
+        (file.original_size, file.compressed_size)
+    }
+}
+
+/// `--stats` as aligned columns: names, sizes, ratio, time and speed
+fn stats_table(run: &RunStats) -> Vec<String> {
+    let mut rows: Vec<[String; 6]> = run
+        .files
+        .iter()
+        .map(|file| {
+            let (from, to) = sizes(file);
+            [
+                format!("{} -> {}", file.input.display(), file.output.display()),
+                format_size(from),
+                format_size(to),
+                format!("{:.1}%", file.ratio() * 100.0),
+                format!("{:.2}s", file.elapsed.as_secs_f64()),
+                format!("{}/s", format_size(file.throughput() as u64)),
+            ]
+        })
+        .collect();
+    let (from, to) = run
+        .files
+        .iter()
+        .map(sizes)
+        .fold((0, 0), |(a, b), (from, to)| (a + from, b + to));
+    rows.push([
+        format!("Total: {} files", run.files.len()),
+        format_size(from),
+        format_size(to),
+        format!("{:.1}%", run.ratio() * 100.0),
+        format!("{:.2}s", run.wall_time.as_secs_f64()),
+        format!("{}/s", format_size(run.throughput() as u64)),
+    ]);
+
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let (name, grew) = match run.files.get(i) {
+                Some(file) => {
+                    let (from, to) = sizes(file);
+                    (format!("{:<w$}", row[0], w = widths[0]), to > from)
+                }
+                None => (
+                    styled(&format!("{:<w$}", row[0], w = widths[0]), Tone::Strong),
+                    to > from,
+                ),
+            };
+            let ratio = format!("{:>w$}", row[3], w = widths[3]);
+            format!(
+                "{}  {:>w1$} -> {:>w2$}  {}  {:>w4$}  {:>w5$}",
+                name,
+                row[1],
+                row[2],
+                if grew {
+                    styled(&ratio, Tone::Warning)
+                } else {
+                    ratio
+                },
+                row[4],
+                row[5],
+                w1 = widths[1],
+                w2 = widths[2],
+                w4 = widths[4],
+                w5 = widths[5],
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn stats(input: &str, original_size: u64, compressed_size: u64) -> FileStats {
+        FileStats {
+            input: PathBuf::from(input),
+            output: PathBuf::from(format!("{}.gz", input)),
+            original_size,
+            compressed_size,
+            elapsed: Duration::from_millis(500),
+            decompressed: false,
+        }
+    }
+
+    #[test]
+    fn test_stats_table_aligns_columns() {
+        let run = RunStats::new(
+            vec![
+                stats("a.log", 10 << 20, 1 << 20),
+                stats("long-name.bin", 100, 120),
+            ],
+            Duration::from_secs(1),
+        );
+        let lines = stats_table(&run);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with("Total: 2 files"));
+        let arrows: Vec<_> = lines.iter().map(|line| line.rfind(" -> ")).collect();
+        assert!(
+            arrows.iter().all(|&arrow| arrow == arrows[0]),
+            "{:#?}",
+            lines
+        );
+        let widths: Vec<_> = lines.iter().map(|line| line.len()).collect();
+        assert!(
+            widths.iter().all(|&width| width == widths[0]),
+            "{:#?}",
+            lines
+        );
+    }
+
+    #[test]
+    fn test_verify_table() {
+        let files = [PathBuf::from("a.tgz"), PathBuf::from("long-name.zip")];
+        let results = vec![
+            Ok(Verification {
+                tree_sha256: None,
+                manifest_files: Some(2),
+                authenticated: true,
+            }),
+            Err(crate::core::error::JcError::IntegrityFailed(
+                "CRC32 mismatch in 1 member: x".to_string(),
+            )),
+        ];
+        let lines = verify_table(&files, &results);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("ARCHIVE        RESULT  DETAILS"));
+        assert_eq!(
+            lines[1],
+            "a.tgz          OK      layers decode; authenticated; 2 files match MANIFEST.sha256"
+        );
+        assert_eq!(
+            lines[2],
+            "long-name.zip  FAILED  CRC32 mismatch in 1 member: x"
+        );
+    }
+}
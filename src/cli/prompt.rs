@@ -0,0 +1,62 @@
+//! Terminal prompts
+//!
+//! The library only sees these through `Secrets` and `OverwritePolicy`, so
+//! embedders can answer the same questions without a terminal.
+
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::core::config::{SecretPurpose, ZipPassword};
+use crate::core::error::{JcError, JcResult};
+
+/// Ask whether to overwrite an existing file
+/// Returns true if user confirms, false otherwise (including on read errors)
+pub fn prompt_overwrite(file_path: &Path) -> bool {
+    print!(
+        "File '{}' already exists. Overwrite? (y/n): ",
+        file_path.display()
+    );
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+
+    let response = input.trim().to_lowercase();
+    response == "y" || response == "yes"
+}
+
+/// Read a password without echo, or from the first line of stdin when it
+/// is piped
+fn read_password(prompt: &str) -> JcResult<String> {
+    if io::stdin().is_terminal() {
+        rpassword::prompt_password(prompt)
+    } else {
+        rpassword::read_password_from_bufread(&mut io::stdin().lock())
+    }
+    .map_err(|e| JcError::Other(format!("Failed to read password: {}", e)))
+}
+
+/// Password for `-e` encryption or for decrypting a .jcze file
+pub fn prompt_password(purpose: SecretPurpose) -> JcResult<Zeroizing<String>> {
+    let prompt = match purpose {
+        SecretPurpose::Encrypt => "Enter encryption password: ",
+        SecretPurpose::Decrypt => "Enter decryption password: ",
+    };
+    read_password(prompt).map(Zeroizing::new)
+}
+
+/// Password for --zip-encrypt
+pub fn prompt_zip_password() -> JcResult<ZipPassword> {
+    let password = read_password("Enter zip password: ")?;
+
+    if password.is_empty() {
+        return Err(JcError::Other("Password cannot be empty".to_string()));
+    }
+
+    Ok(ZipPassword::new(password))
+}
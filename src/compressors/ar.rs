@@ -0,0 +1,553 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::compressors::cpio::set_permissions;
+use crate::compressors::{detect_format, installed, spawn_error};
+use crate::core::compressor::Compressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::utils::bufio::{buffered_reader, buffered_writer};
+use crate::utils::{
+    canonical_path, copy_to_dir, create_scratch_dir, debug, find_extracted_output,
+    generate_output_filename, info, move_file, path_from_bytes, path_to_bytes, place_output,
+};
+
+/// AR archiver implementation (static libraries, .deb outer container)
+#[derive(Debug, Clone, Default)]
+pub struct ArCompressor;
+
+impl ArCompressor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Check that the input carries an ar extension (.a or .ar)
+    fn validate_extension(&self, input: &Path) -> JcResult<()> {
+        if detect_format(input) != Some(CompressionFormat::Ar) {
+            return Err(JcError::InvalidExtension(
+                input.to_path_buf(),
+                "ar or a".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Extract the members of an archive into the given directory with
+    /// `ar x`, or the built-in implementation when ar is not installed
+    pub(crate) fn extract(&self, archive: &Path, dir: &Path) -> JcResult<()> {
+        let archive = canonical_path(archive)?;
+        if !installed("ar") {
+            info!("ar is not installed, using the built-in implementation");
+            return extract_builtin(&archive, dir);
+        }
+
+        // ar extracts members into the current directory
+        let mut cmd = Command::new("ar");
+        cmd.arg("x").arg(&archive).current_dir(dir);
+
+        debug!("Executing: {:?}", cmd);
+
+        let output = cmd.output().map_err(|e| spawn_error("ar", e))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(JcError::DecompressionFailed {
+                tool: "ar".to_string(),
+                stderr: stderr.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Compressor for ArCompressor {
+    fn name(&self) -> &'static str {
+        "ar"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ar"
+    }
+
+    fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        if !input.exists() {
+            return Err(JcError::FileNotFound(input.to_path_buf()));
+        }
+
+        // ar has no notion of directories
+        if input.is_dir() {
+            return Err(JcError::NotAFile(input.to_path_buf()));
+        }
+
+        let output_path = generate_output_filename(input, "ar", config)?;
+        info!(
+            "Creating AR archive {} from {}",
+            output_path.display(),
+            input.display()
+        );
+
+        if !installed("ar") {
+            info!("ar is not installed, using the built-in implementation");
+            write_builtin(&output_path, input, config.reproducible.is_some())?;
+        } else {
+            let mut cmd = Command::new("ar");
+            // D: zero timestamps, owners and modes in member headers
+            let operation = if config.reproducible.is_some() {
+                "rcD"
+            } else {
+                "rc"
+            };
+            cmd.arg(operation).arg(&output_path).arg(input);
+
+            debug!("Executing: {:?}", cmd);
+
+            let output = cmd.output().map_err(|e| spawn_error("ar", e))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(JcError::CompressionFailed {
+                    tool: "ar".to_string(),
+                    stderr: stderr.to_string(),
+                });
+            }
+        }
+
+        // Move to destination if specified
+        let final_path = place_output(&output_path, config)?;
+
+        info!("Created AR archive: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        self.validate_extension(input)?;
+
+        debug!("Extracting AR archive {}", input.display());
+
+        let parent = input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let dest_dir = config.move_to.as_deref().unwrap_or(parent);
+
+        // Members are named after their sources, not after the archive, so
+        // extract aside to learn which ones there are
+        let scratch = create_scratch_dir(config.temp_dir.as_deref(), Some(dest_dir))?;
+        let extracted = self.decompress_in_dir(input, scratch.path(), config)?;
+
+        let final_path = if extracted == scratch.path() {
+            for entry in fs::read_dir(scratch.path())? {
+                move_file(&entry?.path(), dest_dir)?;
+            }
+            dest_dir.to_path_buf()
+        } else {
+            move_file(&extracted, dest_dir)?
+        };
+
+        info!("Extracted AR archive to: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn supports_levels(&self) -> bool {
+        false // AR doesn't support compression levels
+    }
+
+    fn validate_level(&self, _level: u8) -> bool {
+        true // Always valid (no-op)
+    }
+
+    fn default_level(&self) -> u8 {
+        0
+    }
+}
+
+impl ArCompressor {
+    /// Decompress in a specific working directory
+    pub fn decompress_in_dir(
+        &self,
+        input: &Path,
+        working_dir: &Path,
+        _config: &CompressionConfig,
+    ) -> JcResult<PathBuf> {
+        self.validate_extension(input)?;
+
+        debug!(
+            "Extracting AR archive {} in working dir {}",
+            input.display(),
+            working_dir.display()
+        );
+
+        // Copy input file to working directory
+        let work_input = copy_to_dir(input, working_dir)?;
+
+        self.extract(&work_input, working_dir)?;
+
+        find_extracted_output(working_dir, &work_input)
+    }
+}
+
+/// Global header of an ar archive
+const AR_MAGIC: &[u8; 8] = b"!<arch>\n";
+
+/// Length of an ar member header
+const AR_HEADER_LEN: usize = 60;
+
+/// Largest member the 10-digit size field can describe
+const AR_MAX_SIZE: u64 = 9_999_999_999;
+
+/// Write `input` to the ar archive `output` without ar, in the GNU format
+/// of `ar rc`; with `reproducible`, timestamps, owners and modes are
+/// zeroed as by `ar rcD`
+fn write_builtin(output: &Path, input: &Path, reproducible: bool) -> JcResult<()> {
+    let metadata = fs::metadata(input)?;
+    if metadata.len() > AR_MAX_SIZE {
+        return Err(JcError::CompressionFailed {
+            tool: "ar".to_string(),
+            stderr: format!("{}: too large for an ar member", input.display()),
+        });
+    }
+    let name = input
+        .file_name()
+        .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+    let name = path_to_bytes(Path::new(name));
+
+    let (mtime, uid, gid, mode) = if reproducible {
+        (0, 0, 0, 0o644)
+    } else {
+        owner_and_times(&metadata)
+    };
+
+    let mut archive = buffered_writer(File::create(output)?);
+    archive.write_all(AR_MAGIC)?;
+    // GNU ar keeps names past 15 bytes in a `//` member, referred to by offset
+    let header_name = if name.len() < 16 {
+        [&name[..], b"/"].concat()
+    } else {
+        let mut table = [&name[..], b"/\n"].concat();
+        write_header(&mut archive, b"//", 0, 0, 0, None, table.len() as u64)?;
+        if table.len() % 2 == 1 {
+            table.push(b'\n');
+        }
+        archive.write_all(&table)?;
+        b"/0".to_vec()
+    };
+    write_header(
+        &mut archive,
+        &header_name,
+        mtime,
+        uid,
+        gid,
+        Some(mode),
+        metadata.len(),
+    )?;
+    let copied = io::copy(&mut File::open(input)?, &mut archive)?;
+    if copied % 2 == 1 {
+        archive.write_all(b"\n")?;
+    }
+    archive.flush()?;
+    Ok(())
+}
+
+/// Modification time, owner, group and mode of a member from `metadata`
+#[cfg(unix)]
+fn owner_and_times(metadata: &fs::Metadata) -> (u64, u32, u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        metadata.mtime().max(0) as u64,
+        metadata.uid(),
+        metadata.gid(),
+        metadata.mode(),
+    )
+}
+
+#[cfg(not(unix))]
+fn owner_and_times(metadata: &fs::Metadata) -> (u64, u32, u32, u32) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    (mtime, 0, 0, 0o100644)
+}
+
+/// Write a member header; the mode is left blank for `None`, as for the
+/// long name table
+fn write_header(
+    out: &mut impl Write,
+    name: &[u8],
+    mtime: u64,
+    uid: u32,
+    gid: u32,
+    mode: Option<u32>,
+    size: u64,
+) -> io::Result<()> {
+    // Owners too large for their six digits are left out
+    let id = |id: u32| if id > 999_999 { 0 } else { id };
+    let mut header = name.to_vec();
+    header.resize(16, b' ');
+    let fields = match mode {
+        Some(mode) => format!(
+            "{:<12}{:<6}{:<6}{:<8o}{:<10}`\n",
+            mtime,
+            id(uid),
+            id(gid),
+            mode,
+            size
+        ),
+        None => format!("{:<32}{:<10}`\n", "", size),
+    };
+    header.extend_from_slice(fields.as_bytes());
+    out.write_all(&header)
+}
+
+/// Extract the members of the ar `archive` into `dir` without ar
+///
+/// Reads GNU and BSD long names and skips symbol tables. As with `ar x`,
+/// members are written under their base names, later ones replacing
+/// earlier ones of the same name.
+fn extract_builtin(archive: &Path, dir: &Path) -> JcResult<()> {
+    let malformed = |reason: &str| JcError::DecompressionFailed {
+        tool: "ar".to_string(),
+        stderr: format!("{}: {}", archive.display(), reason),
+    };
+
+    let mut reader = buffered_reader(File::open(archive)?);
+    let mut magic = [0u8; 8];
+    if read_full(&mut reader, &mut magic)? < magic.len() || &magic != AR_MAGIC {
+        return Err(malformed("file format not recognized"));
+    }
+
+    let mut long_names = Vec::new();
+    let mut header = [0u8; AR_HEADER_LEN];
+    loop {
+        match read_full(&mut reader, &mut header)? {
+            0 => break,
+            AR_HEADER_LEN if &header[58..] == b"`\n" => {}
+            _ => return Err(malformed("malformed member header")),
+        }
+        let stored =
+            parse_field(&header[48..58], 10).ok_or_else(|| malformed("malformed member size"))?;
+        let mut size = stored;
+        let mode = parse_field(&header[40..48], 8).unwrap_or(0);
+        let field = trim_spaces(&header[..16]);
+
+        let name = if field == b"/" || field == b"/SYM64/" {
+            None
+        } else if field == b"//" {
+            // Read through take: the size is untrusted and may be huge
+            long_names.clear();
+            (&mut reader).take(size).read_to_end(&mut long_names)?;
+            if (long_names.len() as u64) < size {
+                return Err(malformed("truncated long name table"));
+            }
+            size = 0;
+            None
+        } else if let Some(offset) = field.strip_prefix(b"/") {
+            let offset = std::str::from_utf8(offset)
+                .ok()
+                .and_then(|offset| offset.parse::<usize>().ok())
+                .filter(|&offset| offset < long_names.len())
+                .ok_or_else(|| malformed("malformed long member name"))?;
+            let rest = &long_names[offset..];
+            let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            Some(
+                rest[..end]
+                    .strip_suffix(b"/")
+                    .unwrap_or(&rest[..end])
+                    .to_vec(),
+            )
+        } else if let Some(len) = field.strip_prefix(b"#1/") {
+            // BSD ar stores long names in front of the data
+            let len = parse_field(len, 10)
+                .filter(|&len| len <= size)
+                .ok_or_else(|| malformed("malformed long member name"))?;
+            let mut name = Vec::new();
+            (&mut reader).take(len).read_to_end(&mut name)?;
+            if (name.len() as u64) < len {
+                return Err(malformed("truncated long member name"));
+            }
+            size -= len;
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            name.truncate(end);
+            Some(name)
+        } else {
+            Some(field.strip_suffix(b"/").unwrap_or(field).to_vec())
+        };
+
+        let target = name.and_then(|name| Some(dir.join(path_from_bytes(&name).file_name()?)));
+        let copied = match target {
+            Some(target) => {
+                // Replace rather than write through a link already in place
+                if fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+                    fs::remove_file(&target)?;
+                }
+                let mut file = buffered_writer(File::create(&target)?);
+                let copied = io::copy(&mut (&mut reader).take(size), &mut file)?;
+                file.flush()?;
+                drop(file);
+                if mode != 0 {
+                    set_permissions(&target, mode as u32);
+                }
+                copied
+            }
+            None => io::copy(&mut (&mut reader).take(size), &mut io::sink())?,
+        };
+        if copied < size {
+            return Err(malformed("truncated member"));
+        }
+
+        // Members start on even offsets
+        if stored % 2 == 1 && read_full(&mut reader, &mut [0u8; 1])? == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read into `buf` until it is full or the reader is exhausted
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// A space-padded numeric header field in `radix`
+fn parse_field(field: &[u8], radix: u32) -> Option<u64> {
+    let text = std::str::from_utf8(trim_spaces(field)).ok()?;
+    u64::from_str_radix(text, radix).ok()
+}
+
+/// `field` without its trailing space padding
+fn trim_spaces(field: &[u8]) -> &[u8] {
+    let end = field.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+    &field[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_decompress_returns_extracted_member() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("notes.txt");
+        fs::write(&input, b"member data").unwrap();
+
+        let config = CompressionConfig::default();
+        let compressor = ArCompressor::new();
+        // Named unlike its member, as static libraries are
+        let archive = temp_dir.path().join("bundle.a");
+        fs::rename(compressor.compress(&input, &config).unwrap(), &archive).unwrap();
+        fs::remove_file(&input).unwrap();
+
+        let output = compressor.decompress(&archive, &config).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(fs::read(&output).unwrap(), b"member data");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_builtin_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let out = temp_dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+
+        // Short names sit in the header, long ones in the `//` table
+        for (name, data) in [
+            ("odd.txt", &b"odd"[..]),
+            ("a_rather_long_member_name.txt", &b"even"[..]),
+        ] {
+            let input = temp_dir.path().join(name);
+            fs::write(&input, data).unwrap();
+            let archive = temp_dir.path().join(format!("{}.ar", name));
+            write_builtin(&archive, &input, false).unwrap();
+            assert_eq!(fs::metadata(&archive).unwrap().len() % 2, 0);
+
+            extract_builtin(&archive, &out).unwrap();
+            assert_eq!(fs::read(out.join(name)).unwrap(), data);
+
+            // ar itself reads what the built-in writes
+            if installed("ar") {
+                let listed = Command::new("ar").arg("t").arg(&archive).output().unwrap();
+                assert_eq!(String::from_utf8_lossy(&listed.stdout).trim(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_builtin_reproducible_headers() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("notes.txt");
+        fs::write(&input, b"notes").unwrap();
+        let archive = temp_dir.path().join("notes.ar");
+        write_builtin(&archive, &input, true).unwrap();
+
+        let bytes = fs::read(&archive).unwrap();
+        let header = &bytes[AR_MAGIC.len()..AR_MAGIC.len() + AR_HEADER_LEN];
+        assert_eq!(
+            header,
+            b"notes.txt/      0           0     0     644     5         `\n"
+        );
+    }
+
+    #[test]
+    fn test_builtin_reads_bsd_names_and_skips_symbols() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut archive = AR_MAGIC.to_vec();
+        write_header(&mut archive, b"/", 0, 0, 0, Some(0), 4).unwrap();
+        archive.extend_from_slice(b"syms");
+        // BSD long name: the name leads the data, NUL padded
+        write_header(&mut archive, b"#1/20", 0, 0, 0, Some(0o644), 23).unwrap();
+        archive.extend_from_slice(b"bsd_long_name.txt\0\0\0abc\n");
+        // No member may leave the directory
+        write_header(&mut archive, b"../", 0, 0, 0, Some(0o644), 1).unwrap();
+        archive.extend_from_slice(b"x\n");
+        let path = temp_dir.path().join("bsd.a");
+        fs::write(&path, &archive).unwrap();
+
+        let out = temp_dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        extract_builtin(&path, &out).unwrap();
+        let names: Vec<_> = fs::read_dir(&out)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["bsd_long_name.txt"]);
+        assert_eq!(fs::read(out.join("bsd_long_name.txt")).unwrap(), b"abc");
+
+        fs::write(&path, b"!<arch>\nshort").unwrap();
+        assert!(extract_builtin(&path, &out).is_err());
+    }
+
+    #[test]
+    fn test_builtin_rejects_oversized_headers() {
+        let temp_dir = TempDir::new().unwrap();
+        let out = temp_dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let path = temp_dir.path().join("corrupt.a");
+
+        // Sizes far past the end of the file fail cleanly, without
+        // allocating for them
+        for name in [&b"//"[..], b"#1/999999999"] {
+            let mut archive = AR_MAGIC.to_vec();
+            write_header(&mut archive, name, 0, 0, 0, None, AR_MAX_SIZE).unwrap();
+            archive.extend_from_slice(b"short");
+            fs::write(&path, &archive).unwrap();
+
+            let err = extract_builtin(&path, &out).unwrap_err();
+            assert!(matches!(err, JcError::DecompressionFailed { .. }));
+        }
+    }
+}
@@ -1,15 +1,123 @@
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-
-use crate::core::compressor::Compressor;
+use std::process::Command;
+
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use rayon::prelude::*;
+
+use crate::compressors::{
+    compress_piped, decode_error, decode_threads, decompress_piped, detect_format, filter_stream,
+    has_tool_suffix, spawn_error, strip_layer_extension, Direction, Tool,
+};
+use crate::core::compressor::{Compressor, ReadSeek};
 use crate::core::config::CompressionConfig;
 use crate::core::error::{JcError, JcResult};
-use crate::utils::{copy_to_dir, debug, generate_output_filename, info, move_file_if_needed};
+use crate::core::types::CompressionFormat;
+use crate::utils::{
+    copy_to_dir, debug, generate_output_filename, info, move_file_if_needed, place_output,
+};
+
+/// What follows "BZh" and the block size digit at the start of a stream:
+/// the first block's magic number
+const BLOCK_MAGIC: [u8; 6] = [0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+
+/// Input read at a time while looking for streams
+const SCAN_CHUNK: u64 = 4 << 20;
+
+/// Input held without finding the next stream before decoding the rest
+/// in order
+const MAX_PENDING: usize = 32 << 20;
+
+/// Offsets after the first byte at which a bzip2 stream starts
+fn stream_starts(data: &[u8]) -> Vec<usize> {
+    data.windows(10)
+        .enumerate()
+        .skip(1)
+        .filter(|(_, w)| w.starts_with(b"BZh") && (b'1'..=b'9').contains(&w[3]))
+        .filter(|(_, w)| w[4..] == BLOCK_MAGIC)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+/// Decode `streams`, whole bzip2 streams, in parallel and write them in
+/// order
+fn decode_batch(streams: &mut Vec<Vec<u8>>, output: &mut dyn Write) -> io::Result<()> {
+    let decoded: Vec<io::Result<Vec<u8>>> = streams
+        .par_iter()
+        .map(|stream| {
+            let mut data = Vec::new();
+            MultiBzDecoder::new(&stream[..])
+                .read_to_end(&mut data)
+                .map(|_| data)
+        })
+        .collect();
+    streams.clear();
+    for data in decoded {
+        output.write_all(&data?)?;
+    }
+    Ok(())
+}
+
+/// Decode `input`, which may be many bzip2 streams back to back as
+/// pbzip2 and lbzip2 write them, `threads` streams at a time
+///
+/// The input is split where a stream header starts. Once too much of it
+/// passes without one, as in a file of a single stream, the rest is
+/// decoded in order.
+fn decode_streams(
+    input: &mut (dyn Read + Send),
+    output: &mut dyn Write,
+    threads: usize,
+) -> io::Result<()> {
+    let mut pending = Vec::new();
+    let mut streams = Vec::new();
+    loop {
+        let read = (&mut *input).take(SCAN_CHUNK).read_to_end(&mut pending)?;
+        let mut ends = stream_starts(&pending);
+        if read == 0 {
+            ends.push(pending.len());
+        }
+        let mut start = 0;
+        for end in ends {
+            streams.push(pending[start..end].to_vec());
+            start = end;
+        }
+        pending.drain(..start);
+
+        if read == 0 || streams.len() >= threads {
+            decode_batch(&mut streams, output)?;
+        }
+        if read == 0 {
+            break;
+        }
+        if pending.len() > MAX_PENDING {
+            decode_batch(&mut streams, output)?;
+            let rest = io::Cursor::new(pending).chain(input);
+            io::copy(&mut MultiBzDecoder::new(rest), output)?;
+            break;
+        }
+    }
+    output.flush()
+}
+
+/// Whether `input` is better decoded by the built-in decoder, stream by
+/// stream in parallel, than by `tool`: plain bzip2 decodes one stream
+/// after another, so this holds for files of several streams whenever
+/// more than one thread may be used
+fn decodes_streams(input: &Path, tool: Tool, config: &CompressionConfig) -> JcResult<bool> {
+    if tool != Tool::Bzip2 || decode_threads(config) < 2 {
+        return Ok(false);
+    }
+    let mut head = Vec::new();
+    File::open(input)?.take(SCAN_CHUNK).read_to_end(&mut head)?;
+    Ok(!stream_starts(&head).is_empty())
+}
 
 /// BZIP2 compressor implementation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Bzip2Compressor;
 
 impl Bzip2Compressor {
@@ -40,9 +148,11 @@ impl Compressor for Bzip2Compressor {
     }
 
     fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let config = config.for_compressor(self);
+        let config = &*config;
         self.validate_input(input)?;
 
-        let output_path = generate_output_filename(input, "bz2", config.timestamp)?;
+        let output_path = generate_output_filename(input, "bz2", config)?;
         info!(
             "Compressing {} to {} with bzip2",
             input.display(),
@@ -50,47 +160,16 @@ impl Compressor for Bzip2Compressor {
         );
         debug!("Compression level: {}", config.level);
 
-        let output_file = File::create(&output_path)?;
-        let mut writer = BufWriter::new(output_file);
-
-        let mut cmd = Command::new("bzip2");
-        cmd.arg(format!("-{}", config.level))
-            .arg("--keep")
-            .arg("--stdout")
-            .arg(input)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        debug!("Executing: {:?}", cmd);
-
-        let mut child = cmd
-            .spawn()
-            .map_err(|e| JcError::Other(format!("Failed to spawn bzip2: {}", e)))?;
-
-        if let Some(mut stdout) = child.stdout.take() {
-            std::io::copy(&mut stdout, &mut writer)?;
-        }
-
-        writer.flush()?;
+        compress_piped(self, input, &output_path, config)?;
 
-        let output = child.wait_with_output()?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::CompressionFailed {
-                tool: "bzip2".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
-
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        let final_path = place_output(&output_path, config)?;
 
         info!("Compressed file: {}", final_path.display());
         Ok(final_path)
     }
 
     fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
-        if !input.to_string_lossy().ends_with(".bz2") {
+        if detect_format(input) != Some(CompressionFormat::Bzip2) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "bz2".to_string(),
@@ -99,28 +178,99 @@ impl Compressor for Bzip2Compressor {
 
         debug!("Decompressing {} with bzip2", input.display());
 
-        let mut cmd = Command::new("bzip2");
-        cmd.arg("-d").arg("-k").arg(input);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute bzip2: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "bzip2".to_string(),
-                stderr: stderr.to_string(),
-            });
+        let tool = Tool::for_format(CompressionFormat::Bzip2, config);
+        if config.pipes_data()
+            || !tool.available()
+            || !has_tool_suffix(input, &[".bz2", ".tbz2", ".tbz"])
+            || decodes_streams(input, tool, config)?
+        {
+            decompress_piped(self, input, &strip_layer_extension(input), config)?;
+        } else {
+            let mut cmd = Command::new(tool.program());
+            cmd.arg("-d")
+                .arg("-k")
+                .args(tool.thread_args(config.threads))
+                .arg(input);
+
+            let output = cmd.output().map_err(|e| spawn_error(tool.program(), e))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(JcError::DecompressionFailed {
+                    tool: tool.program().to_string(),
+                    stderr: stderr.to_string(),
+                });
+            }
         }
 
-        let output_path = input.with_extension("");
+        let output_path = strip_layer_extension(input);
         let final_path = move_file_if_needed(&output_path, &config.move_to)?;
 
         info!("Decompressed file: {}", final_path.display());
         Ok(final_path)
     }
 
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn compress_stream(
+        &self,
+        input: &mut (dyn Read + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let config = config.for_compressor(self);
+        let config = &*config;
+        let tool = Tool::for_format(CompressionFormat::Bzip2, config);
+        if !tool.available() {
+            debug!("bzip2 is not installed, using the built-in implementation");
+            let mut encoder = BzEncoder::new(output, Compression::new(config.level.into()));
+            io::copy(input, &mut encoder)?;
+            encoder.finish()?.flush()?;
+            return Ok(());
+        }
+        let mut args = vec![format!("-{}", config.level)];
+        args.extend(tool.thread_args(config.threads));
+        filter_stream(tool, &args, input, output, Direction::Compress)
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &mut (dyn Read + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let tool = Tool::for_format(CompressionFormat::Bzip2, config);
+        if !tool.available() {
+            let threads = decode_threads(config);
+            let result = if threads > 1 {
+                decode_streams(input, output, threads)
+            } else {
+                io::copy(&mut MultiBzDecoder::new(input), output).and_then(|_| output.flush())
+            };
+            return result.map_err(|e| decode_error("bzip2", e));
+        }
+        let mut args = vec!["-d".to_string()];
+        args.extend(tool.thread_args(config.threads));
+        filter_stream(tool, &args, input, output, Direction::Decompress)
+    }
+
+    fn decompress_seekable(
+        &self,
+        input: &mut (dyn ReadSeek + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let tool = Tool::for_format(CompressionFormat::Bzip2, config);
+        let threads = decode_threads(config);
+        if tool == Tool::Bzip2 && threads > 1 {
+            debug!("Decoding bzip2 streams on {} threads", threads);
+            return decode_streams(input, output, threads).map_err(|e| decode_error("bzip2", e));
+        }
+        self.decompress_stream(input, output, config)
+    }
+
     fn supports_levels(&self) -> bool {
         true
     }
@@ -130,7 +280,8 @@ impl Compressor for Bzip2Compressor {
     }
 
     fn default_level(&self) -> u8 {
-        6
+        // bzip2's own default: the largest block size
+        9
     }
 }
 
@@ -140,9 +291,9 @@ impl Bzip2Compressor {
         &self,
         input: &Path,
         working_dir: &Path,
-        _config: &CompressionConfig,
+        config: &CompressionConfig,
     ) -> JcResult<PathBuf> {
-        if !input.to_string_lossy().ends_with(".bz2") {
+        if detect_format(input) != Some(CompressionFormat::Bzip2) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "bz2".to_string(),
@@ -155,28 +306,76 @@ impl Bzip2Compressor {
             working_dir.display()
         );
 
-        // Copy input file to working directory
-        let work_input = copy_to_dir(input, working_dir)?;
-
-        // Execute bzip2 decompression in working directory
-        let mut cmd = Command::new("bzip2");
-        cmd.arg("-d").arg("-f").arg(&work_input);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute bzip2: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "bzip2".to_string(),
-                stderr: stderr.to_string(),
-            });
+        let tool = Tool::for_format(CompressionFormat::Bzip2, config);
+        let work_input;
+        if config.pipes_data()
+            || !tool.available()
+            || !has_tool_suffix(input, &[".bz2", ".tbz2", ".tbz"])
+            || decodes_streams(input, tool, config)?
+        {
+            // Read in place; only the output goes to the working directory
+            work_input = working_dir.join(input.file_name().unwrap_or_default());
+            let output_path = strip_layer_extension(&work_input);
+            decompress_piped(self, input, &output_path, config)?;
+        } else {
+            // Copy input file to working directory
+            work_input = copy_to_dir(input, working_dir)?;
+
+            // Execute bzip2 decompression in working directory
+            let mut cmd = Command::new(tool.program());
+            cmd.arg("-d")
+                .arg("-f")
+                .args(tool.thread_args(config.threads))
+                .arg(&work_input);
+
+            let output = cmd.output().map_err(|e| spawn_error(tool.program(), e))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(JcError::DecompressionFailed {
+                    tool: tool.program().to_string(),
+                    stderr: stderr.to_string(),
+                });
+            }
         }
 
-        let output_path = work_input.with_extension("");
+        let output_path = strip_layer_extension(&work_input);
 
         debug!("Decompressed to: {}", output_path.display());
         Ok(output_path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decodes_concatenated_streams_in_parallel() {
+        let parts: Vec<Vec<u8>> = (0..5u8)
+            .map(|i| vec![b'a' + i; 10_000 + i as usize])
+            .collect();
+        let input: Vec<u8> = parts.iter().flat_map(|part| compress(part)).collect();
+        assert_eq!(stream_starts(&input).len(), 4);
+
+        for threads in [1, 2, 8] {
+            let mut output = Vec::new();
+            decode_streams(&mut &input[..], &mut output, threads).unwrap();
+            assert_eq!(output, parts.concat());
+        }
+    }
+
+    #[test]
+    fn test_decodes_single_stream() {
+        let data = b"just one stream".repeat(1000);
+        let mut output = Vec::new();
+        decode_streams(&mut &compress(&data)[..], &mut output, 4).unwrap();
+        assert_eq!(output, data);
+    }
+}
@@ -0,0 +1,597 @@
+//! User-defined compressors that run an external command
+//!
+//! Formats jcz has no built-in support for are registered in a TOML file:
+//!
+//! ```toml
+//! [compressor.lrzip]
+//! compress = "lrzip -q -o {out} {in}"
+//! decompress = "lrzip -q -d -o {out} {in}"
+//! extension = "lrz"
+//! ```
+//!
+//! Commands are split on whitespace and run without a shell. `{in}` and
+//! `{out}` stand for the input and output files and must be words of their
+//! own; a command without `{in}` reads the input on stdin, and one without
+//! `{out}` writes the output to stdout. `{level}` is replaced by the
+//! compression level, which must lie in `levels = [min, max]` (default
+//! `[1, 9]`) and defaults to `default_level` (default 6). `rsyncable`
+//! gives arguments added after the program under `--rsyncable`. The
+//! extension defaults to the name.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::compressors::{detect_format, spawn_error, strip_extension_ignore_case, Direction};
+use crate::core::compressor::Compressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::{CompoundFormat, CompressionFormat};
+use crate::utils::{
+    debug, generate_output_filename, info, move_file_if_needed, place_output, user_config_file,
+};
+
+const INPUT: &str = "{in}";
+const OUTPUT: &str = "{out}";
+const LEVEL: &str = "{level}";
+
+/// Registry file read when `--compressors` is not given:
+/// `$XDG_CONFIG_HOME/jcz/compressors.toml`, else `~/.config/jcz/compressors.toml`
+pub fn default_registry_path() -> Option<PathBuf> {
+    user_config_file("compressors.toml")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(default)]
+    compressor: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    compress: String,
+    decompress: Option<String>,
+    extension: Option<String>,
+    levels: Option<[u8; 2]>,
+    default_level: Option<u8>,
+    rsyncable: Option<String>,
+}
+
+/// Command line with placeholders, split into words
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Template(Vec<String>);
+
+impl Template {
+    fn parse(name: &str, kind: &str, text: &str) -> JcResult<Self> {
+        let invalid = |why: &str| {
+            JcError::Other(format!(
+                "Invalid {} command for compressor {}: {}",
+                kind, name, why
+            ))
+        };
+
+        let words: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+        match words.first() {
+            None => return Err(invalid("empty")),
+            Some(program) if program.contains('{') => {
+                return Err(invalid("the program cannot be a placeholder"))
+            }
+            Some(_) => {}
+        }
+        let embedded = words
+            .iter()
+            .any(|w| w != INPUT && w != OUTPUT && (w.contains(INPUT) || w.contains(OUTPUT)));
+        if embedded {
+            return Err(invalid("{in} and {out} must be words of their own"));
+        }
+        Ok(Template(words))
+    }
+
+    fn uses(&self, placeholder: &str) -> bool {
+        self.0.iter().any(|word| word.contains(placeholder))
+    }
+
+    fn command(&self, input: &Path, output: &Path, level: u8, options: &[String]) -> Command {
+        let mut cmd = Command::new(&self.0[0]);
+        cmd.args(options);
+        for word in &self.0[1..] {
+            match word.as_str() {
+                INPUT => cmd.arg(input),
+                OUTPUT => cmd.arg(output),
+                _ => cmd.arg(word.replace(LEVEL, &level.to_string())),
+            };
+        }
+        cmd
+    }
+}
+
+/// Compressor that runs a user-registered external command
+#[derive(Debug, Clone)]
+pub struct CommandCompressor {
+    // Registered once per process, so the names can live as long as the
+    // `Compressor` trait wants them to
+    name: &'static str,
+    extension: &'static str,
+    compress: Template,
+    decompress: Option<Template>,
+    levels: RangeInclusive<u8>,
+    default_level: u8,
+    /// Arguments added after the program for `--rsyncable`
+    rsyncable: Option<Vec<String>>,
+}
+
+impl CommandCompressor {
+    /// `path` without this format's extension, in any case, if it has it;
+    /// `.t<ext>` stands for `.tar.<ext>`, as `.tgz` does for `.tar.gz`,
+    /// unless it names a built-in format (`.tb2` for a `b2` entry)
+    fn strip_extension(&self, path: &Path) -> Option<PathBuf> {
+        let name = path.file_name()?.to_str()?;
+        let alias = format!("t{}", self.extension);
+        if let Some(stem) = strip_extension_ignore_case(name, &alias) {
+            if detect_format(path).is_none() {
+                return Some(path.with_file_name(format!("{}.tar", stem)));
+            }
+        }
+        let stem = strip_extension_ignore_case(name, self.extension)?;
+        Some(path.with_file_name(stem))
+    }
+
+    /// Whether `path` is named like a file of this format
+    pub fn matches(&self, path: &Path) -> bool {
+        self.strip_extension(path).is_some()
+    }
+
+    /// The tar `path` holds when it is named like a tar compressed with
+    /// this format (`a.tar.<ext>` or `a.t<ext>` give `a.tar`)
+    pub fn tar_path(&self, path: &Path) -> Option<PathBuf> {
+        self.strip_extension(path)
+            .filter(|stem| detect_format(stem) == Some(CompressionFormat::Tar))
+    }
+
+    /// Whether the entry declares arguments for `--rsyncable`
+    pub fn supports_rsyncable(&self) -> bool {
+        self.rsyncable.is_some()
+    }
+
+    /// Run `template` from `input` to `output`, removing a partial output
+    /// when it fails
+    fn run(
+        &self,
+        template: &Template,
+        input: &Path,
+        output: &Path,
+        config: &CompressionConfig,
+        direction: Direction,
+    ) -> JcResult<()> {
+        let failed = |stderr: String| match direction {
+            Direction::Compress => JcError::CompressionFailed {
+                tool: self.name.to_string(),
+                stderr,
+            },
+            Direction::Decompress => JcError::DecompressionFailed {
+                tool: self.name.to_string(),
+                stderr,
+            },
+        };
+
+        let options = match (direction, &self.rsyncable) {
+            (Direction::Compress, Some(args)) if config.command_tuning(self.name).rsyncable => {
+                args.as_slice()
+            }
+            _ => &[],
+        };
+        let mut cmd = template.command(input, output, config.level, options);
+        if template.uses(INPUT) {
+            cmd.stdin(Stdio::null());
+        } else {
+            cmd.stdin(File::open(input)?);
+        }
+        if template.uses(OUTPUT) {
+            cmd.stdout(Stdio::null());
+        } else {
+            cmd.stdout(File::create(output)?);
+        }
+        debug!("Executing: {:?}", cmd);
+
+        let result = cmd
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| spawn_error(&template.0[0], e))
+            .and_then(|out| {
+                if !out.status.success() {
+                    Err(failed(String::from_utf8_lossy(&out.stderr).to_string()))
+                } else if !output.exists() {
+                    Err(failed(format!("{} was not created", output.display())))
+                } else {
+                    Ok(())
+                }
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(output);
+        }
+        result
+    }
+
+    fn decompress_template(&self) -> JcResult<&Template> {
+        self.decompress.as_ref().ok_or_else(|| {
+            JcError::Other(format!(
+                "Compressor {} has no decompress command",
+                self.name
+            ))
+        })
+    }
+
+    fn decompressed_path(&self, input: &Path) -> JcResult<PathBuf> {
+        self.strip_extension(input)
+            .ok_or_else(|| JcError::InvalidExtension(input.to_path_buf(), self.extension.into()))
+    }
+
+    /// Decompress in a specific working directory
+    pub fn decompress_in_dir(
+        &self,
+        input: &Path,
+        working_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<PathBuf> {
+        let template = self.decompress_template()?;
+        let stripped = self.decompressed_path(input)?;
+        let output_path = working_dir.join(stripped.file_name().unwrap_or_default());
+
+        debug!(
+            "Decompressing {} with {} in working dir {}",
+            input.display(),
+            self.name,
+            working_dir.display()
+        );
+        self.run(template, input, &output_path, config, Direction::Decompress)?;
+
+        debug!("Decompressed to: {}", output_path.display());
+        Ok(output_path)
+    }
+}
+
+impl Compressor for CommandCompressor {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn extension(&self) -> &'static str {
+        self.extension
+    }
+
+    fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let config = config.for_compressor(self);
+        let config = &*config;
+        if !input.exists() {
+            return Err(JcError::FileNotFound(input.to_path_buf()));
+        }
+        if input.is_dir() {
+            return Err(JcError::NotAFile(input.to_path_buf()));
+        }
+
+        let output_path = generate_output_filename(input, self.extension, config)?;
+        info!(
+            "Compressing {} to {} with {}",
+            input.display(),
+            output_path.display(),
+            self.name
+        );
+        self.run(
+            &self.compress,
+            input,
+            &output_path,
+            config,
+            Direction::Compress,
+        )?;
+
+        let final_path = place_output(&output_path, config)?;
+
+        info!("Compressed file: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let template = self.decompress_template()?;
+        let output_path = self.decompressed_path(input)?;
+
+        debug!("Decompressing {} with {}", input.display(), self.name);
+        self.run(template, input, &output_path, config, Direction::Decompress)?;
+
+        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+
+        info!("Decompressed file: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn supports_levels(&self) -> bool {
+        self.compress.uses(LEVEL)
+    }
+
+    fn validate_level(&self, level: u8) -> bool {
+        self.levels.contains(&level)
+    }
+
+    fn default_level(&self) -> u8 {
+        self.default_level
+    }
+
+    fn fastest_level(&self) -> u8 {
+        *self.levels.start()
+    }
+
+    fn best_level(&self) -> u8 {
+        *self.levels.end()
+    }
+}
+
+/// External compressors registered in a TOML file of `[compressor.NAME]`
+/// tables
+#[derive(Debug, Clone, Default)]
+pub struct CommandRegistry {
+    compressors: Vec<CommandCompressor>,
+}
+
+impl CommandRegistry {
+    /// Load and validate a registry file
+    pub fn load(path: &Path) -> JcResult<Self> {
+        let text =
+            fs::read_to_string(path).map_err(|_| JcError::FileNotFound(path.to_path_buf()))?;
+        Self::from_toml(&text).map_err(|e| {
+            JcError::Other(format!(
+                "Invalid compressor registry {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Parse and validate registry entries
+    pub fn from_toml(text: &str) -> JcResult<Self> {
+        let file: RegistryFile =
+            toml::from_str(text).map_err(|e| JcError::Other(e.message().to_string()))?;
+
+        let mut registry = CommandRegistry::default();
+        for (name, entry) in file.compressor {
+            let valid_name = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                return Err(JcError::Other(format!("Invalid compressor name: {}", name)));
+            }
+            if name == "auto"
+                || CompressionFormat::from_name(&name).is_some()
+                || CompoundFormat::from_str(&name).is_some()
+            {
+                return Err(JcError::Other(format!("{} is a built-in command", name)));
+            }
+
+            let extension = entry.extension.unwrap_or_else(|| name.clone());
+            let last = extension.rsplit('.').next().unwrap_or_default();
+            if last.is_empty() || extension.starts_with('.') || extension.contains('/') {
+                return Err(JcError::Other(format!(
+                    "Invalid extension for compressor {}: {}",
+                    name, extension
+                )));
+            }
+            if CompressionFormat::from_extension(last).is_some()
+                || CompoundFormat::from_alias(last).is_some()
+                || last == "jcze"
+                || last == "gpg"
+            {
+                return Err(JcError::Other(format!(
+                    "Extension of compressor {} is taken by a built-in format: {}",
+                    name, extension
+                )));
+            }
+            if let Some(other) = registry
+                .compressors
+                .iter()
+                .find(|c| c.extension == extension)
+            {
+                return Err(JcError::Other(format!(
+                    "Compressors {} and {} share the extension {}",
+                    other.name, name, extension
+                )));
+            }
+
+            let [min, max] = entry.levels.unwrap_or([1, 9]);
+            let default_level = entry.default_level.unwrap_or(6);
+            let default_level = match entry.default_level {
+                Some(level) if min <= max && (min..=max).contains(&level) => level,
+                None if min <= max => 6.clamp(min, max),
+                _ => {
+                    return Err(JcError::Other(format!(
+                        "Invalid levels for compressor {}: {}-{} with default {}",
+                        name, min, max, default_level
+                    )))
+                }
+            };
+
+            let compress = Template::parse(&name, "compress", &entry.compress)?;
+            let decompress = entry
+                .decompress
+                .map(|text| Template::parse(&name, "decompress", &text))
+                .transpose()?;
+            registry.compressors.push(CommandCompressor {
+                name: Box::leak(name.into_boxed_str()),
+                extension: Box::leak(extension.into_boxed_str()),
+                compress,
+                decompress,
+                levels: min..=max,
+                default_level,
+                rsyncable: entry
+                    .rsyncable
+                    .map(|args| args.split_whitespace().map(str::to_string).collect()),
+            });
+        }
+        Ok(registry)
+    }
+
+    /// Compressor registered as `name`
+    pub fn get(&self, name: &str) -> Option<&CommandCompressor> {
+        self.compressors.iter().find(|c| c.name == name)
+    }
+
+    /// Compressor whose extension `path` has
+    pub fn for_path(&self, path: &Path) -> Option<&CommandCompressor> {
+        self.compressors.iter().find(|c| c.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::LevelPreset;
+    use tempfile::TempDir;
+
+    const REGISTRY: &str = r#"
+[compressor.gz-copy]
+compress = "gzip -c -{level}"
+decompress = "gzip -dc"
+extension = "gzc"
+
+[compressor.copy]
+compress = "cp {in} {out}"
+"#;
+
+    #[test]
+    fn test_command_compressor_round_trip() {
+        let registry = CommandRegistry::from_toml(REGISTRY).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, b"external data").unwrap();
+        let config = CompressionConfig::default();
+
+        let gz = registry.get("gz-copy").unwrap();
+        assert!(gz.supports_levels());
+        let compressed = gz.compress(&input, &config).unwrap();
+        assert_eq!(compressed, temp_dir.path().join("data.txt.gzc"));
+        fs::remove_file(&input).unwrap();
+        assert!(std::ptr::eq(registry.for_path(&compressed).unwrap(), gz));
+        let output = gz.decompress(&compressed, &config).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"external data");
+
+        let copy = registry.get("copy").unwrap();
+        assert!(!copy.supports_levels());
+        let copied = copy.compress(&input, &config).unwrap();
+        assert_eq!(fs::read(&copied).unwrap(), b"external data");
+        assert!(copy.decompress(&copied, &config).is_err());
+        assert!(registry.for_path(Path::new("x.copy.txt")).is_none());
+        assert!(registry.for_path(Path::new(".gzc")).is_none());
+    }
+
+    #[test]
+    fn test_for_path_ignores_case_and_takes_tar_alias() {
+        let registry = CommandRegistry::from_toml(
+            r#"
+[compressor.zst]
+compress = "zstd -c"
+
+[compressor.b2]
+compress = "cat"
+"#,
+        )
+        .unwrap();
+        let zst = registry.get("zst").unwrap();
+
+        assert!(std::ptr::eq(
+            registry.for_path(Path::new("a.ZST")).unwrap(),
+            zst
+        ));
+        assert_eq!(
+            zst.decompressed_path(Path::new("dir/a.TZST")).unwrap(),
+            Path::new("dir/a.tar")
+        );
+        assert_eq!(
+            zst.decompressed_path(Path::new("a.tar.zst")).unwrap(),
+            Path::new("a.tar")
+        );
+        // `.tb2` is built in, so no alias for a `b2` entry
+        assert!(registry.for_path(Path::new("a.tb2")).is_none());
+        assert!(registry.for_path(Path::new("a.B2")).is_some());
+    }
+
+    #[test]
+    fn test_command_compressor_failure_removes_output() {
+        let registry = CommandRegistry::from_toml(
+            r#"
+[compressor.broken]
+compress = "false {in}"
+"#,
+        )
+        .unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, b"data").unwrap();
+
+        let result = registry
+            .get("broken")
+            .unwrap()
+            .compress(&input, &CompressionConfig::default());
+        assert!(matches!(result, Err(JcError::CompressionFailed { .. })));
+        assert!(!temp_dir.path().join("data.txt.broken").exists());
+    }
+
+    #[test]
+    fn test_registry_rejects_conflicts() {
+        for text in [
+            "[compressor.gzip]\ncompress = \"gzip -c\"",
+            "[compressor.lz]\ncompress = \"lzip -c\"\nextension = \"tar.gz\"",
+            "[compressor.lz]\ncompress = \"lzip -o{out} {in}\"",
+            "[compressor.lz]\ncompress = \"{in} lzip\"",
+            "[compressor.lz]\ncompress = \"\"",
+            "[compressor.lz]\ncompress = \"lzip\"\nlevel = 3",
+            "[compressor.lz]\ncompress = \"lzip\"\nlevels = [9, 1]",
+            "[compressor.lz]\ncompress = \"lzip\"\nlevels = [1, 5]\ndefault_level = 7",
+            "[compressor.a]\ncompress = \"a\"\nextension = \"x\"\n\
+             [compressor.b]\ncompress = \"b\"\nextension = \"x\"",
+        ] {
+            assert!(CommandRegistry::from_toml(text).is_err(), "{}", text);
+        }
+        assert!(CommandRegistry::from_toml("").unwrap().get("lz").is_none());
+    }
+
+    #[test]
+    fn test_registry_level_range() {
+        let registry = CommandRegistry::from_toml(
+            "[compressor.zst]\ncompress = \"zstd -c -{level}\"\nlevels = [1, 22]\ndefault_level = 3",
+        )
+        .unwrap();
+        let zst = registry.get("zst").unwrap();
+        assert!(zst.validate_level(22));
+        assert!(!zst.validate_level(0));
+
+        let best = CompressionConfig::new().with_level_preset(Some(LevelPreset::Best));
+        assert_eq!(best.level_for(zst), 22);
+        let default = CompressionConfig::new().with_level_preset(Some(LevelPreset::Default));
+        assert_eq!(default.for_compressor(zst).level, 3);
+    }
+
+    #[test]
+    fn test_rsyncable_arguments() {
+        let registry = CommandRegistry::from_toml(
+            "[compressor.zst]\ncompress = \"zstd -q -c\"\nrsyncable = \"--rsyncable\"",
+        )
+        .unwrap();
+        let zst = registry.get("zst").unwrap();
+        assert!(zst.supports_rsyncable());
+        assert!(!CommandRegistry::from_toml(REGISTRY)
+            .unwrap()
+            .get("gz-copy")
+            .unwrap()
+            .supports_rsyncable());
+
+        let args = zst.rsyncable.as_deref().unwrap();
+        let cmd = zst
+            .compress
+            .command(Path::new("in"), Path::new("out"), 3, args);
+        let words: Vec<_> = cmd.get_args().collect();
+        assert_eq!(words, ["--rsyncable", "-q", "-c"]);
+    }
+}
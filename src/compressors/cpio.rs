@@ -0,0 +1,660 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::compressors::detect_format;
+use crate::core::compressor::Compressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::utils::bufio::{buffered_reader, buffered_writer};
+use crate::utils::{
+    copy_to_dir, debug, find_extracted_output, generate_output_filename, info,
+    is_inside_without_symlinks, move_file_if_needed, place_output, warn, FileFilter, Throttled,
+};
+
+/// State carried through one archive's directory walk
+struct TreeWalk {
+    ino: u32,
+    dereference: bool,
+    /// Epoch to clamp mtimes to when writing a reproducible archive
+    reproducible: Option<u64>,
+    /// Canonical directories being walked, to catch symlink loops
+    ancestors: Vec<PathBuf>,
+    /// Regular files outside it are left out
+    filter: FileFilter,
+}
+
+/// Magic for the SVR4 "newc" format (no checksum)
+const NEWC_MAGIC: &[u8; 6] = b"070701";
+
+/// Magic for the SVR4 "crc" format (same layout, checksum field is used)
+const CRC_MAGIC: &[u8; 6] = b"070702";
+
+/// Size of a newc header in bytes
+const HEADER_LEN: usize = 110;
+
+/// Name of the entry terminating every cpio archive
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// CPIO archiver implementation (newc format, in-process)
+///
+/// cpio is not installed everywhere, and initramfs images only use the
+/// simple newc layout, so the format is read and written natively.
+#[derive(Debug, Clone, Default)]
+pub struct CpioCompressor;
+
+/// A parsed newc entry header
+#[derive(Debug, Clone)]
+struct CpioHeader {
+    ino: u32,
+    mode: u32,
+    nlink: u32,
+    mtime: u32,
+    filesize: u32,
+    name: String,
+}
+
+impl CpioCompressor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Write a newc archive containing `input` (recursively) to `output`
+    ///
+    /// Symlinks are stored as links unless `config.dereference` is set;
+    /// `config.reproducible` clamps mtimes.
+    fn write_archive(
+        &self,
+        input: &Path,
+        output: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let base = input
+            .file_name()
+            .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+
+        let mut writer = buffered_writer(Throttled::new(
+            config.watched(File::create(output)?, output),
+            config.limit_rate.as_ref(),
+        ));
+        let mut walk = TreeWalk {
+            ino: 1,
+            dereference: config.dereference,
+            reproducible: config.reproducible,
+            ancestors: Vec::new(),
+            filter: config.filter,
+        };
+        self.write_tree(&mut writer, input, Path::new(base), &mut walk)?;
+        write_entry(&mut writer, 0, 0, 1, 0, TRAILER, &[])?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Append `path` (stored as `name`) and its children to the archive
+    fn write_tree<W: Write>(
+        &self,
+        writer: &mut W,
+        path: &Path,
+        name: &Path,
+        walk: &mut TreeWalk,
+    ) -> JcResult<()> {
+        let mut metadata = fs::symlink_metadata(path)?;
+        if walk.dereference && metadata.file_type().is_symlink() {
+            // Dangling links are kept as links
+            metadata = fs::metadata(path).unwrap_or(metadata);
+        }
+        if walk.filter.excludes(&metadata) {
+            debug!("Outside the size range: {}", path.display());
+            return Ok(());
+        }
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mtime = walk.reproducible.map_or(mtime, |epoch| mtime.min(epoch)) as u32;
+        let name_str = name.to_string_lossy();
+        let this_ino = walk.ino;
+        walk.ino += 1;
+
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            let target = fs::read_link(path)?;
+            let target = target.to_string_lossy();
+            let mode = S_IFLNK | 0o777;
+            write_entry(
+                writer,
+                this_ino,
+                mode,
+                1,
+                mtime,
+                &name_str,
+                target.as_bytes(),
+            )?;
+        } else if file_type.is_dir() {
+            let canonical = path.canonicalize()?;
+            if walk.ancestors.contains(&canonical) {
+                warn!("Skipping {}: symlink loop", path.display());
+                return Ok(());
+            }
+
+            let mode = S_IFDIR | permission_bits(&metadata, 0o755);
+            write_entry(writer, this_ino, mode, 2, mtime, &name_str, &[])?;
+
+            // Sort children so identical trees produce identical archives
+            let mut children: Vec<_> = fs::read_dir(path)?
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name())
+                .collect();
+            children.sort();
+            walk.ancestors.push(canonical);
+            for child in children {
+                self.write_tree(writer, &path.join(&child), &name.join(&child), walk)?;
+            }
+            walk.ancestors.pop();
+        } else {
+            let mode = S_IFREG | permission_bits(&metadata, 0o644);
+            let data = fs::read(path)?;
+            write_entry(writer, this_ino, mode, 1, mtime, &name_str, &data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Extract a newc archive into `dest_dir`
+    fn extract_archive(
+        &self,
+        archive: &Path,
+        dest_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let limit = config.limit_rate.as_ref();
+        let file = config.watched(File::open(archive)?, archive);
+        let mut reader = buffered_reader(Throttled::new(file, limit));
+        // Hard links: newc stores the data only with the last link of an inode
+        let mut pending_links: HashMap<u32, Vec<PathBuf>> = HashMap::new();
+
+        loop {
+            let header = read_header(&mut reader)?;
+            if header.name == TRAILER {
+                break;
+            }
+
+            let mut data = vec![0u8; header.filesize as usize];
+            reader.read_exact(&mut data)?;
+            skip_padding(&mut reader, header.filesize as usize)?;
+
+            let relative = match sanitize_entry_path(&header.name) {
+                Some(p) => p,
+                None => {
+                    debug!("Skipping unsafe cpio entry: {}", header.name);
+                    continue;
+                }
+            };
+            // A link extracted earlier must not redirect later entries
+            if !is_inside_without_symlinks(dest_dir, &relative) {
+                warn!("Skipping cpio entry below a symlink: {}", header.name);
+                continue;
+            }
+            let target = dest_dir.join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+                fs::remove_file(&target)?;
+            }
+
+            match header.mode & S_IFMT {
+                S_IFDIR => {
+                    fs::create_dir_all(&target)?;
+                    set_permissions(&target, header.mode);
+                }
+                S_IFLNK => {
+                    let link_target = String::from_utf8_lossy(&data).to_string();
+                    create_symlink(&link_target, &target)?;
+                }
+                S_IFREG => {
+                    if header.nlink > 1 && header.filesize == 0 {
+                        // Data arrives with a later link of the same inode
+                        File::create(&target)?;
+                        pending_links.entry(header.ino).or_default().push(target);
+                        continue;
+                    }
+
+                    write_file(&target, &data, &header)?;
+
+                    if let Some(links) = pending_links.remove(&header.ino) {
+                        for link in links {
+                            fs::remove_file(&link)?;
+                            fs::hard_link(&target, &link)?;
+                        }
+                    }
+                }
+                _ => {
+                    debug!(
+                        "Skipping special cpio entry {} (mode {:o})",
+                        header.name, header.mode
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Compressor for CpioCompressor {
+    fn name(&self) -> &'static str {
+        "cpio"
+    }
+
+    fn extension(&self) -> &'static str {
+        "cpio"
+    }
+
+    fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        if !input.exists() {
+            return Err(JcError::FileNotFound(input.to_path_buf()));
+        }
+
+        let output_path = generate_output_filename(input, "cpio", config)?;
+        info!(
+            "Creating CPIO archive {} from {}",
+            output_path.display(),
+            input.display()
+        );
+
+        self.write_archive(input, &output_path, config)
+            .map_err(|e| {
+                let _ = fs::remove_file(&output_path);
+                if e.is_cancellation() {
+                    return e;
+                }
+                JcError::CompressionFailed {
+                    tool: "cpio".to_string(),
+                    stderr: e.to_string(),
+                }
+            })?;
+
+        // Move to destination if specified
+        let final_path = place_output(&output_path, config)?;
+
+        info!("Created CPIO archive: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        if detect_format(input) != Some(CompressionFormat::Cpio) {
+            return Err(JcError::InvalidExtension(
+                input.to_path_buf(),
+                "cpio".to_string(),
+            ));
+        }
+
+        debug!("Extracting CPIO archive {}", input.display());
+
+        let parent = input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        self.extract_archive(input, parent, config).map_err(|e| {
+            if e.is_cancellation() {
+                return e;
+            }
+            JcError::DecompressionFailed {
+                tool: "cpio".to_string(),
+                stderr: e.to_string(),
+            }
+        })?;
+
+        // Output is the filename without .cpio extension
+        let output_path = input.with_extension("");
+
+        // Move to destination if specified
+        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+
+        info!("Extracted CPIO archive to: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn supports_levels(&self) -> bool {
+        false // CPIO doesn't support compression levels
+    }
+
+    fn validate_level(&self, _level: u8) -> bool {
+        true // Always valid (no-op)
+    }
+
+    fn default_level(&self) -> u8 {
+        0
+    }
+}
+
+impl CpioCompressor {
+    /// Decompress in a specific working directory
+    pub fn decompress_in_dir(
+        &self,
+        input: &Path,
+        working_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<PathBuf> {
+        if detect_format(input) != Some(CompressionFormat::Cpio) {
+            return Err(JcError::InvalidExtension(
+                input.to_path_buf(),
+                "cpio".to_string(),
+            ));
+        }
+
+        debug!(
+            "Extracting CPIO archive {} in working dir {}",
+            input.display(),
+            working_dir.display()
+        );
+
+        // Copy input file to working directory
+        let work_input = copy_to_dir(input, working_dir)?;
+
+        self.extract_archive(&work_input, working_dir, config)
+            .map_err(|e| {
+                if e.is_cancellation() {
+                    return e;
+                }
+                JcError::DecompressionFailed {
+                    tool: "cpio".to_string(),
+                    stderr: e.to_string(),
+                }
+            })?;
+
+        find_extracted_output(working_dir, &work_input)
+    }
+}
+
+/// Write one newc entry (header, name, data and padding)
+fn write_entry<W: Write>(
+    writer: &mut W,
+    ino: u32,
+    mode: u32,
+    nlink: u32,
+    mtime: u32,
+    name: &str,
+    data: &[u8],
+) -> JcResult<()> {
+    let namesize = name.len() + 1;
+    let fields = [
+        ino,
+        mode,
+        0, // uid
+        0, // gid
+        nlink,
+        mtime,
+        data.len() as u32,
+        0, // devmajor
+        0, // devminor
+        0, // rdevmajor
+        0, // rdevminor
+        namesize as u32,
+        0, // check
+    ];
+
+    writer.write_all(NEWC_MAGIC)?;
+    for field in fields {
+        write!(writer, "{:08X}", field)?;
+    }
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(&vec![0u8; padding(HEADER_LEN + namesize)])?;
+    writer.write_all(data)?;
+    writer.write_all(&vec![0u8; padding(data.len())])?;
+    Ok(())
+}
+
+/// Read and parse the next newc header (including its name)
+fn read_header<R: Read>(reader: &mut R) -> JcResult<CpioHeader> {
+    let mut raw = [0u8; HEADER_LEN];
+    reader.read_exact(&mut raw)?;
+
+    if &raw[..6] != NEWC_MAGIC && &raw[..6] != CRC_MAGIC {
+        return Err(JcError::Other(format!(
+            "Unsupported cpio header magic: {}",
+            String::from_utf8_lossy(&raw[..6])
+        )));
+    }
+
+    let field = |index: usize| -> JcResult<u32> {
+        let start = 6 + index * 8;
+        let text = std::str::from_utf8(&raw[start..start + 8])
+            .map_err(|_| JcError::Other("Corrupt cpio header".to_string()))?;
+        u32::from_str_radix(text, 16).map_err(|_| JcError::Other("Corrupt cpio header".to_string()))
+    };
+
+    let namesize = field(11)? as usize;
+    let mut name = vec![0u8; namesize];
+    reader.read_exact(&mut name)?;
+    skip_padding(reader, HEADER_LEN + namesize)?;
+
+    // Name is NUL-terminated
+    if name.last() == Some(&0) {
+        name.pop();
+    }
+
+    Ok(CpioHeader {
+        ino: field(0)?,
+        mode: field(1)?,
+        nlink: field(4)?,
+        mtime: field(5)?,
+        filesize: field(6)?,
+        name: String::from_utf8_lossy(&name).to_string(),
+    })
+}
+
+/// Number of padding bytes needed to reach 4-byte alignment
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+fn skip_padding<R: Read>(reader: &mut R, len: usize) -> JcResult<()> {
+    let mut pad = [0u8; 4];
+    reader.read_exact(&mut pad[..padding(len)])?;
+    Ok(())
+}
+
+/// Turn an archive entry name into a safe relative path
+///
+/// Leading `/` and `./` are stripped (initramfs images often use absolute
+/// names); entries escaping the destination via `..` are rejected.
+pub(crate) fn sanitize_entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+fn write_file(target: &Path, data: &[u8], header: &CpioHeader) -> JcResult<()> {
+    let mut file = File::create(target)?;
+    file.write_all(data)?;
+    let _ = file.set_modified(UNIX_EPOCH + Duration::from_secs(header.mtime as u64));
+    drop(file);
+    set_permissions(target, header.mode);
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn permission_bits(metadata: &fs::Metadata, _default: u32) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+pub(crate) fn permission_bits(_metadata: &fs::Metadata, default: u32) -> u32 {
+    default
+}
+
+#[cfg(unix)]
+pub(crate) fn set_permissions(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777)) {
+        debug!("Failed to set permissions on {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_permissions(_path: &Path, _mode: u32) {}
+
+#[cfg(unix)]
+pub(crate) fn create_symlink(target: &str, link: &Path) -> JcResult<()> {
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)?;
+    }
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn create_symlink(target: &str, link: &Path) -> JcResult<()> {
+    debug!(
+        "Skipping symlink {} -> {} (unsupported on this platform)",
+        link.display(),
+        target
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cpio_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("initramfs");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("init"), b"#!/bin/sh\necho hi\n").unwrap();
+        fs::write(src.join("bin/tool"), b"odd length").unwrap();
+
+        let archive = temp_dir.path().join("out.cpio");
+        let compressor = CpioCompressor::new();
+        compressor
+            .write_archive(&src, &archive, &CompressionConfig::default())
+            .unwrap();
+
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        compressor
+            .extract_archive(&archive, &dest, &CompressionConfig::default())
+            .unwrap();
+
+        assert_eq!(
+            fs::read(dest.join("initramfs/init")).unwrap(),
+            b"#!/bin/sh\necho hi\n"
+        );
+        assert_eq!(
+            fs::read(dest.join("initramfs/bin/tool")).unwrap(),
+            b"odd length"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_stored_or_followed() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("tree");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("real"), b"contents").unwrap();
+        std::os::unix::fs::symlink("real", src.join("link")).unwrap();
+        // Loops back to the tree root; must not recurse forever
+        std::os::unix::fs::symlink(".", src.join("self")).unwrap();
+
+        let compressor = CpioCompressor::new();
+        for dereference in [false, true] {
+            let archive = temp_dir.path().join(format!("{}.cpio", dereference));
+            compressor
+                .write_archive(
+                    &src,
+                    &archive,
+                    &CompressionConfig::default().with_dereference(dereference),
+                )
+                .unwrap();
+
+            let dest = temp_dir.path().join(format!("dest-{}", dereference));
+            fs::create_dir(&dest).unwrap();
+            compressor
+                .extract_archive(&archive, &dest, &CompressionConfig::default())
+                .unwrap();
+
+            let link = dest.join("tree/link");
+            let is_link = fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink();
+            assert_eq!(is_link, !dereference);
+            assert_eq!(fs::read(&link).unwrap(), b"contents");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_never_writes_through_symlink() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+
+        // "escape" links out of the destination, then "escape/owned" tries
+        // to use it
+        let archive = temp_dir.path().join("evil.cpio");
+        let mut writer = File::create(&archive).unwrap();
+        let target = outside.to_string_lossy().to_string();
+        write_entry(
+            &mut writer,
+            1,
+            S_IFLNK | 0o777,
+            1,
+            0,
+            "escape",
+            target.as_bytes(),
+        )
+        .unwrap();
+        write_entry(&mut writer, 2, S_IFREG | 0o644, 1, 0, "escape/owned", b"x").unwrap();
+        write_entry(&mut writer, 0, 0, 1, 0, TRAILER, &[]).unwrap();
+        drop(writer);
+
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        CpioCompressor::new()
+            .extract_archive(&archive, &dest, &CompressionConfig::default())
+            .unwrap();
+
+        assert!(!outside.join("owned").exists());
+    }
+
+    #[test]
+    fn test_sanitize_entry_path() {
+        assert_eq!(
+            sanitize_entry_path("/etc/passwd"),
+            Some(PathBuf::from("etc/passwd"))
+        );
+        assert_eq!(sanitize_entry_path("./init"), Some(PathBuf::from("init")));
+        assert_eq!(sanitize_entry_path("../escape"), None);
+        assert_eq!(sanitize_entry_path("."), None);
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compressors::ar::ArCompressor;
+use crate::compressors::detect_format;
+use crate::core::compressor::Compressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::utils::{debug, info, move_file_if_needed};
+
+/// Debian package handler (decompress only)
+///
+/// A `.deb` is an ar archive holding `debian-binary`, a `control.tar.*`
+/// member and a `data.tar.*` member.
+#[derive(Debug, Clone, Default)]
+pub struct DebCompressor;
+
+/// Members of an extracted Debian package
+#[derive(Debug, Clone)]
+pub struct DebMembers {
+    /// Package metadata archive (control.tar.*)
+    pub control: Option<PathBuf>,
+
+    /// Installed file tree archive (data.tar.*)
+    pub data: PathBuf,
+}
+
+impl DebCompressor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Extract the ar members of a package into `dest_dir`
+    pub fn extract_members(&self, input: &Path, dest_dir: &Path) -> JcResult<DebMembers> {
+        if detect_format(input) != Some(CompressionFormat::Deb) {
+            return Err(JcError::InvalidExtension(
+                input.to_path_buf(),
+                "deb".to_string(),
+            ));
+        }
+
+        ArCompressor::new().extract(input, dest_dir)?;
+
+        if !dest_dir.join("debian-binary").exists() {
+            return Err(JcError::DecompressionFailed {
+                tool: "deb".to_string(),
+                stderr: format!("{} is not a Debian package", input.display()),
+            });
+        }
+
+        let find_member = |prefix: &str| -> Option<PathBuf> {
+            fs::read_dir(dest_dir)
+                .ok()?
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .find(|p| {
+                    p.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.starts_with(prefix))
+                })
+        };
+
+        let data = find_member("data.tar").ok_or_else(|| JcError::DecompressionFailed {
+            tool: "deb".to_string(),
+            stderr: format!("{} has no data.tar member", input.display()),
+        })?;
+
+        Ok(DebMembers {
+            control: find_member("control.tar"),
+            data,
+        })
+    }
+}
+
+impl Compressor for DebCompressor {
+    fn name(&self) -> &'static str {
+        "deb"
+    }
+
+    fn extension(&self) -> &'static str {
+        "deb"
+    }
+
+    fn compress(&self, _input: &Path, _config: &CompressionConfig) -> JcResult<PathBuf> {
+        Err(JcError::Other(
+            "deb packages can only be extracted, not created".to_string(),
+        ))
+    }
+
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        debug!("Extracting Debian package members of {}", input.display());
+
+        // Members go into a directory named after the package
+        let output_path = input.with_extension("");
+        fs::create_dir_all(&output_path)?;
+        self.extract_members(input, &output_path)?;
+
+        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+
+        info!(
+            "Extracted Debian package members to: {}",
+            final_path.display()
+        );
+        Ok(final_path)
+    }
+
+    fn supports_levels(&self) -> bool {
+        false
+    }
+
+    fn validate_level(&self, _level: u8) -> bool {
+        true // Always valid (no-op)
+    }
+
+    fn default_level(&self) -> u8 {
+        0
+    }
+}
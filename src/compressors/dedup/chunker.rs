@@ -0,0 +1,174 @@
+//! FastCDC content-defined chunking
+//!
+//! Cut points depend only on the bytes around them (a gear rolling hash),
+//! so an insertion early in a file shifts the chunk boundaries with the
+//! data instead of changing every chunk after it. Normalized chunking
+//! uses a stricter mask below the average size and a looser one above it,
+//! keeping most chunks close to the average.
+
+use std::io::{self, Read};
+
+use crate::core::error::JcResult;
+
+/// Random values per byte for the gear hash, fixed so that the same data
+/// is always cut the same way
+const GEAR: [u64; 256] = gear_table();
+
+/// splitmix64 over a fixed seed
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6a63_7a5f_6465_6475;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Chunk size limits and the masks derived from the average
+#[derive(Debug, Clone, Copy)]
+pub struct FastCdc {
+    min: usize,
+    avg: usize,
+    max: usize,
+    mask_small: u64,
+    mask_large: u64,
+}
+
+/// `bits` one bits at the top of the word, where the gear hash has seen
+/// the most bytes
+const fn top_mask(bits: u32) -> u64 {
+    !0u64 << (64 - bits)
+}
+
+impl FastCdc {
+    /// Chunks averaging `avg` bytes (a power of two), between a quarter
+    /// and four times that
+    pub fn new(avg: usize) -> Self {
+        let bits = avg.trailing_zeros();
+        FastCdc {
+            min: avg / 4,
+            avg,
+            max: avg * 4,
+            mask_small: top_mask(bits + 2),
+            mask_large: top_mask(bits - 2),
+        }
+    }
+
+    /// Length of the first chunk of `data`; all of it when it is shorter
+    /// than the minimum
+    pub fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let end = data.len().min(self.max);
+        let normal = end.min(self.avg);
+
+        let mut hash: u64 = 0;
+        let mut i = self.min;
+        while i < normal {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & self.mask_small == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+        while i < end {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & self.mask_large == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+        end
+    }
+
+    /// Split everything read from `reader` into chunks, calling `emit` for
+    /// each in order; returns the bytes read
+    pub fn chunk_reader<R: Read>(
+        &self,
+        mut reader: R,
+        mut emit: impl FnMut(&[u8]) -> JcResult<()>,
+    ) -> JcResult<u64> {
+        let mut buf = vec![0u8; self.max];
+        let mut len = 0;
+        let mut total = 0;
+        let mut eof = false;
+        loop {
+            // Keep a full window unless the input has ended
+            while !eof && len < self.max {
+                match reader.read(&mut buf[len..]) {
+                    Ok(0) => eof = true,
+                    Ok(n) => len += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if len == 0 {
+                return Ok(total);
+            }
+            let cut = self.cut(&buf[..len]);
+            emit(&buf[..cut])?;
+            total += cut as u64;
+            buf.copy_within(cut..len, 0);
+            len -= cut;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn chunks(chunker: &FastCdc, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        chunker
+            .chunk_reader(data, |chunk| {
+                out.push(chunk.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_chunks_cover_input_within_limits() {
+        let chunker = FastCdc::new(4096);
+        let data = noise(200_000, 1);
+        let chunks = chunks(&chunker, &data);
+        assert_eq!(chunks.concat(), data);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|c| (1024..=16384).contains(&c.len())));
+        assert!(last.len() <= 16384);
+    }
+
+    #[test]
+    fn test_boundaries_survive_an_insertion() {
+        let chunker = FastCdc::new(4096);
+        let data = noise(200_000, 2);
+        let mut shifted = b"a few inserted bytes".to_vec();
+        shifted.extend_from_slice(&data);
+
+        let before = chunks(&chunker, &data);
+        let after = chunks(&chunker, &shifted);
+        let shared = after.iter().filter(|c| before.contains(c)).count();
+        assert!(shared + 3 >= before.len(), "{} of {}", shared, before.len());
+    }
+}
@@ -0,0 +1,242 @@
+//! The `.jczd` container
+//!
+//! ```text
+//! "JCZDEDUP" version(1)
+//! chunk*                 each unique chunk, deflated, back to back
+//! manifest               deflated JSON: chunk table and entries
+//! manifest_offset (u64 LE) manifest_len (u64 LE) "JCZDEND\0"
+//! ```
+//!
+//! The manifest lists every chunk (offset, stored and plain size and
+//! SHA-256) and every entry (path, kind, mode, mtime and, for files, the
+//! chunk numbers making up its data). Chunks are checked against their
+//! hash as they are read.
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::core::error::{JcError, JcResult};
+
+const MAGIC: &[u8; 8] = b"JCZDEDUP";
+const TRAILER_MAGIC: &[u8; 8] = b"JCZDEND\0";
+const VERSION: u8 = 1;
+
+/// Bytes of the fixed trailer: two offsets and the magic
+const TRAILER_LEN: u64 = 24;
+
+/// Whether `header` starts a `.jczd` archive
+pub fn is_dedup_header(header: &[u8]) -> bool {
+    header.starts_with(MAGIC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// One stored chunk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub offset: u64,
+    pub stored: u32,
+    pub size: u32,
+    pub sha256: String,
+}
+
+/// One file, directory or symlink
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub kind: Kind,
+    pub mode: u32,
+    pub mtime: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub chunks: Vec<Chunk>,
+    pub entries: Vec<Entry>,
+}
+
+/// How much deduplication saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    /// Chunks in all files
+    pub chunks: u64,
+    /// Chunks stored, once each
+    pub unique_chunks: u64,
+    /// Bytes in all files
+    pub bytes: u64,
+    /// Bytes of the unique chunks before compression
+    pub unique_bytes: u64,
+}
+
+/// Writes chunks as they come and the manifest at the end
+pub struct ArchiveWriter<W: Write> {
+    out: W,
+    offset: u64,
+    level: Compression,
+    known: HashMap<[u8; 32], u32>,
+    manifest: Manifest,
+    stats: DedupStats,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut out: W, level: u8) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(ArchiveWriter {
+            out,
+            offset: MAGIC.len() as u64 + 1,
+            level: Compression::new(level.into()),
+            known: HashMap::new(),
+            manifest: Manifest::default(),
+            stats: DedupStats::default(),
+        })
+    }
+
+    /// Store `data` unless an identical chunk already is, returning its
+    /// number
+    pub fn add_chunk(&mut self, data: &[u8]) -> io::Result<u32> {
+        let digest: [u8; 32] = Sha256::digest(data).into();
+        self.stats.chunks += 1;
+        self.stats.bytes += data.len() as u64;
+        if let Some(&index) = self.known.get(&digest) {
+            return Ok(index);
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
+        encoder.write_all(data)?;
+        let stored = encoder.finish()?;
+        self.out.write_all(&stored)?;
+
+        let index = self.manifest.chunks.len() as u32;
+        self.manifest.chunks.push(Chunk {
+            offset: self.offset,
+            stored: stored.len() as u32,
+            size: data.len() as u32,
+            sha256: hex(&digest),
+        });
+        self.offset += stored.len() as u64;
+        self.known.insert(digest, index);
+        self.stats.unique_chunks += 1;
+        self.stats.unique_bytes += data.len() as u64;
+        Ok(index)
+    }
+
+    pub fn add_entry(&mut self, entry: Entry) {
+        self.manifest.entries.push(entry);
+    }
+
+    /// Write the manifest and trailer
+    pub fn finish(mut self) -> JcResult<(W, DedupStats)> {
+        let json = serde_json::to_vec(&self.manifest)
+            .map_err(|e| JcError::Other(format!("Failed to encode dedup manifest: {}", e)))?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
+        encoder.write_all(&json)?;
+        let manifest = encoder.finish()?;
+
+        self.out.write_all(&manifest)?;
+        self.out.write_all(&self.offset.to_le_bytes())?;
+        self.out.write_all(&(manifest.len() as u64).to_le_bytes())?;
+        self.out.write_all(TRAILER_MAGIC)?;
+        self.out.flush()?;
+        Ok((self.out, self.stats))
+    }
+}
+
+/// Reads the manifest up front and chunks on demand
+pub struct ArchiveReader<R: Read + Seek> {
+    inner: R,
+    pub manifest: Manifest,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    pub fn open(mut inner: R) -> JcResult<Self> {
+        let mut header = [0u8; 9];
+        inner
+            .read_exact(&mut header)
+            .map_err(|_| corrupt("truncated"))?;
+        if !is_dedup_header(&header) {
+            return Err(corrupt("not a jcz dedup archive"));
+        }
+        if header[8] != VERSION {
+            return Err(JcError::Other(format!(
+                "Unsupported dedup archive version {}",
+                header[8]
+            )));
+        }
+
+        let len = inner.seek(SeekFrom::End(0))?;
+        if len < header.len() as u64 + TRAILER_LEN {
+            return Err(corrupt("truncated"));
+        }
+        inner.seek(SeekFrom::Start(len - TRAILER_LEN))?;
+        let mut trailer = [0u8; TRAILER_LEN as usize];
+        inner.read_exact(&mut trailer)?;
+        if &trailer[16..] != TRAILER_MAGIC {
+            return Err(corrupt("missing trailer"));
+        }
+        let offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        let stored = u64::from_le_bytes(trailer[8..16].try_into().unwrap());
+        if offset.saturating_add(stored) > len - TRAILER_LEN {
+            return Err(corrupt("manifest out of range"));
+        }
+
+        inner.seek(SeekFrom::Start(offset))?;
+        let mut json = Vec::new();
+        DeflateDecoder::new((&mut inner).take(stored))
+            .read_to_end(&mut json)
+            .map_err(|e| corrupt(&e.to_string()))?;
+        let manifest: Manifest =
+            serde_json::from_slice(&json).map_err(|e| corrupt(&e.to_string()))?;
+        Ok(ArchiveReader { inner, manifest })
+    }
+
+    /// The data of chunk `index`, checked against its hash
+    pub fn read_chunk(&mut self, index: u32) -> JcResult<Vec<u8>> {
+        let chunk = self
+            .manifest
+            .chunks
+            .get(index as usize)
+            .ok_or_else(|| corrupt("chunk number out of range"))?;
+        self.inner.seek(SeekFrom::Start(chunk.offset))?;
+        let mut data = Vec::with_capacity(chunk.size as usize);
+        DeflateDecoder::new((&mut self.inner).take(chunk.stored.into()))
+            .read_to_end(&mut data)
+            .map_err(|e| corrupt(&e.to_string()))?;
+        if data.len() != chunk.size as usize || hex(&Sha256::digest(&data)) != chunk.sha256 {
+            return Err(JcError::IntegrityFailed(format!(
+                "Chunk {} is damaged",
+                index
+            )));
+        }
+        Ok(data)
+    }
+}
+
+fn corrupt(reason: &str) -> JcError {
+    JcError::Other(format!("Corrupt dedup archive: {}", reason))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
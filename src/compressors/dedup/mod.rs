@@ -0,0 +1,404 @@
+//! Deduplicating archives (`-c dedup`, `.jczd`)
+//!
+//! Files are split into content-defined chunks (FastCDC, 64 KiB on
+//! average) and every distinct chunk is stored once, deflated. A directory
+//! of near-identical VM images or backups shrinks to roughly one copy plus
+//! what differs between them, which a stream compressor with its small
+//! window cannot find. See `format` for the container layout.
+
+mod chunker;
+mod format;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::compressors::cpio::{
+    create_symlink, permission_bits, sanitize_entry_path, set_permissions,
+};
+use crate::compressors::detect_format;
+use crate::core::compressor::Compressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::operations::stats::format_size;
+use crate::utils::bufio::{buffered_reader, buffered_writer};
+use crate::utils::{
+    debug, generate_output_filename, info, is_inside_without_symlinks, move_file_if_needed,
+    place_output, warn, Throttled,
+};
+
+pub use chunker::FastCdc;
+pub use format::{is_dedup_header, DedupStats};
+use format::{ArchiveReader, ArchiveWriter, Entry, Kind};
+
+/// Average chunk size: small enough to find shared runs inside large
+/// files, large enough to keep the manifest small
+const AVERAGE_CHUNK: usize = 64 * 1024;
+
+/// Deduplicating archiver (built in)
+#[derive(Debug, Clone, Default)]
+pub struct DedupCompressor;
+
+/// State carried through one archive's directory walk
+struct TreeWalk<W: Write> {
+    writer: ArchiveWriter<W>,
+    chunker: FastCdc,
+    dereference: bool,
+    reproducible: Option<u64>,
+    /// Canonical directories being walked, to catch symlink loops
+    ancestors: Vec<PathBuf>,
+}
+
+impl DedupCompressor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Write an archive of `input` (recursively) to `output`
+    fn write_archive(
+        &self,
+        input: &Path,
+        output: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<DedupStats> {
+        let base = input
+            .file_name()
+            .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+
+        let out = buffered_writer(Throttled::new(
+            config.watched(File::create(output)?, output),
+            config.limit_rate.as_ref(),
+        ));
+        let mut walk = TreeWalk {
+            writer: ArchiveWriter::new(out, config.level)?,
+            chunker: FastCdc::new(AVERAGE_CHUNK),
+            dereference: config.dereference,
+            reproducible: config.reproducible,
+            ancestors: Vec::new(),
+        };
+        write_tree(&mut walk, input, Path::new(base), config)?;
+        let (_, stats) = walk.writer.finish()?;
+        Ok(stats)
+    }
+
+    /// Extract the archive `archive` into `dest_dir`, returning the first
+    /// top-level entry
+    fn extract_archive(
+        &self,
+        archive: &Path,
+        dest_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<Option<PathBuf>> {
+        let file = config.watched(File::open(archive)?, archive);
+        let mut reader = ArchiveReader::open(buffered_reader(file))?;
+        let entries = std::mem::take(&mut reader.manifest.entries);
+
+        let mut top = None;
+        for entry in &entries {
+            let Some(relative) = sanitize_entry_path(&entry.path) else {
+                debug!("Skipping unsafe dedup entry: {}", entry.path);
+                continue;
+            };
+            if !is_inside_without_symlinks(dest_dir, &relative) {
+                warn!("Skipping dedup entry below a symlink: {}", entry.path);
+                continue;
+            }
+            let target = dest_dir.join(&relative);
+            top.get_or_insert_with(|| dest_dir.join(relative.components().next().unwrap()));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+                fs::remove_file(&target)?;
+            }
+
+            match entry.kind {
+                Kind::Directory => {
+                    fs::create_dir_all(&target)?;
+                    set_permissions(&target, entry.mode);
+                }
+                Kind::Symlink => {
+                    create_symlink(entry.target.as_deref().unwrap_or_default(), &target)?;
+                }
+                Kind::File => {
+                    let mut file = File::create(&target)?;
+                    for &chunk in &entry.chunks {
+                        file.write_all(&reader.read_chunk(chunk)?)?;
+                    }
+                    let _ = file.set_modified(UNIX_EPOCH + Duration::from_secs(entry.mtime));
+                    drop(file);
+                    set_permissions(&target, entry.mode);
+                }
+            }
+        }
+        Ok(top)
+    }
+
+    /// Decompress in a specific working directory
+    pub fn decompress_in_dir(
+        &self,
+        input: &Path,
+        working_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<PathBuf> {
+        check_extension(input)?;
+        debug!(
+            "Extracting dedup archive {} in working dir {}",
+            input.display(),
+            working_dir.display()
+        );
+        let top = self
+            .extract_archive(input, working_dir, config)
+            .map_err(extract_error)?;
+        Ok(top.unwrap_or_else(|| working_dir.to_path_buf()))
+    }
+}
+
+/// Append `path` (stored as `name`) and its children to the archive
+fn write_tree<W: Write>(
+    walk: &mut TreeWalk<W>,
+    path: &Path,
+    name: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    let mut metadata = fs::symlink_metadata(path)?;
+    if walk.dereference && metadata.file_type().is_symlink() {
+        // Dangling links are kept as links
+        metadata = fs::metadata(path).unwrap_or(metadata);
+    }
+    if config.filter.excludes(&metadata) {
+        debug!("Outside the size range: {}", path.display());
+        return Ok(());
+    }
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mtime = walk.reproducible.map_or(mtime, |epoch| mtime.min(epoch));
+    let mut entry = Entry {
+        path: name.to_string_lossy().replace('\\', "/"),
+        kind: Kind::File,
+        mode: 0,
+        mtime,
+        size: 0,
+        chunks: Vec::new(),
+        target: None,
+    };
+
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        entry.kind = Kind::Symlink;
+        entry.mode = 0o777;
+        entry.target = Some(fs::read_link(path)?.to_string_lossy().into_owned());
+        walk.writer.add_entry(entry);
+    } else if file_type.is_dir() {
+        let canonical = path.canonicalize()?;
+        if walk.ancestors.contains(&canonical) {
+            warn!("Skipping {}: symlink loop", path.display());
+            return Ok(());
+        }
+        entry.kind = Kind::Directory;
+        entry.mode = permission_bits(&metadata, 0o755);
+        walk.writer.add_entry(entry);
+
+        // Sort children so identical trees produce identical archives
+        let mut children: Vec<_> = fs::read_dir(path)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name())
+            .collect();
+        children.sort();
+        walk.ancestors.push(canonical);
+        for child in children {
+            write_tree(walk, &path.join(&child), &name.join(&child), config)?;
+        }
+        walk.ancestors.pop();
+    } else {
+        entry.mode = permission_bits(&metadata, 0o644);
+        let reader = Throttled::new(
+            config.watched(File::open(path)?, path),
+            config.limit_rate.as_ref(),
+        );
+        let TreeWalk {
+            writer, chunker, ..
+        } = walk;
+        let mut chunks = Vec::new();
+        entry.size = chunker.chunk_reader(buffered_reader(reader), |chunk| {
+            chunks.push(writer.add_chunk(chunk)?);
+            Ok(())
+        })?;
+        entry.chunks = chunks;
+        writer.add_entry(entry);
+    }
+    Ok(())
+}
+
+fn check_extension(input: &Path) -> JcResult<()> {
+    if detect_format(input) == Some(CompressionFormat::Dedup) {
+        Ok(())
+    } else {
+        Err(JcError::InvalidExtension(
+            input.to_path_buf(),
+            "jczd".to_string(),
+        ))
+    }
+}
+
+fn extract_error(e: JcError) -> JcError {
+    if e.is_cancellation() || matches!(e, JcError::IntegrityFailed(_)) {
+        return e;
+    }
+    JcError::DecompressionFailed {
+        tool: "dedup".to_string(),
+        stderr: e.to_string(),
+    }
+}
+
+impl Compressor for DedupCompressor {
+    fn name(&self) -> &'static str {
+        "dedup"
+    }
+
+    fn extension(&self) -> &'static str {
+        "jczd"
+    }
+
+    fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        if !input.exists() {
+            return Err(JcError::FileNotFound(input.to_path_buf()));
+        }
+
+        let output_path = generate_output_filename(input, "jczd", config)?;
+        info!(
+            "Creating dedup archive {} from {}",
+            output_path.display(),
+            input.display()
+        );
+
+        let stats = self
+            .write_archive(input, &output_path, config)
+            .map_err(|e| {
+                let _ = fs::remove_file(&output_path);
+                if e.is_cancellation() {
+                    return e;
+                }
+                JcError::CompressionFailed {
+                    tool: "dedup".to_string(),
+                    stderr: e.to_string(),
+                }
+            })?;
+        info!(
+            "{} chunks, {} unique: {} of {} stored before compression",
+            stats.chunks,
+            stats.unique_chunks,
+            format_size(stats.unique_bytes),
+            format_size(stats.bytes)
+        );
+
+        let final_path = place_output(&output_path, config)?;
+        info!("Created dedup archive: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        check_extension(input)?;
+        debug!("Extracting dedup archive {}", input.display());
+
+        let parent = input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let top = self
+            .extract_archive(input, parent, config)
+            .map_err(extract_error)?;
+        let output_path = top.unwrap_or_else(|| input.with_extension(""));
+
+        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        info!("Extracted dedup archive to: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn supports_levels(&self) -> bool {
+        true
+    }
+
+    fn validate_level(&self, level: u8) -> bool {
+        (1..=9).contains(&level)
+    }
+
+    fn default_level(&self) -> u8 {
+        6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_duplicate_files_are_stored_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("images");
+        fs::create_dir_all(src.join("b")).unwrap();
+        let mut state = 7u64;
+        let image: Vec<u8> = (0..1 << 20)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut patched = image.clone();
+        patched[500_000..500_010].copy_from_slice(b"0123456789");
+        fs::write(src.join("a.img"), &image).unwrap();
+        fs::write(src.join("b/a-copy.img"), &image).unwrap();
+        fs::write(src.join("b/patched.img"), &patched).unwrap();
+
+        let archive = temp_dir.path().join("images.jczd");
+        let stats = DedupCompressor::new()
+            .write_archive(&src, &archive, &CompressionConfig::default())
+            .unwrap();
+        assert_eq!(stats.bytes, 3 << 20);
+        assert!(stats.unique_bytes < (1 << 20) + (300 << 10), "{:?}", stats);
+
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        let top = DedupCompressor::new()
+            .extract_archive(&archive, &dest, &CompressionConfig::default())
+            .unwrap();
+        assert_eq!(top, Some(dest.join("images")));
+        assert_eq!(fs::read(dest.join("images/a.img")).unwrap(), image);
+        assert_eq!(fs::read(dest.join("images/b/a-copy.img")).unwrap(), image);
+        assert_eq!(
+            fs::read(dest.join("images/b/patched.img")).unwrap(),
+            patched
+        );
+    }
+
+    #[test]
+    fn test_damaged_chunk_is_detected() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, b"some data to store").unwrap();
+        let archive = temp_dir.path().join("data.jczd");
+        DedupCompressor::new()
+            .write_archive(&input, &archive, &CompressionConfig::default())
+            .unwrap();
+
+        // The single chunk starts right after the 9-byte header
+        let mut bytes = fs::read(&archive).unwrap();
+        bytes[12] ^= 0x40;
+        fs::write(&archive, bytes).unwrap();
+
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        let result =
+            DedupCompressor::new().extract_archive(&archive, &dest, &CompressionConfig::default());
+        assert!(result.is_err());
+    }
+}
@@ -1,15 +1,61 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
 
+use flate2::bufread::{DeflateDecoder, GzDecoder};
+use flate2::{Compression, GzBuilder};
+
+use crate::compressors::{
+    decode_error, decompress_piped, detect_format, filter_stream, has_tool_suffix, spawn_error,
+    spawn_filter, strip_layer_extension, Direction, Tool,
+};
 use crate::core::compressor::Compressor;
 use crate::core::config::CompressionConfig;
 use crate::core::error::{JcError, JcResult};
-use crate::utils::{copy_to_dir, debug, generate_output_filename, info, move_file_if_needed};
+use crate::core::types::CompressionFormat;
+use crate::utils::bufio::{buffered_reader, buffered_writer};
+use crate::utils::{
+    copy_to_dir, debug, generate_output_filename, info, is_cancelled_io, move_file_if_needed,
+    place_output, remove_file_silent, warn, Throttled,
+};
+
+/// Header flag: optional extra field present
+const FEXTRA: u8 = 0x04;
+
+/// Header flag: zero-terminated original file name present
+const FNAME: u8 = 0x08;
+
+/// Header flag: zero-terminated comment present
+const FCOMMENT: u8 = 0x10;
+
+/// Header flag: CRC16 of the header present
+const FHCRC: u8 = 0x02;
+
+/// Size of a member trailer: CRC32 and length of the data
+const TRAILER_LEN: usize = 8;
+
+/// What gzip, pigz and flate2 say about data failing its CRC32 or length
+const CHECKSUM_MESSAGES: &[&str] = &[
+    "crc error",
+    "length error",
+    "crc32 mismatch",
+    "length mismatch",
+    "does not have a matching checksum",
+];
+
+/// First bytes of every gzip member: magic and the deflate method
+const MEMBER_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// Largest member whose output `--salvage` holds back until it checks out
+const SALVAGE_HOLD: usize = 16 << 20;
+
+/// Fixed-size part of a gzip member header
+const HEADER_LEN: usize = 10;
 
 /// GZIP compressor implementation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct GzipCompressor;
 
 impl GzipCompressor {
@@ -41,9 +87,11 @@ impl Compressor for GzipCompressor {
     }
 
     fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let config = config.for_compressor(self);
+        let config = &*config;
         self.validate_input(input)?;
 
-        let output_path = generate_output_filename(input, "gz", config.timestamp)?;
+        let output_path = generate_output_filename(input, "gz", config)?;
         info!(
             "Compressing {} to {} with gzip",
             input.display(),
@@ -51,45 +99,19 @@ impl Compressor for GzipCompressor {
         );
         debug!("Compression level: {}", config.level);
 
-        // Create output file with buffered writer
-        let output_file = File::create(&output_path)?;
-        let mut writer = BufWriter::new(output_file);
-
-        // Execute gzip command
-        let mut cmd = Command::new("gzip");
-        cmd.arg(format!("-{}", config.level))
-            .arg("--keep")
-            .arg("--stdout")
-            .arg(input)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        debug!("Executing: {:?}", cmd);
-
-        let mut child = cmd
-            .spawn()
-            .map_err(|e| JcError::Other(format!("Failed to spawn gzip: {}", e)))?;
-
-        // Stream stdout to output file
-        if let Some(mut stdout) = child.stdout.take() {
-            std::io::copy(&mut stdout, &mut writer)?;
-        }
-
-        writer.flush()?;
-
-        // Wait for process and check exit status
-        let output = child.wait_with_output()?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::CompressionFailed {
-                tool: "gzip".to_string(),
-                stderr: stderr.to_string(),
-            });
+        let tool = Tool::for_format(CompressionFormat::Gzip, config);
+        if !tool.available() {
+            info!("gzip is not installed, using the built-in implementation");
+            warn_not_rsyncable(config);
+            compress_builtin(input, &output_path, config)?;
+        } else {
+            compress_external(tool, input, &output_path, config).inspect_err(|_| {
+                let _ = remove_file_silent(&output_path);
+            })?;
         }
 
         // Move to destination if specified
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        let final_path = place_output(&output_path, config)?;
 
         info!("Compressed file: {}", final_path.display());
         Ok(final_path)
@@ -97,7 +119,7 @@ impl Compressor for GzipCompressor {
 
     fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
         // Validate extension
-        if !input.to_string_lossy().ends_with(".gz") {
+        if detect_format(input) != Some(CompressionFormat::Gzip) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "gz".to_string(),
@@ -105,25 +127,33 @@ impl Compressor for GzipCompressor {
         }
 
         debug!("Decompressing {} with gzip", input.display());
+        let stored = read_stored_name(input, config)?;
 
         // Execute gzip decompression
-        let mut cmd = Command::new("gzip");
-        cmd.arg("-d").arg("-k").arg(input);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute gzip: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "gzip".to_string(),
-                stderr: stderr.to_string(),
-            });
+        let tool = Tool::for_format(CompressionFormat::Gzip, config);
+        if config.pipes_data()
+            || !tool.available()
+            || config.no_verify
+            || !has_tool_suffix(input, &[".gz", ".tgz"])
+        {
+            decompress_piped(self, input, &strip_layer_extension(input), config)?;
+        } else {
+            let mut cmd = Command::new(tool.program());
+            cmd.arg("-d").arg("-k").arg(input);
+
+            let output = cmd.output().map_err(|e| spawn_error(tool.program(), e))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(checksum_failure(JcError::DecompressionFailed {
+                    tool: tool.program().to_string(),
+                    stderr: stderr.to_string(),
+                }));
+            }
         }
 
         // Determine output filename (remove .gz)
-        let output_path = input.with_extension("");
+        let output_path = restore_stored_name(stored, strip_layer_extension(input))?;
 
         // Move to destination if specified
         let final_path = move_file_if_needed(&output_path, &config.move_to)?;
@@ -132,6 +162,49 @@ impl Compressor for GzipCompressor {
         Ok(final_path)
     }
 
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn compress_stream(
+        &self,
+        input: &mut (dyn Read + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let config = config.for_compressor(self);
+        let config = &*config;
+        let tool = Tool::for_format(CompressionFormat::Gzip, config);
+        if !tool.available() {
+            warn_not_rsyncable(config);
+            let mut encoder = GzBuilder::new().write(output, Compression::new(config.level.into()));
+            io::copy(input, &mut encoder)?;
+            encoder.finish()?.flush()?;
+            return Ok(());
+        }
+        let mut args = vec!["--no-name".to_string(), format!("-{}", config.level)];
+        args.extend(rsyncable_arg(config).map(str::to_string));
+        args.extend(tool.thread_args(config.threads));
+        filter_stream(tool, &args, input, output, Direction::Compress)
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &mut (dyn Read + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        if config.salvage {
+            return decompress_salvaging(input, output);
+        }
+        let tool = Tool::for_format(CompressionFormat::Gzip, config);
+        if !tool.available() || config.no_verify {
+            return decompress_builtin(input, output, !config.no_verify);
+        }
+        let args = ["-d".to_string()];
+        filter_stream(tool, &args, input, output, Direction::Decompress).map_err(checksum_failure)
+    }
+
     fn supports_levels(&self) -> bool {
         true
     }
@@ -151,10 +224,10 @@ impl GzipCompressor {
         &self,
         input: &Path,
         working_dir: &Path,
-        _config: &CompressionConfig,
+        config: &CompressionConfig,
     ) -> JcResult<PathBuf> {
         // Validate extension
-        if !input.to_string_lossy().ends_with(".gz") {
+        if detect_format(input) != Some(CompressionFormat::Gzip) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "gz".to_string(),
@@ -166,30 +239,550 @@ impl GzipCompressor {
             input.display(),
             working_dir.display()
         );
+        // Read first: gzip removes the input when it already sits in
+        // `working_dir`, as inner layers do
+        let stored = read_stored_name(input, config)?;
+
+        let tool = Tool::for_format(CompressionFormat::Gzip, config);
+        let work_input;
+        if config.pipes_data()
+            || !tool.available()
+            || config.no_verify
+            || !has_tool_suffix(input, &[".gz", ".tgz"])
+        {
+            // Read in place; only the output goes to the working directory
+            work_input = working_dir.join(input.file_name().unwrap_or_default());
+            let output_path = strip_layer_extension(&work_input);
+            decompress_piped(self, input, &output_path, config)?;
+        } else {
+            // Copy input file to working directory
+            work_input = copy_to_dir(input, working_dir)?;
+
+            // Execute gzip decompression in working directory
+            let mut cmd = Command::new(tool.program());
+            cmd.arg("-d").arg("-f").arg(&work_input);
+
+            let output = cmd.output().map_err(|e| spawn_error(tool.program(), e))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(checksum_failure(JcError::DecompressionFailed {
+                    tool: tool.program().to_string(),
+                    stderr: stderr.to_string(),
+                }));
+            }
+        }
 
-        // Copy input file to working directory
-        let work_input = copy_to_dir(input, working_dir)?;
+        // Determine output filename (remove .gz)
+        let output_path = restore_stored_name(stored, strip_layer_extension(&work_input))?;
+
+        debug!("Decompressed to: {}", output_path.display());
+        Ok(output_path)
+    }
+}
+
+/// Uncompressed size recorded in the trailer of the gzip at `path`
+///
+/// The trailer holds the size of the last member only, modulo 4 GiB, so
+/// this is a lower bound for multi-member or very large files.
+pub fn uncompressed_size(path: &Path) -> JcResult<Option<u64>> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() < 18 {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; 4];
+    file.seek(SeekFrom::End(-4))?;
+    file.read_exact(&mut trailer)?;
+    Ok(Some(u32::from_le_bytes(trailer) as u64))
+}
+
+/// Modification time of `path` for the gzip header; 0 when unknown
+fn mtime_secs(path: &Path) -> JcResult<u32> {
+    Ok(fs::metadata(path)?
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0))
+}
 
-        // Execute gzip decompression in working directory
-        let mut cmd = Command::new("gzip");
-        cmd.arg("-d").arg("-f").arg(&work_input);
+/// `--rsyncable` when asked for; gzip and pigz spell it the same
+fn rsyncable_arg(config: &CompressionConfig) -> Option<&'static str> {
+    config.gzip_tuning().rsyncable.then_some("--rsyncable")
+}
 
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute gzip: {}", e)))?;
+/// The built-in deflate cannot restart at content-defined points
+fn warn_not_rsyncable(config: &CompressionConfig) {
+    if config.gzip_tuning().rsyncable {
+        warn!("--rsyncable needs the gzip or pigz tool; the output is not rsync-friendly");
+    }
+}
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "gzip".to_string(),
-                stderr: stderr.to_string(),
-            });
+/// Compress `input` to `output` with the gzip binary, filling in the
+/// header's name and mtime as the built-in implementation does
+fn compress_external(
+    tool: Tool,
+    input: &Path,
+    output: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    let output_file = File::create(output)?;
+    let mut writer = Throttled::new(buffered_writer(output_file), config.limit_rate.as_ref());
+
+    // The name and mtime are filled in below, so the header carries the
+    // name jcz will restore, not the input's
+    let mut cmd = Command::new(tool.program());
+    cmd.arg("--no-name")
+        .arg(format!("-{}", config.level))
+        .args(rsyncable_arg(config))
+        .args(tool.thread_args(config.threads))
+        .arg("--keep")
+        .arg("--stdout")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut process = spawn_filter(&mut cmd, input, config, tool.program())?;
+    if let Some(mut stdout) = process.child.stdout.take() {
+        if !config.gzip_no_name && config.reproducible.is_none() {
+            let name = strip_layer_extension(output);
+            let name = name.file_name().unwrap_or_default();
+            let mtime = mtime_secs(input)?;
+            write_named_header(&mut stdout, &mut writer, name.as_encoded_bytes(), mtime)?;
         }
+        io::copy(&mut stdout, &mut writer)?;
+    }
+    writer.flush()?;
+
+    let result = process.wait()?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(JcError::CompressionFailed {
+            tool: tool.program().to_string(),
+            stderr: stderr.to_string(),
+        });
+    }
+    Ok(())
+}
 
-        // Determine output filename (remove .gz)
-        let output_path = work_input.with_extension("");
+/// Compress `input` to `output` with the built-in deflate, for systems
+/// without a gzip binary; the header gets the same name and mtime
+fn compress_builtin(input: &Path, output: &Path, config: &CompressionConfig) -> JcResult<()> {
+    let mut builder = GzBuilder::new();
+    if !config.gzip_no_name && config.reproducible.is_none() {
+        let name = strip_layer_extension(output);
+        let name = name.file_name().unwrap_or_default().as_encoded_bytes();
+        builder = builder.filename(name).mtime(mtime_secs(input)?);
+    }
 
-        debug!("Decompressed to: {}", output_path.display());
-        Ok(output_path)
+    let limit = config.limit_rate.as_ref();
+    let mut reader = Throttled::new(config.watched(File::open(input)?, input), limit);
+    let result = File::create(output)
+        .map_err(JcError::from)
+        .and_then(|file| {
+            let writer = Throttled::new(buffered_writer(file), limit);
+            let mut encoder = builder.write(writer, Compression::new(config.level.into()));
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?.flush()?;
+            Ok(())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+/// Decompress every member of a gzip stream with the built-in inflate,
+/// checking each against the CRC32 and length in its trailer unless
+/// `verify` is false (`--no-verify`)
+fn decompress_builtin(
+    input: &mut (dyn Read + Send),
+    output: &mut dyn Write,
+    verify: bool,
+) -> JcResult<()> {
+    let mut reader = buffered_reader(input);
+    let mut member = 0;
+    loop {
+        member += 1;
+        let decoded = if verify {
+            io::copy(&mut GzDecoder::new(&mut reader), output)
+        } else {
+            skip_member_header(&mut reader).and_then(|()| {
+                let size = io::copy(&mut DeflateDecoder::new(&mut reader), output)?;
+                reader.read_exact(&mut [0u8; TRAILER_LEN])?;
+                Ok(size)
+            })
+        };
+        decoded.map_err(|e| {
+            if is_checksum_message(&e.to_string()) {
+                JcError::IntegrityFailed(format!(
+                    "gzip member {} does not match its CRC32 or length",
+                    member
+                ))
+            } else {
+                decode_error("gzip", e)
+            }
+        })?;
+        if reader.fill_buf()?.is_empty() {
+            break;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Consume the header of a gzip member, whatever optional fields it has
+fn skip_member_header(reader: &mut impl BufRead) -> io::Result<()> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    if header[..MEMBER_MAGIC.len()] != MEMBER_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid gzip header",
+        ));
+    }
+    let flags = header[3];
+    if flags & FEXTRA != 0 {
+        let mut xlen = [0u8; 2];
+        reader.read_exact(&mut xlen)?;
+        let xlen = u16::from_le_bytes(xlen).into();
+        if io::copy(&mut reader.by_ref().take(xlen), &mut io::sink())? < xlen {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    for field in [FNAME, FCOMMENT] {
+        if flags & field != 0 {
+            reader.read_until(0, &mut Vec::new())?;
+        }
+    }
+    if flags & FHCRC != 0 {
+        reader.read_exact(&mut [0u8; 2])?;
+    }
+    Ok(())
+}
+
+/// Whether a decoder's message reports data failing its checksum
+fn is_checksum_message(message: &str) -> bool {
+    CHECKSUM_MESSAGES.iter().any(|m| message.contains(m))
+}
+
+/// `e`, or an integrity failure when it is a tool reporting a CRC32 or
+/// length mismatch
+fn checksum_failure(e: JcError) -> JcError {
+    match e {
+        JcError::DecompressionFailed { ref stderr, .. } if is_checksum_message(stderr) => {
+            JcError::IntegrityFailed(format!(
+                "gzip data does not match its CRC32 or length ({})",
+                stderr.trim()
+            ))
+        }
+        e => e,
+    }
+}
+
+/// Decompress a gzip stream member by member, skipping from a damaged
+/// member to the next member header (`--salvage`)
+///
+/// Members are independent, so everything from the next intact one on is
+/// recovered; for a single-member file, that is nothing after the damage.
+/// Members up to [`SALVAGE_HOLD`] are held back until their CRC checks, so
+/// a damaged one leaves a gap rather than garbage; larger ones are
+/// written as they decode.
+fn decompress_salvaging(input: &mut (dyn Read + Send), output: &mut dyn Write) -> JcResult<()> {
+    let mut reader = buffered_reader(input);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut held = Vec::new();
+    let mut written = 0u64;
+    let mut prefix: &[u8] = &[];
+    while !prefix.is_empty() || !reader.fill_buf()?.is_empty() {
+        let mut decoder = GzDecoder::new(prefix.chain(&mut reader));
+        held.clear();
+        let damage = loop {
+            match decoder.read(&mut buf) {
+                Ok(0) => break None,
+                Ok(n) if held.len() + n <= SALVAGE_HOLD => held.extend_from_slice(&buf[..n]),
+                Ok(n) => {
+                    output.write_all(&held)?;
+                    output.write_all(&buf[..n])?;
+                    written += (held.len() + n) as u64;
+                    held.clear();
+                }
+                Err(e) if is_cancelled_io(&e) => return Err(e.into()),
+                Err(e) => break Some(e),
+            }
+        };
+        prefix = &[];
+        let Some(e) = damage else {
+            output.write_all(&held)?;
+            written += held.len() as u64;
+            continue;
+        };
+        if skip_to_member(&mut reader)? {
+            warn!(
+                "Damaged gzip member after {} bytes of output ({}); resuming at the next member",
+                written, e
+            );
+            prefix = &MEMBER_MAGIC;
+        } else if written == 0 {
+            return Err(decode_error("gzip", e));
+        } else {
+            warn!(
+                "Damaged gzip member after {} bytes of output ({}); no intact member follows",
+                written, e
+            );
+            break;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Consume `reader` up to and including the next member magic, false when
+/// there is none
+fn skip_to_member(reader: &mut impl BufRead) -> io::Result<bool> {
+    let mut matched = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        for (i, &byte) in buf.iter().enumerate() {
+            matched = if byte == MEMBER_MAGIC[matched] {
+                matched + 1
+            } else {
+                usize::from(byte == MEMBER_MAGIC[0])
+            };
+            if matched == MEMBER_MAGIC.len() {
+                reader.consume(i + 1);
+                return Ok(true);
+            }
+        }
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+/// Copy the fixed header from `reader` to `writer` with FNAME and MTIME set
+fn write_named_header<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    name: &[u8],
+    mtime: u32,
+) -> io::Result<()> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    header[3] |= FNAME;
+    header[4..8].copy_from_slice(&mtime.to_le_bytes());
+
+    writer.write_all(&header)?;
+    writer.write_all(name)?;
+    writer.write_all(&[0])
+}
+
+/// Extract the FNAME field of a gzip header, if present
+pub fn stored_name(data: &[u8]) -> Option<String> {
+    let flags = *data.get(3)?;
+    if flags & FNAME == 0 {
+        return None;
+    }
+
+    let mut pos = HEADER_LEN;
+    if flags & FEXTRA != 0 {
+        let xlen = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        pos += 2 + xlen;
+    }
+
+    let rest = data.get(pos..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    let name = String::from_utf8_lossy(&rest[..end]).to_string();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Name stored in `archive`'s header, unless `-d --no-name` ignores it
+fn read_stored_name(archive: &Path, config: &CompressionConfig) -> JcResult<Option<String>> {
+    if config.gzip_no_name {
+        return Ok(None);
+    }
+    let mut header = Vec::new();
+    File::open(archive)?.take(4096).read_to_end(&mut header)?;
+    Ok(stored_name(&header))
+}
+
+/// Rename `output` to the `stored` name, like `gzip -dN`
+///
+/// Only the final path component of the stored name is used, and an
+/// existing file is never replaced.
+fn restore_stored_name(stored: Option<String>, output: PathBuf) -> JcResult<PathBuf> {
+    let Some(stored) = stored else {
+        return Ok(output);
+    };
+    let Some(name) = Path::new(&stored).file_name() else {
+        return Ok(output);
+    };
+    if output.file_name() == Some(name) {
+        return Ok(output);
+    }
+
+    let target = output.with_file_name(name);
+    if fs::symlink_metadata(&target).is_ok() {
+        warn!(
+            "Not restoring stored name {}: {} already exists",
+            stored,
+            target.display()
+        );
+        return Ok(output);
+    }
+
+    fs::rename(&output, &target)?;
+    info!(
+        "Restored original name from gzip header: {}",
+        target.display()
+    );
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_name() {
+        let mut header = vec![0x1F, 0x8B, 0x08, 0x08, 0, 0, 0, 0, 0, 0x03];
+        header.extend_from_slice(b"report.txt\0");
+        assert_eq!(stored_name(&header), Some("report.txt".to_string()));
+
+        // FEXTRA comes before the name
+        let mut header = vec![
+            0x1F, 0x8B, 0x08, 0x0C, 0, 0, 0, 0, 0, 0x03, 2, 0, 0xAA, 0xBB,
+        ];
+        header.extend_from_slice(b"x.tar\0");
+        assert_eq!(stored_name(&header), Some("x.tar".to_string()));
+
+        let header = [0x1F, 0x8B, 0x08, 0x00, 0, 0, 0, 0, 0, 0x03];
+        assert_eq!(stored_name(&header), None);
+    }
+
+    #[test]
+    fn test_named_header_rewrite() {
+        let header = [0x1F, 0x8B, 0x08, 0x00, 0, 0, 0, 0, 0, 0x03, 0xEE];
+        let mut reader = &header[..];
+        let mut out = Vec::new();
+        write_named_header(&mut reader, &mut out, b"a.txt", 1_700_000_000).unwrap();
+
+        assert_eq!(stored_name(&out), Some("a.txt".to_string()));
+        assert_eq!(&out[4..8], &1_700_000_000u32.to_le_bytes());
+        // The compressed stream itself is left to the caller
+        assert_eq!(reader, &[0xEE]);
+    }
+
+    #[test]
+    fn test_builtin_round_trip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, b"built-in deflate ".repeat(100)).unwrap();
+        let output = temp_dir.path().join("data.txt.gz");
+
+        compress_builtin(&input, &output, &CompressionConfig::default()).unwrap();
+        let compressed = fs::read(&output).unwrap();
+        assert_eq!(stored_name(&compressed), Some("data.txt".to_string()));
+
+        // A second member is decompressed too, as gzip does
+        let mut twice = compressed.clone();
+        twice.extend_from_slice(&compressed);
+        let mut decompressed = Vec::new();
+        decompress_builtin(&mut &twice[..], &mut decompressed, true).unwrap();
+        assert_eq!(decompressed, b"built-in deflate ".repeat(200));
+
+        let result = decompress_builtin(&mut &compressed[..20], &mut Vec::new(), true);
+        assert!(matches!(result, Err(JcError::DecompressionFailed { .. })));
+    }
+
+    #[test]
+    fn test_builtin_checksum_mismatch() {
+        let member = |data: &[u8]| {
+            let mut encoder = GzBuilder::new()
+                .filename("a.txt")
+                .comment("comment")
+                .write(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let mut stream = member(b"first");
+        let mut second = member(b"second");
+        // The CRC32 of the second member
+        let crc = second.len() - TRAILER_LEN;
+        second[crc] ^= 0xff;
+        stream.extend(second);
+
+        let result = decompress_builtin(&mut &stream[..], &mut Vec::new(), true);
+        match result {
+            Err(JcError::IntegrityFailed(message)) => assert!(message.contains("member 2")),
+            other => panic!("expected an integrity failure, got {:?}", other),
+        }
+
+        // --no-verify writes what the members decode to
+        let mut decompressed = Vec::new();
+        decompress_builtin(&mut &stream[..], &mut decompressed, false).unwrap();
+        assert_eq!(decompressed, b"firstsecond");
+    }
+
+    #[test]
+    fn test_salvage_skips_damaged_member() {
+        let member = |data: &[u8]| {
+            let mut encoder = GzBuilder::new().write(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let first = member(&b"lost ".repeat(1000));
+        let mut stream = first.clone();
+        stream.extend(member(b"kept"));
+        // Inside the deflate data of the first member
+        let middle = first.len() / 2;
+        stream[middle..middle + 4].fill(0xFF);
+
+        let mut out = Vec::new();
+        decompress_salvaging(&mut &stream[..], &mut out).unwrap();
+        assert_eq!(out, b"kept");
+
+        // Nothing intact at all is still an error
+        let result = decompress_salvaging(&mut &stream[..middle + 4], &mut Vec::new());
+        assert!(matches!(result, Err(JcError::DecompressionFailed { .. })));
+    }
+
+    #[test]
+    fn test_progress_and_cancellation() {
+        use crate::utils::{CancellationToken, ProgressSink};
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+
+        #[derive(Default)]
+        struct Counter(AtomicU64);
+
+        impl ProgressSink for Counter {
+            fn bytes_processed(&self, _path: &Path, bytes: u64) {
+                self.0.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, vec![b'x'; 300_000]).unwrap();
+        let compressor = GzipCompressor::new();
+
+        let counter = Arc::new(Counter::default());
+        let config = CompressionConfig::default().with_progress(counter.clone());
+        let output = compressor.compress(&input, &config).unwrap();
+        assert_eq!(counter.0.load(Ordering::Relaxed), 300_000);
+        fs::remove_file(output).unwrap();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let config = CompressionConfig::default().with_cancellation(cancel);
+        let result = compressor.compress(&input, &config);
+        assert!(matches!(result, Err(JcError::Aborted)));
+        assert!(!temp_dir.path().join("data.txt.gz").exists());
     }
 }
@@ -1,16 +1,41 @@
+pub mod ar;
 pub mod bzip2;
+pub mod command;
+pub mod cpio;
+pub mod deb;
+pub mod dedup;
 pub mod gzip;
+pub mod rpm;
+pub(crate) mod snapshot;
 pub mod tar;
+pub(crate) mod tools;
 pub mod xz;
 pub mod zip;
 
-use std::path::Path;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
 
 use crate::core::compressor::Compressor;
-use crate::core::types::CompressionFormat;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::{CompoundFormat, CompressionFormat};
+use crate::utils::bufio::buffered_writer;
+use crate::utils::{debug, warn, Throttled};
 
+pub(crate) use tools::{decode_error, installed, require_tools, spawn_error, Tool};
+
+pub use ar::ArCompressor;
 pub use bzip2::Bzip2Compressor;
+pub use command::{default_registry_path, CommandCompressor, CommandRegistry};
+pub use cpio::CpioCompressor;
+pub use deb::DebCompressor;
+pub use dedup::DedupCompressor;
 pub use gzip::GzipCompressor;
+pub use rpm::RpmCompressor;
 pub use tar::TarCompressor;
 pub use xz::XzCompressor;
 pub use zip::ZipCompressor;
@@ -23,12 +48,414 @@ pub fn create_compressor(format: CompressionFormat) -> Box<dyn Compressor> {
         CompressionFormat::Xz => Box::new(xz::XzCompressor::new()),
         CompressionFormat::Tar => Box::new(tar::TarCompressor::new()),
         CompressionFormat::Zip => Box::new(zip::ZipCompressor::new()),
+        CompressionFormat::Cpio => Box::new(cpio::CpioCompressor::new()),
+        CompressionFormat::Ar => Box::new(ar::ArCompressor::new()),
+        CompressionFormat::Deb => Box::new(deb::DebCompressor::new()),
+        CompressionFormat::Rpm => Box::new(rpm::RpmCompressor::new()),
+        CompressionFormat::Dedup => Box::new(dedup::DedupCompressor::new()),
     }
 }
 
-/// Detect compression format from file extension
+/// Detect the outermost compression format from the file extension
+///
+/// Compound aliases report their outer layer (`.tgz` is gzip).
 pub fn detect_format(path: &Path) -> Option<CompressionFormat> {
-    path.extension()
+    let ext = path.extension()?.to_str()?;
+    CompressionFormat::from_extension(ext)
+        .or_else(|| CompoundFormat::from_alias(ext).map(|c| c.secondary()))
+}
+
+/// Detect a compound format (`.tar.gz`, `.tgz`, ...) from the full filename
+pub fn detect_compound_format(path: &Path) -> Option<CompoundFormat> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(CompoundFormat::from_filename)
+}
+
+/// Whether the name of `input` ends in one of `suffixes`, spelled exactly
+///
+/// The external tools match only their own suffixes, case-sensitively, so
+/// other spellings (`.GZ`, `.tb2`) are decompressed through a pipe, where
+/// jcz names the output itself.
+pub(crate) fn has_tool_suffix(input: &Path, suffixes: &[&str]) -> bool {
+    let name = input.to_string_lossy();
+    suffixes.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// `name` without the extension `ext` (and its dot), compared ignoring
+/// ASCII case; `None` when it has another one or nothing would be left
+pub(crate) fn strip_extension_ignore_case<'a>(name: &'a str, ext: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(ext.len() + 1)?;
+    if split == 0 || !name.is_char_boundary(split) {
+        return None;
+    }
+    let (stem, suffix) = name.split_at(split);
+    (suffix.starts_with('.') && suffix[1..].eq_ignore_ascii_case(ext)).then_some(stem)
+}
+
+/// Path left once the outermost compression layer is removed
+///
+/// `a.tar.gz` and `a.tgz` both become `a.tar`; `a.gz` becomes `a`.
+pub fn strip_layer_extension(path: &Path) -> PathBuf {
+    let is_alias = path
+        .extension()
         .and_then(|ext| ext.to_str())
-        .and_then(CompressionFormat::from_extension)
+        .and_then(CompoundFormat::from_alias)
+        .is_some();
+
+    if is_alias {
+        path.with_extension("tar")
+    } else {
+        path.with_extension("")
+    }
+}
+
+/// Format of the file at `path` by its leading bytes; `None` when they
+/// match no known format or it cannot be read
+pub fn sniff_format(path: &Path) -> Option<CompressionFormat> {
+    let mut header = Vec::with_capacity(512);
+    File::open(path)
+        .ok()?
+        .take(512)
+        .read_to_end(&mut header)
+        .ok()?;
+    detect_format_from_header(&header)
+}
+
+/// Detect compression format from the leading bytes of a file (magic numbers)
+pub fn detect_format_from_header(header: &[u8]) -> Option<CompressionFormat> {
+    if header.starts_with(&[0x1F, 0x8B]) {
+        Some(CompressionFormat::Gzip)
+    } else if header.starts_with(b"BZh") {
+        Some(CompressionFormat::Bzip2)
+    } else if header.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(CompressionFormat::Xz)
+    } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        Some(CompressionFormat::Zip)
+    } else if header.len() >= 262 && &header[257..262] == b"ustar" {
+        Some(CompressionFormat::Tar)
+    } else if header.starts_with(b"070701") || header.starts_with(b"070702") {
+        Some(CompressionFormat::Cpio)
+    } else if header.starts_with(b"!<arch>\ndebian-binary") {
+        Some(CompressionFormat::Deb)
+    } else if header.starts_with(b"!<arch>\n") {
+        Some(CompressionFormat::Ar)
+    } else if header.starts_with(&[0xED, 0xAB, 0xEE, 0xDB]) {
+        Some(CompressionFormat::Rpm)
+    } else if dedup::is_dedup_header(header) {
+        Some(CompressionFormat::Dedup)
+    } else {
+        None
+    }
+}
+
+/// Distinct first components of archive entry `paths`, in order
+pub(crate) fn top_level_names<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Vec<OsString> {
+    let mut names: Vec<OsString> = Vec::new();
+    for path in paths {
+        let first = path.components().find_map(|c| match c {
+            Component::Normal(name) => Some(name.to_os_string()),
+            _ => None,
+        });
+        if let Some(name) = first {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// External filter tool started by `spawn_filter`
+pub(crate) struct FilterProcess {
+    pub child: Child,
+    feeder: Option<JoinHandle<io::Result<u64>>>,
+}
+
+impl FilterProcess {
+    /// Wait for the tool, and for its input to be fed
+    pub fn wait(self) -> JcResult<Output> {
+        let output = self.child.wait_with_output()?;
+        if let Some(feeder) = self.feeder {
+            match feeder.join() {
+                // The tool stopped reading; its exit status tells why
+                Ok(Err(e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Ok(Ok(_)) => {}
+                Err(_) => return Err(JcError::Other("Input feeder panicked".to_string())),
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Start `cmd` on `input`, with its stdout for the caller to read
+///
+/// Normally the tool opens `input` itself. Under a rate limit, progress
+/// sink or cancellation token, `input` is read by jcz and fed on stdin, so
+/// the tool cannot read faster than the budget allows and stops at EOF
+/// once the job is cancelled.
+pub(crate) fn spawn_filter(
+    cmd: &mut Command,
+    input: &Path,
+    config: &CompressionConfig,
+    tool: &str,
+) -> JcResult<FilterProcess> {
+    let reader = if config.pipes_data() {
+        cmd.stdin(Stdio::piped());
+        let file = config.watched(File::open(input)?, input);
+        Some(Throttled::new(file, config.limit_rate.as_ref()))
+    } else {
+        cmd.arg(input);
+        None
+    };
+    debug!("Executing: {:?}", cmd);
+
+    let mut child = cmd.spawn().map_err(|e| spawn_error(tool, e))?;
+    let feeder = match (reader, child.stdin.take()) {
+        (Some(mut reader), Some(mut stdin)) => {
+            Some(thread::spawn(move || io::copy(&mut reader, &mut stdin)))
+        }
+        _ => None,
+    };
+    Ok(FilterProcess { child, feeder })
+}
+
+/// Which way a filter tool runs, for its error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Compress,
+    Decompress,
+}
+
+/// Run `tool args` as a filter from `input` to `output`
+///
+/// `input` is fed from a scoped thread while the output is copied on this
+/// one, so neither pipe can stall the other.
+pub(crate) fn filter_stream(
+    tool: Tool,
+    args: &[String],
+    input: &mut (dyn Read + Send),
+    output: &mut dyn Write,
+    direction: Direction,
+) -> JcResult<()> {
+    let failed = |stderr: String| match direction {
+        Direction::Compress => JcError::CompressionFailed {
+            tool: tool.program().to_string(),
+            stderr,
+        },
+        Direction::Decompress => JcError::DecompressionFailed {
+            tool: tool.program().to_string(),
+            stderr,
+        },
+    };
+
+    let mut cmd = Command::new(tool.program());
+    cmd.args(args)
+        .args(tool.stdout_arg())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    debug!("Executing: {:?}", cmd);
+    let mut child = cmd.spawn().map_err(|e| spawn_error(tool.program(), e))?;
+
+    let stdin = child.stdin.take();
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let (fed, copied, stderr) = thread::scope(|scope| {
+        let feeder = scope.spawn(move || match stdin {
+            Some(mut stdin) => io::copy(input, &mut stdin).map(drop),
+            None => Ok(()),
+        });
+        let errors = scope.spawn(move || {
+            let mut text = String::new();
+            if let Some(mut stderr) = stderr {
+                let _ = stderr.read_to_string(&mut text);
+            }
+            text
+        });
+
+        let copied = match stdout {
+            Some(mut stdout) => io::copy(&mut stdout, output).and_then(|_| output.flush()),
+            None => Ok(()),
+        };
+        if copied.is_err() {
+            // Unblock the feeder, which may be waiting on a full pipe
+            let _ = child.kill();
+        }
+        (feeder.join(), copied, errors.join().unwrap_or_default())
+    });
+    let status = child.wait()?;
+
+    match fed {
+        // The tool stopped reading; its exit status tells why
+        Ok(Err(e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Ok(Err(e)) => return Err(e.into()),
+        Ok(Ok(())) => {}
+        Err(_) => return Err(JcError::Other("Input feeder panicked".to_string())),
+    }
+    if !status.success() {
+        return Err(failed(stderr));
+    }
+    copied.map_err(|e| failed(e.to_string()))
+}
+
+/// Compress `input` to `output` with `compressor.compress_stream`, under
+/// the rate limit, progress sink and cancellation token of `config`
+///
+/// A partial `output` is removed on failure.
+pub(crate) fn compress_piped(
+    compressor: &dyn Compressor,
+    input: &Path,
+    output: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    let limit = config.limit_rate.as_ref();
+    let mut reader = Throttled::new(config.watched(File::open(input)?, input), limit);
+    let result = File::create(output)
+        .map_err(JcError::from)
+        .and_then(|file| {
+            let mut writer = Throttled::new(buffered_writer(file), limit);
+            compressor.compress_stream(&mut reader, &mut writer, config)
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+/// Threads the built-in decoders may use for one input: `--threads`, or
+/// every core
+pub(crate) fn decode_threads(config: &CompressionConfig) -> usize {
+    config
+        .threads
+        .unwrap_or_else(rayon::current_num_threads)
+        .max(1)
+}
+
+/// Decompress `input` to `output` with `compressor.decompress_seekable`,
+/// both sides passing through jcz
+///
+/// Used instead of the tool's in-place mode when `config.pipes_data()`.
+/// Like that mode, an existing `output` is left alone. With
+/// `config.salvage`, what was decoded before damaged data stops the
+/// decoder is kept rather than removed.
+pub(crate) fn decompress_piped(
+    compressor: &dyn Compressor,
+    input: &Path,
+    output: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    if output.exists() {
+        return Err(JcError::DecompressionFailed {
+            tool: compressor.name().to_string(),
+            stderr: format!("{}: already exists", output.display()),
+        });
+    }
+
+    let limit = config.limit_rate.as_ref();
+    let mut reader = Throttled::new(config.watched(File::open(input)?, input), limit);
+    let result = File::create(output)
+        .map_err(JcError::from)
+        .and_then(|file| {
+            let mut writer = Throttled::new(buffered_writer(file), limit);
+            compressor.decompress_seekable(&mut reader, &mut writer, config)
+        });
+    match result {
+        Err(e) if config.salvage && !e.is_cancellation() => {
+            let kept = fs::metadata(output).map_or(0, |m| m.len());
+            if kept == 0 {
+                let _ = fs::remove_file(output);
+                return Err(e);
+            }
+            warn!(
+                "{}: {}; salvaged the first {} bytes, the rest is lost",
+                input.display(),
+                e,
+                kept
+            );
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_file(output);
+            Err(e)
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format_ignores_case() {
+        let detect = |name: &str| detect_format(Path::new(name));
+        let compound = |name: &str| detect_compound_format(Path::new(name));
+
+        assert_eq!(detect("REPORT.GZ"), Some(CompressionFormat::Gzip));
+        assert_eq!(detect("Backup.Zip"), Some(CompressionFormat::Zip));
+        assert_eq!(detect("site.TBZ"), Some(CompressionFormat::Bzip2));
+        assert_eq!(compound("site.TaR.gZ"), Some(CompoundFormat::Tgz));
+        assert_eq!(compound("site.TGZ"), Some(CompoundFormat::Tgz));
+        assert_eq!(compound("site.tb2"), Some(CompoundFormat::Tbz2));
+        assert_eq!(compound("site.TXZ"), Some(CompoundFormat::Txz));
+        assert_eq!(compound("site.gz"), None);
+
+        assert_eq!(
+            strip_layer_extension(Path::new("site.TB2")),
+            Path::new("site.tar")
+        );
+        assert!(has_tool_suffix(Path::new("site.tgz"), &[".gz", ".tgz"]));
+        assert!(!has_tool_suffix(Path::new("site.TGZ"), &[".gz", ".tgz"]));
+    }
+
+    #[test]
+    fn test_strip_extension_ignore_case() {
+        assert_eq!(strip_extension_ignore_case("a.ZST", "zst"), Some("a"));
+        assert_eq!(
+            strip_extension_ignore_case("a.tar.Zst", "zst"),
+            Some("a.tar")
+        );
+        assert_eq!(strip_extension_ignore_case("azst", "zst"), None);
+        assert_eq!(strip_extension_ignore_case(".zst", "zst"), None);
+        assert_eq!(strip_extension_ignore_case("é.zst", "zst"), Some("é"));
+        assert_eq!(strip_extension_ignore_case("éz", "zst"), None);
+    }
+
+    #[test]
+    fn test_stream_round_trip_in_memory() {
+        let data = b"streamed through memory ".repeat(1000);
+        let config = CompressionConfig::default();
+
+        for format in [
+            CompressionFormat::Gzip,
+            CompressionFormat::Bzip2,
+            CompressionFormat::Xz,
+        ] {
+            let compressor = create_compressor(format);
+            assert!(compressor.supports_streaming());
+
+            let mut compressed = Vec::new();
+            compressor
+                .compress_stream(&mut &data[..], &mut compressed, &config)
+                .unwrap();
+            assert!(compressed.len() < data.len());
+
+            let mut restored = Vec::new();
+            compressor
+                .decompress_stream(&mut &compressed[..], &mut restored, &config)
+                .unwrap();
+            assert_eq!(restored, data, "{} round trip", compressor.name());
+
+            let result = compressor.decompress_stream(&mut &data[..], &mut Vec::new(), &config);
+            assert!(matches!(result, Err(JcError::DecompressionFailed { .. })));
+        }
+
+        let tar = create_compressor(CompressionFormat::Tar);
+        assert!(!tar.supports_streaming());
+        assert!(tar
+            .compress_stream(&mut &data[..], &mut Vec::new(), &config)
+            .is_err());
+    }
 }
@@ -0,0 +1,236 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::compressors::detect_format;
+use crate::core::compressor::Compressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::utils::{debug, info, move_file_if_needed};
+
+/// Magic at the start of the RPM lead
+const LEAD_MAGIC: [u8; 4] = [0xED, 0xAB, 0xEE, 0xDB];
+
+/// Size of the (obsolete, fixed-size) RPM lead
+const LEAD_LEN: usize = 96;
+
+/// Magic at the start of the signature and main headers
+const HEADER_MAGIC: [u8; 3] = [0x8E, 0xAD, 0xE8];
+
+/// RPM package handler (decompress only)
+///
+/// An RPM is a lead, a signature header, a main header and a compressed
+/// cpio payload. The headers are parsed natively to locate the payload,
+/// which is then unpacked by the regular layer loop.
+#[derive(Debug, Clone, Default)]
+pub struct RpmCompressor;
+
+impl RpmCompressor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Write the package payload to `dest_dir` as `<name>.cpio[.gz|.bz2|.xz]`
+    pub fn extract_payload(&self, input: &Path, dest_dir: &Path) -> JcResult<PathBuf> {
+        if detect_format(input) != Some(CompressionFormat::Rpm) {
+            return Err(JcError::InvalidExtension(
+                input.to_path_buf(),
+                "rpm".to_string(),
+            ));
+        }
+
+        let mut reader = BufReader::new(File::open(input)?);
+        skip_headers(&mut reader).map_err(|e| JcError::DecompressionFailed {
+            tool: "rpm".to_string(),
+            stderr: format!("{}: {}", input.display(), e),
+        })?;
+
+        // Sniff the payload compression from its first bytes
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        let extension = payload_extension(&magic)?;
+
+        let stem = input
+            .file_stem()
+            .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?
+            .to_string_lossy()
+            .to_string();
+        let payload_path = dest_dir.join(format!("{}.{}", stem, extension));
+
+        debug!(
+            "Writing {} payload of {} to {}",
+            extension,
+            input.display(),
+            payload_path.display()
+        );
+
+        let mut writer = BufWriter::new(File::create(&payload_path)?);
+        writer.write_all(&magic)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+
+        Ok(payload_path)
+    }
+}
+
+impl Compressor for RpmCompressor {
+    fn name(&self) -> &'static str {
+        "rpm"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rpm"
+    }
+
+    fn compress(&self, _input: &Path, _config: &CompressionConfig) -> JcResult<PathBuf> {
+        Err(JcError::Other(
+            "rpm packages can only be extracted, not created".to_string(),
+        ))
+    }
+
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let parent = input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        let output_path = self.extract_payload(input, parent)?;
+        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+
+        info!("Extracted RPM payload: {}", final_path.display());
+        Ok(final_path)
+    }
+
+    fn supports_levels(&self) -> bool {
+        false
+    }
+
+    fn validate_level(&self, _level: u8) -> bool {
+        true // Always valid (no-op)
+    }
+
+    fn default_level(&self) -> u8 {
+        0
+    }
+}
+
+/// Advance past the lead, signature header and main header
+fn skip_headers<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut lead = [0u8; LEAD_LEN];
+    reader.read_exact(&mut lead)?;
+    if lead[..4] != LEAD_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an RPM package (bad lead magic)",
+        ));
+    }
+
+    // The signature header is padded to an 8-byte boundary; the main header is not
+    let signature_len = skip_header(reader)?;
+    skip_bytes(reader, (8 - signature_len % 8) % 8)?;
+    skip_header(reader)?;
+    Ok(())
+}
+
+/// Skip one header structure, returning its total length
+fn skip_header<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut intro = [0u8; 16];
+    reader.read_exact(&mut intro)?;
+    if intro[..3] != HEADER_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "corrupt RPM header (bad magic)",
+        ));
+    }
+
+    let index_count = u32::from_be_bytes([intro[8], intro[9], intro[10], intro[11]]) as u64;
+    let data_len = u32::from_be_bytes([intro[12], intro[13], intro[14], intro[15]]) as u64;
+    let body_len = index_count * 16 + data_len;
+
+    skip_bytes(reader, body_len)?;
+    Ok(16 + body_len)
+}
+
+fn skip_bytes<R: Read>(reader: &mut R, count: u64) -> io::Result<()> {
+    let copied = io::copy(&mut reader.take(count), &mut io::sink())?;
+    if copied != count {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated RPM header",
+        ));
+    }
+    Ok(())
+}
+
+/// Map payload magic bytes to the extension used for the layer loop
+fn payload_extension(magic: &[u8]) -> JcResult<&'static str> {
+    if magic.starts_with(&[0x1F, 0x8B]) {
+        Ok("cpio.gz")
+    } else if magic.starts_with(b"BZh") {
+        Ok("cpio.bz2")
+    } else if magic.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        Ok("cpio.xz")
+    } else if magic.starts_with(b"070701") || magic.starts_with(b"070702") {
+        Ok("cpio")
+    } else if magic.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        Err(JcError::Other(
+            "RPM payload is zstd-compressed, which is not supported".to_string(),
+        ))
+    } else {
+        Err(JcError::Other(
+            "Unrecognized RPM payload compression".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn header(index_count: u32, data_len: u32) -> Vec<u8> {
+        let mut bytes = vec![0x8E, 0xAD, 0xE8, 0x01, 0, 0, 0, 0];
+        bytes.extend_from_slice(&index_count.to_be_bytes());
+        bytes.extend_from_slice(&data_len.to_be_bytes());
+        bytes.extend(std::iter::repeat_n(
+            0u8,
+            (index_count * 16 + data_len) as usize,
+        ));
+        bytes
+    }
+
+    #[test]
+    fn test_skip_headers_with_signature_padding() {
+        let mut package = vec![0u8; LEAD_LEN];
+        package[..4].copy_from_slice(&LEAD_MAGIC);
+        // 16 + 16 + 5 = 37 bytes, padded to 40
+        package.extend(header(1, 5));
+        package.extend([0u8; 3]);
+        package.extend(header(2, 7));
+        package.extend(b"070701payload");
+
+        let mut reader = Cursor::new(package);
+        skip_headers(&mut reader).unwrap();
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"070701payload");
+    }
+
+    #[test]
+    fn test_skip_headers_rejects_non_rpm() {
+        let mut reader = Cursor::new(vec![0u8; 200]);
+        assert!(skip_headers(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_payload_extension() {
+        assert_eq!(payload_extension(&[0x1F, 0x8B, 8, 0]).unwrap(), "cpio.gz");
+        assert_eq!(
+            payload_extension(&[0xFD, b'7', b'z', b'X', b'Z', 0]).unwrap(),
+            "cpio.xz"
+        );
+        assert!(payload_extension(&[0x28, 0xB5, 0x2F, 0xFD]).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Snapshot files for incremental tar archives (`--listed-incremental`)
+//!
+//! A snapshot records the size and mtime of every entry archived, under
+//! its name in the archive. The next run against the same snapshot stores
+//! only the files that are new or changed, plus every directory so the
+//! tree can be recreated, and lists the names that have disappeared since
+//! in a PAX global header. Extracting with `-d --incremental` deletes
+//! those names, so applying a full archive and then each incremental in
+//! order reproduces the tree as it was at the last run.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::error::{JcError, JcResult};
+
+/// PAX global header record listing the names deleted since the previous
+/// archive, separated by NUL bytes
+pub const DELETED_RECORD: &str = "JCZ.deleted";
+
+/// Format version written to new snapshot files
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// State of one archived entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct EntryState {
+    size: u64,
+    mtime: u64,
+    mtime_nsec: u32,
+}
+
+impl EntryState {
+    fn of(metadata: &fs::Metadata) -> Self {
+        let since_epoch = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            size: metadata.len(),
+            mtime: since_epoch.as_secs(),
+            mtime_nsec: since_epoch.subsec_nanos(),
+        }
+    }
+}
+
+/// Entries seen by one archive run, by archive name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    entries: BTreeMap<String, EntryState>,
+}
+
+impl Snapshot {
+    /// Read the snapshot at `path`; a missing file is an empty snapshot,
+    /// so the first run archives everything
+    pub fn load(path: &Path) -> JcResult<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let snapshot: Self = serde_json::from_str(&text).map_err(|e| {
+            JcError::Other(format!("Invalid snapshot file {}: {}", path.display(), e))
+        })?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(JcError::Other(format!(
+                "Snapshot file {} is version {}, newer than this jcz supports",
+                path.display(),
+                snapshot.version
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    /// Replace the snapshot at `path`, through a hidden sibling so an
+    /// interrupted run never leaves it half-written
+    pub fn save(&self, path: &Path) -> JcResult<()> {
+        let snapshot = Self {
+            version: SNAPSHOT_VERSION,
+            entries: self.entries.clone(),
+        };
+        let json = serde_json::to_string_pretty(&snapshot)
+            .map_err(|e| JcError::Other(format!("Failed to serialize snapshot: {}", e)))?;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{}.tmp", name));
+        fs::write(&temp, json)?;
+        fs::rename(&temp, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })?;
+        Ok(())
+    }
+
+    /// Whether `name` was recorded with the same size and mtime
+    pub fn unchanged(&self, name: &Path, metadata: &fs::Metadata) -> bool {
+        self.entries
+            .get(name.to_string_lossy().as_ref())
+            .is_some_and(|state| *state == EntryState::of(metadata))
+    }
+
+    /// Record `name` as archived with `metadata`
+    pub fn record(&mut self, name: &Path, metadata: &fs::Metadata) {
+        self.entries.insert(
+            name.to_string_lossy().into_owned(),
+            EntryState::of(metadata),
+        );
+    }
+
+    /// Names in `previous` that this snapshot no longer has
+    pub fn deleted_since(&self, previous: &Snapshot) -> Vec<String> {
+        previous
+            .entries
+            .keys()
+            .filter(|name| !self.entries.contains_key(*name))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_tracks_changes_and_deletions() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.txt");
+        fs::write(&file, b"one").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+
+        let mut previous = Snapshot::default();
+        previous.record(Path::new("dir/a.txt"), &metadata);
+        previous.record(Path::new("dir/gone.txt"), &metadata);
+        assert!(previous.unchanged(Path::new("dir/a.txt"), &metadata));
+        assert!(!previous.unchanged(Path::new("dir/new.txt"), &metadata));
+
+        fs::write(&file, b"longer").unwrap();
+        let changed = fs::metadata(&file).unwrap();
+        assert!(!previous.unchanged(Path::new("dir/a.txt"), &changed));
+
+        let mut current = Snapshot::default();
+        current.record(Path::new("dir/a.txt"), &changed);
+        assert_eq!(current.deleted_since(&previous), vec!["dir/gone.txt"]);
+    }
+
+    #[test]
+    fn test_snapshot_save_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.snar");
+        assert_eq!(Snapshot::load(&path).unwrap(), Snapshot::default());
+
+        let metadata = fs::metadata(temp_dir.path()).unwrap();
+        let mut snapshot = Snapshot::default();
+        snapshot.record(Path::new("dir"), &metadata);
+        snapshot.save(&path).unwrap();
+
+        let loaded = Snapshot::load(&path).unwrap();
+        assert!(loaded.unchanged(Path::new("dir"), &metadata));
+        assert_eq!(loaded.version, SNAPSHOT_VERSION);
+
+        fs::write(&path, b"not json").unwrap();
+        assert!(Snapshot::load(&path).is_err());
+    }
+}
@@ -1,19 +1,794 @@
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 
+use crate::compressors::snapshot::{Snapshot, DELETED_RECORD};
+use crate::compressors::{detect_format, top_level_names};
 use crate::core::compressor::{Compressor, MultiFileCompressor};
 use crate::core::config::CompressionConfig;
 use crate::core::error::{JcError, JcResult};
-use crate::utils::{copy_to_dir, debug, generate_output_filename, info, move_file_if_needed};
+use crate::core::types::{ArchiveEntry, CompressionFormat, EntryKind};
+use crate::utils::bufio::{buffered_reader, buffered_writer};
+use crate::utils::xattrs::{self, XattrSelection};
+use crate::utils::{
+    copy_to_dir, debug, find_extracted_output, generate_output_filename, info, is_cancelled_io,
+    is_inside_without_symlinks, move_file_if_needed, path_from_bytes, place_output, warn,
+    Throttled,
+};
 
-/// TAR archiver implementation
-#[derive(Debug, Clone)]
+/// TAR archiver implementation (native, via the `tar` crate)
+#[derive(Debug, Clone, Default)]
 pub struct TarCompressor;
 
 impl TarCompressor {
     pub fn new() -> Self {
         Self
     }
+
+    /// Write `output` containing each `(path, name)` pair, recursing into
+    /// directories in sorted order so archives are reproducible
+    ///
+    /// Selected xattrs/ACLs are stored in a PAX header before each entry.
+    /// Symlinks are stored as links unless `config.dereference` is set.
+    /// With `config.listed_incremental`, unchanged files are left out and
+    /// the snapshot is updated once the archive is written.
+    pub(crate) fn create_archive(
+        &self,
+        output: &Path,
+        entries: &[(PathBuf, OsString)],
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let incremental = match config.listed_incremental {
+            Some(ref path) => Some(IncrementalWalk {
+                previous: Snapshot::load(path)?,
+                current: Snapshot::default(),
+            }),
+            None => None,
+        };
+
+        let result = File::create(output).and_then(|file| {
+            let file = config.watched(file, output);
+            let writer = Throttled::new(buffered_writer(file), config.limit_rate.as_ref());
+            write_archive(writer, entries, config, incremental)
+        });
+
+        let incremental = result.map_err(|e| {
+            let _ = fs::remove_file(output);
+            if is_cancelled_io(&e) {
+                return JcError::Aborted;
+            }
+            JcError::CompressionFailed {
+                tool: "tar".to_string(),
+                stderr: e.to_string(),
+            }
+        })?;
+
+        if let (Some(path), Some(incremental)) = (&config.listed_incremental, incremental) {
+            incremental.current.save(path)?;
+            debug!("Updated snapshot {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Write the archive `create_archive` would make into `writer`, without
+    /// incremental snapshots
+    pub(crate) fn stream_archive<W: Write>(
+        &self,
+        writer: W,
+        entries: &[(PathBuf, OsString)],
+        config: &CompressionConfig,
+    ) -> io::Result<()> {
+        write_archive(writer, entries, config, None).map(|_| ())
+    }
+
+    /// Unpack `archive` into `dest`, keeping permissions and mtimes
+    ///
+    /// Entries that would escape `dest` (absolute paths, `..`) are refused
+    /// by the `tar` crate.
+    fn extract_archive(
+        &self,
+        archive: &Path,
+        dest: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let selection = XattrSelection::from_config(config);
+        let to_error = |e: io::Error| {
+            if is_cancelled_io(&e) {
+                return JcError::Aborted;
+            }
+            JcError::DecompressionFailed {
+                tool: "tar".to_string(),
+                stderr: e.to_string(),
+            }
+        };
+
+        if config.salvage {
+            let losses = unpack_salvaging(archive, dest, config).map_err(to_error)?;
+            let name = archive.file_name().unwrap_or_default().to_string_lossy();
+            for loss in &losses {
+                warn!("{}: {}", name, loss);
+            }
+            if !losses.is_empty() {
+                warn!(
+                    "Salvaged {}: {} damaged regions or members skipped",
+                    name,
+                    losses.len()
+                );
+            }
+        } else {
+            let file = config.watched(File::open(archive)?, archive);
+            let reader = Throttled::new(file, config.limit_rate.as_ref());
+            let mut unpacker = tar::Archive::new(buffered_reader(reader));
+            set_unpack_options(&mut unpacker);
+            unpack_reporting(&mut unpacker, dest, config).map_err(to_error)?;
+        }
+
+        // Attributes go on last, once contents, modes and owners are final;
+        // writing a file clears security.capability.
+        if !selection.is_empty() {
+            match restore_attributes(archive, dest, selection) {
+                Err(e) if config.salvage => warn!("Some attributes not restored: {}", e),
+                result => result.map_err(to_error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Total size of the entries of the tar at `path`, from their headers
+/// (with the PAX `size` of members over 8 GiB); `None` when the archive
+/// cannot be read
+pub fn uncompressed_size(path: &Path) -> JcResult<Option<u64>> {
+    let mut archive = tar::Archive::new(File::open(path)?);
+    let Ok(entries) = archive.entries_with_seek() else {
+        return Ok(None);
+    };
+    let mut total = 0u64;
+    for entry in entries {
+        let Ok(entry) = entry else {
+            return Ok(None);
+        };
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        total = total.saturating_add(entry.size());
+    }
+    Ok(Some(total))
+}
+
+/// `tar::Archive::unpack`, telling `config`'s entry listener about each
+/// entry as it is reached
+fn unpack_reporting<R: Read>(
+    unpacker: &mut tar::Archive<R>,
+    dest: &Path,
+    config: &CompressionConfig,
+) -> io::Result<()> {
+    if dest.symlink_metadata().is_err() {
+        fs::create_dir_all(dest)?;
+    }
+    let dest = &dest.canonicalize().unwrap_or(dest.to_path_buf());
+
+    // Directories go last, deepest first, so their permissions cannot get
+    // in the way of their contents
+    let mut directories = Vec::new();
+    for entry in unpacker.entries()? {
+        let mut entry = entry?;
+        if let Some(listed) = archive_entry(&entry)? {
+            config.report_entry(&listed);
+        }
+        if entry.header().entry_type() == tar::EntryType::Directory {
+            directories.push(entry);
+        } else {
+            entry.unpack_in(dest)?;
+        }
+    }
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut directory in directories {
+        unpack_dir_in(&mut directory, dest)?;
+    }
+    Ok(())
+}
+
+/// Unpack the directory `entry` in `dest` with its mtime, which
+/// `tar::Entry::unpack_in` only sets for files
+fn unpack_dir_in<R: Read>(entry: &mut tar::Entry<'_, R>, dest: &Path) -> io::Result<()> {
+    if !entry.unpack_in(dest)? {
+        return Ok(());
+    }
+    if let Ok(mtime) = entry.header().mtime() {
+        // Best effort, as some platforms cannot open directories
+        let modified = UNIX_EPOCH + Duration::from_secs(mtime);
+        let _ = File::open(dest.join(entry.path()?)).and_then(|dir| dir.set_modified(modified));
+    }
+    Ok(())
+}
+
+/// Keep permissions and mtimes, replacing what is in the way
+fn set_unpack_options<R: Read>(unpacker: &mut tar::Archive<R>) {
+    unpacker.set_preserve_permissions(true);
+    unpacker.set_preserve_mtime(true);
+    unpacker.set_overwrite(true);
+}
+
+/// A reader of the tar at `path` whose archive starts at byte `offset`
+fn unpacker_at(path: &Path, offset: u64) -> io::Result<tar::Archive<BufReader<File>>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut unpacker = tar::Archive::new(buffered_reader(file));
+    set_unpack_options(&mut unpacker);
+    Ok(unpacker)
+}
+
+/// `unpack_reporting` for a damaged tar (`--salvage`), returning what was
+/// lost
+///
+/// An entry that fails to unpack is skipped; when the headers themselves
+/// stop making sense, reading resumes at the next intact header, found by
+/// its `ustar` magic and checksum at any byte offset, since a damaged
+/// compressed layer loses an arbitrary number of bytes.
+fn unpack_salvaging(
+    archive: &Path,
+    dest: &Path,
+    config: &CompressionConfig,
+) -> io::Result<Vec<String>> {
+    if dest.symlink_metadata().is_err() {
+        fs::create_dir_all(dest)?;
+    }
+    let dest = &dest.canonicalize().unwrap_or(dest.to_path_buf());
+    let len = fs::metadata(archive)?.len();
+
+    let mut losses = Vec::new();
+    // Directories by path and where their headers start, unpacked last
+    let mut directories = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = next_header(archive, offset, len)? {
+        if start > offset {
+            losses.push(format!("skipped damaged bytes {}-{}", offset, start));
+        }
+        let mut unpacker = unpacker_at(archive, start)?;
+        // Where the headers of the next entry start
+        let mut end = start;
+        let mut intact = true;
+        for entry in unpacker.entries()? {
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => {
+                    losses.push(format!("damaged entry header at byte {}", end));
+                    intact = false;
+                    break;
+                }
+            };
+            let header_start = end;
+            // The PAX size where the header's field is too small
+            let size = entry.size();
+            end = start + entry.raw_file_position() + size.div_ceil(512) * 512;
+
+            let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            match archive_entry(&entry) {
+                Ok(Some(listed)) => config.report_entry(&listed),
+                Ok(None) => continue,
+                Err(e) => {
+                    losses.push(format!("{}: {}", path, e));
+                    continue;
+                }
+            }
+            if entry.header().entry_type() == tar::EntryType::Directory {
+                directories.push((path, header_start));
+            } else if let Err(e) = entry.unpack_in(dest) {
+                losses.push(format!("{}: {}", path, e));
+            }
+        }
+        offset = if intact { end } else { end.max(start + 1) };
+    }
+
+    directories.sort_by(|a, b| b.0.cmp(&a.0));
+    for (path, header_start) in directories {
+        let unpacked = unpacker_at(archive, header_start).and_then(|mut unpacker| {
+            match unpacker.entries()?.next() {
+                Some(entry) => unpack_dir_in(&mut entry?, dest),
+                None => Ok(()),
+            }
+        });
+        if let Err(e) = unpacked {
+            losses.push(format!("{}: {}", path, e));
+        }
+    }
+    Ok(losses)
+}
+
+/// Offset of the first intact ustar header at or after `from`
+fn next_header(archive: &Path, from: u64, len: u64) -> io::Result<Option<u64>> {
+    const MAGIC_AT: u64 = 257;
+    const CHUNK: usize = 1 << 20;
+    let mut file = File::open(archive)?;
+    let mut chunk = vec![0u8; CHUNK];
+    let mut block = [0u8; 512];
+    let mut pos = from + MAGIC_AT;
+    while pos + 512 - MAGIC_AT <= len {
+        file.seek(SeekFrom::Start(pos))?;
+        let n = (len - pos).min(CHUNK as u64) as usize;
+        file.read_exact(&mut chunk[..n])?;
+        let magics = chunk[..n].windows(5).enumerate();
+        for (found, _) in magics.filter(|(_, w)| w == b"ustar") {
+            let candidate = pos + found as u64 - MAGIC_AT;
+            if candidate + 512 > len {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Start(candidate))?;
+            file.read_exact(&mut block)?;
+            if has_valid_checksum(&block) {
+                return Ok(Some(candidate));
+            }
+        }
+        // Overlap so a magic across the boundary is still seen
+        pos += n.saturating_sub(4).max(1) as u64;
+    }
+    Ok(None)
+}
+
+/// Whether the checksum field of a tar header block matches its bytes
+fn has_valid_checksum(block: &[u8; 512]) -> bool {
+    let Ok(stored) = tar::Header::from_byte_slice(block).cksum() else {
+        return false;
+    };
+    let sum: u32 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                b as u32
+            }
+        })
+        .sum();
+    stored == sum
+}
+
+/// `entry` as listed, or `None` for an archive-wide record such as an
+/// incremental's deletions
+fn archive_entry<R: Read>(entry: &tar::Entry<'_, R>) -> io::Result<Option<ArchiveEntry>> {
+    let kind = match entry.header().entry_type() {
+        t if t.is_pax_global_extensions() => return Ok(None),
+        t if t.is_file() => EntryKind::File,
+        t if t.is_dir() => EntryKind::Directory,
+        t if t.is_symlink() || t.is_hard_link() => EntryKind::Symlink,
+        _ => EntryKind::Other,
+    };
+    Ok(Some(ArchiveEntry {
+        path: entry.path()?.into_owned(),
+        size: if kind == EntryKind::File {
+            entry.size()
+        } else {
+            0
+        },
+        kind,
+    }))
+}
+
+/// Members of the tar read from `reader`, in archive order
+///
+/// Only headers are parsed; member data is skipped over, so `reader` can
+/// be a decompression stream.
+pub fn list_entries(reader: impl Read) -> JcResult<Vec<ArchiveEntry>> {
+    let to_error = |e: io::Error| JcError::DecompressionFailed {
+        tool: "tar".to_string(),
+        stderr: e.to_string(),
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    for entry in archive.entries().map_err(to_error)? {
+        let entry = entry.map_err(to_error)?;
+        if let Some(listed) = archive_entry(&entry).map_err(to_error)? {
+            entries.push(listed);
+        }
+    }
+    Ok(entries)
+}
+
+/// Top-level names the tar at `path` extracts to, in archive order
+pub fn top_level_entries(path: &Path) -> JcResult<Vec<OsString>> {
+    let to_error = |e: io::Error| JcError::DecompressionFailed {
+        tool: "tar".to_string(),
+        stderr: format!("{}: {}", path.display(), e),
+    };
+    let mut archive = tar::Archive::new(File::open(path)?);
+    let mut paths = Vec::new();
+    for entry in archive.entries_with_seek().map_err(to_error)? {
+        let entry = entry.map_err(to_error)?;
+        // Archive-wide records, such as an incremental's deletions
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        paths.push(entry.path().map_err(to_error)?.into_owned());
+    }
+    Ok(top_level_names(paths.iter().map(PathBuf::as_path)))
+}
+
+/// Apply the xattr/ACL PAX records of every entry in `archive` under `dest`
+///
+/// Walks the raw headers because GNU tar writes ACL text containing
+/// newlines, which the `tar` crate's own PAX parser rejects.
+fn restore_attributes(archive: &Path, dest: &Path, selection: XattrSelection) -> io::Result<()> {
+    let mut reader = tar::Archive::new(buffered_reader(File::open(archive)?));
+    let mut pending_pax: Option<Vec<u8>> = None;
+    let mut pending_name: Option<Vec<u8>> = None;
+
+    for entry in reader.entries()?.raw(true) {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+
+        if kind.is_pax_local_extensions() || kind.is_gnu_longname() {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            if kind.is_gnu_longname() {
+                pending_name = Some(data.split(|b| *b == 0).next().unwrap_or(&[]).to_vec());
+            } else {
+                pending_pax = Some(data);
+            }
+            continue;
+        }
+        if kind.is_gnu_longlink() || kind.is_pax_global_extensions() {
+            continue;
+        }
+
+        let records = pending_pax
+            .take()
+            .map(|data| xattrs::parse_pax_records(&data))
+            .unwrap_or_default();
+        let long_name = pending_name.take();
+        if records.is_empty() {
+            continue;
+        }
+
+        let name = records
+            .iter()
+            .find(|(key, _)| key == "path")
+            .map(|(_, value)| value.clone())
+            .or(long_name)
+            .unwrap_or_else(|| entry.header().path_bytes().into_owned());
+        let path = PathBuf::from(String::from_utf8_lossy(&name).into_owned());
+
+        // Same sanitizing as unpack: skip entries that would escape `dest`
+        if path.components().any(|c| c == Component::ParentDir) {
+            continue;
+        }
+        let relative: PathBuf = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        let target = dest.join(&relative);
+
+        // Attributes are set through symlinks, which could point anywhere
+        if !is_inside_without_symlinks(dest, &relative)
+            || fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink())
+        {
+            debug!(
+                "Not restoring attributes through a symlink: {}",
+                path.display()
+            );
+            continue;
+        }
+
+        xattrs::apply_pax_records(
+            &target,
+            records.iter().map(|(k, v)| (k.as_str(), v.as_slice())),
+            selection,
+        );
+    }
+    Ok(())
+}
+
+/// Append a PAX extended header whose records apply to the next entry
+fn append_pax_header<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &Path,
+    records: &[(String, Vec<u8>)],
+) -> io::Result<()> {
+    let data = pax_data(records);
+
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XHeader);
+    let basename = name.file_name().unwrap_or(name.as_os_str());
+    if header
+        .set_path(Path::new("PaxHeaders").join(basename))
+        .is_err()
+    {
+        header.set_path("PaxHeaders/entry")?;
+    }
+    header.set_mode(0o644);
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    builder.append(&header, data.as_slice())
+}
+
+/// Append a PAX global header listing the names an incremental archive
+/// records as deleted since the previous one
+fn append_deletions<W: Write>(builder: &mut tar::Builder<W>, deleted: &[String]) -> io::Result<()> {
+    // Names never contain NUL, unlike newlines
+    let records = [(DELETED_RECORD.to_string(), deleted.join("\0").into_bytes())];
+    let data = pax_data(&records);
+
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XGlobalHeader);
+    header.set_path("pax_global_header")?;
+    header.set_mode(0o644);
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    builder.append(&header, data.as_slice())
+}
+
+/// Names the incremental archive at `path` records as deleted
+fn deleted_names(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut reader = tar::Archive::new(buffered_reader(File::open(path)?));
+    let mut names = Vec::new();
+    for entry in reader.entries()?.raw(true) {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        for (key, value) in xattrs::parse_pax_records(&data) {
+            if key == DELETED_RECORD {
+                names.extend(
+                    value
+                        .split(|b| *b == 0)
+                        .filter(|name| !name.is_empty())
+                        .map(path_from_bytes),
+                );
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Remove `name` under `dest`, as recorded deleted by an incremental
+///
+/// Same sanitizing as unpack: names that would escape `dest`, directly or
+/// through a symlinked parent, are skipped.
+fn remove_deleted(dest: &Path, name: &Path) -> JcResult<()> {
+    if !name.components().all(|c| matches!(c, Component::Normal(_)))
+        || !is_inside_without_symlinks(dest, name)
+    {
+        warn!(
+            "Skipping deletion of {}: outside the destination",
+            name.display()
+        );
+        return Ok(());
+    }
+
+    let target = dest.join(name);
+    let result = match fs::symlink_metadata(&target) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&target),
+        Ok(_) => fs::remove_file(&target),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => Err(e),
+    };
+    result?;
+    debug!("Deleted {}", target.display());
+    Ok(())
+}
+
+/// Encode `records` as the body of a PAX extended header
+fn pax_data(records: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (key, value) in records {
+        // Each record is "<len> <key>=<value>\n" where <len> counts itself
+        let rest = key.len() + value.len() + 3;
+        let mut len = rest + 1;
+        while len.to_string().len() + rest != len {
+            len = len.to_string().len() + rest;
+        }
+        data.extend_from_slice(format!("{} {}=", len, key).as_bytes());
+        data.extend_from_slice(value);
+        data.push(b'\n');
+    }
+    data
+}
+
+/// State carried through one archive's directory walk
+struct TreeWalk<'a> {
+    selection: XattrSelection,
+    dereference: bool,
+    /// Epoch to clamp mtimes to when writing a reproducible archive
+    reproducible: Option<u64>,
+    /// Canonical directories being walked, to catch symlink loops
+    ancestors: Vec<PathBuf>,
+    /// Snapshots of an incremental archive (`--listed-incremental`)
+    incremental: Option<IncrementalWalk>,
+    /// Told about each entry appended
+    config: &'a CompressionConfig,
+}
+
+/// The snapshot an incremental archive is compared against, and the one
+/// its walk records
+/// Archive `entries` into `writer`, returning the walk's snapshots
+fn write_archive<W: Write>(
+    writer: W,
+    entries: &[(PathBuf, OsString)],
+    config: &CompressionConfig,
+    incremental: Option<IncrementalWalk>,
+) -> io::Result<Option<IncrementalWalk>> {
+    let mut builder = tar::Builder::new(writer);
+    // Store symlinks as links by default, like GNU tar does
+    builder.follow_symlinks(config.dereference);
+    if config.reproducible.is_some() {
+        builder.mode(tar::HeaderMode::Deterministic);
+    }
+
+    let mut walk = TreeWalk {
+        selection: XattrSelection::from_config(config),
+        dereference: config.dereference,
+        reproducible: config.reproducible,
+        ancestors: Vec::new(),
+        incremental,
+        config,
+    };
+    // Reproducible archives must not depend on argument order
+    let mut entries: Vec<_> = entries.iter().collect();
+    if config.reproducible.is_some() {
+        entries.sort_by(|a, b| a.1.cmp(&b.1));
+    }
+    for (path, name) in entries {
+        append_tree(&mut builder, path, Path::new(name), &mut walk)?;
+    }
+
+    // Last, so the first entry is still the first thing in the file
+    if let Some(ref incremental) = walk.incremental {
+        let deleted = incremental.current.deleted_since(&incremental.previous);
+        if !deleted.is_empty() {
+            append_deletions(&mut builder, &deleted)?;
+        }
+    }
+
+    builder.into_inner()?.flush()?;
+    Ok(walk.incremental)
+}
+
+struct IncrementalWalk {
+    previous: Snapshot,
+    current: Snapshot,
+}
+
+/// Append a file, symlink or directory tree under the given archive name
+fn append_tree<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    name: &Path,
+    walk: &mut TreeWalk<'_>,
+) -> io::Result<()> {
+    let link_metadata = fs::symlink_metadata(path)?;
+    let followed = walk.dereference && link_metadata.file_type().is_symlink();
+    let metadata = if followed {
+        match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                // Nothing to follow: keep the dangling link as a link
+                warn!(
+                    "{} is a dangling symlink, storing it as a link",
+                    path.display()
+                );
+                if let Some(epoch) = walk.reproducible {
+                    return append_normalized(builder, path, name, &link_metadata, epoch);
+                }
+                builder.follow_symlinks(false);
+                let result = builder.append_path_with_name(path, name);
+                builder.follow_symlinks(true);
+                return result;
+            }
+        }
+    } else {
+        link_metadata
+    };
+
+    if walk.config.filter.excludes(&metadata) {
+        debug!("Outside the size range: {}", path.display());
+        return Ok(());
+    }
+
+    let canonical = if metadata.is_dir() {
+        let canonical = path.canonicalize()?;
+        if walk.ancestors.contains(&canonical) {
+            warn!("Skipping {}: symlink loop", path.display());
+            return Ok(());
+        }
+        Some(canonical)
+    } else {
+        None
+    };
+
+    // Directories always go in, so an incremental can recreate the tree
+    if let Some(ref mut incremental) = walk.incremental {
+        incremental.current.record(name, &metadata);
+        if canonical.is_none() && incremental.previous.unchanged(name, &metadata) {
+            debug!("Unchanged since the snapshot: {}", path.display());
+            return Ok(());
+        }
+    }
+
+    // A followed link carries its target's attributes, like its contents
+    let records = if followed {
+        xattrs::pax_records(&path.canonicalize()?, walk.selection)
+    } else {
+        xattrs::pax_records(path, walk.selection)
+    };
+    if !records.is_empty() {
+        append_pax_header(builder, name, &records)?;
+    }
+    match walk.reproducible {
+        Some(epoch) => append_normalized(builder, path, name, &metadata, epoch)?,
+        None => builder.append_path_with_name(path, name)?,
+    }
+    let file_type = metadata.file_type();
+    walk.config.report_entry(&ArchiveEntry {
+        path: name.to_path_buf(),
+        size: if file_type.is_file() {
+            metadata.len()
+        } else {
+            0
+        },
+        kind: if file_type.is_file() {
+            EntryKind::File
+        } else if file_type.is_dir() {
+            EntryKind::Directory
+        } else if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::Other
+        },
+    });
+
+    if let Some(canonical) = canonical {
+        let mut children: Vec<OsString> = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<Result<_, _>>()?;
+        children.sort();
+
+        walk.ancestors.push(canonical);
+        for child in children {
+            append_tree(builder, &path.join(&child), &name.join(&child), walk)?;
+        }
+        walk.ancestors.pop();
+    }
+
+    Ok(())
+}
+
+/// Append one entry with owner 0:0, normalized mode and its mtime clamped
+/// to `epoch`
+fn append_normalized<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    name: &Path,
+    metadata: &fs::Metadata,
+    epoch: u64,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata_in_mode(metadata, tar::HeaderMode::Deterministic);
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    header.set_mtime(mtime.min(epoch));
+
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        header.set_size(0);
+        builder.append_link(&mut header, name, fs::read_link(path)?)
+    } else if file_type.is_dir() {
+        header.set_size(0);
+        builder.append_data(&mut header, name, io::empty())
+    } else if file_type.is_file() {
+        builder.append_data(&mut header, name, File::open(path)?)
+    } else {
+        // Devices and FIFOs carry no timestamps worth clamping
+        builder.append_path_with_name(path, name)
+    }
 }
 
 impl Compressor for TarCompressor {
@@ -30,50 +805,33 @@ impl Compressor for TarCompressor {
             return Err(JcError::FileNotFound(input.to_path_buf()));
         }
 
-        let output_path = generate_output_filename(input, "tar", config.timestamp)?;
+        let output_path = generate_output_filename(input, "tar", config)?;
         info!(
             "Creating TAR archive {} from {}",
             output_path.display(),
             input.display()
         );
 
-        // Build tar command - if input has no parent, use current directory
-        let mut cmd = Command::new("tar");
-
-        if let Some(parent) = input.parent().filter(|p| !p.as_os_str().is_empty()) {
-            let basename = input
+        // Entries are stored under the input's basename, or its rewritten
+        // path with --transform
+        let name = match config.transform {
+            Some(ref transform) => transform.apply(input)?.into_os_string(),
+            None => input
                 .file_name()
-                .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
-            cmd.arg("-C").arg(parent);
-            cmd.arg("-cf").arg(&output_path).arg(basename);
-        } else {
-            // No parent or empty parent, just use the input path directly
-            cmd.arg("-cf").arg(&output_path).arg(input);
-        }
-
-        debug!("Executing: {:?}", cmd);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute tar: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::CompressionFailed {
-                tool: "tar".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
+                .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?
+                .to_owned(),
+        };
+        self.create_archive(&output_path, &[(input.to_path_buf(), name)], config)?;
 
         // Move to destination if specified
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        let final_path = place_output(&output_path, config)?;
 
         info!("Created TAR archive: {}", final_path.display());
         Ok(final_path)
     }
 
     fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
-        if !input.to_string_lossy().ends_with(".tar") {
+        if detect_format(input) != Some(CompressionFormat::Tar) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "tar".to_string(),
@@ -87,20 +845,7 @@ impl Compressor for TarCompressor {
             .filter(|p| !p.as_os_str().is_empty())
             .unwrap_or_else(|| Path::new("."));
 
-        let mut cmd = Command::new("tar");
-        cmd.arg("-x").arg("-C").arg(parent).arg("-f").arg(input);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute tar: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "tar".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
+        self.extract_archive(input, parent, config)?;
 
         // Output is the filename without .tar extension
         let output_path = input.with_extension("");
@@ -126,14 +871,53 @@ impl Compressor for TarCompressor {
 }
 
 impl TarCompressor {
+    /// Extract `input` straight into `dest_dir`, merging with what is there
+    pub fn extract_into(
+        &self,
+        input: &Path,
+        dest_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        debug!(
+            "Extracting TAR archive {} directly into {}",
+            input.display(),
+            dest_dir.display()
+        );
+        self.extract_archive(input, dest_dir, config)
+    }
+
+    /// Apply the incremental archive `input` to `dest_dir`: the names it
+    /// records as deleted are removed, then its entries are extracted over
+    /// what is there
+    pub fn extract_incremental(
+        &self,
+        input: &Path,
+        dest_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        debug!(
+            "Applying incremental TAR archive {} to {}",
+            input.display(),
+            dest_dir.display()
+        );
+        let deleted = deleted_names(input).map_err(|e| JcError::DecompressionFailed {
+            tool: "tar".to_string(),
+            stderr: format!("{}: {}", input.display(), e),
+        })?;
+        for name in deleted {
+            remove_deleted(dest_dir, &name)?;
+        }
+        self.extract_archive(input, dest_dir, config)
+    }
+
     /// Decompress in a specific working directory
     pub fn decompress_in_dir(
         &self,
         input: &Path,
         working_dir: &Path,
-        _config: &CompressionConfig,
+        config: &CompressionConfig,
     ) -> JcResult<PathBuf> {
-        if !input.to_string_lossy().ends_with(".tar") {
+        if detect_format(input) != Some(CompressionFormat::Tar) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "tar".to_string(),
@@ -150,80 +934,9 @@ impl TarCompressor {
         let work_input = copy_to_dir(input, working_dir)?;
 
         // Extract in working directory
-        let mut cmd = Command::new("tar");
-        cmd.arg("-x")
-            .arg("-C")
-            .arg(working_dir)
-            .arg("-f")
-            .arg(&work_input);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute tar: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "tar".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
+        self.extract_archive(&work_input, working_dir, config)?;
 
-        // TAR extracts files, so we need to find what was extracted
-        // List the directory to find extracted content
-        use std::fs;
-        let entries: Vec<_> = fs::read_dir(working_dir)
-            .map_err(|e| JcError::Io(e))?
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path() != work_input) // Exclude the tar file itself
-            .collect();
-
-        // If we found exactly one entry, use that
-        if entries.len() == 1 {
-            let extracted_path = entries[0].path();
-            debug!("Extracted to: {}", extracted_path.display());
-            return Ok(extracted_path);
-        }
-
-        // Multiple files extracted - check if there's a common parent directory
-        // or a directory with the same base name as the tar file
-        let tar_base_name = work_input
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("");
-
-        // Check if there's a directory with the tar's base name
-        for entry in &entries {
-            let path = entry.path();
-            if path.is_dir() {
-                if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
-                    if dir_name == tar_base_name {
-                        debug!("Extracted to directory: {}", path.display());
-                        return Ok(path);
-                    }
-                }
-            }
-        }
-
-        // If we have multiple files but no matching directory, just return the working directory
-        // This happens when tar extracts multiple loose files
-        if !entries.is_empty() {
-            // Remove the tar file itself to avoid copying it to the destination
-            use std::fs;
-            let _ = fs::remove_file(&work_input);
-
-            debug!(
-                "Extracted {} files to: {}",
-                entries.len(),
-                working_dir.display()
-            );
-            return Ok(working_dir.to_path_buf());
-        }
-
-        // Fallback: assume filename without .tar extension (original behavior)
-        let output_path = work_input.with_extension("");
-        debug!("Extracted to (fallback): {}", output_path.display());
-        Ok(output_path)
+        find_extracted_output(working_dir, &work_input)
     }
 }
 
@@ -259,40 +972,95 @@ impl MultiFileCompressor for TarCompressor {
         // This ensures the intermediate TAR file is created in the same isolated
         // temp directory as the input files, avoiding race conditions in tests
         let mut output_path = parent_dir.join(output_name);
-        if !output_path.extension().map_or(false, |e| e == "tar") {
+        if output_path.extension().is_none_or(|e| e != "tar") {
             output_path.set_extension("tar");
         }
 
         info!("Creating multi-file TAR archive: {}", output_path.display());
 
-        let mut cmd = Command::new("tar");
-        cmd.arg("-C").arg(parent_dir);
-        cmd.arg("-cf").arg(&output_path);
+        let entries = inputs
+            .iter()
+            .map(|input| {
+                let basename = input
+                    .file_name()
+                    .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+                Ok((input.clone(), basename.to_owned()))
+            })
+            .collect::<JcResult<Vec<_>>>()?;
+        self.create_archive(&output_path, &entries, config)?;
 
-        for input in inputs {
-            let basename = input
-                .file_name()
-                .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
-            cmd.arg(basename);
-        }
+        // Move to destination if specified
+        let final_path = place_output(&output_path, config)?;
 
-        debug!("Executing: {:?}", cmd);
+        Ok(final_path)
+    }
+}
 
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute tar: {}", e)))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::CompressionFailed {
-                tool: "tar".to_string(),
-                stderr: stderr.to_string(),
-            });
+    #[test]
+    fn test_uncompressed_size_reads_pax_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("big.tar");
+
+        // As GNU tar stores members over 8 GiB: the size only in PAX
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        let records = [("size".to_string(), b"3".to_vec())];
+        append_pax_header(&mut builder, Path::new("big"), &records).unwrap();
+        let mut header = tar::Header::new_ustar();
+        header.set_path("big").unwrap();
+        header.set_mode(0o644);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append(&header, &b"abc"[..]).unwrap();
+        builder.finish().unwrap();
+
+        assert_eq!(uncompressed_size(&path).unwrap(), Some(3));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_restore_attributes_skips_symlinked_parents() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("dest");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&dest).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("file"), b"x").unwrap();
+        fs::write(dest.join("plain"), b"x").unwrap();
+        // As left by an earlier entry of the archive
+        std::os::unix::fs::symlink(&outside, dest.join("link")).unwrap();
+
+        let archive = temp_dir.path().join("attrs.tar");
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        for name in ["plain", "link/file"] {
+            let records = [("SCHILY.xattr.user.jcz".to_string(), b"set".to_vec())];
+            append_pax_header(&mut builder, Path::new(name), &records).unwrap();
+            let mut header = tar::Header::new_ustar();
+            header.set_path(name).unwrap();
+            header.set_mode(0o644);
+            header.set_size(1);
+            header.set_cksum();
+            builder.append(&header, &b"x"[..]).unwrap();
         }
+        builder.finish().unwrap();
 
-        // Move to destination if specified
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        let selection = XattrSelection {
+            xattrs: true,
+            acls: false,
+        };
+        restore_attributes(&archive, &dest, selection).unwrap();
 
-        Ok(final_path)
+        // Only meaningful where the filesystem takes user attributes
+        if xattr::get(dest.join("plain"), "user.jcz")
+            .ok()
+            .flatten()
+            .is_some()
+        {
+            assert_eq!(xattr::get(outside.join("file"), "user.jcz").unwrap(), None);
+        }
     }
 }
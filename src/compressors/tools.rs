@@ -0,0 +1,262 @@
+//! Which program does the work of the gzip, bzip2 and xz formats, and
+//! what to do when a tool is missing
+//!
+//! Parallel drop-in replacements (pigz, pbzip2 or lbzip2, pixz) are
+//! preferred when installed, since they compress large files several
+//! times faster. `--no-parallel-tools` keeps the reference tools. Without
+//! any gzip, bzip2 or xz binary (as on Windows) the built-in pure-Rust
+//! implementations are used; other missing tools fail with
+//! `JcError::ToolNotFound`, naming the package to install.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::core::config::{CompressionConfig, EncryptionMethod};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::utils::{debug, is_cancelled_io};
+
+/// External program for a stream format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tool {
+    Gzip,
+    Pigz,
+    Bzip2,
+    Pbzip2,
+    Lbzip2,
+    Xz,
+    Pixz,
+}
+
+impl Tool {
+    pub fn program(self) -> &'static str {
+        match self {
+            Tool::Gzip => "gzip",
+            Tool::Pigz => "pigz",
+            Tool::Bzip2 => "bzip2",
+            Tool::Pbzip2 => "pbzip2",
+            Tool::Lbzip2 => "lbzip2",
+            Tool::Xz => "xz",
+            Tool::Pixz => "pixz",
+        }
+    }
+
+    /// Tools for `format` in order of preference, the reference tool last
+    fn candidates(format: CompressionFormat) -> &'static [Tool] {
+        match format {
+            CompressionFormat::Gzip => &[Tool::Pigz, Tool::Gzip],
+            CompressionFormat::Bzip2 => &[Tool::Pbzip2, Tool::Lbzip2, Tool::Bzip2],
+            _ => &[Tool::Pixz, Tool::Xz],
+        }
+    }
+
+    /// Tool to run for `format` (gzip, bzip2 or xz)
+    pub fn for_format(format: CompressionFormat, config: &CompressionConfig) -> Tool {
+        let candidates = Self::candidates(format);
+        let reference = candidates[candidates.len() - 1];
+        if config.serial_tools {
+            return reference;
+        }
+
+        let tool = candidates
+            .iter()
+            .copied()
+            .find(|&tool| tool == reference || (tool.handles(config) && installed(tool.program())))
+            .unwrap_or(reference);
+        if tool != reference {
+            debug!("Using {} for {}", tool.program(), format.name());
+        }
+        tool
+    }
+
+    /// pixz has no memory limit or LZMA2 options, so `--memory-limit` and
+    /// `--lzma2` need xz
+    fn handles(self, config: &CompressionConfig) -> bool {
+        !(self == Tool::Pixz && (config.memory_limit.is_some() || config.xz_tuning().has_lzma2()))
+    }
+
+    /// Arguments capping the tool at `threads` threads
+    pub fn thread_args(self, threads: Option<usize>) -> Vec<String> {
+        let Some(threads) = threads else {
+            return Vec::new();
+        };
+        match self {
+            Tool::Gzip | Tool::Bzip2 => Vec::new(),
+            // Newer xz otherwise uses every core
+            Tool::Xz => vec![format!("-T{}", threads)],
+            Tool::Pbzip2 => vec![format!("-p{}", threads)],
+            Tool::Lbzip2 => vec!["-n".to_string(), threads.to_string()],
+            Tool::Pigz | Tool::Pixz => vec!["-p".to_string(), threads.to_string()],
+        }
+    }
+
+    /// Argument that sends the output to stdout; pixz does so whenever it
+    /// is given no files
+    pub fn stdout_arg(self) -> Option<&'static str> {
+        (self != Tool::Pixz).then_some("--stdout")
+    }
+
+    /// Whether the tool decompresses a file in place like `gzip -d`;
+    /// otherwise jcz pipes the data through it
+    pub fn works_in_place(self) -> bool {
+        self != Tool::Pixz
+    }
+
+    /// Whether the program is installed
+    pub fn available(self) -> bool {
+        installed(self.program())
+    }
+}
+
+/// Package that provides `program`, for `JcError::ToolNotFound`
+fn tool_hint(program: &str) -> String {
+    let package = match program {
+        "xz" => "xz-utils (Debian/Ubuntu) or xz",
+        "bsdtar" => "libarchive-tools (Debian/Ubuntu) or bsdtar",
+        "ar" => "binutils",
+        "gpg" => "gnupg",
+        "aws" => "awscli",
+        "sftp" => "openssh-client (Debian/Ubuntu) or openssh",
+        "gzip" | "bzip2" | "zip" | "unzip" | "pigz" | "pbzip2" | "lbzip2" | "pixz" | "curl" => {
+            program
+        }
+        _ => return format!("install {} or add its directory to PATH", program),
+    };
+    format!("install the {} package", package)
+}
+
+/// Error for a `program` that could not be run
+pub(crate) fn spawn_error(program: &str, err: io::Error) -> JcError {
+    if err.kind() == io::ErrorKind::NotFound {
+        JcError::ToolNotFound {
+            tool: program.to_string(),
+            hint: tool_hint(program),
+        }
+    } else {
+        JcError::Other(format!("Failed to execute {}: {}", program, err))
+    }
+}
+
+/// Error for a built-in decoder failing on `program`'s format: corrupt
+/// input is reported like the tool's own failure
+pub(crate) fn decode_error(program: &str, err: io::Error) -> JcError {
+    if is_cancelled_io(&err) || err.kind() == io::ErrorKind::BrokenPipe {
+        return err.into();
+    }
+    let stderr = if err.kind() == io::ErrorKind::OutOfMemory {
+        // Matched by the xz memory limit check
+        format!("Memory usage limit reached: {}", err)
+    } else {
+        err.to_string()
+    };
+    JcError::DecompressionFailed {
+        tool: program.to_string(),
+        stderr,
+    }
+}
+
+/// Fail before any work when `gpg` is needed for OpenPGP encryption but
+/// missing; every format is built in or falls back to a built-in
+/// implementation
+pub(crate) fn require_tools(config: &CompressionConfig) -> JcResult<()> {
+    if matches!(config.encryption, Some(EncryptionMethod::Gpg { .. })) && !installed("gpg") {
+        return Err(JcError::ToolNotFound {
+            tool: "gpg".to_string(),
+            hint: tool_hint("gpg"),
+        });
+    }
+    Ok(())
+}
+
+/// Whether `program` is installed, looked up once per process
+pub(crate) fn installed(program: &'static str) -> bool {
+    static FOUND: Mutex<Vec<(&str, bool)>> = Mutex::new(Vec::new());
+
+    let mut found = FOUND.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(&(_, hit)) = found.iter().find(|(name, _)| *name == program) {
+        return hit;
+    }
+    let hit = std::env::var_os("PATH").is_some_and(|path| find_in_path(program, &path).is_some());
+    found.push((program, hit));
+    hit
+}
+
+/// First executable named `program` (`program.exe` on Windows) in the
+/// directories of `path`
+fn find_in_path(program: &str, path: &OsStr) -> Option<PathBuf> {
+    let name = format!("{}{}", program, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(path)
+        .map(|dir| dir.join(&name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_in_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin = temp_dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let pigz = bin.join(format!("pigz{}", std::env::consts::EXE_SUFFIX));
+        fs::write(&pigz, "#!/bin/sh\n").unwrap();
+        fs::write(bin.join("pixz"), "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&pigz, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let path = std::env::join_paths([temp_dir.path(), &bin]).unwrap();
+        assert_eq!(find_in_path("pigz", &path), Some(pigz));
+        #[cfg(unix)]
+        assert_eq!(find_in_path("pixz", &path), None);
+        assert_eq!(find_in_path("bin", &path), None);
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let serial = CompressionConfig::new().with_serial_tools(true);
+        assert_eq!(
+            Tool::for_format(CompressionFormat::Bzip2, &serial),
+            Tool::Bzip2
+        );
+        let limited = CompressionConfig::new().with_memory_limit(Some(1 << 20));
+        assert_eq!(Tool::for_format(CompressionFormat::Xz, &limited), Tool::Xz);
+
+        assert_eq!(Tool::Lbzip2.thread_args(Some(4)), ["-n", "4"]);
+        assert_eq!(Tool::Pbzip2.thread_args(Some(4)), ["-p4"]);
+        assert!(Tool::Gzip.thread_args(Some(4)).is_empty());
+        assert!(Tool::Pigz.thread_args(None).is_empty());
+        assert_eq!(Tool::Pixz.stdout_arg(), None);
+    }
+
+    #[test]
+    fn test_spawn_error() {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        let err = spawn_error("xz", missing);
+        assert_eq!(
+            err.to_string(),
+            "xz is not installed (install the xz-utils (Debian/Ubuntu) or xz package)"
+        );
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(spawn_error("zip", denied), JcError::Other(_)));
+    }
+}
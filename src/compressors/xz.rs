@@ -1,17 +1,81 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::Command;
 
-use crate::core::compressor::Compressor;
-use crate::core::config::CompressionConfig;
+use lzma_rust2::{EncodeMode, LzmaOptions, MfType, XzOptions, XzReader, XzReaderMt, XzWriter};
+
+use crate::compressors::{
+    compress_piped, decode_error, decode_threads, decompress_piped, detect_format, filter_stream,
+    has_tool_suffix, spawn_error, strip_layer_extension, Direction, Tool,
+};
+use crate::core::compressor::{Compressor, ReadSeek};
+use crate::core::config::{CompressionConfig, XzTuning};
 use crate::core::error::{JcError, JcResult};
-use crate::utils::{copy_to_dir, debug, generate_output_filename, info, move_file_if_needed};
+use crate::core::types::CompressionFormat;
+use crate::utils::{
+    copy_to_dir, debug, generate_output_filename, info, move_file_if_needed, place_output,
+};
 
 /// XZ compressor implementation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct XzCompressor;
 
+/// Decompression arguments: the thread limit plus `--memlimit-decompress`,
+/// so xz refuses streams whose dictionary would exceed `--memory-limit`
+/// (pixz is not chosen under a limit)
+fn decompress_args(tool: Tool, config: &CompressionConfig) -> Vec<String> {
+    let mut args = tool.thread_args(config.threads);
+    if let Some(limit) = config.memory_limit {
+        args.push(format!("--memlimit-decompress={}", limit));
+    }
+    args
+}
+
+/// Report xz hitting the memory limit as such rather than a tool failure
+fn check_memory_limit(
+    result: JcResult<()>,
+    input: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    match (result, config.memory_limit) {
+        (Err(JcError::DecompressionFailed { stderr, .. }), Some(limit))
+            if stderr.contains("Memory usage limit reached") =>
+        {
+            Err(JcError::MemoryLimitExceeded {
+                path: input.to_path_buf(),
+                limit,
+            })
+        }
+        (result, _) => result,
+    }
+}
+
+/// Whether decompression must go through `decompress_stream` rather than
+/// `xz -d` on the file
+fn pipes(tool: Tool, config: &CompressionConfig) -> bool {
+    config.pipes_data() || !tool.works_in_place() || !tool.available()
+}
+
+/// Run `xz -d` with `args` on `input`, in place
+fn run_xz_decompress(args: &[String], input: &Path) -> JcResult<()> {
+    let output = Command::new("xz")
+        .arg("-d")
+        .args(args)
+        .arg(input)
+        .output()
+        .map_err(|e| spawn_error("xz", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(JcError::DecompressionFailed {
+            tool: "xz".to_string(),
+            stderr: stderr.to_string(),
+        });
+    }
+    Ok(())
+}
+
 impl XzCompressor {
     pub fn new() -> Self {
         Self
@@ -40,9 +104,11 @@ impl Compressor for XzCompressor {
     }
 
     fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let config = config.for_compressor(self);
+        let config = &*config;
         self.validate_input(input)?;
 
-        let output_path = generate_output_filename(input, "xz", config.timestamp)?;
+        let output_path = generate_output_filename(input, "xz", config)?;
         info!(
             "Compressing {} to {} with xz",
             input.display(),
@@ -50,47 +116,16 @@ impl Compressor for XzCompressor {
         );
         debug!("Compression level: {}", config.level);
 
-        let output_file = File::create(&output_path)?;
-        let mut writer = BufWriter::new(output_file);
-
-        let mut cmd = Command::new("xz");
-        cmd.arg(format!("-{}", config.level))
-            .arg("--keep")
-            .arg("--stdout")
-            .arg(input)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        compress_piped(self, input, &output_path, config)?;
 
-        debug!("Executing: {:?}", cmd);
-
-        let mut child = cmd
-            .spawn()
-            .map_err(|e| JcError::Other(format!("Failed to spawn xz: {}", e)))?;
-
-        if let Some(mut stdout) = child.stdout.take() {
-            std::io::copy(&mut stdout, &mut writer)?;
-        }
-
-        writer.flush()?;
-
-        let output = child.wait_with_output()?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::CompressionFailed {
-                tool: "xz".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
-
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        let final_path = place_output(&output_path, config)?;
 
         info!("Compressed file: {}", final_path.display());
         Ok(final_path)
     }
 
     fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
-        if !input.to_string_lossy().ends_with(".xz") {
+        if detect_format(input) != Some(CompressionFormat::Xz) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "xz".to_string(),
@@ -99,26 +134,105 @@ impl Compressor for XzCompressor {
 
         debug!("Decompressing {} with xz", input.display());
 
-        let mut cmd = Command::new("xz");
-        cmd.arg("-d").arg("-k").arg(input);
+        let tool = Tool::for_format(CompressionFormat::Xz, config);
+        let args = decompress_args(tool, config);
+        let result = if pipes(tool, config) || !has_tool_suffix(input, &[".xz", ".txz"]) {
+            decompress_piped(self, input, &strip_layer_extension(input), config)
+        } else {
+            let mut args = args;
+            args.push("-k".to_string());
+            run_xz_decompress(&args, input)
+        };
+        check_memory_limit(result, input, config)?;
+
+        let output_path = strip_layer_extension(input);
+        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
 
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute xz: {}", e)))?;
+        info!("Decompressed file: {}", final_path.display());
+        Ok(final_path)
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "xz".to_string(),
-                stderr: stderr.to_string(),
-            });
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn compress_stream(
+        &self,
+        input: &mut (dyn Read + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let config = config.for_compressor(self);
+        let config = &*config;
+        let tool = Tool::for_format(CompressionFormat::Xz, config);
+        if !tool.available() {
+            debug!("xz is not installed, using the built-in implementation");
+            let mut options = XzOptions::with_preset(config.level.into());
+            tune(&mut options.lzma_options, config.level, &config.xz_tuning());
+            let mut encoder = XzWriter::new(output, options)?;
+            io::copy(input, &mut encoder)?;
+            encoder.finish()?.flush()?;
+            return Ok(());
         }
+        let mut args = vec![preset_arg(config.level, &config.xz_tuning())];
+        args.extend(tool.thread_args(config.threads));
+        filter_stream(tool, &args, input, output, Direction::Compress)
+    }
 
-        let output_path = input.with_extension("");
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+    fn decompress_stream(
+        &self,
+        input: &mut (dyn Read + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let tool = Tool::for_format(CompressionFormat::Xz, config);
+        if !tool.available() {
+            let limit_kb = config.memory_limit.map_or(u32::MAX, |limit| {
+                (limit / 1024).try_into().unwrap_or(u32::MAX)
+            });
+            let mut decoder = XzReader::new_mem_limit(input, true, limit_kb);
+            return io::copy(&mut decoder, output)
+                .and_then(|_| output.flush())
+                .map_err(|e| decode_error("xz", e));
+        }
+        let mut args = vec!["-d".to_string()];
+        args.extend(decompress_args(tool, config));
+        filter_stream(tool, &args, input, output, Direction::Decompress)
+    }
 
-        info!("Decompressed file: {}", final_path.display());
-        Ok(final_path)
+    fn decompress_seekable(
+        &self,
+        input: &mut (dyn ReadSeek + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let tool = Tool::for_format(CompressionFormat::Xz, config);
+        let threads = decode_threads(config);
+        // The multi-threaded decoder has no memory limit
+        if tool.available() || config.memory_limit.is_some() || threads < 2 {
+            return self.decompress_stream(input, output, config);
+        }
+        let ranges = stream_ranges(input).map_err(|e| decode_error("xz", e))?;
+        input.seek(SeekFrom::Start(0))?;
+        let Some(ranges) = ranges else {
+            // Not laid out as expected; the sequential decoder tells why
+            return self.decompress_stream(input, output, config);
+        };
+        // The blocks of each stream, as listed in its index, decode on
+        // their own (pixz and `xz -T` write many)
+        debug!(
+            "Decoding {} xz streams on {} threads",
+            ranges.len(),
+            threads
+        );
+        for range in ranges {
+            let window = Window::new(&mut *input, range)?;
+            let mut decoder = XzReaderMt::new(window, false, threads as u32)
+                .map_err(|e| decode_error("xz", e))?;
+            io::copy(&mut decoder, output).map_err(|e| decode_error("xz", e))?;
+        }
+        output.flush()?;
+        Ok(())
     }
 
     fn supports_levels(&self) -> bool {
@@ -132,6 +246,193 @@ impl Compressor for XzCompressor {
     fn default_level(&self) -> u8 {
         6
     }
+
+    fn fastest_level(&self) -> u8 {
+        0
+    }
+}
+
+/// Magic bytes starting an xz stream
+const STREAM_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0];
+
+/// Bytes in a stream header, and in a stream footer
+const HEADER_LEN: u64 = 12;
+
+/// Largest index read when locating streams
+const MAX_INDEX: u64 = 64 << 20;
+
+/// Byte ranges of the streams in `input`, found from its end through each
+/// stream's footer and index, or `None` when they do not add up
+///
+/// The multi-threaded decoder only reads the last index, so streams
+/// concatenated by `cat` or pixz must be handed to it one at a time.
+fn stream_ranges(input: &mut dyn ReadSeek) -> io::Result<Option<Vec<Range<u64>>>> {
+    let mut end = input.seek(SeekFrom::End(0))?;
+    let mut ranges = Vec::new();
+    let mut word = [0u8; 4];
+    while end > 0 {
+        if end < 4 {
+            return Ok(None);
+        }
+        input.seek(SeekFrom::Start(end - 4))?;
+        input.read_exact(&mut word)?;
+        if word == [0; 4] {
+            // Stream padding
+            end -= 4;
+            continue;
+        }
+        if end < 2 * HEADER_LEN || &word[2..] != b"YZ" {
+            return Ok(None);
+        }
+
+        let mut footer = [0u8; HEADER_LEN as usize];
+        input.seek(SeekFrom::Start(end - HEADER_LEN))?;
+        input.read_exact(&mut footer)?;
+        let index_len = (u64::from(u32::from_le_bytes(footer[4..8].try_into().unwrap())) + 1) * 4;
+        let Some(index_start) = (end - HEADER_LEN)
+            .checked_sub(index_len)
+            .filter(|_| index_len <= MAX_INDEX)
+        else {
+            return Ok(None);
+        };
+        let mut index = vec![0u8; index_len as usize];
+        input.seek(SeekFrom::Start(index_start))?;
+        input.read_exact(&mut index)?;
+        let Some(start) = blocks_len(&index)
+            .and_then(|blocks| index_start.checked_sub(blocks.checked_add(HEADER_LEN)?))
+        else {
+            return Ok(None);
+        };
+
+        let mut magic = [0u8; 6];
+        input.seek(SeekFrom::Start(start))?;
+        input.read_exact(&mut magic)?;
+        if magic != STREAM_MAGIC {
+            return Ok(None);
+        }
+        ranges.push(start..end);
+        end = start;
+    }
+    ranges.reverse();
+    Ok((!ranges.is_empty()).then_some(ranges))
+}
+
+/// Total size of the blocks a stream index lists, each padded to four
+/// bytes
+fn blocks_len(index: &[u8]) -> Option<u64> {
+    let mut rest = index.strip_prefix(&[0])?;
+    let records = varint(&mut rest)?;
+    let mut len = 0u64;
+    for _ in 0..records {
+        let unpadded = varint(&mut rest)?;
+        varint(&mut rest)?;
+        len = len.checked_add(unpadded.checked_add(3)? & !3)?;
+    }
+    Some(len)
+}
+
+/// Read one of the index's variable-length integers from `data`
+fn varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..63).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// One stream of a file, read and seeked as if it were the whole file
+struct Window<'a> {
+    inner: &'a mut (dyn ReadSeek + Send),
+    range: Range<u64>,
+    pos: u64,
+}
+
+impl<'a> Window<'a> {
+    fn new(inner: &'a mut (dyn ReadSeek + Send), range: Range<u64>) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(range.start))?;
+        Ok(Window {
+            inner,
+            range,
+            pos: 0,
+        })
+    }
+
+    fn len(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+}
+
+impl Read for Window<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.len() - self.pos;
+        let max = buf.len().min(left.try_into().unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for Window<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .filter(|&pos| pos <= self.len())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek outside the stream"))?;
+        self.inner.seek(SeekFrom::Start(self.range.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+/// Preset argument for the xz tool: `-9e`, or `--lzma2=preset=9e,dict=...`
+/// when LZMA2 options override parts of it
+fn preset_arg(level: u8, tuning: &XzTuning) -> String {
+    let preset = format!("{}{}", level, if tuning.extreme { "e" } else { "" });
+    if !tuning.has_lzma2() {
+        return format!("-{}", preset);
+    }
+    let mut arg = format!("--lzma2=preset={}", preset);
+    let options = [
+        ("dict", tuning.dict_size),
+        ("lc", tuning.lc),
+        ("lp", tuning.lp),
+        ("pb", tuning.pb),
+    ];
+    for (name, value) in options {
+        if let Some(value) = value {
+            arg.push_str(&format!(",{}={}", name, value));
+        }
+    }
+    arg
+}
+
+/// Apply `tuning` to the built-in encoder's preset `options` for `level`
+fn tune(options: &mut LzmaOptions, level: u8, tuning: &XzTuning) {
+    if tuning.extreme {
+        // What xz's -e does: the normal encoder with the slowest match
+        // finder settings
+        options.mode = EncodeMode::Normal;
+        options.mf = MfType::Bt4;
+        if level == 3 || level == 5 {
+            options.nice_len = 192;
+            options.depth_limit = 0;
+        } else {
+            options.nice_len = LzmaOptions::NICE_LEN_MAX;
+            options.depth_limit = 512;
+        }
+    }
+    options.dict_size = tuning.dict_size.unwrap_or(options.dict_size);
+    options.lc = tuning.lc.unwrap_or(options.lc);
+    options.lp = tuning.lp.unwrap_or(options.lp);
+    options.pb = tuning.pb.unwrap_or(options.pb);
 }
 
 impl XzCompressor {
@@ -140,9 +441,9 @@ impl XzCompressor {
         &self,
         input: &Path,
         working_dir: &Path,
-        _config: &CompressionConfig,
+        config: &CompressionConfig,
     ) -> JcResult<PathBuf> {
-        if !input.to_string_lossy().ends_with(".xz") {
+        if detect_format(input) != Some(CompressionFormat::Xz) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "xz".to_string(),
@@ -155,28 +456,131 @@ impl XzCompressor {
             working_dir.display()
         );
 
-        // Copy input file to working directory
-        let work_input = copy_to_dir(input, working_dir)?;
+        let tool = Tool::for_format(CompressionFormat::Xz, config);
+        let args = decompress_args(tool, config);
+        let work_input;
+        let result = if pipes(tool, config) || !has_tool_suffix(input, &[".xz", ".txz"]) {
+            // Read in place; only the output goes to the working directory
+            work_input = working_dir.join(input.file_name().unwrap_or_default());
+            let output_path = strip_layer_extension(&work_input);
+            decompress_piped(self, input, &output_path, config)
+        } else {
+            // Copy input file to working directory
+            work_input = copy_to_dir(input, working_dir)?;
+
+            // Execute xz decompression in working directory
+            let mut args = args;
+            args.push("-f".to_string());
+            run_xz_decompress(&args, &work_input)
+        };
+        check_memory_limit(result, input, config)?;
+
+        let output_path = strip_layer_extension(&work_input);
+
+        debug!("Decompressed to: {}", output_path.display());
+        Ok(output_path)
+    }
+}
+
+/// Uncompressed size from the index of the xz at `path` (`xz --list`);
+/// `None` when xz cannot read the index or is not installed
+pub fn uncompressed_size(path: &Path) -> JcResult<Option<u64>> {
+    if !Tool::Xz.available() {
+        return Ok(None);
+    }
+    let output = Command::new("xz")
+        .arg("--robot")
+        .arg("--list")
+        .arg(path)
+        .output()
+        .map_err(|e| spawn_error("xz", e))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
 
-        // Execute xz decompression in working directory
-        let mut cmd = Command::new("xz");
-        cmd.arg("-d").arg("-f").arg(&work_input);
+    // totals <streams> <blocks> <compressed> <uncompressed> ...
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("totals\t"))
+        .and_then(|totals| totals.split('\t').nth(3))
+        .and_then(|size| size.parse().ok()))
+}
 
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute xz: {}", e)))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_arg() {
+        assert_eq!(preset_arg(6, &XzTuning::default()), "-6");
+        let tuning = XzTuning {
+            extreme: true,
+            dict_size: Some(64 << 20),
+            pb: Some(0),
+            ..XzTuning::default()
+        };
+        assert_eq!(
+            preset_arg(9, &tuning),
+            "--lzma2=preset=9e,dict=67108864,pb=0"
+        );
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "xz".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
+    #[test]
+    fn test_tuned_builtin_round_trip() {
+        let data = b"tuned xz data ".repeat(1000);
+        let tuning = XzTuning {
+            extreme: true,
+            dict_size: Some(1 << 20),
+            lc: Some(4),
+            lp: Some(0),
+            pb: Some(0),
+        };
+        let mut options = XzOptions::with_preset(3);
+        tune(&mut options.lzma_options, 3, &tuning);
+        assert_eq!(options.lzma_options.dict_size, 1 << 20);
+        assert_eq!(options.lzma_options.nice_len, 192);
+
+        let mut encoder = XzWriter::new(Vec::new(), options).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut decoded = Vec::new();
+        XzReader::new(&compressed[..], true)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
 
-        let output_path = work_input.with_extension("");
+    #[test]
+    fn test_stream_ranges() {
+        let mut input = Vec::new();
+        let mut streams = Vec::new();
+        for part in [&b"first stream "[..], b"second stream"] {
+            let mut encoder = XzWriter::new(Vec::new(), XzOptions::with_preset(1)).unwrap();
+            encoder.write_all(&part.repeat(500)).unwrap();
+            let start = input.len() as u64;
+            input.extend(encoder.finish().unwrap());
+            streams.push(start..input.len() as u64);
+            // Stream padding
+            input.extend([0; 8]);
+        }
+        let ranges = stream_ranges(&mut io::Cursor::new(&input)).unwrap();
+        assert_eq!(ranges, Some(streams.clone()));
+
+        let mut cursor = io::Cursor::new(input.clone());
+        let mut output = Vec::new();
+        for range in streams {
+            let window = Window::new(&mut cursor, range).unwrap();
+            XzReaderMt::new(window, false, 2)
+                .unwrap()
+                .read_to_end(&mut output)
+                .unwrap();
+        }
+        assert_eq!(
+            output,
+            [b"first stream ".repeat(500), b"second stream".repeat(500)].concat()
+        );
 
-        debug!("Decompressed to: {}", output_path.display());
-        Ok(output_path)
+        input[0] = b'x';
+        assert_eq!(stream_ranges(&mut io::Cursor::new(&input)).unwrap(), None);
     }
 }
@@ -1,13 +1,82 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use chrono::{Datelike, TimeZone, Timelike};
+use flate2::CrcWriter;
+use rayon::prelude::*;
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::compressors::cpio::{create_symlink, permission_bits, set_permissions};
+use crate::compressors::{decode_threads, detect_format, installed, spawn_error, top_level_names};
 use crate::core::compressor::Compressor;
-use crate::core::config::CompressionConfig;
+use crate::core::config::{CompressionConfig, ZipPassword};
 use crate::core::error::{JcError, JcResult};
-use crate::utils::{copy_to_dir, debug, generate_output_filename, info, move_file_if_needed};
+use crate::core::heuristics::find_compressed_members;
+use crate::core::types::{ArchiveEntry, CompressionFormat, EntryKind};
+use crate::crypto::CryptoError;
+use crate::utils::bufio::buffered_writer;
+use crate::utils::{
+    copy_to_dir, debug, find_extracted_output, generate_output_filename, info,
+    is_inside_without_symlinks, path_from_bytes, place_output, run_limited, warn,
+};
+
+/// Most entries a zip can count without Zip64 records
+const ZIP32_MAX_ENTRIES: u64 = 0xFFFF;
+
+/// Largest size or offset a zip can record without Zip64 records
+const ZIP32_MAX_SIZE: u64 = 0xFFFF_FFFF;
+
+/// End of central directory signature
+const EOCD_SIGNATURE: &[u8] = b"PK\x05\x06";
+
+/// Zip64 end of central directory locator signature; sits right before the EOCD
+const ZIP64_LOCATOR_SIGNATURE: &[u8] = b"PK\x06\x07";
+
+/// Zip64 end of central directory record signature
+const ZIP64_EOCD_SIGNATURE: &[u8] = b"PK\x06\x06";
+
+/// Central directory file header signature
+const CD_SIGNATURE: &[u8] = b"PK\x01\x02";
+
+/// Fixed sizes of the EOCD record, the Zip64 locator, the Zip64 EOCD
+/// record (without extensible data) and a central directory file header
+const EOCD_LEN: u64 = 22;
+const ZIP64_LOCATOR_LEN: usize = 20;
+const ZIP64_EOCD_LEN: usize = 56;
+const CD_HEADER_LEN: usize = 46;
+
+/// Local file header signature
+const LOCAL_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Compression method marking WinZip AES encrypted entries
+const AES_METHOD: u16 = 99;
+
+/// General purpose flag: name and comment are UTF-8 (APPNOTE bit 11)
+const UTF8_FLAG: u16 = 0x0800;
+
+/// Extra field id of the Zip64 extended information
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+/// Uncompressed size below which one unzip extracts a whole archive
+const PARALLEL_MIN_SIZE: u64 = 1 << 20;
+
+/// Bytes of member names given to one unzip, well within `ARG_MAX`
+const MAX_ARG_BYTES: usize = 64 << 10;
+
+/// Host byte of "version made by" for Unix, whose entries carry a mode
+const UNIX_HOST: u8 = 3;
+
+/// Longest archive comment the 16-bit length field allows
+pub const MAX_COMMENT_LEN: usize = 0xFFFF;
 
 /// ZIP compressor/decompressor implementation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ZipCompressor;
 
 impl ZipCompressor {
@@ -34,9 +103,11 @@ impl Compressor for ZipCompressor {
     }
 
     fn compress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+        let config = config.for_compressor(self);
+        let config = &*config;
         self.validate_input(input)?;
 
-        let output_path = generate_output_filename(input, "zip", config.timestamp)?;
+        let output_path = generate_output_filename(input, "zip", config)?;
         info!(
             "Compressing {} to {} with zip",
             input.display(),
@@ -44,47 +115,100 @@ impl Compressor for ZipCompressor {
         );
         debug!("Compression level: {}", config.level);
 
-        // Build zip command
-        let mut cmd = Command::new("zip");
-
-        // Add compression level (0-9)
-        cmd.arg(format!("-{}", config.level));
+        // Run from the input's directory so entries are stored under its
+        // basename rather than its full path
+        let parent = input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let name = input
+            .file_name()
+            .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+        let archive = std::path::absolute(&output_path)?;
+        // zip and bsdtar walk directories themselves, so files outside the
+        // size range are excluded by name
+        let excluded = size_exclusions(input, name, config)?;
 
-        // Recursive flag for directories
-        if input.is_dir() {
-            cmd.arg("-r");
-        }
-
-        // Quiet mode
-        cmd.arg("-q");
-
-        // Output file and input
-        cmd.arg(&output_path).arg(input);
+        // Info-ZIP cannot write AES, so encrypted zips go through bsdtar
+        let tool = if config.zip_password.is_some() {
+            "bsdtar"
+        } else {
+            "zip"
+        };
+        if !installed(tool) {
+            info!(
+                "{} is not installed, using the built-in implementation",
+                tool
+            );
+            let stored = if config.level > 0 {
+                find_compressed_members(input)?.suffixes
+            } else {
+                Vec::new()
+            };
+            write_builtin(&archive, parent, &[name], &stored, &excluded, config)?;
+        } else {
+            let mut cmd = match config.zip_password {
+                Some(ref password) => {
+                    debug!(
+                        "Executing bsdtar to write AES-256 zip {}",
+                        archive.display()
+                    );
+                    aes_zip_command(&archive, name, &excluded, password, config)
+                }
+                None => {
+                    // A zip without Zip64 support silently writes corrupt archives here
+                    if needs_zip64(input, config.dereference)? {
+                        debug!("{} needs Zip64 records", input.display());
+                        require_zip64_support("zip", input)?;
+                    }
 
-        debug!("Executing: {:?}", cmd);
+                    let stored = if config.level > 0 {
+                        find_compressed_members(input)?.suffixes
+                    } else {
+                        Vec::new()
+                    };
+                    let cmd = zip_command(
+                        &archive,
+                        &[name],
+                        input.is_dir(),
+                        &stored,
+                        &excluded,
+                        config,
+                    );
+                    debug!("Executing: {:?}", cmd);
+                    cmd
+                }
+            };
 
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute zip: {}", e)))?;
+            let output = cmd
+                .current_dir(parent)
+                .output()
+                .map_err(|e| spawn_error(tool, e))?;
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::CompressionFailed {
-                tool: "zip".to_string(),
-                stderr: stderr.to_string(),
-            });
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(JcError::CompressionFailed {
+                    tool: tool.to_string(),
+                    stderr: stderr.to_string(),
+                });
+            }
+        }
+        flag_utf8_names(&archive)?;
+        if let Some(ref comment) = config.zip_comment {
+            write_comment(&archive, comment)?;
         }
+        report_entries(&archive, config)?;
 
         // Move to destination if specified
-        let final_path = move_file_if_needed(&output_path, &config.move_to)?;
+        let final_path = place_output(&output_path, config)?;
 
         info!("Compressed file: {}", final_path.display());
         Ok(final_path)
     }
 
-    fn decompress(&self, input: &Path, _config: &CompressionConfig) -> JcResult<PathBuf> {
+    fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
         // Validate extension
-        if !input.to_string_lossy().ends_with(".zip") {
+        if detect_format(input) != Some(CompressionFormat::Zip) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "zip".to_string(),
@@ -95,24 +219,7 @@ impl Compressor for ZipCompressor {
 
         let parent = input.parent().unwrap_or_else(|| Path::new("."));
 
-        // Execute unzip command
-        let mut cmd = Command::new("unzip");
-        cmd.arg("-o") // overwrite without prompting
-            .arg(input)
-            .arg("-d")
-            .arg(parent);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute unzip: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "unzip".to_string(),
-                stderr: stderr.to_string(),
-            });
-        }
+        run_unzip(input, parent, config)?;
 
         // Output is the filename without .zip extension
         let output_path = input.with_extension("");
@@ -140,10 +247,10 @@ impl ZipCompressor {
         &self,
         input: &Path,
         working_dir: &Path,
-        _config: &CompressionConfig,
+        config: &CompressionConfig,
     ) -> JcResult<PathBuf> {
         // Validate extension
-        if !input.to_string_lossy().ends_with(".zip") {
+        if detect_format(input) != Some(CompressionFormat::Zip) {
             return Err(JcError::InvalidExtension(
                 input.to_path_buf(),
                 "zip".to_string(),
@@ -160,73 +267,1526 @@ impl ZipCompressor {
         let work_input = copy_to_dir(input, working_dir)?;
 
         // Execute unzip command in working directory
-        let mut cmd = Command::new("unzip");
-        cmd.arg("-o") // overwrite without prompting
-            .arg(&work_input)
-            .arg("-d")
-            .arg(working_dir);
-
-        let output = cmd
-            .output()
-            .map_err(|e| JcError::Other(format!("Failed to execute unzip: {}", e)))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(JcError::DecompressionFailed {
-                tool: "unzip".to_string(),
-                stderr: stderr.to_string(),
-            });
+        run_unzip(&work_input, working_dir, config)?;
+
+        find_extracted_output(working_dir, &work_input)
+    }
+
+    /// Extract `input` straight into `dest_dir`, overwriting what is there
+    pub fn extract_into(
+        &self,
+        input: &Path,
+        dest_dir: &Path,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        debug!(
+            "Extracting {} directly into {}",
+            input.display(),
+            dest_dir.display()
+        );
+        run_unzip(input, dest_dir, config)
+    }
+
+    /// Write the entries `names` of `dir` to the zip `archive`, each under
+    /// its own name, as `jcz merge` does with a `.zip` output
+    pub(crate) fn create_archive(
+        &self,
+        archive: &Path,
+        dir: &Path,
+        names: &[OsString],
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        let stored = if config.level > 0 {
+            find_compressed_members(dir)?.suffixes
+        } else {
+            Vec::new()
+        };
+        let archive = std::path::absolute(archive)?;
+        let names: Vec<&OsStr> = names.iter().map(OsString::as_os_str).collect();
+        if !installed("zip") {
+            info!("zip is not installed, using the built-in implementation");
+            write_builtin(&archive, dir, &names, &stored, &[], config)?;
+        } else {
+            if needs_zip64(dir, config.dereference)? {
+                debug!("{} needs Zip64 records", dir.display());
+                require_zip64_support("zip", dir)?;
+            }
+            let mut cmd = zip_command(&archive, &names, true, &stored, &[], config);
+            debug!("Executing: {:?}", cmd);
+
+            let output = cmd
+                .current_dir(dir)
+                .output()
+                .map_err(|e| spawn_error("zip", e))?;
+            if !output.status.success() {
+                return Err(JcError::CompressionFailed {
+                    tool: "zip".to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                });
+            }
+        }
+        flag_utf8_names(&archive)?;
+        if let Some(ref comment) = config.zip_comment {
+            write_comment(&archive, comment)?;
         }
+        Ok(())
+    }
+}
+
+/// Member metadata of the zip at `path` that has no place in a tar, one
+/// message for each kind
+pub fn tar_incompatibilities(path: &Path) -> JcResult<Vec<String>> {
+    let mut notes = Vec::new();
+    if let Some((_, directory)) = read_central_directory(path)? {
+        let entries = central_entries(&directory);
+        // The high byte of "version made by" is the host; only Unix hosts
+        // record a mode in the external attributes
+        let without_mode = entries
+            .iter()
+            .filter(|&&(_, entry)| entry[5] != UNIX_HOST)
+            .count();
+        if without_mode > 0 {
+            notes.push(format!(
+                "{} members were not made on Unix and have no permissions to keep; \
+                 they get default ones",
+                without_mode
+            ));
+        }
+        let commented = entries
+            .iter()
+            .filter(|&&(_, entry)| le_u16(entry, 32) > 0)
+            .count();
+        if commented > 0 {
+            notes.push(format!(
+                "{} member comments cannot be stored in a tar and are dropped",
+                commented
+            ));
+        }
+    }
+    if read_comment(path)?.is_some() {
+        notes.push("The archive comment cannot be stored in a tar and is dropped".to_string());
+    }
+    Ok(notes)
+}
+
+/// Tell `config`'s entry listener about each member of `archive`
+///
+/// zip and unzip do the work, so members are reported from the central
+/// directory once they are done rather than one by one.
+fn report_entries(archive: &Path, config: &CompressionConfig) -> JcResult<()> {
+    if config.entry_listener.is_some() {
+        for entry in list_entries(archive)? {
+            config.report_entry(&entry);
+        }
+    }
+    Ok(())
+}
+
+/// Top-level names the zip at `path` extracts to, from its central
+/// directory in archive order
+pub fn top_level_entries(path: &Path) -> JcResult<Vec<OsString>> {
+    let Some((_, directory)) = read_central_directory(path)? else {
+        return Err(JcError::DecompressionFailed {
+            tool: "unzip".to_string(),
+            stderr: format!("{}: central directory not found", path.display()),
+        });
+    };
+    let names: Vec<PathBuf> = central_entries(&directory)
+        .iter()
+        .map(|&(_, entry)| {
+            let name = &entry[CD_HEADER_LEN..CD_HEADER_LEN + le_u16(entry, 28) as usize];
+            path_from_bytes(name)
+        })
+        .collect();
+    Ok(top_level_names(names.iter().map(PathBuf::as_path)))
+}
+
+/// Members of the zip at `path`, from its central directory in archive
+/// order
+pub fn list_entries(path: &Path) -> JcResult<Vec<ArchiveEntry>> {
+    let Some((_, directory)) = read_central_directory(path)? else {
+        return Err(JcError::DecompressionFailed {
+            tool: "unzip".to_string(),
+            stderr: format!("{}: central directory not found", path.display()),
+        });
+    };
+    Ok(central_entries(&directory)
+        .iter()
+        .map(|&(_, entry)| {
+            let name = &entry[CD_HEADER_LEN..CD_HEADER_LEN + le_u16(entry, 28) as usize];
+            // Unix mode in the high half of the external attributes
+            let mode = le_u32(entry, 38) >> 16;
+            let kind = if name.ends_with(b"/") {
+                EntryKind::Directory
+            } else if mode & 0o170000 == 0o120000 {
+                EntryKind::Symlink
+            } else {
+                EntryKind::File
+            };
+            ArchiveEntry {
+                path: path_from_bytes(name),
+                size: match kind {
+                    EntryKind::File => entry_uncompressed_size(entry).unwrap_or(0),
+                    _ => 0,
+                },
+                kind,
+            }
+        })
+        .collect())
+}
+
+/// Info-ZIP command writing `name` (relative to the working dir) to `archive`
+/// Suffixes Info-ZIP stores uncompressed by default; `-n` replaces them
+const ZIP_DEFAULT_STORED: [&str; 6] = ["Z", "zip", "zoo", "arc", "lzh", "arj"];
+
+/// Info-ZIP command writing `names` to `archive`
+///
+/// Files with the `stored` suffixes (already-compressed members) are
+/// stored rather than deflated again, and the `excluded` paths left out.
+fn zip_command(
+    archive: &Path,
+    names: &[&OsStr],
+    is_dir: bool,
+    stored: &[String],
+    excluded: &[PathBuf],
+    config: &CompressionConfig,
+) -> Command {
+    let mut cmd = Command::new("zip");
 
-        // Find what was extracted (similar to TAR behavior)
-        use std::fs;
-        let entries: Vec<_> = fs::read_dir(working_dir)
-            .map_err(|e| JcError::Io(e))?
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path() != work_input) // Exclude the zip file itself
+    // Add compression level (0-9)
+    cmd.arg(format!("-{}", config.level));
+
+    if !stored.is_empty() {
+        let suffixes: Vec<String> = ZIP_DEFAULT_STORED
+            .iter()
+            .map(|s| s.to_string())
+            .chain(stored.iter().cloned())
+            .map(|s| format!(".{}", s))
             .collect();
+        cmd.arg("-n").arg(suffixes.join(":"));
+    }
 
-        // Remove the copied zip file from working directory
-        let _ = fs::remove_file(&work_input);
-
-        // If we found exactly one entry, use that
-        if entries.len() == 1 {
-            let extracted_path = entries[0].path();
-            debug!("Extracted to: {}", extracted_path.display());
-            return Ok(extracted_path);
-        }
-
-        // Check if there's a directory with the zip's base name
-        let zip_base_name = work_input
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("");
-
-        for entry in &entries {
-            let path = entry.path();
-            if path.is_dir() {
-                if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
-                    if dir_name == zip_base_name {
-                        debug!("Extracted to directory: {}", path.display());
-                        return Ok(path);
-                    }
+    // Recursive flag for directories
+    if is_dir {
+        cmd.arg("-r");
+    }
+
+    // Store symlinks as links unless asked to follow them
+    if !config.dereference {
+        cmd.arg("-y");
+    }
+
+    // Excluded names are taken literally rather than as wildcards
+    if !excluded.is_empty() {
+        cmd.arg("-nw");
+    }
+
+    // Quiet mode
+    cmd.arg("-q").arg(archive).args(names);
+    if !excluded.is_empty() {
+        cmd.arg("-x").args(excluded);
+    }
+    cmd
+}
+
+/// bsdtar command writing `name` to `archive` as a WinZip AES-256 zip
+///
+/// bsdtar only takes the passphrase as an argument, so it is briefly
+/// visible in the process list, as with `zip -P`.
+fn aes_zip_command(
+    archive: &Path,
+    name: &OsStr,
+    excluded: &[PathBuf],
+    password: &ZipPassword,
+    config: &CompressionConfig,
+) -> Command {
+    let compression = if config.level == 0 {
+        "zip:compression=store".to_string()
+    } else {
+        format!("zip:compression-level={}", config.level)
+    };
+
+    let mut cmd = Command::new("bsdtar");
+    cmd.arg("--format")
+        .arg("zip")
+        .arg("--options")
+        .arg(format!("{},zip:encryption=aes256", compression))
+        .arg("--passphrase")
+        .arg(password.expose());
+
+    // bsdtar stores symlinks as links unless told to follow them
+    if config.dereference {
+        cmd.arg("-L");
+    }
+
+    for path in excluded {
+        cmd.arg("--exclude").arg(escape_pattern(path));
+    }
+
+    cmd.arg("-cf").arg(archive).arg(name);
+    cmd
+}
+
+/// `path` as a bsdtar pattern matching only itself
+fn escape_pattern(path: &Path) -> OsString {
+    let mut pattern = String::new();
+    for c in path.to_string_lossy().chars() {
+        if matches!(c, '*' | '?' | '[' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    OsString::from(pattern)
+}
+
+/// Write `names` (relative to `dir`) to `archive` without Info-ZIP or
+/// bsdtar, AES-256 encrypted with `config.zip_password`
+///
+/// As with the tools, files with the `stored` suffixes are stored rather
+/// than deflated, the `excluded` paths left out and symlinks stored as
+/// links unless `config.dereference`. Zip64 records are written as needed.
+fn write_builtin(
+    archive: &Path,
+    dir: &Path,
+    names: &[&OsStr],
+    stored: &[String],
+    excluded: &[PathBuf],
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    let mut walk = ZipWalk {
+        writer: ZipWriter::new(buffered_writer(File::create(archive)?)),
+        stored: ZIP_DEFAULT_STORED
+            .iter()
+            .map(|s| s.to_string())
+            .chain(stored.iter().cloned())
+            .collect(),
+        excluded,
+        ancestors: Vec::new(),
+        config,
+    };
+    let result = names
+        .iter()
+        .try_for_each(|name| append_builtin(&mut walk, &dir.join(name), Path::new(name)))
+        .and_then(|()| {
+            walk.writer
+                .finish()
+                .map_err(|e| builtin_error("zip", e))?
+                .flush()?;
+            Ok(())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(archive);
+    }
+    result
+}
+
+/// State of a built-in zip write
+struct ZipWalk<'a> {
+    writer: ZipWriter<BufWriter<File>>,
+    /// Suffixes of files stored rather than deflated
+    stored: Vec<String>,
+    excluded: &'a [PathBuf],
+    /// Directories being walked, to break symlink loops
+    ancestors: Vec<PathBuf>,
+    config: &'a CompressionConfig,
+}
+
+/// Append a file, symlink or directory tree under the given entry name
+fn append_builtin(walk: &mut ZipWalk<'_>, path: &Path, name: &Path) -> JcResult<()> {
+    if walk.excluded.iter().any(|excluded| excluded == name) {
+        debug!("Outside the size range: {}", path.display());
+        return Ok(());
+    }
+
+    let link_metadata = fs::symlink_metadata(path)?;
+    // A dangling link has nothing to follow and stays a link
+    let metadata = if walk.config.dereference && link_metadata.file_type().is_symlink() {
+        fs::metadata(path).unwrap_or(link_metadata)
+    } else {
+        link_metadata
+    };
+
+    let entry_name = entry_name(name)?;
+    let mut options = SimpleFileOptions::default()
+        .last_modified_time(dos_time(&metadata, walk.config.reproducible))
+        .unix_permissions(permission_bits(&metadata, 0o644));
+    if let Some(ref password) = walk.config.zip_password {
+        options = options.with_aes_encryption(AesMode::Aes256, password.expose());
+    }
+
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        let target = fs::read_link(path)?;
+        walk.writer
+            .add_symlink(entry_name, target.to_string_lossy(), options)
+            .map_err(|e| builtin_error("zip", e))?;
+    } else if file_type.is_dir() {
+        let canonical = path.canonicalize()?;
+        if walk.ancestors.contains(&canonical) {
+            warn!("Skipping {}: symlink loop", path.display());
+            return Ok(());
+        }
+        walk.writer
+            .add_directory(entry_name, options)
+            .map_err(|e| builtin_error("zip", e))?;
+
+        let mut children = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        children.sort();
+        walk.ancestors.push(canonical);
+        for child in children {
+            append_builtin(walk, &path.join(&child), &name.join(&child))?;
+        }
+        walk.ancestors.pop();
+    } else if file_type.is_file() {
+        let suffix = name.extension().map(|s| s.to_string_lossy());
+        let store = walk.config.level == 0
+            || suffix.is_some_and(|suffix| {
+                walk.stored
+                    .iter()
+                    .any(|stored| stored.eq_ignore_ascii_case(&suffix))
+            });
+        options = if store {
+            options.compression_method(CompressionMethod::Stored)
+        } else {
+            options.compression_level(Some(i64::from(walk.config.level)))
+        };
+        walk.writer
+            .start_file(
+                entry_name,
+                options.large_file(metadata.len() > ZIP32_MAX_SIZE),
+            )
+            .map_err(|e| builtin_error("zip", e))?;
+        io::copy(&mut File::open(path)?, &mut walk.writer)?;
+    } else {
+        warn!("Skipping {}: not a regular file", path.display());
+    }
+
+    Ok(())
+}
+
+/// `name` as a zip entry name: UTF-8 with `/` separators
+fn entry_name(name: &Path) -> JcResult<String> {
+    let parts = name
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            JcError::Other(format!(
+                "{} is not valid UTF-8, which the built-in zip requires",
+                name.display()
+            ))
+        })?;
+    Ok(parts.join("/"))
+}
+
+/// DOS timestamp for an entry with `metadata`: its mtime in local time,
+/// as Info-ZIP stores it, or clamped to the `reproducible` epoch in UTC
+fn dos_time(metadata: &fs::Metadata, reproducible: Option<u64>) -> zip::DateTime {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let time = match reproducible {
+        Some(epoch) => chrono::DateTime::from_timestamp(mtime.min(epoch) as i64, 0)
+            .map(|time| time.naive_utc()),
+        None => chrono::DateTime::from_timestamp(mtime as i64, 0)
+            .map(|time| time.with_timezone(&chrono::Local).naive_local()),
+    };
+    // Zip cannot date anything before 1980; those get its earliest time
+    time.and_then(|time| {
+        zip::DateTime::from_date_and_time(
+            u16::try_from(time.year()).ok()?,
+            time.month() as u8,
+            time.day() as u8,
+            time.hour() as u8,
+            time.minute() as u8,
+            time.second() as u8,
+        )
+        .ok()
+    })
+    .unwrap_or_default()
+}
+
+/// Regular files under the directory `input` that `config.filter` leaves
+/// out, as they are stored: under `name`
+fn size_exclusions(
+    input: &Path,
+    name: &OsStr,
+    config: &CompressionConfig,
+) -> JcResult<Vec<PathBuf>> {
+    let mut excluded = Vec::new();
+    if !config.filter.is_active() || !input.is_dir() {
+        return Ok(excluded);
+    }
+
+    let mut pending = vec![(input.to_path_buf(), PathBuf::from(name))];
+    while let Some((path, stored)) = pending.pop() {
+        let mut metadata = fs::symlink_metadata(&path)?;
+        if config.dereference && metadata.file_type().is_symlink() {
+            metadata = fs::metadata(&path).unwrap_or(metadata);
+        }
+        if metadata.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                pending.push((entry.path(), stored.join(entry.file_name())));
+            }
+        } else if config.filter.excludes(&metadata) {
+            excluded.push(stored);
+        }
+    }
+    excluded.sort();
+    Ok(excluded)
+}
+
+/// Extract `archive` into `dest_dir`, then report its members
+fn run_unzip(archive: &Path, dest_dir: &Path, config: &CompressionConfig) -> JcResult<()> {
+    extract_zip(archive, dest_dir, config)?;
+    report_entries(archive, config)
+}
+
+/// Extract `archive` into `dest_dir` with unzip, or bsdtar for AES entries
+/// and legacy name encodings, or the built-in implementation when the
+/// tool is not installed
+fn extract_zip(archive: &Path, dest_dir: &Path, config: &CompressionConfig) -> JcResult<()> {
+    let encrypted = has_aes_entries(archive)?;
+    if encrypted && config.zip_password.is_none() {
+        return Err(JcError::Other(format!(
+            "{} is AES-encrypted; pass --zip-encrypt to enter its password",
+            archive.display()
+        )));
+    }
+
+    // Names in a legacy codepage are only decoded by bsdtar
+    if config.zip_encoding.is_some() {
+        return extract_with_bsdtar(archive, dest_dir, config);
+    }
+
+    // unzip cannot decrypt AES
+    let tool = if encrypted { "bsdtar" } else { "unzip" };
+    if !installed(tool) {
+        info!(
+            "{} is not installed, using the built-in implementation",
+            tool
+        );
+        return extract_builtin(archive, dest_dir, config);
+    }
+    if encrypted {
+        return extract_with_bsdtar(archive, dest_dir, config);
+    }
+
+    if is_zip64_archive(archive)? {
+        debug!("{} uses Zip64 records", archive.display());
+        require_zip64_support("unzip", archive)?;
+    }
+
+    let threads = decode_threads(config);
+    if threads > 1 {
+        if let Some(groups) = member_groups(archive, threads)? {
+            return unzip_in_parallel(archive, dest_dir, &groups, threads, config.no_verify);
+        }
+    }
+    unzip_members(archive, dest_dir, &[], config.no_verify)
+}
+
+/// Extract `members` of `archive`, or all of them when empty, into
+/// `dest_dir` with unzip; members failing their CRC32 check are kept
+/// with `no_verify`
+fn unzip_members(
+    archive: &Path,
+    dest_dir: &Path,
+    members: &[OsString],
+    no_verify: bool,
+) -> JcResult<()> {
+    // Under a non-UTF-8 locale unzip escapes flagged UTF-8 names as #Uxxxx
+    let mut cmd = Command::new("unzip");
+    cmd.env("LC_ALL", "C.UTF-8")
+        .arg("-o") // overwrite without prompting
+        .arg(archive)
+        .args(members)
+        .arg("-d")
+        .arg(dest_dir);
+
+    let output = cmd.output().map_err(|e| spawn_error("unzip", e))?;
+
+    // Exit code 1 means warnings only; everything was still extracted
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => {
+            warn!(
+                "unzip reported warnings for {}: {}",
+                archive.display(),
+                String::from_utf8_lossy(&output.stdout).trim()
+            );
+            Ok(())
+        }
+        _ => check_crcs(archive, "unzip", &output, no_verify, || {
+            // Extraction interleaves names and errors across stdout and
+            // stderr; a quiet test run lists one failed member per line
+            let test = Command::new("unzip")
+                .env("LC_ALL", "C.UTF-8")
+                .arg("-tq")
+                .arg(archive)
+                .args(members)
+                .output()
+                .map_err(|e| spawn_error("unzip", e))?;
+            Ok(crc_failures(&String::from_utf8_lossy(&test.stdout)))
+        }),
+    }
+}
+
+/// Extract `groups` of the members of `archive` into `dest_dir`, one
+/// unzip per group and up to `threads` at once
+fn unzip_in_parallel(
+    archive: &Path,
+    dest_dir: &Path,
+    groups: &[Vec<OsString>],
+    threads: usize,
+    no_verify: bool,
+) -> JcResult<()> {
+    // Directories first, so that no two runs race to create one
+    for name in groups.iter().flatten() {
+        if let Some(parent) = Path::new(name).parent() {
+            fs::create_dir_all(dest_dir.join(parent))?;
+        }
+    }
+    debug!(
+        "Extracting {} in {} parts on {} threads",
+        archive.display(),
+        groups.len(),
+        threads
+    );
+    run_limited(Some(threads), || {
+        groups
+            .par_iter()
+            .try_for_each(|group| unzip_members(archive, dest_dir, group, no_verify))
+    })
+}
+
+/// Members of the zip at `archive` split into groups for separate unzip
+/// runs on `threads` threads, or `None` when one run should extract all
+///
+/// Names unzip would take for wildcards, paths leaving the destination
+/// and symlinks, which other members might be written through, keep an
+/// archive in one run.
+fn member_groups(archive: &Path, threads: usize) -> JcResult<Option<Vec<Vec<OsString>>>> {
+    let Some((_, directory)) = read_central_directory(archive)? else {
+        return Ok(None);
+    };
+    let mut members = Vec::new();
+    for (_, entry) in central_entries(&directory) {
+        let name = &entry[CD_HEADER_LEN..CD_HEADER_LEN + le_u16(entry, 28) as usize];
+        let mode = le_u32(entry, 38) >> 16;
+        // unzip converts names in legacy encodings before matching them
+        let utf8 = le_u16(entry, 8) & UTF8_FLAG != 0;
+        if mode & 0o170000 == 0o120000 || !(utf8 || name.is_ascii()) || !is_plain_member_name(name)
+        {
+            return Ok(None);
+        }
+        let size = entry_uncompressed_size(entry).unwrap_or(0);
+        members.push((path_from_bytes(name).into_os_string(), size));
+    }
+    Ok(split_members(members, threads))
+}
+
+/// Whether unzip matches `name` only by itself and extracts it below the
+/// destination
+fn is_plain_member_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && !name.starts_with(b"/")
+        && !name.iter().any(|b| b"*?[]\\".contains(b))
+        && name.split(|&b| b == b'/').all(|part| part != b"..")
+}
+
+/// Split `members` (name and size, in archive order) into runs holding
+/// about an equal share of the data for `threads` threads, each short
+/// enough for one command line
+fn split_members(members: Vec<(OsString, u64)>, threads: usize) -> Option<Vec<Vec<OsString>>> {
+    let total: u64 = members.iter().map(|(_, size)| size).sum();
+    if members.len() < 2 || total < PARALLEL_MIN_SIZE {
+        return None;
+    }
+    let share = total.div_ceil(threads as u64);
+
+    let mut groups = Vec::new();
+    let mut group = Vec::new();
+    let (mut size, mut arg_bytes) = (0, 0);
+    for (name, member_size) in members {
+        if !group.is_empty() && (size >= share || arg_bytes + name.len() > MAX_ARG_BYTES) {
+            groups.push(std::mem::take(&mut group));
+            (size, arg_bytes) = (0, 0);
+        }
+        size += member_size;
+        arg_bytes += name.len();
+        group.push(name);
+    }
+    groups.push(group);
+    (groups.len() > 1).then_some(groups)
+}
+
+/// Extract a zip with bsdtar, decrypting AES entries and decoding names
+/// from `config.zip_encoding`
+fn extract_with_bsdtar(
+    archive: &Path,
+    dest_dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    debug!("Executing bsdtar to extract {}", archive.display());
+
+    let mut cmd = Command::new("bsdtar");
+    cmd.arg("-xf").arg(archive).arg("-C").arg(dest_dir);
+
+    // Entries flagged as UTF-8 stay UTF-8; the rest are converted from the
+    // given charset into the locale, which must be UTF-8 for that
+    if let Some(ref encoding) = config.zip_encoding {
+        cmd.env("LC_ALL", "C.UTF-8")
+            .arg("--options")
+            .arg(format!("zip:hdrcharset={}", encoding));
+    }
+    if let Some(ref password) = config.zip_password {
+        cmd.arg("--passphrase").arg(password.expose());
+    }
+
+    let output = cmd.output().map_err(|e| spawn_error("bsdtar", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("Incorrect passphrase") {
+            return Err(CryptoError::AuthenticationFailed.into());
+        }
+        return check_crcs(archive, "bsdtar", &output, config.no_verify, || {
+            Ok(crc_failures(&stderr))
+        });
+    }
+    Ok(())
+}
+
+/// Extract `archive` into `dest_dir` without unzip or bsdtar, decrypting
+/// AES entries with `config.zip_password`
+///
+/// Existing files are overwritten, and members whose paths leave
+/// `dest_dir`, also through symlinks extracted before them, are skipped.
+/// CRC32 mismatches fail the extraction unless `config.no_verify`.
+fn extract_builtin(archive: &Path, dest_dir: &Path, config: &CompressionConfig) -> JcResult<()> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(archive)?))
+        .map_err(|e| builtin_error("unzip", e))?;
+    let password = config
+        .zip_password
+        .as_ref()
+        .map_or(&b""[..], |password| password.expose().as_bytes());
+
+    let mut failed = Vec::new();
+    for index in 0..zip.len() {
+        let encrypted = zip
+            .by_index_raw(index)
+            .map_err(|e| builtin_error("unzip", e))?
+            .encrypted();
+        let mut member = if encrypted {
+            zip.by_index_decrypt(index, password)
+        } else {
+            zip.by_index(index)
+        }
+        .map_err(|e| builtin_error("unzip", e))?;
+
+        let relative = match member.enclosed_name() {
+            Some(relative) if is_inside_without_symlinks(dest_dir, &relative) => relative,
+            _ => {
+                warn!(
+                    "Skipping {} in {}: it would be extracted outside {}",
+                    member.name(),
+                    archive.display(),
+                    dest_dir.display()
+                );
+                continue;
+            }
+        };
+        let path = dest_dir.join(&relative);
+        if member.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if member.is_symlink() {
+            let mut target = String::new();
+            member.read_to_string(&mut target)?;
+            create_symlink(&target, &path)?;
+            continue;
+        }
+
+        // Replace rather than write through a link already in place
+        if fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+            fs::remove_file(&path)?;
+        }
+        let mut output = CrcWriter::new(buffered_writer(File::create(&path)?));
+        match io::copy(&mut member, &mut output) {
+            Ok(_) => {}
+            // The crate refuses a member failing its CRC32 once read to its
+            // end; the CRC32 of what was written tells that apart from other
+            // invalid data
+            Err(e)
+                if e.kind() == io::ErrorKind::InvalidData
+                    && output.crc().sum() != member.crc32() =>
+            {
+                failed.push(member.name().to_string());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        output.flush()?;
+        if let Some(time) = member.last_modified().and_then(local_time) {
+            let _ = output.get_ref().get_ref().set_modified(time);
+        }
+        drop(output);
+        if let Some(mode) = member.unix_mode() {
+            set_permissions(&path, mode);
+        }
+    }
+    crc_mismatches(archive, &failed, config.no_verify)
+}
+
+/// `time`, a DOS timestamp in local time, as a `SystemTime`
+fn local_time(time: zip::DateTime) -> Option<SystemTime> {
+    let naive = chrono::NaiveDate::from_ymd_opt(
+        time.year().into(),
+        time.month().into(),
+        time.day().into(),
+    )?
+    .and_hms_opt(
+        time.hour().into(),
+        time.minute().into(),
+        time.second().into(),
+    )?;
+    let local = chrono::Local.from_local_datetime(&naive).earliest()?;
+    Some(local.into())
+}
+
+/// Error for a failed built-in zip or unzip run of `tool`
+fn builtin_error(tool: &str, err: ZipError) -> JcError {
+    match err {
+        ZipError::Io(e) => e.into(),
+        ZipError::InvalidPassword => CryptoError::AuthenticationFailed.into(),
+        e => {
+            let stderr = e.to_string();
+            if tool == "zip" {
+                JcError::CompressionFailed {
+                    tool: tool.to_string(),
+                    stderr,
+                }
+            } else {
+                JcError::DecompressionFailed {
+                    tool: tool.to_string(),
+                    stderr,
                 }
             }
         }
+    }
+}
+
+/// Error for a failed unzip or bsdtar run: an integrity failure naming
+/// each member that failed its CRC32 check, as listed by `failures`, if
+/// the tool reported any
+///
+/// With `no_verify`, a run whose only errors were CRC32 mismatches
+/// succeeds, with a warning for each member, which is extracted as is.
+fn check_crcs(
+    archive: &Path,
+    tool: &str,
+    output: &Output,
+    no_verify: bool,
+    failures: impl FnOnce() -> JcResult<Vec<String>>,
+) -> JcResult<()> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let failed = if stderr.contains("bad CRC") {
+        failures()?
+    } else {
+        Vec::new()
+    };
+    if failed.is_empty() {
+        return Err(JcError::DecompressionFailed {
+            tool: tool.to_string(),
+            stderr: stderr.to_string(),
+        });
+    }
+
+    let only_crcs = stderr.lines().all(|line| {
+        line.trim().is_empty() || line.contains("bad CRC") || line.contains("Error exit delayed")
+    });
+    crc_mismatches(archive, &failed, no_verify && only_crcs)
+}
 
-        // Multiple files extracted - return the working directory
-        if !entries.is_empty() {
-            debug!(
-                "Extracted {} files to: {}",
-                entries.len(),
-                working_dir.display()
+/// Integrity failure naming the members of `archive` in `failed`, which
+/// did not match their CRC32, or with `keep` a warning for each, which
+/// is kept as extracted
+fn crc_mismatches(archive: &Path, failed: &[String], keep: bool) -> JcResult<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    if keep {
+        let name = archive.file_name().unwrap_or(archive.as_os_str());
+        for member in failed {
+            warn!(
+                "{} in {} does not match its CRC32; kept as extracted (--no-verify)",
+                member,
+                name.to_string_lossy()
             );
-            return Ok(working_dir.to_path_buf());
         }
+        return Ok(());
+    }
+    Err(JcError::IntegrityFailed(format!(
+        "CRC32 mismatch in {} member{}: {}",
+        failed.len(),
+        if failed.len() == 1 { "" } else { "s" },
+        failed.join(", ")
+    )))
+}
 
-        // Fallback: assume filename without .zip extension
-        let output_path = work_input.with_extension("");
-        debug!("Extracted to (fallback): {}", output_path.display());
-        Ok(output_path)
+/// Members that `unzip -tq` (`NAME  bad CRC ...`) or bsdtar
+/// (`NAME: ZIP bad CRC: ...`) reported failing their CRC32 check
+fn crc_failures(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, _) = line
+                .split_once(": ZIP bad CRC")
+                .or_else(|| line.split_once(" bad CRC "))?;
+            Some(name.trim_end().to_string())
+        })
+        .collect()
+}
+
+/// Whether zipping `input` needs Zip64 records: over 65535 entries, or
+/// enough data that a size or offset may not fit in 32 bits
+///
+/// Stops walking as soon as either limit is crossed.
+fn needs_zip64(input: &Path, dereference: bool) -> JcResult<bool> {
+    let mut entries = 0u64;
+    let mut total = 0u64;
+    let mut pending = vec![input.to_path_buf()];
+
+    while let Some(path) = pending.pop() {
+        let mut metadata = fs::symlink_metadata(&path)?;
+        if dereference && metadata.file_type().is_symlink() {
+            metadata = fs::metadata(&path).unwrap_or(metadata);
+        }
+
+        entries += 1;
+        if metadata.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                pending.push(entry?.path());
+            }
+        } else if metadata.is_file() {
+            total += metadata.len();
+        }
+
+        if entries > ZIP32_MAX_ENTRIES || total > ZIP32_MAX_SIZE {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Whether the zip at `path` carries Zip64 records
+///
+/// Archives past 4 GB always do; smaller ones are recognized by the Zip64
+/// locator in front of the end of central directory record.
+fn is_zip64_archive(path: &Path) -> JcResult<bool> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() > ZIP32_MAX_SIZE {
+        return Ok(true);
+    }
+
+    Ok(has_zip64_locator(&read_tail(&mut file)?))
+}
+
+/// Whether any entry of the zip at `path` is WinZip AES encrypted
+fn has_aes_entries(path: &Path) -> JcResult<bool> {
+    let Some((_, directory)) = read_central_directory(path)? else {
+        return Ok(false);
+    };
+    Ok(central_entries(&directory)
+        .iter()
+        .any(|&(_, entry)| le_u16(entry, 10) == AES_METHOD))
+}
+
+/// Total uncompressed size of the entries of the zip at `path`, from its
+/// central directory; `None` when the directory cannot be read
+pub fn uncompressed_size(path: &Path) -> JcResult<Option<u64>> {
+    let Some((_, directory)) = read_central_directory(path)? else {
+        return Ok(None);
+    };
+    Ok(central_entries(&directory)
+        .iter()
+        .map(|&(_, entry)| entry_uncompressed_size(entry))
+        .sum())
+}
+
+/// Set the UTF-8 flag on entries whose names are non-ASCII UTF-8
+///
+/// Info-ZIP on Unix stores names as raw bytes and never sets the flag, so
+/// Windows tools decode them with a legacy codepage. Only the flag fields
+/// of the local and central headers change.
+fn flag_utf8_names(archive: &Path) -> JcResult<()> {
+    let Some((directory_offset, directory)) = read_central_directory(archive)? else {
+        return Ok(());
+    };
+    let mut file = OpenOptions::new().read(true).write(true).open(archive)?;
+
+    let mut flagged = 0;
+    for (pos, entry) in central_entries(&directory) {
+        let flags = le_u16(entry, 8);
+        let name = &entry[CD_HEADER_LEN..CD_HEADER_LEN + le_u16(entry, 28) as usize];
+        if flags & UTF8_FLAG != 0 || name.is_ascii() || std::str::from_utf8(name).is_err() {
+            continue;
+        }
+        let Some(local) = local_header_offset(entry) else {
+            continue;
+        };
+
+        let mut local_header = [0u8; 8];
+        file.seek(SeekFrom::Start(local))?;
+        file.read_exact(&mut local_header)?;
+        if !local_header.starts_with(LOCAL_SIGNATURE) {
+            continue;
+        }
+
+        let local_flags = le_u16(&local_header, 6) | UTF8_FLAG;
+        file.seek(SeekFrom::Start(local + 6))?;
+        file.write_all(&local_flags.to_le_bytes())?;
+        file.seek(SeekFrom::Start(directory_offset + pos as u64 + 8))?;
+        file.write_all(&(flags | UTF8_FLAG).to_le_bytes())?;
+        flagged += 1;
+    }
+
+    if flagged > 0 {
+        debug!("Flagged {} UTF-8 names in {}", flagged, archive.display());
+    }
+    Ok(())
+}
+
+/// Central directory file headers in `directory`, each with its offset and
+/// its variable-length name, extra field and comment
+fn central_entries(directory: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while let Some(header) = directory.get(pos..pos + CD_HEADER_LEN) {
+        if !header.starts_with(CD_SIGNATURE) {
+            break;
+        }
+        let len = CD_HEADER_LEN
+            + le_u16(header, 28) as usize
+            + le_u16(header, 30) as usize
+            + le_u16(header, 32) as usize;
+        let Some(entry) = directory.get(pos..pos + len) else {
+            break;
+        };
+        entries.push((pos, entry));
+        pos += len;
+    }
+    entries
+}
+
+/// Local header offset of a central directory entry, following the Zip64
+/// extra field when the 32-bit field overflowed
+fn local_header_offset(entry: &[u8]) -> Option<u64> {
+    let offset = le_u32(entry, 42);
+    if offset != u32::MAX {
+        return Some(offset as u64);
+    }
+
+    let name_len = le_u16(entry, 28) as usize;
+    let extra = entry
+        .get(CD_HEADER_LEN + name_len..CD_HEADER_LEN + name_len + le_u16(entry, 30) as usize)?;
+
+    // The Zip64 field holds only the overflowed values: uncompressed size,
+    // compressed size, then the offset
+    let mut pos = 0;
+    while let Some(field) = extra.get(pos..pos + 4) {
+        let len = le_u16(field, 2) as usize;
+        if le_u16(field, 0) == ZIP64_EXTRA_ID {
+            let skip = [le_u32(entry, 24), le_u32(entry, 20)]
+                .iter()
+                .filter(|&&size| size == u32::MAX)
+                .count()
+                * 8;
+            return extra
+                .get(pos + 4 + skip..pos + 12 + skip)
+                .map(|b| le_u64(b, 0));
+        }
+        pos += 4 + len;
+    }
+    None
+}
+
+/// Uncompressed size of a central directory entry, from the Zip64 extra
+/// field when the 32-bit field overflowed
+fn entry_uncompressed_size(entry: &[u8]) -> Option<u64> {
+    let size = le_u32(entry, 24);
+    if size != u32::MAX {
+        return Some(size as u64);
+    }
+
+    let name_len = le_u16(entry, 28) as usize;
+    let extra = entry
+        .get(CD_HEADER_LEN + name_len..CD_HEADER_LEN + name_len + le_u16(entry, 30) as usize)?;
+    let mut pos = 0;
+    while let Some(field) = extra.get(pos..pos + 4) {
+        if le_u16(field, 0) == ZIP64_EXTRA_ID {
+            return extra.get(pos + 4..pos + 12).map(|b| le_u64(b, 0));
+        }
+        pos += 4 + le_u16(field, 2) as usize;
+    }
+    None
+}
+
+/// Offset and raw bytes of the central directory of the zip at `path`, if
+/// its end records can be found
+fn read_central_directory(path: &Path) -> JcResult<Option<(u64, Vec<u8>)>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let tail = read_tail(&mut file)?;
+    let Some(eocd) = find_eocd(&tail) else {
+        return Ok(None);
+    };
+    let Some(record) = tail.get(eocd..eocd + EOCD_LEN as usize) else {
+        return Ok(None);
+    };
+
+    let (mut size, mut offset) = (le_u32(record, 12) as u64, le_u32(record, 16) as u64);
+    if has_zip64_locator(&tail) {
+        // Real values live in the Zip64 EOCD record the locator points to
+        let locator = &tail[eocd - ZIP64_LOCATOR_LEN..eocd];
+        let mut record = [0u8; ZIP64_EOCD_LEN];
+        file.seek(SeekFrom::Start(le_u64(locator, 8)))?;
+        file.read_exact(&mut record)?;
+        if !record.starts_with(ZIP64_EOCD_SIGNATURE) {
+            return Ok(None);
+        }
+        size = le_u64(&record, 40);
+        offset = le_u64(&record, 48);
+    }
+
+    if offset.checked_add(size).is_none_or(|end| end > len) {
+        return Ok(None);
+    }
+    let mut directory = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.take(size).read_to_end(&mut directory)?;
+    Ok(Some((offset, directory)))
+}
+
+/// The last bytes of `file`, enough to hold the end records
+fn read_tail(file: &mut File) -> JcResult<Vec<u8>> {
+    // The EOCD is followed by a comment of at most 64 KiB
+    let len = file.metadata()?.len();
+    let tail_len = len.min(EOCD_LEN + 0xFFFF + ZIP64_LOCATOR_LEN as u64);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::with_capacity(tail_len as usize);
+    file.read_to_end(&mut tail)?;
+    Ok(tail)
+}
+
+/// Offset of the EOCD record in `tail`
+///
+/// The archive comment may itself contain the signature, so the record
+/// whose comment ends the file is preferred over the last signature.
+fn find_eocd(tail: &[u8]) -> Option<usize> {
+    let mut candidates = tail
+        .windows(EOCD_SIGNATURE.len())
+        .enumerate()
+        .rev()
+        .filter(|(_, w)| *w == EOCD_SIGNATURE)
+        .map(|(pos, _)| pos);
+    let last = candidates.next()?;
+    std::iter::once(last)
+        .chain(candidates)
+        .find(|&pos| comment_range(tail, pos).is_some_and(|range| range.end == tail.len()))
+        .or(Some(last))
+}
+
+/// Where the comment of the EOCD record at `eocd` in `tail` lies
+fn comment_range(tail: &[u8], eocd: usize) -> Option<std::ops::Range<usize>> {
+    let start = eocd + EOCD_LEN as usize;
+    let len = le_u16(tail.get(eocd..start)?, 20) as usize;
+    Some(start..start + len)
+}
+
+/// Archive comment of the zip at `path`, if it has one
+pub fn read_comment(path: &Path) -> JcResult<Option<String>> {
+    let tail = read_tail(&mut File::open(path)?)?;
+    let comment = find_eocd(&tail)
+        .and_then(|eocd| comment_range(&tail, eocd))
+        .and_then(|range| tail.get(range))
+        .filter(|comment| !comment.is_empty());
+    Ok(comment.map(|comment| String::from_utf8_lossy(comment).into_owned()))
+}
+
+/// Replace the archive comment of the zip at `archive` with `comment`
+fn write_comment(archive: &Path, comment: &str) -> JcResult<()> {
+    if comment.len() > MAX_COMMENT_LEN {
+        return Err(JcError::Other(format!(
+            "Zip comments are limited to {} bytes",
+            MAX_COMMENT_LEN
+        )));
+    }
+    let mut file = OpenOptions::new().read(true).write(true).open(archive)?;
+    let tail = read_tail(&mut file)?;
+    let Some(eocd) = find_eocd(&tail) else {
+        return Err(JcError::Other(format!(
+            "{}: end of central directory not found",
+            archive.display()
+        )));
+    };
+
+    // The comment length is the last field of the record, right before it
+    let record_end = file.metadata()?.len() - (tail.len() - eocd) as u64 + EOCD_LEN;
+    file.set_len(record_end)?;
+    file.seek(SeekFrom::Start(record_end - 2))?;
+    file.write_all(&(comment.len() as u16).to_le_bytes())?;
+    file.write_all(comment.as_bytes())?;
+    debug!("Set the comment of {}", archive.display());
+    Ok(())
+}
+
+/// Look for a Zip64 locator in front of the last EOCD record in `tail`
+fn has_zip64_locator(tail: &[u8]) -> bool {
+    find_eocd(tail)
+        .and_then(|eocd| eocd.checked_sub(ZIP64_LOCATOR_LEN))
+        .is_some_and(|start| tail[start..].starts_with(ZIP64_LOCATOR_SIGNATURE))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// Fail unless `tool` (zip or unzip) was built with Zip64 support
+///
+/// Builds without it either refuse such archives or, worse, write ones
+/// with truncated sizes and offsets. If `tool -v` cannot be run, the
+/// real invocation reports that instead.
+fn require_zip64_support(tool: &str, path: &Path) -> JcResult<()> {
+    let supported = Command::new(tool)
+        .arg("-v")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).contains("ZIP64_SUPPORT"))
+        .unwrap_or(true);
+
+    if supported {
+        Ok(())
+    } else {
+        Err(JcError::Other(format!(
+            "{} needs Zip64 (over 4 GB or 65535 entries), but {} was built without ZIP64_SUPPORT",
+            path.display(),
+            tool
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_builtin_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("tree");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), b"plain text ".repeat(100)).unwrap();
+        fs::write(src.join("sub").join("b.gz"), b"already compressed").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", src.join("link")).unwrap();
+
+        let archive = temp_dir.path().join("tree.zip");
+        let config = CompressionConfig::default();
+        let names = [OsStr::new("tree")];
+        write_builtin(
+            &archive,
+            temp_dir.path(),
+            &names,
+            &["gz".to_string()],
+            &[],
+            &config,
+        )
+        .unwrap();
+
+        let mut zip = ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        let method =
+            |zip: &mut ZipArchive<File>, name: &str| zip.by_name(name).unwrap().compression();
+        assert_eq!(method(&mut zip, "tree/a.txt"), CompressionMethod::Deflated);
+        assert_eq!(method(&mut zip, "tree/sub/b.gz"), CompressionMethod::Stored);
+        let entries = list_entries(&archive).unwrap();
+        assert!(entries
+            .iter()
+            .any(|e| e.path == Path::new("tree/sub/") && e.kind == EntryKind::Directory));
+
+        let dest = temp_dir.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        extract_builtin(&archive, &dest, &config).unwrap();
+        assert_eq!(
+            fs::read(dest.join("tree/a.txt")).unwrap(),
+            b"plain text ".repeat(100)
+        );
+        assert_eq!(
+            fs::read(dest.join("tree/sub/b.gz")).unwrap(),
+            b"already compressed"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(dest.join("tree/link")).unwrap(),
+            Path::new("a.txt")
+        );
+
+        // Readable by Info-ZIP, which in turn writes zips it can read
+        if installed("unzip") {
+            let test = Command::new("unzip")
+                .arg("-tq")
+                .arg(&archive)
+                .output()
+                .unwrap();
+            assert!(test.status.success());
+        }
+        if installed("zip") {
+            let info_zip = temp_dir.path().join("info.zip");
+            let status = zip_command(&info_zip, &names, true, &[], &[], &config)
+                .current_dir(temp_dir.path())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let dest = temp_dir.path().join("info");
+            fs::create_dir(&dest).unwrap();
+            extract_builtin(&info_zip, &dest, &config).unwrap();
+            assert_eq!(
+                fs::read(dest.join("tree/sub/b.gz")).unwrap(),
+                b"already compressed"
+            );
+        }
+    }
+
+    #[test]
+    fn test_builtin_aes_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("secret.txt"), b"secret data").unwrap();
+        let archive = temp_dir.path().join("secret.zip");
+        let config = CompressionConfig::default()
+            .with_zip_password(Some(ZipPassword::new("hunter2".to_string())));
+        let names = [OsStr::new("secret.txt")];
+        write_builtin(&archive, temp_dir.path(), &names, &[], &[], &config).unwrap();
+        assert!(has_aes_entries(&archive).unwrap());
+
+        let dest = temp_dir.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        let wrong = CompressionConfig::default()
+            .with_zip_password(Some(ZipPassword::new("wrong".to_string())));
+        let err = extract_builtin(&archive, &dest, &wrong).unwrap_err();
+        assert!(matches!(
+            err,
+            JcError::Crypto(CryptoError::AuthenticationFailed)
+        ));
+
+        extract_builtin(&archive, &dest, &config).unwrap();
+        assert_eq!(fs::read(dest.join("secret.txt")).unwrap(), b"secret data");
+    }
+
+    #[test]
+    fn test_builtin_crc_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("data.txt"), b"original contents").unwrap();
+        let archive = temp_dir.path().join("data.zip");
+        let config = CompressionConfig::default().with_level(0);
+        write_builtin(
+            &archive,
+            temp_dir.path(),
+            &[OsStr::new("data.txt")],
+            &[],
+            &[],
+            &config,
+        )
+        .unwrap();
+
+        // Stored, so the contents can be changed in place
+        let mut bytes = fs::read(&archive).unwrap();
+        let at = bytes.windows(8).position(|w| w == b"original").unwrap();
+        bytes[at] = b'O';
+        fs::write(&archive, bytes).unwrap();
+
+        let dest = temp_dir.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        let err = extract_builtin(&archive, &dest, &config).unwrap_err();
+        assert!(matches!(err, JcError::IntegrityFailed(ref m) if m.contains("data.txt")));
+
+        extract_builtin(&archive, &dest, &config.with_no_verify(true)).unwrap();
+        assert_eq!(
+            fs::read(dest.join("data.txt")).unwrap(),
+            b"Original contents"
+        );
+    }
+
+    #[test]
+    fn test_has_zip64_locator() {
+        let mut eocd = EOCD_SIGNATURE.to_vec();
+        eocd.resize(EOCD_LEN as usize, 0);
+        let mut locator = ZIP64_LOCATOR_SIGNATURE.to_vec();
+        locator.resize(ZIP64_LOCATOR_LEN, 0);
+
+        let plain = [b"central directory".to_vec(), eocd.clone()].concat();
+        assert!(!has_zip64_locator(&plain));
+
+        let zip64 = [b"central directory".to_vec(), locator, eocd].concat();
+        assert!(has_zip64_locator(&zip64));
+
+        assert!(!has_zip64_locator(b"not a zip"));
+    }
+
+    #[test]
+    fn test_comment_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("empty.zip");
+        let mut eocd = EOCD_SIGNATURE.to_vec();
+        eocd.resize(EOCD_LEN as usize, 0);
+        fs::write(&archive, &eocd).unwrap();
+        assert_eq!(read_comment(&archive).unwrap(), None);
+
+        // A signature inside the comment is not taken for the record
+        let tricky = "build 42 PK\x05\x06 and more";
+        write_comment(&archive, tricky).unwrap();
+        assert_eq!(read_comment(&archive).unwrap().as_deref(), Some(tricky));
+        assert_eq!(
+            fs::metadata(&archive).unwrap().len(),
+            EOCD_LEN + tricky.len() as u64
+        );
+
+        write_comment(&archive, "short").unwrap();
+        assert_eq!(read_comment(&archive).unwrap().as_deref(), Some("short"));
+        assert_eq!(fs::read(&archive).unwrap().len(), EOCD_LEN as usize + 5);
+
+        assert!(write_comment(&archive, &"x".repeat(MAX_COMMENT_LEN + 1)).is_err());
+    }
+
+    /// Central directory file header for `name` with the given method
+    fn cd_header(name: &[u8], method: u16) -> Vec<u8> {
+        let mut header = CD_SIGNATURE.to_vec();
+        header.resize(CD_HEADER_LEN, 0);
+        header[10..12].copy_from_slice(&method.to_le_bytes());
+        header[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(name);
+        header
+    }
+
+    #[test]
+    fn test_central_entries() {
+        let directory = [cd_header(b"dir/", 0), cd_header(b"dir/a.txt", AES_METHOD)].concat();
+        let entries = central_entries(&directory);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].0, CD_HEADER_LEN + 4);
+        assert_eq!(le_u16(entries[1].1, 10), AES_METHOD);
+
+        assert!(central_entries(b"PK\x01\x02 truncated").is_empty());
+    }
+
+    #[test]
+    fn test_local_header_offset_from_zip64_extra() {
+        let mut entry = cd_header(b"big.bin", 8);
+        assert_eq!(local_header_offset(&entry), Some(0));
+
+        // Both sizes and the offset overflowed
+        entry[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+        entry[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        entry[42..46].copy_from_slice(&u32::MAX.to_le_bytes());
+        entry[30..32].copy_from_slice(&28u16.to_le_bytes());
+        entry.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        entry.extend_from_slice(&24u16.to_le_bytes());
+        entry.extend_from_slice(&(5u64 << 32).to_le_bytes());
+        entry.extend_from_slice(&(4u64 << 32).to_le_bytes());
+        entry.extend_from_slice(&(6u64 << 32).to_le_bytes());
+        assert_eq!(local_header_offset(&entry), Some(6 << 32));
+    }
+
+    #[test]
+    fn test_needs_zip64_by_entry_count_and_size() {
+        let dir = TempDir::new().unwrap();
+        let small = dir.path().join("small");
+        fs::create_dir(&small).unwrap();
+        fs::write(small.join("a.txt"), b"a").unwrap();
+        assert!(!needs_zip64(&small, false).unwrap());
+
+        // Sparse, so nothing is actually written
+        let big = dir.path().join("big.bin");
+        File::create(&big)
+            .unwrap()
+            .set_len(ZIP32_MAX_SIZE + 1)
+            .unwrap();
+        assert!(needs_zip64(&big, false).unwrap());
+
+        let many = dir.path().join("many");
+        fs::create_dir(&many).unwrap();
+        for i in 0..ZIP32_MAX_ENTRIES {
+            File::create(many.join(i.to_string())).unwrap();
+        }
+        assert!(needs_zip64(&many, false).unwrap());
+    }
+
+    #[test]
+    fn test_split_members() {
+        let members = |sizes: &[u64]| -> Vec<(OsString, u64)> {
+            sizes
+                .iter()
+                .enumerate()
+                .map(|(i, &size)| (OsString::from(format!("f{}", i)), size))
+                .collect()
+        };
+        let mb = 1 << 20;
+
+        // Small archives and single members stay in one run
+        assert_eq!(split_members(members(&[1000, 1000]), 4), None);
+        assert_eq!(split_members(members(&[8 * mb]), 4), None);
+
+        let groups = split_members(members(&[mb, mb, mb, mb, mb, mb]), 3).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0], ["f0", "f1"]);
+        assert_eq!(groups[2], ["f4", "f5"]);
+    }
+
+    #[test]
+    fn test_escape_pattern() {
+        assert_eq!(
+            escape_pattern(Path::new("d/a[1]*?.txt")),
+            OsString::from("d/a\\[1]\\*\\?.txt")
+        );
+    }
+
+    #[test]
+    fn test_crc_failures() {
+        let unzip = "a.txt                   bad CRC b739e0d5  (should be af083b2d)\n\
+            dir/c d.txt  bad CRC 00000000  (should be 12345678)\n\
+            At least one error was detected in bad.zip.\n";
+        assert_eq!(crc_failures(unzip), ["a.txt", "dir/c d.txt"]);
+
+        let bsdtar = "a.txt: ZIP bad CRC: 0xb739e0d5 should be 0xaf083b2d: Unknown error -1\n\
+            bsdtar: Error exit delayed from previous errors.\n";
+        assert_eq!(crc_failures(bsdtar), ["a.txt"]);
+    }
+
+    #[test]
+    fn test_is_plain_member_name() {
+        assert!(is_plain_member_name(b"dir/file.txt"));
+        assert!(is_plain_member_name(b"dir/"));
+        assert!(!is_plain_member_name(b"file[1].txt"));
+        assert!(!is_plain_member_name(b"*.txt"));
+        assert!(!is_plain_member_name(b"../escape"));
+        assert!(!is_plain_member_name(b"/etc/passwd"));
     }
 }
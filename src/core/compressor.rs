@@ -1,7 +1,13 @@
+use std::io::{Read, Seek, Write};
 use std::path::{Path, PathBuf};
 
 use crate::core::config::CompressionConfig;
-use crate::core::error::JcResult;
+use crate::core::error::{JcError, JcResult};
+
+/// A reader that can also seek, such as an open file
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
 
 /// Common interface for all compression/decompression implementations
 #[allow(dead_code)]
@@ -18,6 +24,55 @@ pub trait Compressor: Send + Sync {
     /// Decompress a single file
     fn decompress(&self, input: &Path, config: &CompressionConfig) -> JcResult<PathBuf>;
 
+    /// Whether `compress_stream` and `decompress_stream` work; archive
+    /// formats, which hold file trees, have no stream form
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+
+    /// Compress everything read from `input` into `output`
+    ///
+    /// Stream formats build their file-based methods on this. Library
+    /// users can compress in memory or between pipes, and chain layers
+    /// without temp files.
+    fn compress_stream(
+        &self,
+        _input: &mut (dyn Read + Send),
+        _output: &mut dyn Write,
+        _config: &CompressionConfig,
+    ) -> JcResult<()> {
+        Err(JcError::Other(format!(
+            "{} does not support streaming",
+            self.name()
+        )))
+    }
+
+    /// Decompress everything read from `input` into `output`
+    fn decompress_stream(
+        &self,
+        _input: &mut (dyn Read + Send),
+        _output: &mut dyn Write,
+        _config: &CompressionConfig,
+    ) -> JcResult<()> {
+        Err(JcError::Other(format!(
+            "{} does not support streaming",
+            self.name()
+        )))
+    }
+
+    /// Decompress all of `input`, a file rather than a pipe, into `output`
+    ///
+    /// Formats that can find their independent blocks by seeking override
+    /// this to decode them in parallel; the default is `decompress_stream`.
+    fn decompress_seekable(
+        &self,
+        input: &mut (dyn ReadSeek + Send),
+        output: &mut dyn Write,
+        config: &CompressionConfig,
+    ) -> JcResult<()> {
+        self.decompress_stream(input, output, config)
+    }
+
     /// Check if this compressor supports compression levels
     fn supports_levels(&self) -> bool;
 
@@ -26,6 +81,16 @@ pub trait Compressor: Send + Sync {
 
     /// Get default compression level
     fn default_level(&self) -> u8;
+
+    /// Fastest compression level (`-l fast`)
+    fn fastest_level(&self) -> u8 {
+        1
+    }
+
+    /// Strongest compression level (`-l best`)
+    fn best_level(&self) -> u8 {
+        9
+    }
 }
 
 /// Extended trait for compressors that support multi-file operations
@@ -1,12 +1,34 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use zeroize::Zeroizing;
+
+use crate::compressors::CommandRegistry;
+use crate::core::compressor::Compressor;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::{ArchiveEntry, CompressionFormat};
+use crate::utils::{
+    info, run_unless_failed, run_watched, tool_threads, BackupMode, CancellationToken, FailFast,
+    FileFilter, PathTransform, ProgressSink, RateLimit, RetryPolicy, Watched,
+};
 
 /// Timestamp formatting options
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TimestampOption {
     None,        // 0: No timestamp
     Date,        // 1: YYYYMMDD
     DateTime,    // 2: YYYYMMDD_HHMMSS
     Nanoseconds, // 3: Nanoseconds only
+    /// strftime pattern (`--timestamp-format`), in local time unless `utc`
+    Format {
+        pattern: String,
+        utc: bool,
+    },
 }
 
 impl TimestampOption {
@@ -19,6 +41,126 @@ impl TimestampOption {
             _ => None,
         }
     }
+
+    /// The same layout in UTC instead of local time
+    ///
+    /// Presets become their equivalent pattern; nanoseconds and no
+    /// timestamp do not depend on the time zone and are unchanged.
+    pub fn in_utc(self) -> Self {
+        let pattern = match self {
+            TimestampOption::Date => "%Y%m%d".to_string(),
+            TimestampOption::DateTime => "%Y%m%d_%H%M%S".to_string(),
+            TimestampOption::Format { pattern, .. } => pattern,
+            other => return other,
+        };
+        TimestampOption::Format { pattern, utc: true }
+    }
+
+    /// Whether the timestamp is formatted in UTC
+    pub fn is_utc(&self) -> bool {
+        matches!(self, TimestampOption::Format { utc: true, .. })
+    }
+}
+
+/// Named compression level, resolved by each compressor (`-l fast|default|best`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelPreset {
+    Fast,
+    Default,
+    Best,
+}
+
+impl LevelPreset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "fast" => Some(LevelPreset::Fast),
+            "default" => Some(LevelPreset::Default),
+            "best" => Some(LevelPreset::Best),
+            _ => None,
+        }
+    }
+}
+
+/// xz encoder settings beyond the level preset (`--extreme`, `--lzma2`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XzTuning {
+    /// Extreme mode: slower, for a little more compression at any level
+    pub extreme: bool,
+
+    /// LZMA2 dictionary size in bytes
+    pub dict_size: Option<u32>,
+
+    /// Literal context bits
+    pub lc: Option<u32>,
+
+    /// Literal position bits
+    pub lp: Option<u32>,
+
+    /// Position bits
+    pub pb: Option<u32>,
+}
+
+impl XzTuning {
+    /// Whether any LZMA2 option overrides the preset's
+    pub fn has_lzma2(&self) -> bool {
+        self.dict_size.is_some() || self.lc.is_some() || self.lp.is_some() || self.pb.is_some()
+    }
+}
+
+/// gzip encoder settings (`--rsyncable`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GzipTuning {
+    /// Restart compression at content-defined points, so a local change
+    /// only changes the output nearby and rsync can skip the rest
+    pub rsyncable: bool,
+}
+
+/// Settings for a compressor registered in `compressors.toml`, applied
+/// through the arguments its entry declares for them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandTuning {
+    pub rsyncable: bool,
+}
+
+/// Settings only one format understands, kept in
+/// `CompressionConfig::format_options` under its compressor's name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatOptions {
+    Xz(XzTuning),
+    Gzip(GzipTuning),
+    Command(CommandTuning),
+}
+
+/// How aggressively extracted outputs are flushed to stable storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Never fsync; rely on the OS to write data back eventually
+    Lazy,
+    /// Fsync each top-level output and its parent directory
+    #[default]
+    Default,
+    /// Fsync every extracted file and directory, then the parent directory
+    Full,
+}
+
+impl Durability {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "lazy" => Some(Durability::Lazy),
+            "default" => Some(Durability::Default),
+            "full" => Some(Durability::Full),
+            _ => None,
+        }
+    }
+}
+
+/// Directory each archive is extracted into (`--extract-dir`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtractDir {
+    /// Named after the archive, less its extensions
+    FromArchive,
+    /// The given name
+    Named(String),
 }
 
 /// Encryption method for compression
@@ -28,22 +170,175 @@ pub enum EncryptionMethod {
     Password,
     /// RSA encryption with public key file path
     Rsa { public_key_path: PathBuf },
+    /// Random key split into `shares` share files, any `threshold` of
+    /// which decrypt
+    Threshold { threshold: u8, shares: u8 },
+    /// Encryption with the 32-byte key in a keyfile
+    Keyfile { keyfile_path: PathBuf },
+    /// X25519 encryption with public key file path
+    X25519 { public_key_path: PathBuf },
+    /// OpenPGP encryption by `gpg` to these key IDs, written as `.gpg`
+    Gpg { recipients: Vec<String> },
+}
+
+/// Password for WinZip AES-256 zip encryption
+///
+/// Wiped from memory on drop and never printed, so configs can still be
+/// logged with `{:?}`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ZipPassword(Zeroizing<String>);
+
+impl ZipPassword {
+    pub fn new(password: String) -> Self {
+        Self(Zeroizing::new(password))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ZipPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ZipPassword(***)")
+    }
+}
+
+/// What a password is requested for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretPurpose {
+    Encrypt,
+    Decrypt,
+}
+
+/// Supplies passwords for password encryption and decryption
+///
+/// The library never prompts by itself; the CLI asks on the terminal, while
+/// a GUI or server can answer from a dialog or a secret store. Closures
+/// taking a `SecretPurpose` implement it.
+pub trait SecretProvider: Send + Sync {
+    fn password(&self, purpose: SecretPurpose) -> JcResult<Zeroizing<String>>;
+}
+
+impl<F> SecretProvider for F
+where
+    F: Fn(SecretPurpose) -> JcResult<Zeroizing<String>> + Send + Sync,
+{
+    fn password(&self, purpose: SecretPurpose) -> JcResult<Zeroizing<String>> {
+        self(purpose)
+    }
+}
+
+/// Shared handle to a `SecretProvider`, as stored in configs
+#[derive(Clone)]
+pub struct Secrets(Arc<dyn SecretProvider>);
+
+impl Secrets {
+    pub fn new(provider: impl SecretProvider + 'static) -> Self {
+        Self(Arc::new(provider))
+    }
+
+    /// Ask the provider for a password, refusing empty ones
+    pub fn password(&self, purpose: SecretPurpose) -> JcResult<Zeroizing<String>> {
+        let password = self.0.password(purpose)?;
+        if password.is_empty() {
+            return Err(JcError::Other("Password cannot be empty".to_string()));
+        }
+        Ok(password)
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secrets(..)")
+    }
+}
+
+/// Told about each archive member as it is added or extracted (`-v`)
+///
+/// Clones share the callback. Members of inputs processed in parallel
+/// interleave, and calls may come from several threads at once.
+#[derive(Clone)]
+pub struct EntryListener(Arc<dyn Fn(&ArchiveEntry) + Send + Sync>);
+
+impl EntryListener {
+    pub fn new(listener: impl Fn(&ArchiveEntry) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(listener))
+    }
+
+    pub fn entry(&self, entry: &ArchiveEntry) {
+        (self.0)(entry)
+    }
+}
+
+impl fmt::Debug for EntryListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EntryListener(..)")
+    }
+}
+
+/// Callback deciding whether an existing path may be replaced
+pub type OverwriteCallback = Arc<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// What extraction does about outputs that already exist, unless `force`
+/// is set
+#[derive(Clone, Default)]
+pub enum OverwritePolicy {
+    /// Keep the existing path; its input fails, or the entry is skipped
+    #[default]
+    Keep,
+
+    /// Replace the existing path
+    Overwrite,
+
+    /// Let the callback decide for each path (the CLI asks the user)
+    Ask(OverwriteCallback),
+}
+
+impl OverwritePolicy {
+    /// Whether `path`, which exists, may be replaced
+    pub fn allows(&self, path: &Path) -> bool {
+        match self {
+            OverwritePolicy::Keep => false,
+            OverwritePolicy::Overwrite => true,
+            OverwritePolicy::Ask(ask) => ask(path),
+        }
+    }
+}
+
+impl fmt::Debug for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverwritePolicy::Keep => f.write_str("Keep"),
+            OverwritePolicy::Overwrite => f.write_str("Overwrite"),
+            OverwritePolicy::Ask(_) => f.write_str("Ask(..)"),
+        }
+    }
 }
 
 /// Configuration for compression/decompression operations
 #[derive(Debug, Clone)]
 pub struct CompressionConfig {
-    /// Compression level (0-9, meaning varies by algorithm)
+    /// Compression level (range and meaning vary by algorithm)
     pub level: u8,
 
+    /// Named level that replaces `level` with each compressor's own value
+    pub level_preset: Option<LevelPreset>,
+
+    /// Format-specific settings, by the name of the compressor they apply to
+    pub format_options: HashMap<&'static str, FormatOptions>,
+
     /// Timestamp option for output filenames
     pub timestamp: TimestampOption,
 
+    /// Output filename template (`--name-template`), see
+    /// `generate_output_filename`
+    pub name_template: Option<String>,
+
     /// Destination directory for output files
     pub move_to: Option<PathBuf>,
 
-    /// Show output file size (future feature)
-    #[allow(dead_code)]
+    /// Print per-file and total size/timing statistics after the run
     pub show_output_size: bool,
 
     /// Force overwrite without prompting
@@ -51,21 +346,215 @@ pub struct CompressionConfig {
 
     /// Encryption method (if any)
     pub encryption: Option<EncryptionMethod>,
+
+    /// Pad encrypted payloads to a multiple of this many bytes
+    pub pad_to: Option<u64>,
+
+    /// Fsync policy for extracted outputs
+    pub durability: Durability,
+
+    /// Fsync outputs and their directories before reporting them (`--fsync`,
+    /// `--no-fsync`); `None` does so for encrypted outputs only
+    pub fsync: Option<bool>,
+
+    /// Write a JSON metadata sidecar next to each output
+    pub sidecar_metadata: bool,
+
+    /// Store/restore extended attributes in tar archives
+    pub preserve_xattrs: bool,
+
+    /// Store/restore POSIX ACLs in tar archives
+    pub preserve_acls: bool,
+
+    /// Never replace an existing file when placing outputs (set with -t)
+    pub unique_names: bool,
+
+    /// Archive what symlinks point to instead of the links themselves
+    pub dereference: bool,
+
+    /// Leave regular files it rejects by size or mtime out of directory
+    /// walks (`--min-size`, `--newer-mtime`, ...)
+    pub filter: FileFilter,
+
+    /// Neither store nor restore the original name in gzip headers
+    pub gzip_no_name: bool,
+
+    /// Reproducible output: entry mtimes are clamped to this epoch (0
+    /// unless SOURCE_DATE_EPOCH is set) and owners and names normalized
+    pub reproducible: Option<u64>,
+
+    /// Password for standard AES-256 encrypted zips (WinZip AE-2)
+    pub zip_password: Option<ZipPassword>,
+
+    /// Charset of names in legacy zips without the UTF-8 flag (e.g. cp932)
+    pub zip_encoding: Option<String>,
+
+    /// Archive comment written to zips (`--comment`)
+    pub zip_comment: Option<String>,
+
+    /// Embed a MANIFEST.sha256 in collection archives
+    pub manifest: bool,
+
+    /// Write `.tar.gz` archives as independent gzip blocks with an index
+    /// beside them, so members can be read without streaming the archive
+    pub indexed: bool,
+
+    /// Snapshot file of incremental tar archives: only entries changed
+    /// since it was written are archived, and it is updated afterwards
+    pub listed_incremental: Option<PathBuf>,
+
+    /// Rewrites of the names tar and collection archives store inputs
+    /// under (`--transform`)
+    pub transform: Option<PathTransform>,
+
+    /// Extract tar archives into the destination as incrementals, merging
+    /// with what is there and deleting the names they record as removed
+    pub incremental: bool,
+
+    /// Extract into a directory of its own, created in the destination,
+    /// whatever the archive holds at its top level
+    pub extract_dir: Option<ExtractDir>,
+
+    /// Rename outputs that would be replaced to a backup name instead of
+    /// overwriting them or asking (`--backup`)
+    pub backup: Option<BackupMode>,
+
+    /// Move originals that are removed to the OS trash instead of
+    /// unlinking them (`--trash`)
+    pub trash: bool,
+
+    /// Keep going past damaged data: skip to the next intact gzip member
+    /// or tar header and report what was lost instead of failing
+    pub salvage: bool,
+
+    /// Skip the CRC32 checks of gzip and zip data on extraction, or keep
+    /// what failed them (`--no-verify`)
+    pub no_verify: bool,
+
+    /// Format to decompress inputs as, whatever their names say
+    /// (`-d --format`): a command name, format extension or registered
+    /// compressor
+    pub decode_as: Option<String>,
+
+    /// Thread limit (`None`: one per core); batches process at most this
+    /// many files at once and split it among the tools they run
+    pub threads: Option<usize>,
+
+    /// Bandwidth budget for the data compressors read and write, shared
+    /// by all files in the run
+    pub limit_rate: Option<RateLimit>,
+
+    /// Where extraction scratch space goes (`None`: `TMPDIR`, else next
+    /// to the destination)
+    pub temp_dir: Option<PathBuf>,
+
+    /// Extract plain tar and zip archives straight into the destination,
+    /// without scratch space
+    pub direct: bool,
+
+    /// Run gzip, bzip2 and xz even when parallel replacements (pigz,
+    /// pbzip2/lbzip2, pixz) are installed
+    pub serial_tools: bool,
+
+    /// Stop after decrypting `.jcze` inputs, leaving what they contain
+    /// compressed
+    pub decrypt_only: bool,
+
+    /// Memory cap for decoders in bytes; inputs needing more are refused
+    /// instead of decompressed
+    pub memory_limit: Option<u64>,
+
+    /// Stop starting new files once one fails (`None`: keep going)
+    pub fail_fast: Option<FailFast>,
+
+    /// Run each file's job again when it fails transiently
+    pub retry: RetryPolicy,
+
+    /// Where password encryption and decryption get their password
+    pub secrets: Option<Secrets>,
+
+    /// What to do about existing extraction outputs when not forced
+    pub overwrite: OverwritePolicy,
+
+    /// Told about each input and the bytes read
+    pub progress: Option<Arc<dyn ProgressSink>>,
+
+    /// Told about each tar and zip member added or extracted
+    pub entry_listener: Option<EntryListener>,
+
+    /// Stops the operation when cancelled
+    pub cancel: Option<CancellationToken>,
+
+    /// Stops a batch from starting further inputs when cancelled, leaving
+    /// inputs in progress to finish (Ctrl-C in the CLI); unlike `cancel`,
+    /// data still flows straight between the tools and the files
+    pub interrupt: Option<CancellationToken>,
+
+    /// User-registered external compressors
+    pub external: Option<Arc<CommandRegistry>>,
 }
 
 impl Default for CompressionConfig {
     fn default() -> Self {
         Self {
             level: 6,
+            level_preset: None,
+            format_options: HashMap::new(),
             timestamp: TimestampOption::None,
+            name_template: None,
             move_to: None,
             show_output_size: false,
             force: false,
             encryption: None,
+            pad_to: None,
+            durability: Durability::Default,
+            fsync: None,
+            sidecar_metadata: false,
+            preserve_xattrs: false,
+            preserve_acls: false,
+            unique_names: false,
+            dereference: false,
+            filter: FileFilter::default(),
+            gzip_no_name: false,
+            reproducible: None,
+            zip_password: None,
+            zip_encoding: None,
+            zip_comment: None,
+            manifest: false,
+            indexed: false,
+            listed_incremental: None,
+            transform: None,
+            incremental: false,
+            extract_dir: None,
+            backup: None,
+            trash: false,
+            salvage: false,
+            no_verify: false,
+            decode_as: None,
+            threads: None,
+            limit_rate: None,
+            temp_dir: None,
+            direct: false,
+            serial_tools: false,
+            decrypt_only: false,
+            memory_limit: None,
+            fail_fast: None,
+            retry: RetryPolicy::default(),
+            secrets: None,
+            overwrite: OverwritePolicy::Keep,
+            progress: None,
+            entry_listener: None,
+            cancel: None,
+            interrupt: None,
+            external: None,
         }
     }
 }
 
+/// How long a failed job waits for the Ctrl-C that may have killed its
+/// tool to be seen by jcz as well
+const INTERRUPT_GRACE: Duration = Duration::from_millis(50);
+
 impl CompressionConfig {
     pub fn new() -> Self {
         Self::default()
@@ -76,16 +565,83 @@ impl CompressionConfig {
         self
     }
 
+    pub fn with_level_preset(mut self, preset: Option<LevelPreset>) -> Self {
+        self.level_preset = preset;
+        self
+    }
+
+    pub fn with_format_options(mut self, compressor: &'static str, options: FormatOptions) -> Self {
+        self.format_options.insert(compressor, options);
+        self
+    }
+
+    /// xz settings, the defaults when none were given
+    pub fn xz_tuning(&self) -> XzTuning {
+        match self.format_options.get(CompressionFormat::Xz.name()) {
+            Some(FormatOptions::Xz(tuning)) => *tuning,
+            _ => XzTuning::default(),
+        }
+    }
+
+    /// gzip settings, the defaults when none were given
+    pub fn gzip_tuning(&self) -> GzipTuning {
+        match self.format_options.get(CompressionFormat::Gzip.name()) {
+            Some(FormatOptions::Gzip(tuning)) => *tuning,
+            _ => GzipTuning::default(),
+        }
+    }
+
+    /// Settings for the registered compressor `name`
+    pub fn command_tuning(&self, name: &str) -> CommandTuning {
+        match self.format_options.get(name) {
+            Some(FormatOptions::Command(tuning)) => *tuning,
+            _ => CommandTuning::default(),
+        }
+    }
+
+    /// Level `compressor` should use: its value for the preset, if any
+    pub fn level_for(&self, compressor: &dyn Compressor) -> u8 {
+        match self.level_preset {
+            Some(LevelPreset::Fast) => compressor.fastest_level(),
+            Some(LevelPreset::Default) => compressor.default_level(),
+            Some(LevelPreset::Best) => compressor.best_level(),
+            None => self.level,
+        }
+    }
+
+    /// This config with the level preset resolved for `compressor`, so the
+    /// compressor and the helpers it calls can read `level` directly
+    pub fn for_compressor(&self, compressor: &dyn Compressor) -> Cow<'_, Self> {
+        match self.level_preset {
+            None => Cow::Borrowed(self),
+            Some(_) => Cow::Owned(
+                self.clone()
+                    .with_level(self.level_for(compressor))
+                    .with_level_preset(None),
+            ),
+        }
+    }
+
     pub fn with_timestamp(mut self, timestamp: TimestampOption) -> Self {
         self.timestamp = timestamp;
         self
     }
 
+    pub fn with_name_template(mut self, template: Option<String>) -> Self {
+        self.name_template = template;
+        self
+    }
+
     pub fn with_move_to(mut self, path: PathBuf) -> Self {
         self.move_to = Some(path);
         self
     }
 
+    pub fn with_show_output_size(mut self, show_output_size: bool) -> Self {
+        self.show_output_size = show_output_size;
+        self
+    }
+
     pub fn with_force(mut self, force: bool) -> Self {
         self.force = force;
         self
@@ -95,6 +651,306 @@ impl CompressionConfig {
         self.encryption = encryption;
         self
     }
+
+    pub fn with_pad_to(mut self, pad_to: Option<u64>) -> Self {
+        self.pad_to = pad_to;
+        self
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn with_fsync(mut self, fsync: Option<bool>) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Whether outputs are fsynced before they are reported or their
+    /// intermediates removed
+    pub fn syncs_outputs(&self) -> bool {
+        self.fsync.unwrap_or(self.encryption.is_some())
+    }
+
+    pub fn with_sidecar_metadata(mut self, sidecar_metadata: bool) -> Self {
+        self.sidecar_metadata = sidecar_metadata;
+        self
+    }
+
+    pub fn with_xattrs(mut self, preserve_xattrs: bool) -> Self {
+        self.preserve_xattrs = preserve_xattrs;
+        self
+    }
+
+    pub fn with_acls(mut self, preserve_acls: bool) -> Self {
+        self.preserve_acls = preserve_acls;
+        self
+    }
+
+    pub fn with_unique_names(mut self, unique_names: bool) -> Self {
+        self.unique_names = unique_names;
+        self
+    }
+
+    pub fn with_dereference(mut self, dereference: bool) -> Self {
+        self.dereference = dereference;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_gzip_no_name(mut self, gzip_no_name: bool) -> Self {
+        self.gzip_no_name = gzip_no_name;
+        self
+    }
+
+    pub fn with_reproducible(mut self, reproducible: Option<u64>) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    pub fn with_zip_password(mut self, zip_password: Option<ZipPassword>) -> Self {
+        self.zip_password = zip_password;
+        self
+    }
+
+    pub fn with_zip_comment(mut self, zip_comment: Option<String>) -> Self {
+        self.zip_comment = zip_comment;
+        self
+    }
+
+    pub fn with_zip_encoding(mut self, zip_encoding: Option<String>) -> Self {
+        self.zip_encoding = zip_encoding;
+        self
+    }
+
+    pub fn with_manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    pub fn with_indexed(mut self, indexed: bool) -> Self {
+        self.indexed = indexed;
+        self
+    }
+
+    pub fn with_listed_incremental(mut self, snapshot: Option<PathBuf>) -> Self {
+        self.listed_incremental = snapshot;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Option<PathTransform>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    pub fn with_extract_dir(mut self, extract_dir: Option<ExtractDir>) -> Self {
+        self.extract_dir = extract_dir;
+        self
+    }
+
+    pub fn with_backup(mut self, backup: Option<BackupMode>) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    pub fn with_trash(mut self, trash: bool) -> Self {
+        self.trash = trash;
+        self
+    }
+
+    pub fn with_salvage(mut self, salvage: bool) -> Self {
+        self.salvage = salvage;
+        self
+    }
+
+    pub fn with_decode_as(mut self, decode_as: Option<String>) -> Self {
+        self.decode_as = decode_as;
+        self
+    }
+
+    pub fn with_no_verify(mut self, no_verify: bool) -> Self {
+        self.no_verify = no_verify;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_limit_rate(mut self, limit_rate: Option<RateLimit>) -> Self {
+        self.limit_rate = limit_rate;
+        self
+    }
+
+    pub fn with_temp_dir(mut self, temp_dir: Option<PathBuf>) -> Self {
+        self.temp_dir = temp_dir;
+        self
+    }
+
+    pub fn with_direct(mut self, direct: bool) -> Self {
+        self.direct = direct;
+        self
+    }
+
+    pub fn with_serial_tools(mut self, serial_tools: bool) -> Self {
+        self.serial_tools = serial_tools;
+        self
+    }
+
+    pub fn with_decrypt_only(mut self, decrypt_only: bool) -> Self {
+        self.decrypt_only = decrypt_only;
+        self
+    }
+
+    pub fn with_memory_limit(mut self, memory_limit: Option<u64>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast.then(FailFast::new);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_secrets(mut self, secrets: Option<Secrets>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn with_entry_listener(mut self, listener: Option<EntryListener>) -> Self {
+        self.entry_listener = listener;
+        self
+    }
+
+    /// Tell the entry listener, if any, about `entry`
+    pub fn report_entry(&self, entry: &ArchiveEntry) {
+        if let Some(ref listener) = self.entry_listener {
+            listener.entry(entry);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn with_interrupt(mut self, interrupt: Option<CancellationToken>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    pub fn with_external(mut self, external: Option<Arc<CommandRegistry>>) -> Self {
+        self.external = external;
+        self
+    }
+
+    /// Whether the existing `path` may be replaced by an extraction output
+    pub fn may_overwrite(&self, path: &Path) -> bool {
+        self.force || self.overwrite.allows(path)
+    }
+
+    /// Rename `path`, when it exists, to its backup name if backups are
+    /// on, so that a new output can take its place
+    pub fn back_up(&self, path: &Path) -> JcResult<()> {
+        let Some(ref mode) = self.backup else {
+            return Ok(());
+        };
+        if fs::symlink_metadata(path).is_ok() {
+            let backup = mode.back_up(path)?;
+            info!("Backed up {} to {}", path.display(), backup.display());
+        }
+        Ok(())
+    }
+
+    /// Whether an output may take the place of `path`, which exists: once
+    /// it is backed up with `--backup`, else if it may be overwritten
+    pub fn make_way(&self, path: &Path) -> JcResult<bool> {
+        if self.backup.is_some() {
+            self.back_up(path)?;
+            return Ok(true);
+        }
+        Ok(self.may_overwrite(path))
+    }
+
+    /// Whether data must flow through jcz rather than straight between
+    /// the tools and the files, to be rate limited, reported, cancelled or
+    /// salvaged
+    pub fn pipes_data(&self) -> bool {
+        self.limit_rate.is_some()
+            || self.progress.is_some()
+            || self.cancel.is_some()
+            || self.salvage
+    }
+
+    /// Wrap `inner`, which reads or writes the data of `path`, for progress
+    /// and cancellation
+    pub fn watched<T>(&self, inner: T, path: &Path) -> Watched<T> {
+        Watched::new(inner, path, self.progress.as_ref(), self.cancel.as_ref())
+    }
+
+    /// Run the job for one input of a batch: not started once cancelled or
+    /// interrupted or after a `--fail-fast` failure, retried after transient
+    /// failures and reported to the progress sink
+    pub fn run_job<T>(&self, input: &Path, mut job: impl FnMut() -> JcResult<T>) -> JcResult<T> {
+        let interrupted = || self.interrupt.as_ref().is_some_and(|i| i.is_cancelled());
+        run_unless_failed(self.fail_fast.as_ref(), || {
+            run_watched(input, self.progress.as_ref(), self.cancel.as_ref(), || {
+                self.retry.run(&input.display().to_string(), || {
+                    if interrupted() {
+                        return Err(JcError::Aborted);
+                    }
+                    // A tool killed by the same Ctrl-C fails its input; the
+                    // signal can reach jcz a moment after the tool's exit
+                    job().map_err(|e| {
+                        if self.interrupt.is_some() && !interrupted() {
+                            std::thread::sleep(INTERRUPT_GRACE);
+                        }
+                        if interrupted() {
+                            JcError::Aborted
+                        } else {
+                            e
+                        }
+                    })
+                })
+            })
+        })
+    }
+
+    /// Config for one of `jobs` files processed at once, with its share of
+    /// the thread limit
+    pub fn for_batch(&self, jobs: usize) -> Self {
+        let mut config = self.clone();
+        config.threads = self.threads.map(|threads| tool_threads(threads, jobs));
+        config
+    }
 }
 
 /// Collection operation mode
@@ -111,14 +967,20 @@ pub enum CollectionMode {
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum DecryptionMethod {
-    /// Password-based decryption (will prompt for password)
+    /// Password-based decryption (password from the config's `Secrets`)
     Password,
     /// RSA decryption with private key file path
     Rsa { private_key_path: PathBuf },
+    /// Key recovered from share files of a threshold-encrypted file
+    Shares { share_paths: Vec<PathBuf> },
+    /// Decryption with the 32-byte key in a keyfile
+    Keyfile { keyfile_path: PathBuf },
+    /// X25519 decryption with private key file path
+    X25519 { private_key_path: PathBuf },
 }
 
 /// Configuration for decompression operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DecompressionConfig {
     /// Destination directory for output files
     pub move_to: Option<PathBuf>,
@@ -131,17 +993,84 @@ pub struct DecompressionConfig {
 
     /// Remove encrypted file after successful decryption
     pub remove_encrypted: bool,
-}
 
-impl Default for DecompressionConfig {
-    fn default() -> Self {
-        Self {
-            move_to: None,
-            force: false,
-            decryption: None,
-            remove_encrypted: false,
-        }
-    }
+    /// Fsync policy for extracted outputs
+    pub durability: Durability,
+
+    /// Restore extended attributes from tar archives
+    pub preserve_xattrs: bool,
+
+    /// Restore POSIX ACLs from tar archives
+    pub preserve_acls: bool,
+
+    /// Ignore the original name stored in gzip headers
+    pub gzip_no_name: bool,
+
+    /// Password for AES-encrypted zips
+    pub zip_password: Option<ZipPassword>,
+
+    /// Charset of names in legacy zips
+    pub zip_encoding: Option<String>,
+
+    /// Threads the decompressor may use
+    pub threads: Option<usize>,
+
+    /// Bandwidth budget for the data decompressors read and write
+    pub limit_rate: Option<RateLimit>,
+
+    /// Where extraction scratch space goes
+    pub temp_dir: Option<PathBuf>,
+
+    /// Extract plain tar and zip archives without scratch space
+    pub direct: bool,
+
+    /// Apply tar archives as incrementals
+    pub incremental: bool,
+
+    /// Extract each archive into a directory of its own
+    pub extract_dir: Option<ExtractDir>,
+
+    /// Back up outputs that would be replaced
+    pub backup: Option<BackupMode>,
+
+    /// Move removed encrypted files to the trash
+    pub trash: bool,
+
+    /// Skip damaged data instead of failing
+    pub salvage: bool,
+
+    /// Skip CRC32 checks of gzip and zip data
+    pub no_verify: bool,
+
+    /// Format to decompress as, whatever the name says
+    pub decode_as: Option<String>,
+
+    /// Run the reference tools rather than parallel replacements
+    pub serial_tools: bool,
+
+    /// Stop after decryption
+    pub decrypt_only: bool,
+
+    /// Memory cap for decoders in bytes
+    pub memory_limit: Option<u64>,
+
+    /// Where password decryption gets its password
+    pub secrets: Option<Secrets>,
+
+    /// What to do about existing outputs when not forced
+    pub overwrite: OverwritePolicy,
+
+    /// Told about the bytes read
+    pub progress: Option<Arc<dyn ProgressSink>>,
+
+    /// Told about each tar and zip member extracted
+    pub entry_listener: Option<EntryListener>,
+
+    /// Stops the operation when cancelled
+    pub cancel: Option<CancellationToken>,
+
+    /// User-registered external compressors
+    pub external: Option<Arc<CommandRegistry>>,
 }
 
 impl DecompressionConfig {
@@ -168,11 +1097,101 @@ impl DecompressionConfig {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn with_secrets(mut self, secrets: Option<Secrets>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_external(mut self, external: Option<Arc<CommandRegistry>>) -> Self {
+        self.external = external;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_remove_encrypted(mut self, remove_encrypted: bool) -> Self {
         self.remove_encrypted = remove_encrypted;
         self
     }
+
+    #[allow(dead_code)]
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_serial_tools(mut self, serial_tools: bool) -> Self {
+        self.serial_tools = serial_tools;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_decrypt_only(mut self, decrypt_only: bool) -> Self {
+        self.decrypt_only = decrypt_only;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_extract_dir(mut self, extract_dir: Option<ExtractDir>) -> Self {
+        self.extract_dir = extract_dir;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_backup(mut self, backup: Option<BackupMode>) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_trash(mut self, trash: bool) -> Self {
+        self.trash = trash;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_salvage(mut self, salvage: bool) -> Self {
+        self.salvage = salvage;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_decode_as(mut self, decode_as: Option<String>) -> Self {
+        self.decode_as = decode_as;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_no_verify(mut self, no_verify: bool) -> Self {
+        self.no_verify = no_verify;
+        self
+    }
 }
 
 /// Configuration for collection operations (multi-file archives)
@@ -186,4 +1205,46 @@ pub struct CollectionConfig {
 
     /// Collection mode
     pub mode: CollectionMode,
+
+    /// Store inputs under their relative paths instead of their basenames
+    /// (`--keep-paths`)
+    pub keep_paths: bool,
+
+    /// Add or refresh inputs in an existing archive instead of replacing
+    /// it (`-u`)
+    pub update: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overwrite_policy() {
+        let path = Path::new("out.txt");
+        assert!(!OverwritePolicy::Keep.allows(path));
+        assert!(OverwritePolicy::Overwrite.allows(path));
+        let ask = OverwritePolicy::Ask(Arc::new(|p: &Path| p.ends_with("yes.txt")));
+        assert!(ask.allows(Path::new("yes.txt")));
+        assert!(!ask.allows(path));
+
+        let config = CompressionConfig::new();
+        assert!(!config.may_overwrite(path));
+        assert!(config.with_force(true).may_overwrite(path));
+    }
+
+    #[test]
+    fn test_secrets_reject_empty_password() {
+        let secrets = Secrets::new(|purpose: SecretPurpose| {
+            Ok(Zeroizing::new(match purpose {
+                SecretPurpose::Encrypt => "hunter2".to_string(),
+                SecretPurpose::Decrypt => String::new(),
+            }))
+        });
+        assert_eq!(
+            secrets.password(SecretPurpose::Encrypt).unwrap().as_str(),
+            "hunter2"
+        );
+        assert!(secrets.password(SecretPurpose::Decrypt).is_err());
+    }
 }
@@ -1,143 +1,318 @@
-use std::fmt;
 use std::io;
 use std::path::PathBuf;
 
+use thiserror::Error;
+
+use crate::crypto::CryptoError;
+use crate::operations::stats::format_size;
+use crate::utils::is_cancelled_io;
+
 /// Result type for JC operations
 pub type JcResult<T> = Result<T, JcError>;
 
+/// Exit code for invalid arguments
+pub const EXIT_USAGE: i32 = 1;
+
+/// Exit code when some inputs of a batch failed and others succeeded
+pub const EXIT_PARTIAL_FAILURE: i32 = 2;
+
+/// Exit code when the run, or every input of a batch, failed
+pub const EXIT_FAILURE: i32 = 3;
+
+/// Exit code for a wrong password or key
+pub const EXIT_AUTHENTICATION: i32 = 4;
+
+/// Exit code for an archive that failed its integrity check
+pub const EXIT_INTEGRITY: i32 = 5;
+
+/// Exit code when the run was interrupted (Ctrl-C), as shells report a
+/// process killed by SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
 /// Comprehensive error type for JC operations
-#[derive(Debug)]
+///
+/// Non-exhaustive, so variants can be added without breaking library
+/// users; match on the ones you handle and fall back to `Display`.
+#[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum JcError {
     /// File not found
+    #[error("File not found: {}", .0.display())]
     FileNotFound(PathBuf),
 
     /// Path is not a file (e.g., directory when file expected)
+    #[error("{} is not a file", .0.display())]
     NotAFile(PathBuf),
 
     /// Path is not a directory
+    #[error("{} is not a directory", .0.display())]
     NotADirectory(PathBuf),
 
     /// Invalid file extension for operation
+    #[error("{} has invalid extension, expected: {}", .0.display(), .1)]
     InvalidExtension(PathBuf, String),
 
     /// Invalid compression level for algorithm
+    #[error("Invalid compression level {level} for {algorithm}")]
     InvalidCompressionLevel { algorithm: String, level: u8 },
 
     /// Invalid timestamp option
+    #[error("Invalid timestamp option: {0}")]
     InvalidTimestampOption(u8),
 
     /// Invalid compression command
+    #[error("Invalid compression command: {0}")]
     InvalidCommand(String),
 
     /// Duplicate basenames in collection
+    #[error("Duplicate basenames in collection: {}", .0.join(", "))]
     DuplicateBasenames(Vec<String>),
 
+    /// Merged inputs with different content at the same path
+    #[error("Paths collide between merged inputs: {}", .0.join("; "))]
+    PathCollisions(Vec<String>),
+
     /// Archive/package name already exists
+    #[error("{0} already exists and cannot be used as package name")]
     NameExists(String),
 
     /// Move-to directory error
+    #[error("Move-to directory error: {0}")]
     MoveToError(String),
 
     /// Compression tool execution failed
+    #[error("{tool} compression failed: {stderr}")]
     CompressionFailed { tool: String, stderr: String },
 
     /// Decompression tool execution failed
+    #[error("{tool} decompression failed: {stderr}")]
     DecompressionFailed { tool: String, stderr: String },
 
+    /// Sending an output to remote storage failed
+    #[error("Upload to {destination} failed: {stderr}")]
+    UploadFailed { destination: String, stderr: String },
+
+    /// Fetching an input from a URL failed
+    #[error("Download of {url} failed: {stderr}")]
+    DownloadFailed { url: String, stderr: String },
+
+    /// External tool is not installed
+    #[error("{tool} is not installed ({hint})")]
+    ToolNotFound { tool: String, hint: String },
+
     /// I/O error
-    Io(io::Error),
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+
+    /// Encryption or decryption failed; `CryptoError::AuthenticationFailed`
+    /// means a wrong password or key, or tampered data
+    #[error("{0}")]
+    Crypto(#[from] CryptoError),
 
     /// Symbolic link resolution failed
+    #[error("Failed to resolve symbolic link: {}", .0.display())]
     SymlinkResolution(PathBuf),
 
     /// Temporary directory creation failed
+    #[error("Temporary directory creation failed: {0}")]
     TempDirFailed(String),
 
+    /// Not enough free space to extract an archive
+    #[error(
+        "Not enough space in {}: about {} needed, {} available",
+        .path.display(),
+        format_size(*.needed),
+        format_size(*.available)
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+
+    /// Decompressing would need more memory than `--memory-limit` allows
+    #[error(
+        "{} needs more than the {} memory limit to decompress",
+        .path.display(),
+        format_size(*.limit)
+    )]
+    MemoryLimitExceeded { path: PathBuf, limit: u64 },
+
     /// No input files provided
+    #[error("No input files provided")]
     NoInputFiles,
 
+    /// Not started because an earlier file failed (`--fail-fast`)
+    #[error("Cancelled after an earlier failure")]
+    Cancelled,
+
+    /// Stopped by the caller's `CancellationToken`
+    #[error("Operation cancelled")]
+    Aborted,
+
+    /// Invalid command-line arguments
+    #[error("{0}")]
+    Usage(String),
+
+    /// Archive contents do not match their integrity tag, manifest or sidecar
+    #[error("{0}")]
+    IntegrityFailed(String),
+
+    /// Some inputs of a batch failed; each was reported as it failed
+    #[error("{message} ({failed} of {total}{})", cancelled_note(*.cancelled))]
+    BatchFailed {
+        message: String,
+        failed: usize,
+        cancelled: usize,
+        total: usize,
+        code: i32,
+    },
+
     /// Generic error with message
+    #[error("{0}")]
     Other(String),
 }
 
-impl fmt::Display for JcError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            JcError::FileNotFound(path) => {
-                write!(f, "File not found: {}", path.display())
-            }
-            JcError::NotAFile(path) => {
-                write!(f, "{} is not a file", path.display())
-            }
-            JcError::NotADirectory(path) => {
-                write!(f, "{} is not a directory", path.display())
-            }
-            JcError::InvalidExtension(path, expected) => {
-                write!(
-                    f,
-                    "{} has invalid extension, expected: {}",
-                    path.display(),
-                    expected
-                )
-            }
-            JcError::InvalidCompressionLevel { algorithm, level } => {
-                write!(f, "Invalid compression level {} for {}", level, algorithm)
-            }
-            JcError::InvalidTimestampOption(opt) => {
-                write!(f, "Invalid timestamp option: {}", opt)
-            }
-            JcError::InvalidCommand(cmd) => {
-                write!(f, "Invalid compression command: {}", cmd)
-            }
-            JcError::DuplicateBasenames(names) => {
-                write!(f, "Duplicate basenames in collection: {}", names.join(", "))
-            }
-            JcError::NameExists(name) => {
-                write!(
-                    f,
-                    "{} already exists and cannot be used as package name",
-                    name
-                )
-            }
-            JcError::MoveToError(msg) => {
-                write!(f, "Move-to directory error: {}", msg)
-            }
-            JcError::CompressionFailed { tool, stderr } => {
-                write!(f, "{} compression failed: {}", tool, stderr)
-            }
-            JcError::DecompressionFailed { tool, stderr } => {
-                write!(f, "{} decompression failed: {}", tool, stderr)
-            }
-            JcError::Io(err) => {
-                write!(f, "I/O error: {}", err)
-            }
-            JcError::SymlinkResolution(path) => {
-                write!(f, "Failed to resolve symbolic link: {}", path.display())
-            }
-            JcError::TempDirFailed(msg) => {
-                write!(f, "Temporary directory creation failed: {}", msg)
-            }
-            JcError::NoInputFiles => {
-                write!(f, "No input files provided")
-            }
-            JcError::Other(msg) => {
-                write!(f, "{}", msg)
-            }
-        }
+/// ", n cancelled" for a batch summary, when any were
+fn cancelled_note(cancelled: usize) -> String {
+    if cancelled > 0 {
+        format!(", {} cancelled", cancelled)
+    } else {
+        String::new()
     }
 }
 
-impl std::error::Error for JcError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+impl JcError {
+    /// Error for a batch of `total` inputs in which `failures` failed or
+    /// were cancelled
+    ///
+    /// When every input failed for the same authentication or integrity
+    /// reason, the batch exits with that reason's code; an interrupted
+    /// batch exits with `EXIT_INTERRUPTED`.
+    pub fn batch_failed(message: &str, failures: &[JcError], total: usize) -> Self {
+        let codes: Vec<i32> = failures
+            .iter()
+            .filter(|e| !e.is_cancellation())
+            .map(JcError::exit_code)
+            .collect();
+        let shared = codes
+            .first()
+            .copied()
+            .filter(|first| codes.iter().all(|code| code == first));
+        let interrupted = failures.iter().any(|e| matches!(e, JcError::Aborted));
+        let code = match shared {
+            _ if interrupted => EXIT_INTERRUPTED,
+            _ if failures.len() < total => EXIT_PARTIAL_FAILURE,
+            Some(code @ (EXIT_AUTHENTICATION | EXIT_INTEGRITY)) => code,
+            _ => EXIT_FAILURE,
+        };
+        JcError::BatchFailed {
+            message: message.to_string(),
+            failed: codes.len(),
+            cancelled: failures.len() - codes.len(),
+            total,
+            code,
+        }
+    }
+
+    /// Whether the input was stopped rather than failing by itself
+    pub fn is_cancellation(&self) -> bool {
+        matches!(self, JcError::Cancelled | JcError::Aborted)
+    }
+
+    /// Process exit code for this error (see the `EXIT_*` constants)
+    pub fn exit_code(&self) -> i32 {
         match self {
-            JcError::Io(err) => Some(err),
-            _ => None,
+            JcError::Usage(_)
+            | JcError::InvalidCommand(_)
+            | JcError::InvalidTimestampOption(_)
+            | JcError::InvalidCompressionLevel { .. } => EXIT_USAGE,
+            JcError::Crypto(CryptoError::AuthenticationFailed) => EXIT_AUTHENTICATION,
+            JcError::IntegrityFailed(_) => EXIT_INTEGRITY,
+            JcError::Aborted => EXIT_INTERRUPTED,
+            JcError::BatchFailed { code, .. } => *code,
+            _ => EXIT_FAILURE,
         }
     }
 }
 
 impl From<io::Error> for JcError {
     fn from(err: io::Error) -> Self {
+        if is_cancelled_io(&err) {
+            return JcError::Aborted;
+        }
         JcError::Io(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> JcError {
+        JcError::Crypto(CryptoError::AuthenticationFailed)
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(JcError::Usage("bad".to_string()).exit_code(), EXIT_USAGE);
+        assert_eq!(
+            JcError::InvalidCommand("x".to_string()).exit_code(),
+            EXIT_USAGE
+        );
+        assert_eq!(auth().exit_code(), EXIT_AUTHENTICATION);
+        assert_eq!(
+            JcError::IntegrityFailed("mismatch".to_string()).exit_code(),
+            EXIT_INTEGRITY
+        );
+        assert_eq!(JcError::NoInputFiles.exit_code(), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_crypto_errors_keep_their_kind() {
+        use std::error::Error as _;
+
+        let error = JcError::from(CryptoError::AuthenticationFailed);
+        assert!(matches!(
+            error,
+            JcError::Crypto(CryptoError::AuthenticationFailed)
+        ));
+        assert_eq!(error.exit_code(), EXIT_AUTHENTICATION);
+
+        // Sources chain through both enums down to the I/O error
+        let io = io::Error::new(io::ErrorKind::NotFound, "missing");
+        let error = JcError::from(CryptoError::from(io));
+        let source = error.source().and_then(|e| e.source()).unwrap();
+        assert_eq!(source.to_string(), "missing");
+    }
+
+    #[test]
+    fn test_batch_exit_codes() {
+        let other = || JcError::Other("failed".to_string());
+        assert_eq!(
+            JcError::batch_failed("m", &[other()], 3).exit_code(),
+            EXIT_PARTIAL_FAILURE
+        );
+        assert_eq!(
+            JcError::batch_failed("m", &[auth(), other()], 2).exit_code(),
+            EXIT_FAILURE
+        );
+        assert_eq!(
+            JcError::batch_failed("m", &[auth(), auth()], 2).exit_code(),
+            EXIT_AUTHENTICATION
+        );
+
+        let error = JcError::batch_failed("Some files failed", &[auth()], 4);
+        assert_eq!(error.to_string(), "Some files failed (1 of 4)");
+
+        // Cancelled inputs don't hide the reason of the one that failed
+        let error = JcError::batch_failed("m", &[auth(), JcError::Cancelled], 2);
+        assert_eq!(error.exit_code(), EXIT_AUTHENTICATION);
+        assert_eq!(error.to_string(), "m (1 of 2, 1 cancelled)");
+
+        // An interrupted batch says so, whatever else happened
+        let error = JcError::batch_failed("m", &[other(), JcError::Aborted], 3);
+        assert_eq!(error.exit_code(), EXIT_INTERRUPTED);
+        assert_eq!(JcError::Aborted.exit_code(), EXIT_INTERRUPTED);
+    }
+}
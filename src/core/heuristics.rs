@@ -0,0 +1,430 @@
+//! Format selection heuristics for `-c auto`
+//!
+//! Each input is classified from magic bytes and the byte entropy of a few
+//! samples: already-compressed media and archives are stored, highly
+//! redundant data (text, logs) gets xz, and everything else gzip.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::core::error::JcResult;
+
+/// Bytes read per sample; files get up to three (start, middle, end)
+const SAMPLE_SIZE: usize = 16 * 1024;
+
+/// Total bytes sampled from a directory's files
+const DIRECTORY_BUDGET: usize = 1024 * 1024;
+
+/// Bits per byte above which data is treated as already compressed
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.5;
+
+/// Bits per byte below which xz's larger window pays off over gzip
+const REDUNDANT_ENTROPY: f64 = 5.5;
+
+/// Share of a directory's bytes that must be incompressible to store it
+const STORE_FRACTION: f64 = 0.9;
+
+/// Signatures of formats that are already compressed
+///
+/// (offset, magic, description)
+const COMPRESSED_MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, &[0xFF, 0xD8, 0xFF], "JPEG image"),
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"GIF8", "GIF image"),
+    (8, b"WEBP", "WebP image"),
+    (4, b"ftyp", "MP4/QuickTime media"),
+    (0, &[0x1A, 0x45, 0xDF, 0xA3], "Matroska/WebM video"),
+    (0, b"ID3", "MP3 audio"),
+    (0, b"OggS", "Ogg media"),
+    (0, b"fLaC", "FLAC audio"),
+    (0, &[0x1F, 0x8B], "gzip data"),
+    (0, b"BZh", "bzip2 data"),
+    (0, &[0xFD, b'7', b'z', b'X', b'Z', 0x00], "xz data"),
+    (0, &[0x28, 0xB5, 0x2F, 0xFD], "zstd data"),
+    (0, b"PK\x03\x04", "zip archive"),
+    (0, &[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C], "7-Zip archive"),
+    (0, b"Rar!\x1a\x07", "RAR archive"),
+    (0, b"JCZE", "jcz encrypted file"),
+];
+
+/// Share of an archive's bytes in already-compressed members above which
+/// tar-based formats warn
+const WARN_FRACTION: f64 = 0.5;
+
+/// What `-c auto` does with an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoChoice {
+    /// Leave files as they are; only tar directories
+    Store,
+
+    /// gzip (tgz for directories)
+    Gzip,
+
+    /// xz (txz for directories)
+    Xz,
+}
+
+impl AutoChoice {
+    /// The `-c` command to run, or `None` when a file is left alone
+    pub fn command(&self, directory: bool) -> Option<&'static str> {
+        match (self, directory) {
+            (AutoChoice::Store, false) => None,
+            (AutoChoice::Store, true) => Some("tar"),
+            (AutoChoice::Gzip, false) => Some("gzip"),
+            (AutoChoice::Gzip, true) => Some("tgz"),
+            (AutoChoice::Xz, false) => Some("xz"),
+            (AutoChoice::Xz, true) => Some("txz"),
+        }
+    }
+}
+
+/// Choice for one input and why it was made
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoDecision {
+    pub choice: AutoChoice,
+    pub reason: String,
+}
+
+impl fmt::Display for AutoDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.choice {
+            AutoChoice::Store => "store",
+            AutoChoice::Gzip => "gzip",
+            AutoChoice::Xz => "xz",
+        };
+        write!(f, "{} ({})", name, self.reason)
+    }
+}
+
+/// Pick a format for `input` (a file, or a directory as a whole)
+pub fn choose_format(input: &Path) -> JcResult<AutoDecision> {
+    if fs::metadata(input)?.is_dir() {
+        choose_for_directory(input)
+    } else {
+        let sample = sample_file(input)?;
+        Ok(match describe_compressed(&sample.head) {
+            Some(kind) => AutoDecision {
+                choice: AutoChoice::Store,
+                reason: kind.to_string(),
+            },
+            None => decide_by_entropy(entropy(&sample.histogram)),
+        })
+    }
+}
+
+/// Already-compressed regular files at or below a path, found by magic bytes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressedMembers {
+    /// Bytes in already-compressed files
+    pub bytes: u64,
+
+    /// Bytes in all regular files
+    pub total: u64,
+
+    /// Kinds found, e.g. "JPEG image", in order of first appearance
+    pub kinds: Vec<&'static str>,
+
+    /// Extensions of the compressed files as spelled, e.g. "jpg" and "JPG"
+    pub suffixes: Vec<String>,
+}
+
+impl CompressedMembers {
+    /// Fraction of all bytes that are already compressed
+    pub fn share(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.bytes as f64 / self.total as f64
+        }
+    }
+
+    /// Whether recompressing at a high level mostly wastes CPU
+    pub fn worth_warning(&self) -> bool {
+        self.share() >= WARN_FRACTION
+    }
+}
+
+/// Find already-compressed members (jpeg, png, mp4, zip, gz, ...) of a file
+/// or directory tree; only the first bytes of each file are read
+pub fn find_compressed_members(path: &Path) -> JcResult<CompressedMembers> {
+    let mut files = Vec::new();
+    if fs::symlink_metadata(path)?.is_dir() {
+        list_files(path, &mut files)?;
+    } else {
+        files.push((path.to_path_buf(), fs::metadata(path)?.len()));
+    }
+
+    let mut found = CompressedMembers::default();
+    let mut head = [0u8; 64];
+    for (file, size) in files {
+        found.total += size;
+
+        let mut reader = File::open(&file)?;
+        let mut filled = 0;
+        while filled < head.len() {
+            match reader.read(&mut head[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        let Some(kind) = describe_compressed(&head[..filled]) else {
+            continue;
+        };
+
+        found.bytes += size;
+        if !found.kinds.contains(&kind) {
+            found.kinds.push(kind);
+        }
+        if let Some(ext) = file.extension().and_then(|e| e.to_str()) {
+            if !found.suffixes.iter().any(|s| s == ext) {
+                found.suffixes.push(ext.to_string());
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Classify by entropy alone; incompressible data is stored
+fn decide_by_entropy(bits: f64) -> AutoDecision {
+    let choice = if bits >= INCOMPRESSIBLE_ENTROPY {
+        AutoChoice::Store
+    } else if bits < REDUNDANT_ENTROPY {
+        AutoChoice::Xz
+    } else {
+        AutoChoice::Gzip
+    };
+    AutoDecision {
+        choice,
+        reason: format!("entropy {:.1} bits/byte", bits),
+    }
+}
+
+/// Store a directory that is almost all compressed media; otherwise pick
+/// by the entropy of its compressible files
+fn choose_for_directory(dir: &Path) -> JcResult<AutoDecision> {
+    let mut files = Vec::new();
+    list_files(dir, &mut files)?;
+
+    let mut total = 0u64;
+    let mut incompressible = 0u64;
+    let mut histogram = [0u64; 256];
+    let mut budget = DIRECTORY_BUDGET;
+
+    for (path, size) in files {
+        total += size;
+        if budget == 0 {
+            continue;
+        }
+        let sample = sample_file(&path)?;
+        budget = budget.saturating_sub(sample.bytes);
+        if describe_compressed(&sample.head).is_some()
+            || entropy(&sample.histogram) >= INCOMPRESSIBLE_ENTROPY
+        {
+            incompressible += size;
+        } else {
+            for (count, add) in histogram.iter_mut().zip(sample.histogram) {
+                *count += add;
+            }
+        }
+    }
+
+    if total > 0 && incompressible as f64 / total as f64 >= STORE_FRACTION {
+        return Ok(AutoDecision {
+            choice: AutoChoice::Store,
+            reason: format!(
+                "{:.0}% already compressed",
+                incompressible as f64 * 100.0 / total as f64
+            ),
+        });
+    }
+    Ok(decide_by_entropy(entropy(&histogram)))
+}
+
+/// Regular files below `dir` with their sizes, in name order
+fn list_files(dir: &Path, out: &mut Vec<(PathBuf, u64)>) -> JcResult<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    children.sort();
+
+    for path in children {
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            list_files(&path, out)?;
+        } else if metadata.is_file() {
+            out.push((path, metadata.len()));
+        }
+    }
+    Ok(())
+}
+
+struct Sample {
+    /// Start of the file, for magic bytes
+    head: Vec<u8>,
+    histogram: [u64; 256],
+    bytes: usize,
+}
+
+/// Read the start, middle and end of a file
+fn sample_file(path: &Path) -> JcResult<Sample> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut offsets = vec![0];
+    if len > 3 * SAMPLE_SIZE as u64 {
+        offsets.push(len / 2);
+        offsets.push(len - SAMPLE_SIZE as u64);
+    }
+
+    let mut sample = Sample {
+        head: Vec::new(),
+        histogram: [0; 256],
+        bytes: 0,
+    };
+    let mut buffer = vec![0u8; SAMPLE_SIZE];
+    for offset in offsets {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match file.read(&mut buffer[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if offset == 0 {
+            sample.head = buffer[..filled.min(64)].to_vec();
+        }
+        for &b in &buffer[..filled] {
+            sample.histogram[b as usize] += 1;
+        }
+        sample.bytes += filled;
+    }
+    Ok(sample)
+}
+
+/// Name of the compressed format `head` starts with, if any
+fn describe_compressed(head: &[u8]) -> Option<&'static str> {
+    COMPRESSED_MAGIC
+        .iter()
+        .find(|(offset, magic, _)| head.get(*offset..*offset + magic.len()) == Some(*magic))
+        .map(|(_, _, kind)| *kind)
+}
+
+/// Shannon entropy of a byte histogram, in bits per byte
+fn entropy(histogram: &[u64; 256]) -> f64 {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Deterministic pseudo-random bytes (xorshift), as incompressible data
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_choices_for_files() {
+        let dir = TempDir::new().unwrap();
+        let text = dir.path().join("app.log");
+        let jpeg = dir.path().join("photo.jpg");
+        let random = dir.path().join("blob.bin");
+        let mixed = dir.path().join("mixed.bin");
+        fs::write(&text, "GET /index.html 200\n".repeat(2000)).unwrap();
+        fs::write(&jpeg, [&[0xFF, 0xD8, 0xFF, 0xE0][..], b"JFIF"].concat()).unwrap();
+        fs::write(&random, noise(100_000)).unwrap();
+        // 64 distinct byte values: 6 bits/byte
+        let narrow: Vec<u8> = noise(100_000).iter().map(|b| b & 0x3F).collect();
+        fs::write(&mixed, narrow).unwrap();
+
+        assert_eq!(choose_format(&text).unwrap().choice, AutoChoice::Xz);
+        let decision = choose_format(&jpeg).unwrap();
+        assert_eq!(decision.choice, AutoChoice::Store);
+        assert_eq!(decision.reason, "JPEG image");
+        assert_eq!(choose_format(&random).unwrap().choice, AutoChoice::Store);
+        assert_eq!(choose_format(&mixed).unwrap().choice, AutoChoice::Gzip);
+    }
+
+    #[test]
+    fn test_directory_of_media_is_stored() {
+        let dir = TempDir::new().unwrap();
+        let media = dir.path().join("media");
+        fs::create_dir(&media).unwrap();
+        for i in 0..3 {
+            fs::write(media.join(format!("{}.mp4", i)), {
+                let mut data = b"\0\0\0\x18ftypisom".to_vec();
+                data.extend(noise(20_000));
+                data
+            })
+            .unwrap();
+        }
+        fs::write(media.join("notes.txt"), "short note\n").unwrap();
+
+        let decision = choose_format(&media).unwrap();
+        assert_eq!(decision.choice, AutoChoice::Store);
+        assert_eq!(decision.choice.command(true), Some("tar"));
+
+        // Mostly text: compress it
+        fs::write(media.join("notes.txt"), "a long note\n".repeat(100_000)).unwrap();
+        assert_eq!(choose_format(&media).unwrap().choice, AutoChoice::Xz);
+    }
+
+    #[test]
+    fn test_find_compressed_members() {
+        let dir = TempDir::new().unwrap();
+        let tree = dir.path().join("tree");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        fs::write(
+            tree.join("a.JPG"),
+            [&[0xFF, 0xD8, 0xFF][..], &[0; 97]].concat(),
+        )
+        .unwrap();
+        fs::write(
+            tree.join("sub/b.gz"),
+            [&[0x1F, 0x8B][..], &[0; 198]].concat(),
+        )
+        .unwrap();
+        fs::write(tree.join("notes.txt"), vec![b'x'; 100]).unwrap();
+
+        let found = find_compressed_members(&tree).unwrap();
+        assert_eq!(found.bytes, 300);
+        assert_eq!(found.total, 400);
+        assert_eq!(found.kinds, vec!["JPEG image", "gzip data"]);
+        assert_eq!(found.suffixes, vec!["JPG", "gz"]);
+        assert!(found.worth_warning());
+
+        let text = find_compressed_members(&tree.join("notes.txt")).unwrap();
+        assert_eq!(text.bytes, 0);
+        assert!(!text.worth_warning());
+    }
+
+    #[test]
+    fn test_command_names() {
+        assert_eq!(AutoChoice::Store.command(false), None);
+        assert_eq!(AutoChoice::Gzip.command(false), Some("gzip"));
+        assert_eq!(AutoChoice::Xz.command(true), Some("txz"));
+    }
+}
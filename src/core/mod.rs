@@ -1,14 +1,25 @@
 pub mod compressor;
 pub mod config;
 pub mod error;
+pub mod heuristics;
+pub mod report;
 pub mod types;
 
 // Re-exported for library users
 #[allow(unused_imports)]
 pub use compressor::{Compressor, MultiFileCompressor};
 #[allow(unused_imports)]
-pub use config::{CollectionConfig, CollectionMode, CompressionConfig, TimestampOption};
+pub use config::{
+    CollectionConfig, CollectionMode, CompressionConfig, Durability, EntryListener, ExtractDir,
+    OverwritePolicy, SecretProvider, SecretPurpose, Secrets, TimestampOption,
+};
 #[allow(unused_imports)]
 pub use error::{JcError, JcResult};
 #[allow(unused_imports)]
+pub use heuristics::{
+    choose_format, find_compressed_members, AutoChoice, AutoDecision, CompressedMembers,
+};
+#[allow(unused_imports)]
+pub use report::{Operation, Report, ReportEntry, ReportStatus};
+#[allow(unused_imports)]
 pub use types::{CompoundFormat, CompressionFormat, InputFile, OperationMode};
@@ -0,0 +1,209 @@
+//! Machine-readable per-file results (`--report`)
+//!
+//! Batch operations hand every input and its outcome to a `Report`, which
+//! is written as JSON once the batch is done, failures included.
+
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::error::{JcError, JcResult};
+use crate::operations::sidecar::sha256_file;
+use crate::operations::FileStats;
+use crate::utils::{info, warn};
+
+/// What a batch did to its inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Compress,
+    Decompress,
+}
+
+impl Operation {
+    /// Name as written to reports and hook environments
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Compress => "compress",
+            Operation::Decompress => "decompress",
+        }
+    }
+
+    /// Noun for log messages, e.g. "Compression failed"
+    pub fn noun(self) -> &'static str {
+        match self {
+            Operation::Compress => "Compression",
+            Operation::Decompress => "Decompression",
+        }
+    }
+}
+
+/// Outcome of one input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Ok,
+    Failed,
+    /// Not started because an earlier input failed (`--fail-fast`), or
+    /// stopped by the caller
+    Cancelled,
+}
+
+/// Record of one input; fields that don't apply to its status are null
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportEntry {
+    pub input: PathBuf,
+    pub status: ReportStatus,
+
+    /// Archive written, or file or directory extracted
+    pub output: Option<PathBuf>,
+
+    /// Uncompressed size in bytes
+    pub original_size: Option<u64>,
+
+    /// Compressed size in bytes, including any encryption overhead
+    pub compressed_size: Option<u64>,
+
+    pub duration_secs: Option<f64>,
+
+    /// SHA-256 of the output, when it is a single file
+    pub sha256: Option<String>,
+
+    pub error: Option<String>,
+}
+
+impl ReportEntry {
+    pub fn new(input: &Path, result: &JcResult<FileStats>) -> Self {
+        let failed = |status, error: Option<String>| ReportEntry {
+            input: input.to_path_buf(),
+            status,
+            output: None,
+            original_size: None,
+            compressed_size: None,
+            duration_secs: None,
+            sha256: None,
+            error,
+        };
+
+        match result {
+            Ok(stats) => ReportEntry {
+                input: input.to_path_buf(),
+                status: ReportStatus::Ok,
+                output: Some(stats.output.clone()),
+                original_size: Some(stats.original_size),
+                compressed_size: Some(stats.compressed_size),
+                duration_secs: Some(stats.elapsed.as_secs_f64()),
+                sha256: output_sha256(&stats.output),
+                error: None,
+            },
+            Err(e) if e.is_cancellation() => failed(ReportStatus::Cancelled, None),
+            Err(e) => failed(ReportStatus::Failed, Some(e.to_string())),
+        }
+    }
+}
+
+/// Records of a whole batch, in input order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub operation: Operation,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub files: Vec<ReportEntry>,
+}
+
+impl Report {
+    pub fn new(operation: Operation) -> Self {
+        Report {
+            operation,
+            succeeded: 0,
+            failed: 0,
+            cancelled: 0,
+            files: Vec::new(),
+        }
+    }
+
+    /// Record the outcome of `input`
+    pub fn add(&mut self, input: &Path, result: &JcResult<FileStats>) {
+        let entry = ReportEntry::new(input, result);
+        match entry.status {
+            ReportStatus::Ok => self.succeeded += 1,
+            ReportStatus::Failed => self.failed += 1,
+            ReportStatus::Cancelled => self.cancelled += 1,
+        }
+        self.files.push(entry);
+    }
+
+    /// Write the report as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> JcResult<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| JcError::Other(format!("Failed to encode report: {}", e)))?;
+        fs::write(path, json + "\n")?;
+        info!("Report written to {}", path.display());
+        Ok(())
+    }
+}
+
+/// Hash of a file output; directories and unreadable outputs have none
+fn output_sha256(output: &Path) -> Option<String> {
+    if !output.is_file() {
+        return None;
+    }
+    sha256_file(output)
+        .map_err(|e| warn!("Failed to hash {}: {}", output.display(), e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_report_records_each_outcome() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("a.txt.gz");
+        fs::write(&output, b"abc").unwrap();
+        let stats = FileStats {
+            input: dir.path().join("a.txt"),
+            output: output.clone(),
+            original_size: 10,
+            compressed_size: 3,
+            elapsed: Duration::from_millis(500),
+            decompressed: false,
+        };
+
+        let mut report = Report::new(Operation::Compress);
+        report.add(Path::new("a.txt"), &Ok(stats));
+        report.add(
+            Path::new("b.txt"),
+            &Err(JcError::FileNotFound(PathBuf::from("b.txt"))),
+        );
+        report.add(Path::new("c.txt"), &Err(JcError::Cancelled));
+
+        assert_eq!(
+            (report.succeeded, report.failed, report.cancelled),
+            (1, 1, 1)
+        );
+        let ok = &report.files[0];
+        assert_eq!(ok.output.as_deref(), Some(output.as_path()));
+        assert_eq!(
+            ok.sha256.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            report.files[1].error.as_deref(),
+            Some("File not found: b.txt")
+        );
+        assert_eq!(report.files[2].status, ReportStatus::Cancelled);
+
+        let path = dir.path().join("report.json");
+        report.write(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["operation"], "compress");
+        assert_eq!(json["files"][1]["status"], "failed");
+        assert_eq!(json["files"][2]["error"], serde_json::Value::Null);
+    }
+}
@@ -8,6 +8,11 @@ pub enum CompressionFormat {
     Xz,
     Tar,
     Zip,
+    Cpio,
+    Ar,
+    Deb,
+    Rpm,
+    Dedup,
 }
 
 impl CompressionFormat {
@@ -20,6 +25,11 @@ impl CompressionFormat {
             CompressionFormat::Xz => "xz",
             CompressionFormat::Tar => "tar",
             CompressionFormat::Zip => "zip",
+            CompressionFormat::Cpio => "cpio",
+            CompressionFormat::Ar => "ar",
+            CompressionFormat::Deb => "deb",
+            CompressionFormat::Rpm => "rpm",
+            CompressionFormat::Dedup => "jczd",
         }
     }
 
@@ -31,6 +41,11 @@ impl CompressionFormat {
             "xz" => Some(CompressionFormat::Xz),
             "tar" => Some(CompressionFormat::Tar),
             "zip" => Some(CompressionFormat::Zip),
+            "cpio" => Some(CompressionFormat::Cpio),
+            "a" | "ar" => Some(CompressionFormat::Ar),
+            "deb" => Some(CompressionFormat::Deb),
+            "rpm" => Some(CompressionFormat::Rpm),
+            "jczd" => Some(CompressionFormat::Dedup),
             _ => None,
         }
     }
@@ -43,9 +58,19 @@ impl CompressionFormat {
             CompressionFormat::Xz => "xz",
             CompressionFormat::Tar => "tar",
             CompressionFormat::Zip => "zip",
+            CompressionFormat::Cpio => "cpio",
+            CompressionFormat::Ar => "ar",
+            CompressionFormat::Deb => "deb",
+            CompressionFormat::Rpm => "rpm",
+            CompressionFormat::Dedup => "dedup",
         }
     }
 
+    /// Whether jcz can create this format (packages are extract-only)
+    pub fn can_compress(&self) -> bool {
+        !matches!(self, CompressionFormat::Deb | CompressionFormat::Rpm)
+    }
+
     /// Create format from command name
     pub fn from_name(name: &str) -> Option<Self> {
         match name.to_lowercase().as_str() {
@@ -54,6 +79,9 @@ impl CompressionFormat {
             "xz" => Some(CompressionFormat::Xz),
             "tar" => Some(CompressionFormat::Tar),
             "zip" => Some(CompressionFormat::Zip),
+            "cpio" => Some(CompressionFormat::Cpio),
+            "ar" => Some(CompressionFormat::Ar),
+            "dedup" => Some(CompressionFormat::Dedup),
             _ => None,
         }
     }
@@ -93,6 +121,38 @@ impl CompoundFormat {
         }
     }
 
+    /// Single-extension aliases (`.tgz`, `.tbz2`, `.tbz`, `.tb2`, `.txz`),
+    /// in any case
+    pub fn from_alias(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "tgz" => Some(CompoundFormat::Tgz),
+            "tbz2" | "tbz" | "tb2" => Some(CompoundFormat::Tbz2),
+            "txz" => Some(CompoundFormat::Txz),
+            _ => None,
+        }
+    }
+
+    /// Detect a compound format from a full filename, treating
+    /// `.tar.gz` and `.tgz` alike as one unit
+    pub fn from_filename(name: &str) -> Option<Self> {
+        let (rest, ext) = name.rsplit_once('.')?;
+        if let Some(compound) = Self::from_alias(ext) {
+            return Some(compound);
+        }
+
+        let (_, inner) = rest.rsplit_once('.')?;
+        if !inner.eq_ignore_ascii_case("tar") {
+            return None;
+        }
+        match CompressionFormat::from_extension(ext)? {
+            CompressionFormat::Gzip => Some(CompoundFormat::Tgz),
+            CompressionFormat::Bzip2 => Some(CompoundFormat::Tbz2),
+            CompressionFormat::Xz => Some(CompoundFormat::Txz),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "tgz" => Some(CompoundFormat::Tgz),
@@ -115,10 +175,10 @@ pub enum OperationMode {
 #[derive(Debug, Clone)]
 pub struct InputFile {
     /// Original path provided by user
-    #[allow(dead_code)]
     pub original_path: PathBuf,
 
-    /// Resolved real path (after symlink resolution)
+    /// Resolved real path (the link target when this is a symlink)
+    #[allow(dead_code)]
     pub real_path: PathBuf,
 
     /// File basename
@@ -129,3 +189,24 @@ pub struct InputFile {
     #[allow(dead_code)]
     pub was_symlink: bool,
 }
+
+/// Kind of an archive member
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+/// One member of an archive, as listed from its headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Path inside the archive
+    pub path: PathBuf,
+
+    /// Uncompressed size in bytes (0 for directories and links)
+    pub size: u64,
+
+    pub kind: EntryKind,
+}
@@ -0,0 +1,281 @@
+//! AES-256-GCM in place, shared by every encryption type
+//!
+//! The data is encrypted or decrypted where it lies, so the caller decides
+//! where that is: a vector for small payloads, a memory-mapped output file
+//! for archives larger than memory.
+//!
+//! Version 2 containers seal the payload in chunks of `CHUNK_LEN` bytes,
+//! each followed by its tag, so the chunks are sealed and opened on all
+//! cores. Chunk `i` uses the container nonce with `i` XORed into its last
+//! eight bytes, and authenticates whether it is the last chunk, so chunks
+//! cannot be reordered, dropped or cut off at a chunk boundary unnoticed.
+//!
+//! Every function takes the associated data to authenticate along with the
+//! payload; containers pass their header (see
+//! `EncryptedContainer::associated_data`), which each chunk authenticates
+//! ahead of its last-chunk flag.
+
+use super::{CryptoError, CryptoResult};
+use rayon::prelude::*;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+
+/// Length of the authentication tag following the ciphertext
+pub const TAG_LEN: usize = 16;
+
+/// Plaintext length of every chunk but the last
+pub const CHUNK_LEN: u32 = 1 << 20;
+
+/// Encrypt `data` in place and return its authentication tag
+pub fn seal_in_place(
+    data: &mut [u8],
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+) -> CryptoResult<[u8; TAG_LEN]> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| CryptoError::EncryptionFailed("Failed to create key".to_string()))?;
+    let tag = LessSafeKey::new(key)
+        .seal_in_place_separate_tag(Nonce::assume_unique_for_key(*nonce), Aad::from(aad), data)
+        .map_err(|_| CryptoError::EncryptionFailed("Encryption failed".to_string()))?;
+
+    let mut bytes = [0u8; TAG_LEN];
+    bytes.copy_from_slice(tag.as_ref());
+    Ok(bytes)
+}
+
+/// Decrypt `data`, the ciphertext followed by its tag, in place and return
+/// the length of the plaintext now at its start
+pub fn open_in_place(
+    data: &mut [u8],
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+) -> CryptoResult<usize> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| CryptoError::DecryptionFailed("Failed to create key".to_string()))?;
+    let plaintext = LessSafeKey::new(key)
+        .open_in_place(Nonce::assume_unique_for_key(*nonce), Aad::from(aad), data)
+        .map_err(|_| CryptoError::AuthenticationFailed)?;
+    Ok(plaintext.len())
+}
+
+/// Length of `len` bytes sealed in chunks of `chunk` bytes
+///
+/// Even empty data has one chunk, so its tag marks the end.
+pub fn chunked_len(len: u64, chunk: u64) -> u64 {
+    len + len.div_ceil(chunk).max(1) * TAG_LEN as u64
+}
+
+/// The plaintext part of each chunk of `data`, laid out for sealing in
+/// chunks of `chunk` bytes, in order
+pub fn chunk_slots(data: &mut [u8], chunk: usize) -> impl Iterator<Item = &mut [u8]> {
+    data.chunks_mut(chunk + TAG_LEN).map(|sealed| {
+        let end = sealed.len() - TAG_LEN;
+        &mut sealed[..end]
+    })
+}
+
+/// Encrypt `data` in place, its plaintext already in `chunk_slots` of
+/// `chunk` bytes, writing the tag after each chunk
+pub fn seal_chunks_in_place(
+    data: &mut [u8],
+    chunk: usize,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+) -> CryptoResult<()> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| CryptoError::EncryptionFailed("Failed to create key".to_string()))?;
+    let key = LessSafeKey::new(key);
+    let count = data.len().div_ceil(chunk + TAG_LEN);
+
+    data.par_chunks_mut(chunk + TAG_LEN)
+        .enumerate()
+        .try_for_each(|(index, sealed)| {
+            let (plaintext, tag) = sealed.split_at_mut(sealed.len() - TAG_LEN);
+            let (nonce, aad) = chunk_nonce(nonce, aad, index, index + 1 == count);
+            let chunk_tag = key
+                .seal_in_place_separate_tag(nonce, aad, plaintext)
+                .map_err(|_| CryptoError::EncryptionFailed("Encryption failed".to_string()))?;
+            tag.copy_from_slice(chunk_tag.as_ref());
+            Ok(())
+        })
+}
+
+/// Decrypt `data`, sealed in chunks of `chunk` bytes, in place and return
+/// the length of the plaintext now at its start
+pub fn open_chunks_in_place(
+    data: &mut [u8],
+    chunk: usize,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+) -> CryptoResult<usize> {
+    // Every chunk, the last one included, needs at least its tag
+    let last = data.len() % (chunk + TAG_LEN);
+    if data.is_empty() || (last != 0 && last < TAG_LEN) {
+        return Err(CryptoError::AuthenticationFailed);
+    }
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| CryptoError::DecryptionFailed("Failed to create key".to_string()))?;
+    let key = LessSafeKey::new(key);
+    let count = data.len().div_ceil(chunk + TAG_LEN);
+
+    data.par_chunks_mut(chunk + TAG_LEN)
+        .enumerate()
+        .try_for_each(|(index, sealed)| {
+            let (nonce, aad) = chunk_nonce(nonce, aad, index, index + 1 == count);
+            key.open_in_place(nonce, aad, sealed)
+                .map(|_| ())
+                .map_err(|_| CryptoError::AuthenticationFailed)
+        })?;
+
+    // Close the gaps the tags leave between the chunks
+    let mut len = 0;
+    for index in 0..count {
+        let start = index * (chunk + TAG_LEN);
+        let end = (start + chunk).min(data.len() - TAG_LEN);
+        data.copy_within(start..end, len);
+        len += end - start;
+    }
+    Ok(len)
+}
+
+/// Nonce and associated data of chunk `index`: `aad` and the last-chunk
+/// flag
+fn chunk_nonce(nonce: &[u8; 12], aad: &[u8], index: usize, last: bool) -> (Nonce, Aad<Vec<u8>>) {
+    let mut bytes = *nonce;
+    for (byte, counter) in bytes[4..].iter_mut().zip((index as u64).to_be_bytes()) {
+        *byte ^= counter;
+    }
+    let mut aad = aad.to_vec();
+    aad.push(u8::from(last));
+    (Nonce::assume_unique_for_key(bytes), Aad::from(aad))
+}
+
+/// `data` sealed in chunks of `chunk` bytes into a new vector
+pub fn seal_chunks(
+    data: &[u8],
+    chunk: usize,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+) -> CryptoResult<Vec<u8>> {
+    let mut sealed = vec![0; chunked_len(data.len() as u64, chunk as u64) as usize];
+    for (slot, plaintext) in chunk_slots(&mut sealed, chunk).zip(data.chunks(chunk)) {
+        slot.copy_from_slice(plaintext);
+    }
+    seal_chunks_in_place(&mut sealed, chunk, key, nonce, aad)?;
+    Ok(sealed)
+}
+
+/// Plaintext of `sealed`, sealed in chunks of `chunk` bytes
+pub fn open_chunks(
+    sealed: &[u8],
+    chunk: usize,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+) -> CryptoResult<Vec<u8>> {
+    let mut data = sealed.to_vec();
+    let len = open_chunks_in_place(&mut data, chunk, key, nonce, aad)?;
+    data.truncate(len);
+    Ok(data)
+}
+
+/// `data` encrypted into a new vector, with the tag appended
+#[allow(dead_code)]
+pub fn seal(data: &[u8], key: &[u8; 32], nonce: &[u8; 12], aad: &[u8]) -> CryptoResult<Vec<u8>> {
+    let mut sealed = Vec::with_capacity(data.len() + TAG_LEN);
+    sealed.extend_from_slice(data);
+    let tag = seal_in_place(&mut sealed, key, nonce, aad)?;
+    sealed.extend_from_slice(&tag);
+    Ok(sealed)
+}
+
+/// Plaintext of `sealed`, the ciphertext followed by its tag
+pub fn open(sealed: &[u8], key: &[u8; 32], nonce: &[u8; 12], aad: &[u8]) -> CryptoResult<Vec<u8>> {
+    let mut data = sealed.to_vec();
+    let len = open_in_place(&mut data, key, nonce, aad)?;
+    data.truncate(len);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_place_round_trip() {
+        let key = [7u8; 32];
+        let nonce = [1u8; 12];
+        let mut data = b"in place data".to_vec();
+        let tag = seal_in_place(&mut data, &key, &nonce, b"header").unwrap();
+        assert_ne!(data, b"in place data");
+
+        // Same bytes as the vector form
+        let mut sealed = data.clone();
+        sealed.extend_from_slice(&tag);
+        assert_eq!(
+            seal(b"in place data", &key, &nonce, b"header").unwrap(),
+            sealed
+        );
+
+        let len = open_in_place(&mut sealed, &key, &nonce, b"header").unwrap();
+        assert_eq!(&sealed[..len], b"in place data");
+
+        let mut tampered = seal(b"in place data", &key, &nonce, b"header").unwrap();
+        tampered[0] ^= 1;
+        assert!(matches!(
+            open(&tampered, &key, &nonce, b"header"),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let key = [7u8; 32];
+        let nonce = [1u8; 12];
+        let data: Vec<u8> = (0..100u8).collect();
+
+        for len in [0, 1, 31, 32, 33, 64, 100] {
+            let sealed = seal_chunks(&data[..len], 32, &key, &nonce, b"header").unwrap();
+            assert_eq!(sealed.len() as u64, chunked_len(len as u64, 32));
+            assert_eq!(
+                open_chunks(&sealed, 32, &key, &nonce, b"header").unwrap(),
+                &data[..len]
+            );
+        }
+
+        // Four chunks of 32, 32, 32 and 4 bytes
+        let sealed = seal_chunks(&data, 32, &key, &nonce, b"header").unwrap();
+        let stride = 32 + TAG_LEN;
+        assert_eq!(sealed.len(), 100 + 4 * TAG_LEN);
+
+        // Cut off at a chunk boundary
+        assert!(matches!(
+            open_chunks(&sealed[..3 * stride], 32, &key, &nonce, b"header"),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+
+        // Two chunks swapped
+        let mut swapped = sealed.clone();
+        swapped[..stride].copy_from_slice(&sealed[stride..2 * stride]);
+        swapped[stride..2 * stride].copy_from_slice(&sealed[..stride]);
+        assert!(open_chunks(&swapped, 32, &key, &nonce, b"header").is_err());
+
+        // Tampered, or too short for a tag
+        let mut tampered = sealed.clone();
+        tampered[2 * stride] ^= 1;
+        assert!(open_chunks(&tampered, 32, &key, &nonce, b"header").is_err());
+        assert!(open_chunks(&sealed[..3 * stride + 3], 32, &key, &nonce, b"header").is_err());
+        assert!(open_chunks(&[], 32, &key, &nonce, b"header").is_err());
+
+        // Sealed with other associated data
+        assert!(matches!(
+            open_chunks(&sealed, 32, &key, &nonce, b"HEADER"),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+        assert!(open_chunks(&sealed, 32, &key, &nonce, b"").is_err());
+    }
+}
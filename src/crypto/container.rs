@@ -1,15 +1,47 @@
 //! Encrypted container format implementation
 
 use super::{CryptoError, CryptoResult, EncryptionMetadata, EncryptionType};
-use std::io::{Read, Write};
+use crate::utils::bufio::{buffered_reader, buffered_writer};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
 use std::path::Path;
 
 /// Magic bytes for JCZ encrypted files: "JCZE"
 const MAGIC_BYTES: [u8; 4] = [0x4A, 0x43, 0x5A, 0x45];
 
-/// Current container format version
+/// Container format version of data sealed in one piece
 const CONTAINER_VERSION: u8 = 1;
 
+/// Container format version of data sealed in chunks (see `aead`)
+///
+/// Older readers would fail these on their first tag, so they get a
+/// version they reject up front instead.
+const CHUNKED_VERSION: u8 = 2;
+
+/// Container format version of data sealed in chunks along with the header
+/// (see `associated_data`)
+///
+/// Nothing authenticates the header of the earlier versions, so their
+/// integrity tag, padding block size and chunk length can be stripped or
+/// changed unnoticed; they are only read.
+const BOUND_VERSION: u8 = 3;
+
+/// Marker of the optional integrity tag trailing the encryption metadata
+///
+/// Readers that predate the tag stop after the fields they know, so the
+/// container version is unchanged.
+const INTEGRITY_MARKER: [u8; 4] = *b"JCZI";
+
+/// Marker of the optional padding block size, after the integrity tag
+///
+/// The padding is framed inside the ciphertext (see `padding`); this
+/// marker tells readers to strip it.
+const PADDING_MARKER: [u8; 4] = *b"JCZP";
+
+/// Marker of the chunk length of a version 2 or 3 container, after the
+/// padding block size
+const CHUNK_MARKER: [u8; 4] = *b"JCZK";
+
 /// Encrypted container structure
 #[derive(Debug, Clone)]
 pub struct EncryptedContainer {
@@ -19,6 +51,12 @@ pub struct EncryptedContainer {
     pub encryption_type: EncryptionType,
     /// Encryption metadata
     pub metadata: EncryptionMetadata,
+    /// SHA-256 tree digest of the plaintext below every layer, if recorded
+    pub integrity: Option<[u8; 32]>,
+    /// Block size the plaintext was padded to, if padded
+    pub pad_to: Option<u64>,
+    /// Plaintext length of the chunks the data is sealed in, if chunked
+    pub chunk_len: Option<u32>,
     /// Encrypted data
     pub encrypted_data: Vec<u8>,
 }
@@ -34,28 +72,67 @@ impl EncryptedContainer {
             version: CONTAINER_VERSION,
             encryption_type,
             metadata,
+            integrity: None,
+            pad_to: None,
+            chunk_len: None,
             encrypted_data,
         }
     }
 
+    /// Record that the data is sealed in chunks of `len` bytes, along with
+    /// the header
+    pub fn set_chunk_len(&mut self, len: u32) {
+        self.version = BOUND_VERSION;
+        self.chunk_len = Some(len);
+    }
+
+    /// Associated data the encrypted data is sealed with: the magic,
+    /// version and type and the integrity tag, padding block size and chunk
+    /// length, so that none of them can be changed or stripped without
+    /// failing authentication
+    ///
+    /// The key material is left out, since altering it only yields the
+    /// wrong key, and a rewrapped key then needs no resealing. Empty for
+    /// versions 1 and 2.
+    pub fn associated_data(&self) -> Vec<u8> {
+        if self.version != BOUND_VERSION {
+            return Vec::new();
+        }
+        let mut bytes = MAGIC_BYTES.to_vec();
+        bytes.push(self.version);
+        bytes.push(self.encryption_type.to_u8());
+        bytes.extend_from_slice(&self.serialize_trailer());
+        bytes
+    }
+
     /// Write container to file
+    #[allow(dead_code)]
     pub fn write_to_file(&self, path: &Path) -> CryptoResult<()> {
-        let mut file = std::fs::File::create(path)?;
-        let bytes = self.to_bytes()?;
-        file.write_all(&bytes)?;
+        let mut file = buffered_writer(File::create(path)?);
+        file.write_all(&self.header_bytes()?)?;
+        file.write_all(&self.encrypted_data)?;
+        file.flush()?;
         Ok(())
     }
 
     /// Read container from file
     pub fn read_from_file(path: &Path) -> CryptoResult<Self> {
-        let mut file = std::fs::File::open(path)?;
-        let mut bytes = Vec::new();
-        file.read_to_end(&mut bytes)?;
-        Self::from_bytes(&bytes)
+        let mut file = buffered_reader(File::open(path)?);
+        let (mut container, _) = Self::read_header(&mut file)?;
+        file.read_to_end(&mut container.encrypted_data)?;
+        Ok(container)
     }
 
     /// Serialize container to bytes
     pub fn to_bytes(&self) -> CryptoResult<Vec<u8>> {
+        let mut bytes = self.header_bytes()?;
+        bytes.extend_from_slice(&self.encrypted_data);
+        Ok(bytes)
+    }
+
+    /// Everything before the encrypted data: magic, version, type and
+    /// metadata
+    pub fn header_bytes(&self) -> CryptoResult<Vec<u8>> {
         let mut bytes = Vec::new();
 
         // Magic bytes
@@ -73,73 +150,80 @@ impl EncryptedContainer {
         bytes.extend_from_slice(&metadata_len.to_le_bytes());
         bytes.extend_from_slice(&metadata_bytes);
 
-        // Encrypted data
-        bytes.extend_from_slice(&self.encrypted_data);
-
         Ok(bytes)
     }
 
     /// Deserialize container from bytes
-    pub fn from_bytes(bytes: &[u8]) -> CryptoResult<Self> {
-        if bytes.len() < 10 {
-            return Err(CryptoError::InvalidContainer(
-                "Container too small".to_string(),
-            ));
-        }
+    pub fn from_bytes(mut bytes: &[u8]) -> CryptoResult<Self> {
+        let (mut container, _) = Self::read_header(&mut bytes)?;
+        container.encrypted_data = bytes.to_vec();
+        Ok(container)
+    }
 
-        let mut pos = 0;
+    /// Read the header from `reader`, leaving it at the encrypted data
+    ///
+    /// Returns the container, its `encrypted_data` still empty, and the
+    /// length of the header.
+    pub fn read_header(reader: &mut impl Read) -> CryptoResult<(Self, usize)> {
+        let mut fixed = [0u8; 10];
+        reader.read_exact(&mut fixed).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => {
+                CryptoError::InvalidContainer("Container too small".to_string())
+            }
+            _ => e.into(),
+        })?;
 
         // Check magic bytes
-        if &bytes[pos..pos + 4] != &MAGIC_BYTES {
+        if fixed[..4] != MAGIC_BYTES {
             return Err(CryptoError::InvalidContainer(
                 "Invalid magic bytes".to_string(),
             ));
         }
-        pos += 4;
 
         // Read version
-        let version = bytes[pos];
-        pos += 1;
-
-        if version != CONTAINER_VERSION {
+        let version = fixed[4];
+        if ![CONTAINER_VERSION, CHUNKED_VERSION, BOUND_VERSION].contains(&version) {
             return Err(CryptoError::UnsupportedVersion(version));
         }
 
         // Read encryption type
-        let encryption_type = EncryptionType::from_u8(bytes[pos])
+        let encryption_type = EncryptionType::from_u8(fixed[5])
             .ok_or_else(|| CryptoError::InvalidContainer("Invalid encryption type".to_string()))?;
-        pos += 1;
-
-        // Read metadata length
-        if bytes.len() < pos + 4 {
-            return Err(CryptoError::InvalidContainer(
-                "Missing metadata length".to_string(),
-            ));
-        }
-        let metadata_len =
-            u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
-                as usize;
-        pos += 4;
 
-        // Read metadata
-        if bytes.len() < pos + metadata_len {
+        // Read metadata, never trusting its length for an allocation
+        let metadata_len = u32::from_le_bytes([fixed[6], fixed[7], fixed[8], fixed[9]]) as usize;
+        let mut metadata_bytes = Vec::new();
+        reader
+            .take(metadata_len as u64)
+            .read_to_end(&mut metadata_bytes)?;
+        if metadata_bytes.len() < metadata_len {
             return Err(CryptoError::InvalidContainer(
                 "Truncated metadata".to_string(),
             ));
         }
-        let metadata_bytes = &bytes[pos..pos + metadata_len];
-        let metadata = Self::deserialize_metadata(encryption_type, metadata_bytes)?;
-        pos += metadata_len;
-
-        // Read encrypted data
-        let encrypted_data = bytes[pos..].to_vec();
+        let metadata = Self::deserialize_metadata(encryption_type, &metadata_bytes)?;
+        let (integrity, pad_to, chunk_len) =
+            Self::deserialize_trailer(&metadata_bytes[metadata.size()..]);
+        let chunk_len = match (version, chunk_len) {
+            (CONTAINER_VERSION, _) => None,
+            (_, Some(len)) if len > 0 => Some(len),
+            _ => {
+                return Err(CryptoError::InvalidContainer(
+                    "Missing chunk length".to_string(),
+                ));
+            }
+        };
 
-        Ok(Self {
+        let container = Self {
             version,
             encryption_type,
             metadata,
-            encrypted_data,
-        })
+            integrity,
+            pad_to,
+            chunk_len,
+            encrypted_data: Vec::new(),
+        };
+        Ok((container, fixed.len() + metadata_len))
     }
 
     /// Serialize metadata to bytes
@@ -167,11 +251,81 @@ impl EncryptedContainer {
                 bytes.extend_from_slice(encrypted_key);
                 bytes.extend_from_slice(nonce);
             }
+            EncryptionMetadata::Threshold {
+                key_id,
+                threshold,
+                shares,
+                nonce,
+            } => {
+                bytes.extend_from_slice(key_id);
+                bytes.push(*threshold);
+                bytes.push(*shares);
+                bytes.extend_from_slice(nonce);
+            }
+            EncryptionMetadata::Keyfile { key_id, nonce } => {
+                bytes.extend_from_slice(key_id);
+                bytes.extend_from_slice(nonce);
+            }
+            EncryptionMetadata::X25519 {
+                ephemeral_public,
+                nonce,
+            } => {
+                bytes.extend_from_slice(ephemeral_public);
+                bytes.extend_from_slice(nonce);
+            }
         }
 
+        bytes.extend_from_slice(&self.serialize_trailer());
         Ok(bytes)
     }
 
+    /// Serialize the integrity tag, padding block size and chunk length
+    /// that follow the known metadata fields
+    fn serialize_trailer(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        if let Some(digest) = &self.integrity {
+            bytes.extend_from_slice(&INTEGRITY_MARKER);
+            bytes.extend_from_slice(digest);
+        }
+
+        if let Some(block) = self.pad_to {
+            bytes.extend_from_slice(&PADDING_MARKER);
+            bytes.extend_from_slice(&block.to_le_bytes());
+        }
+
+        if let Some(len) = self.chunk_len {
+            bytes.extend_from_slice(&CHUNK_MARKER);
+            bytes.extend_from_slice(&len.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Read the integrity tag, padding block size and chunk length from the
+    /// bytes after the known metadata fields
+    fn deserialize_trailer(mut trailer: &[u8]) -> (Option<[u8; 32]>, Option<u64>, Option<u32>) {
+        let mut integrity = None;
+        if let Some(rest) = trailer.strip_prefix(&INTEGRITY_MARKER) {
+            integrity = rest.get(..32).and_then(|d| d.try_into().ok());
+            trailer = rest.get(32..).unwrap_or_default();
+        }
+        let mut pad_to = None;
+        if let Some(rest) = trailer.strip_prefix(&PADDING_MARKER) {
+            pad_to = rest
+                .get(..8)
+                .and_then(|block| block.try_into().ok())
+                .map(u64::from_le_bytes);
+            trailer = rest.get(8..).unwrap_or_default();
+        }
+        let chunk_len = trailer
+            .strip_prefix(&CHUNK_MARKER)
+            .and_then(|rest| rest.get(..4))
+            .and_then(|len| len.try_into().ok())
+            .map(u32::from_le_bytes);
+        (integrity, pad_to, chunk_len)
+    }
+
     /// Deserialize metadata from bytes
     fn deserialize_metadata(
         encryption_type: EncryptionType,
@@ -230,9 +384,67 @@ impl EncryptedContainer {
                     nonce,
                 })
             }
+            EncryptionType::Threshold => {
+                if bytes.len() < 16 + 1 + 1 + 12 {
+                    return Err(CryptoError::InvalidContainer(
+                        "Invalid threshold metadata size".to_string(),
+                    ));
+                }
+
+                let mut key_id = [0u8; 16];
+                key_id.copy_from_slice(&bytes[0..16]);
+
+                let mut nonce = [0u8; 12];
+                nonce.copy_from_slice(&bytes[18..30]);
+
+                Ok(EncryptionMetadata::Threshold {
+                    key_id,
+                    threshold: bytes[16],
+                    shares: bytes[17],
+                    nonce,
+                })
+            }
+            EncryptionType::Keyfile => {
+                if bytes.len() < 16 + 12 {
+                    return Err(CryptoError::InvalidContainer(
+                        "Invalid keyfile metadata size".to_string(),
+                    ));
+                }
+
+                let mut key_id = [0u8; 16];
+                key_id.copy_from_slice(&bytes[0..16]);
+
+                let mut nonce = [0u8; 12];
+                nonce.copy_from_slice(&bytes[16..28]);
+
+                Ok(EncryptionMetadata::Keyfile { key_id, nonce })
+            }
+            EncryptionType::X25519 => {
+                if bytes.len() < 32 + 12 {
+                    return Err(CryptoError::InvalidContainer(
+                        "Invalid X25519 metadata size".to_string(),
+                    ));
+                }
+
+                let mut ephemeral_public = [0u8; 32];
+                ephemeral_public.copy_from_slice(&bytes[0..32]);
+
+                let mut nonce = [0u8; 12];
+                nonce.copy_from_slice(&bytes[32..44]);
+
+                Ok(EncryptionMetadata::X25519 {
+                    ephemeral_public,
+                    nonce,
+                })
+            }
         }
     }
 
+    /// Check whether the given bytes start with the JCZE magic
+    pub fn has_magic(bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC_BYTES)
+    }
+
     /// Get the encryption type
     #[allow(dead_code)]
     pub fn get_encryption_type(&self) -> EncryptionType {
@@ -391,4 +603,137 @@ mod tests {
         let result = EncryptedContainer::from_bytes(&bytes);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_integrity_tag_round_trip() {
+        let metadata = EncryptionMetadata::Rsa {
+            encrypted_key: vec![7; 256],
+            nonce: [3; 12],
+        };
+        let mut container =
+            EncryptedContainer::new(EncryptionType::Rsa, metadata.clone(), vec![1, 2, 3]);
+        let untagged = container.to_bytes().unwrap();
+        assert_eq!(
+            EncryptedContainer::from_bytes(&untagged).unwrap().integrity,
+            None
+        );
+
+        container.integrity = Some([0xab; 32]);
+        let parsed = EncryptedContainer::from_bytes(&container.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.integrity, Some([0xab; 32]));
+        assert_eq!(parsed.metadata, metadata);
+        assert_eq!(parsed.encrypted_data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_threshold_container_round_trip() {
+        let metadata = EncryptionMetadata::Threshold {
+            key_id: [0x11; 16],
+            threshold: 3,
+            shares: 5,
+            nonce: [4; 12],
+        };
+        let mut container =
+            EncryptedContainer::new(EncryptionType::Threshold, metadata.clone(), vec![9; 40]);
+        container.integrity = Some([0xcd; 32]);
+
+        let parsed = EncryptedContainer::from_bytes(&container.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.encryption_type, EncryptionType::Threshold);
+        assert_eq!(parsed.metadata, metadata);
+        assert_eq!(parsed.integrity, Some([0xcd; 32]));
+        assert_eq!(parsed.encrypted_data, vec![9; 40]);
+    }
+
+    #[test]
+    fn test_padding_block_round_trip() {
+        let metadata = EncryptionMetadata::Keyfile {
+            key_id: [0x22; 16],
+            nonce: [5; 12],
+        };
+        let mut container =
+            EncryptedContainer::new(EncryptionType::Keyfile, metadata.clone(), vec![8; 64]);
+        container.pad_to = Some(4096);
+
+        let parsed = EncryptedContainer::from_bytes(&container.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.pad_to, Some(4096));
+        assert_eq!(parsed.integrity, None);
+
+        container.integrity = Some([0xef; 32]);
+        let parsed = EncryptedContainer::from_bytes(&container.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.pad_to, Some(4096));
+        assert_eq!(parsed.integrity, Some([0xef; 32]));
+        assert_eq!(parsed.metadata, metadata);
+    }
+
+    #[test]
+    fn test_chunk_len_round_trip() {
+        let metadata = EncryptionMetadata::Keyfile {
+            key_id: [0x33; 16],
+            nonce: [6; 12],
+        };
+        let mut container =
+            EncryptedContainer::new(EncryptionType::Keyfile, metadata.clone(), vec![9; 64]);
+        container.set_chunk_len(1 << 20);
+        container.pad_to = Some(512);
+        container.integrity = Some([0x12; 32]);
+
+        let bytes = container.to_bytes().unwrap();
+        assert_eq!(bytes[4], BOUND_VERSION);
+        let parsed = EncryptedContainer::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.chunk_len, Some(1 << 20));
+        assert_eq!(parsed.pad_to, Some(512));
+        assert_eq!(parsed.integrity, Some([0x12; 32]));
+
+        // Version 1 data is never chunked, and later versions always are
+        let mut plain = EncryptedContainer::new(EncryptionType::Keyfile, metadata, vec![9; 64]);
+        let parsed = EncryptedContainer::from_bytes(&plain.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            (parsed.version, parsed.chunk_len),
+            (CONTAINER_VERSION, None)
+        );
+        for version in [CHUNKED_VERSION, BOUND_VERSION] {
+            plain.version = version;
+            assert!(matches!(
+                EncryptedContainer::from_bytes(&plain.to_bytes().unwrap()),
+                Err(CryptoError::InvalidContainer(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_associated_data_covers_trailer_but_not_key() {
+        let metadata = EncryptionMetadata::Rsa {
+            encrypted_key: vec![7; 256],
+            nonce: [3; 12],
+        };
+        let mut container = EncryptedContainer::new(EncryptionType::Rsa, metadata, Vec::new());
+        container.set_chunk_len(1 << 20);
+        container.integrity = Some([0xab; 32]);
+        let aad = container.associated_data();
+        assert_eq!(&aad[..6], &[0x4A, 0x43, 0x5A, 0x45, BOUND_VERSION, 2]);
+
+        // Stripping or changing any trailer field changes it
+        let changes: [fn(&mut EncryptedContainer); 3] = [
+            |c| c.integrity = None,
+            |c| c.pad_to = Some(4096),
+            |c| c.chunk_len = Some(1 << 10),
+        ];
+        for change in changes {
+            let mut changed = container.clone();
+            change(&mut changed);
+            assert_ne!(changed.associated_data(), aad);
+        }
+
+        // A rewrapped key does not
+        let mut rewrapped = container.clone();
+        rewrapped.metadata = EncryptionMetadata::Rsa {
+            encrypted_key: vec![8; 512],
+            nonce: [3; 12],
+        };
+        assert_eq!(rewrapped.associated_data(), aad);
+
+        // Earlier versions authenticate no header
+        container.version = CHUNKED_VERSION;
+        assert!(container.associated_data().is_empty());
+    }
 }
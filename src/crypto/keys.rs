@@ -1,11 +1,18 @@
 //! Key management utilities
 
 use super::{CryptoError, CryptoResult};
+use ring::rand::{SecureRandom, SystemRandom};
 use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
 use rsa::traits::PublicKeyParts;
 use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
 use std::fs;
+use std::io::Write;
 use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Size of a symmetric keyfile: one raw AES-256 key
+pub const KEYFILE_SIZE: usize = 32;
 
 /// Validate that a key file exists and is readable
 pub fn validate_key_file(path: &Path) -> CryptoResult<()> {
@@ -65,6 +72,79 @@ pub fn read_public_key_pem(path: &Path) -> CryptoResult<RsaPublicKey> {
     Ok(public_key)
 }
 
+/// Read a symmetric keyfile of exactly `KEYFILE_SIZE` random bytes
+pub fn read_keyfile(path: &Path) -> CryptoResult<Zeroizing<[u8; 32]>> {
+    validate_key_file(path)?;
+
+    let data = Zeroizing::new(
+        fs::read(path).map_err(|_| CryptoError::KeyFileNotReadable(path.to_path_buf()))?,
+    );
+    if data.len() != KEYFILE_SIZE {
+        return Err(CryptoError::InvalidKeyfile {
+            path: path.to_path_buf(),
+            size: data.len(),
+        });
+    }
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    key.copy_from_slice(&data);
+    Ok(key)
+}
+
+/// Write a new random keyfile readable only by its owner; an existing
+/// file is never overwritten
+pub fn generate_keyfile(path: &Path) -> CryptoResult<()> {
+    let mut key = Zeroizing::new([0u8; KEYFILE_SIZE]);
+    SystemRandom::new()
+        .fill(key.as_mut())
+        .map_err(|_| CryptoError::EncryptionFailed("Failed to generate keyfile".to_string()))?;
+
+    write_new_private_file(path, key.as_ref())
+}
+
+/// Create `path` readable only by its owner and write `contents`; fails if
+/// it already exists
+pub fn write_new_private_file(path: &Path, contents: &[u8]) -> CryptoResult<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Generate an RSA key pair; returns the private (PKCS#8) and public PEM
+pub fn generate_rsa_key_pair(bits: usize) -> CryptoResult<(Zeroizing<String>, String)> {
+    use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
+
+    validate_key_size(bits)?;
+    let private_key = RsaPrivateKey::new(&mut rand::rngs::OsRng, bits)
+        .map_err(|e| CryptoError::RsaError(format!("Failed to generate key: {}", e)))?;
+    let private_pem = private_key
+        .to_pkcs8_pem(LineEnding::LF)
+        .map_err(|e| CryptoError::RsaError(format!("Failed to encode private key: {}", e)))?;
+    let public_pem = RsaPublicKey::from(&private_key)
+        .to_public_key_pem(LineEnding::LF)
+        .map_err(|e| CryptoError::RsaError(format!("Failed to encode public key: {}", e)))?;
+
+    Ok((Zeroizing::new(private_pem.to_string()), public_pem))
+}
+
+/// Identifier of a keyfile key, stored in containers to tell a wrong
+/// keyfile apart from corrupted data; reveals nothing about the key
+pub fn keyfile_id(key: &[u8; 32]) -> [u8; 16] {
+    let digest = Sha256::new()
+        .chain_update(b"jcz keyfile id")
+        .chain_update(key)
+        .finalize();
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&digest[..16]);
+    id
+}
+
 /// Validate RSA key size (minimum 2048 bits)
 pub fn validate_key_size(key_bits: usize) -> CryptoResult<()> {
     const MIN_KEY_SIZE: usize = 2048;
@@ -155,6 +235,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_generate_and_read_keyfile() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("secret.bin");
+
+        generate_keyfile(&path).unwrap();
+        let key = read_keyfile(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), key.as_ref());
+        assert_eq!(keyfile_id(&key), keyfile_id(&key));
+        assert_ne!(keyfile_id(&key), keyfile_id(&[0; 32]));
+
+        // Never overwrites
+        assert!(generate_keyfile(&path).is_err());
+        assert_eq!(*read_keyfile(&path).unwrap(), *key);
+
+        fs::write(&path, b"too short").unwrap();
+        assert!(matches!(
+            read_keyfile(&path),
+            Err(CryptoError::InvalidKeyfile { size: 9, .. })
+        ));
+    }
+
     #[test]
     fn test_read_invalid_pem() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -3,18 +3,30 @@
 //! This module provides encryption capabilities for compressed files using:
 //! - Password-based encryption with AES-256-GCM and Argon2id key derivation
 //! - RSA public-key encryption with OAEP padding
+//! - Shamir secret sharing of the key, so K of N share holders must agree
+//! - Raw 32-byte keyfiles for unattended use
+//! - X25519 public-key encryption (ECIES with HKDF-SHA256)
+//!
+//! Any of them can pad the payload to hide the exact size of the data.
 
+pub mod aead;
 pub mod container;
 pub mod keys;
+pub mod padding;
 pub mod password;
 pub mod rsa;
+pub mod shamir;
+pub mod x25519;
 
 use std::path::PathBuf;
+use thiserror::Error;
 
 // Re-export commonly used types
 pub use container::EncryptedContainer;
 pub use password::PasswordEncryption;
 pub use rsa::RsaEncryption;
+pub use shamir::KeyShare;
+pub use x25519::X25519Encryption;
 
 /// Encryption type identifier
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,6 +35,12 @@ pub enum EncryptionType {
     Password = 0x01,
     /// RSA encryption (0x02)
     Rsa = 0x02,
+    /// Key split into Shamir shares (0x03)
+    Threshold = 0x03,
+    /// Symmetric keyfile (0x04)
+    Keyfile = 0x04,
+    /// X25519 public-key encryption (0x05)
+    X25519 = 0x05,
 }
 
 impl EncryptionType {
@@ -31,6 +49,9 @@ impl EncryptionType {
         match value {
             0x01 => Some(EncryptionType::Password),
             0x02 => Some(EncryptionType::Rsa),
+            0x03 => Some(EncryptionType::Threshold),
+            0x04 => Some(EncryptionType::Keyfile),
+            0x05 => Some(EncryptionType::X25519),
             _ => None,
         }
     }
@@ -76,92 +97,100 @@ pub enum EncryptionMetadata {
         encrypted_key: Vec<u8>,
         nonce: [u8; 12],
     },
+    /// Shamir-split key metadata; the shares live in separate files
+    Threshold {
+        key_id: [u8; 16],
+        threshold: u8,
+        shares: u8,
+        nonce: [u8; 12],
+    },
+    /// Keyfile encryption metadata; `key_id` identifies the keyfile
+    Keyfile { key_id: [u8; 16], nonce: [u8; 12] },
+    /// X25519 metadata: the sender's one-time public key
+    X25519 {
+        ephemeral_public: [u8; 32],
+        nonce: [u8; 12],
+    },
+}
+
+impl EncryptionMetadata {
+    /// Nonce of the AES-GCM encryption of the data
+    pub fn nonce(&self) -> &[u8; 12] {
+        match self {
+            EncryptionMetadata::Password { nonce, .. }
+            | EncryptionMetadata::Rsa { nonce, .. }
+            | EncryptionMetadata::Threshold { nonce, .. }
+            | EncryptionMetadata::Keyfile { nonce, .. }
+            | EncryptionMetadata::X25519 { nonce, .. } => nonce,
+        }
+    }
+
+    /// Serialized length in the container header
+    pub fn size(&self) -> usize {
+        match self {
+            EncryptionMetadata::Password { .. } => 32 + 12 + 12,
+            EncryptionMetadata::Rsa { encrypted_key, .. } => 4 + encrypted_key.len() + 12,
+            EncryptionMetadata::Threshold { .. } => 16 + 1 + 1 + 12,
+            EncryptionMetadata::Keyfile { .. } => 16 + 12,
+            EncryptionMetadata::X25519 { .. } => 32 + 12,
+        }
+    }
 }
 
 /// Cryptography error types
-#[derive(Debug)]
+#[derive(Debug, Error)]
+#[non_exhaustive]
 #[allow(dead_code)]
 pub enum CryptoError {
     /// Invalid or empty password
+    #[error("Invalid or empty password")]
     InvalidPassword,
     /// Invalid key
+    #[error("Invalid encryption key")]
     InvalidKey,
     /// Key derivation failed
+    #[error("Key derivation failed: {0}")]
     KeyDerivationFailed(String),
     /// Encryption operation failed
+    #[error("Encryption failed: {0}")]
     EncryptionFailed(String),
     /// Decryption operation failed
+    #[error("Decryption failed: {0}")]
     DecryptionFailed(String),
-    /// Authentication failed (wrong password/key)
+    /// Authentication failed (wrong password or key, or tampered data)
+    #[error("Authentication failed: incorrect password or key, or corrupted data")]
     AuthenticationFailed,
+    /// Unusable key share file or set of shares
+    #[error("Invalid key share: {0}")]
+    InvalidShare(String),
     /// Invalid container format
+    #[error("Invalid container format: {0}")]
     InvalidContainer(String),
     /// Unsupported container version
+    #[error("Unsupported container version: {0}")]
     UnsupportedVersion(u8),
     /// I/O error
-    IoError(std::io::Error),
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
     /// RSA error
+    #[error("RSA error: {0}")]
     RsaError(String),
     /// Key file not found
+    #[error("Key file not found: {}", .0.display())]
     KeyFileNotFound(PathBuf),
     /// Key file not readable
+    #[error("Key file not readable: {}", .0.display())]
     KeyFileNotReadable(PathBuf),
+    /// Keyfile of the wrong size
+    #[error("Invalid keyfile {}: expected 32 bytes, found {size}", .path.display())]
+    InvalidKeyfile { path: PathBuf, size: usize },
     /// Invalid PEM format
+    #[error("Invalid PEM format: {0}")]
     InvalidPemFormat(String),
     /// Key size too small
+    #[error("Key size too small: {actual} bits (minimum: {minimum} bits)")]
     KeySizeTooSmall { actual: usize, minimum: usize },
 }
 
-impl std::fmt::Display for CryptoError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CryptoError::InvalidPassword => write!(f, "Invalid or empty password"),
-            CryptoError::InvalidKey => write!(f, "Invalid encryption key"),
-            CryptoError::KeyDerivationFailed(msg) => write!(f, "Key derivation failed: {}", msg),
-            CryptoError::EncryptionFailed(msg) => write!(f, "Encryption failed: {}", msg),
-            CryptoError::DecryptionFailed(msg) => write!(f, "Decryption failed: {}", msg),
-            CryptoError::AuthenticationFailed => {
-                write!(
-                    f,
-                    "Authentication failed: incorrect password or corrupted data"
-                )
-            }
-            CryptoError::InvalidContainer(msg) => write!(f, "Invalid container format: {}", msg),
-            CryptoError::UnsupportedVersion(v) => {
-                write!(f, "Unsupported container version: {}", v)
-            }
-            CryptoError::IoError(err) => write!(f, "I/O error: {}", err),
-            CryptoError::RsaError(msg) => write!(f, "RSA error: {}", msg),
-            CryptoError::KeyFileNotFound(path) => {
-                write!(f, "Key file not found: {}", path.display())
-            }
-            CryptoError::KeyFileNotReadable(path) => {
-                write!(f, "Key file not readable: {}", path.display())
-            }
-            CryptoError::InvalidPemFormat(msg) => write!(f, "Invalid PEM format: {}", msg),
-            CryptoError::KeySizeTooSmall { actual, minimum } => write!(
-                f,
-                "Key size too small: {} bits (minimum: {} bits)",
-                actual, minimum
-            ),
-        }
-    }
-}
-
-impl std::error::Error for CryptoError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match self {
-            CryptoError::IoError(err) => Some(err),
-            _ => None,
-        }
-    }
-}
-
-impl From<std::io::Error> for CryptoError {
-    fn from(err: std::io::Error) -> Self {
-        CryptoError::IoError(err)
-    }
-}
-
 /// Result type for crypto operations
 pub type CryptoResult<T> = Result<T, CryptoError>;
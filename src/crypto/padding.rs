@@ -0,0 +1,92 @@
+//! Size padding of encrypted payloads (`--pad-to`)
+//!
+//! The plaintext is framed as a magic, its real length and zero bytes up
+//! to the next multiple of the block size. The frame is encrypted along
+//! with the data and the block size recorded in the header is its
+//! associated data, so the padding cannot be altered or stripped without
+//! failing authentication, and the file size only reveals the number of
+//! blocks.
+
+use super::{CryptoError, CryptoResult};
+use std::ops::Range;
+
+/// First bytes of a padded plaintext
+const PADDING_MAGIC: [u8; 8] = *b"JCZPAD01";
+
+/// Magic plus the little-endian length of the data
+pub const FRAME_HEADER_LEN: usize = PADDING_MAGIC.len() + 8;
+
+/// Length of `len` bytes of data once framed and padded to `block`
+pub fn padded_len(len: u64, block: u64) -> u64 {
+    let block = block.max(1);
+    (FRAME_HEADER_LEN as u64 + len).div_ceil(block) * block
+}
+
+/// Frame header of `len` bytes of data
+pub fn frame_header(len: u64) -> [u8; FRAME_HEADER_LEN] {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    header[..PADDING_MAGIC.len()].copy_from_slice(&PADDING_MAGIC);
+    header[PADDING_MAGIC.len()..].copy_from_slice(&len.to_le_bytes());
+    header
+}
+
+/// Frame `data` and zero-pad it to a multiple of `block` bytes
+pub fn pad(data: &[u8], block: u64) -> Vec<u8> {
+    let padded = padded_len(data.len() as u64, block);
+
+    let mut out = Vec::with_capacity(padded as usize);
+    out.extend_from_slice(&frame_header(data.len() as u64));
+    out.extend_from_slice(data);
+    out.resize(padded as usize, 0);
+    out
+}
+
+/// Where the data of a plaintext framed by `pad` lies in it
+pub fn data_range(padded: &[u8]) -> CryptoResult<Range<usize>> {
+    let invalid = || CryptoError::InvalidContainer("Invalid padding".to_string());
+
+    if padded.len() < FRAME_HEADER_LEN || padded[..PADDING_MAGIC.len()] != PADDING_MAGIC {
+        return Err(invalid());
+    }
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&padded[PADDING_MAGIC.len()..FRAME_HEADER_LEN]);
+    let len = usize::try_from(u64::from_le_bytes(len)).map_err(|_| invalid())?;
+    if len > padded.len() - FRAME_HEADER_LEN {
+        return Err(invalid());
+    }
+    Ok(FRAME_HEADER_LEN..FRAME_HEADER_LEN + len)
+}
+
+/// Data of a plaintext framed by `pad`
+pub fn unpad(mut padded: Vec<u8>) -> CryptoResult<Vec<u8>> {
+    let range = data_range(&padded)?;
+    padded.truncate(range.end);
+    padded.drain(..range.start);
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_round_trip() {
+        for data in [&b""[..], b"x", &[7u8; 4096], &[1u8; 5000]] {
+            let padded = pad(data, 4096);
+            assert_eq!(padded.len() % 4096, 0);
+            assert_eq!(unpad(padded).unwrap(), data);
+        }
+        // Sizes in the same block look the same
+        assert_eq!(pad(b"short", 1024).len(), pad(&[0; 900], 1024).len());
+        assert_eq!(padded_len(5000, 4096), pad(&[1u8; 5000], 4096).len() as u64);
+    }
+
+    #[test]
+    fn test_unpad_rejects_bad_frames() {
+        assert!(unpad(b"not padded at all".to_vec()).is_err());
+
+        let mut padded = pad(b"data", 64);
+        padded[8] = 200;
+        assert!(unpad(padded).is_err());
+    }
+}
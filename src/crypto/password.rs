@@ -1,6 +1,6 @@
 //! Password-based encryption implementation
 
-use super::{Argon2Params, CryptoError, CryptoResult};
+use super::{aead, Argon2Params, CryptoError, CryptoResult};
 use argon2::{Argon2, Version};
 use ring::rand::{SecureRandom, SystemRandom};
 
@@ -67,73 +67,19 @@ impl PasswordEncryption {
     }
 
     /// Encrypt data with AES-256-GCM
+    #[allow(dead_code)]
     pub fn encrypt(data: &[u8], key: &[u8; 32], nonce: &[u8; 12]) -> CryptoResult<Vec<u8>> {
-        use ring::aead::{
-            Aad, BoundKey, Nonce, NonceSequence, SealingKey, UnboundKey, AES_256_GCM,
-        };
-        use ring::error::Unspecified;
-
-        // Create a nonce sequence that returns our nonce once
-        struct SingleNonce([u8; 12]);
-
-        impl NonceSequence for SingleNonce {
-            fn advance(&mut self) -> Result<Nonce, Unspecified> {
-                Nonce::try_assume_unique_for_key(&self.0)
-            }
-        }
-
-        // Create sealing key
-        let unbound_key = UnboundKey::new(&AES_256_GCM, key)
-            .map_err(|_| CryptoError::EncryptionFailed("Failed to create key".to_string()))?;
-        let nonce_sequence = SingleNonce(*nonce);
-        let mut sealing_key = SealingKey::new(unbound_key, nonce_sequence);
-
-        // Prepare data for encryption (ring modifies in place)
-        let mut in_out = data.to_vec();
-
-        // Seal (encrypt and authenticate)
-        sealing_key
-            .seal_in_place_append_tag(Aad::empty(), &mut in_out)
-            .map_err(|_| CryptoError::EncryptionFailed("Encryption failed".to_string()))?;
-
-        Ok(in_out)
+        aead::seal(data, key, nonce, &[])
     }
 
     /// Decrypt data with AES-256-GCM
+    #[allow(dead_code)]
     pub fn decrypt(
         encrypted_data: &[u8],
         key: &[u8; 32],
         nonce: &[u8; 12],
     ) -> CryptoResult<Vec<u8>> {
-        use ring::aead::{
-            Aad, BoundKey, Nonce, NonceSequence, OpeningKey, UnboundKey, AES_256_GCM,
-        };
-        use ring::error::Unspecified;
-
-        // Create a nonce sequence that returns our nonce once
-        struct SingleNonce([u8; 12]);
-
-        impl NonceSequence for SingleNonce {
-            fn advance(&mut self) -> Result<Nonce, Unspecified> {
-                Nonce::try_assume_unique_for_key(&self.0)
-            }
-        }
-
-        // Create opening key
-        let unbound_key = UnboundKey::new(&AES_256_GCM, key)
-            .map_err(|_| CryptoError::DecryptionFailed("Failed to create key".to_string()))?;
-        let nonce_sequence = SingleNonce(*nonce);
-        let mut opening_key = OpeningKey::new(unbound_key, nonce_sequence);
-
-        // Prepare data for decryption (ring modifies in place)
-        let mut in_out = encrypted_data.to_vec();
-
-        // Open (decrypt and verify authentication)
-        let decrypted = opening_key
-            .open_in_place(Aad::empty(), &mut in_out)
-            .map_err(|_| CryptoError::AuthenticationFailed)?;
-
-        Ok(decrypted.to_vec())
+        aead::open(encrypted_data, key, nonce, &[])
     }
 }
 
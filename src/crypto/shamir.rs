@@ -0,0 +1,287 @@
+//! Shamir secret sharing of symmetric keys over GF(256)
+//!
+//! Each byte of the key is the constant term of its own random polynomial
+//! of degree `threshold - 1`; share `i` holds the polynomials evaluated at
+//! `x = i`. Any `threshold` shares recover the key by Lagrange
+//! interpolation at `x = 0`, fewer reveal nothing about it.
+
+use super::{CryptoError, CryptoResult};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+use zeroize::{Zeroize, Zeroizing};
+
+/// First line of a share file
+const SHARE_HEADER: &str = "jcz key share v1";
+
+/// One share of a split symmetric key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    /// Random identifier of the split key, also stored in the container
+    pub key_id: [u8; 16],
+    /// Number of shares needed to recover the key
+    pub threshold: u8,
+    /// Evaluation point, 1 to 255
+    pub index: u8,
+    /// Polynomial values at `index`, one per key byte
+    pub value: [u8; 32],
+}
+
+impl Drop for KeyShare {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl KeyShare {
+    /// Share file contents: short text lines that survive copy and paste
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\nkey-id: {}\nthreshold: {}\nindex: {}\nshare: {}\n",
+            SHARE_HEADER,
+            to_hex(&self.key_id),
+            self.threshold,
+            self.index,
+            to_hex(&self.value)
+        )
+    }
+
+    /// Parse share file contents
+    pub fn from_text(text: &str) -> CryptoResult<Self> {
+        let invalid = |msg: &str| CryptoError::InvalidShare(msg.to_string());
+
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        if lines.next() != Some(SHARE_HEADER) {
+            return Err(invalid("not a jcz key share"));
+        }
+
+        let (mut key_id, mut threshold, mut index, mut value) = (None, None, None, None);
+        for line in lines {
+            let (field, content) = line
+                .split_once(':')
+                .ok_or_else(|| invalid("malformed line"))?;
+            let content = content.trim();
+            match field.trim() {
+                "key-id" => key_id = from_hex::<16>(content),
+                "threshold" => threshold = content.parse::<u8>().ok(),
+                "index" => index = content.parse::<u8>().ok(),
+                "share" => value = from_hex::<32>(content),
+                _ => {}
+            }
+        }
+
+        let share = Self {
+            key_id: key_id.ok_or_else(|| invalid("missing or bad key-id"))?,
+            threshold: threshold.ok_or_else(|| invalid("missing or bad threshold"))?,
+            index: index.ok_or_else(|| invalid("missing or bad index"))?,
+            value: value.ok_or_else(|| invalid("missing or bad share"))?,
+        };
+        if share.index == 0 || share.threshold < 2 {
+            return Err(invalid("index and threshold out of range"));
+        }
+        Ok(share)
+    }
+
+    /// Write the share as a text file readable only by its owner
+    pub fn write_to_file(&self, path: &Path) -> CryptoResult<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(path)?;
+        file.write_all(self.to_text().as_bytes())?;
+        Ok(())
+    }
+
+    /// Read a share file
+    pub fn read_from_file(path: &Path) -> CryptoResult<Self> {
+        Self::from_text(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Split `key` into `shares` shares of which any `threshold` recover it
+pub fn split_key(key: &[u8; 32], threshold: u8, shares: u8) -> CryptoResult<Vec<KeyShare>> {
+    if threshold < 2 || shares < threshold {
+        return Err(CryptoError::EncryptionFailed(format!(
+            "Cannot split a key into {}/{} shares",
+            threshold, shares
+        )));
+    }
+
+    let rng = SystemRandom::new();
+    let random_error = |_| CryptoError::EncryptionFailed("Failed to generate shares".to_string());
+    let mut key_id = [0u8; 16];
+    rng.fill(&mut key_id).map_err(random_error)?;
+
+    // coefficients[j][b]: coefficient of x^(j+1) for key byte b
+    let mut coefficients = Zeroizing::new(vec![[0u8; 32]; threshold as usize - 1]);
+    for row in coefficients.iter_mut() {
+        rng.fill(row).map_err(random_error)?;
+    }
+
+    Ok((1..=shares)
+        .map(|x| {
+            let mut value = [0u8; 32];
+            for (b, out) in value.iter_mut().enumerate() {
+                // Horner's rule from the highest coefficient down
+                let mut y = 0u8;
+                for row in coefficients.iter().rev() {
+                    y = gf_mul(y, x) ^ row[b];
+                }
+                *out = gf_mul(y, x) ^ key[b];
+            }
+            KeyShare {
+                key_id,
+                threshold,
+                index: x,
+                value,
+            }
+        })
+        .collect())
+}
+
+/// Recover the key from at least `threshold` shares of the same split
+pub fn combine_shares(shares: &[KeyShare]) -> CryptoResult<Zeroizing<[u8; 32]>> {
+    let first = shares
+        .first()
+        .ok_or_else(|| CryptoError::InvalidShare("no shares given".to_string()))?;
+    if shares.iter().any(|s| s.key_id != first.key_id) {
+        return Err(CryptoError::InvalidShare(
+            "shares belong to different keys".to_string(),
+        ));
+    }
+
+    // Repeated shares do not count twice
+    let mut distinct: Vec<&KeyShare> = Vec::new();
+    for share in shares {
+        if !distinct.iter().any(|s| s.index == share.index) {
+            distinct.push(share);
+        }
+    }
+    let threshold = first.threshold as usize;
+    if distinct.len() < threshold {
+        return Err(CryptoError::InvalidShare(format!(
+            "{} distinct shares given, {} needed",
+            distinct.len(),
+            threshold
+        )));
+    }
+    let used = &distinct[..threshold];
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    for (i, share) in used.iter().enumerate() {
+        // Lagrange basis polynomial for this share, evaluated at 0
+        let mut basis = 1u8;
+        for (j, other) in used.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_div(other.index, share.index ^ other.index));
+            }
+        }
+        for (k, v) in key.iter_mut().zip(share.value.iter()) {
+            *k ^= gf_mul(*v, basis);
+        }
+    }
+    Ok(key)
+}
+
+/// Multiplication in GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Division in GF(2^8); `b` must not be zero
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b, since the multiplicative group has order 255
+    let mut inverse = 1u8;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+fn from_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_any_threshold_subset_recovers_key() {
+        let key = [0x5a; 32];
+        let shares = split_key(&key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for subset in [[0, 1, 2], [0, 2, 4], [4, 3, 1], [1, 2, 3]] {
+            let picked: Vec<KeyShare> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(*combine_shares(&picked).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn test_too_few_shares_rejected() {
+        let shares = split_key(&[7; 32], 3, 5).unwrap();
+        let duplicated = vec![shares[0].clone(), shares[1].clone(), shares[1].clone()];
+        assert!(matches!(
+            combine_shares(&duplicated),
+            Err(CryptoError::InvalidShare(_))
+        ));
+    }
+
+    #[test]
+    fn test_shares_of_different_keys_rejected() {
+        let a = split_key(&[1; 32], 2, 2).unwrap();
+        let b = split_key(&[1; 32], 2, 2).unwrap();
+        assert!(combine_shares(&[a[0].clone(), b[1].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_share_text_round_trip() {
+        let share = split_key(&[9; 32], 2, 3).unwrap().remove(2);
+        let parsed = KeyShare::from_text(&share.to_text()).unwrap();
+        assert_eq!(parsed, share);
+        assert_eq!(parsed.index, 3);
+
+        assert!(KeyShare::from_text("hello\n").is_err());
+        let truncated = share.to_text().replace(&to_hex(&share.value), "abcd");
+        assert!(KeyShare::from_text(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_gf_arithmetic() {
+        // Known AES field product
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for b in 1..=255u8 {
+            assert_eq!(gf_mul(gf_div(1, b), b), 1);
+        }
+    }
+}
@@ -9,12 +9,13 @@ pub mod utils;
 // These are exported for external use, so allow dead_code warnings
 #[allow(unused_imports)]
 pub use core::{
-    CollectionConfig, CollectionMode, CompressionConfig, CompressionFormat, Compressor, JcError,
-    JcResult, TimestampOption,
+    CollectionConfig, CollectionMode, CompressionConfig, CompressionFormat, Compressor, Durability,
+    JcError, JcResult, TimestampOption,
 };
 
 #[allow(unused_imports)]
 pub use operations::{
-    collect_and_compress, compress_compound, compress_file, compress_files, decompress_file,
-    decompress_files,
+    collect_and_compress, collect_by_directory, compress_compound, compress_file, compress_files,
+    decompress_file, decompress_files, identify_file, restore_tiered_file, tier_directory,
+    FileStats, Identification, RunStats, TierRules,
 };
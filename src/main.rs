@@ -7,15 +7,36 @@ mod crypto;
 mod operations;
 mod utils;
 
+use crate::core::error::EXIT_USAGE;
+use cli::output::Tone;
 use cli::{execute, CliArgs};
 use utils::init_logger;
 
 fn main() {
-    // Initialize logging
-    init_logger();
+    // Parse command-line arguments; clap's own usage errors exit 2, which
+    // jcz reserves for partial failures
+    let args = match CliArgs::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(if e.use_stderr() { EXIT_USAGE } else { 0 });
+        }
+    };
 
-    // Parse command-line arguments
-    let args = CliArgs::parse();
+    // Initialize logging
+    cli::output::init(args.no_color);
+    if let Err(e) = init_logger(
+        args.log_level(),
+        args.log_file.as_deref(),
+        cli::output::colors_allowed(args.no_color),
+    ) {
+        eprintln!(
+            "{}: {}",
+            cli::output::styled_stderr("ERROR", Tone::Error),
+            e
+        );
+        std::process::exit(e.exit_code());
+    }
 
     // Execute command
     match execute(args) {
@@ -23,8 +44,12 @@ fn main() {
             std::process::exit(0);
         }
         Err(e) => {
-            eprintln!("ERROR: {}", e);
-            std::process::exit(1);
+            eprintln!(
+                "{}: {}",
+                cli::output::styled_stderr("ERROR", Tone::Error),
+                e
+            );
+            std::process::exit(e.exit_code());
         }
     }
 }
@@ -0,0 +1,353 @@
+//! Content reports for pruning before archiving (`jcz analyze`)
+//!
+//! Every regular file of a directory, or member of a tar-based archive, is
+//! hashed with SHA-256 (as for sidecars and manifests). The report groups
+//! files with the same contents, lists the largest ones and breaks the
+//! total down by extension. Archives are decompressed as a stream (see
+//! `listing::read_decoded`), so nothing is extracted.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::operations::listing::{read_decoded, tar_layer};
+use crate::operations::sidecar::{sha256_file, sha256_reader};
+use crate::operations::stats::format_size;
+use crate::utils::debug;
+
+/// Largest entries listed unless `--top` says otherwise
+pub const DEFAULT_TOP: usize = 10;
+
+/// One regular file or member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalyzedFile {
+    /// Path relative to the directory, or member path
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Files with identical contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Size of each copy
+    pub size: u64,
+    pub sha256: String,
+    /// Paths of the copies, sorted
+    pub paths: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// Bytes saved by keeping only one copy
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Files sharing an extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionStats {
+    /// Lowercase extension without the dot, empty for none
+    pub extension: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+/// Report on one directory or archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub target: PathBuf,
+    pub files: u64,
+    pub total_size: u64,
+    /// Groups of two or more non-empty files with the same contents, the
+    /// most reclaimable first
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Largest files, largest first
+    pub largest: Vec<AnalyzedFile>,
+    /// Size per extension, largest first
+    pub extensions: Vec<ExtensionStats>,
+}
+
+impl Analysis {
+    /// Bytes saved by keeping one copy of each duplicate
+    pub fn reclaimable(&self) -> u64 {
+        self.duplicates
+            .iter()
+            .map(DuplicateGroup::reclaimable)
+            .sum()
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} files, {}",
+            self.target.display(),
+            self.files,
+            format_size(self.total_size)
+        )?;
+
+        if self.duplicates.is_empty() {
+            writeln!(f, "Duplicates: none")?;
+        } else {
+            writeln!(
+                f,
+                "Duplicates: {} groups, {} reclaimable",
+                self.duplicates.len(),
+                format_size(self.reclaimable())
+            )?;
+            for group in &self.duplicates {
+                writeln!(
+                    f,
+                    "  {} x {}  sha256 {}",
+                    group.paths.len(),
+                    format_size(group.size),
+                    &group.sha256[..12]
+                )?;
+                for path in &group.paths {
+                    writeln!(f, "    {}", path)?;
+                }
+            }
+        }
+
+        if !self.largest.is_empty() {
+            writeln!(f, "Largest:")?;
+            for file in &self.largest {
+                writeln!(f, "  {:>10}  {}", format_size(file.size), file.path)?;
+            }
+        }
+
+        if !self.extensions.is_empty() {
+            writeln!(f, "By extension:")?;
+            for ext in &self.extensions {
+                let share = if self.total_size == 0 {
+                    0.0
+                } else {
+                    ext.size as f64 * 100.0 / self.total_size as f64
+                };
+                let name = if ext.extension.is_empty() {
+                    "(none)".to_string()
+                } else {
+                    format!(".{}", ext.extension)
+                };
+                writeln!(
+                    f,
+                    "  {:<12} {:>6} files  {:>10}  {:>5.1}%",
+                    name,
+                    ext.files,
+                    format_size(ext.size),
+                    share
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Analyze the directory or tar-based archive `target`, listing the `top`
+/// largest files
+pub fn analyze(target: &Path, top: usize, config: &CompressionConfig) -> JcResult<Analysis> {
+    let files = if fs::metadata(target)?.is_dir() {
+        let mut paths = Vec::new();
+        list_files(target, Path::new(""), &mut paths)?;
+        paths
+            .par_iter()
+            .map(|relative| {
+                let path = target.join(relative);
+                Ok(AnalyzedFile {
+                    path: relative.to_string_lossy().into_owned(),
+                    size: fs::symlink_metadata(&path)?.len(),
+                    sha256: sha256_file(&path)?,
+                })
+            })
+            .collect::<JcResult<Vec<_>>>()?
+    } else {
+        let Some(layer) = tar_layer(target) else {
+            return Err(JcError::InvalidExtension(
+                target.to_path_buf(),
+                "a directory, tar, tgz, tbz2 or txz".to_string(),
+            ));
+        };
+        read_decoded(target, layer, config, |reader| hash_members(reader))?
+    };
+    Ok(summarize(target, files, top))
+}
+
+/// Hash the regular-file members of the tar read from `reader`
+fn hash_members(reader: &mut dyn Read) -> JcResult<Vec<AnalyzedFile>> {
+    let to_error = |e: std::io::Error| JcError::DecompressionFailed {
+        tool: "tar".to_string(),
+        stderr: e.to_string(),
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    let mut files = Vec::new();
+    for entry in archive.entries().map_err(to_error)? {
+        let mut entry = entry.map_err(to_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .map_err(to_error)?
+            .to_string_lossy()
+            .into_owned();
+        debug!("Hashing {}", path);
+        files.push(AnalyzedFile {
+            path,
+            size: entry.size(),
+            sha256: sha256_reader(&mut entry)?,
+        });
+    }
+    Ok(files)
+}
+
+/// Regular files below `dir`, as paths relative to the root; symlinks are
+/// not followed
+fn list_files(dir: &Path, relative: &Path, out: &mut Vec<PathBuf>) -> JcResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let child_relative = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &child_relative, out)?;
+        } else if file_type.is_file() {
+            out.push(child_relative);
+        }
+    }
+    Ok(())
+}
+
+/// Report on `files`, listing the `top` largest
+fn summarize(target: &Path, mut files: Vec<AnalyzedFile>, top: usize) -> Analysis {
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    let mut groups: BTreeMap<(u64, &str), Vec<String>> = BTreeMap::new();
+    let mut extensions: BTreeMap<String, ExtensionStats> = BTreeMap::new();
+    for file in &files {
+        // Empty files are all alike, and free to keep
+        if file.size > 0 {
+            groups
+                .entry((file.size, &file.sha256))
+                .or_default()
+                .push(file.path.clone());
+        }
+        let extension = Path::new(&file.path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let stats = extensions
+            .entry(extension.clone())
+            .or_insert(ExtensionStats {
+                extension,
+                files: 0,
+                size: 0,
+            });
+        stats.files += 1;
+        stats.size += file.size;
+    }
+
+    let mut duplicates: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, sha256), mut paths)| {
+            paths.sort();
+            DuplicateGroup {
+                size,
+                sha256: sha256.to_string(),
+                paths,
+            }
+        })
+        .collect();
+    duplicates.sort_by(|a, b| {
+        b.reclaimable()
+            .cmp(&a.reclaimable())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    let mut extensions: Vec<ExtensionStats> = extensions.into_values().collect();
+    extensions.sort_by(|a, b| {
+        b.size
+            .cmp(&a.size)
+            .then_with(|| a.extension.cmp(&b.extension))
+    });
+
+    Analysis {
+        target: target.to_path_buf(),
+        files: files.len() as u64,
+        total_size: files.iter().map(|file| file.size).sum(),
+        duplicates,
+        largest: files.iter().take(top).cloned().collect(),
+        extensions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64, sha256: &str) -> AnalyzedFile {
+        AnalyzedFile {
+            path: path.to_string(),
+            size,
+            sha256: sha256.repeat(64 / sha256.len()),
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let files = vec![
+            file("a/logo.PNG", 100, "aa"),
+            file("b/logo.png", 100, "aa"),
+            file("c/copy.bin", 100, "aa"),
+            file("notes.txt", 40, "bb"),
+            file("old/notes.txt", 40, "bb"),
+            file("big.iso", 500, "cc"),
+            file("empty1", 0, "dd"),
+            file("empty2", 0, "dd"),
+        ];
+        let analysis = summarize(Path::new("project"), files, 2);
+
+        assert_eq!(analysis.files, 8);
+        assert_eq!(analysis.total_size, 880);
+        // Empty files are not reported as duplicates
+        assert_eq!(analysis.duplicates.len(), 2);
+        assert_eq!(
+            analysis.duplicates[0].paths,
+            ["a/logo.PNG", "b/logo.png", "c/copy.bin"]
+        );
+        assert_eq!(analysis.duplicates[0].reclaimable(), 200);
+        assert_eq!(analysis.reclaimable(), 240);
+
+        let largest: Vec<_> = analysis.largest.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(largest, ["big.iso", "a/logo.PNG"]);
+
+        let extensions: Vec<_> = analysis
+            .extensions
+            .iter()
+            .map(|e| (e.extension.as_str(), e.files, e.size))
+            .collect();
+        assert_eq!(
+            extensions,
+            [
+                ("iso", 1, 500),
+                ("png", 2, 200),
+                ("bin", 1, 100),
+                ("txt", 2, 80),
+                ("", 2, 0)
+            ]
+        );
+
+        let report = analysis.to_string();
+        assert!(report.contains("Duplicates: 2 groups, 240 B reclaimable"));
+        assert!(report.contains("  3 x 100 B  sha256 aaaaaaaaaaaa"));
+        assert!(report.contains("(none)"));
+    }
+}
@@ -0,0 +1,187 @@
+//! Async versions of the file operations (`tokio` feature)
+//!
+//! Inputs are checked with `tokio::fs`; the work itself runs on tokio's
+//! blocking pool, since it spawns compression tools and does CPU-bound
+//! encryption. An async service can await these without stalling its
+//! runtime. Use `CompressionConfig::with_cancellation` to stop a job early;
+//! dropping the future does not stop the blocking work.
+
+use std::path::{Path, PathBuf};
+
+use crate::core::config::{
+    CompressionConfig, DecompressionConfig, DecryptionMethod, EncryptionMethod, Secrets,
+};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::operations::{compress, decompress, decrypt, encrypt, FileStats};
+
+/// Run `job` on the blocking pool
+///
+/// A panic in `job` is resumed in the caller; a job the runtime dropped
+/// while shutting down counts as aborted.
+async fn blocking<T: Send + 'static>(
+    job: impl FnOnce() -> JcResult<T> + Send + 'static,
+) -> JcResult<T> {
+    match tokio::task::spawn_blocking(job).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(JcError::Aborted),
+    }
+}
+
+/// Fail early, without a blocking thread, when `path` does not exist, or
+/// is not a file when `file_only` is set
+async fn check_input(path: &Path, file_only: bool) -> JcResult<()> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .map_err(|_| JcError::FileNotFound(path.to_path_buf()))?;
+    if file_only && !metadata.is_file() {
+        return Err(JcError::NotAFile(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Async `compress::compress_file`
+#[allow(dead_code)]
+pub async fn compress_file(
+    input: impl AsRef<Path>,
+    format: CompressionFormat,
+    config: CompressionConfig,
+) -> JcResult<PathBuf> {
+    let input = input.as_ref().to_path_buf();
+    check_input(&input, false).await?;
+    blocking(move || compress::compress_file(&input, format, &config)).await
+}
+
+/// Async `compress::compress_files`; results are in input order
+#[allow(dead_code)]
+pub async fn compress_files(
+    inputs: Vec<PathBuf>,
+    format: CompressionFormat,
+    config: CompressionConfig,
+) -> JcResult<Vec<JcResult<FileStats>>> {
+    blocking(move || Ok(compress::compress_files(inputs, format, config))).await
+}
+
+/// Async `decompress::decompress_file`
+#[allow(dead_code)]
+pub async fn decompress_file(
+    input: impl AsRef<Path>,
+    config: CompressionConfig,
+) -> JcResult<PathBuf> {
+    let input = input.as_ref().to_path_buf();
+    check_input(&input, false).await?;
+    blocking(move || decompress::decompress_file(&input, &config)).await
+}
+
+/// Async `decompress::decompress_file_with_decryption`
+#[allow(dead_code)]
+pub async fn decompress_file_with_decryption(
+    input: impl AsRef<Path>,
+    config: DecompressionConfig,
+) -> JcResult<PathBuf> {
+    let input = input.as_ref().to_path_buf();
+    check_input(&input, false).await?;
+    blocking(move || decompress::decompress_file_with_decryption(&input, &config)).await
+}
+
+/// Async `decompress::decompress_files`; results are in input order
+#[allow(dead_code)]
+pub async fn decompress_files(
+    inputs: Vec<PathBuf>,
+    config: CompressionConfig,
+    decryption: Option<DecryptionMethod>,
+    remove_encrypted: bool,
+) -> JcResult<Vec<JcResult<FileStats>>> {
+    blocking(move || {
+        Ok(decompress::decompress_files(
+            inputs,
+            config,
+            decryption,
+            remove_encrypted,
+        ))
+    })
+    .await
+}
+
+/// Async `encrypt::encrypt_file`
+#[allow(dead_code)]
+pub async fn encrypt_file(
+    file: impl AsRef<Path>,
+    method: EncryptionMethod,
+    secrets: Option<Secrets>,
+    pad_to: Option<u64>,
+) -> JcResult<PathBuf> {
+    let file = file.as_ref().to_path_buf();
+    check_input(&file, true).await?;
+    blocking(move || encrypt::encrypt_file(&file, &method, secrets.as_ref(), pad_to, None, true))
+        .await
+}
+
+/// Async `decrypt::decrypt_file`
+#[allow(dead_code)]
+pub async fn decrypt_file(
+    file: impl AsRef<Path>,
+    method: Option<DecryptionMethod>,
+    secrets: Option<Secrets>,
+    remove_encrypted: bool,
+) -> JcResult<PathBuf> {
+    let file = file.as_ref().to_path_buf();
+    check_input(&file, true).await?;
+    blocking(move || {
+        decrypt::decrypt_file(&file, method.as_ref(), secrets.as_ref(), remove_encrypted)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use zeroize::Zeroizing;
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_async_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, b"async data").unwrap();
+        let secrets = Secrets::new(|_| Ok(Zeroizing::new("hunter2".to_string())));
+
+        run(async {
+            let compressed =
+                compress_file(&input, CompressionFormat::Gzip, CompressionConfig::new())
+                    .await
+                    .unwrap();
+            let encrypted = encrypt_file(
+                &compressed,
+                EncryptionMethod::Password,
+                Some(secrets.clone()),
+                None,
+            )
+            .await
+            .unwrap();
+            fs::remove_file(&input).unwrap();
+
+            let decrypted = decrypt_file(&encrypted, None, Some(secrets), true)
+                .await
+                .unwrap();
+            let output = decompress_file(&decrypted, CompressionConfig::new())
+                .await
+                .unwrap();
+            assert_eq!(fs::read(output).unwrap(), b"async data");
+
+            let missing = temp_dir.path().join("missing.txt");
+            let result =
+                compress_file(&missing, CompressionFormat::Gzip, CompressionConfig::new()).await;
+            assert!(matches!(result, Err(JcError::FileNotFound(_))));
+        });
+    }
+}
@@ -0,0 +1,221 @@
+//! Format/level benchmark (`jcz benchmark`)
+//!
+//! Compresses a sample with every level of each compressing format, in
+//! parallel in scratch space, so users can pick `-c`/`-l` for their data.
+
+use rayon::prelude::*;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::compressors::{create_compressor, tar::TarCompressor};
+use crate::core::compressor::Compressor;
+use crate::core::config::{CompressionConfig, TimestampOption};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::operations::stats::{self, format_size, timed, tree_size};
+use crate::utils::{copy_tree, create_decompress_temp_dir, debug, info, warn};
+
+/// Formats with compression levels, in table order
+const FORMATS: [CompressionFormat; 4] = [
+    CompressionFormat::Gzip,
+    CompressionFormat::Bzip2,
+    CompressionFormat::Xz,
+    CompressionFormat::Zip,
+];
+
+/// One format/level combination measured on the sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    /// `-c` command that produces this output (tgz, tbz2, txz for directories)
+    pub command: String,
+
+    /// `-l` level
+    pub level: u8,
+
+    /// Sample size in bytes
+    pub original_size: u64,
+
+    /// Output size in bytes
+    pub compressed_size: u64,
+
+    /// Time spent compressing, while other combinations ran alongside
+    pub elapsed: Duration,
+}
+
+impl BenchmarkResult {
+    /// Compressed size as a fraction of the sample size
+    pub fn ratio(&self) -> f64 {
+        stats::ratio(self.compressed_size, self.original_size)
+    }
+
+    /// Sample bytes compressed per second
+    pub fn throughput(&self) -> f64 {
+        stats::throughput(self.original_size, self.elapsed)
+    }
+}
+
+impl fmt::Display for BenchmarkResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<6} {:>5} {:>11} {:>7.1}% {:>13} {:>8.2}s",
+            self.command,
+            self.level,
+            format_size(self.compressed_size),
+            self.ratio() * 100.0,
+            format!("{}/s", format_size(self.throughput() as u64)),
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+/// Header line matching the `BenchmarkResult` display columns
+pub const BENCHMARK_HEADER: &str = "FORMAT LEVEL        SIZE    RATIO         SPEED      TIME";
+
+/// Compress `sample` with each format and level, all in parallel
+///
+/// A directory sample is archived with tar once and the tar compressed,
+/// as tgz/tbz2/txz would; zip is only measured on files. Combinations whose
+/// tool fails (e.g. is not installed) are left out with a warning.
+pub fn benchmark(sample: &Path) -> JcResult<Vec<BenchmarkResult>> {
+    let metadata = fs::metadata(sample).map_err(|_| JcError::FileNotFound(sample.to_path_buf()))?;
+    let original_size = tree_size(sample)?;
+
+    let scratch = create_decompress_temp_dir()?;
+    let input = if metadata.is_dir() {
+        tar_sample(sample, scratch.path())?
+    } else {
+        sample.to_path_buf()
+    };
+
+    let combinations: Vec<(CompressionFormat, u8)> = FORMATS
+        .iter()
+        .filter(|format| !(metadata.is_dir() && **format == CompressionFormat::Zip))
+        .flat_map(|&format| {
+            let compressor = create_compressor(format);
+            (1..=9)
+                .filter(|&level| compressor.supports_levels() && compressor.validate_level(level))
+                .map(|level| (format, level))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    info!(
+        "Benchmarking {} combinations on {}",
+        combinations.len(),
+        sample.display()
+    );
+
+    let results = combinations
+        .par_iter()
+        .filter_map(|&(format, level)| {
+            let work_dir = scratch.path().join(format!("{}-{}", format.name(), level));
+            match run_one(&input, &work_dir, format, level) {
+                Ok((compressed_size, elapsed)) => Some(BenchmarkResult {
+                    command: command_name(format, metadata.is_dir()).to_string(),
+                    level,
+                    original_size,
+                    compressed_size,
+                    elapsed,
+                }),
+                Err(e) => {
+                    warn!("Skipping {} level {}: {}", format.name(), level, e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    Ok(results)
+}
+
+/// Compress a private link (or copy) of `input` in `work_dir`, returning the
+/// output size and compression time
+fn run_one(
+    input: &Path,
+    work_dir: &Path,
+    format: CompressionFormat,
+    level: u8,
+) -> JcResult<(u64, Duration)> {
+    fs::create_dir(work_dir)?;
+    let name = input
+        .file_name()
+        .ok_or_else(|| JcError::Other("Invalid sample filename".to_string()))?;
+    let local = work_dir.join(name);
+    if fs::hard_link(input, &local).is_err() {
+        fs::copy(input, &local)?;
+    }
+
+    let config = CompressionConfig::new()
+        .with_level(level)
+        .with_timestamp(TimestampOption::None)
+        .with_force(true);
+    let (output, elapsed) = timed(|| create_compressor(format).compress(&local, &config));
+    let size = fs::metadata(output?)?.len();
+
+    // Outputs can be large; don't keep them until the whole run ends
+    fs::remove_dir_all(work_dir)?;
+    debug!("{} level {}: {} bytes", format.name(), level, size);
+    Ok((size, elapsed))
+}
+
+/// Tar a directory sample into `scratch`, from a copy so nothing is
+/// written next to it
+fn tar_sample(sample: &Path, scratch: &Path) -> JcResult<PathBuf> {
+    let name = sample
+        .canonicalize()?
+        .file_name()
+        .map(PathBuf::from)
+        .ok_or_else(|| JcError::Other("Invalid sample directory".to_string()))?;
+    let staging = scratch.join("sample");
+    fs::create_dir(&staging)?;
+    copy_tree(sample, &staging.join(&name), false)?;
+
+    let tar = TarCompressor::new().compress(&staging.join(&name), &CompressionConfig::default())?;
+    let input = scratch.join(tar.file_name().unwrap_or_default());
+    fs::rename(&tar, &input)?;
+    fs::remove_dir_all(&staging)?;
+    Ok(input)
+}
+
+/// The `-c` command for `format`, as a tar compound for directories
+fn command_name(format: CompressionFormat, directory: bool) -> &'static str {
+    match (format, directory) {
+        (CompressionFormat::Gzip, true) => "tgz",
+        (CompressionFormat::Bzip2, true) => "tbz2",
+        (CompressionFormat::Xz, true) => "txz",
+        (format, _) => format.name(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_benchmark_directory_uses_compound_names() {
+        let dir = TempDir::new().unwrap();
+        let sample = dir.path().join("sample");
+        fs::create_dir(&sample).unwrap();
+        fs::write(sample.join("a.txt"), "hello world\n".repeat(200)).unwrap();
+
+        let results = benchmark(&sample).unwrap();
+        assert!(results.iter().all(|r| r.command != "zip"));
+        assert!(results.iter().any(|r| r.command == "tgz" && r.level == 9));
+        assert!(results.iter().all(|r| r.original_size == 2400));
+        assert!(results.iter().all(|r| r.ratio() < 1.0));
+
+        // Nothing left next to the sample
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_command_name() {
+        assert_eq!(command_name(CompressionFormat::Xz, true), "txz");
+        assert_eq!(command_name(CompressionFormat::Xz, false), "xz");
+        assert_eq!(command_name(CompressionFormat::Zip, false), "zip");
+    }
+}
@@ -0,0 +1,80 @@
+//! Printing members of archives (`jcz cat`)
+//!
+//! A `.tar.gz` written with `--indexed` is read from the block its member
+//! starts in (see `index`); other tar-based archives are decompressed as a
+//! stream up to the member.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::operations::index::{normalize, read_indexed, ArchiveIndex};
+use crate::operations::listing::{read_decoded, tar_layer};
+use crate::utils::debug;
+
+/// Copy the data of the member `member` of `archive` to `out`, returning
+/// its size
+///
+/// Handles `.tar`, `.tar.gz`, `.tar.bz2` and `.tar.xz` (and their
+/// aliases); only regular files can be printed.
+pub fn cat_member(
+    archive: &Path,
+    member: &str,
+    config: &CompressionConfig,
+    out: &mut dyn Write,
+) -> JcResult<u64> {
+    let Some(layer) = tar_layer(archive) else {
+        return Err(JcError::InvalidExtension(
+            archive.to_path_buf(),
+            "tar, tgz, tbz2 or txz".to_string(),
+        ));
+    };
+
+    if let Some(index) = ArchiveIndex::load(archive)? {
+        let entry = index
+            .find(member)
+            .ok_or_else(|| not_found(archive, member))?;
+        debug!(
+            "Reading {} from offset {} of {}",
+            member,
+            entry.offset,
+            archive.display()
+        );
+        return read_indexed(archive, entry, out);
+    }
+
+    read_decoded(archive, layer, config, |reader| {
+        stream_member(reader, member, out)?.ok_or_else(|| not_found(archive, member))
+    })
+}
+
+/// Copy the member `member` of the tar read from `reader` to `out`
+fn stream_member(
+    reader: &mut dyn Read,
+    member: &str,
+    out: &mut dyn Write,
+) -> JcResult<Option<u64>> {
+    let to_error = |e: io::Error| JcError::DecompressionFailed {
+        tool: "tar".to_string(),
+        stderr: e.to_string(),
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(to_error)? {
+        let mut entry = entry.map_err(to_error)?;
+        let path = entry.path().map_err(to_error)?;
+        if normalize(&path.to_string_lossy()) != normalize(member) {
+            continue;
+        }
+        if !entry.header().entry_type().is_file() {
+            return Err(JcError::Other(format!("{} is not a regular file", member)));
+        }
+        return Ok(Some(io::copy(&mut entry, out)?));
+    }
+    Ok(None)
+}
+
+fn not_found(archive: &Path, member: &str) -> JcError {
+    JcError::Other(format!("{}: no member {}", archive.display(), member))
+}
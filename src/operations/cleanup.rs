@@ -0,0 +1,186 @@
+//! Removing the scratch space of crashed runs (`jcz cleanup`)
+//!
+//! A run that crashes or is killed leaves its scratch directories behind:
+//! `.jcz-tmp-*` next to a destination, under `--tempdir` or in the system
+//! temp dir, and the `jczpkg_*` staging directories of `--collect`. Those
+//! listed in the record of a run that is gone (see `utils::tempdirs`) are
+//! removed whatever their age, and every run does so on startup. Others
+//! are found by name in the directories scanned and only taken once they
+//! are older than a cutoff, since a run that kept no record may still be
+//! using them; none that a live run has recorded is touched.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::core::error::{JcError, JcResult};
+use crate::utils::tempdirs::{scan_records, StaleRecord, SCRATCH_PREFIX, STAGING_PREFIX};
+use crate::utils::{canonical_path, debug, info, remove_file_silent, warn};
+
+/// Remove the scratch space of runs that are gone: the directories their
+/// records list, and the entries of `dirs` and of the system temp dir
+/// named as jcz names scratch space and last modified before `cutoff`
+///
+/// Returns the paths removed, sorted, or with `dry_run` those that would
+/// be.
+pub fn clean_orphans(
+    dirs: &[PathBuf],
+    cutoff: SystemTime,
+    dry_run: bool,
+) -> JcResult<Vec<PathBuf>> {
+    let (stale, live) = scan_records()?;
+    let live: HashSet<PathBuf> = live.into_iter().collect();
+    let mut orphans: Vec<PathBuf> = stale
+        .iter()
+        .flat_map(|record| record.dirs.iter().cloned())
+        .collect();
+
+    let mut scanned = HashSet::new();
+    for dir in dirs {
+        if !dir.is_dir() {
+            return Err(JcError::NotADirectory(dir.clone()));
+        }
+        let dir = canonical_path(dir)?;
+        if scanned.insert(dir.clone()) {
+            orphans.extend(named_orphans(&dir, cutoff, &live)?);
+        }
+    }
+    // The system temp dir may be missing or unreadable; it is only a guess
+    if let Ok(dir) = canonical_path(&std::env::temp_dir()) {
+        if scanned.insert(dir.clone()) {
+            match named_orphans(&dir, cutoff, &live) {
+                Ok(found) => orphans.extend(found),
+                Err(e) => debug!("Not scanning {}: {}", dir.display(), e),
+            }
+        }
+    }
+    orphans.sort();
+    orphans.dedup();
+    if dry_run {
+        return Ok(orphans);
+    }
+
+    for orphan in &orphans {
+        remove_entry(orphan)?;
+    }
+    for record in stale {
+        record.remove()?;
+    }
+    Ok(orphans)
+}
+
+/// Remove the directories listed in the records of runs that are gone, as
+/// every run does on startup; failures are only logged
+pub fn clean_recorded_orphans() {
+    let stale = match scan_records() {
+        Ok((stale, _)) => stale,
+        Err(e) => {
+            debug!("Failed to read temp directory records: {}", e);
+            return;
+        }
+    };
+    for record in stale {
+        remove_stale(record);
+    }
+}
+
+/// Remove what `record` lists, then the record itself unless something
+/// could not be removed
+fn remove_stale(record: StaleRecord) {
+    let mut removed = true;
+    for dir in &record.dirs {
+        match remove_entry(dir) {
+            Ok(()) => info!("Removed {}, left by an interrupted run", dir.display()),
+            Err(e) => {
+                warn!("Failed to remove {}: {}", dir.display(), e);
+                removed = false;
+            }
+        }
+    }
+    if removed {
+        if let Err(e) = record.remove() {
+            debug!("Failed to remove temp directory record: {}", e);
+        }
+    }
+}
+
+/// Entries of `dir` named as jcz scratch space, last modified before
+/// `cutoff` and not recorded by a live run
+fn named_orphans(
+    dir: &Path,
+    cutoff: SystemTime,
+    live: &HashSet<PathBuf>,
+) -> JcResult<Vec<PathBuf>> {
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // Staged files are named like scratch directories
+        let named = (name.starts_with(SCRATCH_PREFIX) && !file_type.is_symlink())
+            || (name.starts_with(STAGING_PREFIX) && file_type.is_dir());
+        if !named {
+            continue;
+        }
+
+        let path = entry.path();
+        let old = entry.metadata()?.modified().is_ok_and(|t| t < cutoff);
+        if old && !live.contains(&path) {
+            orphans.push(path);
+        }
+    }
+    Ok(orphans)
+}
+
+fn remove_entry(path: &Path) -> JcResult<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => remove_file_silent(path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_named_orphans_by_name_age_and_owner() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        for name in [
+            ".jcz-tmp-a1b2c3",
+            "jczpkg_18f3a",
+            ".jcz-tmp-live",
+            "jcz-backup",
+        ] {
+            fs::create_dir(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join(".jcz-tmp-staged"), b"partial").unwrap();
+        fs::write(dir.join("jczpkg_file"), b"not staging").unwrap();
+
+        let live = HashSet::from([dir.join(".jcz-tmp-live")]);
+        let future = SystemTime::now() + Duration::from_secs(60);
+        let mut found = named_orphans(dir, future, &live).unwrap();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                dir.join(".jcz-tmp-a1b2c3"),
+                dir.join(".jcz-tmp-staged"),
+                dir.join("jczpkg_18f3a"),
+            ]
+        );
+
+        // Nothing is old enough yet
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        assert!(named_orphans(dir, past, &live).unwrap().is_empty());
+    }
+}
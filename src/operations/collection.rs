@@ -1,26 +1,63 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
-use crate::compressors::{create_compressor, tar::TarCompressor};
+use crate::compressors::tar::TarCompressor;
 use crate::core::compressor::{Compressor, MultiFileCompressor};
 use crate::core::config::{CollectionConfig, CollectionMode, CompressionConfig, TimestampOption};
 use crate::core::error::{JcError, JcResult};
 use crate::core::types::CompoundFormat;
+use crate::operations::compound::{compress_tar, warn_compressed_members};
 use crate::operations::encrypt::encrypt_file;
-use crate::utils::{copy_recursive, create_temp_dir, debug, info, move_file, remove_file_silent};
+use crate::operations::integrity::{collection_digest, record_tag, tree_digest};
+use crate::operations::listing::read_decoded;
+use crate::operations::manifest::{write_manifest, MANIFEST_NAME};
+use crate::operations::stats::{timed, tree_size, FileStats};
+use crate::utils::tempdirs::STAGING_PREFIX;
+use crate::utils::transform::archive_name;
+use crate::utils::{
+    canonical_path, copy_tree, create_temp_dir, debug, info, move_file, move_file_no_clobber,
+    remove_file_silent,
+};
 
 /// Collect multiple files into a compressed archive
 pub fn collect_and_compress(
     inputs: Vec<PathBuf>,
     format: CompoundFormat,
     collection_config: CollectionConfig,
-) -> JcResult<PathBuf> {
+) -> JcResult<FileStats> {
     // Validate inputs
     if inputs.is_empty() {
         return Err(JcError::NoInputFiles);
     }
 
-    // Check for duplicate basenames
+    if collection_config.keep_paths || collection_config.base.transform.is_some() {
+        check_overlaps(&member_names(&inputs, &collection_config)?)?;
+    } else {
+        check_duplicate_basenames(&inputs)?;
+    }
+
+    // Check package name doesn't exist
+    let package_path = PathBuf::from(&collection_config.package_name);
+    if package_path.exists() && !collection_config.update {
+        return Err(JcError::NameExists(collection_config.package_name.clone()));
+    }
+    if collection_config.update && collection_config.base.encryption.is_some() {
+        return Err(JcError::Usage(
+            "--update cannot be combined with encryption".to_string(),
+        ));
+    }
+
+    let base = collection_config.base.clone();
+    base.run_job(&package_path, || {
+        timed_collection(&inputs, format, collection_config.clone())
+    })
+}
+
+/// Reject inputs that would share a basename in the archive
+fn check_duplicate_basenames(inputs: &[PathBuf]) -> JcResult<()> {
     let basenames: Vec<String> = inputs
         .iter()
         .filter_map(|p| p.file_name())
@@ -40,13 +77,133 @@ pub fn collect_and_compress(
             .collect();
         return Err(JcError::DuplicateBasenames(duplicates));
     }
+    Ok(())
+}
 
-    // Check package name doesn't exist
-    let package_path = PathBuf::from(&collection_config.package_name);
-    if package_path.exists() {
-        return Err(JcError::NameExists(collection_config.package_name.clone()));
+/// Reject kept paths that repeat or lie inside one another
+fn check_overlaps(names: &[PathBuf]) -> JcResult<()> {
+    let mut sorted: Vec<&PathBuf> = names.iter().collect();
+    // Component-wise order puts everything under a path right after it
+    sorted.sort();
+    for pair in sorted.windows(2) {
+        if pair[1].starts_with(pair[0]) {
+            return Err(JcError::Other(format!(
+                "Inputs overlap in the collection: {} and {}",
+                pair[0].display(),
+                pair[1].display()
+            )));
+        }
     }
+    Ok(())
+}
+
+/// Path each input is stored under: its basename, its path as given with
+/// `keep_paths` (without a leading `/` or `./`), or its rewritten path
+/// with a transform
+fn member_names(
+    inputs: &[PathBuf],
+    collection_config: &CollectionConfig,
+) -> JcResult<Vec<PathBuf>> {
+    inputs
+        .iter()
+        .map(|input| match collection_config.base.transform {
+            Some(ref transform) => transform.apply(input),
+            None if collection_config.keep_paths => archive_name(input),
+            None => input
+                .file_name()
+                .map(PathBuf::from)
+                .ok_or_else(|| JcError::Other(format!("Invalid filename: {}", input.display()))),
+        })
+        .collect()
+}
+
+/// Collect inputs into one archive per parent directory, named after it
+///
+/// Each archive is written next to its directory unless a move-to
+/// directory is configured. Results are paired with their directory.
+pub fn collect_by_directory(
+    inputs: Vec<PathBuf>,
+    format: CompoundFormat,
+    base: CompressionConfig,
+) -> JcResult<Vec<(PathBuf, JcResult<FileStats>)>> {
+    let groups = group_by_parent(inputs)?;
+
+    info!("Collecting inputs into {} directory archives", groups.len());
 
+    // Sequential: staging directories are named by timestamp in the cwd
+    Ok(groups
+        .into_iter()
+        .map(|(dir, files)| {
+            let result = base.run_job(&dir, || {
+                let package_name = dir
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .ok_or_else(|| {
+                        JcError::Other(format!("Cannot name archive for {}", dir.display()))
+                    })?
+                    .to_string();
+
+                let mut config = base.clone();
+                if config.move_to.is_none() {
+                    config.move_to = dir.parent().map(Path::to_path_buf);
+                }
+
+                let collection_config = CollectionConfig {
+                    base: config,
+                    package_name,
+                    mode: CollectionMode::WithParent,
+                    keep_paths: false,
+                    update: false,
+                };
+
+                timed_collection(&files, format, collection_config)
+            });
+            (dir, result)
+        })
+        .collect())
+}
+
+/// Build a collection, reporting it under its package name
+fn timed_collection(
+    inputs: &[PathBuf],
+    format: CompoundFormat,
+    collection_config: CollectionConfig,
+) -> JcResult<FileStats> {
+    let package = PathBuf::from(&collection_config.package_name);
+    let original_size = inputs
+        .iter()
+        .map(|input| tree_size(input))
+        .sum::<JcResult<u64>>()?;
+
+    let (output, elapsed) = timed(|| build_collection(inputs, format, collection_config));
+    FileStats::compressed(package, original_size, output?, elapsed)
+}
+
+/// Group inputs by their (absolute) parent directory
+fn group_by_parent(inputs: Vec<PathBuf>) -> JcResult<BTreeMap<PathBuf, Vec<PathBuf>>> {
+    if inputs.is_empty() {
+        return Err(JcError::NoInputFiles);
+    }
+
+    let mut groups: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for input in inputs {
+        let absolute = canonical_path(&input)?;
+        let parent = absolute
+            .parent()
+            .ok_or_else(|| JcError::Other(format!("{} has no parent", input.display())))?
+            .to_path_buf();
+        groups.entry(parent).or_default().push(absolute);
+    }
+
+    Ok(groups)
+}
+
+/// Stage inputs and build the compressed (and optionally encrypted) archive
+fn build_collection(
+    inputs: &[PathBuf],
+    format: CompoundFormat,
+    collection_config: CollectionConfig,
+) -> JcResult<PathBuf> {
     info!(
         "Collecting {} files into {}",
         inputs.len(),
@@ -54,17 +211,35 @@ pub fn collect_and_compress(
     );
 
     // Create temporary staging directory
-    let temp_dir = create_temp_dir("jczpkg_")?;
+    let temp_dir = create_temp_dir(STAGING_PREFIX)?;
     debug!("Created temporary directory: {}", temp_dir.display());
 
     // Ensure cleanup on exit
     let _cleanup = CleanupGuard::new(temp_dir.clone());
 
+    let destination = collection_config
+        .base
+        .move_to
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    // An update starts from what the existing archive holds
+    let existing = destination.join(format!(
+        "{}.{}",
+        collection_config.package_name,
+        format.extension()
+    ));
+    let updating = collection_config.update && existing.is_file();
+    if updating {
+        info!("Updating {}", existing.display());
+        unpack_existing(&existing, format, &temp_dir, &collection_config.base)?;
+    }
+
     let staging_dir = match collection_config.mode {
         CollectionMode::WithParent => {
             // Create subdirectory with package name
             let pkg_dir = temp_dir.join(&collection_config.package_name);
-            fs::create_dir(&pkg_dir)?;
+            fs::create_dir_all(&pkg_dir)?;
             pkg_dir
         }
         CollectionMode::Flat => {
@@ -73,15 +248,38 @@ pub fn collect_and_compress(
         }
     };
 
+    warn_compressed_members(inputs, collection_config.base.level);
+
     // Copy files to staging directory
-    for input in &inputs {
-        let basename = input
-            .file_name()
-            .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
-        let dest = staging_dir.join(basename);
+    let names = member_names(inputs, &collection_config)?;
+    let mut refreshed = 0;
+    for (input, name) in inputs.iter().zip(&names) {
+        let dest = staging_dir.join(name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
 
         debug!("Copying {} to {}", input.display(), dest.display());
-        copy_recursive(input, &dest)?;
+        if updating {
+            refreshed += refresh_tree(input, &dest, collection_config.base.dereference)?;
+        } else {
+            copy_tree(input, &dest, collection_config.base.dereference)?;
+        }
+    }
+    if updating {
+        if refreshed == 0 {
+            info!("{} is up to date", existing.display());
+            return Ok(existing);
+        }
+        info!("Refreshing {} entries", refreshed);
+        // Rewritten below for the updated contents
+        let _ = remove_file_silent(&staging_dir.join(MANIFEST_NAME));
+    }
+
+    // Hashes the staged copies, so links are listed as the archive stores them
+    if collection_config.base.manifest {
+        let files = write_manifest(&staging_dir)?;
+        info!("Added {} listing {} files", MANIFEST_NAME, files);
     }
 
     // Create TAR archive
@@ -89,20 +287,36 @@ pub fn collect_and_compress(
 
     let tar_config = CompressionConfig {
         level: 0,
-        timestamp: collection_config.base.timestamp,
         move_to: None,
         show_output_size: false,
-        force: collection_config.base.force,
         encryption: None, // Encryption happens after collection
+        transform: None,  // Inputs are staged under their final names
+        ..collection_config.base.clone()
     };
 
     // Generate TAR filename
     let tar_filename = if collection_config.mode == CollectionMode::Flat {
-        // For flat mode, create TAR from staging dir contents
-        let file_list: Vec<PathBuf> = inputs
-            .iter()
-            .map(|p| staging_dir.join(p.file_name().unwrap()))
-            .collect();
+        // For flat mode, create TAR from staging dir contents; kept paths
+        // are archived from their first component
+        let mut file_list: Vec<PathBuf> = Vec::new();
+        for name in &names {
+            let top = staging_dir.join(name.components().next().unwrap());
+            if !file_list.contains(&top) {
+                file_list.push(top);
+            }
+        }
+        // Entries of the updated archive that no input replaced
+        if updating {
+            let mut kept: Vec<PathBuf> = fs::read_dir(&staging_dir)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<_, _>>()?;
+            kept.sort();
+            kept.retain(|path| !file_list.contains(path) && !path.ends_with(MANIFEST_NAME));
+            file_list.extend(kept);
+        }
+        if collection_config.base.manifest {
+            file_list.push(staging_dir.join(MANIFEST_NAME));
+        }
 
         tar_compressor.compress_multi(&file_list, &collection_config.package_name, &tar_config)?
     } else {
@@ -114,43 +328,162 @@ pub fn collect_and_compress(
     debug!("Created TAR archive: {}", tar_filename.display());
 
     // Apply secondary compression
-    let final_output = if format.secondary() != format.primary() {
-        let secondary_compressor = create_compressor(format.secondary());
-
-        // Remove timestamp to avoid duplication
+    let (final_output, index) = if format.secondary() != format.primary() {
+        // Remove timestamp and name template to avoid duplication
         let new_config = collection_config
             .base
             .clone()
-            .with_timestamp(TimestampOption::None);
-        let compressed = secondary_compressor.compress(&tar_filename, &new_config)?;
+            .with_timestamp(TimestampOption::None)
+            .with_name_template(None);
+        let compressed = compress_tar(&tar_filename, format, &new_config)?;
 
         // Remove intermediate TAR
         let _ = remove_file_silent(&tar_filename);
 
         compressed
     } else {
-        tar_filename
+        (tar_filename, None)
+    };
+
+    let base = &collection_config.base;
+    let digest = if base.encryption.is_some() || base.sidecar_metadata {
+        // Tag what extraction yields: the lone entry of a one-input flat
+        // archive, otherwise the directory holding the inputs (and manifest)
+        Some(
+            if base.manifest || collection_config.keep_paths || base.transform.is_some() || updating
+            {
+                tree_digest(&staging_dir, false)?
+            } else if collection_config.mode == CollectionMode::Flat && inputs.len() == 1 {
+                tree_digest(&inputs[0], base.dereference)?
+            } else {
+                collection_digest(inputs, base.dereference)?
+            },
+        )
+    } else {
+        None
     };
 
-    // Apply encryption if specified
-    let final_output = if let Some(ref encryption_method) = collection_config.base.encryption {
-        encrypt_file(&final_output, encryption_method)?
+    // Apply encryption if specified, sealing the tag into .jcze containers
+    let final_output = if let Some(ref encryption_method) = base.encryption {
+        encrypt_file(
+            &final_output,
+            encryption_method,
+            base.secrets.as_ref(),
+            base.pad_to,
+            digest,
+            base.syncs_outputs(),
+        )?
     } else {
         final_output
     };
 
-    // Move to destination or current directory
-    let destination = collection_config
-        .base
-        .move_to
-        .unwrap_or_else(|| PathBuf::from("."));
+    // Move to destination or current directory; an update replaces the
+    // archive it started from
+    let final_path = if collection_config.base.unique_names && !updating {
+        move_file_no_clobber(&final_output, &destination)?
+    } else {
+        if let Some(name) = final_output.file_name() {
+            let target = destination.join(name);
+            if target != final_output {
+                collection_config.base.back_up(&target)?;
+            }
+        }
+        move_file(&final_output, &destination)?
+    };
+    if let Some(index) = index {
+        index.save(&final_path)?;
+    }
 
-    let final_path = move_file(&final_output, &destination)?;
+    if let Some(digest) = digest {
+        let sources: Vec<(PathBuf, PathBuf)> = inputs
+            .iter()
+            .zip(names)
+            .map(|(input, name)| {
+                let name = match collection_config.mode {
+                    CollectionMode::WithParent => {
+                        Path::new(&collection_config.package_name).join(name)
+                    }
+                    CollectionMode::Flat => name,
+                };
+                (input.clone(), name)
+            })
+            .collect();
+        record_tag(&final_path, &digest, &sources, &collection_config.base)?;
+    }
 
     info!("Created collection archive: {}", final_path.display());
     Ok(final_path)
 }
 
+/// Unpack the collection archive `existing` into `dir`
+fn unpack_existing(
+    existing: &Path,
+    format: CompoundFormat,
+    dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    read_decoded(existing, Some(format.secondary()), config, |reader| {
+        let mut archive = tar::Archive::new(reader);
+        archive.set_preserve_permissions(true);
+        archive
+            .unpack(dir)
+            .map_err(|e| JcError::DecompressionFailed {
+                tool: "tar".to_string(),
+                stderr: format!("{}: {}", existing.display(), e),
+            })
+    })
+}
+
+/// Copy `src` over its staged copy `dest` where it is missing or newer,
+/// to the second tar keeps, returning how many entries were copied
+fn refresh_tree(src: &Path, dest: &Path, dereference: bool) -> io::Result<u64> {
+    let metadata = if dereference {
+        fs::metadata(src)?
+    } else {
+        fs::symlink_metadata(src)?
+    };
+    let staged = fs::symlink_metadata(dest).ok();
+
+    match staged {
+        Some(ref staged) if metadata.is_dir() && staged.is_dir() => {
+            let mut copied = 0;
+            for entry in fs::read_dir(src)? {
+                let name = entry?.file_name();
+                copied += refresh_tree(&src.join(&name), &dest.join(&name), dereference)?;
+            }
+            Ok(copied)
+        }
+        Some(ref staged)
+            if !metadata.is_dir()
+                && !staged.is_dir()
+                && mtime_secs(&metadata) <= mtime_secs(staged) =>
+        {
+            Ok(0)
+        }
+        Some(staged) => {
+            if staged.is_dir() {
+                fs::remove_dir_all(dest)?;
+            } else {
+                fs::remove_file(dest)?;
+            }
+            copy_tree(src, dest, dereference)?;
+            Ok(1)
+        }
+        None => {
+            copy_tree(src, dest, dereference)?;
+            Ok(1)
+        }
+    }
+}
+
+fn mtime_secs(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
 /// RAII guard for cleaning up temporary directory
 struct CleanupGuard {
     path: PathBuf,
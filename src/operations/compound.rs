@@ -1,17 +1,56 @@
 use rayon::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::compressors::create_compressor;
 use crate::core::config::CompressionConfig;
 use crate::core::config::TimestampOption;
 use crate::core::error::JcResult;
+use crate::core::heuristics::{find_compressed_members, CompressedMembers};
 use crate::core::types::CompoundFormat;
-use crate::operations::encrypt;
-use crate::utils::{debug, info, remove_file_silent};
+use crate::operations::compress::encrypt_successful;
+use crate::operations::index::{self, ArchiveIndex};
+use crate::operations::stats::{self, timed, FileStats};
+use crate::operations::{encrypt, integrity};
+use crate::utils::{debug, info, remove_file_silent, run_limited, warn};
+
+/// Warn when most of the bytes going into a tar-based archive are already
+/// compressed, since a high level then mostly burns CPU
+///
+/// Compound formats compress one stream, so such members cannot be
+/// stored individually the way zip stores them.
+pub(crate) fn warn_compressed_members(inputs: &[PathBuf], level: u8) {
+    if level <= 1 {
+        return;
+    }
+
+    let mut found = CompressedMembers::default();
+    for input in inputs {
+        match find_compressed_members(input) {
+            Ok(members) => {
+                found.bytes += members.bytes;
+                found.total += members.total;
+                for kind in members.kinds {
+                    if !found.kinds.contains(&kind) {
+                        found.kinds.push(kind);
+                    }
+                }
+            }
+            Err(e) => debug!("Could not scan {}: {}", input.display(), e),
+        }
+    }
+
+    if found.worth_warning() {
+        warn!(
+            "{:.0}% of the input is already compressed ({}); -l 1 saves CPU at little cost, or -c zip stores those files as they are",
+            found.share() * 100.0,
+            found.kinds.join(", ")
+        );
+    }
+}
 
 /// Compress file(s) with compound format (TAR + secondary compression)
 pub fn compress_compound(
-    input: &PathBuf,
+    input: &Path,
     format: CompoundFormat,
     config: &CompressionConfig,
 ) -> JcResult<PathBuf> {
@@ -20,49 +59,92 @@ pub fn compress_compound(
         input.display(),
         format.extension()
     );
+    warn_compressed_members(&[input.to_path_buf()], config.level);
 
     // Step 1: Create TAR archive
     let tar_compressor = create_compressor(format.primary());
     let tar_config = CompressionConfig {
-        level: 0, // TAR doesn't use compression level
-        timestamp: config.timestamp,
+        // TAR doesn't use compression level; it only names the output
+        // ({level} in a name template)
+        level: config.level_for(&*create_compressor(format.secondary())),
+        level_preset: None,
         move_to: None, // Don't move intermediate file
         show_output_size: false,
-        force: config.force,
         encryption: None, // Encryption happens after compound compression
+        ..config.clone()
     };
 
-    // Remove timestamp to avoid duplication
-    let new_config = config.clone().with_timestamp(TimestampOption::None);
+    // Remove timestamp and name template to avoid duplication
+    let new_config = config
+        .clone()
+        .with_timestamp(TimestampOption::None)
+        .with_name_template(None);
 
     let tar_output = tar_compressor.compress(input, &tar_config)?;
     debug!("Created intermediate TAR: {}", tar_output.display());
 
     // Step 2: Compress TAR with secondary compressor
-    let secondary_compressor = create_compressor(format.secondary());
-    let secondary_output = secondary_compressor.compress(&tar_output, &new_config)?;
+    let compressed = compress_tar(&tar_output, format, &new_config);
 
-    // Step 3: Remove intermediate TAR file
+    // Step 3: Remove intermediate TAR file, also when step 2 failed or
+    // was interrupted
     if let Err(e) = remove_file_silent(&tar_output) {
         debug!("Failed to remove intermediate TAR: {}", e);
     }
+    let (secondary_output, index) = compressed?;
+    if let Some(index) = index {
+        index.save(&secondary_output)?;
+    }
 
     info!("Created compound archive: {}", secondary_output.display());
 
     // Step 4: Encrypt if encryption is enabled
     if let Some(encryption_method) = &config.encryption {
-        encrypt::encrypt_file(&secondary_output, encryption_method)
+        encrypt::encrypt_file(
+            &secondary_output,
+            encryption_method,
+            config.secrets.as_ref(),
+            config.pad_to,
+            integrity::seal_tag(input, config)?,
+            config.syncs_outputs(),
+        )
     } else {
         Ok(secondary_output)
     }
 }
 
+/// Compress the intermediate `tar` with the secondary compressor of
+/// `format`, or into a seekable `.tar.gz` and its index (to be saved beside
+/// the archive once it is in place) with `config.indexed`
+pub(crate) fn compress_tar(
+    tar: &Path,
+    format: CompoundFormat,
+    config: &CompressionConfig,
+) -> JcResult<(PathBuf, Option<ArchiveIndex>)> {
+    if config.indexed && format == CompoundFormat::Tgz {
+        let (output, index) = index::compress_indexed(tar, config)?;
+        return Ok((output, Some(index)));
+    }
+    let output = create_compressor(format.secondary()).compress(tar, config)?;
+    Ok((output, None))
+}
+
 /// Compress multiple files with compound format
 pub fn compress_compound_batch(
     inputs: Vec<PathBuf>,
     format: CompoundFormat,
     config: CompressionConfig,
-) -> Vec<JcResult<PathBuf>> {
+) -> Vec<JcResult<FileStats>> {
+    run_limited(config.threads, || batch_limited(inputs, format, config))
+}
+
+fn batch_limited(
+    inputs: Vec<PathBuf>,
+    format: CompoundFormat,
+    config: CompressionConfig,
+) -> Vec<JcResult<FileStats>> {
+    let job_config = config.for_batch(inputs.len());
+
     // Check if password encryption is used
     let has_password_encryption = matches!(
         config.encryption,
@@ -71,31 +153,36 @@ pub fn compress_compound_batch(
 
     if has_password_encryption {
         // For password encryption, compress all files first, then encrypt with shared password
-        let compressed: Vec<JcResult<PathBuf>> = inputs
+        let (compressed, elapsed): (Vec<JcResult<PathBuf>>, Vec<_>) = inputs
             .par_iter()
             .map(|input| {
                 // Compress without encryption first
-                let mut temp_config = config.clone();
+                let mut temp_config = job_config.clone();
                 temp_config.encryption = None;
-                compress_compound(input, format, &temp_config)
+                timed(|| config.run_job(input, || compress_compound(input, format, &temp_config)))
             })
-            .collect();
-
-        // Collect successful compressions
-        let compressed_paths: Vec<PathBuf> =
-            compressed.into_iter().filter_map(|r| r.ok()).collect();
+            .unzip();
 
         // Encrypt all with the same password
-        if let Some(encryption_method) = &config.encryption {
-            encrypt::encrypt_files(compressed_paths, encryption_method)
-        } else {
-            vec![]
-        }
+        let outputs = match &config.encryption {
+            Some(encryption_method) => {
+                encrypt_successful(&inputs, compressed, encryption_method, &config)
+            }
+            None => compressed,
+        };
+
+        let outputs = integrity::attach_tags(&inputs, outputs, &config);
+        stats::compression_stats(&inputs, outputs, &elapsed)
     } else {
         // For RSA or no encryption, process independently
-        inputs
+        let (outputs, elapsed): (Vec<_>, Vec<_>) = inputs
             .par_iter()
-            .map(|input| compress_compound(input, format, &config))
-            .collect()
+            .map(|input| {
+                timed(|| config.run_job(input, || compress_compound(input, format, &job_config)))
+            })
+            .unzip();
+
+        let outputs = integrity::attach_tags(&inputs, outputs, &config);
+        stats::compression_stats(&inputs, outputs, &elapsed)
     }
 }
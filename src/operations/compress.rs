@@ -1,27 +1,30 @@
 use rayon::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::compressors::create_compressor;
+use crate::core::compressor::Compressor;
 use crate::core::config::CompressionConfig;
 use crate::core::error::{JcError, JcResult};
 use crate::core::types::CompressionFormat;
-use crate::operations::encrypt;
-use crate::utils::{error, info};
+use crate::operations::stats::{self, timed, FileStats};
+use crate::operations::{encrypt, integrity};
+use crate::utils::{error, info, run_limited};
 
 /// Compress a single file
 #[allow(dead_code)]
 pub fn compress_file(
-    input: &PathBuf,
+    input: &Path,
     format: CompressionFormat,
     config: &CompressionConfig,
 ) -> JcResult<PathBuf> {
     let compressor = create_compressor(format);
 
     // Validate compression level if supported
-    if compressor.supports_levels() && !compressor.validate_level(config.level) {
+    let level = config.level_for(&*compressor);
+    if compressor.supports_levels() && !compressor.validate_level(level) {
         return Err(JcError::InvalidCompressionLevel {
             algorithm: compressor.name().to_string(),
-            level: config.level,
+            level,
         });
     }
 
@@ -29,45 +32,108 @@ pub fn compress_file(
 
     // Encrypt if encryption is enabled
     if let Some(encryption_method) = &config.encryption {
-        encrypt::encrypt_file(&compressed_path, encryption_method)
+        encrypt::encrypt_file(
+            &compressed_path,
+            encryption_method,
+            config.secrets.as_ref(),
+            config.pad_to,
+            integrity::seal_tag(input, config)?,
+            config.syncs_outputs(),
+        )
     } else {
         Ok(compressed_path)
     }
 }
 
-/// Compress multiple files concurrently
+/// Compress multiple files concurrently, returning one result per input
+/// in input order
 pub fn compress_files(
     inputs: Vec<PathBuf>,
     format: CompressionFormat,
     config: CompressionConfig,
-) -> Vec<JcResult<PathBuf>> {
-    info!("Compressing {} files with {}", inputs.len(), format.name());
+) -> Vec<JcResult<FileStats>> {
+    compress_files_with(inputs, create_compressor(format).as_ref(), config)
+}
+
+/// Compress multiple files concurrently with `compressor`, which may be a
+/// user-registered one
+pub fn compress_files_with(
+    inputs: Vec<PathBuf>,
+    compressor: &dyn Compressor,
+    config: CompressionConfig,
+) -> Vec<JcResult<FileStats>> {
+    info!(
+        "Compressing {} files with {}",
+        inputs.len(),
+        compressor.name()
+    );
 
-    // Compress files first
-    let compressed: Vec<JcResult<PathBuf>> = inputs
-        .par_iter()
-        .map(|input| {
-            let compressor = create_compressor(format);
-            if compressor.supports_levels() && !compressor.validate_level(config.level) {
-                return Err(JcError::InvalidCompressionLevel {
-                    algorithm: compressor.name().to_string(),
-                    level: config.level,
-                });
-            }
-            compressor.compress(input, &config).map_err(|e| {
-                error!("Failed to compress {}: {}", input.display(), e);
-                e
+    run_limited(config.threads, || {
+        let job_config = config.for_batch(inputs.len());
+
+        // Compress files first
+        let (compressed, elapsed): (Vec<JcResult<PathBuf>>, Vec<_>) = inputs
+            .par_iter()
+            .map(|input| {
+                timed(|| {
+                    config.run_job(input, || {
+                        let level = config.level_for(&*compressor);
+                        if compressor.supports_levels() && !compressor.validate_level(level) {
+                            return Err(JcError::InvalidCompressionLevel {
+                                algorithm: compressor.name().to_string(),
+                                level,
+                            });
+                        }
+                        compressor.compress(input, &job_config).map_err(|e| {
+                            error!("Failed to compress {}: {}", input.display(), e);
+                            e
+                        })
+                    })
+                })
             })
-        })
-        .collect();
+            .unzip();
 
-    // If encryption is enabled, encrypt all compressed files
-    if let Some(encryption_method) = &config.encryption {
-        let compressed_paths: Vec<PathBuf> =
-            compressed.into_iter().filter_map(|r| r.ok()).collect();
+        // If encryption is enabled, encrypt all compressed files
+        let outputs = if let Some(encryption_method) = &config.encryption {
+            encrypt_successful(&inputs, compressed, encryption_method, &config)
+        } else {
+            compressed
+        };
 
-        encrypt::encrypt_files(compressed_paths, encryption_method)
-    } else {
-        compressed
-    }
+        let outputs = integrity::attach_tags(&inputs, outputs, &config);
+        stats::compression_stats(&inputs, outputs, &elapsed)
+    })
+}
+
+/// Encrypt the successful outputs of a batch, each sealed with the
+/// integrity tag of its input, keeping results aligned with the batch
+/// inputs
+pub(crate) fn encrypt_successful(
+    inputs: &[PathBuf],
+    results: Vec<JcResult<PathBuf>>,
+    encryption_method: &crate::core::config::EncryptionMethod,
+    config: &CompressionConfig,
+) -> Vec<JcResult<PathBuf>> {
+    let results: Vec<JcResult<(PathBuf, Option<[u8; 32]>)>> = results
+        .into_iter()
+        .zip(inputs)
+        .map(|(result, input)| Ok((result?, integrity::seal_tag(input, config)?)))
+        .collect();
+    let compressed_paths = results
+        .iter()
+        .filter_map(|r| r.as_ref().ok().cloned())
+        .collect();
+    let mut encrypted = encrypt::encrypt_files(
+        compressed_paths,
+        encryption_method,
+        config.secrets.as_ref(),
+        config.pad_to,
+        config.syncs_outputs(),
+    )
+    .into_iter();
+
+    results
+        .into_iter()
+        .map(|result| result.and_then(|_| encrypted.next().expect("one result per path")))
+        .collect()
 }
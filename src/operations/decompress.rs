@@ -1,24 +1,37 @@
 use rayon::prelude::*;
+use std::ffi::OsString;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+use tempfile::TempDir;
 
 use crate::compressors::{
-    detect_format, Bzip2Compressor, GzipCompressor, TarCompressor, XzCompressor, ZipCompressor,
+    create_compressor, detect_compound_format, detect_format, sniff_format, strip_layer_extension,
+    ArCompressor, Bzip2Compressor, CommandCompressor, CommandRegistry, CpioCompressor,
+    DebCompressor, DedupCompressor, GzipCompressor, RpmCompressor, TarCompressor, XzCompressor,
+    ZipCompressor,
 };
-use crate::core::config::{CompressionConfig, DecompressionConfig};
+use crate::core::compressor::Compressor;
+use crate::core::config::{CompressionConfig, DecompressionConfig, ExtractDir};
 use crate::core::error::{JcError, JcResult};
-use crate::operations::decrypt;
-use crate::utils::{create_decompress_temp_dir, debug, error, info, prompt_overwrite};
+use crate::core::types::{CompoundFormat, CompressionFormat};
+use crate::operations::space::check_space;
+use crate::operations::stats::{tree_size, FileStats};
+use crate::operations::{decrypt, gpg};
+use crate::utils::lock::ExtractionLock;
+use crate::utils::xattrs::{copy_attributes, XattrSelection};
+use crate::utils::{
+    create_scratch_dir, debug, error, info, move_atomic, move_file_if_needed, remove_original,
+    run_limited, sync_output,
+};
 
 /// Helper function to decompress in a working directory based on format
 fn decompress_in_working_dir(
-    format: crate::core::types::CompressionFormat,
-    input: &PathBuf,
-    working_dir: &PathBuf,
+    format: CompressionFormat,
+    input: &Path,
+    working_dir: &Path,
     config: &CompressionConfig,
 ) -> JcResult<PathBuf> {
-    use crate::core::types::CompressionFormat;
-
     match format {
         CompressionFormat::Gzip => {
             let compressor = GzipCompressor::new();
@@ -40,98 +53,471 @@ fn decompress_in_working_dir(
             let compressor = ZipCompressor::new();
             compressor.decompress_in_dir(input, working_dir, config)
         }
+        CompressionFormat::Cpio => {
+            let compressor = CpioCompressor::new();
+            compressor.decompress_in_dir(input, working_dir, config)
+        }
+        CompressionFormat::Ar => {
+            let compressor = ArCompressor::new();
+            compressor.decompress_in_dir(input, working_dir, config)
+        }
+        CompressionFormat::Dedup => {
+            let compressor = DedupCompressor::new();
+            compressor.decompress_in_dir(input, working_dir, config)
+        }
+        CompressionFormat::Deb | CompressionFormat::Rpm => {
+            decompress_package(format, input, working_dir, config)
+        }
     }
 }
 
+/// Unpack a .deb or .rpm into a directory named after the package
+///
+/// Each member archive is run through the regular layer loop in its own
+/// scratch directory and the result is moved into the package directory.
+/// Debian control files land in `DEBIAN/`, as with `dpkg-deb -R`.
+fn decompress_package(
+    format: CompressionFormat,
+    input: &Path,
+    working_dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<PathBuf> {
+    let stem = input
+        .file_stem()
+        .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+    let package_dir = working_dir.join(stem);
+    fs::create_dir_all(&package_dir)?;
+
+    let members_dir = create_scratch_dir(config.temp_dir.as_deref(), None)?;
+
+    if format == CompressionFormat::Deb {
+        let members = DebCompressor::new().extract_members(input, members_dir.path())?;
+        extract_member(&members.data, &package_dir, config)?;
+        if let Some(control) = members.control {
+            extract_member(&control, &package_dir.join("DEBIAN"), config)?;
+        }
+    } else {
+        let payload = RpmCompressor::new().extract_payload(input, members_dir.path())?;
+        extract_member(&payload, &package_dir, config)?;
+    }
+
+    Ok(package_dir)
+}
+
+/// Fully decompress a package member and move its contents into `dest_dir`
+fn extract_member(member: &Path, dest_dir: &Path, config: &CompressionConfig) -> JcResult<()> {
+    let scratch = create_scratch_dir(config.temp_dir.as_deref(), dest_dir.parent())?;
+    let output = decompress_layers(member, scratch.path(), config)?;
+
+    fs::create_dir_all(dest_dir)?;
+
+    // Several top-level entries come back as the scratch dir itself
+    let entries = if output == scratch.path() {
+        fs::read_dir(&output)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![output]
+    };
+
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .ok_or_else(|| JcError::Other("Invalid extracted filename".to_string()))?;
+        let target = dest_dir.join(name);
+        if !move_atomic(&entry, &target)? {
+            copy_attributes(&entry, &target, XattrSelection::from_config(config))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// User-registered compressor for `path`, when no built-in format claims it
+fn external_format<'a>(
+    path: &Path,
+    config: &'a CompressionConfig,
+) -> Option<&'a CommandCompressor> {
+    config.external.as_ref()?.for_path(path)
+}
+
+/// Peel compression layers off `input` inside `working_dir` until none remain
+fn decompress_layers(
+    input: &Path,
+    working_dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<PathBuf> {
+    let mut current_file = input.to_path_buf();
+
+    // Iteratively decompress until no more compression detected
+    loop {
+        let output = if let Some(compound) = detect_compound_format(&current_file) {
+            // .tar.gz / .tgz and friends are peeled as one unit
+            info!(
+                "Decompression iteration: format={:?}, current_file={}",
+                compound,
+                current_file.display()
+            );
+
+            let tar_file = decompress_in_working_dir(
+                compound.secondary(),
+                &current_file,
+                working_dir,
+                config,
+            )?;
+            decompress_in_working_dir(compound.primary(), &tar_file, working_dir, config)?
+        } else if let Some(external) = external_format(&current_file, config) {
+            info!(
+                "Decompression iteration: format={}, current_file={}",
+                external.name(),
+                current_file.display()
+            );
+            external.decompress_in_dir(&current_file, working_dir, config)?
+        } else {
+            let format = detect_format(&current_file).ok_or_else(|| {
+                JcError::InvalidExtension(
+                    current_file.clone(),
+                    "supported compression format".to_string(),
+                )
+            })?;
+
+            info!(
+                "Decompression iteration: format={:?}, current_file={}",
+                format,
+                current_file.display()
+            );
+
+            // Decompress in working directory
+            decompress_in_working_dir(format, &current_file, working_dir, config)?
+        };
+
+        info!("Decompressed to: {}", output.display());
+
+        current_file = output;
+
+        // Check if output has another compression layer
+        if current_file == working_dir || !has_another_layer(&current_file, config) {
+            info!("No more compression layers detected");
+            break;
+        }
+    }
+
+    Ok(current_file)
+}
+
+/// Whether the output of a layer is itself compressed
+///
+/// Its name only says so when its contents agree: `notes.zip.gz` may well
+/// hold a text file that was merely named `notes.zip`. Registered
+/// compressors have no magic to check, so their extension is trusted.
+fn has_another_layer(path: &Path, config: &CompressionConfig) -> bool {
+    if path.is_dir() {
+        return false;
+    }
+    match detect_format(path) {
+        Some(named) => match sniff_format(path) {
+            Some(found) if found == named => true,
+            found => {
+                info!(
+                    "{} is named like {} but holds {}; leaving it as is",
+                    path.display(),
+                    named.name(),
+                    found.map_or("no known format", |f| f.name())
+                );
+                false
+            }
+        },
+        None => external_format(path, config).is_some(),
+    }
+}
+
+/// Peel every layer off `input` in a fresh temp dir, leaving it in place
+///
+/// The returned temp dir owns the output and removes it when dropped.
+pub(crate) fn extract_to_temp(
+    input: &Path,
+    config: &CompressionConfig,
+) -> JcResult<(TempDir, PathBuf)> {
+    let temp_dir = create_scratch_dir(config.temp_dir.as_deref(), None)?;
+    let output = decompress_layers(input, temp_dir.path(), config)?;
+    Ok((temp_dir, output))
+}
+
 /// Decompress a single file with decryption support
+///
+/// A `.jcze` or `.gpg` input is decrypted into scratch space and unpacked from
+/// there, so the intermediate decrypted file never lands next to the
+/// input and is gone afterwards, whether or not decompression succeeds.
+/// With `decrypt_only`, the decrypted file is the output instead.
 pub fn decompress_file_with_decryption(
-    input: &PathBuf,
+    input: &Path,
     config: &DecompressionConfig,
 ) -> JcResult<PathBuf> {
-    // First, decrypt if the file is encrypted
-    let decrypted_path =
-        decrypt::decrypt_file(input, config.decryption.as_ref(), config.remove_encrypted)?;
+    if config.decrypt_only {
+        let decrypted_path = decrypt::decrypt_file(
+            input,
+            config.decryption.as_ref(),
+            config.secrets.as_ref(),
+            false,
+        )?;
+        if config.remove_encrypted && decrypted_path != input {
+            sync_output(&decrypted_path, config.durability)?;
+            remove_original(input, config.trash)?;
+        }
+        let final_path = move_file_if_needed(&decrypted_path, &config.move_to)?;
+        info!("Decrypted file: {}", final_path.display());
+        return Ok(final_path);
+    }
+
+    // Outputs go where they would for the encrypted input
+    let dest_dir = match config.move_to {
+        Some(ref move_to) => move_to.clone(),
+        None => input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+    };
+
+    let scratch;
+    let decrypted_path = if decrypt::is_encrypted_file(input) || gpg::is_gpg_file(input) {
+        scratch = create_scratch_dir(config.temp_dir.as_deref(), Some(&dest_dir))?;
+        decrypt::decrypt_into(
+            input,
+            scratch.path(),
+            config.decryption.as_ref(),
+            config.secrets.as_ref(),
+        )?
+    } else {
+        input.to_path_buf()
+    };
 
     // Then decompress using the standard config
     let compression_config = CompressionConfig {
-        level: 6,
-        timestamp: crate::core::config::TimestampOption::None,
-        move_to: config.move_to.clone(),
-        show_output_size: false,
+        move_to: Some(dest_dir),
         force: config.force,
-        encryption: None,
+        durability: config.durability,
+        preserve_xattrs: config.preserve_xattrs,
+        preserve_acls: config.preserve_acls,
+        gzip_no_name: config.gzip_no_name,
+        zip_password: config.zip_password.clone(),
+        zip_encoding: config.zip_encoding.clone(),
+        threads: config.threads,
+        limit_rate: config.limit_rate.clone(),
+        temp_dir: config.temp_dir.clone(),
+        direct: config.direct,
+        incremental: config.incremental,
+        extract_dir: config.extract_dir.clone(),
+        backup: config.backup.clone(),
+        trash: config.trash,
+        salvage: config.salvage,
+        no_verify: config.no_verify,
+        decode_as: config.decode_as.clone(),
+        serial_tools: config.serial_tools,
+        decrypt_only: config.decrypt_only,
+        memory_limit: config.memory_limit,
+        secrets: config.secrets.clone(),
+        overwrite: config.overwrite.clone(),
+        progress: config.progress.clone(),
+        entry_listener: config.entry_listener.clone(),
+        cancel: config.cancel.clone(),
+        external: config.external.clone(),
+        ..CompressionConfig::default()
     };
 
-    decompress_file(&decrypted_path, &compression_config)
+    let output = decompress_file(&decrypted_path, &compression_config)?;
+
+    // Only once the contents are safely out
+    if config.remove_encrypted && decrypted_path != input {
+        remove_original(input, config.trash)?;
+    }
+    Ok(output)
 }
 
 /// Decompress a single file, handling compound formats
-pub fn decompress_file(input: &PathBuf, config: &CompressionConfig) -> JcResult<PathBuf> {
-    // Create a temporary directory for decompression work
-    let temp_dir = create_decompress_temp_dir()?;
-    let temp_dir_path = temp_dir.path().to_path_buf();
-
-    debug!("Created temp directory: {}", temp_dir_path.display());
-
-    let mut current_file = input.clone();
+///
+/// Extractions of the same archive into the same directory are serialized
+/// across processes. A process that waited while another finished the
+/// same extraction returns that output instead of extracting again.
+pub fn decompress_file(input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+    if let Some(ref format) = config.decode_as {
+        return decompress_as(input, format, config);
+    }
 
-    // Iteratively decompress until no more compression detected
-    loop {
-        let format = detect_format(&current_file).ok_or_else(|| {
-            JcError::InvalidExtension(
-                current_file.clone(),
-                "supported compression format".to_string(),
-            )
-        })?;
+    let dest_dir = match config.move_to {
+        Some(ref move_to) => move_to.clone(),
+        None => input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+    };
+    if !input.exists() {
+        return Err(JcError::FileNotFound(input.to_path_buf()));
+    }
 
+    let mut lock = ExtractionLock::acquire(input, &dest_dir)?;
+    if let Some(output) = lock.completed_output()? {
         info!(
-            "Decompression iteration: format={:?}, current_file={}",
-            format,
-            current_file.display()
+            "{} was just extracted by another jcz process: {}",
+            input.display(),
+            output.display()
         );
+        return Ok(output);
+    }
 
-        // Decompress in temp directory
-        let output = decompress_in_working_dir(format, &current_file, &temp_dir_path, config)?;
+    let output = extract_to_destination(input, config)?;
+    lock.record_completion(&output)?;
+    Ok(output)
+}
 
-        info!("Decompressed to: {}", output.display());
+/// Decompress `input` as `format` (`-d --format`), whatever its name says
+///
+/// A link to it (or a copy, across filesystems) is named with the format's
+/// extension in scratch space and decompressed from there into the
+/// directory `input` would go to: `data.bin` as xz gives `data`, and
+/// `blob`, with no extension to drop, gives `blob.out`.
+fn decompress_as(input: &Path, format: &str, config: &CompressionConfig) -> JcResult<PathBuf> {
+    if !input.exists() {
+        return Err(JcError::FileNotFound(input.to_path_buf()));
+    }
+    let extension = format_extension(format, config.external.as_deref())
+        .ok_or_else(|| JcError::Other(format!("Unknown format: {}", format)))?;
+    let dest_dir = match config.move_to {
+        Some(ref move_to) => move_to.clone(),
+        None => input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+    };
 
-        current_file = output;
+    let name = input
+        .file_name()
+        .ok_or_else(|| JcError::Other("Invalid input path".to_string()))?;
+    let stem = match Path::new(name).file_stem() {
+        Some(stem) if stem != name => stem.to_os_string(),
+        _ => {
+            let mut stem = name.to_os_string();
+            stem.push(".out");
+            stem
+        }
+    };
+    let mut staged_name = stem;
+    staged_name.push(".");
+    staged_name.push(&extension);
 
-        // Check if output has another compression layer
-        if detect_format(&current_file).is_none() {
-            info!("No more compression layers detected");
-            break;
+    let scratch = create_scratch_dir(config.temp_dir.as_deref(), Some(&dest_dir))?;
+    let staged = scratch.path().join(staged_name);
+    if fs::hard_link(input, &staged).is_err() {
+        fs::copy(input, &staged)?;
+    }
+    debug!(
+        "Decompressing {} as {} through {}",
+        input.display(),
+        format,
+        staged.display()
+    );
+
+    let config = CompressionConfig {
+        move_to: Some(dest_dir),
+        decode_as: None,
+        ..config.clone()
+    };
+    decompress_file(&staged, &config)
+}
+
+/// Extension of files in `format`: a command name (`gzip`, `tgz`), a
+/// format extension (`gz`, `tar.gz`, `deb`) or a registered compressor
+pub fn format_extension(format: &str, external: Option<&CommandRegistry>) -> Option<String> {
+    let compound = CompoundFormat::from_str(format)
+        .or_else(|| CompoundFormat::from_filename(&format!("x.{}", format)));
+    if let Some(compound) = compound {
+        return Some(compound.extension().to_string());
+    }
+    if let Some(known) = CompressionFormat::from_name(format) {
+        return Some(create_compressor(known).extension().to_string());
+    }
+    if CompressionFormat::from_extension(format).is_some()
+        || CompoundFormat::from_alias(format).is_some()
+    {
+        return Some(format.to_lowercase());
+    }
+    external?.get(format).map(|c| c.extension().to_string())
+}
+
+/// Decompress `input` in a temp dir, then place the result at its destination
+fn extract_to_destination(input: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+    let dest_dir = match config.move_to {
+        Some(ref move_to) => move_to.as_path(),
+        None => input
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new(".")),
+    };
+
+    if config.incremental {
+        return extract_incremental(input, dest_dir, config);
+    }
+
+    let own_dir = config
+        .extract_dir
+        .as_ref()
+        .map(|extract_dir| extract_dir_name(extract_dir, input))
+        .transpose()?;
+
+    if config.direct {
+        if let Some(name) = &own_dir {
+            let dir = dest_dir.join(name);
+            if extract_direct(input, &dir, config)?.is_some() {
+                return Ok(dir);
+            }
+        } else if let Some(output) = extract_direct(input, dest_dir, config)? {
+            return Ok(output);
         }
+        debug!("{} is not a plain tar or zip, staging", input.display());
     }
 
-    // Determine final destination
-    let final_dest = if let Some(ref move_to) = config.move_to {
-        // When using -C with multiple extracted files, use move_to directly
-        // Otherwise, create a subdirectory based on the actual extracted content name
-        if current_file.is_dir() && current_file == temp_dir_path {
-            // Multiple files extracted - put them directly in move_to
-            move_to.clone()
-        } else {
-            // Single file or directory - use the actual extracted content name
-            let extracted_name = current_file
-                .file_name()
-                .ok_or_else(|| JcError::Other("Invalid extracted filename".to_string()))?;
-            move_to.join(extracted_name)
+    // Create a temporary directory for decompression work, on the
+    // destination filesystem unless told otherwise
+    let temp_dir = create_scratch_dir(config.temp_dir.as_deref(), Some(dest_dir))?;
+    let temp_dir_path = temp_dir.path().to_path_buf();
+
+    debug!("Created temp directory: {}", temp_dir_path.display());
+
+    // Fail before anything is written rather than halfway through
+    check_space(input, dest_dir, &temp_dir_path)?;
+
+    let current_file = match &own_dir {
+        Some(name) => {
+            // Layers are peeled in a directory of their own, so that no
+            // entry can clash with the one named after the archive
+            let layers_dir = temp_dir_path.join(LAYERS_DIR);
+            fs::create_dir(&layers_dir)?;
+            let output = decompress_layers(input, &layers_dir, config)?;
+            gather_into(&output, &layers_dir, &temp_dir_path.join(name))?
         }
+        None => decompress_layers(input, &temp_dir_path, config)?,
+    };
+
+    // Determine final destination: -C, else next to the input archive
+    let final_dest = if current_file.is_dir() && current_file == temp_dir_path {
+        // Multiple files extracted - put them directly in the destination
+        dest_dir.to_path_buf()
     } else {
         // Use the actual extracted content name, not the archive name
         // current_file points to the extracted content in temp directory
         let extracted_name = current_file
             .file_name()
             .ok_or_else(|| JcError::Other("Invalid extracted filename".to_string()))?;
-
-        // Place it in the same directory as the input archive
-        let input_parent = input.parent().unwrap_or_else(|| Path::new("."));
-        input_parent.join(extracted_name)
+        dest_dir.join(extracted_name)
     };
 
     debug!("Final destination: {}", final_dest.display());
 
+    // Plain copies drop xattrs/ACLs, so carry them over explicitly
+    let selection = XattrSelection::from_config(config);
+
     // Move from temp directory to final destination
     // All decompressed files are in temp directory, so we always need to copy/move them
     if current_file.is_dir() {
@@ -140,85 +526,57 @@ pub fn decompress_file(input: &PathBuf, config: &CompressionConfig) -> JcResult<
         if current_file == temp_dir_path {
             // This is the working directory itself (multiple loose files from TAR)
             // Copy contents to final destination
-            fs::create_dir_all(&final_dest).map_err(|e| JcError::Io(e))?;
-            for entry in fs::read_dir(&current_file).map_err(|e| JcError::Io(e))? {
-                let entry = entry.map_err(|e| JcError::Io(e))?;
+            fs::create_dir_all(&final_dest).map_err(JcError::Io)?;
+            let mut placed = 0;
+            for entry in fs::read_dir(&current_file).map_err(JcError::Io)? {
+                let entry = entry.map_err(JcError::Io)?;
                 let src_path = entry.path();
                 let dst_path = final_dest.join(entry.file_name());
 
-                // Check if individual file exists and prompt for overwrite
-                if dst_path.exists() && !config.force {
-                    if !prompt_overwrite(&dst_path)? {
-                        info!("Skipping {}", dst_path.display());
-                        continue;
-                    }
+                // Check if individual file exists and may be overwritten
+                if dst_path.exists() && !config.make_way(&dst_path)? {
+                    info!("Skipping {}", dst_path.display());
+                    continue;
                 }
 
-                use crate::utils::copy_recursive;
-                if src_path.is_dir() {
-                    copy_recursive(&src_path, &dst_path).map_err(|e| JcError::Io(e))?;
-                } else {
-                    fs::copy(&src_path, &dst_path).map_err(|e| JcError::Io(e))?;
+                if !move_atomic(&src_path, &dst_path).map_err(JcError::Io)? {
+                    copy_attributes(&src_path, &dst_path, selection)?;
                 }
+                sync_output(&dst_path, config.durability)?;
+                placed += 1;
             }
-            info!(
-                "Decompressed {} files to: {}",
-                fs::read_dir(&final_dest).map(|d| d.count()).unwrap_or(0),
-                final_dest.display()
-            );
+            info!("Decompressed {} files to: {}", placed, final_dest.display());
         } else {
             // This is a subdirectory that was extracted from TAR
             // Check if destination exists
-            if final_dest.exists() && !config.force {
-                if !prompt_overwrite(&final_dest)? {
-                    return Err(JcError::Other(format!(
-                        "Decompression aborted: directory already exists: {}",
-                        final_dest.display()
-                    )));
-                }
-                // Remove existing directory/file before copying
-                if final_dest.is_dir() {
-                    fs::remove_dir_all(&final_dest).map_err(|e| JcError::Io(e))?;
-                } else {
-                    fs::remove_file(&final_dest).map_err(|e| JcError::Io(e))?;
-                }
-            } else if final_dest.exists() && config.force {
-                // Force mode: remove without prompting
-                if final_dest.is_dir() {
-                    fs::remove_dir_all(&final_dest).map_err(|e| JcError::Io(e))?;
-                } else {
-                    fs::remove_file(&final_dest).map_err(|e| JcError::Io(e))?;
-                }
+            if final_dest.exists() && !config.make_way(&final_dest)? {
+                return Err(JcError::Other(format!(
+                    "Decompression aborted: directory already exists: {}",
+                    final_dest.display()
+                )));
+            }
+            // Renamed, or a staged copy, so the existing directory is only
+            // replaced once complete
+            if !move_atomic(&current_file, &final_dest).map_err(JcError::Io)? {
+                copy_attributes(&current_file, &final_dest, selection)?;
             }
-            use crate::utils::copy_recursive;
-            copy_recursive(&current_file, &final_dest).map_err(|e| JcError::Io(e))?;
+            sync_output(&final_dest, config.durability)?;
             info!("Decompressed directory: {}", final_dest.display());
         }
     } else {
         // Copy single file
         // Check if destination exists
-        if final_dest.exists() && !config.force {
-            if !prompt_overwrite(&final_dest)? {
-                return Err(JcError::Other(format!(
-                    "Decompression aborted: file already exists: {}",
-                    final_dest.display()
-                )));
-            }
-            // Remove existing file/directory before copying
-            if final_dest.is_dir() {
-                fs::remove_dir_all(&final_dest).map_err(|e| JcError::Io(e))?;
-            } else {
-                fs::remove_file(&final_dest).map_err(|e| JcError::Io(e))?;
-            }
-        } else if final_dest.exists() && config.force {
-            // Force mode: remove without prompting
-            if final_dest.is_dir() {
-                fs::remove_dir_all(&final_dest).map_err(|e| JcError::Io(e))?;
-            } else {
-                fs::remove_file(&final_dest).map_err(|e| JcError::Io(e))?;
-            }
+        if final_dest.exists() && !config.make_way(&final_dest)? {
+            return Err(JcError::Other(format!(
+                "Decompression aborted: file already exists: {}",
+                final_dest.display()
+            )));
         }
-        fs::copy(&current_file, &final_dest).map_err(|e| JcError::Io(e))?;
+        // Renamed, or a staged copy, so readers never see a half-written file
+        if !move_atomic(&current_file, &final_dest).map_err(JcError::Io)? {
+            copy_attributes(&current_file, &final_dest, selection)?;
+        }
+        sync_output(&final_dest, config.durability)?;
         info!("Decompressed file: {}", final_dest.display());
     }
 
@@ -226,50 +584,260 @@ pub fn decompress_file(input: &PathBuf, config: &CompressionConfig) -> JcResult<
     Ok(final_dest)
 }
 
-/// Decompress multiple files concurrently
+/// Scratch subdirectory layers are peeled in under `--extract-dir`
+const LAYERS_DIR: &str = ".jcz-layers";
+
+/// Name of the directory `input` is extracted into under `extract_dir`:
+/// the given name, or the archive's less its compression and archive
+/// extensions (`site.tar.gz` extracts into `site/`)
+fn extract_dir_name(extract_dir: &ExtractDir, input: &Path) -> JcResult<OsString> {
+    let name = match extract_dir {
+        ExtractDir::Named(name) => OsString::from(name),
+        ExtractDir::FromArchive => {
+            let mut path = PathBuf::from(input.file_name().unwrap_or_default());
+            while detect_compound_format(&path).is_some() || detect_format(&path).is_some() {
+                let stripped = strip_layer_extension(&path);
+                if stripped.as_os_str().is_empty() {
+                    break;
+                }
+                path = stripped;
+            }
+            path.into_os_string()
+        }
+    };
+    let mut components = Path::new(&name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if name != LAYERS_DIR => Ok(name),
+        _ => Err(JcError::Other(format!(
+            "Invalid extraction directory name: {}",
+            name.to_string_lossy()
+        ))),
+    }
+}
+
+/// Move `output`, what was peeled in `layers_dir`, into a new `dir`: the
+/// entries of `layers_dir` itself when the archive held several, else
+/// `output` alone
+fn gather_into(output: &Path, layers_dir: &Path, dir: &Path) -> JcResult<PathBuf> {
+    if output == layers_dir {
+        fs::rename(layers_dir, dir)?;
+    } else {
+        fs::create_dir(dir)?;
+        let name = output
+            .file_name()
+            .ok_or_else(|| JcError::Other("Invalid extracted filename".to_string()))?;
+        fs::rename(output, dir.join(name))?;
+    }
+    Ok(dir.to_path_buf())
+}
+
+/// Extract a plain tar or zip straight into `dest_dir` (`--direct`)
+///
+/// Skips scratch space and the move out of it. Existing top-level entries
+/// are confirmed first, as in staged mode, but existing directories are
+/// merged into rather than replaced, and a failure partway leaves what was
+/// extracted so far. `None` for other formats, which are staged as usual.
+fn extract_direct(
+    input: &Path,
+    dest_dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<Option<PathBuf>> {
+    if detect_compound_format(input).is_some() {
+        return Ok(None);
+    }
+    let format = detect_format(input);
+    let names = match format {
+        Some(CompressionFormat::Tar) => crate::compressors::tar::top_level_entries(input)?,
+        Some(CompressionFormat::Zip) => crate::compressors::zip::top_level_entries(input)?,
+        _ => return Ok(None),
+    };
+
+    for name in &names {
+        let target = dest_dir.join(name);
+        if target.exists() && !config.make_way(&target)? {
+            return Err(JcError::Other(format!(
+                "Decompression aborted: {} already exists",
+                target.display()
+            )));
+        }
+    }
+    // The input is not copied, so this overstates the need a little
+    check_space(input, dest_dir, dest_dir)?;
+
+    fs::create_dir_all(dest_dir)?;
+    if format == Some(CompressionFormat::Tar) {
+        TarCompressor::new().extract_into(input, dest_dir, config)?;
+    } else {
+        ZipCompressor::new().extract_into(input, dest_dir, config)?;
+    }
+    for name in &names {
+        sync_output(&dest_dir.join(name), config.durability)?;
+    }
+
+    let output = match names.as_slice() {
+        [name] => dest_dir.join(name),
+        _ => dest_dir.to_path_buf(),
+    };
+    info!(
+        "Extracted {} directly to: {}",
+        input.display(),
+        output.display()
+    );
+    Ok(Some(output))
+}
+
+/// The tar inside `input`: `input` itself when it is a plain tar, else
+/// the tar of a compound archive (or of a registered compressor's
+/// `.tar.<ext>`), decompressed into `working_dir`; `None` for anything else
+pub(crate) fn peel_to_tar(
+    input: &Path,
+    working_dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<Option<PathBuf>> {
+    if let Some(compound) = detect_compound_format(input) {
+        let tar_file = decompress_in_working_dir(compound.secondary(), input, working_dir, config)?;
+        return Ok(Some(tar_file));
+    }
+    if let Some(external) = external_format(input, config).filter(|c| c.tar_path(input).is_some()) {
+        return external
+            .decompress_in_dir(input, working_dir, config)
+            .map(Some);
+    }
+    Ok((detect_format(input) == Some(CompressionFormat::Tar)).then(|| input.to_path_buf()))
+}
+
+/// Apply a tar-based archive to `dest_dir` as an incremental
+/// (`--incremental`)
+///
+/// The outer compression layer of a compound archive is peeled in scratch
+/// space; the tar is then extracted straight into `dest_dir`, merging with
+/// what an earlier archive of the chain left there, and the names it
+/// records as deleted are removed.
+fn extract_incremental(
+    input: &Path,
+    dest_dir: &Path,
+    config: &CompressionConfig,
+) -> JcResult<PathBuf> {
+    let temp_dir = create_scratch_dir(config.temp_dir.as_deref(), Some(dest_dir))?;
+    check_space(input, dest_dir, temp_dir.path())?;
+
+    let tar_file = peel_to_tar(input, temp_dir.path(), config)?.ok_or_else(|| {
+        JcError::InvalidExtension(input.to_path_buf(), "tar, tgz, tbz2 or txz".to_string())
+    })?;
+
+    let names = crate::compressors::tar::top_level_entries(&tar_file)?;
+    fs::create_dir_all(dest_dir)?;
+    TarCompressor::new().extract_incremental(&tar_file, dest_dir, config)?;
+    for name in &names {
+        sync_output(&dest_dir.join(name), config.durability)?;
+    }
+
+    let output = match names.as_slice() {
+        [name] => dest_dir.join(name),
+        _ => dest_dir.to_path_buf(),
+    };
+    info!(
+        "Applied incremental {} to: {}",
+        input.display(),
+        output.display()
+    );
+    Ok(output)
+}
+
+/// Decompress multiple files concurrently, returning one result per input
+/// in input order
 pub fn decompress_files(
     inputs: Vec<PathBuf>,
     config: CompressionConfig,
     decryption_method: Option<crate::core::config::DecryptionMethod>,
     remove_encrypted: bool,
-) -> Vec<JcResult<PathBuf>> {
+) -> Vec<JcResult<FileStats>> {
     info!("Decompressing {} files", inputs.len());
 
-    inputs
-        .par_iter()
-        .map(|input| {
-            // Check if file is encrypted (has .jcze extension)
-            let is_encrypted = input
-                .extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s == "jcze")
-                .unwrap_or(false);
-
-            if is_encrypted {
-                // Decrypt first, then decompress
-                let decompression_config = DecompressionConfig {
-                    move_to: config.move_to.clone(),
-                    force: config.force,
-                    decryption: decryption_method.clone(),
-                    remove_encrypted,
-                };
-                match decompress_file_with_decryption(input, &decompression_config) {
-                    Ok(output) => Ok(output),
-                    Err(e) => {
-                        error!("Failed to decompress {}: {}", input.display(), e);
-                        Err(e)
-                    }
-                }
-            } else {
-                // Normal decompression
-                match decompress_file(input, &config) {
-                    Ok(output) => Ok(output),
-                    Err(e) => {
-                        error!("Failed to decompress {}: {}", input.display(), e);
+    // A chain of incrementals must be applied one after another, in order
+    let min_len = if config.incremental { inputs.len() } else { 1 };
+
+    run_limited(config.threads, || {
+        let config = config.for_batch(inputs.len());
+        inputs
+            .par_iter()
+            .with_min_len(min_len.max(1))
+            .map(|input| {
+                config.run_job(input, || {
+                    let compressed_size = tree_size(input)?;
+                    let start = Instant::now();
+
+                    // Check if file is encrypted (has .jcze or .gpg extension)
+                    let is_encrypted = decrypt::is_encrypted_file(input) || gpg::is_gpg_file(input);
+
+                    if is_encrypted {
+                        // Decrypt first, then decompress
+                        let decompression_config = DecompressionConfig {
+                            move_to: config.move_to.clone(),
+                            force: config.force,
+                            decryption: decryption_method.clone(),
+                            remove_encrypted,
+                            durability: config.durability,
+                            preserve_xattrs: config.preserve_xattrs,
+                            preserve_acls: config.preserve_acls,
+                            gzip_no_name: config.gzip_no_name,
+                            zip_password: config.zip_password.clone(),
+                            zip_encoding: config.zip_encoding.clone(),
+                            threads: config.threads,
+                            limit_rate: config.limit_rate.clone(),
+                            temp_dir: config.temp_dir.clone(),
+                            direct: config.direct,
+                            incremental: config.incremental,
+                            extract_dir: config.extract_dir.clone(),
+                            backup: config.backup.clone(),
+                            trash: config.trash,
+                            salvage: config.salvage,
+                            no_verify: config.no_verify,
+                            decode_as: config.decode_as.clone(),
+                            serial_tools: config.serial_tools,
+                            decrypt_only: config.decrypt_only,
+                            memory_limit: config.memory_limit,
+                            secrets: config.secrets.clone(),
+                            overwrite: config.overwrite.clone(),
+                            progress: config.progress.clone(),
+                            entry_listener: config.entry_listener.clone(),
+                            cancel: config.cancel.clone(),
+                            external: config.external.clone(),
+                        };
+                        match decompress_file_with_decryption(input, &decompression_config) {
+                            Ok(output) => FileStats::decompressed(
+                                input.clone(),
+                                compressed_size,
+                                output,
+                                start.elapsed(),
+                            ),
+                            Err(e) => {
+                                error!("Failed to decompress {}: {}", input.display(), e);
+                                Err(e)
+                            }
+                        }
+                    } else if config.decrypt_only {
+                        // Nothing to decrypt, and decompression was not asked for
+                        let e = JcError::InvalidExtension(input.clone(), "jcze".to_string());
+                        error!("Failed to decrypt {}: {}", input.display(), e);
                         Err(e)
+                    } else {
+                        // Normal decompression
+                        match decompress_file(input, &config) {
+                            Ok(output) => FileStats::decompressed(
+                                input.clone(),
+                                compressed_size,
+                                output,
+                                start.elapsed(),
+                            ),
+                            Err(e) => {
+                                error!("Failed to decompress {}: {}", input.display(), e);
+                                Err(e)
+                            }
+                        }
                     }
-                }
-            }
-        })
-        .collect()
+                })
+            })
+            .collect()
+    })
 }
@@ -1,28 +1,28 @@
 //! Decryption operations for encrypted files
 
-use crate::core::config::DecryptionMethod;
+use crate::core::config::{DecryptionMethod, Durability, SecretPurpose, Secrets};
 use crate::core::error::{JcError, JcResult};
-use crate::crypto::{EncryptedContainer, EncryptionMetadata, PasswordEncryption, RsaEncryption};
-use crate::utils::{error, info};
+use crate::crypto::keys::{keyfile_id, read_keyfile};
+use crate::crypto::{
+    aead, padding, shamir, EncryptedContainer, EncryptionMetadata, KeyShare, PasswordEncryption,
+    RsaEncryption, X25519Encryption,
+};
+use crate::operations::gpg;
+use crate::utils::bufio::{buffered_reader, MappedFile};
+use crate::utils::{error, info, sync_output};
 use rayon::prelude::*;
-use std::fs;
+use std::fs::{self, File};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
-/// Prompt user for password securely (without echo)
-fn prompt_password() -> JcResult<String> {
-    use std::io::{self, Write};
-
-    print!("Enter decryption password: ");
-    io::stdout().flush()?;
-
-    let password = rpassword::read_password()
-        .map_err(|e| JcError::Other(format!("Failed to read password: {}", e)))?;
-
-    if password.is_empty() {
-        return Err(JcError::Other("Password cannot be empty".to_string()));
-    }
-
-    Ok(password)
+/// Decryption password from `secrets`
+fn password(secrets: Option<&Secrets>) -> JcResult<Zeroizing<String>> {
+    secrets
+        .ok_or_else(|| {
+            JcError::Other("Password-encrypted file needs a password provider".to_string())
+        })?
+        .password(SecretPurpose::Decrypt)
 }
 
 /// Check if a file is encrypted by looking for .jcze extension
@@ -37,79 +37,240 @@ pub fn is_encrypted_file(path: &Path) -> bool {
 pub fn decrypt_file(
     encrypted_file: &Path,
     decryption_method: Option<&DecryptionMethod>,
+    secrets: Option<&Secrets>,
     remove_encrypted: bool,
 ) -> JcResult<PathBuf> {
     // Check if file is encrypted
-    if !is_encrypted_file(encrypted_file) {
+    if !is_encrypted_file(encrypted_file) && !gpg::is_gpg_file(encrypted_file) {
         // Not encrypted, return as-is
         return Ok(encrypted_file.to_path_buf());
     }
 
+    // Written next to the encrypted file
+    let dir = encrypted_file.parent().unwrap_or(Path::new(""));
+    let output_path = decrypt_into(encrypted_file, dir, decryption_method, secrets)?;
+
+    // Remove encrypted file only if requested, once the output is durable
+    if remove_encrypted {
+        sync_output(&output_path, Durability::Default)?;
+        fs::remove_file(encrypted_file)?;
+        info!("Removed encrypted file: {}", encrypted_file.display());
+    }
+
+    Ok(output_path)
+}
+
+/// Decrypt `encrypted_file` into `dir`, under its name without `.jcze`
+/// (or `.gpg`, for which `gpg` does the work)
+pub fn decrypt_into(
+    encrypted_file: &Path,
+    dir: &Path,
+    decryption_method: Option<&DecryptionMethod>,
+    secrets: Option<&Secrets>,
+) -> JcResult<PathBuf> {
+    if gpg::is_gpg_file(encrypted_file) {
+        return gpg::decrypt_into(encrypted_file, dir);
+    }
+
     info!("Decrypting file: {}", encrypted_file.display());
 
-    // Read encrypted container
-    let container = EncryptedContainer::read_from_file(encrypted_file)
-        .map_err(|e| JcError::Other(format!("Failed to read encrypted file: {}", e)))?;
+    // Generate output filename by removing .jcze extension
+    let name = encrypted_file.file_stem().unwrap_or_default();
+    let output_path = dir.join(name);
+
+    decrypt_container(encrypted_file, &output_path, decryption_method, || {
+        password(secrets)
+    })?;
+
+    info!("Decrypted file created: {}", output_path.display());
+
+    Ok(output_path)
+}
+
+/// Decrypt the container `encrypted_file` into `output_path`, calling
+/// `password` only for password encryption
+///
+/// The data is read into the mapped output file and decrypted in place, so
+/// it never has to fit in memory. It is written under a temporary name and
+/// renamed into place once it authenticates.
+fn decrypt_container(
+    encrypted_file: &Path,
+    output_path: &Path,
+    decryption_method: Option<&DecryptionMethod>,
+    password: impl FnOnce() -> JcResult<Zeroizing<String>>,
+) -> JcResult<()> {
+    let file = File::open(encrypted_file)?;
+    let len = file.metadata()?.len();
+    let mut reader = buffered_reader(file);
+    let (container, header_len) = EncryptedContainer::read_header(&mut reader)?;
+    let key = container_key(&container, decryption_method, password)?;
 
-    // Decrypt based on container type and provided method
-    let decrypted_data = match (&container.metadata, decryption_method) {
+    let mut staged = output_path.as_os_str().to_owned();
+    staged.push(".jcz-tmp");
+    let staged = PathBuf::from(staged);
+    let opened = open_into(
+        &mut reader,
+        len - header_len as u64,
+        &staged,
+        &container,
+        &key,
+    );
+    if let Err(e) = opened.and_then(|_| Ok(fs::rename(&staged, output_path)?)) {
+        let _ = fs::remove_file(&staged);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Read the `len` bytes of encrypted data of `container` from `reader`
+/// and decrypt them with `key` into `output_path`
+fn open_into(
+    reader: &mut impl Read,
+    len: u64,
+    output_path: &Path,
+    container: &EncryptedContainer,
+    key: &[u8; 32],
+) -> JcResult<()> {
+    let mut output = MappedFile::create(output_path, len)?;
+    reader.read_exact(&mut output)?;
+    let nonce = container.metadata.nonce();
+    let aad = container.associated_data();
+    let opened = match container.chunk_len {
+        Some(chunk) => aead::open_chunks_in_place(&mut output, chunk as usize, key, nonce, &aad)?,
+        None => aead::open_in_place(&mut output, key, nonce, &aad)?,
+    };
+
+    // The frame and the block size are authenticated with the data, so
+    // safe to strip now
+    let data = match container.pad_to {
+        Some(_) => padding::data_range(&output[..opened])?,
+        None => 0..opened,
+    };
+    output.copy_within(data.clone(), 0);
+    output.finish(data.len() as u64)?;
+    Ok(())
+}
+
+/// Plaintext of `container`, calling `password` only for password
+/// encryption
+pub(crate) fn open_container(
+    container: &EncryptedContainer,
+    decryption_method: Option<&DecryptionMethod>,
+    password: impl FnOnce() -> JcResult<Zeroizing<String>>,
+) -> JcResult<Vec<u8>> {
+    let key = container_key(container, decryption_method, password)?;
+    let nonce = container.metadata.nonce();
+    let aad = container.associated_data();
+    let data = match container.chunk_len {
+        Some(chunk) => {
+            aead::open_chunks(&container.encrypted_data, chunk as usize, &key, nonce, &aad)?
+        }
+        None => aead::open(&container.encrypted_data, &key, nonce, &aad)?,
+    };
+
+    // The frame and the block size are authenticated with the data, so
+    // safe to strip now
+    if container.pad_to.is_some() {
+        return Ok(padding::unpad(data)?);
+    }
+    Ok(data)
+}
+
+/// AES key of the data of `container`, calling `password` only for
+/// password encryption
+fn container_key(
+    container: &EncryptedContainer,
+    decryption_method: Option<&DecryptionMethod>,
+    password: impl FnOnce() -> JcResult<Zeroizing<String>>,
+) -> JcResult<Zeroizing<[u8; 32]>> {
+    // Derive the key based on container type and provided method
+    let key = match (&container.metadata, decryption_method) {
         (
             EncryptionMetadata::Password {
                 salt,
-                nonce,
                 argon2_params,
+                ..
             },
             _,
         ) => {
-            // Password encryption - prompt for password
-            let password = prompt_password()?;
-
-            // Derive key
-            let key = PasswordEncryption::derive_key(&password, salt, argon2_params)
-                .map_err(|e| JcError::Other(format!("Key derivation failed: {}", e)))?;
+            // Password encryption - ask the provider
+            let password = password()?;
 
-            // Decrypt
-            PasswordEncryption::decrypt(&container.encrypted_data, &key, nonce)
-                .map_err(|e| JcError::Other(format!("Decryption failed: {}", e)))?
+            Zeroizing::new(PasswordEncryption::derive_key(
+                &password,
+                salt,
+                argon2_params,
+            )?)
         }
         (
-            EncryptionMetadata::Rsa {
-                encrypted_key,
-                nonce,
-            },
+            EncryptionMetadata::Rsa { encrypted_key, .. },
             Some(DecryptionMethod::Rsa { private_key_path }),
-        ) => {
-            // RSA encryption with provided private key
-            let symmetric_key =
-                RsaEncryption::decrypt_symmetric_key(encrypted_key, private_key_path).map_err(
-                    |e| JcError::Other(format!("Failed to decrypt symmetric key: {}", e)),
-                )?;
-
-            RsaEncryption::decrypt_data(&container.encrypted_data, &symmetric_key, nonce)
-                .map_err(|e| JcError::Other(format!("Decryption failed: {}", e)))?
-        }
+        ) => Zeroizing::new(RsaEncryption::decrypt_symmetric_key(
+            encrypted_key,
+            private_key_path,
+        )?),
         (EncryptionMetadata::Rsa { .. }, _) => {
             return Err(JcError::Other(
                 "RSA encrypted file requires --decrypt-key option".to_string(),
             ));
         }
-    };
-
-    // Generate output filename by removing .jcze extension
-    let output_path = encrypted_file.with_extension("");
-
-    // Write decrypted data
-    fs::write(&output_path, &decrypted_data)?;
-
-    info!("Decrypted file created: {}", output_path.display());
-
-    // Remove encrypted file only if requested
-    if remove_encrypted {
-        fs::remove_file(encrypted_file)?;
-        info!("Removed encrypted file: {}", encrypted_file.display());
-    }
+        (
+            EncryptionMetadata::Threshold { key_id, .. },
+            Some(DecryptionMethod::Shares { share_paths }),
+        ) => {
+            let shares = share_paths
+                .iter()
+                .map(|path| KeyShare::read_from_file(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            if shares.iter().any(|share| share.key_id != *key_id) {
+                return Err(JcError::Other(
+                    "Key share belongs to a different encrypted file".to_string(),
+                ));
+            }
+            shamir::combine_shares(&shares)?
+        }
+        (EncryptionMetadata::Threshold { threshold, .. }, _) => {
+            return Err(JcError::Other(format!(
+                "Split-key encrypted file requires {} --share files",
+                threshold
+            )));
+        }
+        (
+            EncryptionMetadata::Keyfile { key_id, .. },
+            Some(DecryptionMethod::Keyfile { keyfile_path }),
+        ) => {
+            let key = read_keyfile(keyfile_path)?;
+            if keyfile_id(&key) != *key_id {
+                return Err(JcError::Other(format!(
+                    "Keyfile {} does not match this file",
+                    keyfile_path.display()
+                )));
+            }
+            key
+        }
+        (EncryptionMetadata::Keyfile { .. }, _) => {
+            return Err(JcError::Other(
+                "Keyfile-encrypted file requires --decrypt-keyfile option".to_string(),
+            ));
+        }
+        (
+            EncryptionMetadata::X25519 {
+                ephemeral_public, ..
+            },
+            Some(DecryptionMethod::X25519 { private_key_path }),
+        ) => {
+            let private_key = X25519Encryption::read_private_key_pem(private_key_path)?;
 
-    Ok(output_path)
+            X25519Encryption::decryption_key(ephemeral_public, &private_key)?
+        }
+        (EncryptionMetadata::X25519 { .. }, _) => {
+            return Err(JcError::Other(
+                "X25519 encrypted file requires --decrypt-key with an X25519 private key"
+                    .to_string(),
+            ));
+        }
+    };
+    Ok(key)
 }
 
 /// Decrypt multiple encrypted files in parallel
@@ -117,6 +278,7 @@ pub fn decrypt_file(
 pub fn decrypt_files(
     encrypted_files: Vec<PathBuf>,
     decryption_method: Option<&DecryptionMethod>,
+    secrets: Option<&Secrets>,
 ) -> Vec<JcResult<PathBuf>> {
     info!("Decrypting {} files", encrypted_files.len());
 
@@ -130,8 +292,8 @@ pub fn decrypt_files(
     });
 
     if has_password_encrypted {
-        // Prompt for password once
-        let password = match prompt_password() {
+        // Ask for the password once
+        let password = match password(secrets) {
             Ok(p) => p,
             Err(e) => {
                 let err_msg = format!("{}", e);
@@ -157,7 +319,7 @@ pub fn decrypt_files(
         encrypted_files
             .par_iter()
             .map(|file| {
-                decrypt_file(file, decryption_method, false).map_err(|e| {
+                decrypt_file(file, decryption_method, secrets, false).map_err(|e| {
                     error!("Failed to decrypt {}: {}", file.display(), e);
                     e
                 })
@@ -178,53 +340,63 @@ fn decrypt_file_with_password(
         return Ok(encrypted_file.to_path_buf());
     }
 
-    let container = EncryptedContainer::read_from_file(encrypted_file)
-        .map_err(|e| JcError::Other(format!("Failed to read encrypted file: {}", e)))?;
-
-    let decrypted_data = match (&container.metadata, decryption_method) {
-        (
-            EncryptionMetadata::Password {
-                salt,
-                nonce,
-                argon2_params,
-            },
-            _,
-        ) => {
-            let key = PasswordEncryption::derive_key(password, salt, argon2_params)
-                .map_err(|e| JcError::Other(format!("Key derivation failed: {}", e)))?;
-
-            PasswordEncryption::decrypt(&container.encrypted_data, &key, nonce)
-                .map_err(|e| JcError::Other(format!("Decryption failed: {}", e)))?
-        }
-        (
-            EncryptionMetadata::Rsa {
-                encrypted_key,
-                nonce,
-            },
-            Some(DecryptionMethod::Rsa { private_key_path }),
-        ) => {
-            let symmetric_key =
-                RsaEncryption::decrypt_symmetric_key(encrypted_key, private_key_path).map_err(
-                    |e| JcError::Other(format!("Failed to decrypt symmetric key: {}", e)),
-                )?;
-
-            RsaEncryption::decrypt_data(&container.encrypted_data, &symmetric_key, nonce)
-                .map_err(|e| JcError::Other(format!("Decryption failed: {}", e)))?
-        }
-        (EncryptionMetadata::Rsa { .. }, _) => {
-            return Err(JcError::Other(
-                "RSA encrypted file requires --decrypt-key option".to_string(),
-            ));
-        }
-    };
-
     let output_path = encrypted_file.with_extension("");
-    fs::write(&output_path, &decrypted_data)?;
+    decrypt_container(encrypted_file, &output_path, decryption_method, || {
+        Ok(Zeroizing::new(password.to_string()))
+    })?;
 
-    // Remove encrypted file only if requested
+    // Remove encrypted file only if requested, once the output is durable
     if remove_encrypted {
+        sync_output(&output_path, Durability::Default)?;
         fs::remove_file(encrypted_file)?;
     }
 
     Ok(output_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::EncryptionMethod;
+    use crate::crypto::keys::generate_keyfile;
+    use crate::crypto::CryptoError;
+    use crate::operations::encrypt::seal_data;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tampered_header_fails_authentication() {
+        let temp_dir = TempDir::new().unwrap();
+        let keyfile_path = temp_dir.path().join("key");
+        generate_keyfile(&keyfile_path).unwrap();
+        let encryption = EncryptionMethod::Keyfile {
+            keyfile_path: keyfile_path.clone(),
+        };
+        let decryption = DecryptionMethod::Keyfile { keyfile_path };
+
+        let (container, _) =
+            seal_data(b"padded data", &encryption, None, Some(64), Some([7; 32])).unwrap();
+        let open = |container: &EncryptedContainer| {
+            let parsed = EncryptedContainer::from_bytes(&container.to_bytes().unwrap()).unwrap();
+            open_container(&parsed, Some(&decryption), || unreachable!())
+        };
+        assert_eq!(open(&container).unwrap(), b"padded data");
+
+        // Stripping the padding marker would otherwise hand out the frame
+        let changes: [fn(&mut EncryptedContainer); 6] = [
+            |c| c.pad_to = None,
+            |c| c.pad_to = Some(128),
+            |c| c.integrity = None,
+            |c| c.integrity = Some([8; 32]),
+            |c| c.chunk_len = Some(1 << 10),
+            |c| c.version = 2,
+        ];
+        for change in changes {
+            let mut tampered = container.clone();
+            change(&mut tampered);
+            assert!(matches!(
+                open(&tampered),
+                Err(JcError::Crypto(CryptoError::AuthenticationFailed))
+            ));
+        }
+    }
+}
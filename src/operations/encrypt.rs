@@ -1,64 +1,257 @@
 //! Encryption operations for compressed files
 
-use crate::core::config::EncryptionMethod;
+use crate::core::config::{Durability, EncryptionMethod, SecretPurpose, Secrets};
 use crate::core::error::{JcError, JcResult};
+use crate::crypto::keys::{keyfile_id, read_keyfile};
 use crate::crypto::{
-    Argon2Params, EncryptedContainer, EncryptionMetadata, EncryptionType, PasswordEncryption,
-    RsaEncryption,
+    aead, padding, shamir, Argon2Params, EncryptedContainer, EncryptionMetadata, EncryptionType,
+    KeyShare, PasswordEncryption, RsaEncryption, X25519Encryption,
 };
-use crate::utils::{error, info};
+use crate::operations::gpg;
+use crate::utils::bufio::{buffered_reader, MappedFile};
+use crate::utils::{error, info, sync_output};
 use rayon::prelude::*;
-use std::fs;
+use std::fs::{self, File};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
-/// Prompt user for password securely (without echo)
-fn prompt_password() -> JcResult<String> {
-    use std::io::{self, Write};
+/// Encryption password from `secrets`
+fn password(secrets: Option<&Secrets>) -> JcResult<Zeroizing<String>> {
+    secrets
+        .ok_or_else(|| JcError::Other("Password encryption needs a password provider".to_string()))?
+        .password(SecretPurpose::Encrypt)
+}
+
+/// Encrypt a single compressed file, asking `secrets` for the password of
+/// password encryption, with the payload padded to a multiple of `pad_to`
+/// bytes if given and the `integrity` tag of its contents in the header
+///
+/// With `fsync`, the encrypted output is on stable storage before the
+/// compressed file is removed.
+pub fn encrypt_file(
+    compressed_file: &Path,
+    encryption_method: &EncryptionMethod,
+    secrets: Option<&Secrets>,
+    pad_to: Option<u64>,
+    integrity: Option<[u8; 32]>,
+    fsync: bool,
+) -> JcResult<PathBuf> {
+    info!("Encrypting file: {}", compressed_file.display());
 
-    print!("Enter encryption password: ");
-    io::stdout().flush()?;
+    let password = match encryption_method {
+        EncryptionMethod::Password => Some(password(secrets)?),
+        EncryptionMethod::Rsa { .. }
+        | EncryptionMethod::Threshold { .. }
+        | EncryptionMethod::Keyfile { .. }
+        | EncryptionMethod::X25519 { .. }
+        | EncryptionMethod::Gpg { .. } => None,
+    };
+    let output_path = seal(
+        compressed_file,
+        encryption_method,
+        password.as_ref().map(|p| p.as_str()),
+        pad_to,
+        integrity,
+        fsync,
+    )?;
 
-    // Read password without echo
-    let password = rpassword::read_password()
-        .map_err(|e| JcError::Other(format!("Failed to read password: {}", e)))?;
+    info!("Encrypted file created: {}", output_path.display());
 
-    if password.is_empty() {
-        return Err(JcError::Other("Password cannot be empty".to_string()));
-    }
+    // Remove original compressed file
+    fs::remove_file(compressed_file)?;
 
-    Ok(password)
+    Ok(output_path)
 }
 
-/// Encrypt a single compressed file
-pub fn encrypt_file(
-    compressed_file: &Path,
+/// Write `<file>.jcze`, encrypted with `password` for password encryption,
+/// and its share files for threshold encryption; `file` itself is left alone
+///
+/// OpenPGP encryption writes `<file>.gpg` through `gpg` instead, which has
+/// no room for the `integrity` tag. With `fsync`, the outputs and their
+/// directory are fsynced.
+fn seal(
+    file: &Path,
     encryption_method: &EncryptionMethod,
+    password: Option<&str>,
+    pad_to: Option<u64>,
+    integrity: Option<[u8; 32]>,
+    fsync: bool,
 ) -> JcResult<PathBuf> {
-    info!("Encrypting file: {}", compressed_file.display());
+    if let EncryptionMethod::Gpg { recipients } = encryption_method {
+        if pad_to.is_some() {
+            return Err(JcError::Other(
+                "Padding is not available for OpenPGP output".to_string(),
+            ));
+        }
+        let output_path = gpg::encrypt(file, recipients)?;
+        if fsync {
+            sync_output(&output_path, Durability::Default)?;
+        }
+        return Ok(output_path);
+    }
+
+    // Generate output filename with .jcze extension
+    let mut output_path = file.as_os_str().to_owned();
+    output_path.push(".jcze");
+    let output_path = PathBuf::from(output_path);
+
+    let shares = seal_file(
+        file,
+        &output_path,
+        encryption_method,
+        password,
+        pad_to,
+        integrity,
+    )
+    .inspect_err(|_| {
+        let _ = fs::remove_file(&output_path);
+    })?;
+    write_shares(&output_path, &shares)?;
+    if fsync {
+        for share in &shares {
+            sync_output(&share_path(&output_path, share.index), Durability::Default)?;
+        }
+        sync_output(&output_path, Durability::Default)?;
+    }
+
+    Ok(output_path)
+}
+
+/// Encrypt `file` into the container `output_path` and return the key
+/// shares, if any
+///
+/// The container is built in the mapped output file, where the data is read
+/// into its chunks and encrypted in place on all cores, so it never has to
+/// fit in memory.
+fn seal_file(
+    file: &Path,
+    output_path: &Path,
+    encryption_method: &EncryptionMethod,
+    password: Option<&str>,
+    pad_to: Option<u64>,
+    integrity: Option<[u8; 32]>,
+) -> JcResult<Vec<KeyShare>> {
+    let input = File::open(file)?;
+    let len = input.metadata()?.len();
+    let sealed = seal_key(encryption_method, password, pad_to, integrity)?;
+    let header = sealed.container.header_bytes()?;
+    let aad = sealed.container.associated_data();
+
+    let payload = match pad_to {
+        Some(block) => padding::padded_len(len, block),
+        None => len,
+    };
+    let chunk = aead::CHUNK_LEN as usize;
+    let total = header.len() as u64 + aead::chunked_len(payload, chunk as u64);
+    let mut output = MappedFile::create(output_path, total)?;
+
+    let (head, body) = output.split_at_mut(header.len());
+    head.copy_from_slice(&header);
+    let frame = match pad_to {
+        Some(_) => padding::frame_header(len).to_vec(),
+        None => Vec::new(),
+    };
+    let mut remaining = frame.len() as u64 + len;
+    let mut plaintext = frame.as_slice().chain(buffered_reader(input));
+    // The rest of a padded payload is zero already
+    for slot in aead::chunk_slots(body, chunk) {
+        let filled = (slot.len() as u64).min(remaining);
+        plaintext.read_exact(&mut slot[..filled as usize])?;
+        remaining -= filled;
+    }
+    aead::seal_chunks_in_place(body, chunk, &sealed.key, &sealed.nonce, &aad)?;
+
+    output.finish(total)?;
+    Ok(sealed.shares)
+}
+
+/// Share file `index` of `encrypted_file`: `<encrypted_file>.share<index>`
+pub fn share_path(encrypted_file: &Path, index: u8) -> PathBuf {
+    let mut path = encrypted_file.as_os_str().to_owned();
+    path.push(format!(".share{}", index));
+    PathBuf::from(path)
+}
+
+/// Write the key shares of `encrypted_file` next to it
+pub(crate) fn write_shares(encrypted_file: &Path, shares: &[KeyShare]) -> JcResult<()> {
+    for share in shares {
+        let path = share_path(encrypted_file, share.index);
+        share.write_to_file(&path)?;
+        info!("Key share written: {}", path.display());
+    }
+    Ok(())
+}
+
+/// Container holding `compressed_data` encrypted with `encryption_method`,
+/// with a fresh salt or symmetric key and nonce, plus the key shares when
+/// the key is split
+///
+/// With `pad_to`, the data is padded inside the ciphertext to a multiple
+/// of that many bytes. `integrity` goes into the header, which is sealed
+/// along with the data.
+pub(crate) fn seal_data(
+    compressed_data: &[u8],
+    encryption_method: &EncryptionMethod,
+    password: Option<&str>,
+    pad_to: Option<u64>,
+    integrity: Option<[u8; 32]>,
+) -> JcResult<(EncryptedContainer, Vec<KeyShare>)> {
+    let mut sealed = seal_key(encryption_method, password, pad_to, integrity)?;
+    let aad = sealed.container.associated_data();
+
+    let padded;
+    let data = match pad_to {
+        Some(block) => {
+            padded = padding::pad(compressed_data, block);
+            &padded
+        }
+        None => compressed_data,
+    };
+    sealed.container.encrypted_data = aead::seal_chunks(
+        data,
+        aead::CHUNK_LEN as usize,
+        &sealed.key,
+        &sealed.nonce,
+        &aad,
+    )?;
+    Ok((sealed.container, sealed.shares))
+}
+
+/// What a new container is encrypted with
+struct SealKey {
+    /// Header of the container, without data
+    container: EncryptedContainer,
+    key: Zeroizing<[u8; 32]>,
+    nonce: [u8; 12],
+    /// Key shares of split-key encryption
+    shares: Vec<KeyShare>,
+}
 
-    // Read the compressed data
-    let compressed_data = fs::read(compressed_file)?;
+/// A fresh salt or symmetric key and nonce for `encryption_method`, the
+/// container header recording them, the padding block size and the
+/// integrity tag and, when the key is split, its shares
+fn seal_key(
+    encryption_method: &EncryptionMethod,
+    password: Option<&str>,
+    pad_to: Option<u64>,
+    integrity: Option<[u8; 32]>,
+) -> JcResult<SealKey> {
+    let mut shares = Vec::new();
 
-    // Encrypt based on method
-    let (encryption_type, metadata, encrypted_data) = match encryption_method {
+    let (encryption_type, metadata, key, nonce) = match encryption_method {
         EncryptionMethod::Password => {
-            // Prompt for password
-            let password = prompt_password()?;
+            let password = password.ok_or_else(|| {
+                JcError::Other("Password encryption needs a password".to_string())
+            })?;
 
             // Generate salt and nonce
-            let salt = PasswordEncryption::generate_salt()
-                .map_err(|e| JcError::Other(format!("Failed to generate salt: {}", e)))?;
-            let nonce = PasswordEncryption::generate_nonce()
-                .map_err(|e| JcError::Other(format!("Failed to generate nonce: {}", e)))?;
+            let salt = PasswordEncryption::generate_salt()?;
+            let nonce = PasswordEncryption::generate_nonce()?;
 
             // Derive key from password
             let params = Argon2Params::default();
-            let key = PasswordEncryption::derive_key(&password, &salt, &params)
-                .map_err(|e| JcError::Other(format!("Key derivation failed: {}", e)))?;
-
-            // Encrypt data
-            let encrypted = PasswordEncryption::encrypt(&compressed_data, &key, &nonce)
-                .map_err(|e| JcError::Other(format!("Encryption failed: {}", e)))?;
+            let key = Zeroizing::new(PasswordEncryption::derive_key(password, &salt, &params)?);
 
             let metadata = EncryptionMetadata::Password {
                 salt,
@@ -66,63 +259,95 @@ pub fn encrypt_file(
                 argon2_params: params,
             };
 
-            (EncryptionType::Password, metadata, encrypted)
+            (EncryptionType::Password, metadata, key, nonce)
         }
         EncryptionMethod::Rsa { public_key_path } => {
             // Generate symmetric key and nonce
-            let symmetric_key = RsaEncryption::generate_symmetric_key()
-                .map_err(|e| JcError::Other(format!("Failed to generate symmetric key: {}", e)))?;
-            let nonce = RsaEncryption::generate_nonce()
-                .map_err(|e| JcError::Other(format!("Failed to generate nonce: {}", e)))?;
-
-            // Encrypt data with symmetric key
-            let encrypted_data =
-                RsaEncryption::encrypt_data(&compressed_data, &symmetric_key, &nonce)
-                    .map_err(|e| JcError::Other(format!("Data encryption failed: {}", e)))?;
+            let symmetric_key = Zeroizing::new(RsaEncryption::generate_symmetric_key()?);
+            let nonce = RsaEncryption::generate_nonce()?;
 
             // Encrypt symmetric key with RSA public key
             let encrypted_key =
-                RsaEncryption::encrypt_symmetric_key(&symmetric_key, public_key_path)
-                    .map_err(|e| JcError::Other(format!("RSA encryption failed: {}", e)))?;
+                RsaEncryption::encrypt_symmetric_key(&symmetric_key, public_key_path)?;
 
             let metadata = EncryptionMetadata::Rsa {
                 encrypted_key,
                 nonce,
             };
 
-            (EncryptionType::Rsa, metadata, encrypted_data)
+            (EncryptionType::Rsa, metadata, symmetric_key, nonce)
         }
-    };
+        EncryptionMethod::Threshold {
+            threshold,
+            shares: count,
+        } => {
+            // Generate symmetric key and nonce, as for RSA
+            let symmetric_key = Zeroizing::new(RsaEncryption::generate_symmetric_key()?);
+            let nonce = RsaEncryption::generate_nonce()?;
+
+            // Only the shares can bring the key back
+            shares = shamir::split_key(&symmetric_key, *threshold, *count)?;
+
+            let metadata = EncryptionMetadata::Threshold {
+                key_id: shares[0].key_id,
+                threshold: *threshold,
+                shares: *count,
+                nonce,
+            };
 
-    // Create encrypted container
-    let container = EncryptedContainer::new(encryption_type, metadata, encrypted_data);
+            (EncryptionType::Threshold, metadata, symmetric_key, nonce)
+        }
+        EncryptionMethod::Keyfile { keyfile_path } => {
+            let key = read_keyfile(keyfile_path)?;
+            // A fresh nonce per file, since every file shares the key
+            let nonce = RsaEncryption::generate_nonce()?;
 
-    // Generate output filename with .jcze extension
-    let output_path = compressed_file.with_extension(format!(
-        "{}.jcze",
-        compressed_file
-            .extension()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-    ));
-
-    // Write encrypted container
-    container
-        .write_to_file(&output_path)
-        .map_err(|e| JcError::Other(format!("Failed to write encrypted file: {}", e)))?;
+            let metadata = EncryptionMetadata::Keyfile {
+                key_id: keyfile_id(&key),
+                nonce,
+            };
 
-    info!("Encrypted file created: {}", output_path.display());
+            (EncryptionType::Keyfile, metadata, key, nonce)
+        }
+        EncryptionMethod::X25519 { public_key_path } => {
+            let recipient = X25519Encryption::read_public_key_pem(public_key_path)?;
+            let (ephemeral_public, key) = X25519Encryption::encryption_key(&recipient)?;
+            let nonce = RsaEncryption::generate_nonce()?;
 
-    // Remove original compressed file
-    fs::remove_file(compressed_file)?;
+            let metadata = EncryptionMetadata::X25519 {
+                ephemeral_public,
+                nonce,
+            };
 
-    Ok(output_path)
+            (EncryptionType::X25519, metadata, key, nonce)
+        }
+        EncryptionMethod::Gpg { .. } => {
+            return Err(JcError::Other(
+                "OpenPGP encryption writes .gpg files, not .jcze containers".to_string(),
+            ));
+        }
+    };
+
+    let mut container = EncryptedContainer::new(encryption_type, metadata, Vec::new());
+    container.pad_to = pad_to;
+    container.integrity = integrity;
+    container.set_chunk_len(aead::CHUNK_LEN);
+    Ok(SealKey {
+        container,
+        key,
+        nonce,
+        shares,
+    })
 }
 
-/// Encrypt multiple compressed files in parallel
+/// Encrypt multiple compressed files in parallel, each with the integrity
+/// tag of its contents, fsyncing the outputs first with `fsync`
 pub fn encrypt_files(
-    compressed_files: Vec<PathBuf>,
+    compressed_files: Vec<(PathBuf, Option<[u8; 32]>)>,
     encryption_method: &EncryptionMethod,
+    secrets: Option<&Secrets>,
+    pad_to: Option<u64>,
+    fsync: bool,
 ) -> Vec<JcResult<PathBuf>> {
     info!(
         "Encrypting {} files with {}",
@@ -130,15 +355,19 @@ pub fn encrypt_files(
         match encryption_method {
             EncryptionMethod::Password => "password",
             EncryptionMethod::Rsa { .. } => "RSA",
+            EncryptionMethod::Threshold { .. } => "split key",
+            EncryptionMethod::Keyfile { .. } => "keyfile",
+            EncryptionMethod::X25519 { .. } => "X25519",
+            EncryptionMethod::Gpg { .. } => "OpenPGP",
         }
     );
 
-    // For password encryption, we need to prompt once and reuse
-    // For RSA, each file can be encrypted independently
+    // For password encryption, we need to ask once and reuse
+    // For RSA and split keys, each file can be encrypted independently
     match encryption_method {
         EncryptionMethod::Password => {
-            // Prompt for password once
-            let password = match prompt_password() {
+            // Ask for the password once
+            let password = match password(secrets) {
                 Ok(p) => p,
                 Err(e) => {
                     let err_msg = format!("{}", e);
@@ -152,23 +381,30 @@ pub fn encrypt_files(
             // Encrypt all files with the same password
             compressed_files
                 .par_iter()
-                .map(|file| {
-                    encrypt_file_with_password(file, &password).map_err(|e| {
-                        error!("Failed to encrypt {}: {}", file.display(), e);
-                        e
-                    })
+                .map(|(file, integrity)| {
+                    encrypt_file_with_password(file, &password, pad_to, *integrity, fsync).map_err(
+                        |e| {
+                            error!("Failed to encrypt {}: {}", file.display(), e);
+                            e
+                        },
+                    )
                 })
                 .collect()
         }
-        EncryptionMethod::Rsa { .. } => {
+        EncryptionMethod::Rsa { .. }
+        | EncryptionMethod::Threshold { .. }
+        | EncryptionMethod::Keyfile { .. }
+        | EncryptionMethod::X25519 { .. }
+        | EncryptionMethod::Gpg { .. } => {
             // Each file can be encrypted independently
             compressed_files
                 .par_iter()
-                .map(|file| {
-                    encrypt_file(file, encryption_method).map_err(|e| {
-                        error!("Failed to encrypt {}: {}", file.display(), e);
-                        e
-                    })
+                .map(|(file, integrity)| {
+                    encrypt_file(file, encryption_method, secrets, pad_to, *integrity, fsync)
+                        .map_err(|e| {
+                            error!("Failed to encrypt {}: {}", file.display(), e);
+                            e
+                        })
                 })
                 .collect()
         }
@@ -176,49 +412,74 @@ pub fn encrypt_files(
 }
 
 /// Helper function to encrypt with a pre-obtained password
-fn encrypt_file_with_password(compressed_file: &Path, password: &str) -> JcResult<PathBuf> {
-    let compressed_data = fs::read(compressed_file)?;
-
-    // Generate salt and nonce
-    let salt = PasswordEncryption::generate_salt()
-        .map_err(|e| JcError::Other(format!("Failed to generate salt: {}", e)))?;
-    let nonce = PasswordEncryption::generate_nonce()
-        .map_err(|e| JcError::Other(format!("Failed to generate nonce: {}", e)))?;
-
-    // Derive key from password
-    let params = Argon2Params::default();
-    let key = PasswordEncryption::derive_key(password, &salt, &params)
-        .map_err(|e| JcError::Other(format!("Key derivation failed: {}", e)))?;
-
-    // Encrypt data
-    let encrypted = PasswordEncryption::encrypt(&compressed_data, &key, &nonce)
-        .map_err(|e| JcError::Other(format!("Encryption failed: {}", e)))?;
-
-    let metadata = EncryptionMetadata::Password {
-        salt,
-        nonce,
-        argon2_params: params,
-    };
-
-    // Create encrypted container
-    let container = EncryptedContainer::new(EncryptionType::Password, metadata, encrypted);
-
-    // Generate output filename
-    let output_path = compressed_file.with_extension(format!(
-        "{}.jcze",
-        compressed_file
-            .extension()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-    ));
-
-    // Write encrypted container
-    container
-        .write_to_file(&output_path)
-        .map_err(|e| JcError::Other(format!("Failed to write encrypted file: {}", e)))?;
+fn encrypt_file_with_password(
+    compressed_file: &Path,
+    password: &str,
+    pad_to: Option<u64>,
+    integrity: Option<[u8; 32]>,
+    fsync: bool,
+) -> JcResult<PathBuf> {
+    let output_path = seal(
+        compressed_file,
+        &EncryptionMethod::Password,
+        Some(password),
+        pad_to,
+        integrity,
+        fsync,
+    )?;
 
     // Remove original compressed file
     fs::remove_file(compressed_file)?;
 
     Ok(output_path)
 }
+
+/// Encrypt arbitrary files without compressing them (`jcz encrypt`)
+///
+/// Each file gets a `<file>.jcze` next to it, fsynced, and is kept. The
+/// password of password encryption is asked once for all files.
+pub fn encrypt_only(
+    files: Vec<PathBuf>,
+    encryption_method: &EncryptionMethod,
+    secrets: Option<&Secrets>,
+    pad_to: Option<u64>,
+) -> Vec<JcResult<PathBuf>> {
+    info!("Encrypting {} files without compression", files.len());
+
+    let password = match encryption_method {
+        EncryptionMethod::Password => match password(secrets) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                let err_msg = format!("{}", e);
+                return files
+                    .iter()
+                    .map(|_| Err(JcError::Other(err_msg.clone())))
+                    .collect();
+            }
+        },
+        EncryptionMethod::Rsa { .. }
+        | EncryptionMethod::Threshold { .. }
+        | EncryptionMethod::Keyfile { .. }
+        | EncryptionMethod::X25519 { .. }
+        | EncryptionMethod::Gpg { .. } => None,
+    };
+
+    files
+        .par_iter()
+        .map(|file| {
+            if !file.is_file() {
+                return Err(JcError::NotAFile(file.clone()));
+            }
+            let output = seal(
+                file,
+                encryption_method,
+                password.as_ref().map(|p| p.as_str()),
+                pad_to,
+                None,
+                true,
+            )?;
+            info!("Encrypted file created: {}", output.display());
+            Ok(output)
+        })
+        .collect()
+}
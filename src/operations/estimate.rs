@@ -0,0 +1,298 @@
+//! Compressed size estimates (`jcz estimate`)
+//!
+//! An input is streamed through the compressor into a sink that only counts
+//! bytes, so nothing is written. Directories are streamed as the tar jcz
+//! would create. Inputs larger than the sample size are sampled: evenly
+//! spaced blocks adding up to the sample are compressed and their ratio is
+//! applied to the whole input.
+
+use std::cmp;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::compressors::{create_compressor, tar::TarCompressor};
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::{CompoundFormat, CompressionFormat};
+use crate::operations::stats::{self, format_size, tree_size};
+use crate::utils::debug;
+
+/// Bytes compressed per input unless `--sample` or `--full` says otherwise
+pub const DEFAULT_SAMPLE: u64 = 64 << 20;
+
+/// Size of each sampled block
+const BLOCK: u64 = 1 << 20;
+
+/// Predicted output size of one input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub input: PathBuf,
+
+    /// `-c` command that would produce the output (tgz, tbz2, txz for
+    /// directories)
+    pub command: String,
+
+    /// Bytes the compressor would read: the file, or the tar stream
+    pub original_size: u64,
+
+    /// Bytes actually compressed
+    pub sampled_size: u64,
+
+    /// Output size of the sampled bytes
+    pub compressed_size: u64,
+}
+
+impl Estimate {
+    /// Whether only part of the input was compressed
+    pub fn is_sampled(&self) -> bool {
+        self.sampled_size < self.original_size
+    }
+
+    /// Output size for the whole input
+    pub fn predicted_size(&self) -> u64 {
+        if !self.is_sampled() || self.sampled_size == 0 {
+            return self.compressed_size;
+        }
+        (self.compressed_size as f64 * self.original_size as f64 / self.sampled_size as f64) as u64
+    }
+
+    /// Predicted size as a fraction of the input size
+    pub fn ratio(&self) -> f64 {
+        stats::ratio(self.predicted_size(), self.original_size)
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}{} ({:.1}%) with {}",
+            self.input.display(),
+            format_size(self.original_size),
+            if self.is_sampled() { "~" } else { "" },
+            format_size(self.predicted_size()),
+            self.ratio() * 100.0,
+            self.command
+        )?;
+        if self.is_sampled() {
+            write!(f, ", from a {} sample", format_size(self.sampled_size))?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimate the output of compressing `input` with `command`
+///
+/// Only the stream formats (gzip, bzip2, xz, their tar compounds and tar
+/// itself) can be measured this way. With `sample`, inputs larger than it
+/// are sampled; `None` compresses everything.
+pub fn estimate(
+    input: &Path,
+    command: &str,
+    sample: Option<u64>,
+    config: &CompressionConfig,
+) -> JcResult<Estimate> {
+    let metadata = fs::metadata(input).map_err(|_| JcError::FileNotFound(input.to_path_buf()))?;
+    let (format, tar) = match CompoundFormat::from_str(command) {
+        Some(compound) => (Some(compound.secondary()), true),
+        None => match CompressionFormat::from_name(command) {
+            Some(CompressionFormat::Tar) => (None, true),
+            Some(
+                format @ (CompressionFormat::Gzip
+                | CompressionFormat::Bzip2
+                | CompressionFormat::Xz),
+            ) => (Some(format), metadata.is_dir()),
+            _ => {
+                return Err(JcError::Usage(format!(
+                    "Cannot estimate {}: only gzip, bzip2, xz, tar, tgz, tbz2 and txz can be",
+                    command
+                )))
+            }
+        },
+    };
+
+    let (block, stride) = match sample {
+        Some(sample) => sampling(tree_size(input)?, sample),
+        None => (u64::MAX, u64::MAX),
+    };
+    debug!(
+        "Estimating {} with {} ({} byte blocks every {} bytes)",
+        input.display(),
+        command,
+        block,
+        stride
+    );
+
+    let measure = |reader: &mut (dyn Read + Send)| -> JcResult<(u64, u64, u64)> {
+        let mut sampler = Sampler::new(reader, block, stride);
+        let mut counter = Counter::default();
+        match format {
+            Some(format) => {
+                create_compressor(format).compress_stream(&mut sampler, &mut counter, config)?
+            }
+            None => {
+                io::copy(&mut sampler, &mut counter)?;
+            }
+        }
+        Ok((sampler.pos, sampler.taken, counter.0))
+    };
+
+    let (original_size, sampled_size, compressed_size) = if tar {
+        let name = input
+            .file_name()
+            .ok_or_else(|| JcError::Other(format!("Invalid input: {}", input.display())))?;
+        let entries = [(input.to_path_buf(), name.to_os_string())];
+        let (mut reader, writer) = io::pipe()?;
+        thread::scope(|scope| {
+            let archiver = scope.spawn(move || {
+                // Dropping the writer at the end is the reader's EOF
+                TarCompressor::new().stream_archive(BufWriter::new(writer), &entries, config)
+            });
+            let result = measure(&mut reader);
+            // Stop the archiver if the compressor failed
+            drop(reader);
+            let archived = archiver
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("Archiver panicked")));
+            match (result, archived) {
+                (Ok(sizes), Ok(())) => Ok(sizes),
+                (Err(e), _) => Err(e),
+                (Ok(_), Err(e)) => Err(e.into()),
+            }
+        })?
+    } else {
+        measure(&mut File::open(input)?)?
+    };
+
+    let command = match (tar, format) {
+        (true, Some(CompressionFormat::Gzip)) => "tgz",
+        (true, Some(CompressionFormat::Bzip2)) => "tbz2",
+        (true, Some(CompressionFormat::Xz)) => "txz",
+        (true, _) => "tar",
+        (false, format) => format.map_or(command, |format| format.name()),
+    };
+    Ok(Estimate {
+        input: input.to_path_buf(),
+        command: command.to_string(),
+        original_size,
+        sampled_size,
+        compressed_size,
+    })
+}
+
+/// Block size and distance between block starts that take about `sample`
+/// bytes from `total`, or everything when `total` fits in the sample
+fn sampling(total: u64, sample: u64) -> (u64, u64) {
+    if total <= sample {
+        return (u64::MAX, u64::MAX);
+    }
+    let block = BLOCK.min(sample).max(1);
+    let blocks = sample.div_ceil(block);
+    (block, cmp::max(total / blocks, block))
+}
+
+/// Passes on the first `block` bytes of every `stride` and skips the rest
+struct Sampler<R> {
+    inner: R,
+    block: u64,
+    stride: u64,
+    /// Bytes read from `inner`
+    pos: u64,
+    /// Bytes passed on
+    taken: u64,
+}
+
+impl<R: Read> Sampler<R> {
+    fn new(inner: R, block: u64, stride: u64) -> Self {
+        Sampler {
+            inner,
+            block,
+            stride,
+            pos: 0,
+            taken: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Sampler<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let offset = self.pos % self.stride;
+            if offset < self.block {
+                let max = buf
+                    .len()
+                    .min((self.block - offset).try_into().unwrap_or(usize::MAX));
+                let read = self.inner.read(&mut buf[..max])?;
+                self.pos += read as u64;
+                self.taken += read as u64;
+                return Ok(read);
+            }
+            let gap = self.stride - offset;
+            let skipped = io::copy(&mut (&mut self.inner).take(gap), &mut io::sink())?;
+            self.pos += skipped;
+            if skipped < gap {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Counts what is written to it and throws it away
+#[derive(Debug, Default)]
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sampler() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut sampler = Sampler::new(&data[..], 10, 40);
+        let mut taken = Vec::new();
+        sampler.read_to_end(&mut taken).unwrap();
+        let expected: Vec<u8> = (0..10).chain(40..50).chain(80..90).collect();
+        assert_eq!(taken, expected);
+        assert_eq!((sampler.pos, sampler.taken), (100, 30));
+
+        assert_eq!(sampling(100, 200), (u64::MAX, u64::MAX));
+        assert_eq!(sampling(100 << 20, 10 << 20), (BLOCK, 10 << 20));
+    }
+
+    #[test]
+    fn test_estimate() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("data.txt");
+        fs::write(&input, "compressible line\n".repeat(20_000)).unwrap();
+        let config = CompressionConfig::new();
+
+        let full = estimate(&input, "gzip", None, &config).unwrap();
+        assert_eq!(full.command, "gzip");
+        assert_eq!(full.original_size, 360_000);
+        assert!(!full.is_sampled());
+        assert!(full.ratio() < 0.1);
+
+        let sampled = estimate(&input, "gzip", Some(100_000), &config).unwrap();
+        assert!(sampled.is_sampled());
+        assert_eq!(sampled.original_size, 360_000);
+        assert!(sampled.predicted_size() > 0);
+
+        assert!(estimate(&input, "zip", None, &config).is_err());
+        assert!(fs::read_dir(temp_dir.path()).unwrap().count() == 1);
+    }
+}
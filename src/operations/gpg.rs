@@ -0,0 +1,91 @@
+//! OpenPGP encryption through GnuPG (`--encrypt-gpg`)
+//!
+//! jcz shells out to `gpg` rather than writing its own container, so the
+//! `.gpg` files it produces open with a plain `gpg -d` for recipients who
+//! never install jcz. Key lookup, trust and passphrases are left to the
+//! user's GnuPG setup (keyring, trust model and gpg-agent).
+
+use crate::compressors::spawn_error;
+use crate::core::error::{JcError, JcResult};
+use crate::utils::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Check if a file is OpenPGP-encrypted by looking for a .gpg or .pgp
+/// extension
+pub fn is_gpg_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|s| s.eq_ignore_ascii_case("gpg") || s.eq_ignore_ascii_case("pgp"))
+}
+
+/// Write `<file>.gpg`, encrypted to every key in `recipients`; `file`
+/// itself is left alone
+pub(crate) fn encrypt(file: &Path, recipients: &[String]) -> JcResult<PathBuf> {
+    let mut output_path = file.as_os_str().to_owned();
+    output_path.push(".gpg");
+    let output_path = PathBuf::from(output_path);
+
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--yes", "--encrypt"]);
+    for recipient in recipients {
+        cmd.arg("--recipient").arg(recipient);
+    }
+    cmd.arg("--output").arg(&output_path).arg(file);
+
+    run(cmd, "encryption").inspect_err(|_| {
+        let _ = fs::remove_file(&output_path);
+    })?;
+    Ok(output_path)
+}
+
+/// Decrypt `encrypted_file` into `dir`, under its name without `.gpg`
+///
+/// Any passphrase is asked for by gpg-agent, not by jcz.
+pub fn decrypt_into(encrypted_file: &Path, dir: &Path) -> JcResult<PathBuf> {
+    info!("Decrypting OpenPGP file: {}", encrypted_file.display());
+
+    let name = encrypted_file.file_stem().unwrap_or_default();
+    let output_path = dir.join(name);
+
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--yes", "--decrypt", "--output"])
+        .arg(&output_path)
+        .arg(encrypted_file);
+
+    run(cmd, "decryption").inspect_err(|_| {
+        let _ = fs::remove_file(&output_path);
+    })?;
+
+    info!("Decrypted file created: {}", output_path.display());
+    Ok(output_path)
+}
+
+fn run(mut cmd: Command, what: &str) -> JcResult<()> {
+    debug!("Executing: {:?}", cmd);
+
+    let output = cmd.output().map_err(|e| spawn_error("gpg", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(JcError::Other(format!(
+            "gpg {} failed: {}",
+            what,
+            stderr.trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_gpg_file() {
+        assert!(is_gpg_file(Path::new("backup.tar.gz.gpg")));
+        assert!(is_gpg_file(Path::new("notes.txt.xz.PGP")));
+        assert!(!is_gpg_file(Path::new("backup.tar.gz.jcze")));
+        assert!(!is_gpg_file(Path::new("gpg")));
+    }
+}
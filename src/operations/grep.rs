@@ -0,0 +1,230 @@
+//! Searching inside compressed archives (`jcz grep`)
+//!
+//! The archive is decompressed as a stream (see `listing::read_decoded`)
+//! and tar members are read from it one after another, so nothing is
+//! written to disk and memory use does not grow with the archive. A
+//! single compressed file (`.gz`, `.bz2`, `.xz`) is searched as one
+//! member named after what it decompresses to.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::compressors::detect_format;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::operations::listing::{read_decoded, tar_layer};
+use crate::operations::tier::glob_match;
+use crate::utils::debug;
+
+/// How much of a member is looked at for NUL bytes to tell binary data
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// One matching line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    /// Member path inside the archive
+    pub member: String,
+
+    /// 1-based line number within the member
+    pub line_number: u64,
+
+    /// The line without its newline, lossily decoded; empty for binary
+    /// members, which are reported once instead of line by line
+    pub line: String,
+
+    /// The member holds binary data
+    pub binary: bool,
+}
+
+/// What to look for
+#[derive(Debug, Clone)]
+pub struct GrepOptions {
+    /// Compiled pattern (see `build_pattern`)
+    pub pattern: Regex,
+
+    /// Only search members whose path matches this glob (`*` and `?`)
+    pub members: Option<String>,
+}
+
+/// Compile `pattern`, as a literal string when `fixed`
+pub fn build_pattern(pattern: &str, ignore_case: bool, fixed: bool) -> JcResult<Regex> {
+    let pattern = if fixed {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| JcError::Usage(format!("Invalid pattern: {}", e)))
+}
+
+/// Search every member of `archive` and call `on_match` for each match,
+/// returning the number of matches
+///
+/// Handles `.tar`, `.tar.gz`, `.tar.bz2` and `.tar.xz` (and their
+/// aliases), and single `.gz`, `.bz2` and `.xz` files. An error from
+/// `on_match` (say, a closed stdout) stops the search and is returned.
+pub fn grep_archive(
+    archive: &Path,
+    options: &GrepOptions,
+    config: &CompressionConfig,
+    mut on_match: impl FnMut(&GrepMatch) -> io::Result<()>,
+) -> JcResult<u64> {
+    let (layer, tar) = match (tar_layer(archive), detect_format(archive)) {
+        (Some(layer), _) => (layer, true),
+        (
+            None,
+            Some(
+                format @ (CompressionFormat::Gzip
+                | CompressionFormat::Bzip2
+                | CompressionFormat::Xz),
+            ),
+        ) => (Some(format), false),
+        _ => {
+            return Err(JcError::InvalidExtension(
+                archive.to_path_buf(),
+                "tar, tgz, tbz2, txz, gz, bz2 or xz".to_string(),
+            ))
+        }
+    };
+    // A lone compressed file is one member, named as -d would name it
+    let single_name = archive
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    read_decoded(archive, layer, config, |reader| {
+        if tar {
+            search_tar(reader, options, &mut on_match)
+        } else {
+            search_member(&single_name, reader, &options.pattern, &mut on_match)
+                .map_err(JcError::from)
+        }
+    })
+}
+
+/// Search the regular-file members of the tar read from `reader`
+fn search_tar(
+    reader: &mut dyn Read,
+    options: &GrepOptions,
+    on_match: &mut impl FnMut(&GrepMatch) -> io::Result<()>,
+) -> JcResult<u64> {
+    let to_error = |e: io::Error| JcError::DecompressionFailed {
+        tool: "tar".to_string(),
+        stderr: e.to_string(),
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    let mut count = 0;
+    for entry in archive.entries().map_err(to_error)? {
+        let mut entry = entry.map_err(to_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let member = entry
+            .path()
+            .map_err(to_error)?
+            .to_string_lossy()
+            .into_owned();
+        if let Some(ref glob) = options.members {
+            if !glob_match(glob, &member) {
+                continue;
+            }
+        }
+        debug!("Searching {}", member);
+        count += search_member(&member, &mut entry, &options.pattern, on_match)?;
+    }
+    Ok(count)
+}
+
+/// Search the lines of one member
+fn search_member(
+    member: &str,
+    reader: &mut dyn Read,
+    pattern: &Regex,
+    on_match: &mut impl FnMut(&GrepMatch) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(64 * 1024, reader);
+    let head = reader.fill_buf()?;
+    let binary = head[..head.len().min(BINARY_SNIFF_LEN)].contains(&0);
+
+    let mut line = Vec::new();
+    let mut line_number = 0;
+    let mut count = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if !pattern.is_match(&line) {
+            continue;
+        }
+
+        count += 1;
+        on_match(&GrepMatch {
+            member: member.to_string(),
+            line_number,
+            line: if binary {
+                String::new()
+            } else {
+                String::from_utf8_lossy(&line).into_owned()
+            },
+            binary,
+        })?;
+        if binary {
+            // Once is enough; the rest of the member is skipped
+            io::copy(&mut reader, &mut io::sink())?;
+            break;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(data: &[u8], pattern: &str) -> Vec<GrepMatch> {
+        let pattern = build_pattern(pattern, false, false).unwrap();
+        let mut found = Vec::new();
+        search_member("log", &mut &data[..], &pattern, &mut |m: &GrepMatch| {
+            found.push(m.clone());
+            Ok(())
+        })
+        .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_search_member_reports_line_numbers() {
+        let found = matches(b"ok\nERROR one\nok\nERROR two", "ERROR");
+        let lines: Vec<_> = found
+            .iter()
+            .map(|m| (m.line_number, m.line.as_str()))
+            .collect();
+        assert_eq!(lines, vec![(2, "ERROR one"), (4, "ERROR two")]);
+    }
+
+    #[test]
+    fn test_search_member_reports_binary_once() {
+        let found = matches(b"\0\x01match\nmatch\n", "match");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].binary);
+    }
+
+    #[test]
+    fn test_build_pattern() {
+        let fixed = build_pattern("a.b", true, true).unwrap();
+        assert!(fixed.is_match(b"A.B"));
+        assert!(!fixed.is_match(b"axb"));
+        assert!(build_pattern("(", false, false).is_err());
+    }
+}
@@ -0,0 +1,540 @@
+//! Header-only identification of jcz outputs
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use lzma_rust2::XzReader;
+
+use crate::compressors::{detect_format_from_header, gzip, zip};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::crypto::{CryptoError, EncryptedContainer, EncryptionMetadata};
+use crate::operations::stats::format_size;
+use crate::utils::debug;
+
+/// Number of bytes read from the start of each file
+const HEADER_READ_SIZE: usize = 8192;
+
+/// Maximum number of decompressed bytes inspected per inner layer
+const PEEK_OUTPUT_LIMIT: u64 = 8192;
+
+/// How a layer in the format chain was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerEvidence {
+    /// Confirmed by magic bytes in the file content
+    Header,
+    /// Inferred from the filename only (e.g., layers hidden by encryption)
+    Extension,
+}
+
+/// Kind of a single layer in the format chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerKind {
+    /// JCZE encrypted container
+    Encrypted,
+    /// Compression or archive format
+    Format(CompressionFormat),
+}
+
+/// One layer of the identified format chain, outermost first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layer {
+    pub kind: LayerKind,
+    pub evidence: LayerEvidence,
+}
+
+/// Result of identifying a single file
+#[derive(Debug, Clone)]
+pub struct Identification {
+    /// Identified file
+    pub path: PathBuf,
+
+    /// Format chain, outermost layer first
+    pub layers: Vec<Layer>,
+
+    /// Encrypted container version (if encrypted)
+    pub container_version: Option<u8>,
+
+    /// Human-readable encryption description (if encrypted)
+    pub encryption: Option<String>,
+
+    /// Facts gathered from the headers (stored names, checks, first entries)
+    pub notes: Vec<String>,
+
+    /// Options jcz was likely invoked with to create this file
+    pub options: Vec<String>,
+}
+
+impl Identification {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            layers: Vec::new(),
+            container_version: None,
+            encryption: None,
+            notes: Vec::new(),
+            options: Vec::new(),
+        }
+    }
+
+    /// Whether the file is a JCZE encrypted container
+    #[allow(dead_code)]
+    pub fn is_encrypted(&self) -> bool {
+        self.layers
+            .first()
+            .is_some_and(|l| l.kind == LayerKind::Encrypted)
+    }
+
+    /// Compression formats in the chain, outermost first
+    pub fn formats(&self) -> Vec<CompressionFormat> {
+        self.layers
+            .iter()
+            .filter_map(|l| match l.kind {
+                LayerKind::Format(f) => Some(f),
+                LayerKind::Encrypted => None,
+            })
+            .collect()
+    }
+
+    /// Format chain rendered as `jcze -> gzip -> tar`
+    pub fn format_chain(&self) -> String {
+        if self.layers.is_empty() {
+            return "unknown".to_string();
+        }
+
+        self.layers
+            .iter()
+            .map(|l| {
+                let name = match l.kind {
+                    LayerKind::Encrypted => "jcze",
+                    LayerKind::Format(f) => f.name(),
+                };
+                match l.evidence {
+                    LayerEvidence::Header => name.to_string(),
+                    LayerEvidence::Extension => format!("{}?", name),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    /// Compression command (`-c`) that produces this format chain
+    pub fn likely_command(&self) -> Option<&'static str> {
+        match self.formats().as_slice() {
+            [CompressionFormat::Gzip, CompressionFormat::Tar] => Some("tgz"),
+            [CompressionFormat::Bzip2, CompressionFormat::Tar] => Some("tbz2"),
+            [CompressionFormat::Xz, CompressionFormat::Tar] => Some("txz"),
+            [single] if single.can_compress() => Some(single.name()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.path.display())?;
+        writeln!(f, "  Format chain: {}", self.format_chain())?;
+        match &self.encryption {
+            Some(desc) => writeln!(f, "  Encrypted: yes ({})", desc)?,
+            None => writeln!(f, "  Encrypted: no")?,
+        }
+        if let Some(version) = self.container_version {
+            writeln!(f, "  Container version: {}", version)?;
+        }
+        for note in &self.notes {
+            writeln!(f, "  {}", note)?;
+        }
+        if let Some(command) = self.likely_command() {
+            let mut options = vec![format!("-c {}", command)];
+            options.extend(self.options.iter().cloned());
+            write!(f, "  Likely created with: jcz {}", options.join(" "))?;
+        } else {
+            write!(f, "  Likely created with: not a recognized jcz output")?;
+        }
+        Ok(())
+    }
+}
+
+/// Identify a file by reading only its first few KB
+pub fn identify_file(path: &Path) -> JcResult<Identification> {
+    if path.is_dir() {
+        return Err(JcError::NotAFile(path.to_path_buf()));
+    }
+
+    let mut header = Vec::with_capacity(HEADER_READ_SIZE);
+    File::open(path)?
+        .take(HEADER_READ_SIZE as u64)
+        .read_to_end(&mut header)?;
+
+    debug!("Read {} header bytes from {}", header.len(), path.display());
+
+    let mut ident = Identification::new(path);
+
+    if EncryptedContainer::has_magic(&header) {
+        identify_container(&header, &mut ident);
+        // Inner layers are encrypted; fall back to the filename
+        for format in formats_from_extensions(&path.with_extension("")) {
+            ident.layers.push(Layer {
+                kind: LayerKind::Format(format),
+                evidence: LayerEvidence::Extension,
+            });
+        }
+    } else {
+        identify_layers(header, &mut ident);
+
+        // Layers hidden beyond the header window can still be inferred from the name
+        let from_name = formats_from_extensions(path);
+        let seen = ident.formats();
+        if !seen.is_empty() && seen.len() < from_name.len() && from_name.starts_with(&seen) {
+            for format in &from_name[seen.len()..] {
+                ident.layers.push(Layer {
+                    kind: LayerKind::Format(*format),
+                    evidence: LayerEvidence::Extension,
+                });
+            }
+        }
+
+        // The archive comment is at the very end, after the central directory
+        if ident.formats().first() == Some(&CompressionFormat::Zip) {
+            if let Some(comment) = zip::read_comment(path)? {
+                ident.notes.push(format!("Comment: {}", comment));
+            }
+        }
+    }
+
+    if let Some(option) = timestamp_option_from_name(path) {
+        ident.options.push(option);
+    }
+
+    Ok(ident)
+}
+
+/// Fill in encryption details from a JCZE container header
+fn identify_container(header: &[u8], ident: &mut Identification) {
+    ident.layers.push(Layer {
+        kind: LayerKind::Encrypted,
+        evidence: LayerEvidence::Header,
+    });
+
+    match EncryptedContainer::from_bytes(header) {
+        Ok(container) => {
+            ident.container_version = Some(container.version);
+            match container.metadata {
+                EncryptionMetadata::Password { argon2_params, .. } => {
+                    ident.encryption = Some(format!(
+                        "password, AES-256-GCM, Argon2id m={} t={} p={}",
+                        argon2_params.memory_cost,
+                        argon2_params.time_cost,
+                        argon2_params.parallelism
+                    ));
+                    ident.options.push("-e".to_string());
+                }
+                EncryptionMetadata::Rsa { encrypted_key, .. } => {
+                    ident.encryption = Some(format!(
+                        "RSA-{} wrapped key, AES-256-GCM",
+                        encrypted_key.len() * 8
+                    ));
+                    ident.options.push("--encrypt-key <public.pem>".to_string());
+                }
+                EncryptionMetadata::Threshold {
+                    threshold, shares, ..
+                } => {
+                    ident.encryption = Some(format!(
+                        "key split into {} shares ({} needed), AES-256-GCM",
+                        shares, threshold
+                    ));
+                    ident
+                        .options
+                        .push(format!("--split-key {}/{}", threshold, shares));
+                }
+                EncryptionMetadata::Keyfile { .. } => {
+                    ident.encryption = Some("32-byte keyfile, AES-256-GCM".to_string());
+                    ident
+                        .options
+                        .push("--encrypt-keyfile <secret.bin>".to_string());
+                }
+                EncryptionMetadata::X25519 { .. } => {
+                    ident.encryption = Some("X25519 ECIES (HKDF-SHA256), AES-256-GCM".to_string());
+                    ident
+                        .options
+                        .push("--encrypt-key <x25519.pub.pem>".to_string());
+                }
+            }
+            if let Some(block) = container.pad_to {
+                ident.options.push(format!("--pad-to {}", block));
+            }
+            if let Some(len) = container.chunk_len {
+                ident
+                    .notes
+                    .push(format!("Sealed in chunks of {}", format_size(len.into())));
+            }
+        }
+        Err(CryptoError::UnsupportedVersion(version)) => {
+            ident.container_version = Some(version);
+            ident.encryption = Some("unsupported container version".to_string());
+        }
+        Err(e) => {
+            ident.container_version = header.get(4).copied();
+            ident.encryption = Some(format!("unreadable header: {}", e));
+        }
+    }
+}
+
+/// Walk compression layers by sniffing magic bytes and peeking into each layer
+fn identify_layers(mut data: Vec<u8>, ident: &mut Identification) {
+    while let Some(format) = detect_format_from_header(&data) {
+        ident.layers.push(Layer {
+            kind: LayerKind::Format(format),
+            evidence: LayerEvidence::Header,
+        });
+        collect_header_facts(format, &data, ident);
+
+        match format {
+            CompressionFormat::Gzip | CompressionFormat::Bzip2 | CompressionFormat::Xz => {
+                data = peek_decompressed(format, &data);
+                if data.is_empty() {
+                    ident
+                        .notes
+                        .push("Inner layer: not visible in the first few KB".to_string());
+                    break;
+                }
+            }
+            CompressionFormat::Tar
+            | CompressionFormat::Zip
+            | CompressionFormat::Cpio
+            | CompressionFormat::Ar
+            | CompressionFormat::Deb
+            | CompressionFormat::Rpm
+            | CompressionFormat::Dedup => break,
+        }
+    }
+}
+
+/// Record per-format header facts and creator option hints
+fn collect_header_facts(format: CompressionFormat, data: &[u8], ident: &mut Identification) {
+    match format {
+        CompressionFormat::Gzip => {
+            // XFL byte: 2 = maximum compression, 4 = fastest
+            match data.get(8) {
+                Some(2) => ident.options.push("-l 9".to_string()),
+                Some(4) => ident.options.push("-l 1".to_string()),
+                _ => {}
+            }
+            if let Some(name) = gzip::stored_name(data) {
+                ident.notes.push(format!("Stored name: {}", name));
+            }
+        }
+        CompressionFormat::Bzip2 => {
+            // Block size digit equals the compression level
+            if let Some(level) = data.get(3).filter(|b| b.is_ascii_digit()) {
+                let level = (level - b'0').to_string();
+                if level != "6" {
+                    ident.options.push(format!("-l {}", level));
+                }
+            }
+        }
+        CompressionFormat::Xz => {
+            let check = match data.get(7).map(|b| b & 0x0F) {
+                Some(0x00) => "none",
+                Some(0x01) => "CRC32",
+                Some(0x04) => "CRC64",
+                Some(0x0A) => "SHA-256",
+                _ => "unknown",
+            };
+            ident.notes.push(format!("XZ integrity check: {}", check));
+        }
+        CompressionFormat::Tar => {
+            let mut name = c_string(&data[..100.min(data.len())]);
+            // Typeflag '5' marks a directory; not every writer adds the slash
+            if data.get(156) == Some(&b'5') && !name.ends_with('/') {
+                name.push('/');
+            }
+            if !name.is_empty() {
+                ident.notes.push(format!("First entry: {}", name));
+            }
+        }
+        CompressionFormat::Cpio => {
+            if data.len() > 110 {
+                let name_len = std::str::from_utf8(&data[94..102])
+                    .ok()
+                    .and_then(|s| usize::from_str_radix(s, 16).ok())
+                    .unwrap_or(0);
+                if let Some(name) = data.get(110..110 + name_len) {
+                    ident.notes.push(format!("First entry: {}", c_string(name)));
+                }
+            }
+        }
+        CompressionFormat::Ar => {
+            // First member header follows the 8-byte global header
+            if let Some(name) = data.get(8..24) {
+                let name = String::from_utf8_lossy(name);
+                let name = name.trim_end().trim_end_matches('/');
+                if !name.is_empty() {
+                    ident.notes.push(format!("First member: {}", name));
+                }
+            }
+        }
+        CompressionFormat::Deb => {
+            ident
+                .notes
+                .push("Debian package: control.tar and data.tar members".to_string());
+        }
+        CompressionFormat::Dedup => {
+            if let Some(version) = data.get(8) {
+                ident.notes.push(format!(
+                    "jcz deduplicating archive, version {}; manifest at the end",
+                    version
+                ));
+            }
+        }
+        CompressionFormat::Rpm => {
+            // Package name-version-release is stored in the lead
+            if let Some(name) = data.get(10..76) {
+                let name = c_string(name);
+                if !name.is_empty() {
+                    ident.notes.push(format!("Package: {}", name));
+                }
+            }
+        }
+        CompressionFormat::Zip => {
+            if data.len() >= 30 {
+                let name_len = u16::from_le_bytes([data[26], data[27]]) as usize;
+                if let Some(name) = data.get(30..30 + name_len) {
+                    ident
+                        .notes
+                        .push(format!("First entry: {}", String::from_utf8_lossy(name)));
+                }
+            }
+        }
+    }
+}
+
+/// Interpret bytes up to the first NUL as a (lossy) string
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+/// Decompress as much of a truncated stream as the built-in decoders allow
+fn peek_decompressed(format: CompressionFormat, data: &[u8]) -> Vec<u8> {
+    let decoder: Box<dyn Read + '_> = match format {
+        CompressionFormat::Gzip => Box::new(MultiGzDecoder::new(data)),
+        CompressionFormat::Bzip2 => Box::new(MultiBzDecoder::new(data)),
+        CompressionFormat::Xz => Box::new(XzReader::new(data, true)),
+        _ => return Vec::new(),
+    };
+
+    // The stream is truncated on purpose, so it ends in an error; what was
+    // decoded up to there is kept
+    let mut output = Vec::new();
+    if let Err(e) = decoder.take(PEEK_OUTPUT_LIMIT).read_to_end(&mut output) {
+        debug!("{} header peek stopped: {}", format.name(), e);
+    }
+    output
+}
+
+/// Map trailing filename extensions to formats, outermost first
+fn formats_from_extensions(path: &Path) -> Vec<CompressionFormat> {
+    let mut formats = Vec::new();
+    let mut current = path.to_path_buf();
+
+    loop {
+        if let Some(compound) = crate::compressors::detect_compound_format(&current) {
+            formats.push(compound.secondary());
+            formats.push(compound.primary());
+            current = crate::compressors::strip_layer_extension(&current).with_extension("");
+        } else if let Some(format) = crate::compressors::detect_format(&current) {
+            formats.push(format);
+            current = current.with_extension("");
+        } else {
+            break;
+        }
+    }
+
+    formats
+}
+
+/// Guess the `-t` option from a timestamp suffix in the filename
+fn timestamp_option_from_name(path: &Path) -> Option<String> {
+    let mut stem = path.to_path_buf();
+    while stem.extension().is_some_and(|e| e == "jcze")
+        || crate::compressors::detect_format(&stem).is_some()
+    {
+        stem = stem.with_extension("");
+    }
+    let name = stem.file_name()?.to_str()?;
+
+    let all_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let parts: Vec<&str> = name.rsplitn(3, '_').collect();
+
+    match parts.as_slice() {
+        [time, date, _]
+            if date.len() == 8 && time.len() == 6 && all_digits(date) && all_digits(time) =>
+        {
+            Some("-t 2".to_string())
+        }
+        [date, ..] if date.len() == 8 && all_digits(date) => Some("-t 1".to_string()),
+        [nanos, _, ..] if all_digits(nanos) && (6..=9).contains(&nanos.len()) => {
+            Some("-t 3".to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{Argon2Params, EncryptionType};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identify_password_container() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.tar.gz.jcze");
+
+        let container = EncryptedContainer::new(
+            EncryptionType::Password,
+            EncryptionMetadata::Password {
+                salt: [1u8; 32],
+                nonce: [2u8; 12],
+                argon2_params: Argon2Params::default(),
+            },
+            vec![0u8; 64],
+        );
+        container.write_to_file(&path).unwrap();
+
+        let ident = identify_file(&path).unwrap();
+        assert!(ident.is_encrypted());
+        assert_eq!(ident.container_version, Some(1));
+        assert_eq!(ident.format_chain(), "jcze -> gzip? -> tar?");
+        assert_eq!(ident.likely_command(), Some("tgz"));
+        assert!(ident.options.contains(&"-e".to_string()));
+    }
+
+    #[test]
+    fn test_identify_unknown_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, b"plain text").unwrap();
+
+        let ident = identify_file(&path).unwrap();
+        assert!(ident.layers.is_empty());
+        assert_eq!(ident.format_chain(), "unknown");
+        assert_eq!(ident.likely_command(), None);
+    }
+
+    #[test]
+    fn test_timestamp_option_from_name() {
+        let ts = |name: &str| timestamp_option_from_name(Path::new(name));
+        assert_eq!(ts("file.txt_20251101.gz"), Some("-t 1".to_string()));
+        assert_eq!(
+            ts("file.txt_20251101_121019.tar.gz"),
+            Some("-t 2".to_string())
+        );
+        assert_eq!(ts("file.txt.gz"), None);
+    }
+}
@@ -0,0 +1,359 @@
+//! Seekable `.tar.gz` archives (`--indexed`)
+//!
+//! The tar is compressed in independent gzip members of `BLOCK_SIZE`
+//! uncompressed bytes each, which any gzip reader decompresses as one
+//! stream, and an index is written beside the archive
+//! (`<archive>.jcz.idx`, JSON) recording for every member the compressed
+//! offset of the block its headers start in and how far into that block
+//! they are. A member is then read by seeking to its block and
+//! decompressing from there, instead of streaming the whole archive.
+
+use flate2::read::MultiGzDecoder;
+use flate2::{Compression, GzBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::utils::{
+    debug, generate_output_filename, info, is_cancelled_io, place_output, warn, Throttled,
+};
+
+/// Suffix appended to an archive's filename to name its index
+pub const INDEX_SUFFIX: &str = ".jcz.idx";
+
+/// Uncompressed bytes per gzip member: the most decompressed to reach a
+/// member's headers
+pub const BLOCK_SIZE: u64 = 1 << 20;
+
+/// Where each member of an indexed archive starts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveIndex {
+    /// Uncompressed bytes per gzip member
+    pub block_size: u64,
+
+    /// Size of the archive the index was written for, to notice a stale one
+    pub archive_size: u64,
+
+    /// Members in archive order
+    pub entries: Vec<IndexEntry>,
+}
+
+/// One tar member
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Path stored in the archive
+    pub name: String,
+
+    /// Compressed offset of the gzip member holding the entry's first header
+    pub offset: u64,
+
+    /// Uncompressed bytes of that gzip member before the first header
+    pub skip: u64,
+
+    /// Size of the member's data
+    pub size: u64,
+}
+
+/// Path of the index of `archive`
+pub fn index_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(INDEX_SUFFIX);
+    PathBuf::from(name)
+}
+
+impl ArchiveIndex {
+    /// The index beside `archive`, if there is one that still matches it
+    pub fn load(archive: &Path) -> JcResult<Option<Self>> {
+        let path = index_path(archive);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)?;
+        let index: ArchiveIndex = serde_json::from_str(&text).map_err(|e| {
+            JcError::Other(format!("Invalid archive index {}: {}", path.display(), e))
+        })?;
+        if index.archive_size != fs::metadata(archive)?.len() {
+            warn!(
+                "Ignoring {}: it does not match {}",
+                path.display(),
+                archive.display()
+            );
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+
+    /// Write the index beside `archive`
+    pub fn save(&self, archive: &Path) -> JcResult<PathBuf> {
+        let path = index_path(archive);
+        let json = serde_json::to_string(self)
+            .map_err(|e| JcError::Other(format!("Failed to encode archive index: {}", e)))?;
+        fs::write(&path, json)?;
+        debug!("Wrote archive index {}", path.display());
+        Ok(path)
+    }
+
+    /// The entry stored as `name` (a leading `./` and trailing `/` ignored)
+    pub fn find(&self, name: &str) -> Option<&IndexEntry> {
+        let name = normalize(name);
+        self.entries
+            .iter()
+            .find(|entry| normalize(&entry.name) == name)
+    }
+}
+
+/// `name` without a leading `./` or trailing `/`
+pub(crate) fn normalize(name: &str) -> &str {
+    name.trim_start_matches("./").trim_end_matches('/')
+}
+
+/// Compress the tar `tar` into a seekable `.tar.gz` named and placed as
+/// gzip would, returning the archive and its index (not yet saved)
+pub(crate) fn compress_indexed(
+    tar: &Path,
+    config: &CompressionConfig,
+) -> JcResult<(PathBuf, ArchiveIndex)> {
+    let output = generate_output_filename(tar, "gz", config)?;
+    info!(
+        "Compressing {} to {} with a seekable index",
+        tar.display(),
+        output.display()
+    );
+
+    let starts = scan_members(tar)?;
+    let offsets = write_blocks(tar, &output, config).inspect_err(|_| {
+        let _ = fs::remove_file(&output);
+    })?;
+    let entries = starts
+        .into_iter()
+        .map(|(name, start, size)| IndexEntry {
+            name,
+            offset: offsets[(start / BLOCK_SIZE) as usize],
+            skip: start % BLOCK_SIZE,
+            size,
+        })
+        .collect();
+
+    let final_path = place_output(&output, config)?;
+    let index = ArchiveIndex {
+        block_size: BLOCK_SIZE,
+        archive_size: fs::metadata(&final_path)?.len(),
+        entries,
+    };
+    info!("Compressed file: {}", final_path.display());
+    Ok((final_path, index))
+}
+
+/// Name, offset of the first header and data size of each member of `tar`
+///
+/// Long names and PAX attributes come in extra headers before the
+/// member's own, so a member starts at the first of them.
+fn scan_members(tar: &Path) -> JcResult<Vec<(String, u64, u64)>> {
+    let to_error = |e: io::Error| JcError::CompressionFailed {
+        tool: "tar".to_string(),
+        stderr: e.to_string(),
+    };
+
+    let mut archive = tar::Archive::new(BufReader::new(File::open(tar)?));
+    let mut members = Vec::new();
+    let mut start = None;
+    let mut long_name = None;
+    let mut pax_name = None;
+    for entry in archive.entries().map_err(to_error)?.raw(true) {
+        let mut entry = entry.map_err(to_error)?;
+        let kind = entry.header().entry_type();
+        if kind.is_pax_global_extensions() {
+            continue;
+        }
+        let first = *start.get_or_insert(entry.raw_header_position());
+        if kind.is_gnu_longname() {
+            let mut name = Vec::new();
+            entry.read_to_end(&mut name).map_err(to_error)?;
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            long_name = Some(String::from_utf8_lossy(&name[..end]).into_owned());
+            continue;
+        }
+        if kind.is_pax_local_extensions() {
+            if let Some(extensions) = entry.pax_extensions().map_err(to_error)? {
+                for extension in extensions {
+                    let extension = extension.map_err(to_error)?;
+                    if extension.key_bytes() == b"path" {
+                        pax_name = Some(String::from_utf8_lossy(extension.value_bytes()).into());
+                    }
+                }
+            }
+            continue;
+        }
+        if kind.is_gnu_longlink() {
+            continue;
+        }
+
+        let name = match pax_name.take().or(long_name.take()) {
+            Some(name) => name,
+            None => entry
+                .header()
+                .path()
+                .map_err(to_error)?
+                .to_string_lossy()
+                .into_owned(),
+        };
+        members.push((name, first, entry.size()));
+        start = None;
+    }
+    Ok(members)
+}
+
+/// Write `tar` to `output` as gzip members of `BLOCK_SIZE` bytes each,
+/// returning the compressed offset of every member
+fn write_blocks(tar: &Path, output: &Path, config: &CompressionConfig) -> JcResult<Vec<u64>> {
+    let limit = config.limit_rate.as_ref();
+    let mut reader = Throttled::new(config.watched(File::open(tar)?, tar), limit);
+    let mut writer = Throttled::new(BufWriter::new(File::create(output)?), limit);
+    let level = Compression::new(config.level.into());
+
+    let to_error = |e: io::Error| {
+        if is_cancelled_io(&e) {
+            return JcError::Aborted;
+        }
+        JcError::CompressionFailed {
+            tool: "gzip".to_string(),
+            stderr: e.to_string(),
+        }
+    };
+
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    let mut block = Vec::with_capacity(BLOCK_SIZE as usize);
+    loop {
+        block.clear();
+        (&mut reader)
+            .take(BLOCK_SIZE)
+            .read_to_end(&mut block)
+            .map_err(to_error)?;
+        if block.is_empty() && !offsets.is_empty() {
+            break;
+        }
+        // No name or mtime: every member gets the same minimal header
+        let mut encoder = GzBuilder::new().write(Vec::new(), level);
+        encoder.write_all(&block).map_err(to_error)?;
+        let compressed = encoder.finish().map_err(to_error)?;
+        writer.write_all(&compressed).map_err(to_error)?;
+        offsets.push(offset);
+        offset += compressed.len() as u64;
+        if (block.len() as u64) < BLOCK_SIZE {
+            break;
+        }
+    }
+    writer.flush().map_err(to_error)?;
+    debug!(
+        "Wrote {} gzip members to {}",
+        offsets.len(),
+        output.display()
+    );
+    Ok(offsets)
+}
+
+/// Copy the data of the member `entry` of the indexed `archive` to `out`,
+/// decompressing only from the block it starts in
+pub fn read_indexed(archive: &Path, entry: &IndexEntry, out: &mut dyn Write) -> JcResult<u64> {
+    let to_error = |e: io::Error| JcError::DecompressionFailed {
+        tool: "gzip".to_string(),
+        stderr: e.to_string(),
+    };
+
+    let mut file = File::open(archive)?;
+    file.seek(SeekFrom::Start(entry.offset))?;
+    let mut decoder = MultiGzDecoder::new(BufReader::new(file));
+    let skipped =
+        io::copy(&mut (&mut decoder).take(entry.skip), &mut io::sink()).map_err(to_error)?;
+
+    let mut tar = tar::Archive::new(decoder);
+    let mut member = match tar.entries().map_err(to_error)?.next() {
+        Some(member) if skipped == entry.skip => member.map_err(to_error)?,
+        _ => return Err(stale(archive)),
+    };
+    let name = member.path().map_err(to_error)?;
+    if normalize(&name.to_string_lossy()) != normalize(&entry.name) {
+        return Err(stale(archive));
+    }
+    Ok(io::copy(&mut member, out)?)
+}
+
+fn stale(archive: &Path) -> JcError {
+    JcError::Other(format!(
+        "{} does not match {}; remove it to read the archive without it",
+        index_path(archive).display(),
+        archive.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A tar with a small file, a long name and a member spanning blocks
+    fn sample_tar(dir: &Path) -> PathBuf {
+        let path = dir.join("sample.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        let mut add = |name: &str, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data).unwrap();
+        };
+        add("small.txt", b"hello");
+        let big: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        add("big.bin", &big);
+        add(
+            &format!("{}/tail.txt", "d".repeat(120)),
+            b"after the long name",
+        );
+        builder.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_indexed_members_read_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let tar = sample_tar(temp_dir.path());
+        let config = CompressionConfig::new();
+        let (archive, index) = compress_indexed(&tar, &config).unwrap();
+        assert_eq!(archive, temp_dir.path().join("sample.tar.gz"));
+        assert_eq!(index.entries.len(), 3);
+        index.save(&archive).unwrap();
+        let index = ArchiveIndex::load(&archive).unwrap().unwrap();
+
+        let tail = index.find(&format!("./{}/tail.txt", "d".repeat(120)));
+        let mut out = Vec::new();
+        read_indexed(&archive, tail.unwrap(), &mut out).unwrap();
+        assert_eq!(out, b"after the long name");
+        assert!(index.entries[2].offset > 0);
+
+        // Still one valid gzip stream holding the whole tar
+        let mut whole = Vec::new();
+        MultiGzDecoder::new(File::open(&archive).unwrap())
+            .read_to_end(&mut whole)
+            .unwrap();
+        assert_eq!(whole, fs::read(&tar).unwrap());
+    }
+
+    #[test]
+    fn test_stale_index_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let tar = sample_tar(temp_dir.path());
+        let (archive, index) = compress_indexed(&tar, &CompressionConfig::new()).unwrap();
+        index.save(&archive).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&archive)
+            .unwrap()
+            .write_all(b"x")
+            .unwrap();
+        assert_eq!(ArchiveIndex::load(&archive).unwrap(), None);
+    }
+}
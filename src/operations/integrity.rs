@@ -0,0 +1,386 @@
+//! End-to-end integrity tags
+//!
+//! The tag is a SHA-256 over the plaintext tree as it comes back out of
+//! every layer: paths relative to the extracted root, entry types, file
+//! contents and link targets. It is recorded in the encrypted container
+//! and/or the metadata sidecar, so `jcz verify` on the final artifact
+//! attests the whole archive/compress/encrypt chain.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::core::config::{CompressionConfig, DecryptionMethod, EncryptionMethod, Secrets};
+use crate::core::error::{JcError, JcResult};
+use crate::crypto::EncryptedContainer;
+use crate::operations::decrypt::{decrypt_file, is_encrypted_file};
+use crate::operations::manifest::{check_manifest, MANIFEST_NAME};
+use crate::operations::sidecar::{self, SIDECAR_SUFFIX};
+use crate::operations::{decompress, sidecar::sha256_file};
+use crate::utils::bufio::buffered_reader;
+use crate::utils::{copy_to_dir, create_decompress_temp_dir, info};
+
+/// Tree digest of `root`; its own name is not part of the digest
+///
+/// Symlinks are hashed as links unless `dereference` is set, matching
+/// what the archivers store.
+pub fn tree_digest(root: &Path, dereference: bool) -> JcResult<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    let mut followed = dereference.then(Vec::new);
+    hash_entry(&mut hasher, root, Path::new(""), &mut followed)?;
+    Ok(hasher.finalize().into())
+}
+
+/// Tree digest of a directory holding `inputs` under their basenames
+///
+/// This is what a collection archive extracts to, so it equals
+/// `tree_digest` of the extracted directory.
+pub fn collection_digest(inputs: &[PathBuf], dereference: bool) -> JcResult<[u8; 32]> {
+    let mut children = inputs
+        .iter()
+        .map(|input| {
+            let name = input
+                .file_name()
+                .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+            Ok((PathBuf::from(name), input))
+        })
+        .collect::<JcResult<Vec<_>>>()?;
+    children.sort();
+
+    let mut hasher = Sha256::new();
+    let mut followed = dereference.then(Vec::new);
+    hash_record(&mut hasher, b'D', Path::new(""), &[]);
+    for (name, input) in children {
+        hash_entry(&mut hasher, input, &name, &mut followed)?;
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Hash `path` (known as `relative` below the root) and its children
+///
+/// `followed` holds the canonical directories being walked when symlinks
+/// are followed, to skip loops the way the archivers do.
+fn hash_entry(
+    hasher: &mut Sha256,
+    path: &Path,
+    relative: &Path,
+    followed: &mut Option<Vec<PathBuf>>,
+) -> JcResult<()> {
+    let mut metadata = fs::symlink_metadata(path)?;
+    if followed.is_some() && metadata.file_type().is_symlink() {
+        // Dangling links stay links, as in the archive
+        metadata = fs::metadata(path).unwrap_or(metadata);
+    }
+
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        hash_record(
+            hasher,
+            b'L',
+            relative,
+            target.as_os_str().as_encoded_bytes(),
+        );
+    } else if metadata.is_dir() {
+        let canonical = path.canonicalize()?;
+        if followed.as_ref().is_some_and(|a| a.contains(&canonical)) {
+            return Ok(());
+        }
+        hash_record(hasher, b'D', relative, &[]);
+
+        let mut children: Vec<_> = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<Result<_, _>>()?;
+        children.sort();
+        if let Some(ancestors) = followed.as_mut() {
+            ancestors.push(canonical);
+        }
+        for child in children {
+            hash_entry(hasher, &path.join(&child), &relative.join(&child), followed)?;
+        }
+        if let Some(ancestors) = followed.as_mut() {
+            ancestors.pop();
+        }
+    } else {
+        hash_record(hasher, b'F', relative, sha256_file(path)?.as_bytes());
+    }
+
+    Ok(())
+}
+
+fn hash_record(hasher: &mut Sha256, kind: u8, relative: &Path, payload: &[u8]) {
+    hasher.update([kind]);
+    hasher.update(relative.as_os_str().as_encoded_bytes());
+    hasher.update([0]);
+    hasher.update(payload);
+    hasher.update([0]);
+}
+
+/// Tag to seal into the encrypted output of `input`, if it is going into a
+/// `.jcze` container
+///
+/// The container header is authenticated along with the data, so the tag
+/// has to be known before the data is sealed; OpenPGP output has no room
+/// for it.
+pub fn seal_tag(input: &Path, config: &CompressionConfig) -> JcResult<Option<[u8; 32]>> {
+    match config.encryption {
+        Some(EncryptionMethod::Gpg { .. }) | None => Ok(None),
+        Some(_) => Ok(Some(tree_digest(input, config.dereference)?)),
+    }
+}
+
+/// Record `digest` for a finished archive in its sidecar, if sidecars are
+/// enabled
+///
+/// Encrypted containers already carry it in their header, sealed along
+/// with the data (see `seal_tag`); the sidecar's archive hash covers that
+/// too. `sources` is passed through to the sidecar manifest.
+pub fn record_tag(
+    archive: &Path,
+    digest: &[u8; 32],
+    sources: &[(PathBuf, PathBuf)],
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    if config.sidecar_metadata {
+        sidecar::write_sidecar(archive, sources, &to_hex(digest), config)?;
+    }
+    Ok(())
+}
+
+/// Write the sidecars of every archive of a batch whose results line up
+/// with its inputs
+///
+/// Inputs are stored under their basenames, as the single-input
+/// compressors do. Results pass through untouched unless sidecars are on.
+pub fn attach_tags(
+    inputs: &[PathBuf],
+    results: Vec<JcResult<PathBuf>>,
+    config: &CompressionConfig,
+) -> Vec<JcResult<PathBuf>> {
+    if !config.sidecar_metadata {
+        return results;
+    }
+
+    results
+        .into_iter()
+        .zip(inputs)
+        .map(|(result, input)| {
+            let archive = result?;
+            let name = input
+                .file_name()
+                .map(PathBuf::from)
+                .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?;
+            // Encrypted outputs were sealed with it already
+            let digest = match sealed_tag(&archive)? {
+                Some(digest) => digest,
+                None => tree_digest(input, config.dereference)?,
+            };
+            record_tag(&archive, &digest, &[(input.clone(), name)], config)?;
+            Ok(archive)
+        })
+        .collect()
+}
+
+/// Tag in the header of `archive`, if it is an encrypted container
+fn sealed_tag(archive: &Path) -> JcResult<Option<[u8; 32]>> {
+    if !is_encrypted_file(archive) {
+        return Ok(None);
+    }
+    let (container, _) =
+        EncryptedContainer::read_header(&mut buffered_reader(File::open(archive)?))?;
+    Ok(container.integrity)
+}
+
+/// What `verify_archive` checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// Verified tree digest (hex), if the archive carries a tag
+    pub tree_sha256: Option<String>,
+
+    /// Number of files checked against an embedded MANIFEST.sha256
+    pub manifest_files: Option<usize>,
+
+    /// Whether the archive was decrypted, its authentication tag checking out
+    pub authenticated: bool,
+}
+
+impl Verification {
+    /// Whether anything beyond the structure of the archive was checked
+    /// against a record of its contents
+    pub fn is_tagged(&self) -> bool {
+        self.tree_sha256.is_some() || self.manifest_files.is_some()
+    }
+
+    /// What was checked, one phrase per check
+    pub fn checks(&self) -> Vec<String> {
+        let mut checks = vec!["layers decode".to_string()];
+        if self.authenticated {
+            checks.push("authenticated".to_string());
+        }
+        if let Some(ref digest) = self.tree_sha256 {
+            checks.push(format!("tree sha256 {}", digest));
+        }
+        if let Some(files) = self.manifest_files {
+            checks.push(format!("{} files match {}", files, MANIFEST_NAME));
+        }
+        checks
+    }
+}
+
+/// Decrypt and unpack `archive` in scratch space and check its tree against
+/// the recorded tag and/or the embedded manifest
+///
+/// Every layer must decode cleanly, which checks the authentication tag of
+/// an encrypted container and the CRC32s of gzip and zip data. When a
+/// sidecar is present, the archive bytes are checked against it first. An
+/// archive with neither tag nor manifest passes on those checks alone;
+/// see [`Verification::is_tagged`].
+pub fn verify_archive(
+    archive: &Path,
+    decryption: Option<&DecryptionMethod>,
+    secrets: Option<&Secrets>,
+) -> JcResult<Verification> {
+    if !archive.exists() {
+        return Err(JcError::FileNotFound(archive.to_path_buf()));
+    }
+
+    let embedded = sealed_tag(archive)?.map(|digest| to_hex(&digest));
+
+    let from_sidecar = match read_sidecar(archive)? {
+        Some((archive_sha256, tree_sha256)) => {
+            if sha256_file(archive)? != archive_sha256 {
+                return Err(JcError::IntegrityFailed(format!(
+                    "{} does not match the hash in its sidecar",
+                    archive.display()
+                )));
+            }
+            tree_sha256
+        }
+        None => None,
+    };
+
+    let expected = match (embedded, from_sidecar) {
+        (Some(embedded), Some(from_sidecar)) if embedded != from_sidecar => {
+            return Err(JcError::IntegrityFailed(format!(
+                "{}: container and sidecar record different integrity tags",
+                archive.display()
+            )));
+        }
+        (Some(tag), _) | (None, Some(tag)) => Some(tag),
+        (None, None) => None,
+    };
+
+    // Decrypt a copy, so nothing is written next to the archive
+    let scratch = create_decompress_temp_dir()?;
+    let copy = copy_to_dir(archive, scratch.path())?;
+    let plain = decrypt_file(&copy, decryption, secrets, false)?;
+    let authenticated = plain != copy;
+
+    let (_work_dir, output) = decompress::extract_to_temp(&plain, &CompressionConfig::default())?;
+
+    let manifest_files = match check_manifest(&output)? {
+        Some(check) if !check.problems.is_empty() => {
+            return Err(JcError::IntegrityFailed(format!(
+                "Manifest check failed for {}: {}",
+                archive.display(),
+                check.problems.join("; ")
+            )));
+        }
+        Some(check) => Some(check.files),
+        None => None,
+    };
+
+    let tree_sha256 = match expected {
+        Some(expected) => {
+            let actual = to_hex(&tree_digest(&output, false)?);
+            if actual != expected {
+                return Err(JcError::IntegrityFailed(format!(
+                    "Integrity check failed for {}: expected {}, got {}",
+                    archive.display(),
+                    expected,
+                    actual
+                )));
+            }
+            Some(actual)
+        }
+        None => None,
+    };
+
+    info!("Verified {}", archive.display());
+    Ok(Verification {
+        tree_sha256,
+        manifest_files,
+        authenticated,
+    })
+}
+
+/// Archive hash and tree tag from `<archive>.jcz.json`, if there is one
+fn read_sidecar(archive: &Path) -> JcResult<Option<(String, Option<String>)>> {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(SIDECAR_SUFFIX);
+    let Ok(json) = fs::read_to_string(PathBuf::from(path)) else {
+        return Ok(None);
+    };
+
+    let value: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| JcError::Other(format!("Invalid sidecar for {}: {}", archive.display(), e)))?;
+    let field = |name: &str| value.get(name).and_then(|v| v.as_str()).map(String::from);
+
+    match field("sha256") {
+        Some(archive_sha256) => Ok(Some((archive_sha256, field("tree_sha256")))),
+        None => Err(JcError::Other(format!(
+            "Invalid sidecar for {}: missing sha256",
+            archive.display()
+        ))),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_collection_digest_matches_extracted_directory() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("b")).unwrap();
+        fs::write(src.join("a.txt"), b"alpha").unwrap();
+        fs::write(src.join("b/c.txt"), b"gamma").unwrap();
+
+        // Same layout a WithParent collection extracts to
+        let pkg = dir.path().join("pkg");
+        fs::create_dir_all(pkg.join("b")).unwrap();
+        fs::write(pkg.join("a.txt"), b"alpha").unwrap();
+        fs::write(pkg.join("b/c.txt"), b"gamma").unwrap();
+
+        let inputs = vec![src.join("b"), src.join("a.txt")];
+        assert_eq!(
+            collection_digest(&inputs, false).unwrap(),
+            tree_digest(&pkg, false).unwrap()
+        );
+
+        fs::write(pkg.join("b/c.txt"), b"GAMMA").unwrap();
+        assert_ne!(
+            collection_digest(&inputs, false).unwrap(),
+            tree_digest(&pkg, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_root_name_is_not_hashed() {
+        let dir = TempDir::new().unwrap();
+        let a = dir.path().join("report.txt");
+        let b = dir.path().join("report.txt_20250101");
+        fs::write(&a, b"same").unwrap();
+        fs::write(&b, b"same").unwrap();
+
+        assert_eq!(
+            tree_digest(&a, false).unwrap(),
+            tree_digest(&b, false).unwrap()
+        );
+    }
+}
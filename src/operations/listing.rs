@@ -0,0 +1,108 @@
+//! Listing archive members without extracting them
+//!
+//! Tar-based archives are decompressed as a stream into an in-process pipe
+//! and only their headers are parsed; zips are listed from their central
+//! directory. Nothing is written to disk.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+
+use crate::compressors::{
+    create_compressor, detect_compound_format, detect_format, tar as tar_format, zip,
+};
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::{ArchiveEntry, CompressionFormat};
+
+/// Members of `archive`, in archive order
+///
+/// Handles `.tar`, `.tar.gz`, `.tar.bz2`, `.tar.xz` (and their aliases)
+/// and `.zip`.
+pub fn list_archive(archive: &Path, config: &CompressionConfig) -> JcResult<Vec<ArchiveEntry>> {
+    if detect_format(archive) == Some(CompressionFormat::Zip) {
+        return zip::list_entries(archive);
+    }
+    let Some(layer) = tar_layer(archive) else {
+        return Err(JcError::InvalidExtension(
+            archive.to_path_buf(),
+            "tar, tgz, tbz2, txz or zip".to_string(),
+        ));
+    };
+    read_decoded(archive, layer, config, |reader| {
+        tar_format::list_entries(reader)
+    })
+}
+
+/// Compression layer over the tar of a tar-based `archive`: `Some(None)`
+/// for a plain `.tar`, `None` when it is not tar-based
+pub(crate) fn tar_layer(archive: &Path) -> Option<Option<CompressionFormat>> {
+    match detect_compound_format(archive) {
+        Some(compound) => Some(Some(compound.secondary())),
+        None if detect_format(archive) == Some(CompressionFormat::Tar) => Some(None),
+        None => None,
+    }
+}
+
+/// Run `read` on the contents of `archive` decompressed from `layer`
+///
+/// The decoder runs on its own thread and writes into a pipe that `read`
+/// consumes, so the decompressed data is never held in full or written
+/// out. Whatever `read` leaves unread is drained so the decoder can finish.
+pub(crate) fn read_decoded<T>(
+    archive: &Path,
+    layer: Option<CompressionFormat>,
+    config: &CompressionConfig,
+    read: impl FnOnce(&mut dyn Read) -> JcResult<T>,
+) -> JcResult<T> {
+    let mut file = config.watched(File::open(archive)?, archive);
+    let Some(format) = layer else {
+        return read(&mut file);
+    };
+
+    let compressor = create_compressor(format);
+    let (mut reader, mut writer) = io::pipe()?;
+    thread::scope(|scope| {
+        let decoder = scope.spawn(move || {
+            // Dropping the writer at the end is the reader's EOF
+            compressor.decompress_stream(&mut file, &mut writer, config)
+        });
+        // Tar readers stop at the end-of-archive blocks; drain the padding
+        // behind them
+        let result = read(&mut reader).and_then(|value| {
+            io::copy(&mut reader, &mut io::sink())?;
+            Ok(value)
+        });
+        // Stop the decoder if `read` ended early
+        drop(reader);
+        let decoded = decoder
+            .join()
+            .unwrap_or_else(|_| Err(JcError::Other("Decoder panicked".to_string())));
+        match (result, decoded) {
+            (Ok(value), Ok(())) => Ok(value),
+            (Err(e), _) => Err(e),
+            (Ok(_), Err(e)) => Err(e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tar_layer() {
+        assert_eq!(
+            tar_layer(Path::new("a.tar.xz")),
+            Some(Some(CompressionFormat::Xz))
+        );
+        assert_eq!(
+            tar_layer(Path::new("a.tgz")),
+            Some(Some(CompressionFormat::Gzip))
+        );
+        assert_eq!(tar_layer(Path::new("a.tar")), Some(None));
+        assert_eq!(tar_layer(Path::new("a.gz")), None);
+        assert_eq!(tar_layer(Path::new("a.zip")), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Checksum manifests embedded in collection archives
+//!
+//! `MANIFEST.sha256` lists every regular file of the collection with its
+//! SHA-256 and size. The body is `sha256sum -c` compatible: sizes live in
+//! `# <n> bytes` comment lines in front of each entry, which sha256sum
+//! skips.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::error::{JcError, JcResult};
+use crate::operations::sidecar::sha256_file;
+use crate::utils::{debug, path_from_bytes, path_to_bytes};
+
+/// File name of the manifest, at the top of the collection
+pub const MANIFEST_NAME: &str = "MANIFEST.sha256";
+
+/// Header line identifying manifests written by jcz
+const MANIFEST_HEADER: &str =
+    "# Written by jcz; check with `jcz verify <archive>` or `sha256sum -c`";
+
+/// Outcome of checking an extracted collection against its manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestCheck {
+    /// Number of files listed in the manifest
+    pub files: usize,
+
+    /// One line per missing, changed or unlisted file
+    pub problems: Vec<String>,
+}
+
+/// One manifest line: relative path, size (if recorded) and hex SHA-256
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    path: PathBuf,
+    size: Option<u64>,
+    sha256: String,
+}
+
+/// Write `root/MANIFEST.sha256` covering every regular file below `root`
+///
+/// Returns the number of files listed. Symlinks are not followed or listed.
+pub fn write_manifest(root: &Path) -> JcResult<usize> {
+    let manifest = root.join(MANIFEST_NAME);
+    if fs::symlink_metadata(&manifest).is_ok() {
+        return Err(JcError::NameExists(MANIFEST_NAME.to_string()));
+    }
+
+    let mut files = Vec::new();
+    list_files(root, Path::new(""), &mut files)?;
+
+    let mut body = format!("{}\n", MANIFEST_HEADER).into_bytes();
+    for relative in &files {
+        let path = root.join(relative);
+        body.extend_from_slice(format!("# {} bytes\n", fs::metadata(&path)?.len()).as_bytes());
+        body.extend_from_slice(&format_line(&sha256_file(&path)?, relative));
+    }
+    fs::write(&manifest, body)?;
+
+    debug!("Wrote {} with {} files", manifest.display(), files.len());
+    Ok(files.len())
+}
+
+/// Check the files below `root` against `root/MANIFEST.sha256`
+///
+/// Returns `None` when there is no manifest.
+pub fn check_manifest(root: &Path) -> JcResult<Option<ManifestCheck>> {
+    let manifest = root.join(MANIFEST_NAME);
+    if !root.is_dir() || !manifest.is_file() {
+        return Ok(None);
+    }
+
+    let entries = parse_manifest(&fs::read(&manifest)?)?;
+    let mut problems = Vec::new();
+
+    for entry in &entries {
+        let path = root.join(&entry.path);
+        let shown = entry.path.display();
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_file() => match entry.size {
+                Some(size) if size != metadata.len() => problems.push(format!(
+                    "{}: size {} (expected {})",
+                    shown,
+                    metadata.len(),
+                    size
+                )),
+                _ if sha256_file(&path)? != entry.sha256 => {
+                    problems.push(format!("{}: checksum mismatch", shown))
+                }
+                _ => {}
+            },
+            Ok(_) => problems.push(format!("{}: not a regular file", shown)),
+            Err(_) => problems.push(format!("{}: missing", shown)),
+        }
+    }
+
+    let mut present = Vec::new();
+    list_files(root, Path::new(""), &mut present)?;
+    for path in present {
+        if !entries.iter().any(|entry| entry.path == path) {
+            problems.push(format!("{}: not in manifest", path.display()));
+        }
+    }
+
+    Ok(Some(ManifestCheck {
+        files: entries.len(),
+        problems,
+    }))
+}
+
+/// Regular files below `dir` (as paths relative to the root), sorted, and
+/// skipping the manifest itself
+fn list_files(dir: &Path, relative: &Path, out: &mut Vec<PathBuf>) -> JcResult<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<Result<_, _>>()?;
+    children.sort();
+
+    for child in children {
+        let path = dir.join(&child);
+        let child_relative = relative.join(&child);
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            list_files(&path, &child_relative, out)?;
+        } else if metadata.is_file() && child_relative != Path::new(MANIFEST_NAME) {
+            out.push(child_relative);
+        }
+    }
+    Ok(())
+}
+
+/// A `sha256sum` line; names with a backslash or newline are escaped and
+/// the line prefixed with `\`, as GNU coreutils does
+fn format_line(sha256: &str, relative: &Path) -> Vec<u8> {
+    let name = path_to_bytes(relative);
+    let escape = name.iter().any(|&b| b == b'\\' || b == b'\n');
+
+    let mut line = Vec::with_capacity(name.len() + 68);
+    if escape {
+        line.push(b'\\');
+    }
+    line.extend_from_slice(sha256.as_bytes());
+    line.extend_from_slice(b"  ");
+    for &b in name.iter() {
+        match b {
+            b'\\' if escape => line.extend_from_slice(b"\\\\"),
+            b'\n' if escape => line.extend_from_slice(b"\\n"),
+            _ => line.push(b),
+        }
+    }
+    line.push(b'\n');
+    line
+}
+
+fn parse_manifest(data: &[u8]) -> JcResult<Vec<Entry>> {
+    let invalid = |line: &[u8]| {
+        JcError::Other(format!(
+            "Invalid {} line: {}",
+            MANIFEST_NAME,
+            String::from_utf8_lossy(line)
+        ))
+    };
+
+    let mut entries = Vec::new();
+    let mut size = None;
+    for line in data.split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+        if let Some(comment) = line.strip_prefix(b"# ") {
+            size = std::str::from_utf8(comment)
+                .ok()
+                .and_then(|c| c.strip_suffix(" bytes"))
+                .and_then(|n| n.parse().ok());
+            continue;
+        }
+
+        let (escaped, line) = match line.strip_prefix(b"\\") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.len() < 66 || !line[..64].iter().all(u8::is_ascii_hexdigit) {
+            return Err(invalid(line));
+        }
+        // Two spaces (text mode) or space-asterisk (binary mode)
+        let name = match &line[64..66] {
+            b"  " | b" *" => &line[66..],
+            _ => return Err(invalid(line)),
+        };
+        let name = if escaped {
+            unescape(name)
+        } else {
+            name.to_vec()
+        };
+
+        entries.push(Entry {
+            path: path_from_bytes(&name),
+            size: size.take(),
+            sha256: String::from_utf8_lossy(&line[..64]).to_lowercase(),
+        });
+    }
+    Ok(entries)
+}
+
+fn unescape(name: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(name.len());
+    let mut bytes = name.iter();
+    while let Some(&b) = bytes.next() {
+        match (b, bytes.as_slice().first()) {
+            (b'\\', Some(b'n')) => {
+                out.push(b'\n');
+                bytes.next();
+            }
+            (b'\\', Some(b'\\')) => {
+                out.push(b'\\');
+                bytes.next();
+            }
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_round_trip_and_tamper_detection() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), b"alpha").unwrap();
+        fs::write(root.join("sub/b.txt"), b"beta").unwrap();
+        fs::write(root.join("odd\\name"), b"gamma").unwrap();
+
+        assert_eq!(write_manifest(root).unwrap(), 3);
+        let check = check_manifest(root).unwrap().unwrap();
+        assert_eq!(check.files, 3);
+        assert!(check.problems.is_empty(), "{:?}", check.problems);
+
+        fs::write(root.join("a.txt"), b"ALPHA").unwrap();
+        fs::remove_file(root.join("sub/b.txt")).unwrap();
+        fs::write(root.join("extra.txt"), b"").unwrap();
+        let problems = check_manifest(root).unwrap().unwrap().problems;
+        assert_eq!(
+            problems,
+            vec![
+                "a.txt: checksum mismatch",
+                "sub/b.txt: missing",
+                "extra.txt: not in manifest"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_sha256sum_lines() {
+        let hash = "ab".repeat(32);
+        let data = format!("# 5 bytes\n{hash}  a.txt\n{hash} *b.bin\n\\{hash}  x\\\\y\\nz\n");
+        let entries = parse_manifest(data.as_bytes()).unwrap();
+
+        assert_eq!(entries[0].size, Some(5));
+        assert_eq!(entries[0].path, PathBuf::from("a.txt"));
+        assert_eq!(entries[1].size, None);
+        assert_eq!(entries[1].path, PathBuf::from("b.bin"));
+        assert_eq!(entries[2].path, PathBuf::from("x\\y\nz"));
+
+        assert!(parse_manifest(b"not a checksum line\n").is_err());
+    }
+
+    #[test]
+    fn test_no_manifest() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(check_manifest(dir.path()).unwrap(), None);
+    }
+}
@@ -0,0 +1,535 @@
+//! Combining archives and directories into one archive (`jcz merge`)
+//!
+//! Each archive input is unpacked into scratch space with the same
+//! extractors `-d` uses, and directory inputs contribute their tree under
+//! their own name, as `-c tar` would store them. The trees are laid over
+//! each other in argument order and the result is archived with the tar
+//! writer and compressors of `-c`, or by `zip`. Two inputs providing
+//! different content at one path is a collision; files with identical
+//! content are not.
+//!
+//! Unpacking and repacking keeps modes, mtimes (of directories too),
+//! symlinks and empty directories across tar and zip. Metadata the output
+//! format cannot hold is reported with a warning instead of being dropped
+//! silently: member comments and missing Unix modes of zip inputs going
+//! into a tar, special files and hard links going into a zip.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::compressors::{
+    create_compressor, detect_compound_format, detect_format, strip_layer_extension,
+};
+use crate::compressors::{zip, CommandCompressor, TarCompressor, ZipCompressor};
+use crate::core::compressor::Compressor;
+use crate::core::config::{CompressionConfig, TimestampOption};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::{CompoundFormat, CompressionFormat};
+use crate::operations::decompress::peel_to_tar;
+use crate::utils::{copy_tree, create_scratch_dir, debug, info, move_atomic, warn};
+
+/// What to do when two inputs provide different content at one path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// Fail, listing every collision (default)
+    #[default]
+    Error,
+    /// Keep what the earlier input provides
+    First,
+    /// Let the later input win
+    Last,
+}
+
+impl CollisionPolicy {
+    /// Parse a `--on-collision` value
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(CollisionPolicy::Error),
+            "first" => Some(CollisionPolicy::First),
+            "last" => Some(CollisionPolicy::Last),
+            _ => None,
+        }
+    }
+}
+
+/// One path that more than one input provides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    /// Path inside the merged archive
+    pub path: PathBuf,
+
+    /// Inputs providing it, in argument order
+    pub sources: Vec<PathBuf>,
+}
+
+/// Merge `inputs` (archives and directories) into the archive `output`,
+/// whose extension picks its format: `.tar`, `.tar.gz`, `.tar.bz2`,
+/// `.tar.xz` (and their aliases), `.zip`, or `.tar.<ext>` of a compressor
+/// registered in `config.external`
+///
+/// Collisions are handled according to `on_collision`; the ones resolved
+/// by `First` or `Last` are returned so callers can report them.
+pub fn merge_archives(
+    output: &Path,
+    inputs: &[PathBuf],
+    on_collision: CollisionPolicy,
+    config: &CompressionConfig,
+) -> JcResult<Vec<Collision>> {
+    if inputs.is_empty() {
+        return Err(JcError::NoInputFiles);
+    }
+    let format = output_format(output, config)?;
+    if output.exists() && !config.make_way(output)? {
+        return Err(JcError::Other(format!(
+            "Merge aborted: {} already exists",
+            output.display()
+        )));
+    }
+    for input in inputs {
+        if !input.exists() {
+            return Err(JcError::FileNotFound(input.clone()));
+        }
+    }
+
+    let out_dir = output
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let scratch = create_scratch_dir(config.temp_dir.as_deref(), Some(out_dir))?;
+    let merged = scratch.path().join("merged");
+    fs::create_dir(&merged)?;
+
+    let mut merge = Merge {
+        root: merged.clone(),
+        policy: on_collision,
+        owners: HashMap::new(),
+        collisions: Vec::new(),
+        dir_times: Vec::new(),
+        for_zip: format == OutputFormat::Zip,
+        hard_links: 0,
+    };
+    for (index, input) in inputs.iter().enumerate() {
+        info!("Merging {}", input.display());
+        if input.is_dir() {
+            let name = input
+                .file_name()
+                .ok_or_else(|| JcError::Other("Invalid input path".to_string()))?;
+            merge.place(input, Path::new(name), index, false)?;
+            continue;
+        }
+
+        if format != OutputFormat::Zip && is_zip(input) {
+            for note in zip::tar_incompatibilities(input)? {
+                warn!("{}: {}", input.display(), note);
+            }
+        }
+        let unpacked = scratch.path().join(format!("input{}", index));
+        fs::create_dir(&unpacked)?;
+        unpack(input, &unpacked, scratch.path(), config)?;
+        for name in sorted_children(&unpacked)? {
+            merge.place(&unpacked.join(&name), Path::new(&name), index, true)?;
+        }
+    }
+
+    if merge.hard_links > 0 {
+        warn!(
+            "{} hard-linked files are stored in the zip as separate copies",
+            merge.hard_links
+        );
+    }
+    merge.restore_dir_times();
+
+    let collisions: Vec<Collision> = merge
+        .collisions
+        .into_iter()
+        .map(|(path, sources)| Collision {
+            path,
+            sources: sources.into_iter().map(|i| inputs[i].clone()).collect(),
+        })
+        .collect();
+    if on_collision == CollisionPolicy::Error && !collisions.is_empty() {
+        return Err(JcError::PathCollisions(
+            collisions.iter().map(describe_collision).collect(),
+        ));
+    }
+    for collision in &collisions {
+        warn!("Path collision: {}", describe_collision(collision));
+    }
+
+    write_archive(&merged, scratch.path(), output, format, config)?;
+    info!("Merged {} inputs into {}", inputs.len(), output.display());
+    Ok(collisions)
+}
+
+/// `path (a.tar.gz, b.zip)`
+fn describe_collision(collision: &Collision) -> String {
+    let sources: Vec<String> = collision
+        .sources
+        .iter()
+        .map(|s| s.display().to_string())
+        .collect();
+    format!("{} ({})", collision.path.display(), sources.join(", "))
+}
+
+/// Format of the merge output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Tar,
+    Compound(CompoundFormat),
+    /// A tar compressed by a registered compressor
+    External,
+    Zip,
+}
+
+fn output_format(output: &Path, config: &CompressionConfig) -> JcResult<OutputFormat> {
+    if let Some(compound) = detect_compound_format(output) {
+        return Ok(OutputFormat::Compound(compound));
+    }
+    if external_tar(output, config).is_some() {
+        return Ok(OutputFormat::External);
+    }
+    match detect_format(output) {
+        Some(CompressionFormat::Tar) => Ok(OutputFormat::Tar),
+        Some(CompressionFormat::Zip) => Ok(OutputFormat::Zip),
+        _ => Err(JcError::InvalidExtension(
+            output.to_path_buf(),
+            "tar, tar.gz, tar.bz2, tar.xz, zip or tar.<ext> of a registered compressor".to_string(),
+        )),
+    }
+}
+
+/// Fail unless `output` is named like an archive `merge_archives` writes
+pub(crate) fn check_output(output: &Path, config: &CompressionConfig) -> JcResult<()> {
+    output_format(output, config).map(|_| ())
+}
+
+/// Registered compressor whose `.tar.<ext>` `output` is named like
+fn external_tar<'a>(output: &Path, config: &'a CompressionConfig) -> Option<&'a CommandCompressor> {
+    config
+        .external
+        .as_ref()?
+        .for_path(output)
+        .filter(|c| c.tar_path(output).is_some())
+}
+
+fn is_zip(input: &Path) -> bool {
+    detect_compound_format(input).is_none() && detect_format(input) == Some(CompressionFormat::Zip)
+}
+
+/// Unpack the archive `input` into `dest`, peeling any outer compression
+/// layer in `scratch`
+pub(crate) fn unpack(
+    input: &Path,
+    dest: &Path,
+    scratch: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    if is_zip(input) {
+        return ZipCompressor::new().extract_into(input, dest, config);
+    }
+    let tar_file = peel_to_tar(input, scratch, config)?.ok_or_else(|| {
+        JcError::InvalidExtension(
+            input.to_path_buf(),
+            "directory, tar, tgz, tbz2, txz or zip".to_string(),
+        )
+    })?;
+    TarCompressor::new().extract_into(&tar_file, dest, config)?;
+    if tar_file != input {
+        let _ = fs::remove_file(&tar_file);
+    }
+    Ok(())
+}
+
+/// Names in `dir`, sorted so merges are reproducible
+fn sorted_children(dir: &Path) -> io::Result<Vec<OsString>> {
+    let mut names: Vec<OsString> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Archive the top-level entries of `merged` as `output`
+fn write_archive(
+    merged: &Path,
+    scratch: &Path,
+    output: &Path,
+    format: OutputFormat,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    if format == OutputFormat::Zip {
+        let archive = scratch.join("merged.zip");
+        let names = sorted_children(merged)?;
+        ZipCompressor::new().create_archive(&archive, merged, &names, config)?;
+        move_atomic(&archive, output)?;
+        return Ok(());
+    }
+
+    let entries: Vec<(PathBuf, OsString)> = sorted_children(merged)?
+        .into_iter()
+        .map(|name| (merged.join(&name), name))
+        .collect();
+
+    let tar_config = CompressionConfig {
+        level: 0,
+        move_to: None,
+        encryption: None,
+        listed_incremental: None,
+        ..config.clone()
+    };
+    // Named after the output, as compressors record the name they are given
+    let tar_name = match format {
+        OutputFormat::Compound(_) => Some(strip_layer_extension(output)),
+        OutputFormat::External => external_tar(output, config).and_then(|c| c.tar_path(output)),
+        _ => None,
+    }
+    .and_then(|path| path.file_name().map(|name| name.to_os_string()))
+    .unwrap_or_else(|| OsString::from("merged.tar"));
+    let tar_file = scratch.join(tar_name);
+    TarCompressor::new().create_archive(&tar_file, &entries, &tar_config)?;
+
+    let secondary_config = CompressionConfig {
+        move_to: None,
+        encryption: None,
+        ..config
+            .clone()
+            .with_timestamp(TimestampOption::None)
+            .with_name_template(None)
+    };
+    let archive = match format {
+        OutputFormat::Compound(compound) => {
+            create_compressor(compound.secondary()).compress(&tar_file, &secondary_config)?
+        }
+        OutputFormat::External => external_tar(output, config)
+            .ok_or_else(|| JcError::InvalidExtension(output.to_path_buf(), "tar".to_string()))?
+            .compress(&tar_file, &secondary_config)?,
+        _ => tar_file,
+    };
+    move_atomic(&archive, output)?;
+    Ok(())
+}
+
+/// Trees of the inputs laid over each other under `root`
+struct Merge {
+    root: PathBuf,
+    policy: CollisionPolicy,
+    /// Input that placed each non-directory entry
+    owners: HashMap<PathBuf, usize>,
+    /// Colliding paths and the inputs involved
+    collisions: Vec<(PathBuf, Vec<usize>)>,
+    /// Directories created, parents first, with the mtime of their source
+    dir_times: Vec<(PathBuf, SystemTime)>,
+    /// Leave out what a zip cannot hold, warning about it
+    for_zip: bool,
+    /// Hard-linked files met, which a zip stores as separate copies
+    hard_links: usize,
+}
+
+impl Merge {
+    /// Place `src` at `name` for input `index`; `owned` sources sit in
+    /// scratch space and are moved rather than copied
+    fn place(&mut self, src: &Path, name: &Path, index: usize, owned: bool) -> JcResult<()> {
+        let target = self.root.join(name);
+        let metadata = fs::symlink_metadata(src)?;
+        let existing = fs::symlink_metadata(&target).ok();
+
+        if metadata.is_dir() {
+            match existing {
+                Some(ref existing) if existing.is_dir() => {}
+                Some(_) => {
+                    // A file here in an earlier input, a directory now
+                    if !self.collide(name, index) {
+                        return Ok(());
+                    }
+                    fs::remove_file(&target)?;
+                    self.create_dir(&target, &metadata)?;
+                    self.owners.insert(name.to_path_buf(), index);
+                }
+                None => {
+                    self.create_dir(&target, &metadata)?;
+                    self.owners.insert(name.to_path_buf(), index);
+                }
+            }
+            for child in sorted_children(src)? {
+                self.place(&src.join(&child), &name.join(&child), index, owned)?;
+            }
+            return Ok(());
+        }
+
+        if self.for_zip {
+            let file_type = metadata.file_type();
+            if !file_type.is_file() && !file_type.is_symlink() {
+                warn!(
+                    "{}: special files (devices, FIFOs, sockets) cannot be stored in a zip; left out",
+                    name.display()
+                );
+                return Ok(());
+            }
+            #[cfg(unix)]
+            if file_type.is_file() && std::os::unix::fs::MetadataExt::nlink(&metadata) > 1 {
+                self.hard_links += 1;
+            }
+        }
+
+        if let Some(existing) = existing {
+            if !existing.is_dir() && same_content(src, &target)? {
+                debug!("Identical in both inputs: {}", name.display());
+                return Ok(());
+            }
+            if !self.collide(name, index) {
+                return Ok(());
+            }
+            if existing.is_dir() {
+                fs::remove_dir_all(&target)?;
+            } else {
+                fs::remove_file(&target)?;
+            }
+        }
+
+        if owned {
+            fs::rename(src, &target)?;
+        } else {
+            copy_tree(src, &target, false)?;
+        }
+        self.owners.insert(name.to_path_buf(), index);
+        Ok(())
+    }
+
+    /// Record a collision at `name`; whether input `index` replaces what
+    /// is there
+    fn collide(&mut self, name: &Path, index: usize) -> bool {
+        let earlier = self.owners.get(name).copied();
+        match self.collisions.iter_mut().find(|(path, _)| path == name) {
+            Some((_, sources)) => sources.push(index),
+            None => {
+                let mut sources: Vec<usize> = earlier.into_iter().collect();
+                sources.push(index);
+                self.collisions.push((name.to_path_buf(), sources));
+            }
+        }
+        self.policy == CollisionPolicy::Last
+    }
+
+    fn create_dir(&mut self, target: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+        fs::create_dir(target)?;
+        if let Ok(modified) = metadata.modified() {
+            self.dir_times.push((target.to_path_buf(), modified));
+        }
+        fs::set_permissions(target, metadata.permissions())
+    }
+
+    /// Give the directories created the mtime of their source, now that
+    /// nothing is added to them any more
+    ///
+    /// Children go first, since setting theirs would not touch the
+    /// parent's. Best effort, as some platforms cannot open directories.
+    fn restore_dir_times(&self) {
+        for (dir, modified) in self.dir_times.iter().rev() {
+            if let Err(e) = fs::File::open(dir).and_then(|dir| dir.set_modified(*modified)) {
+                debug!("Cannot set the mtime of {}: {}", dir.display(), e);
+            }
+        }
+    }
+}
+
+/// Whether the regular files (or symlinks) `a` and `b` are the same
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (meta_a, meta_b) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+    if meta_a.file_type().is_symlink() || meta_b.file_type().is_symlink() {
+        return Ok(meta_a.file_type().is_symlink()
+            && meta_b.file_type().is_symlink()
+            && fs::read_link(a)? == fs::read_link(b)?);
+    }
+    if !meta_a.is_file() || !meta_b.is_file() || meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+
+    let (mut file_a, mut file_b) = (fs::File::open(a)?, fs::File::open(b)?);
+    let (mut buf_a, mut buf_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let read = file_a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        file_b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressors::CommandRegistry;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_output_format() {
+        let config = CompressionConfig::new();
+        assert_eq!(
+            output_format(Path::new("out.tar.gz"), &config).unwrap(),
+            OutputFormat::Compound(CompoundFormat::Tgz)
+        );
+        assert_eq!(
+            output_format(Path::new("out.tar"), &config).unwrap(),
+            OutputFormat::Tar
+        );
+        assert_eq!(
+            output_format(Path::new("out.zip"), &config).unwrap(),
+            OutputFormat::Zip
+        );
+        assert!(output_format(Path::new("out.tar.zst"), &config).is_err());
+
+        let registry = CommandRegistry::from_toml(
+            "[compressor.zstd]\ncompress = \"zstd -c\"\nextension = \"zst\"\n",
+        )
+        .unwrap();
+        let config = config.with_external(Some(Arc::new(registry)));
+        for name in ["out.tar.zst", "out.tzst"] {
+            assert_eq!(
+                output_format(Path::new(name), &config).unwrap(),
+                OutputFormat::External
+            );
+        }
+        assert!(output_format(Path::new("out.zst"), &config).is_err());
+    }
+
+    #[test]
+    fn test_merge_reports_collisions() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("merged");
+        fs::create_dir(&root).unwrap();
+        let a = temp_dir.path().join("a");
+        let b = temp_dir.path().join("b");
+        fs::write(&a, b"first").unwrap();
+        fs::write(&b, b"second").unwrap();
+
+        let mut merge = Merge {
+            root: root.clone(),
+            policy: CollisionPolicy::First,
+            owners: HashMap::new(),
+            collisions: Vec::new(),
+            dir_times: Vec::new(),
+            for_zip: false,
+            hard_links: 0,
+        };
+        merge.place(&a, Path::new("x"), 0, false).unwrap();
+        merge.place(&a, Path::new("x"), 1, false).unwrap();
+        assert!(merge.collisions.is_empty());
+
+        merge.place(&b, Path::new("x"), 2, false).unwrap();
+        assert_eq!(merge.collisions, vec![(PathBuf::from("x"), vec![0, 2])]);
+        assert_eq!(fs::read(root.join("x")).unwrap(), b"first");
+
+        merge.policy = CollisionPolicy::Last;
+        merge.place(&b, Path::new("y"), 0, false).unwrap();
+        merge.place(&a, Path::new("y"), 1, false).unwrap();
+        assert_eq!(fs::read(root.join("y")).unwrap(), b"first");
+    }
+}
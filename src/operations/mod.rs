@@ -1,19 +1,83 @@
+pub mod analyze;
+#[cfg(feature = "tokio")]
+pub mod r#async;
+pub mod benchmark;
+pub mod cat;
+pub mod cleanup;
 pub mod collection;
 pub mod compound;
 pub mod compress;
 pub mod decompress;
 pub mod decrypt;
 pub mod encrypt;
+pub mod estimate;
+pub mod gpg;
+pub mod grep;
+pub mod identify;
+pub mod index;
+pub mod integrity;
+pub mod listing;
+pub mod manifest;
+pub mod merge;
+pub mod recovery;
+pub mod reencrypt;
+pub mod rotate;
+pub mod sidecar;
+pub mod space;
+pub mod stats;
+pub mod tier;
+pub mod tree;
+pub mod watch;
 
 #[allow(unused_imports)]
-pub use collection::collect_and_compress;
+pub use analyze::{analyze, Analysis, DuplicateGroup, DEFAULT_TOP};
+#[allow(unused_imports)]
+pub use benchmark::{benchmark, BenchmarkResult};
+#[allow(unused_imports)]
+pub use cat::cat_member;
+#[allow(unused_imports)]
+pub use cleanup::{clean_orphans, clean_recorded_orphans};
+#[allow(unused_imports)]
+pub use collection::{collect_and_compress, collect_by_directory};
 #[allow(unused_imports)]
 pub use compound::{compress_compound, compress_compound_batch};
 #[allow(unused_imports)]
 pub use compress::{compress_file, compress_files};
 #[allow(unused_imports)]
-pub use decompress::{decompress_file, decompress_files};
+pub use decompress::{decompress_file, decompress_files, format_extension};
 #[allow(unused_imports)]
 pub use decrypt::{decrypt_file, decrypt_files};
 #[allow(unused_imports)]
-pub use encrypt::{encrypt_file, encrypt_files};
+pub use encrypt::{encrypt_file, encrypt_files, encrypt_only};
+#[allow(unused_imports)]
+pub use estimate::{estimate, Estimate, DEFAULT_SAMPLE};
+#[allow(unused_imports)]
+pub use grep::{build_pattern, grep_archive, GrepMatch, GrepOptions};
+#[allow(unused_imports)]
+pub use identify::{identify_file, Identification};
+#[allow(unused_imports)]
+pub use index::{index_path, ArchiveIndex, IndexEntry};
+#[allow(unused_imports)]
+pub use integrity::{tree_digest, verify_archive, Verification};
+#[allow(unused_imports)]
+pub use listing::list_archive;
+#[allow(unused_imports)]
+pub use merge::{merge_archives, Collision, CollisionPolicy};
+#[allow(unused_imports)]
+pub use recovery::{recovery_path, repair, write_recovery};
+#[allow(unused_imports)]
+pub use reencrypt::reencrypt_files;
+#[allow(unused_imports)]
+pub use rotate::{rotate_logs, Rotation};
+#[allow(unused_imports)]
+pub use sidecar::write_sidecar;
+#[allow(unused_imports)]
+pub use space::{check_space, estimate_extracted_size};
+#[allow(unused_imports)]
+pub use stats::{FileStats, RunStats};
+#[allow(unused_imports)]
+pub use tier::{restore_tiered_file, tier_directory, TierRules};
+#[allow(unused_imports)]
+pub use tree::render_tree;
+#[allow(unused_imports)]
+pub use watch::{watch_directory, WatchOptions};
@@ -0,0 +1,596 @@
+//! Reed-Solomon recovery data for archives (`--recovery`, `jcz repair`)
+//!
+//! The archive is cut into equal blocks, dealt round-robin into stripes of
+//! at most [`MAX_STRIPE`] so that a damaged run of neighbouring blocks is
+//! spread over every stripe. Each stripe gets parity blocks from a Cauchy
+//! matrix over GF(256): any `k` intact blocks out of a stripe's `k` data
+//! and `m` parity blocks rebuild the others. The SHA-256 of every block
+//! tells which ones are damaged.
+//!
+//! The recovery data sits next to the archive as `<archive>.jczr`:
+//!
+//! ```text
+//! "JCZRECV" version(1)
+//! parity blocks          stripe by stripe, block_size bytes each
+//! header                 JSON: sizes, percentage and block hashes
+//! header_len (u64 LE) "JCZREND\0"
+//! ```
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::core::error::{JcError, JcResult};
+use crate::utils::{debug, info};
+
+/// Appended to the archive name for its recovery data
+pub const RECOVERY_SUFFIX: &str = ".jczr";
+
+const MAGIC: &[u8; 8] = b"JCZRECV\x01";
+const TRAILER_MAGIC: &[u8; 8] = b"JCZREND\0";
+const TRAILER_LEN: u64 = 16;
+
+/// Blocks are a multiple of this and never smaller
+const MIN_BLOCK: u64 = 4096;
+/// Aim for about this many blocks, whatever the archive size
+const TARGET_BLOCKS: u64 = 1024;
+/// Most data blocks in one stripe
+const MAX_STRIPE: usize = 64;
+/// Bytes of each block processed at a time
+const SLICE: usize = 64 * 1024;
+
+/// Where the recovery data of `archive` goes
+pub fn recovery_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(RECOVERY_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Parse a `--recovery` percentage: `5%` or `5`, from 1 to 100
+pub fn parse_percent(text: &str) -> Option<u8> {
+    let number = text.strip_suffix('%').unwrap_or(text);
+    number
+        .parse::<u8>()
+        .ok()
+        .filter(|percent| (1..=100).contains(percent))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    archive: String,
+    size: u64,
+    block_size: u64,
+    percent: u8,
+    /// SHA-256 of each data block, the last one only as long as the archive
+    data: Vec<String>,
+    /// SHA-256 of each parity block, in file order
+    parity: Vec<String>,
+}
+
+/// How an archive of a given size is cut into blocks and stripes
+struct Layout {
+    size: u64,
+    block_size: u64,
+    blocks: usize,
+    stripes: usize,
+    percent: u8,
+}
+
+impl Layout {
+    fn new(size: u64, percent: u8) -> Self {
+        let block_size = size.div_ceil(TARGET_BLOCKS).div_ceil(MIN_BLOCK).max(1) * MIN_BLOCK;
+        Self::with_block_size(size, block_size, percent)
+    }
+
+    fn with_block_size(size: u64, block_size: u64, percent: u8) -> Self {
+        let blocks = size.div_ceil(block_size) as usize;
+        Layout {
+            size,
+            block_size,
+            blocks,
+            stripes: blocks.div_ceil(MAX_STRIPE),
+            percent,
+        }
+    }
+
+    /// Data blocks of stripe `stripe`
+    fn members(&self, stripe: usize) -> Vec<usize> {
+        (stripe..self.blocks).step_by(self.stripes).collect()
+    }
+
+    /// Parity blocks of a stripe of `data` blocks: at least one
+    fn parity_count(&self, data: usize) -> usize {
+        (data * self.percent as usize).div_ceil(100).max(1)
+    }
+
+    /// Number of the first parity block of each stripe, and the total
+    fn parity_starts(&self) -> (Vec<usize>, usize) {
+        let mut starts = Vec::with_capacity(self.stripes);
+        let mut total = 0;
+        for stripe in 0..self.stripes {
+            starts.push(total);
+            total += self.parity_count(self.members(stripe).len());
+        }
+        (starts, total)
+    }
+
+    /// Bytes of data block `block` inside the archive
+    fn block_len(&self, block: usize) -> usize {
+        (self.size - block as u64 * self.block_size).min(self.block_size) as usize
+    }
+
+    fn data_offset(&self, block: usize) -> u64 {
+        block as u64 * self.block_size
+    }
+
+    fn parity_offset(&self, parity: usize) -> u64 {
+        MAGIC.len() as u64 + parity as u64 * self.block_size
+    }
+}
+
+/// Write recovery data for `archive` able to rebuild `percent` percent of
+/// it, returning its path
+pub fn write_recovery(archive: &Path, percent: u8) -> JcResult<PathBuf> {
+    let mut input = File::open(archive)?;
+    let layout = Layout::new(input.metadata()?.len(), percent);
+    let (starts, total) = layout.parity_starts();
+    debug!(
+        "Recovery data for {}: {} blocks of {} bytes, {} stripes, {} parity blocks",
+        archive.display(),
+        layout.blocks,
+        layout.block_size,
+        layout.stripes,
+        total
+    );
+
+    let data = (0..layout.blocks)
+        .map(|block| block_hash(&mut input, &layout, block))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let path = recovery_path(archive);
+    let mut out = File::create(&path)?;
+    out.write_all(MAGIC)?;
+    let mut parity = Vec::with_capacity(total);
+    let mut slice = vec![0u8; SLICE];
+    for (stripe, &first) in starts.iter().enumerate() {
+        let members = layout.members(stripe);
+        let matrix = cauchy(layout.parity_count(members.len()), members.len());
+        let mut hashers = vec![Sha256::new(); matrix.len()];
+        for column in (0..layout.block_size as usize).step_by(SLICE) {
+            let len = SLICE.min(layout.block_size as usize - column);
+            let mut sums = vec![vec![0u8; len]; matrix.len()];
+            for (j, &block) in members.iter().enumerate() {
+                read_padded(
+                    &mut input,
+                    layout.data_offset(block) + column as u64,
+                    &mut slice[..len],
+                )?;
+                for (sum, row) in sums.iter_mut().zip(&matrix) {
+                    mul_add(sum, &slice[..len], row[j]);
+                }
+            }
+            for (i, sum) in sums.iter().enumerate() {
+                out.seek(SeekFrom::Start(
+                    layout.parity_offset(first + i) + column as u64,
+                ))?;
+                out.write_all(sum)?;
+                hashers[i].update(sum);
+            }
+        }
+        parity.extend(hashers.into_iter().map(|h| hex(&h.finalize())));
+    }
+
+    let header = Header {
+        archive: archive
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size: layout.size,
+        block_size: layout.block_size,
+        percent,
+        data,
+        parity,
+    };
+    let json = serde_json::to_vec(&header)
+        .map_err(|e| JcError::Other(format!("Failed to encode recovery header: {}", e)))?;
+    out.seek(SeekFrom::Start(layout.parity_offset(total)))?;
+    out.write_all(&json)?;
+    out.write_all(&(json.len() as u64).to_le_bytes())?;
+    out.write_all(TRAILER_MAGIC)?;
+    out.sync_all()?;
+    Ok(path)
+}
+
+/// Check `archive` against its recovery data and rebuild the damaged
+/// blocks in place, returning how many there were
+pub fn repair(archive: &Path) -> JcResult<usize> {
+    let path = recovery_path(archive);
+    let mut recovery = File::open(&path).map_err(|_| JcError::FileNotFound(path.clone()))?;
+    let header = read_header(&mut recovery)?;
+    if header.block_size == 0 {
+        return Err(corrupt("zero block size"));
+    }
+    let layout = Layout::with_block_size(header.size, header.block_size, header.percent);
+    let (starts, total) = layout.parity_starts();
+    if header.data.len() != layout.blocks || header.parity.len() != total {
+        return Err(corrupt("block counts do not match"));
+    }
+
+    let mut input = File::open(archive).map_err(|_| JcError::FileNotFound(archive.into()))?;
+    let actual_size = input.metadata()?.len();
+    let mut damaged = Vec::new();
+    for block in 0..layout.blocks {
+        if block_hash(&mut input, &layout, block)? != header.data[block] {
+            damaged.push(block);
+        }
+    }
+    if damaged.is_empty() && actual_size == layout.size {
+        return Ok(0);
+    }
+    info!(
+        "{}: {} of {} blocks damaged",
+        archive.display(),
+        damaged.len(),
+        layout.blocks
+    );
+
+    // Work on a copy so a failed repair leaves the archive as it was
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = archive.with_file_name(format!(".{}.jcz-repair", name));
+    fs::copy(archive, &temp)?;
+    let result = rebuild(
+        &layout,
+        &header,
+        &starts,
+        &damaged,
+        &mut input,
+        &mut recovery,
+        &temp,
+    )
+    .and_then(|()| {
+        fs::rename(&temp, archive)?;
+        Ok(damaged.len())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Write the `damaged` data blocks, rebuilt from the intact ones and the
+/// parity, into `temp`
+fn rebuild(
+    layout: &Layout,
+    header: &Header,
+    starts: &[usize],
+    damaged: &[usize],
+    input: &mut File,
+    recovery: &mut File,
+    temp: &Path,
+) -> JcResult<()> {
+    let mut out = OpenOptions::new().write(true).open(temp)?;
+    out.set_len(layout.size)?;
+
+    let mut slice = vec![0u8; SLICE];
+    for (stripe, &first) in starts.iter().enumerate() {
+        let members = layout.members(stripe);
+        let lost: Vec<usize> = (0..members.len())
+            .filter(|&j| damaged.contains(&members[j]))
+            .collect();
+        if lost.is_empty() {
+            continue;
+        }
+
+        // Rows of the code that are still intact, data blocks first
+        let k = members.len();
+        let matrix = cauchy(layout.parity_count(k), k);
+        let mut rows: Vec<Source> = (0..k)
+            .filter(|j| !lost.contains(j))
+            .map(Source::Data)
+            .collect();
+        for i in 0..matrix.len() {
+            if rows.len() == k {
+                break;
+            }
+            if parity_hash(recovery, layout, first + i)? == header.parity[first + i] {
+                rows.push(Source::Parity(i));
+            }
+        }
+        if rows.len() < k {
+            return Err(JcError::IntegrityFailed(format!(
+                "Too damaged to repair: {} of {} blocks lost in one stripe, recovery data covers {}",
+                lost.len(),
+                k,
+                matrix.len()
+            )));
+        }
+
+        let system: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|row| match *row {
+                Source::Data(j) => (0..k).map(|c| u8::from(c == j)).collect(),
+                Source::Parity(i) => matrix[i].clone(),
+            })
+            .collect();
+        let inverse = invert(system).ok_or_else(|| corrupt("singular recovery matrix"))?;
+
+        for column in (0..layout.block_size as usize).step_by(SLICE) {
+            let len = SLICE.min(layout.block_size as usize - column);
+            let mut rebuilt = vec![vec![0u8; len]; lost.len()];
+            for (r, row) in rows.iter().enumerate() {
+                match *row {
+                    Source::Data(j) => read_padded(
+                        input,
+                        layout.data_offset(members[j]) + column as u64,
+                        &mut slice[..len],
+                    )?,
+                    Source::Parity(i) => read_padded(
+                        recovery,
+                        layout.parity_offset(first + i) + column as u64,
+                        &mut slice[..len],
+                    )?,
+                }
+                for (out, &j) in rebuilt.iter_mut().zip(&lost) {
+                    mul_add(out, &slice[..len], inverse[j][r]);
+                }
+            }
+            for (data, &j) in rebuilt.iter().zip(&lost) {
+                let block = members[j];
+                let end = layout.block_len(block).saturating_sub(column).min(len);
+                if end > 0 {
+                    out.seek(SeekFrom::Start(layout.data_offset(block) + column as u64))?;
+                    out.write_all(&data[..end])?;
+                }
+            }
+        }
+    }
+    out.sync_all()?;
+    drop(out);
+
+    let mut check = File::open(temp)?;
+    for &block in damaged {
+        if block_hash(&mut check, layout, block)? != header.data[block] {
+            return Err(JcError::IntegrityFailed(format!(
+                "Block {} does not match its hash after repair",
+                block
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Where one row of a stripe's equations comes from
+#[derive(Clone, Copy)]
+enum Source {
+    Data(usize),
+    Parity(usize),
+}
+
+fn read_header(recovery: &mut File) -> JcResult<Header> {
+    let len = recovery.metadata()?.len();
+    let mut magic = [0u8; 8];
+    if len < MAGIC.len() as u64 + TRAILER_LEN || recovery.read_exact(&mut magic).is_err() {
+        return Err(corrupt("truncated"));
+    }
+    if &magic != MAGIC {
+        return Err(corrupt("not jcz recovery data"));
+    }
+    recovery.seek(SeekFrom::Start(len - TRAILER_LEN))?;
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    recovery.read_exact(&mut trailer)?;
+    if &trailer[8..] != TRAILER_MAGIC {
+        return Err(corrupt("missing trailer"));
+    }
+    let header_len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    if header_len > len - TRAILER_LEN - MAGIC.len() as u64 {
+        return Err(corrupt("header out of range"));
+    }
+    recovery.seek(SeekFrom::Start(len - TRAILER_LEN - header_len))?;
+    let mut json = vec![0u8; header_len as usize];
+    recovery.read_exact(&mut json)?;
+    serde_json::from_slice(&json).map_err(|e| corrupt(&e.to_string()))
+}
+
+/// SHA-256 of data block `block` as `file` has it now
+fn block_hash(file: &mut File, layout: &Layout, block: usize) -> io::Result<String> {
+    let mut buf = vec![0u8; layout.block_len(block)];
+    read_padded(file, layout.data_offset(block), &mut buf)?;
+    Ok(hex(&Sha256::digest(&buf)))
+}
+
+fn parity_hash(file: &mut File, layout: &Layout, parity: usize) -> io::Result<String> {
+    let mut buf = vec![0u8; layout.block_size as usize];
+    read_padded(file, layout.parity_offset(parity), &mut buf)?;
+    Ok(hex(&Sha256::digest(&buf)))
+}
+
+/// Fill `buf` from `offset`, with zeros past the end of the file
+fn read_padded(file: &mut File, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    buf[filled..].fill(0);
+    Ok(())
+}
+
+fn corrupt(reason: &str) -> JcError {
+    JcError::Other(format!("Corrupt recovery data: {}", reason))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Exponent and logarithm tables of GF(256) with polynomial 0x11d
+const fn gf_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+static GF: ([u8; 512], [u8; 256]) = gf_tables();
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (exp, log) = &GF;
+    exp[log[a as usize] as usize + log[b as usize] as usize]
+}
+
+fn gf_inv(a: u8) -> u8 {
+    let (exp, log) = &GF;
+    exp[255 - log[a as usize] as usize]
+}
+
+/// `dst += coefficient * src`, byte by byte
+fn mul_add(dst: &mut [u8], src: &[u8], coefficient: u8) {
+    match coefficient {
+        0 => {}
+        1 => dst.iter_mut().zip(src).for_each(|(d, s)| *d ^= s),
+        _ => {
+            let (exp, log) = &GF;
+            let shift = log[coefficient as usize] as usize;
+            for (d, &s) in dst.iter_mut().zip(src) {
+                if s != 0 {
+                    *d ^= exp[log[s as usize] as usize + shift];
+                }
+            }
+        }
+    }
+}
+
+/// The `m` by `k` Cauchy matrix `1 / (x_i + y_j)` with `x_i = k + i` and
+/// `y_j = j`; every square submatrix of it, stacked with identity rows,
+/// is invertible
+fn cauchy(m: usize, k: usize) -> Vec<Vec<u8>> {
+    (0..m)
+        .map(|i| (0..k).map(|j| gf_inv((k + i) as u8 ^ j as u8)).collect())
+        .collect()
+}
+
+/// Gauss-Jordan inverse of a square matrix, None when singular
+fn invert(mut matrix: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<u8>> = (0..n)
+        .map(|i| (0..n).map(|j| u8::from(i == j)).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n).find(|&row| matrix[row][col] != 0)?;
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let scale = gf_inv(matrix[col][col]);
+        for j in 0..n {
+            matrix[col][j] = gf_mul(matrix[col][j], scale);
+            inverse[col][j] = gf_mul(inverse[col][j], scale);
+        }
+        for row in 0..n {
+            let factor = matrix[row][col];
+            if row != col && factor != 0 {
+                for j in 0..n {
+                    matrix[row][j] ^= gf_mul(factor, matrix[col][j]);
+                    inverse[row][j] ^= gf_mul(factor, inverse[col][j]);
+                }
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample(len: usize) -> Vec<u8> {
+        let mut state = 0x9e37_79b9_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gf_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("5%"), Some(5));
+        assert_eq!(parse_percent("100"), Some(100));
+        assert_eq!(parse_percent("0%"), None);
+        assert_eq!(parse_percent("101"), None);
+        assert_eq!(parse_percent("five"), None);
+    }
+
+    #[test]
+    fn test_repair_scattered_and_contiguous_damage() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("data.bin");
+        let original = sample(300_000);
+        fs::write(&archive, &original).unwrap();
+        write_recovery(&archive, 10).unwrap();
+        assert_eq!(repair(&archive).unwrap(), 0);
+
+        // One damaged run of neighbouring blocks, spread over the stripes
+        let mut damaged = original.clone();
+        damaged[8_000..20_000].iter_mut().for_each(|b| *b = !*b);
+        fs::write(&archive, &damaged).unwrap();
+        assert!(repair(&archive).unwrap() > 0);
+        assert_eq!(fs::read(&archive).unwrap(), original);
+
+        // Truncation loses the tail
+        fs::write(&archive, &original[..295_000]).unwrap();
+        assert!(repair(&archive).unwrap() > 0);
+        assert_eq!(fs::read(&archive).unwrap(), original);
+    }
+
+    #[test]
+    fn test_too_much_damage_leaves_archive_alone() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("data.bin");
+        let original = sample(100_000);
+        fs::write(&archive, &original).unwrap();
+        write_recovery(&archive, 5).unwrap();
+
+        let mut damaged = original.clone();
+        damaged[..50_000].fill(0);
+        fs::write(&archive, &damaged).unwrap();
+        assert!(matches!(repair(&archive), Err(JcError::IntegrityFailed(_))));
+        assert_eq!(fs::read(&archive).unwrap(), damaged);
+    }
+}
@@ -0,0 +1,190 @@
+//! Key rotation for `.jcze` files (`jcz reencrypt`)
+//!
+//! The compressed payload is never touched. For RSA to RSA only the
+//! wrapped symmetric key changes; any other combination (a new password,
+//! or switching between password and RSA) decrypts the payload and
+//! encrypts it again under a fresh key. The integrity tag is carried over.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::core::config::{DecryptionMethod, EncryptionMethod, SecretPurpose, Secrets};
+use crate::core::error::{JcError, JcResult};
+use crate::crypto::{EncryptedContainer, EncryptionMetadata, RsaEncryption};
+use crate::operations::decrypt::{is_encrypted_file, open_container};
+use crate::operations::encrypt::{seal_data, write_shares};
+use crate::utils::{debug, error, info};
+
+/// Password asked for at most once, by the first file that needs it
+struct CachedPassword<'a> {
+    secrets: Option<&'a Secrets>,
+    purpose: SecretPurpose,
+    value: Mutex<Option<Zeroizing<String>>>,
+}
+
+impl<'a> CachedPassword<'a> {
+    fn new(secrets: Option<&'a Secrets>, purpose: SecretPurpose) -> Self {
+        Self {
+            secrets,
+            purpose,
+            value: Mutex::new(None),
+        }
+    }
+
+    fn get(&self) -> JcResult<Zeroizing<String>> {
+        // Held while prompting, so parallel files wait for the one answer
+        let mut value = self.value.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(ref password) = *value {
+            return Ok(password.clone());
+        }
+        let password = self
+            .secrets
+            .ok_or_else(|| JcError::Other("Re-encryption needs a password provider".to_string()))?
+            .password(self.purpose)?;
+        *value = Some(password.clone());
+        Ok(password)
+    }
+}
+
+/// Re-encrypt `.jcze` files in place for `encryption_method`, in parallel
+///
+/// `decryption_method` opens RSA-encrypted files; password-encrypted ones
+/// use the decryption password from `secrets`. Each password is asked
+/// once for the whole batch, the current one first.
+pub fn reencrypt_files(
+    files: Vec<PathBuf>,
+    decryption_method: Option<&DecryptionMethod>,
+    encryption_method: &EncryptionMethod,
+    secrets: Option<&Secrets>,
+) -> Vec<JcResult<PathBuf>> {
+    info!("Re-encrypting {} files", files.len());
+
+    let old_password = CachedPassword::new(secrets, SecretPurpose::Decrypt);
+    let new_password = CachedPassword::new(secrets, SecretPurpose::Encrypt);
+
+    // Without a private key only password-encrypted files can be opened
+    let mut upfront = Ok(());
+    if decryption_method.is_none() {
+        upfront = old_password.get().map(drop);
+    }
+    if upfront.is_ok() && matches!(encryption_method, EncryptionMethod::Password) {
+        upfront = new_password.get().map(drop);
+    }
+    if let Err(e) = upfront {
+        let err_msg = format!("{}", e);
+        return files
+            .iter()
+            .map(|_| Err(JcError::Other(err_msg.clone())))
+            .collect();
+    }
+
+    files
+        .par_iter()
+        .map(|file| {
+            reencrypt_file(
+                file,
+                decryption_method,
+                encryption_method,
+                &old_password,
+                &new_password,
+            )
+            .map(|()| file.clone())
+            .inspect_err(|e| error!("Failed to re-encrypt {}: {}", file.display(), e))
+        })
+        .collect()
+}
+
+fn reencrypt_file(
+    file: &Path,
+    decryption_method: Option<&DecryptionMethod>,
+    encryption_method: &EncryptionMethod,
+    old_password: &CachedPassword,
+    new_password: &CachedPassword,
+) -> JcResult<()> {
+    if !is_encrypted_file(file) {
+        return Err(JcError::InvalidExtension(
+            file.to_path_buf(),
+            "jcze".to_string(),
+        ));
+    }
+    let container = EncryptedContainer::read_from_file(file)?;
+
+    let mut shares = Vec::new();
+    let rewrapped = match (&container.metadata, decryption_method, encryption_method) {
+        (
+            EncryptionMetadata::Rsa {
+                encrypted_key,
+                nonce,
+            },
+            Some(DecryptionMethod::Rsa { private_key_path }),
+            EncryptionMethod::Rsa { public_key_path },
+        ) => {
+            debug!("Rewrapping the key of {}", file.display());
+            let symmetric_key = Zeroizing::new(RsaEncryption::decrypt_symmetric_key(
+                encrypted_key,
+                private_key_path,
+            )?);
+            let encrypted_key =
+                RsaEncryption::encrypt_symmetric_key(&symmetric_key, public_key_path)?;
+            EncryptedContainer {
+                metadata: EncryptionMetadata::Rsa {
+                    encrypted_key,
+                    nonce: *nonce,
+                },
+                ..container
+            }
+        }
+        _ => {
+            debug!("Re-encrypting the payload of {}", file.display());
+            let data = Zeroizing::new(open_container(&container, decryption_method, || {
+                old_password.get()
+            })?);
+            let password = match encryption_method {
+                EncryptionMethod::Password => Some(new_password.get()?),
+                EncryptionMethod::Rsa { .. }
+                | EncryptionMethod::Threshold { .. }
+                | EncryptionMethod::Keyfile { .. }
+                | EncryptionMethod::X25519 { .. }
+                | EncryptionMethod::Gpg { .. } => None,
+            };
+            let (sealed, new_shares) = seal_data(
+                &data,
+                encryption_method,
+                password.as_ref().map(|p| p.as_str()),
+                container.pad_to,
+                container.integrity,
+            )?;
+            shares = new_shares;
+            sealed
+        }
+    };
+
+    replace_file(file, &rewrapped.to_bytes()?)?;
+    write_shares(file, &shares)?;
+    info!("Re-encrypted file: {}", file.display());
+    Ok(())
+}
+
+/// Replace `path` with `bytes` atomically, keeping its permissions, so an
+/// interrupted rotation never leaves a truncated archive
+fn replace_file(path: &Path, bytes: &[u8]) -> JcResult<()> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let permissions = fs::metadata(path)?.permissions();
+
+    let mut staged = tempfile::Builder::new()
+        .prefix(".jcz-tmp-")
+        .tempfile_in(dir)?;
+    staged.write_all(bytes)?;
+    staged.as_file().sync_all()?;
+    fs::set_permissions(staged.path(), permissions)?;
+    staged.persist(path).map_err(|e| JcError::Io(e.error))?;
+    Ok(())
+}
@@ -0,0 +1,192 @@
+//! Archiving rotated logs (`jcz rotate`)
+//!
+//! The files of a directory whose names match a glob are added to a dated
+//! archive, written with the same writers as `jcz merge`. When the archive
+//! for the period already exists, they join what it holds; a file it
+//! already holds with the same content is not a collision, so a rotation
+//! interrupted before deleting anything can simply be run again. The new
+//! archive is unpacked in scratch space and every file compared with its
+//! original before it replaces the old one and the originals are deleted,
+//! so a failure at any step leaves the logs where they were.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compressors::TarCompressor;
+use crate::core::config::CompressionConfig;
+use crate::core::error::{JcError, JcResult};
+use crate::operations::merge::{check_output, merge_archives, unpack, CollisionPolicy};
+use crate::operations::sidecar::sha256_file;
+use crate::operations::tier::glob_match;
+use crate::utils::timestamp::format_local;
+use crate::utils::{create_scratch_dir, info, move_atomic, remove_original, sync_output};
+
+/// Files gathered by `rotate_logs` and the archive they went into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// Archive written (or, for a dry run, that would be)
+    pub archive: PathBuf,
+
+    /// Files added, sorted by name
+    pub files: Vec<PathBuf>,
+}
+
+/// Archive the files of `dir` matching `pattern` (`*` and `?`) into
+/// `into`, a path whose strftime fields (`%Y`, `%m`, ...) are filled in
+/// from the local time, then delete them unless `keep`
+///
+/// The archive format follows its extension, as for `merge_archives`.
+/// Returns `None` when no file matches. With `dry_run`, nothing is
+/// written or deleted.
+pub fn rotate_logs(
+    dir: &Path,
+    pattern: &str,
+    into: &str,
+    keep: bool,
+    dry_run: bool,
+    config: &CompressionConfig,
+) -> JcResult<Option<Rotation>> {
+    let archive = PathBuf::from(format_local(into)?);
+    check_output(&archive, config)?;
+
+    let files = matching_files(dir, pattern, &archive)?;
+    if files.is_empty() {
+        info!("No files in {} match {}", dir.display(), pattern);
+        return Ok(None);
+    }
+    let rotation = Rotation { archive, files };
+    if dry_run {
+        return Ok(Some(rotation));
+    }
+
+    let out_dir = rotation
+        .archive
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(out_dir)?;
+    let scratch = create_scratch_dir(config.temp_dir.as_deref(), Some(out_dir))?;
+
+    // The new files as one tar, laid over the existing archive if any
+    let entries: Vec<(PathBuf, OsString)> = rotation
+        .files
+        .iter()
+        .map(|file| (file.clone(), file_name(file)))
+        .collect();
+    let tar_config = CompressionConfig {
+        level: 0,
+        move_to: None,
+        encryption: None,
+        listed_incremental: None,
+        ..config.clone()
+    };
+    let rotated = scratch.path().join("rotated.tar");
+    TarCompressor::new().create_archive(&rotated, &entries, &tar_config)?;
+
+    let mut inputs = Vec::new();
+    if rotation.archive.exists() {
+        info!("Adding to {}", rotation.archive.display());
+        inputs.push(rotation.archive.clone());
+    }
+    inputs.push(rotated.clone());
+    let staged = scratch.path().join(file_name(&rotation.archive));
+    merge_archives(&staged, &inputs, CollisionPolicy::Error, config)?;
+    fs::remove_file(&rotated)?;
+
+    verify_rotation(&staged, &rotation.files, scratch.path(), config)?;
+    move_atomic(&staged, &rotation.archive)?;
+    sync_output(&rotation.archive, config.durability)?;
+
+    if !keep {
+        for file in &rotation.files {
+            remove_original(file, config.trash)?;
+        }
+    }
+    info!(
+        "Rotated {} files into {}",
+        rotation.files.len(),
+        rotation.archive.display()
+    );
+    Ok(Some(rotation))
+}
+
+/// Regular files directly in `dir` whose names match `pattern`, sorted,
+/// leaving out `archive` itself
+fn matching_files(dir: &Path, pattern: &str, archive: &Path) -> JcResult<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Err(JcError::NotADirectory(dir.to_path_buf()));
+    }
+    let archive = fs::canonicalize(archive).ok();
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        if !glob_match(pattern, &entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        if archive.is_some() && fs::canonicalize(&path).ok() == archive {
+            continue;
+        }
+        files.push(path);
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Unpack `archive` under `scratch` and check it holds each of `files`
+/// byte for byte
+fn verify_rotation(
+    archive: &Path,
+    files: &[PathBuf],
+    scratch: &Path,
+    config: &CompressionConfig,
+) -> JcResult<()> {
+    // Layers are peeled apart from `archive`, which tools like gzip would
+    // otherwise consume
+    let peeled = scratch.join("peeled");
+    let unpacked = scratch.join("verify");
+    fs::create_dir(&peeled)?;
+    fs::create_dir(&unpacked)?;
+    unpack(archive, &unpacked, &peeled, config)?;
+
+    for file in files {
+        let copy = unpacked.join(file_name(file));
+        if !copy.is_file() || sha256_file(&copy)? != sha256_file(file)? {
+            return Err(JcError::IntegrityFailed(format!(
+                "The rotated archive does not hold {} as it is; nothing was deleted",
+                file.display()
+            )));
+        }
+    }
+    fs::remove_dir_all(&peeled)?;
+    fs::remove_dir_all(&unpacked)?;
+    Ok(())
+}
+
+fn file_name(path: &Path) -> OsString {
+    path.file_name().unwrap_or_default().to_os_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_matching_files_skips_archive_and_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        for name in ["app.log", "app.log.2", "app.log.1", "app.log.tar.gz"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        fs::create_dir(dir.join("app.log.d")).unwrap();
+
+        let files = matching_files(dir, "app.log.*", &dir.join("app.log.tar.gz")).unwrap();
+        assert_eq!(files, vec![dir.join("app.log.1"), dir.join("app.log.2")]);
+    }
+}
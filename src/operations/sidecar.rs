@@ -0,0 +1,310 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::core::config::{CompressionConfig, EncryptionMethod};
+use crate::core::error::{JcError, JcResult};
+use crate::utils::bufio::buffered_reader;
+use crate::utils::{debug, info};
+
+/// Suffix appended to an archive's filename to name its sidecar
+pub const SIDECAR_SUFFIX: &str = ".jcz.json";
+
+/// Metadata kept beside an archive instead of inside it
+#[derive(Debug, Serialize)]
+pub struct Sidecar {
+    /// Archive filename (the sidecar sits in the same directory)
+    pub archive: String,
+
+    /// Extension chain of the archive, e.g. `tar.gz` or `gz.jcze`
+    pub format: String,
+
+    /// Archive size in bytes
+    pub size: u64,
+
+    /// SHA-256 of the archive bytes
+    pub sha256: String,
+
+    /// Integrity tag of the plaintext tree inside every layer (`jcz verify`)
+    pub tree_sha256: String,
+
+    /// Creation time (RFC 3339)
+    pub created: String,
+
+    /// Version of jcz that wrote the archive
+    pub jcz_version: String,
+
+    /// Encryption parameters; keys, salts and nonces are never recorded
+    pub encryption: Option<SidecarEncryption>,
+
+    /// Entries stored in the archive, in archive order
+    pub manifest: Vec<ManifestEntry>,
+}
+
+/// Non-secret description of how an archive was encrypted
+#[derive(Debug, Serialize)]
+pub struct SidecarEncryption {
+    pub method: &'static str,
+    pub cipher: &'static str,
+    pub key_derivation: &'static str,
+
+    /// SHA-256 of the public key file, to tell which private key opens it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key_sha256: Option<String>,
+}
+
+/// One file, directory or symlink recorded in the manifest
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Write `<archive>.jcz.json` describing `archive` and its sources
+///
+/// `sources` pairs each input path with the name it is stored under;
+/// `tree_sha256` is the archive's integrity tag.
+pub fn write_sidecar(
+    archive: &Path,
+    sources: &[(PathBuf, PathBuf)],
+    tree_sha256: &str,
+    config: &CompressionConfig,
+) -> JcResult<PathBuf> {
+    let archive_name = archive
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| JcError::Other(format!("Invalid archive name: {}", archive.display())))?;
+
+    let mut manifest = Vec::new();
+    for (source, name) in sources {
+        let mut followed = config.dereference.then(Vec::new);
+        collect_manifest(source, name, &mut followed, &mut manifest)?;
+    }
+
+    let sidecar = Sidecar {
+        archive: archive_name.to_string(),
+        format: format_chain(archive_name),
+        size: fs::metadata(archive)?.len(),
+        sha256: sha256_file(archive)?,
+        tree_sha256: tree_sha256.to_string(),
+        created: chrono::Utc::now().to_rfc3339(),
+        jcz_version: env!("CARGO_PKG_VERSION").to_string(),
+        encryption: config
+            .encryption
+            .as_ref()
+            .map(describe_encryption)
+            .transpose()?,
+        manifest,
+    };
+
+    let mut sidecar_path = archive.as_os_str().to_owned();
+    sidecar_path.push(SIDECAR_SUFFIX);
+    let sidecar_path = PathBuf::from(sidecar_path);
+
+    let json = serde_json::to_string_pretty(&sidecar)
+        .map_err(|e| JcError::Other(format!("Failed to serialize sidecar: {}", e)))?;
+    fs::write(&sidecar_path, json + "\n")?;
+
+    info!("Wrote metadata sidecar: {}", sidecar_path.display());
+    Ok(sidecar_path)
+}
+
+/// `followed` holds the canonical directories being walked when symlinks
+/// are followed (`--dereference`), to skip loops the way the archive does
+fn collect_manifest(
+    source: &Path,
+    name: &Path,
+    followed: &mut Option<Vec<PathBuf>>,
+    out: &mut Vec<ManifestEntry>,
+) -> JcResult<()> {
+    let mut metadata = fs::symlink_metadata(source)?;
+    if followed.is_some() && metadata.file_type().is_symlink() {
+        // Dangling links stay links, as in the archive
+        metadata = fs::metadata(source).unwrap_or(metadata);
+    }
+    let path = name.to_string_lossy().to_string();
+
+    if metadata.file_type().is_symlink() {
+        out.push(ManifestEntry {
+            path,
+            kind: "symlink",
+            size: None,
+            sha256: None,
+            target: Some(fs::read_link(source)?.to_string_lossy().to_string()),
+        });
+    } else if metadata.is_dir() {
+        let canonical = source.canonicalize()?;
+        if followed.as_ref().is_some_and(|a| a.contains(&canonical)) {
+            return Ok(());
+        }
+
+        out.push(ManifestEntry {
+            path,
+            kind: "dir",
+            size: None,
+            sha256: None,
+            target: None,
+        });
+
+        // Same order the tar backend writes entries in
+        let mut children: Vec<_> = fs::read_dir(source)?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<Result<_, _>>()?;
+        children.sort();
+        if let Some(ancestors) = followed.as_mut() {
+            ancestors.push(canonical);
+        }
+        for child in children {
+            collect_manifest(&source.join(&child), &name.join(&child), followed, out)?;
+        }
+        if let Some(ancestors) = followed.as_mut() {
+            ancestors.pop();
+        }
+    } else {
+        out.push(ManifestEntry {
+            path,
+            kind: "file",
+            size: Some(metadata.len()),
+            sha256: Some(sha256_file(source)?),
+            target: None,
+        });
+    }
+
+    Ok(())
+}
+
+fn describe_encryption(method: &EncryptionMethod) -> JcResult<SidecarEncryption> {
+    Ok(match method {
+        EncryptionMethod::Password => SidecarEncryption {
+            method: "password",
+            cipher: "AES-256-GCM",
+            key_derivation: "Argon2id",
+            public_key_sha256: None,
+        },
+        EncryptionMethod::Rsa { public_key_path } => SidecarEncryption {
+            method: "rsa",
+            cipher: "AES-256-GCM",
+            key_derivation: "RSA-OAEP-SHA256",
+            public_key_sha256: Some(sha256_file(public_key_path)?),
+        },
+        EncryptionMethod::Threshold { .. } => SidecarEncryption {
+            method: "threshold",
+            cipher: "AES-256-GCM",
+            key_derivation: "Shamir-GF256",
+            public_key_sha256: None,
+        },
+        EncryptionMethod::X25519 { public_key_path } => SidecarEncryption {
+            method: "x25519",
+            cipher: "AES-256-GCM",
+            key_derivation: "X25519-HKDF-SHA256",
+            public_key_sha256: Some(sha256_file(public_key_path)?),
+        },
+        EncryptionMethod::Keyfile { .. } => SidecarEncryption {
+            method: "keyfile",
+            cipher: "AES-256-GCM",
+            key_derivation: "none",
+            public_key_sha256: None,
+        },
+        EncryptionMethod::Gpg { .. } => SidecarEncryption {
+            method: "gpg",
+            cipher: "OpenPGP",
+            key_derivation: "OpenPGP",
+            public_key_sha256: None,
+        },
+    })
+}
+
+/// Extensions after the first known archive/compression extension
+fn format_chain(name: &str) -> String {
+    let parts: Vec<&str> = name.split('.').skip(1).collect();
+    let known = |ext: &&str| {
+        matches!(
+            ext.to_lowercase().as_str(),
+            "tar"
+                | "gz"
+                | "bz2"
+                | "xz"
+                | "zip"
+                | "cpio"
+                | "ar"
+                | "tgz"
+                | "tbz2"
+                | "tbz"
+                | "tb2"
+                | "txz"
+                | "jcze"
+                | "gpg"
+        )
+    };
+    let start = parts.iter().position(known).unwrap_or(parts.len());
+    parts[start..].join(".")
+}
+
+pub(crate) fn sha256_file(path: &Path) -> JcResult<String> {
+    let digest = sha256_reader(buffered_reader(File::open(path)?))?;
+    debug!("Hashed {}", path.display());
+    Ok(digest)
+}
+
+/// Hex SHA-256 of everything `reader` yields
+pub(crate) fn sha256_reader(mut reader: impl Read) -> JcResult<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(JcError::Io(e)),
+        };
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_chain() {
+        assert_eq!(format_chain("project.tar.gz"), "tar.gz");
+        assert_eq!(format_chain("notes.v2.txt.gz.jcze"), "gz.jcze");
+        assert_eq!(format_chain("file_20250101.tgz"), "tgz");
+    }
+
+    #[test]
+    fn test_rsa_encryption_records_only_key_fingerprint() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let key = dir.path().join("public.pem");
+        fs::write(&key, b"not really a key").unwrap();
+
+        let info = describe_encryption(&EncryptionMethod::Rsa {
+            public_key_path: key,
+        })
+        .unwrap();
+        let json = serde_json::to_value(&info).unwrap();
+
+        assert_eq!(json["method"], "rsa");
+        assert_eq!(json["public_key_sha256"].as_str().unwrap().len(), 64);
+        assert_eq!(json.as_object().unwrap().len(), 4);
+    }
+}
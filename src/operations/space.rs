@@ -0,0 +1,169 @@
+//! Disk-space pre-flight check for decompression
+//!
+//! Archives are unpacked in scratch space and then copied to their
+//! destination, so both must have room before extraction starts; running
+//! out halfway leaves a partially extracted tree behind.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::compressors::{detect_compound_format, detect_format, gzip, tar, xz, zip};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::operations::stats::format_size;
+use crate::utils::{available_space, debug, same_filesystem};
+
+/// Estimated size of what `input` extracts to, from the sizes its format
+/// records: the gzip trailer, the xz index, the zip central directory or
+/// the tar entry headers
+///
+/// For compound archives this is the size of the inner tar. `None` when
+/// the format records no size (bzip2, cpio, ar, packages).
+pub fn estimate_extracted_size(input: &Path) -> JcResult<Option<u64>> {
+    match detect_format(input) {
+        Some(CompressionFormat::Gzip) => gzip::uncompressed_size(input),
+        Some(CompressionFormat::Xz) => xz::uncompressed_size(input),
+        Some(CompressionFormat::Zip) => zip::uncompressed_size(input),
+        Some(CompressionFormat::Tar) => tar::uncompressed_size(input),
+        _ => Ok(None),
+    }
+}
+
+/// Fail with `JcError::InsufficientSpace` unless `scratch_dir` and
+/// `dest_dir` have room to extract `input`
+///
+/// Scratch space holds a copy of the input and every layer (the inner tar
+/// and the tree of a compound archive) until the result is placed. The
+/// destination needs room only if that takes a copy rather than a rename.
+pub fn check_space(input: &Path, dest_dir: &Path, scratch_dir: &Path) -> JcResult<()> {
+    let Some(size) = estimate_extracted_size(input)? else {
+        debug!(
+            "No size recorded in {}, skipping space check",
+            input.display()
+        );
+        return Ok(());
+    };
+    let layers = if detect_compound_format(input).is_some() {
+        2
+    } else {
+        1
+    };
+    let scratch = fs::metadata(input)?
+        .len()
+        .saturating_add(size.saturating_mul(layers));
+    debug!(
+        "{} extracts to about {}",
+        input.display(),
+        format_size(size)
+    );
+
+    let placed = if same_filesystem(scratch_dir, dest_dir) {
+        0
+    } else {
+        size
+    };
+    require_space(
+        &[(scratch_dir, scratch), (dest_dir, placed)],
+        available_space,
+    )
+}
+
+/// Check each `(dir, bytes)` need against `available`, adding up the needs
+/// of directories on the same filesystem
+fn require_space(
+    needs: &[(&Path, u64)],
+    available: impl Fn(&Path) -> JcResult<u64>,
+) -> JcResult<()> {
+    let mut by_device: HashMap<u64, (&Path, u64)> = HashMap::new();
+    for &(dir, bytes) in needs {
+        // A destination that does not exist yet is created later
+        let Ok(metadata) = fs::metadata(dir) else {
+            continue;
+        };
+        by_device
+            .entry(device_id(&metadata))
+            .and_modify(|(_, total)| *total = total.saturating_add(bytes))
+            .or_insert((dir, bytes));
+    }
+
+    for (dir, needed) in by_device.into_values() {
+        let available = available(dir)?;
+        if needed > available {
+            return Err(JcError::InsufficientSpace {
+                path: dir.to_path_buf(),
+                needed,
+                available,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn device_id(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
+
+/// Without a stable volume id every directory counts as one filesystem,
+/// which can only overstate the space needed
+#[cfg(not(unix))]
+fn device_id(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_estimate_from_recorded_sizes() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("data.txt");
+        fs::write(&file, "hello world\n".repeat(1000)).unwrap();
+
+        for tool in ["gzip", "xz", "bzip2"] {
+            assert!(Command::new(tool)
+                .arg("-k")
+                .arg(&file)
+                .status()
+                .unwrap()
+                .success());
+        }
+        let estimate = |name: &str| estimate_extracted_size(&dir.path().join(name)).unwrap();
+        assert_eq!(estimate("data.txt.gz"), Some(12000));
+        assert_eq!(estimate("data.txt.xz"), Some(12000));
+        assert_eq!(estimate("data.txt.bz2"), None);
+
+        let mut builder = ::tar::Builder::new(fs::File::create(dir.path().join("a.tar")).unwrap());
+        builder.append_path_with_name(&file, "one").unwrap();
+        builder.append_path_with_name(&file, "two").unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+        assert_eq!(estimate("a.tar"), Some(24000));
+    }
+
+    #[test]
+    fn test_require_space_adds_up_same_filesystem() {
+        let dir = TempDir::new().unwrap();
+        let (scratch, dest) = (dir.path().join("scratch"), dir.path().join("dest"));
+        fs::create_dir(&scratch).unwrap();
+        fs::create_dir(&dest).unwrap();
+        let needs = [(scratch.as_path(), 600), (dest.as_path(), 500)];
+
+        assert!(require_space(&needs, |_| Ok(1100)).is_ok());
+        match require_space(&needs, |_| Ok(1000)) {
+            Err(JcError::InsufficientSpace {
+                needed, available, ..
+            }) => assert_eq!((needed, available), (1100, 1000)),
+            other => panic!("Expected InsufficientSpace, got {:?}", other),
+        }
+
+        // Missing directories are skipped
+        let missing = dir.path().join("missing");
+        assert!(require_space(&[(missing.as_path(), u64::MAX)], |_| Ok(0)).is_ok());
+    }
+}
@@ -0,0 +1,286 @@
+//! Size and timing statistics for compression runs
+//!
+//! Every batch operation reports a `FileStats` per successful input; the
+//! CLI prints them, plus a `RunStats` total, when `show_output_size` is set
+//! (`--stats`).
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::core::error::JcResult;
+
+/// Sizes and timing of one input and the file it produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStats {
+    /// Input file or directory (the package name for collections)
+    pub input: PathBuf,
+
+    /// Output archive, or extracted file or directory
+    pub output: PathBuf,
+
+    /// Uncompressed size in bytes (regular files below a directory)
+    pub original_size: u64,
+
+    /// Compressed size in bytes, including any encryption overhead
+    pub compressed_size: u64,
+
+    /// Time spent producing the output
+    pub elapsed: Duration,
+
+    /// Whether the output is the uncompressed side
+    pub decompressed: bool,
+}
+
+impl FileStats {
+    /// Stats for `output` compressed from inputs of `original_size` bytes
+    pub fn compressed(
+        input: PathBuf,
+        original_size: u64,
+        output: PathBuf,
+        elapsed: Duration,
+    ) -> JcResult<Self> {
+        let compressed_size = tree_size(&output)?;
+        Ok(FileStats {
+            input,
+            output,
+            original_size,
+            compressed_size,
+            elapsed,
+            decompressed: false,
+        })
+    }
+
+    /// Stats for `output` extracted from the archive `input` of
+    /// `compressed_size` bytes
+    ///
+    /// The archive size is taken before extraction, since the archive may
+    /// be removed afterwards.
+    pub fn decompressed(
+        input: PathBuf,
+        compressed_size: u64,
+        output: PathBuf,
+        elapsed: Duration,
+    ) -> JcResult<Self> {
+        Ok(FileStats {
+            original_size: tree_size(&output)?,
+            compressed_size,
+            input,
+            output,
+            elapsed,
+            decompressed: true,
+        })
+    }
+
+    /// Compressed size as a fraction of the original size
+    pub fn ratio(&self) -> f64 {
+        ratio(self.compressed_size, self.original_size)
+    }
+
+    /// Uncompressed bytes processed per second
+    pub fn throughput(&self) -> f64 {
+        throughput(self.original_size, self.elapsed)
+    }
+}
+
+impl fmt::Display for FileStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (from, to) = if self.decompressed {
+            (self.compressed_size, self.original_size)
+        } else {
+            (self.original_size, self.compressed_size)
+        };
+        write!(
+            f,
+            "{} -> {}: {} -> {} ({:.1}%) in {:.2}s, {}/s",
+            self.input.display(),
+            self.output.display(),
+            format_size(from),
+            format_size(to),
+            self.ratio() * 100.0,
+            self.elapsed.as_secs_f64(),
+            format_size(self.throughput() as u64)
+        )
+    }
+}
+
+/// Totals over the successful files of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunStats {
+    /// Per-file statistics, in input order
+    pub files: Vec<FileStats>,
+
+    /// Wall time of the whole run; files are processed in parallel, so
+    /// this is usually less than the sum of their times
+    pub wall_time: Duration,
+}
+
+impl RunStats {
+    pub fn new(files: Vec<FileStats>, wall_time: Duration) -> Self {
+        RunStats { files, wall_time }
+    }
+
+    pub fn original_size(&self) -> u64 {
+        self.files.iter().map(|f| f.original_size).sum()
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.files.iter().map(|f| f.compressed_size).sum()
+    }
+
+    /// Total compressed size as a fraction of the total original size
+    pub fn ratio(&self) -> f64 {
+        ratio(self.compressed_size(), self.original_size())
+    }
+
+    /// Uncompressed bytes processed per second of wall time
+    pub fn throughput(&self) -> f64 {
+        throughput(self.original_size(), self.wall_time)
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decompressed = self.files.iter().all(|f| f.decompressed) && !self.files.is_empty();
+        let (from, to) = if decompressed {
+            (self.compressed_size(), self.original_size())
+        } else {
+            (self.original_size(), self.compressed_size())
+        };
+        write!(
+            f,
+            "Total: {} files, {} -> {} ({:.1}%) in {:.2}s, {}/s",
+            self.files.len(),
+            format_size(from),
+            format_size(to),
+            self.ratio() * 100.0,
+            self.wall_time.as_secs_f64(),
+            format_size(self.throughput() as u64)
+        )
+    }
+}
+
+/// Run `f`, returning its result and how long it took
+pub(crate) fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+/// Stats for a compression batch whose results line up with its inputs
+pub(crate) fn compression_stats(
+    inputs: &[PathBuf],
+    results: Vec<JcResult<PathBuf>>,
+    elapsed: &[Duration],
+) -> Vec<JcResult<FileStats>> {
+    results
+        .into_iter()
+        .zip(inputs.iter().zip(elapsed))
+        .map(|(result, (input, elapsed))| {
+            FileStats::compressed(input.clone(), tree_size(input)?, result?, *elapsed)
+        })
+        .collect()
+}
+
+/// Total size of the regular files at or below `path`; symlinks are not
+/// followed
+pub fn tree_size(path: &Path) -> JcResult<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        let mut total = 0;
+        for entry in fs::read_dir(path)? {
+            total += tree_size(&entry?.path())?;
+        }
+        Ok(total)
+    } else if metadata.is_file() {
+        Ok(metadata.len())
+    } else {
+        Ok(0)
+    }
+}
+
+/// Human-readable size with binary units, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+pub(crate) fn ratio(compressed: u64, original: u64) -> f64 {
+    if original == 0 {
+        0.0
+    } else {
+        compressed as f64 / original as f64
+    }
+}
+
+pub(crate) fn throughput(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        bytes as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_tree_size_counts_regular_files() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a"), vec![0u8; 100]).unwrap();
+        fs::write(dir.path().join("sub/b"), vec![0u8; 50]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a", dir.path().join("link")).unwrap();
+
+        assert_eq!(tree_size(dir.path()).unwrap(), 150);
+        assert_eq!(tree_size(&dir.path().join("a")).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_run_totals() {
+        let file = |original_size, compressed_size| FileStats {
+            input: PathBuf::from("in"),
+            output: PathBuf::from("out"),
+            original_size,
+            compressed_size,
+            elapsed: Duration::from_secs(1),
+            decompressed: false,
+        };
+        let run = RunStats::new(
+            vec![file(3000, 1000), file(1000, 1000)],
+            Duration::from_secs(2),
+        );
+
+        assert_eq!(run.original_size(), 4000);
+        assert_eq!(run.compressed_size(), 2000);
+        assert_eq!(run.ratio(), 0.5);
+        assert_eq!(run.throughput(), 2000.0);
+        assert_eq!(run.files[0].throughput(), 3000.0);
+        assert_eq!(
+            run.to_string(),
+            "Total: 2 files, 3.9 KiB -> 2.0 KiB (50.0%) in 2.00s, 2.0 KiB/s"
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use serde::Deserialize;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::compressors::{create_compressor, detect_format};
+use crate::core::config::{CompressionConfig, Durability};
+use crate::core::error::{JcError, JcResult};
+use crate::core::types::CompressionFormat;
+use crate::operations::decompress::decompress_file;
+use crate::operations::gpg::is_gpg_file;
+use crate::utils::{debug, info, sync_output, warn};
+
+/// Name of the per-root index that records tiered files
+pub const TIER_INDEX_NAME: &str = ".jcz-tier-index";
+
+/// Timestamp used to measure a file's age
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgeBy {
+    /// Last access time
+    Atime,
+    /// Last modification time
+    #[default]
+    Mtime,
+}
+
+/// A single tiering rule; the first matching rule wins
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TierRule {
+    /// Filename glob (`*` and `?`); matches every file when absent
+    #[serde(default)]
+    pub pattern: Option<String>,
+
+    /// Minimum age in days before a file is tiered
+    pub older_than_days: u64,
+
+    /// Which timestamp the age is measured from
+    #[serde(default)]
+    pub age_by: AgeBy,
+
+    /// Compression command: gzip, bzip2 or xz
+    #[serde(default = "default_command")]
+    pub command: String,
+
+    /// Compression level (defaults to the compressor's default)
+    #[serde(default)]
+    pub level: Option<u8>,
+
+    /// Skip files smaller than this many bytes
+    #[serde(default)]
+    pub min_size: u64,
+}
+
+/// Rules without a command compress with xz
+fn default_command() -> String {
+    "xz".to_string()
+}
+
+/// Tiering rules loaded from a TOML file of `[[rule]]` tables
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TierRules {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<TierRule>,
+}
+
+impl TierRules {
+    /// Load and validate rules from a TOML file
+    pub fn load(path: &Path) -> JcResult<Self> {
+        if !path.exists() {
+            return Err(JcError::FileNotFound(path.to_path_buf()));
+        }
+        let text = fs::read_to_string(path)?;
+        let rules: TierRules = toml::from_str(&text).map_err(|e| {
+            JcError::Usage(format!("Invalid tiering rules {}: {}", path.display(), e))
+        })?;
+
+        if rules.rules.is_empty() {
+            return Err(JcError::Usage(format!(
+                "No [[rule]] entries in {}",
+                path.display()
+            )));
+        }
+
+        for rule in &rules.rules {
+            let format = rule_format(rule)?;
+            if let Some(level) = rule.level {
+                let compressor = create_compressor(format);
+                if !compressor.validate_level(level) {
+                    return Err(JcError::InvalidCompressionLevel {
+                        algorithm: compressor.name().to_string(),
+                        level,
+                    });
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Find the first rule that applies to a file
+    fn matching(&self, path: &Path, metadata: &fs::Metadata, now: SystemTime) -> Option<&TierRule> {
+        let name = path.file_name()?.to_str()?;
+        self.rules.iter().find(|rule| {
+            let stamp = match rule.age_by {
+                AgeBy::Atime => metadata.accessed(),
+                AgeBy::Mtime => metadata.modified(),
+            };
+            let old_enough = stamp
+                .ok()
+                .and_then(|t| now.duration_since(t).ok())
+                .is_some_and(|age| age >= Duration::from_secs(rule.older_than_days * 86_400));
+
+            old_enough
+                && metadata.len() >= rule.min_size
+                && rule.pattern.as_deref().is_none_or(|p| glob_match(p, name))
+        })
+    }
+}
+
+/// Only single-file compressors can tier a file in place
+fn rule_format(rule: &TierRule) -> JcResult<CompressionFormat> {
+    match CompressionFormat::from_name(&rule.command) {
+        Some(
+            format @ (CompressionFormat::Gzip | CompressionFormat::Bzip2 | CompressionFormat::Xz),
+        ) => Ok(format),
+        _ => Err(JcError::InvalidCommand(rule.command.clone())),
+    }
+}
+
+/// One file moved to the compressed tier
+#[derive(Debug, Clone)]
+pub struct TierEntry {
+    /// Original path of the file
+    pub original: PathBuf,
+
+    /// Compressed file that replaced it
+    pub archive: PathBuf,
+
+    /// Size before compression
+    pub original_size: u64,
+
+    /// Size after compression (0 in dry-run mode)
+    pub archive_size: u64,
+}
+
+/// Compress every file under `root` matched by the rules, in place
+///
+/// Originals are replaced by their compressed form, which keeps the
+/// original timestamps, and each move is recorded in the root's index so
+/// `restore_tiered_file` can bring it back.
+pub fn tier_directory(root: &Path, rules: &TierRules, dry_run: bool) -> JcResult<Vec<TierEntry>> {
+    if !root.is_dir() {
+        return Err(JcError::NotADirectory(root.to_path_buf()));
+    }
+
+    let root = root.canonicalize()?;
+    let now = SystemTime::now();
+    let mut candidates = Vec::new();
+    collect_candidates(&root, rules, now, &mut candidates)?;
+
+    info!(
+        "{} files under {} match the tiering rules",
+        candidates.len(),
+        root.display()
+    );
+
+    let mut entries = Vec::new();
+    for (path, rule) in candidates {
+        let format = rule_format(&rule)?;
+        let archive = archive_path(&path, format);
+
+        // Never clobber an unrelated file that already has the archive name
+        if archive.exists() {
+            warn!(
+                "Skipping {}: {} already exists",
+                path.display(),
+                archive.display()
+            );
+            continue;
+        }
+
+        if dry_run {
+            entries.push(TierEntry {
+                original_size: fs::metadata(&path)?.len(),
+                original: path,
+                archive,
+                archive_size: 0,
+            });
+            continue;
+        }
+
+        entries.push(tier_file(&root, &path, format, rule.level)?);
+    }
+
+    Ok(entries)
+}
+
+/// Files under `dir` matched by a rule, in name order
+fn collect_candidates(
+    dir: &Path,
+    rules: &TierRules,
+    now: SystemTime,
+    out: &mut Vec<(PathBuf, TierRule)>,
+) -> JcResult<()> {
+    let mut children: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    children.sort();
+
+    for path in children {
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            collect_candidates(&path, rules, now, out)?;
+            continue;
+        }
+
+        // Symlinks, the index itself and already-compressed files stay put
+        if !metadata.is_file()
+            || path.file_name().is_some_and(|n| n == TIER_INDEX_NAME)
+            || detect_format(&path).is_some()
+            || path.extension().is_some_and(|e| e == "jcze")
+            || is_gpg_file(&path)
+        {
+            continue;
+        }
+
+        if let Some(rule) = rules.matching(&path, &metadata, now) {
+            out.push((path, rule.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the compressed form of a file is written
+fn archive_path(path: &Path, format: CompressionFormat) -> PathBuf {
+    let mut archive = path.as_os_str().to_owned();
+    archive.push(".");
+    archive.push(format.extension());
+    PathBuf::from(archive)
+}
+
+/// Compress one file in place and record it in the index
+fn tier_file(
+    root: &Path,
+    path: &Path,
+    format: CompressionFormat,
+    level: Option<u8>,
+) -> JcResult<TierEntry> {
+    let compressor = create_compressor(format);
+    let config = CompressionConfig::new()
+        .with_level(level.unwrap_or_else(|| compressor.default_level()))
+        .with_force(true);
+
+    let metadata = fs::metadata(path)?;
+    let archive = compressor.compress(path, &config)?;
+
+    // The compressed copy stands in for the original, timestamps included
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    File::options()
+        .write(true)
+        .open(&archive)?
+        .set_times(times)?;
+
+    append_index(root, path, &archive)?;
+    // Both must survive a crash before the original goes
+    sync_output(&archive, Durability::Default)?;
+    sync_output(&root.join(TIER_INDEX_NAME), Durability::Default)?;
+    fs::remove_file(path)?;
+
+    debug!("Tiered {} -> {}", path.display(), archive.display());
+
+    Ok(TierEntry {
+        original: path.to_path_buf(),
+        archive_size: fs::metadata(&archive)?.len(),
+        archive,
+        original_size: metadata.len(),
+    })
+}
+
+/// Record a tiered file in the root's index, as paths relative to it
+fn append_index(root: &Path, original: &Path, archive: &Path) -> JcResult<()> {
+    // The index is tab-separated, one file per line
+    let relative = |p: &Path| -> JcResult<String> {
+        p.strip_prefix(root)
+            .ok()
+            .and_then(|rel| rel.to_str())
+            .filter(|rel| !rel.contains(['\t', '\n']))
+            .map(str::to_string)
+            .ok_or_else(|| {
+                JcError::Usage(format!(
+                    "{} cannot be recorded in the tiering index",
+                    p.display()
+                ))
+            })
+    };
+
+    let line = format!("{}\t{}\n", relative(original)?, relative(archive)?);
+
+    let mut index = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(root.join(TIER_INDEX_NAME))?;
+    index.write_all(line.as_bytes())?;
+    index.sync_all()?;
+    Ok(())
+}
+
+/// Bring a tiered file back to its original path
+///
+/// Accepts either the original path or the compressed file. The nearest
+/// index in the file's ancestor directories is consulted and updated.
+pub fn restore_tiered_file(path: &Path) -> JcResult<PathBuf> {
+    let absolute = absolute_path(path)?;
+
+    let mut dir = absolute.parent();
+    while let Some(root) = dir {
+        let index_path = root.join(TIER_INDEX_NAME);
+        if index_path.is_file() {
+            if let Some(restored) = restore_from_index(root, &index_path, &absolute)? {
+                return Ok(restored);
+            }
+        }
+        dir = root.parent();
+    }
+
+    Err(JcError::Usage(format!(
+        "{} is not recorded in any tiering index",
+        path.display()
+    )))
+}
+
+/// Restore `target` if the index at `index_path` records it, dropping its
+/// line; `None` when the index does not mention it
+fn restore_from_index(root: &Path, index_path: &Path, target: &Path) -> JcResult<Option<PathBuf>> {
+    let text = fs::read_to_string(index_path)?;
+    let mut remaining = Vec::new();
+    let mut found = None;
+
+    for line in text.lines() {
+        let Some((original, archive)) = line.split_once('\t') else {
+            warn!(
+                "Ignoring malformed line in {}: {}",
+                index_path.display(),
+                line
+            );
+            continue;
+        };
+        let (original, archive) = (root.join(original), root.join(archive));
+
+        if found.is_none() && (original == target || archive == target) {
+            found = Some((original, archive));
+        } else {
+            remaining.push(line);
+        }
+    }
+
+    let Some((original, archive)) = found else {
+        return Ok(None);
+    };
+
+    if original.exists() {
+        return Err(JcError::NameExists(original.display().to_string()));
+    }
+    if !archive.is_file() {
+        return Err(JcError::FileNotFound(archive));
+    }
+
+    let metadata = fs::metadata(&archive)?;
+    let config = CompressionConfig::new().with_force(true);
+    let restored = decompress_file(&archive, &config)?;
+
+    if restored != original {
+        fs::rename(&restored, &original)?;
+    }
+
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    File::options()
+        .write(true)
+        .open(&original)?
+        .set_times(times)?;
+
+    sync_output(&original, Durability::Default)?;
+    fs::remove_file(&archive)?;
+
+    if remaining.is_empty() {
+        fs::remove_file(index_path)?;
+    } else {
+        let mut text = remaining.join("\n");
+        text.push('\n');
+        fs::write(index_path, text)?;
+    }
+
+    info!("Restored {}", original.display());
+    Ok(Some(original))
+}
+
+/// Absolute path without requiring the file to exist
+fn absolute_path(path: &Path) -> JcResult<PathBuf> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or_else(|| JcError::NotAFile(path.to_path_buf()))?;
+    Ok(parent.canonicalize()?.join(name))
+}
+
+/// Match a filename against a glob with `*` and `?` wildcards
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.log", "app.log"));
+        assert!(glob_match("app-??.log", "app-01.log"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("*.log", "app.log.1"));
+        assert!(!glob_match("app-?.log", "app-10.log"));
+    }
+
+    #[test]
+    fn test_rules_parse_defaults() {
+        let rules: TierRules = toml::from_str(
+            r#"
+            [[rule]]
+            pattern = "*.log"
+            older_than_days = 30
+            "#,
+        )
+        .unwrap();
+
+        let rule = &rules.rules[0];
+        assert_eq!(rule.age_by, AgeBy::Mtime);
+        assert_eq!(rule.command, "xz");
+        assert_eq!(rule.min_size, 0);
+    }
+
+    #[test]
+    fn test_rule_format_rejects_archivers() {
+        let rule = TierRule {
+            pattern: None,
+            older_than_days: 1,
+            age_by: AgeBy::Atime,
+            command: "tar".to_string(),
+            level: None,
+            min_size: 0,
+        };
+        assert!(matches!(
+            rule_format(&rule),
+            Err(JcError::InvalidCommand(command)) if command == "tar"
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+//! Tree view of archive contents (`jcz tree`)
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Component;
+
+use crate::core::types::{ArchiveEntry, EntryKind};
+use crate::operations::stats::format_size;
+
+/// A member, or a directory implied by the members below it
+#[derive(Debug, Default)]
+struct Node {
+    /// Own size for files, cumulative size for directories
+    size: u64,
+    is_dir: bool,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert(&mut self, entry: &ArchiveEntry) {
+        let names: Vec<String> = entry
+            .path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let Some((last, parents)) = names.split_last() else {
+            return;
+        };
+
+        let mut node = self;
+        node.size += entry.size;
+        for name in parents {
+            node = node.children.entry(name.clone()).or_default();
+            node.is_dir = true;
+            node.size += entry.size;
+        }
+        let leaf = node.children.entry(last.clone()).or_default();
+        leaf.size += entry.size;
+        leaf.is_dir |= entry.kind == EntryKind::Directory;
+    }
+
+    fn render(&self, prefix: &str, out: &mut String, counts: &mut (usize, usize)) {
+        let last = self.children.len().saturating_sub(1);
+        for (i, (name, child)) in self.children.iter().enumerate() {
+            let (branch, indent) = if i == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            child.line(&format!("{}{}", prefix, branch), name, out, counts);
+            child.render(&format!("{}{}", prefix, indent), out, counts);
+        }
+    }
+
+    fn line(&self, lead: &str, name: &str, out: &mut String, counts: &mut (usize, usize)) {
+        let slash = if self.is_dir { "/" } else { "" };
+        let _ = writeln!(
+            out,
+            "{}{}{} ({})",
+            lead,
+            name,
+            slash,
+            format_size(self.size)
+        );
+        if self.is_dir {
+            counts.0 += 1;
+        } else {
+            counts.1 += 1;
+        }
+    }
+}
+
+/// Render `entries` as an indented tree, each directory with the total
+/// size of everything below it, followed by a summary line
+pub fn render_tree(entries: &[ArchiveEntry]) -> String {
+    let mut root = Node::default();
+    for entry in entries {
+        root.insert(entry);
+    }
+
+    let mut out = String::new();
+    let mut counts = (0, 0);
+    for (name, node) in &root.children {
+        node.line("", name, &mut out, &mut counts);
+        node.render("", &mut out, &mut counts);
+    }
+    let _ = writeln!(
+        out,
+        "\n{} directories, {} files, {} total",
+        counts.0,
+        counts.1,
+        format_size(root.size)
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, size: u64, kind: EntryKind) -> ArchiveEntry {
+        ArchiveEntry {
+            path: PathBuf::from(path),
+            size,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_render_tree_sums_directories() {
+        let entries = [
+            entry("logs/", 0, EntryKind::Directory),
+            entry("logs/notes.txt", 100, EntryKind::File),
+            entry("logs/app/server.log", 2048, EntryKind::File),
+            entry("logs/empty/", 0, EntryKind::Directory),
+            entry("README", 10, EntryKind::File),
+        ];
+        assert_eq!(
+            render_tree(&entries),
+            "README (10 B)\n\
+             logs/ (2.1 KiB)\n\
+             ├── app/ (2.0 KiB)\n\
+             │   └── server.log (2.0 KiB)\n\
+             ├── empty/ (0 B)\n\
+             └── notes.txt (100 B)\n\
+             \n\
+             3 directories, 3 files, 2.1 KiB total\n"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Continuous compression of new files (`jcz watch`)
+//!
+//! A directory is watched for files that are created in it or moved into
+//! it. Each one is handed on once it has stopped changing for the settle
+//! time, so files still being written are left alone. Hidden files and
+//! files that are already compressed or encrypted (including jcz's own
+//! outputs) are ignored.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+
+use crate::compressors::detect_format;
+use crate::core::error::{JcError, JcResult};
+use crate::operations::decrypt::is_encrypted_file;
+use crate::operations::gpg::is_gpg_file;
+use crate::operations::tier::glob_match;
+use crate::utils::{debug, info, warn, CancellationToken};
+
+/// How often pending files are checked when no events arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What `watch_directory` hands on
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Filename glob (`*` and `?`); every file when absent
+    pub pattern: Option<String>,
+
+    /// How long a file must stay unchanged before it is handed on
+    pub settle: Duration,
+
+    /// Stops the watch when cancelled
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            settle: Duration::from_secs(2),
+            cancel: None,
+        }
+    }
+}
+
+/// Size and modification time last seen for a pending file
+struct Pending {
+    size: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+/// Watch `dir` and call `on_files` with each batch of new files once they
+/// have settled, until `options.cancel` is cancelled
+pub fn watch_directory(
+    dir: &Path,
+    options: &WatchOptions,
+    mut on_files: impl FnMut(Vec<PathBuf>),
+) -> JcResult<()> {
+    if !dir.is_dir() {
+        return Err(JcError::NotADirectory(dir.to_path_buf()));
+    }
+    let watch_error =
+        |e: notify::Error| JcError::Other(format!("Failed to watch {}: {}", dir.display(), e));
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(watch_error)?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+    info!("Watching {} for new files", dir.display());
+
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    loop {
+        if options.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            info!("Stopped watching {}", dir.display());
+            return Ok(());
+        }
+
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                // Renames report the new name too; files that are gone by
+                // the time they settle are dropped
+                let arrived = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+                );
+                for path in event.paths {
+                    if arrived && !pending.contains_key(&path) && wanted(&path, options) {
+                        debug!("New file: {}", path.display());
+                        pending.insert(
+                            path,
+                            Pending {
+                                size: 0,
+                                modified: None,
+                                since: Instant::now(),
+                            },
+                        );
+                    }
+                }
+            }
+            Ok(Err(e)) => warn!("Watch error on {}: {}", dir.display(), e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(JcError::Other(format!(
+                    "Stopped receiving events for {}",
+                    dir.display()
+                )));
+            }
+        }
+
+        let ready = settled(&mut pending, options.settle);
+        if !ready.is_empty() {
+            on_files(ready);
+        }
+    }
+}
+
+/// Remove and return the pending files unchanged for `settle`; vanished
+/// files and anything that is not a regular file are dropped
+fn settled(pending: &mut HashMap<PathBuf, Pending>, settle: Duration) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    pending.retain(|path, seen| {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return false;
+        };
+        if !metadata.is_file() {
+            return false;
+        }
+
+        let modified = metadata.modified().ok();
+        if metadata.len() != seen.size || modified != seen.modified {
+            seen.size = metadata.len();
+            seen.modified = modified;
+            seen.since = Instant::now();
+            return true;
+        }
+        if seen.since.elapsed() < settle {
+            return true;
+        }
+        ready.push(path.clone());
+        false
+    });
+    ready.sort();
+    ready
+}
+
+/// Whether a new file at `path` should be handed on
+fn wanted(path: &Path, options: &WatchOptions) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    // Hidden files include jcz's own temporaries
+    if name.starts_with('.')
+        || detect_format(path).is_some()
+        || is_encrypted_file(path)
+        || is_gpg_file(path)
+    {
+        return false;
+    }
+    options
+        .pattern
+        .as_deref()
+        .is_none_or(|pattern| glob_match(pattern, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_wanted_skips_outputs_and_hidden_files() {
+        let options = WatchOptions {
+            pattern: Some("*.log".to_string()),
+            ..WatchOptions::default()
+        };
+        assert!(wanted(Path::new("/logs/app.log"), &options));
+        assert!(!wanted(Path::new("/logs/app.txt"), &options));
+        assert!(!wanted(Path::new("/logs/.app.log"), &options));
+        assert!(!wanted(Path::new("/logs/app.log.gz"), &options));
+        assert!(!wanted(Path::new("/logs/app.log.xz.jcze"), &options));
+    }
+
+    #[test]
+    fn test_settled_waits_for_unchanged_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("app.log");
+        fs::write(&path, b"line\n").unwrap();
+
+        let mut pending = HashMap::new();
+        pending.insert(
+            path.clone(),
+            Pending {
+                size: 0,
+                modified: None,
+                since: Instant::now(),
+            },
+        );
+
+        // First look records the size, so nothing is ready yet
+        assert!(settled(&mut pending, Duration::ZERO).is_empty());
+        assert_eq!(settled(&mut pending, Duration::ZERO), vec![path.clone()]);
+        assert!(pending.is_empty());
+
+        // Files gone before settling are dropped
+        pending.insert(
+            temp_dir.path().join("gone.log"),
+            Pending {
+                size: 0,
+                modified: None,
+                since: Instant::now(),
+            },
+        );
+        assert!(settled(&mut pending, Duration::ZERO).is_empty());
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Backups of outputs that would be replaced (`--backup`)
+//!
+//! As in coreutils: simple backups add a suffix (`~`, or
+//! `SIMPLE_BACKUP_SUFFIX`), numbered backups are named `NAME.~N~`, and
+//! `existing` makes numbered backups of paths that already have some and
+//! simple ones of the rest. Without a value, `VERSION_CONTROL` picks the
+//! kind, defaulting to `existing`.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What kind of backup to make
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupMode {
+    /// `NAME` + suffix, replacing an older backup
+    Simple(String),
+    /// `NAME.~N~`, one more than the highest so far
+    Numbered,
+    /// Numbered if `NAME.~N~` backups exist, else simple with the suffix
+    Existing(String),
+}
+
+impl BackupMode {
+    /// Mode for `--backup[=VALUE]`: a coreutils control word, or else the
+    /// suffix of simple backups; `None` for `none`/`off`
+    pub fn parse(value: Option<&str>) -> Result<Option<Self>, String> {
+        let control = match value {
+            Some(value) => value.to_string(),
+            None => env::var("VERSION_CONTROL").unwrap_or_default(),
+        };
+        let suffix = || env::var("SIMPLE_BACKUP_SUFFIX").unwrap_or_else(|_| "~".to_string());
+        Ok(Some(match control.as_str() {
+            "none" | "off" => return Ok(None),
+            "simple" | "never" => BackupMode::Simple(suffix()),
+            "numbered" | "t" => BackupMode::Numbered,
+            "" | "existing" | "nil" => BackupMode::Existing(suffix()),
+            _ if value.is_none() => {
+                return Err(format!("Invalid VERSION_CONTROL: {}", control));
+            }
+            suffix if suffix.contains('/') => {
+                return Err(format!("Invalid backup suffix: {}", suffix));
+            }
+            suffix => BackupMode::Simple(suffix.to_string()),
+        }))
+    }
+
+    /// Where the backup of `path` goes
+    pub fn backup_path(&self, path: &Path) -> PathBuf {
+        let numbered = next_number(path);
+        match self {
+            BackupMode::Numbered => numbered_path(path, numbered),
+            BackupMode::Existing(_) if numbered > 1 => numbered_path(path, numbered),
+            BackupMode::Simple(suffix) | BackupMode::Existing(suffix) => {
+                let mut name = path.as_os_str().to_owned();
+                name.push(suffix);
+                PathBuf::from(name)
+            }
+        }
+    }
+
+    /// Rename `path`, which exists, to its backup name, replacing an older
+    /// simple backup, and return that name
+    pub fn back_up(&self, path: &Path) -> io::Result<PathBuf> {
+        let backup = self.backup_path(path);
+        if let Ok(metadata) = fs::symlink_metadata(&backup) {
+            if metadata.is_dir() {
+                fs::remove_dir_all(&backup)?;
+            } else {
+                fs::remove_file(&backup)?;
+            }
+        }
+        fs::rename(path, &backup)?;
+        Ok(backup)
+    }
+}
+
+/// `NAME.~N~`
+fn numbered_path(path: &Path, number: u64) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".~{}~", number));
+    PathBuf::from(name)
+}
+
+/// One more than the highest N of the `NAME.~N~` backups of `path`
+fn next_number(path: &Path) -> u64 {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return 1;
+    };
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.~", name);
+    let highest = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.file_name();
+            let number = entry.to_str()?.strip_prefix(&prefix)?.strip_suffix('~')?;
+            number.parse::<u64>().ok()
+        })
+        .max()
+        .unwrap_or(0);
+    highest + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse() {
+        let simple = BackupMode::parse(Some(".bak")).unwrap();
+        assert_eq!(simple, Some(BackupMode::Simple(".bak".to_string())));
+        assert_eq!(
+            BackupMode::parse(Some("numbered")).unwrap(),
+            Some(BackupMode::Numbered)
+        );
+        assert_eq!(BackupMode::parse(Some("off")).unwrap(), None);
+        assert!(BackupMode::parse(Some("a/b")).is_err());
+    }
+
+    #[test]
+    fn test_backups() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("out.gz");
+        let simple = BackupMode::Simple("~".to_string());
+        let existing = BackupMode::Existing("~".to_string());
+
+        fs::write(&path, "1").unwrap();
+        assert_eq!(
+            existing.back_up(&path).unwrap(),
+            temp_dir.path().join("out.gz~")
+        );
+        fs::write(&path, "2").unwrap();
+        assert_eq!(
+            simple.back_up(&path).unwrap(),
+            temp_dir.path().join("out.gz~")
+        );
+        assert_eq!(fs::read(temp_dir.path().join("out.gz~")).unwrap(), b"2");
+
+        for n in 1..=2 {
+            fs::write(&path, n.to_string()).unwrap();
+            let backup = BackupMode::Numbered.back_up(&path).unwrap();
+            assert_eq!(backup, temp_dir.path().join(format!("out.gz.~{}~", n)));
+        }
+        // Numbered backups exist now, so existing makes another
+        fs::write(&path, "3").unwrap();
+        let backup = existing.back_up(&path).unwrap();
+        assert_eq!(backup, temp_dir.path().join("out.gz.~3~"));
+        assert!(!path.exists());
+    }
+}
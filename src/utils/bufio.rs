@@ -0,0 +1,212 @@
+//! Buffered and memory-mapped file I/O (`--buffer-size`)
+//!
+//! Streams read and write files through buffers of one process-wide size.
+//! AES-GCM, though, seals or opens a whole payload in one piece, so
+//! encryption works in a `MappedFile`: the output file mapped into memory,
+//! which the kernel pages to and from disk. That way an archive larger
+//! than RAM costs page cache rather than memory. Where files cannot be
+//! mapped, the mapping falls back to an ordinary buffer.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::utils::debug;
+
+/// Buffer size unless `--buffer-size` says otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Smallest buffer `set_buffer_size` accepts
+const MIN_BUFFER_SIZE: usize = 4096;
+
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_BUFFER_SIZE);
+
+/// Use buffers of `size` bytes (at least 4 KiB) from now on
+pub fn set_buffer_size(size: usize) {
+    BUFFER_SIZE.store(size.max(MIN_BUFFER_SIZE), Ordering::Relaxed);
+    debug!("Buffer size set to {}", size.max(MIN_BUFFER_SIZE));
+}
+
+/// Size of I/O buffers
+pub fn buffer_size() -> usize {
+    BUFFER_SIZE.load(Ordering::Relaxed)
+}
+
+/// `inner` read through a buffer of `buffer_size()`
+pub fn buffered_reader<R: Read>(inner: R) -> BufReader<R> {
+    BufReader::with_capacity(buffer_size(), inner)
+}
+
+/// `inner` written through a buffer of `buffer_size()`
+pub fn buffered_writer<W: Write>(inner: W) -> BufWriter<W> {
+    BufWriter::with_capacity(buffer_size(), inner)
+}
+
+/// A new file of a fixed size, writable in place as a byte slice
+///
+/// The file must not be changed by anyone else while it is mapped.
+pub struct MappedFile {
+    file: File,
+    map: Map,
+}
+
+enum Map {
+    /// Shared mapping of the whole file
+    #[cfg(unix)]
+    Mapped { ptr: *mut u8, len: usize },
+    /// Contents to write out on `finish`
+    Buffer(Vec<u8>),
+}
+
+impl MappedFile {
+    /// Create (or truncate) `path` as `len` zero bytes and map it
+    ///
+    /// The space is allocated up front where the filesystem allows, so a
+    /// full disk fails here rather than while the mapping is written.
+    pub fn create(path: &Path, len: u64) -> io::Result<Self> {
+        let size = usize::try_from(len)
+            .map_err(|_| io::Error::other(format!("{} is too large to map", path.display())))?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(len)?;
+        let map = Map::new(&file, size).unwrap_or_else(|e| {
+            debug!("Cannot map {} ({}), buffering it", path.display(), e);
+            Map::Buffer(vec![0; size])
+        });
+        Ok(MappedFile { file, map })
+    }
+
+    /// Write the contents out, cutting the file to its first `len` bytes
+    pub fn finish(mut self, len: u64) -> io::Result<()> {
+        match std::mem::replace(&mut self.map, Map::Buffer(Vec::new())) {
+            #[cfg(unix)]
+            Map::Mapped { ptr, len: size } => unmap(ptr, size)?,
+            Map::Buffer(buffer) => {
+                let end = buffer.len().min(usize::try_from(len).unwrap_or(usize::MAX));
+                (&self.file).write_all(&buffer[..end])?;
+            }
+        }
+        self.file.set_len(len)
+    }
+}
+
+impl Map {
+    #[cfg(unix)]
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        if len == 0 {
+            return Ok(Map::Buffer(Vec::new()));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            // SAFETY: only reads its integer arguments
+            let result = unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len as libc::off_t) };
+            // Filesystems without allocation support just get a sparse file
+            if result == libc::ENOSPC || result == libc::EFBIG {
+                return Err(io::Error::from_raw_os_error(result));
+            }
+        }
+        // SAFETY: a new shared mapping of an open file; it is only ever
+        // accessed through the slice of `len` bytes and unmapped once
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Map::Mapped {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn new(_file: &File, len: usize) -> io::Result<Self> {
+        Ok(Map::Buffer(vec![0; len]))
+    }
+}
+
+#[cfg(unix)]
+fn unmap(ptr: *mut u8, len: usize) -> io::Result<()> {
+    // SAFETY: `ptr` and `len` describe a live mapping, unmapped only here
+    if unsafe { libc::munmap(ptr.cast(), len) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.map {
+            // SAFETY: the mapping is `len` bytes and lives as long as `self`
+            #[cfg(unix)]
+            Map::Mapped { ptr, len } => unsafe { std::slice::from_raw_parts(*ptr, *len) },
+            Map::Buffer(buffer) => buffer,
+        }
+    }
+}
+
+impl DerefMut for MappedFile {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.map {
+            // SAFETY: as above, and `&mut self` makes this the only access
+            #[cfg(unix)]
+            Map::Mapped { ptr, len } => unsafe { std::slice::from_raw_parts_mut(*ptr, *len) },
+            Map::Buffer(buffer) => buffer,
+        }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Map::Mapped { ptr, len } = self.map {
+            let _ = unmap(ptr, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mapped_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("out.bin");
+
+        let mut mapped = MappedFile::create(&path, 8192).unwrap();
+        assert!(mapped.iter().all(|&b| b == 0));
+        mapped[..5].copy_from_slice(b"hello");
+        mapped[8191] = b'!';
+        mapped.finish(8192).unwrap();
+        let written = fs::read(&path).unwrap();
+        assert_eq!((&written[..5], written[8191]), (&b"hello"[..], b'!'));
+
+        // Cut short, and empty
+        let mut mapped = MappedFile::create(&path, 100).unwrap();
+        mapped[..3].copy_from_slice(b"abc");
+        mapped.finish(3).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"abc");
+        MappedFile::create(&path, 0).unwrap().finish(0).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+}
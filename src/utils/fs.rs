@@ -4,15 +4,41 @@ use std::path::{Path, PathBuf};
 
 use tempfile::TempDir;
 
-use crate::core::config::TimestampOption;
+use crate::core::config::{CompressionConfig, Durability, TimestampOption};
 use crate::core::error::{JcError, JcResult};
-use crate::utils::timestamp::generate_timestamp;
+use crate::utils::logger::{debug, info, warn};
+use crate::utils::tempdirs::{self, SCRATCH_PREFIX};
+use crate::utils::timestamp::{generate_timestamp, host_label};
+
+/// Extensions jcz appends to output names, used to find where a name's
+/// extension chain starts
+const OUTPUT_EXTENSIONS: &[&str] = &[
+    "tar", "gz", "bz2", "xz", "zip", "cpio", "ar", "tgz", "tbz2", "txz", "jcze",
+];
+
+/// Give up looking for a free disambiguated name after this many tries
+const MAX_NAME_ATTEMPTS: u32 = 1000;
+
+/// Variables `--name-template` can use, as `{name}`
+pub const NAME_TEMPLATE_VARIABLES: &[&str] = &[
+    "stem",
+    "name",
+    "ext",
+    "hostname",
+    "date",
+    "timestamp",
+    "level",
+];
 
 /// Generate output filename with optional timestamp
+///
+/// With `config.name_template`, the file name is the expanded template
+/// instead, next to `input`; the timestamp is then only used by
+/// `{timestamp}` (the datetime layout when none is set).
 pub fn generate_output_filename(
     input: &Path,
     extension: &str,
-    timestamp_opt: TimestampOption,
+    config: &CompressionConfig,
 ) -> JcResult<PathBuf> {
     let mut filename = input.as_os_str().to_string_lossy().to_string();
 
@@ -21,18 +47,247 @@ pub fn generate_output_filename(
         filename.pop();
     }
 
+    if let Some(ref template) = config.name_template {
+        let source = Path::new(&filename);
+        let name = render_name_template(template, source, extension, config)?;
+        let output = source.with_file_name(name);
+        config.back_up(&output)?;
+        return Ok(output);
+    }
+
     // Add timestamp if requested
-    if timestamp_opt != TimestampOption::None {
+    let timestamp_opt = &config.timestamp;
+    if *timestamp_opt != TimestampOption::None {
         let ts = generate_timestamp(timestamp_opt);
         filename.push('_');
         filename.push_str(&ts);
+
+        // Another run in the same instant may already own this name, with
+        // any extension chain (file_TS.gz, file_TS.tar.xz.jcze, ...)
+        let stamped = PathBuf::from(&filename);
+        let mut attempt = 0;
+        while name_taken(&stamped, attempt) {
+            attempt += 1;
+            if attempt > MAX_NAME_ATTEMPTS {
+                return Err(JcError::Other(format!(
+                    "No free output name for {}",
+                    stamped.display()
+                )));
+            }
+        }
+        if attempt > 0 {
+            filename.push_str(&disambiguation_suffix(attempt));
+        }
     }
 
     // Add extension
     filename.push('.');
     filename.push_str(extension);
 
-    Ok(PathBuf::from(filename))
+    let output = PathBuf::from(filename);
+    config.back_up(&output)?;
+    Ok(output)
+}
+
+/// Expand `template` for the output of `input` with `extension`
+fn render_name_template(
+    template: &str,
+    input: &Path,
+    extension: &str,
+    config: &CompressionConfig,
+) -> JcResult<String> {
+    let part = |p: Option<&std::ffi::OsStr>| p.unwrap_or_default().to_string_lossy().into_owned();
+    expand_template(template, |variable| {
+        Some(match variable {
+            "stem" => part(input.file_stem()),
+            "name" => part(input.file_name()),
+            "ext" => extension.to_string(),
+            "hostname" => host_label(),
+            "date" if config.timestamp.is_utc() => {
+                generate_timestamp(&TimestampOption::Date.in_utc())
+            }
+            "date" => generate_timestamp(&TimestampOption::Date),
+            "timestamp" if config.timestamp == TimestampOption::None => {
+                generate_timestamp(&TimestampOption::DateTime)
+            }
+            "timestamp" => generate_timestamp(&config.timestamp),
+            "level" => config.level.to_string(),
+            _ => return None,
+        })
+    })
+}
+
+/// Check a `--name-template`: known variables only, and no path separator
+pub fn validate_name_template(template: &str) -> JcResult<()> {
+    let sample = expand_template(template, |variable| {
+        NAME_TEMPLATE_VARIABLES
+            .contains(&variable)
+            .then(|| "x".to_string())
+    })?;
+    if sample.is_empty() {
+        return Err(JcError::Usage("Name template is empty".to_string()));
+    }
+    if sample.contains(['/', '\\']) {
+        return Err(JcError::Usage(format!(
+            "Name template '{}' must not contain a path separator; use -C to choose the directory",
+            template
+        )));
+    }
+    Ok(())
+}
+
+/// Replace each `{variable}` in `template` with `lookup(variable)`
+fn expand_template(
+    template: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) -> JcResult<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after.find('}').ok_or_else(|| {
+            JcError::Usage(format!("Unclosed '{{' in name template '{}'", template))
+        })?;
+        let variable = &after[..end];
+        let value = lookup(variable).ok_or_else(|| {
+            JcError::Usage(format!(
+                "Unknown variable {{{}}} in name template; available: {}",
+                variable,
+                NAME_TEMPLATE_VARIABLES.join(", ")
+            ))
+        })?;
+        result.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// `_<host>` for the first retry, then `_<host>_2`, `_<host>_3`, ...
+fn disambiguation_suffix(attempt: u32) -> String {
+    match attempt {
+        0 => String::new(),
+        1 => format!("_{}", host_label()),
+        n => format!("_{}_{}", host_label(), n),
+    }
+}
+
+/// Whether any entry next to `stamped` starts with its name plus the
+/// `attempt` suffix, followed by an extension
+fn name_taken(stamped: &Path, attempt: u32) -> bool {
+    let Some(base) = stamped.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let prefix = format!("{}{}.", base, disambiguation_suffix(attempt));
+    let dir = stamped
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    fs::read_dir(dir).is_ok_and(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .any(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+    })
+}
+
+/// Insert the `attempt` suffix before the extension chain of `name`
+///
+/// `file_20250101.tar.gz` becomes `file_20250101_<host>.tar.gz`.
+fn disambiguate_name(name: &str, attempt: u32) -> String {
+    let parts: Vec<&str> = name.split('.').collect();
+    let mut chain_start = parts.len();
+    while chain_start > 1 && OUTPUT_EXTENSIONS.contains(&parts[chain_start - 1]) {
+        chain_start -= 1;
+    }
+    // Names without a known extension keep their last one
+    if chain_start == parts.len() && parts.len() > 1 {
+        chain_start -= 1;
+    }
+
+    let mut result = parts[..chain_start].join(".");
+    result.push_str(&disambiguation_suffix(attempt));
+    for ext in &parts[chain_start..] {
+        result.push('.');
+        result.push_str(ext);
+    }
+    result
+}
+
+/// Move a finished output to `config.move_to`, if set
+///
+/// With `unique_names`, an existing file at the destination is never
+/// replaced; the output takes a disambiguated name instead.
+pub fn place_output(output: &Path, config: &CompressionConfig) -> JcResult<PathBuf> {
+    match config.move_to {
+        Some(ref dest_dir) if config.unique_names => move_file_no_clobber(output, dest_dir),
+        Some(ref dest_dir) => {
+            if let Some(name) = output.file_name() {
+                config.back_up(&dest_dir.join(name))?;
+            }
+            move_file(output, dest_dir)
+        }
+        None => Ok(output.to_path_buf()),
+    }
+}
+
+/// Move file into `dest_dir` without ever replacing an existing entry
+///
+/// Names are claimed atomically (hard link, or exclusive create across
+/// devices), so concurrent writers on a shared directory - including
+/// other hosts - each end up with their own file.
+pub fn move_file_no_clobber(source: &Path, dest_dir: &Path) -> JcResult<PathBuf> {
+    if !dest_dir.is_dir() {
+        return Err(JcError::NotADirectory(dest_dir.to_path_buf()));
+    }
+
+    let filename = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| JcError::Other("Invalid source filename".to_string()))?;
+
+    for attempt in 0..=MAX_NAME_ATTEMPTS {
+        let dest_path = dest_dir.join(disambiguate_name(filename, attempt));
+
+        let claimed = match fs::hard_link(source, &dest_path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+            // Cross-device, or no hard links on this filesystem
+            Err(_) => copy_exclusive(source, &dest_path),
+        };
+
+        match claimed {
+            Ok(()) => {
+                fs::remove_file(source)?;
+                return Ok(dest_path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(JcError::Io(e)),
+        }
+    }
+
+    Err(JcError::Other(format!(
+        "No free name for {} in {}",
+        filename,
+        dest_dir.display()
+    )))
+}
+
+/// Copy `source` to a `dest` that must not exist yet
+fn copy_exclusive(source: &Path, dest: &Path) -> io::Result<()> {
+    let mut output = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    // The source is removed next, so the copy must be on disk first
+    let result =
+        io::copy(&mut fs::File::open(source)?, &mut output).and_then(|_| output.sync_all());
+    if let Err(e) = result {
+        let _ = fs::remove_file(dest);
+        return Err(e);
+    }
+    Ok(())
 }
 
 /// Move file to destination directory if specified
@@ -57,33 +312,152 @@ pub fn move_file(source: &Path, dest_dir: &Path) -> JcResult<PathBuf> {
 
     let dest_path = dest_dir.join(filename);
 
-    // Try rename first (fast, atomic), fall back to copy+delete for cross-device
-    match fs::rename(source, &dest_path) {
-        Ok(_) => Ok(dest_path),
-        Err(e) if e.raw_os_error() == Some(18) => {
-            // EXDEV (cross-device link) - fall back to copy + delete
-            fs::copy(source, &dest_path)?;
-            fs::remove_file(source)?;
-            Ok(dest_path)
-        }
-        Err(e) => Err(JcError::Io(e)),
+    // A rename is instant and needs no extra space; only across
+    // filesystems is the file copied (and synced) before the source goes
+    if !move_atomic(source, &dest_path)? {
+        debug!("{} is on another filesystem, copied it", dest_dir.display());
+        fs::remove_file(source)?;
     }
+    Ok(dest_path)
 }
 
 /// Recursively copy file or directory
+///
+/// Symlinks are recreated as links and never followed, so copying an
+/// extracted tree cannot read from or write to anything outside it.
 pub fn copy_recursive(src: &Path, dst: &Path) -> io::Result<()> {
-    if src.is_dir() {
+    copy_tree(src, dst, false)
+}
+
+/// Recursively copy, following symlinks when `dereference` is set
+///
+/// Directory links that lead back into a directory being copied are
+/// skipped instead of recursing forever. Files keep their mtime.
+pub fn copy_tree(src: &Path, dst: &Path, dereference: bool) -> io::Result<()> {
+    copy_tree_inner(src, dst, dereference, &mut Vec::new())
+}
+
+fn copy_tree_inner(
+    src: &Path,
+    dst: &Path,
+    dereference: bool,
+    ancestors: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let link_metadata = fs::symlink_metadata(src)?;
+    let metadata = if dereference && link_metadata.file_type().is_symlink() {
+        // Dangling links are kept as links
+        fs::metadata(src).unwrap_or(link_metadata)
+    } else {
+        link_metadata
+    };
+
+    if metadata.file_type().is_symlink() {
+        copy_symlink(src, dst)
+    } else if metadata.is_dir() {
+        let canonical = src.canonicalize()?;
+        if ancestors.contains(&canonical) {
+            warn!("Skipping {}: symlink loop", src.display());
+            return Ok(());
+        }
+        ancestors.push(canonical);
+
         fs::create_dir_all(dst)?;
         for entry in fs::read_dir(src)? {
             let entry = entry?;
             let src_path = entry.path();
             let dst_path = dst.join(entry.file_name());
-            copy_recursive(&src_path, &dst_path)?;
+            copy_tree_inner(&src_path, &dst_path, dereference, ancestors)?;
         }
+
+        ancestors.pop();
+        Ok(())
     } else {
         fs::copy(src, dst)?;
+        // Keep the mtime, as tar does, so updates can compare against it;
+        // best effort, since some platforms need write access to set it
+        if let Ok(modified) = metadata.modified() {
+            let _ = fs::File::open(dst).and_then(|file| file.set_modified(modified));
+        }
+        Ok(())
     }
-    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::copy(src, dst).map(|_| ())
+}
+
+/// Whether `relative` can be created under `base` without passing through
+/// a symlink (which could point anywhere)
+pub fn is_inside_without_symlinks(base: &Path, relative: &Path) -> bool {
+    let mut current = base.to_path_buf();
+    let components: Vec<_> = relative.components().collect();
+    for component in components.iter().take(components.len().saturating_sub(1)) {
+        current.push(component);
+        if fs::symlink_metadata(&current).is_ok_and(|m| m.file_type().is_symlink()) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Copy `src` over `dst` through a hidden sibling, then rename into place
+///
+/// An interrupted copy never leaves a half-written `dst`; an existing
+/// `dst` is replaced only once the new copy is complete and synced, so
+/// the caller may remove `src` right after.
+pub fn copy_atomic(src: &Path, dst: &Path) -> io::Result<()> {
+    let name = dst
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid destination"))?;
+    let mut staged_name = std::ffi::OsString::from(".");
+    staged_name.push(name);
+    staged_name.push(format!(".jcz-tmp-{}", std::process::id()));
+    let staged = dst.with_file_name(staged_name);
+
+    let result = copy_recursive(src, &staged).and_then(|()| {
+        sync_tree(&staged)?;
+        rename_over(&staged, dst)?;
+        sync_path(parent_dir(dst))
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&staged).or_else(|_| fs::remove_file(&staged));
+    }
+    result
+}
+
+/// Move `src` over `dst`: a rename when both are on one filesystem,
+/// otherwise `copy_atomic`
+///
+/// Returns whether `src` was renamed, in which case its xattrs and ACLs
+/// moved with it and `src` no longer exists.
+pub fn move_atomic(src: &Path, dst: &Path) -> io::Result<bool> {
+    if !same_filesystem(src, parent_dir(dst)) {
+        copy_atomic(src, dst)?;
+        return Ok(false);
+    }
+    rename_over(src, dst)?;
+    Ok(true)
+}
+
+/// Rename `src` to `dst`, replacing whatever `dst` is
+fn rename_over(src: &Path, dst: &Path) -> io::Result<()> {
+    // rename() replaces a file with a file atomically; anything else has
+    // to be cleared out of the way first
+    if let Ok(existing) = fs::symlink_metadata(dst) {
+        if existing.is_dir() {
+            fs::remove_dir_all(dst)?;
+        } else if src.is_dir() {
+            fs::remove_file(dst)?;
+        }
+    }
+    fs::rename(src, dst)
 }
 
 /// Copy directory contents excluding specific files
@@ -114,6 +488,25 @@ pub fn remove_file_silent(path: &Path) -> io::Result<()> {
     }
 }
 
+/// Delete an original the user asked to be rid of, or move it to the OS
+/// trash with `trash`, so that it can still be restored
+pub fn remove_original(path: &Path, trash: bool) -> JcResult<()> {
+    if trash {
+        trash::delete(path).map_err(|e| {
+            JcError::Other(format!(
+                "Failed to move {} to the trash: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        info!("Moved {} to the trash", path.display());
+    } else {
+        fs::remove_file(path)?;
+        info!("Removed {}", path.display());
+    }
+    Ok(())
+}
+
 /// Create temporary directory with prefix
 pub fn create_temp_dir(prefix: &str) -> JcResult<PathBuf> {
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -127,15 +520,159 @@ pub fn create_temp_dir(prefix: &str) -> JcResult<PathBuf> {
     let temp_path = PathBuf::from(dir_name);
 
     fs::create_dir(&temp_path).map_err(|e| JcError::TempDirFailed(e.to_string()))?;
+    tempdirs::record(&temp_path);
 
     Ok(temp_path)
 }
 
-/// Create a temporary directory for decompression work in /tmp
+/// Create a temporary directory for decompression work in the system temp
+/// dir (`TMPDIR`, or /tmp)
 /// Returns a TempDir that will be automatically cleaned up when dropped
 pub fn create_decompress_temp_dir() -> JcResult<TempDir> {
-    TempDir::new_in("/tmp")
-        .map_err(|e| JcError::TempDirFailed(format!("Failed to create temp directory: {}", e)))
+    create_scratch_dir(None, None)
+}
+
+/// Create a scratch directory for unpacking, removed when dropped
+///
+/// It goes in `base` (`--tempdir`) when given, else in `TMPDIR` when set,
+/// else as a hidden directory in `near` (the destination), so results can
+/// be renamed into place instead of copied. Without any of these, or when
+/// `near` is not writable, the system temp dir is used.
+pub fn create_scratch_dir(base: Option<&Path>, near: Option<&Path>) -> JcResult<TempDir> {
+    let dir = scratch_dir_in(base, near)?;
+    tempdirs::record(dir.path());
+    Ok(dir)
+}
+
+fn scratch_dir_in(base: Option<&Path>, near: Option<&Path>) -> JcResult<TempDir> {
+    let failed =
+        |e: io::Error| JcError::TempDirFailed(format!("Failed to create temp directory: {}", e));
+    // Named to be recognized by `jcz cleanup` if a crash leaves it behind
+    let builder = || {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(SCRATCH_PREFIX);
+        builder
+    };
+
+    if let Some(base) = base {
+        return builder().tempdir_in(base).map_err(|e| {
+            JcError::TempDirFailed(format!(
+                "Failed to create temp directory in {}: {}",
+                base.display(),
+                e
+            ))
+        });
+    }
+
+    if std::env::var_os("TMPDIR").is_none() {
+        if let Some(near) = near {
+            if let Ok(dir) = builder().tempdir_in(near) {
+                return Ok(dir);
+            }
+        }
+    }
+
+    builder().tempdir_in(std::env::temp_dir()).map_err(failed)
+}
+
+/// Absolute path of `path` with symlinks resolved
+///
+/// On Windows `canonicalize` returns `\\?\`-prefixed paths, which many
+/// programs reject; the prefix is dropped unless the path is too long to
+/// work without it.
+pub fn canonical_path(path: &Path) -> io::Result<PathBuf> {
+    Ok(strip_verbatim(fs::canonicalize(path)?))
+}
+
+#[cfg(windows)]
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    /// Longest path Win32 accepts without the verbatim prefix
+    const MAX_PATH: usize = 260;
+
+    let Some(text) = path.to_str() else {
+        return path;
+    };
+    let stripped = if let Some(share) = text.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", share)
+    } else if let Some(local) = text.strip_prefix(r"\\?\") {
+        if local.as_bytes().get(1) != Some(&b':') {
+            return path;
+        }
+        local.to_string()
+    } else {
+        return path;
+    };
+    if stripped.len() < MAX_PATH {
+        PathBuf::from(stripped)
+    } else {
+        path
+    }
+}
+
+#[cfg(not(windows))]
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Path stored as raw bytes in an archive or manifest
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// Windows names are Unicode; archives written elsewhere are taken as UTF-8
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Raw bytes of a relative path, `/`-separated on every platform
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    std::borrow::Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    let parts: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    std::borrow::Cow::Owned(parts.join("/").into_bytes())
+}
+
+/// Whether `a` and `b` are on the same filesystem, so a rename between
+/// them works
+#[cfg(unix)]
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+/// Without device numbers, paths on the same drive or share count as one
+/// filesystem
+#[cfg(not(unix))]
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::path::Component;
+
+    let volume = |path: &Path| match path.canonicalize().ok()?.components().next()? {
+        Component::Prefix(prefix) => Some(prefix.as_os_str().to_ascii_lowercase()),
+        _ => None,
+    };
+    matches!((volume(a), volume(b)), (Some(a), Some(b)) if a == b)
+}
+
+/// Directory `path` is in, `.` for a bare name
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
 }
 
 /// Copy a file to a target directory, preserving the filename
@@ -152,7 +689,241 @@ pub fn copy_to_dir(source: &Path, target_dir: &Path) -> JcResult<PathBuf> {
         return Ok(dest_path);
     }
 
-    fs::copy(source, &dest_path).map_err(|e| JcError::Io(e))?;
+    fs::copy(source, &dest_path).map_err(JcError::Io)?;
 
     Ok(dest_path)
 }
+
+/// Locate what an archive extracted into a working directory
+///
+/// The copied archive itself is removed first. Returns the single extracted
+/// entry, a directory named after the archive, or the working directory
+/// itself when several loose entries were extracted.
+pub fn find_extracted_output(working_dir: &Path, archive: &Path) -> JcResult<PathBuf> {
+    remove_file_silent(archive)?;
+
+    let entries: Vec<PathBuf> = fs::read_dir(working_dir)
+        .map_err(JcError::Io)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+
+    if entries.len() == 1 {
+        return Ok(entries[0].clone());
+    }
+
+    let base_name = archive.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    if let Some(dir) = entries
+        .iter()
+        .find(|p| p.is_dir() && p.file_name().and_then(|s| s.to_str()) == Some(base_name))
+    {
+        return Ok(dir.clone());
+    }
+
+    if !entries.is_empty() {
+        return Ok(working_dir.to_path_buf());
+    }
+
+    Ok(archive.with_extension(""))
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `dir`
+#[cfg(unix)]
+pub fn available_space(dir: &Path) -> JcResult<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .map_err(|_| JcError::Other(format!("Invalid path: {}", dir.display())))?;
+    // SAFETY: statvfs writes only into the zeroed struct we own
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(JcError::Io(io::Error::last_os_error()));
+    }
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_dir: &Path) -> JcResult<u64> {
+    Ok(u64::MAX)
+}
+
+/// Flush an extracted output to stable storage according to the durability policy
+pub fn sync_output(path: &Path, durability: Durability) -> JcResult<()> {
+    match durability {
+        Durability::Lazy => return Ok(()),
+        Durability::Default => sync_path(path)?,
+        Durability::Full => sync_tree(path)?,
+    }
+
+    // Make the directory entry (the "rename" into place) durable as well
+    Ok(sync_path(parent_dir(path))?)
+}
+
+/// Fsync a single file or directory (symlinks are skipped)
+fn sync_path(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+
+    // Directories cannot be opened for syncing on every platform
+    if metadata.is_dir() && !cfg!(unix) {
+        return Ok(());
+    }
+
+    fs::File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// Fsync a file, or a directory and everything below it (children first)
+fn sync_tree(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        for entry in fs::read_dir(path)? {
+            sync_tree(&entry?.path())?;
+        }
+    }
+    sync_path(path)
+}
+
+/// `name` in jcz's configuration directory: `$XDG_CONFIG_HOME/jcz`, else
+/// `~/.config/jcz`
+pub fn user_config_file(name: &str) -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("jcz").join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_output_filename_with_template() {
+        let config = CompressionConfig::new()
+            .with_level(6)
+            .with_name_template(Some("{stem}-{hostname}-L{level}.{ext}".to_string()));
+        let output = generate_output_filename(Path::new("logs/app.log"), "gz", &config).unwrap();
+        assert_eq!(
+            output,
+            PathBuf::from(format!("logs/app-{}-L6.gz", host_label()))
+        );
+
+        let config = config.with_name_template(Some("{name}_{date}.{ext}".to_string()));
+        let output = generate_output_filename(Path::new("home/"), "tar", &config).unwrap();
+        let name = output.to_string_lossy();
+        assert!(
+            name.starts_with("home_") && name.ends_with(".tar"),
+            "{}",
+            name
+        );
+    }
+
+    #[test]
+    fn test_validate_name_template() {
+        assert!(validate_name_template("{stem}-{hostname}-{date}.{ext}").is_ok());
+        assert!(validate_name_template("{stem}-{user}.{ext}").is_err());
+        assert!(validate_name_template("{stem.{ext}").is_err());
+        assert!(validate_name_template("backups/{stem}.{ext}").is_err());
+        assert!(validate_name_template("").is_err());
+    }
+
+    #[test]
+    fn test_disambiguate_name_keeps_extension_chain() {
+        let host = host_label();
+        assert_eq!(
+            disambiguate_name("data_20250101.tar.gz", 1),
+            format!("data_20250101_{}.tar.gz", host)
+        );
+        assert_eq!(
+            disambiguate_name("notes.txt_20250101.gz.jcze", 3),
+            format!("notes.txt_20250101_{}_3.gz.jcze", host)
+        );
+        assert_eq!(disambiguate_name("report.pdf", 0), "report.pdf");
+        assert_eq!(
+            disambiguate_name("report.pdf", 2),
+            format!("report_{}_2.pdf", host)
+        );
+    }
+
+    #[test]
+    fn test_move_file_no_clobber_keeps_existing() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        fs::write(dest.join("a_1.gz"), b"first").unwrap();
+
+        let source = dir.path().join("a_1.gz");
+        fs::write(&source, b"second").unwrap();
+
+        let placed = move_file_no_clobber(&source, &dest).unwrap();
+        assert_ne!(placed, dest.join("a_1.gz"));
+        assert_eq!(fs::read(dest.join("a_1.gz")).unwrap(), b"first");
+        assert_eq!(fs::read(&placed).unwrap(), b"second");
+        assert!(!source.exists());
+    }
+
+    #[test]
+    fn test_move_atomic_renames_on_same_filesystem() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("staged");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a"), "new").unwrap();
+        let dst = dir.path().join("out");
+        fs::create_dir(&dst).unwrap();
+        fs::write(dst.join("old"), "old").unwrap();
+
+        assert!(move_atomic(&src, &dst).unwrap());
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dst.join("a")).unwrap(), "new");
+        assert!(!dst.join("old").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_file_renames_in_place() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        let source = dir.path().join("big.xz");
+        fs::write(&source, b"data").unwrap();
+        let inode = fs::metadata(&source).unwrap().ino();
+
+        // The same file, not a copy of it
+        let moved = move_file(&source, &dest).unwrap();
+        assert_eq!(fs::metadata(&moved).unwrap().ino(), inode);
+        assert!(!source.exists());
+    }
+
+    #[test]
+    fn test_copy_atomic_replaces_destination() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("src");
+        fs::write(&src, "new").unwrap();
+        let dst = dir.path().join("dst");
+        fs::write(&dst, "old").unwrap();
+
+        copy_atomic(&src, &dst).unwrap();
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "new");
+        assert!(src.exists());
+        // No staged copy is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_create_scratch_dir_in_base() {
+        let dir = TempDir::new().unwrap();
+        let scratch = create_scratch_dir(Some(dir.path()), None).unwrap();
+        assert_eq!(scratch.path().parent(), Some(dir.path()));
+        assert!(same_filesystem(scratch.path(), dir.path()));
+
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            create_scratch_dir(Some(&missing), None),
+            Err(JcError::TempDirFailed(_))
+        ));
+    }
+}
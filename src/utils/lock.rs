@@ -0,0 +1,249 @@
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::error::{JcError, JcResult};
+use crate::utils::debug;
+
+/// Directory (under the system temp dir) holding extraction lock files,
+/// suffixed with the user id on Unix
+const LOCK_DIR_NAME: &str = "jcz-locks";
+
+/// Cooperative lock serializing extractions of one archive into one directory
+///
+/// The lock file lives in a per-user directory (see `lock_dir`), keyed by
+/// the canonical archive and destination paths, so destinations are never
+/// littered.
+/// It also records the last completed extraction: a process that was
+/// waiting while another finished the same job can return that result
+/// instead of extracting again. The lock is released on drop.
+#[derive(Debug)]
+pub struct ExtractionLock {
+    file: File,
+    started: u128,
+    archive_stamp: String,
+}
+
+impl ExtractionLock {
+    /// Block until no other jcz process is extracting `archive` into `dest_dir`
+    pub fn acquire(archive: &Path, dest_dir: &Path) -> JcResult<Self> {
+        // Taken before waiting: completions after this point were concurrent
+        let started = now_nanos();
+
+        let archive = archive.canonicalize()?;
+        let dest_dir = dest_dir.canonicalize()?;
+        let metadata = fs::metadata(&archive)?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let archive_stamp = format!("{}:{}", metadata.len(), mtime);
+
+        let mut hasher = Sha256::new();
+        hasher.update(archive.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(dest_dir.as_os_str().as_encoded_bytes());
+        let key: String = hasher.finalize()[..16]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let lock_path = lock_dir()?.join(format!("{}.lock", key));
+        let file = open_lock_file(&lock_path)?;
+
+        debug!(
+            "Waiting for extraction lock {} ({} -> {})",
+            lock_path.display(),
+            archive.display(),
+            dest_dir.display()
+        );
+        file.lock().map_err(|e| {
+            JcError::Other(format!("Failed to lock {}: {}", lock_path.display(), e))
+        })?;
+
+        Ok(Self {
+            file,
+            started,
+            archive_stamp,
+        })
+    }
+
+    /// Output of an identical extraction that finished while we waited
+    ///
+    /// Returns `None` when the last recorded run predates this process, used
+    /// a different version of the archive, or its output is gone.
+    pub fn completed_output(&mut self) -> JcResult<Option<PathBuf>> {
+        let mut record = String::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_string(&mut record)?;
+
+        let mut lines = record.lines();
+        let (Some(stamp), Some(completed), Some(output)) =
+            (lines.next(), lines.next(), lines.next())
+        else {
+            return Ok(None);
+        };
+
+        let concurrent = completed
+            .parse::<u128>()
+            .is_ok_and(|completed| completed >= self.started);
+        let output = PathBuf::from(output);
+
+        if stamp == self.archive_stamp && concurrent && output.exists() {
+            Ok(Some(output))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Record a finished extraction for processes still waiting on the lock
+    pub fn record_completion(&mut self, output: &Path) -> JcResult<()> {
+        // Waiters may run from a different working directory
+        let output = output.canonicalize()?;
+        let record = format!(
+            "{}\n{}\n{}\n",
+            self.archive_stamp,
+            now_nanos(),
+            output.display()
+        );
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(record.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Directory holding this user's lock files: `$XDG_RUNTIME_DIR/jcz/locks`
+/// when set, else `jcz-locks-<uid>` in the system temp dir
+///
+/// It is created 0700. One that is a symlink, is owned by another user or
+/// is open to others is refused, since they could plant links in it.
+#[cfg(unix)]
+fn lock_dir() -> JcResult<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    let uid = unsafe { libc::geteuid() };
+    let dir = match crate::utils::tempdirs::records_dir() {
+        Some(records) => records.join("locks"),
+        None => std::env::temp_dir().join(format!("{}-{}", LOCK_DIR_NAME, uid)),
+    };
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(JcError::Other(format!(
+            "Refusing to use lock directory {}: not a private directory of this user",
+            dir.display()
+        )));
+    }
+    Ok(dir)
+}
+
+/// Directory holding this user's lock files, in the per-user temp dir
+#[cfg(not(unix))]
+fn lock_dir() -> JcResult<PathBuf> {
+    let dir = std::env::temp_dir().join(LOCK_DIR_NAME);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Open (creating) the lock file at `path`, never through a symlink
+fn open_lock_file(path: &Path) -> JcResult<File> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    Ok(options.open(path)?)
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_waiter_sees_concurrent_completion() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("data.tar");
+        let output = dir.path().join("data");
+        fs::write(&archive, b"archive").unwrap();
+        fs::create_dir(&output).unwrap();
+
+        let mut first = ExtractionLock::acquire(&archive, dir.path()).unwrap();
+        assert_eq!(first.completed_output().unwrap(), None);
+
+        let (archive2, dest2) = (archive.clone(), dir.path().to_path_buf());
+        let waiter = thread::spawn(move || {
+            let mut second = ExtractionLock::acquire(&archive2, &dest2).unwrap();
+            second.completed_output().unwrap()
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        first.record_completion(&output).unwrap();
+        drop(first);
+
+        assert_eq!(waiter.join().unwrap(), Some(output));
+    }
+
+    #[test]
+    fn test_later_run_ignores_earlier_completion() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("data.tar");
+        let output = dir.path().join("data");
+        fs::write(&archive, b"archive").unwrap();
+        fs::create_dir(&output).unwrap();
+
+        let mut first = ExtractionLock::acquire(&archive, dir.path()).unwrap();
+        first.record_completion(&output).unwrap();
+        drop(first);
+
+        let mut second = ExtractionLock::acquire(&archive, dir.path()).unwrap();
+        assert_eq!(second.completed_output().unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_file_is_not_followed() {
+        let dir = TempDir::new().unwrap();
+        let victim = dir.path().join("victim");
+        fs::write(&victim, b"keep me").unwrap();
+        let link = dir.path().join("planted.lock");
+        std::os::unix::fs::symlink(&victim, &link).unwrap();
+
+        assert!(open_lock_file(&link).is_err());
+        assert_eq!(fs::read(&victim).unwrap(), b"keep me");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = lock_dir().unwrap();
+        let mode = fs::symlink_metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
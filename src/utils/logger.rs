@@ -1,11 +1,56 @@
-use env_logger::{Builder, Env};
-use log::LevelFilter;
+use env_logger::fmt::Color;
+use env_logger::{Builder, Env, Target, WriteStyle};
+use log::{Level, LevelFilter};
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 use std::sync::OnceLock;
 
+use crate::core::error::{JcError, JcResult};
+
 static LOGGER_INIT: OnceLock<()> = OnceLock::new();
 
-/// Initialize the logging system based on JCDBG environment variable
-pub fn init_logger() {
+/// Log level names accepted by `--log-level`
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Parse a log level name, as taken by `--log-level` and `JCDBG`
+pub fn parse_log_level(name: &str) -> Option<LevelFilter> {
+    match name.to_lowercase().as_str() {
+        "off" => Some(LevelFilter::Off),
+        "error" => Some(LevelFilter::Error),
+        "warn" => Some(LevelFilter::Warn),
+        "info" => Some(LevelFilter::Info),
+        "debug" => Some(LevelFilter::Debug),
+        "trace" => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// Initialize the logging system
+///
+/// `level` (from `-q` or `--log-level`) takes precedence over the JCDBG
+/// environment variable, and messages are appended to `log_file`, without
+/// colors, instead of going to stderr when one is given. On a terminal,
+/// messages are printed as plain status lines with highlighted warnings
+/// and errors instead of timestamped records; `color` is false for
+/// `--no-color`.
+pub fn init_logger(
+    level: Option<LevelFilter>,
+    log_file: Option<&Path>,
+    color: bool,
+) -> JcResult<()> {
+    let file = log_file
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| {
+                    JcError::Other(format!("Cannot open log file {}: {}", path.display(), e))
+                })
+        })
+        .transpose()?;
+
     LOGGER_INIT.get_or_init(|| {
         let env = Env::default()
             .filter_or("JCDBG", "info")
@@ -13,25 +58,59 @@ pub fn init_logger() {
 
         let mut builder = Builder::from_env(env);
 
-        // Map JCDBG values to log levels
-        let level = std::env::var("JCDBG")
-            .ok()
-            .and_then(|val| match val.to_lowercase().as_str() {
-                "error" => Some(LevelFilter::Error),
-                "warn" => Some(LevelFilter::Warn),
-                "info" => Some(LevelFilter::Info),
-                "debug" => Some(LevelFilter::Debug),
-                _ => None,
+        // An explicit level wins over JCDBG
+        let level = level
+            .or_else(|| {
+                std::env::var("JCDBG")
+                    .ok()
+                    .and_then(|val| parse_log_level(&val))
             })
             .unwrap_or(LevelFilter::Info);
 
+        if let Some(file) = file {
+            builder
+                .target(Target::Pipe(Box::new(file)))
+                .write_style(WriteStyle::Never);
+        } else if io::stderr().is_terminal() {
+            builder.format(|buf, record| {
+                let (label, color) = match record.level() {
+                    Level::Info => return writeln!(buf, "{}", record.args()),
+                    Level::Error => ("error", Color::Red),
+                    Level::Warn => ("warning", Color::Yellow),
+                    Level::Debug | Level::Trace => ("debug", Color::Cyan),
+                };
+                let mut style = buf.style();
+                style.set_color(color).set_bold(true);
+                writeln!(buf, "{}: {}", style.value(label), record.args())
+            });
+        }
+        if !color {
+            builder.write_style(WriteStyle::Never);
+        }
+
         builder
             .filter_level(level)
             .format_module_path(false)
             .format_target(false)
             .init();
     });
+    Ok(())
 }
 
 // Re-export log macros for convenience
-pub use log::{debug, error, info};
+pub use log::{debug, error, info, warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level("DEBUG"), Some(LevelFilter::Debug));
+        assert_eq!(parse_log_level("off"), Some(LevelFilter::Off));
+        assert_eq!(parse_log_level("loud"), None);
+        assert!(LOG_LEVELS
+            .iter()
+            .all(|name| parse_log_level(name).is_some()));
+    }
+}
@@ -1,13 +1,37 @@
+pub mod backup;
+pub mod bufio;
 pub mod fs;
+pub mod lock;
 pub mod logger;
-pub mod prompt;
+pub mod parallel;
+pub mod priority;
+pub mod progress;
+pub mod remote;
+pub mod retry;
+pub mod tempdirs;
+pub mod throttle;
 pub mod timestamp;
+pub mod transform;
 pub mod validation;
+pub mod xattrs;
 
+pub use backup::BackupMode;
 pub use fs::{
-    copy_recursive, copy_to_dir, create_decompress_temp_dir, create_temp_dir,
-    generate_output_filename, move_file, move_file_if_needed, remove_file_silent,
+    available_space, canonical_path, copy_to_dir, copy_tree, create_decompress_temp_dir,
+    create_scratch_dir, create_temp_dir, find_extracted_output, generate_output_filename,
+    is_inside_without_symlinks, move_atomic, move_file, move_file_if_needed, move_file_no_clobber,
+    path_from_bytes, path_to_bytes, place_output, remove_file_silent, remove_original,
+    same_filesystem, sync_output, user_config_file,
 };
-pub use logger::{debug, error, info, init_logger};
-pub use prompt::prompt_overwrite;
-pub use validation::{validate_input_files, validate_move_to};
+pub use logger::{debug, error, info, init_logger, parse_log_level, warn, LOG_LEVELS};
+pub use parallel::{run_limited, run_unless_failed, tool_threads, FailFast};
+pub use priority::{set_io_priority, set_niceness, IoPriority};
+pub use progress::{is_cancelled_io, run_watched, CancellationToken, ProgressSink, Watched};
+pub use remote::{
+    download, is_download_url, is_remote_url, is_sha256_hex, require_download_tool, RemoteTarget,
+    Upload,
+};
+pub use retry::RetryPolicy;
+pub use throttle::{parse_size, RateLimit, Throttled};
+pub use transform::PathTransform;
+pub use validation::{validate_input_files, validate_move_to, FileFilter};
@@ -0,0 +1,126 @@
+//! Thread limits and failure policy for batch operations (`--threads`,
+//! `--fail-fast`)
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::core::error::{JcError, JcResult};
+use crate::utils::warn;
+
+/// Run `f`, with its rayon work on at most `threads` threads
+///
+/// Without a limit the global pool (one thread per core) is used.
+pub fn run_limited<T: Send>(threads: Option<usize>, f: impl FnOnce() -> T + Send) -> T {
+    let Some(threads) = threads else {
+        return f();
+    };
+
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(f),
+        Err(e) => {
+            warn!("Failed to create a {}-thread pool: {}", threads, e);
+            f()
+        }
+    }
+}
+
+/// Threads each external tool may use when `jobs` files are processed at
+/// once under a limit of `threads`
+pub fn tool_threads(threads: usize, jobs: usize) -> usize {
+    (threads / jobs.clamp(1, threads.max(1))).max(1)
+}
+
+/// Stops a batch after its first failure (`--fail-fast`)
+///
+/// Clones share the state, so every file of a run stops, including files
+/// in other batches of the same run. Jobs already running are finished;
+/// jobs not yet started fail with `JcError::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct FailFast {
+    failed: Arc<AtomicBool>,
+}
+
+impl FailFast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a job has failed
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// Run `job`, unless `fail_fast` is set and an earlier job failed
+pub fn run_unless_failed<T>(
+    fail_fast: Option<&FailFast>,
+    job: impl FnOnce() -> JcResult<T>,
+) -> JcResult<T> {
+    let Some(fail_fast) = fail_fast else {
+        return job();
+    };
+    if fail_fast.has_failed() {
+        return Err(JcError::Cancelled);
+    }
+
+    let result = job();
+    if result.is_err() {
+        fail_fast.failed.store(true, Ordering::Relaxed);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_run_limited_caps_pool_size() {
+        let seen = run_limited(Some(2), rayon::current_num_threads);
+        assert_eq!(seen, 2);
+
+        let indices: Vec<usize> = run_limited(Some(1), || {
+            (0..8)
+                .into_par_iter()
+                .map(|_| rayon::current_thread_index().unwrap_or(0))
+                .collect()
+        });
+        assert!(indices.iter().all(|&i| i == 0));
+    }
+
+    #[test]
+    fn test_fail_fast_cancels_later_jobs() {
+        let fail_fast = FailFast::new();
+        let results: Vec<JcResult<usize>> = run_limited(Some(1), || {
+            (0..4)
+                .into_par_iter()
+                .map(|i| {
+                    run_unless_failed(Some(&fail_fast.clone()), || match i {
+                        1 => Err(JcError::Other("failed".to_string())),
+                        _ => Ok(i),
+                    })
+                })
+                .collect()
+        });
+
+        assert_eq!(results[0].as_ref().unwrap(), &0);
+        assert!(matches!(results[1], Err(JcError::Other(_))));
+        assert!(results[2..]
+            .iter()
+            .all(|r| matches!(r, Err(JcError::Cancelled))));
+
+        // Without the policy every job runs
+        let result = run_unless_failed(None, || Ok(1));
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_tool_threads_share() {
+        assert_eq!(tool_threads(8, 1), 8);
+        assert_eq!(tool_threads(8, 2), 4);
+        assert_eq!(tool_threads(8, 3), 2);
+        assert_eq!(tool_threads(4, 100), 1);
+        assert_eq!(tool_threads(1, 0), 1);
+    }
+}
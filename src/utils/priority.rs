@@ -0,0 +1,122 @@
+//! CPU and I/O scheduling priority (`--nice`, `--ionice`)
+//!
+//! Both are per-thread on Linux and inherited by new threads and child
+//! processes, so they must be set before any worker threads start.
+
+use crate::core::error::{JcError, JcResult};
+#[cfg(unix)]
+use crate::utils::debug;
+
+/// I/O scheduling class for `--ionice`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Only get disk time when no other process needs it
+    Idle,
+
+    /// Normal scheduling at level 0 (highest) to 7 (lowest)
+    BestEffort(u8),
+}
+
+impl IoPriority {
+    /// Parse `idle`, `best-effort` (level 7) or `best-effort:N`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.split_once(':') {
+            None if name == "idle" => Some(IoPriority::Idle),
+            None if name == "best-effort" => Some(IoPriority::BestEffort(7)),
+            Some(("best-effort", level)) => match level.parse() {
+                Ok(level @ 0..=7) => Some(IoPriority::BestEffort(level)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Set the niceness of the calling thread, from -20 (favoured) to 19
+///
+/// Lowering it below the current value usually requires root.
+#[cfg(unix)]
+pub fn set_niceness(niceness: i32) -> JcResult<()> {
+    // SAFETY: setpriority only reads its integer arguments
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) };
+    if result != 0 {
+        return Err(JcError::Other(format!(
+            "Failed to set niceness {}: {}",
+            niceness,
+            std::io::Error::last_os_error()
+        )));
+    }
+    debug!("Niceness set to {}", niceness);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_niceness(_niceness: i32) -> JcResult<()> {
+    Err(JcError::Other(
+        "--nice is not supported on this platform".to_string(),
+    ))
+}
+
+/// Set the I/O scheduling class of the calling thread
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(priority: IoPriority) -> JcResult<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_SHIFT: u32 = 13;
+    const IOPRIO_CLASS_BE: libc::c_long = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+
+    let value = match priority {
+        IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        IoPriority::BestEffort(level) => {
+            (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | libc::c_long::from(level)
+        }
+    };
+    // SAFETY: ioprio_set only reads its integer arguments
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) };
+    if result != 0 {
+        return Err(JcError::Other(format!(
+            "Failed to set I/O priority: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    debug!("I/O priority set to {:?}", priority);
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_priority(_priority: IoPriority) -> JcResult<()> {
+    Err(JcError::Other(
+        "--ionice is only supported on Linux".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_priority_names() {
+        assert_eq!(IoPriority::from_name("idle"), Some(IoPriority::Idle));
+        assert_eq!(
+            IoPriority::from_name("best-effort"),
+            Some(IoPriority::BestEffort(7))
+        );
+        assert_eq!(
+            IoPriority::from_name("best-effort:3"),
+            Some(IoPriority::BestEffort(3))
+        );
+        assert_eq!(IoPriority::from_name("best-effort:8"), None);
+        assert_eq!(IoPriority::from_name("realtime"), None);
+    }
+
+    #[test]
+    fn test_lowering_priority_in_a_thread() {
+        // Per-thread, so this does not affect other tests
+        std::thread::spawn(|| {
+            set_niceness(19).unwrap();
+            set_io_priority(IoPriority::Idle).unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Progress reporting and cancellation for embedding applications
+//!
+//! Operations tell a `ProgressSink` when each input starts and finishes,
+//! and how many bytes of it have been read where data passes through jcz.
+//! A `CancellationToken` is checked before each input and between chunks,
+//! so a long job stops soon after it is cancelled.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::core::error::{JcError, JcResult};
+
+/// Receives progress of an operation; every method defaults to doing
+/// nothing
+///
+/// Files are processed in parallel, so calls for different inputs
+/// interleave and may come from several threads at once.
+pub trait ProgressSink: Send + Sync {
+    /// Work on `input` is starting
+    fn file_started(&self, _input: &Path) {}
+
+    /// `bytes` more of `path` were read
+    ///
+    /// `path` is the file being read, which for directories and compound
+    /// formats is an intermediate archive rather than the input itself.
+    fn bytes_processed(&self, _path: &Path, _bytes: u64) {}
+
+    /// Work on `input` ended, with `error` if it failed
+    fn file_finished(&self, _input: &Path, _error: Option<&JcError>) {}
+}
+
+impl fmt::Debug for dyn ProgressSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressSink(..)")
+    }
+}
+
+/// Cancels a running operation from another thread
+///
+/// Clones share the state. Inputs not yet started fail with
+/// `JcError::Aborted`, and inputs in progress stop at their next chunk
+/// and have their partial outputs removed.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(dead_code)]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `Err(JcError::Aborted)` once cancelled
+    pub fn check(&self) -> JcResult<()> {
+        if self.is_cancelled() {
+            Err(JcError::Aborted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Payload of the I/O error a cancelled `Watched` returns; converting it
+/// into a `JcError` gives `JcError::Aborted`
+#[derive(Debug)]
+struct CancelledIo;
+
+impl fmt::Display for CancelledIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation cancelled")
+    }
+}
+
+impl Error for CancelledIo {}
+
+/// Whether `err` came from a cancelled `Watched`
+pub fn is_cancelled_io(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<CancelledIo>())
+}
+
+/// Reader or writer that reports its traffic to a `ProgressSink` and
+/// fails once its `CancellationToken` is cancelled
+pub struct Watched<T> {
+    inner: T,
+    path: PathBuf,
+    progress: Option<Arc<dyn ProgressSink>>,
+    cancel: Option<CancellationToken>,
+}
+
+impl<T> Watched<T> {
+    /// Wrap `inner`, whose data belongs to `path`
+    pub fn new(
+        inner: T,
+        path: &Path,
+        progress: Option<&Arc<dyn ProgressSink>>,
+        cancel: Option<&CancellationToken>,
+    ) -> Self {
+        Watched {
+            inner,
+            path: path.to_path_buf(),
+            progress: progress.cloned(),
+            cancel: cancel.cloned(),
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        match &self.cancel {
+            Some(cancel) if cancel.is_cancelled() => Err(io::Error::other(CancelledIo)),
+            _ => Ok(()),
+        }
+    }
+
+    fn report(&self, bytes: usize) {
+        if let (Some(progress), 1..) = (&self.progress, bytes) {
+            progress.bytes_processed(&self.path, bytes as u64);
+        }
+    }
+}
+
+impl<R: Read> Read for Watched<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        let n = self.inner.read(buf)?;
+        self.report(n);
+        Ok(n)
+    }
+}
+
+impl<S: Seek> Seek for Watched<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.check()?;
+        self.inner.seek(pos)
+    }
+}
+
+impl<W: Write> Write for Watched<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check()?;
+        let n = self.inner.write(buf)?;
+        self.report(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Run `job` for `input`, unless `cancel` was cancelled, telling
+/// `progress` when it starts and ends
+pub fn run_watched<T>(
+    input: &Path,
+    progress: Option<&Arc<dyn ProgressSink>>,
+    cancel: Option<&CancellationToken>,
+    job: impl FnOnce() -> JcResult<T>,
+) -> JcResult<T> {
+    if let Some(cancel) = cancel {
+        cancel.check()?;
+    }
+    let Some(progress) = progress else {
+        return job();
+    };
+
+    progress.file_started(input);
+    let result = job();
+    progress.file_finished(input, result.as_ref().err());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ProgressSink for Recorder {
+        fn file_started(&self, input: &Path) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("start {}", input.display()));
+        }
+
+        fn bytes_processed(&self, path: &Path, bytes: u64) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", path.display(), bytes));
+        }
+
+        fn file_finished(&self, input: &Path, error: Option<&JcError>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("end {} {}", input.display(), error.is_none()));
+        }
+    }
+
+    #[test]
+    fn test_watched_reports_and_cancels() {
+        let recorder = Arc::new(Recorder::default());
+        let progress: Arc<dyn ProgressSink> = recorder.clone();
+        let cancel = CancellationToken::new();
+
+        let result = run_watched(Path::new("in"), Some(&progress), Some(&cancel), || {
+            let mut reader = Watched::new(
+                &b"hello"[..],
+                Path::new("in"),
+                Some(&progress),
+                Some(&cancel),
+            );
+            let mut buf = [0u8; 3];
+            reader.read_exact(&mut buf)?;
+            cancel.cancel();
+            Ok(reader.read(&mut buf)?)
+        });
+
+        assert!(matches!(result, Err(JcError::Aborted)));
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            ["start in", "in 3", "end in false"]
+        );
+
+        // Nothing starts once cancelled
+        let result = run_watched(Path::new("next"), Some(&progress), Some(&cancel), || Ok(()));
+        assert!(matches!(result, Err(JcError::Aborted)));
+        assert_eq!(recorder.0.lock().unwrap().len(), 3);
+    }
+}
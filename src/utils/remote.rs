@@ -0,0 +1,437 @@
+//! Sending finished outputs to remote storage (`--upload`, `-C URL`) and
+//! fetching archives to decompress (`jcz -d https://...`)
+//!
+//! Like the compression formats, transfers are left to the standard
+//! tools: `s3://bucket/prefix/` goes through `aws s3 cp`, `sftp://host/dir/`
+//! through `sftp` in batch mode (key authentication only), and `http://`
+//! and `https://` URLs are PUT with `curl`. The archive is streamed from
+//! where it was written, and each output is stored under its file name
+//! below the URL. Downloads are read from `curl`'s stdout and hashed as
+//! they arrive.
+
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::compressors::{installed, spawn_error};
+use crate::core::error::{JcError, JcResult};
+use crate::utils::{debug, info, remove_file_silent, RetryPolicy};
+
+/// Where outputs are uploaded to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteTarget {
+    /// `s3://bucket/prefix`
+    S3 { bucket: String, prefix: String },
+
+    /// `sftp://[user@]host[:port]/dir`
+    Sftp {
+        host: String,
+        port: Option<u16>,
+        dir: String,
+    },
+
+    /// `http(s)://...`, PUT below this URL
+    Http { url: String },
+}
+
+/// Whether `text` is a URL jcz can upload to rather than a local path
+pub fn is_remote_url(text: &str) -> bool {
+    ["s3://", "sftp://", "http://", "https://"]
+        .iter()
+        .any(|scheme| text.starts_with(scheme))
+}
+
+impl RemoteTarget {
+    /// Parse an upload URL
+    pub fn parse(url: &str) -> JcResult<Self> {
+        let invalid = |why: &str| JcError::Usage(format!("Invalid upload URL '{}': {}", url, why));
+
+        if let Some(rest) = url.strip_prefix("s3://") {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return Err(invalid("no bucket"));
+            }
+            Ok(RemoteTarget::S3 {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_matches('/').to_string(),
+            })
+        } else if let Some(rest) = url.strip_prefix("sftp://") {
+            let (authority, dir) = match rest.find('/') {
+                Some(slash) => (&rest[..slash], &rest[slash..]),
+                None => (rest, ""),
+            };
+            let (host, port) = match authority.rsplit_once(':') {
+                Some((host, port)) => {
+                    let port = port.parse().map_err(|_| invalid("invalid port"))?;
+                    (host, Some(port))
+                }
+                None => (authority, None),
+            };
+            if host.is_empty() || host.ends_with('@') {
+                return Err(invalid("no host"));
+            }
+            Ok(RemoteTarget::Sftp {
+                host: host.to_string(),
+                port,
+                dir: dir.trim_end_matches('/').to_string(),
+            })
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            let host = url.split_once("://").map(|(_, rest)| rest).unwrap_or("");
+            if host.is_empty() || host.starts_with('/') {
+                return Err(invalid("no host"));
+            }
+            Ok(RemoteTarget::Http {
+                url: url.trim_end_matches('/').to_string(),
+            })
+        } else {
+            Err(invalid("expected s3://, sftp://, http:// or https://"))
+        }
+    }
+
+    /// Program doing the transfer
+    pub fn program(&self) -> &'static str {
+        match self {
+            RemoteTarget::S3 { .. } => "aws",
+            RemoteTarget::Sftp { .. } => "sftp",
+            RemoteTarget::Http { .. } => "curl",
+        }
+    }
+
+    /// Fail before any work when the transfer tool is missing
+    pub fn require_tool(&self) -> JcResult<()> {
+        require(self.program())
+    }
+
+    /// URL that a file called `name` is stored at
+    pub fn location(&self, name: &str) -> String {
+        match self {
+            RemoteTarget::S3 { bucket, prefix } if prefix.is_empty() => {
+                format!("s3://{}/{}", bucket, name)
+            }
+            RemoteTarget::S3 { bucket, prefix } => format!("s3://{}/{}/{}", bucket, prefix, name),
+            RemoteTarget::Sftp { host, port, dir } => match port {
+                Some(port) => format!("sftp://{}:{}{}/{}", host, port, dir, name),
+                None => format!("sftp://{}{}/{}", host, dir, name),
+            },
+            RemoteTarget::Http { url } => format!("{}/{}", url, percent_encode(name)),
+        }
+    }
+
+    /// Send the file at `path`, returning the URL it was stored at
+    pub fn upload(&self, path: &Path) -> JcResult<String> {
+        if !path.is_file() {
+            return Err(JcError::Other(format!(
+                "Cannot upload {}: only files can be uploaded",
+                path.display()
+            )));
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let destination = self.location(&name);
+        info!("Uploading {} to {}", path.display(), destination);
+
+        let mut batch = None;
+        let mut cmd = Command::new(self.program());
+        match self {
+            RemoteTarget::S3 { .. } => {
+                let size = path.metadata()?.len();
+                cmd.args(["s3", "cp", "-", &destination, "--only-show-errors"])
+                    .arg("--expected-size")
+                    .arg(size.to_string())
+                    .stdin(File::open(path)?);
+            }
+            RemoteTarget::Sftp { host, port, dir } => {
+                cmd.args(["-q", "-b", "-"]);
+                if let Some(port) = port {
+                    cmd.arg("-P").arg(port.to_string());
+                }
+                cmd.arg(host).stdin(Stdio::piped());
+                let remote = if dir.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", dir, name)
+                };
+                batch = Some(format!(
+                    "put {} {}\n",
+                    sftp_quote(&path.to_string_lossy()),
+                    sftp_quote(&remote)
+                ));
+            }
+            RemoteTarget::Http { .. } => {
+                cmd.args(["--fail", "--silent", "--show-error", "--upload-file"])
+                    .arg(path)
+                    .arg(&destination);
+            }
+        }
+        debug!("Running {:?}", cmd);
+
+        let mut child = cmd
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| spawn_error(self.program(), e))?;
+        if let (Some(batch), Some(mut stdin)) = (batch, child.stdin.take()) {
+            stdin.write_all(batch.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(JcError::UploadFailed {
+                destination,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(destination)
+    }
+}
+
+/// Where each output of a run is sent
+#[derive(Debug, Clone)]
+pub struct Upload {
+    pub target: RemoteTarget,
+
+    /// Delete the local output once it is uploaded (`-C URL`)
+    pub remove_local: bool,
+
+    /// Upload again when it fails
+    pub retry: RetryPolicy,
+}
+
+impl Upload {
+    /// Upload `output`, returning where it now is: the remote URL when the
+    /// local copy was removed, else `output`
+    pub fn send(&self, output: &Path) -> JcResult<PathBuf> {
+        let destination = self
+            .retry
+            .run(&output.display().to_string(), || self.target.upload(output))?;
+        if self.remove_local {
+            remove_file_silent(output)?;
+            Ok(PathBuf::from(destination))
+        } else {
+            Ok(output.to_path_buf())
+        }
+    }
+}
+
+/// Whether `text` is a URL `jcz -d` can download
+pub fn is_download_url(text: &str) -> bool {
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+/// File name a download from `url` is saved under: its last path
+/// segment, without query or fragment
+pub fn download_name(url: &str) -> JcResult<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let name = percent_decode(path.rsplit('/').next().unwrap_or_default());
+    let host_only = path
+        .split("://")
+        .nth(1)
+        .is_some_and(|rest| !rest.contains('/'));
+    if host_only || name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(JcError::Usage(format!(
+            "Cannot tell the file name of {}: the URL must end in the archive name",
+            url
+        )));
+    }
+    Ok(name)
+}
+
+/// Fail before any work when `curl`, needed for URL inputs, is missing
+pub fn require_download_tool() -> JcResult<()> {
+    require("curl")
+}
+
+fn require(program: &'static str) -> JcResult<()> {
+    if installed(program) {
+        Ok(())
+    } else {
+        Err(spawn_error(program, std::io::ErrorKind::NotFound.into()))
+    }
+}
+
+/// Download `url` into `dir` with `curl`, returning the file written
+///
+/// The data is hashed as it arrives and checked against `sha256` (hex)
+/// when given; on a mismatch the file is removed and
+/// `JcError::IntegrityFailed` returned.
+pub fn download(url: &str, dir: &Path, sha256: Option<&str>) -> JcResult<PathBuf> {
+    let path = dir.join(download_name(url)?);
+    info!("Downloading {}", url);
+
+    let mut child = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error("curl", e))?;
+
+    let mut stdout = child.stdout.take().expect("curl stdout is piped");
+    let mut file = BufWriter::new(File::create(&path)?);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let copied = loop {
+        match stdout.read(&mut buf) {
+            Ok(0) => break file.flush(),
+            Ok(n) => {
+                hasher.update(&buf[..n]);
+                if let Err(e) = file.write_all(&buf[..n]) {
+                    break Err(e);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+    drop(stdout);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        remove_file_silent(&path)?;
+        return Err(JcError::DownloadFailed {
+            url: url.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    copied.inspect_err(|_| {
+        let _ = remove_file_silent(&path);
+    })?;
+
+    let actual: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    debug!("Downloaded {} (sha256 {})", url, actual);
+    if let Some(expected) = sha256 {
+        if !expected.eq_ignore_ascii_case(&actual) {
+            remove_file_silent(&path)?;
+            return Err(JcError::IntegrityFailed(format!(
+                "Checksum mismatch for {}: expected sha256 {}, got {}",
+                url,
+                expected.to_lowercase(),
+                actual
+            )));
+        }
+    }
+    Ok(path)
+}
+
+/// Whether `text` is a SHA-256 digest in hex
+pub fn is_sha256_hex(text: &str) -> bool {
+    text.len() == 64 && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// `text` as a double-quoted sftp batch argument
+fn sftp_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Decode `%XX` escapes, keeping malformed ones as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escape everything but unreserved URL characters
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_location() {
+        let s3 = RemoteTarget::parse("s3://backups/nightly/").unwrap();
+        assert_eq!(s3.location("a.tgz"), "s3://backups/nightly/a.tgz");
+        assert_eq!(
+            RemoteTarget::parse("s3://backups")
+                .unwrap()
+                .location("a.tgz"),
+            "s3://backups/a.tgz"
+        );
+
+        let sftp = RemoteTarget::parse("sftp://me@host:2222/srv/dumps/").unwrap();
+        assert_eq!(
+            sftp,
+            RemoteTarget::Sftp {
+                host: "me@host".to_string(),
+                port: Some(2222),
+                dir: "/srv/dumps".to_string(),
+            }
+        );
+        assert_eq!(
+            sftp.location("a.tgz"),
+            "sftp://me@host:2222/srv/dumps/a.tgz"
+        );
+
+        let http = RemoteTarget::parse("https://store.example/up/").unwrap();
+        assert_eq!(
+            http.location("my file.tgz"),
+            "https://store.example/up/my%20file.tgz"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(is_remote_url("s3://b"));
+        assert!(!is_remote_url("backups/s3:"));
+        for url in [
+            "s3://",
+            "s3:///x",
+            "sftp://",
+            "sftp://host:x/",
+            "https://",
+            "ftp://h/",
+        ] {
+            assert!(RemoteTarget::parse(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_download_name() {
+        assert_eq!(
+            download_name("https://example.org/v1/release%201.tar.gz?sig=x#frag").unwrap(),
+            "release 1.tar.gz"
+        );
+        assert!(download_name("https://example.org").is_err());
+        assert!(download_name("https://example.org/dir/").is_err());
+        assert!(download_name("https://example.org/%2e%2e").is_err());
+        assert!(download_name("https://example.org/a%2Fb.gz").is_err());
+    }
+
+    #[test]
+    fn test_sftp_quote() {
+        assert_eq!(sftp_quote(r#"a "b"\c"#), r#""a \"b\"\\c""#);
+    }
+}
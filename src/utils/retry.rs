@@ -0,0 +1,180 @@
+//! Retrying transient failures (`--retries`, `--retry-delay`)
+//!
+//! Network filesystems and remote destinations fail now and then for
+//! reasons that go away on their own: a stale NFS handle, an I/O error
+//! while a server fails over, a dropped connection. Such failures are
+//! retried after a pause that doubles each time; anything else, such as a
+//! missing file or corrupt data, fails at once.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use crate::core::error::{JcError, JcResult};
+use crate::utils::warn;
+
+/// Longest pause between two attempts
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// What tool error output says about failures worth another try
+const TRANSIENT_MESSAGES: &[&str] = &[
+    "Input/output error",
+    "Stale file handle",
+    "Resource temporarily unavailable",
+    "Connection reset",
+    "Connection timed out",
+    "timed out",
+    "Network is unreachable",
+];
+
+/// How often and how patiently to retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first
+    pub retries: u32,
+    /// Pause before the first retry, doubled before each further one
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, delay: Duration) -> Self {
+        RetryPolicy { retries, delay }
+    }
+
+    /// Run `job`, again after a pause each time it fails transiently, up
+    /// to `retries` more times; `what` names it in warnings
+    pub fn run<T>(&self, what: &str, mut job: impl FnMut() -> JcResult<T>) -> JcResult<T> {
+        let mut attempt = 0;
+        loop {
+            match job() {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    let pause = self.pause(attempt);
+                    attempt += 1;
+                    warn!(
+                        "{}: {} (retrying in {:.1}s, {} of {})",
+                        what,
+                        e,
+                        pause.as_secs_f64(),
+                        attempt,
+                        self.retries
+                    );
+                    thread::sleep(pause);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Pause before retry number `attempt + 1`
+    fn pause(&self, attempt: u32) -> Duration {
+        self.delay
+            .checked_mul(1 << attempt.min(16))
+            .map_or(MAX_DELAY, |pause| pause.min(MAX_DELAY))
+    }
+}
+
+/// Whether `e` may well not happen again
+pub fn is_transient(e: &JcError) -> bool {
+    match e {
+        JcError::Io(e) => is_transient_io(e),
+        JcError::UploadFailed { .. } | JcError::DownloadFailed { .. } => true,
+        JcError::CompressionFailed { stderr, .. } | JcError::DecompressionFailed { stderr, .. } => {
+            TRANSIENT_MESSAGES.iter().any(|m| stderr.contains(m))
+        }
+        _ => false,
+    }
+}
+
+fn is_transient_io(e: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    if matches!(
+        e.kind(),
+        TimedOut | Interrupted | WouldBlock | ConnectionReset | ConnectionAborted | NotConnected
+    ) {
+        return true;
+    }
+    #[cfg(unix)]
+    if let Some(code) = e.raw_os_error() {
+        return [
+            libc::EIO,
+            libc::EAGAIN,
+            libc::ESTALE,
+            libc::ETIMEDOUT,
+            libc::ENETDOWN,
+            libc::ENETUNREACH,
+            libc::EHOSTUNREACH,
+        ]
+        .contains(&code);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn fast(retries: u32) -> RetryPolicy {
+        RetryPolicy::new(retries, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_retries_transient_failures() {
+        let calls = Cell::new(0);
+        let result = fast(3).run("job", || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(JcError::Io(io::ErrorKind::TimedOut.into()))
+            } else {
+                Ok(calls.get())
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Out of retries
+        calls.set(0);
+        let result: JcResult<()> = fast(1).run("job", || {
+            calls.set(calls.get() + 1);
+            Err(JcError::Io(io::ErrorKind::TimedOut.into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_permanent_failures_are_not_retried() {
+        let calls = Cell::new(0);
+        let result: JcResult<()> = fast(3).run("job", || {
+            calls.set(calls.get() + 1);
+            Err(JcError::Io(io::ErrorKind::NotFound.into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        assert!(!is_transient(&JcError::DecompressionFailed {
+            tool: "gzip".to_string(),
+            stderr: "not in gzip format".to_string(),
+        }));
+        assert!(is_transient(&JcError::DecompressionFailed {
+            tool: "xz".to_string(),
+            stderr: "data.xz: Read error: Input/output error".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_pause_doubles_up_to_limit() {
+        let policy = RetryPolicy::new(30, Duration::from_secs(1));
+        assert_eq!(policy.pause(0), Duration::from_secs(1));
+        assert_eq!(policy.pause(2), Duration::from_secs(4));
+        assert_eq!(policy.pause(20), MAX_DELAY);
+    }
+}
@@ -0,0 +1,156 @@
+//! Records of the temp directories a run creates
+//!
+//! When `$XDG_RUNTIME_DIR` is set, every run lists the scratch directories
+//! it creates in `$XDG_RUNTIME_DIR/jcz/<pid>.dirs` and keeps that file
+//! locked until it exits. A record nobody holds was left by a run that
+//! finished, crashed or was killed, so the directories it lists that still
+//! exist are orphans, however young.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::utils::{canonical_path, debug, path_from_bytes, path_to_bytes, remove_file_silent};
+
+/// Prefix of scratch directories (and of some staged files)
+pub const SCRATCH_PREFIX: &str = ".jcz-tmp-";
+
+/// Prefix of the staging directories of `--collect`
+pub const STAGING_PREFIX: &str = "jczpkg_";
+
+/// This run's record, opened and locked on first use; `None` when records
+/// cannot be kept
+static RECORD: OnceLock<Option<Mutex<File>>> = OnceLock::new();
+
+/// Directory holding the records: `$XDG_RUNTIME_DIR/jcz`
+pub fn records_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("jcz"))
+}
+
+/// Add `dir`, just created, to this run's record, if records are kept
+pub fn record(dir: &Path) {
+    let record = RECORD.get_or_init(|| {
+        let dir = records_dir()?;
+        open_record(&dir)
+            .inspect_err(|e| debug!("Not recording temp directories: {}", e))
+            .ok()
+            .map(Mutex::new)
+    });
+    let (Some(record), Ok(dir)) = (record, canonical_path(dir)) else {
+        return;
+    };
+
+    let mut line = path_to_bytes(&dir).into_owned();
+    line.push(b'\n');
+    let mut file = record.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = file.write_all(&line) {
+        debug!("Failed to record {}: {}", dir.display(), e);
+    }
+}
+
+fn open_record(dir: &Path) -> io::Result<File> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.dirs", std::process::id()));
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Record of a run that is gone, locked until it is removed
+#[derive(Debug)]
+pub struct StaleRecord {
+    path: PathBuf,
+    _file: File,
+
+    /// Directories it lists that still exist
+    pub dirs: Vec<PathBuf>,
+}
+
+impl StaleRecord {
+    /// Delete the record, once its directories are gone
+    pub fn remove(self) -> io::Result<()> {
+        remove_file_silent(&self.path)
+    }
+}
+
+/// Records in `records_dir()`: those of runs that are gone, and the
+/// directories of runs still going
+pub fn scan_records() -> io::Result<(Vec<StaleRecord>, Vec<PathBuf>)> {
+    match records_dir().filter(|dir| dir.is_dir()) {
+        Some(dir) => scan_records_in(&dir),
+        None => Ok((Vec::new(), Vec::new())),
+    }
+}
+
+fn scan_records_in(dir: &Path) -> io::Result<(Vec<StaleRecord>, Vec<PathBuf>)> {
+    let mut stale = Vec::new();
+    let mut live = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "dirs") {
+            continue;
+        }
+        // Another jcz may be removing it right now
+        let Ok(mut file) = OpenOptions::new().read(true).write(true).open(&path) else {
+            continue;
+        };
+        let held = file.try_lock().is_err();
+
+        let mut bytes = Vec::new();
+        if file.read_to_end(&mut bytes).is_err() {
+            continue;
+        }
+        let dirs = bytes
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(path_from_bytes);
+        if held {
+            live.extend(dirs);
+        } else {
+            let dirs = dirs.filter(|dir| dir.exists()).collect();
+            stale.push(StaleRecord {
+                path,
+                _file: file,
+                dirs,
+            });
+        }
+    }
+    Ok((stale, live))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_records_of_gone_runs_are_stale() {
+        let temp_dir = TempDir::new().unwrap();
+        let records = temp_dir.path().join("jcz");
+        fs::create_dir(&records).unwrap();
+
+        let orphan = temp_dir.path().join(".jcz-tmp-orphan");
+        let removed = temp_dir.path().join(".jcz-tmp-removed");
+        fs::create_dir(&orphan).unwrap();
+        let listing = format!("{}\n{}\n", orphan.display(), removed.display());
+        fs::write(records.join("1.dirs"), &listing).unwrap();
+
+        // A record some run holds locked is live
+        let mut held = open_record(&records).unwrap();
+        held.write_all(b"/live\n").unwrap();
+
+        let (stale, live) = scan_records_in(&records).unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].dirs, vec![orphan]);
+        assert_eq!(live, vec![PathBuf::from("/live")]);
+
+        stale
+            .into_iter()
+            .for_each(|record| record.remove().unwrap());
+        assert!(!records.join("1.dirs").exists());
+        drop(held);
+    }
+}
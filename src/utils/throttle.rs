@@ -0,0 +1,178 @@
+//! Bandwidth limiting for compressor I/O (`--limit-rate`)
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest write passed on at once, so pacing stays smooth
+const MAX_CHUNK: usize = 64 * 1024;
+
+/// A bytes-per-second budget shared by every reader and writer it wraps
+///
+/// Clones share the budget, so files processed in parallel together stay
+/// under the limit.
+#[derive(Clone)]
+pub struct RateLimit {
+    bytes_per_sec: u64,
+    next_free: Arc<Mutex<Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimit {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next_free: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Parse a rate such as `500K`, `50M` or `1G` (see `parse_size`), in
+    /// bytes per second
+    pub fn parse(text: &str) -> Option<Self> {
+        parse_size(text).map(RateLimit::new)
+    }
+
+    #[allow(dead_code)]
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// Account for `bytes` of I/O, sleeping until the budget covers them
+    pub fn consume(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let now = Instant::now();
+        let done = {
+            let mut next_free = self.next_free.lock().unwrap_or_else(|e| e.into_inner());
+            *next_free = (*next_free).max(now) + cost;
+            *next_free
+        };
+        if done > now {
+            thread::sleep(done - now);
+        }
+    }
+}
+
+/// Parse a positive byte count such as `512K`, `50M` or `1G` (binary
+/// multiples), or a plain number of bytes
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (digits, multiplier) = match text.char_indices().last()? {
+        (i, 'k' | 'K') => (&text[..i], 1024),
+        (i, 'm' | 'M') => (&text[..i], 1024 * 1024),
+        (i, 'g' | 'G') => (&text[..i], 1024 * 1024 * 1024),
+        _ => (text, 1),
+    };
+    let size = digits.parse::<u64>().ok()?.checked_mul(multiplier)?;
+    (size > 0).then_some(size)
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RateLimit({} B/s)", self.bytes_per_sec)
+    }
+}
+
+/// Reader or writer whose traffic is charged to an optional `RateLimit`
+pub struct Throttled<T> {
+    inner: T,
+    limit: Option<RateLimit>,
+}
+
+impl<T> Throttled<T> {
+    /// Wrap `inner`; without a limit it is passed through untouched
+    pub fn new(inner: T, limit: Option<&RateLimit>) -> Self {
+        Throttled {
+            inner,
+            limit: limit.cloned(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn charge(&self, bytes: usize) {
+        if let Some(limit) = &self.limit {
+            limit.consume(bytes);
+        }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(MAX_CHUNK);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.charge(n);
+        Ok(n)
+    }
+}
+
+/// Seeking moves no data, so it is not charged
+impl<S: Seek> Seek for Throttled<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(MAX_CHUNK);
+        let n = self.inner.write(&buf[..len])?;
+        self.charge(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(RateLimit::parse("50M").unwrap().bytes_per_sec(), 50 << 20);
+        assert_eq!(RateLimit::parse("512k").unwrap().bytes_per_sec(), 512 << 10);
+        assert_eq!(RateLimit::parse("1G").unwrap().bytes_per_sec(), 1 << 30);
+        assert_eq!(RateLimit::parse("4096").unwrap().bytes_per_sec(), 4096);
+        assert!(RateLimit::parse("0").is_none());
+        assert!(RateLimit::parse("fast").is_none());
+        assert!(RateLimit::parse("M").is_none());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512M"), Some(512 << 20));
+        assert_eq!(parse_size(" 2g "), Some(2 << 30));
+        assert_eq!(parse_size("0K"), None);
+        assert_eq!(parse_size("99999999999G"), None);
+    }
+
+    #[test]
+    fn test_throttled_copy_takes_budgeted_time() {
+        let limit = RateLimit::new(100 * 1024);
+        let data = vec![7u8; 20 * 1024];
+        let mut output = Throttled::new(Vec::new(), Some(&limit));
+
+        let start = Instant::now();
+        io::copy(&mut Throttled::new(&data[..], Some(&limit)), &mut output).unwrap();
+
+        // 40 KiB of shared traffic at 100 KiB/s
+        assert!(start.elapsed() >= Duration::from_millis(350));
+        assert_eq!(output.into_inner(), data);
+    }
+
+    #[test]
+    fn test_unlimited_passes_through() {
+        let mut output = Throttled::new(Vec::new(), None);
+        output.write_all(b"hello").unwrap();
+        assert_eq!(output.into_inner(), b"hello");
+    }
+}
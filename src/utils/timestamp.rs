@@ -1,9 +1,15 @@
-use chrono::Local;
+use std::fmt::Write;
+
+use std::time::{Duration, SystemTime};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 
 use crate::core::config::TimestampOption;
+use crate::core::error::{JcError, JcResult};
 
 /// Generate timestamp string based on option
-pub fn generate_timestamp(option: TimestampOption) -> String {
+pub fn generate_timestamp(option: &TimestampOption) -> String {
     let now = Local::now();
 
     match option {
@@ -11,6 +17,117 @@ pub fn generate_timestamp(option: TimestampOption) -> String {
         TimestampOption::Date => now.format("%Y%m%d").to_string(),
         TimestampOption::DateTime => now.format("%Y%m%d_%H%M%S").to_string(),
         TimestampOption::Nanoseconds => now.timestamp_subsec_nanos().to_string(),
+        // Checked by `validate_timestamp_format`, so formatting cannot fail
+        TimestampOption::Format { pattern, utc: true } => {
+            now.with_timezone(&Utc).format(pattern).to_string()
+        }
+        TimestampOption::Format {
+            pattern,
+            utc: false,
+        } => now.format(pattern).to_string(),
+    }
+}
+
+/// Check that a `--timestamp-format` pattern is valid strftime and yields
+/// a non-empty file name component
+pub fn validate_timestamp_format(pattern: &str) -> JcResult<()> {
+    let invalid = |reason: &str| {
+        JcError::Usage(format!(
+            "Invalid timestamp format '{}': {}",
+            pattern, reason
+        ))
+    };
+
+    if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+        return Err(invalid("unknown or incomplete % specifier"));
+    }
+    let mut sample = String::new();
+    write!(sample, "{}", Local::now().format(pattern))
+        .map_err(|_| invalid("specifier cannot be formatted"))?;
+    if sample.is_empty() {
+        return Err(invalid("produces an empty timestamp"));
+    }
+    if sample.contains(['/', '\\', '\0']) {
+        return Err(invalid("produces a path separator"));
+    }
+    Ok(())
+}
+
+/// `pattern` with its strftime fields filled in from the local time, such
+/// as `logs-%Y%m.tar.xz`; unlike a timestamp format, it may hold a path
+pub fn format_local(pattern: &str) -> JcResult<String> {
+    let invalid = || JcError::Usage(format!("Invalid strftime pattern '{}'", pattern));
+    if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+        return Err(invalid());
+    }
+    let mut formatted = String::new();
+    write!(formatted, "{}", Local::now().format(pattern)).map_err(|_| invalid())?;
+    Ok(formatted)
+}
+
+/// Point in time for `--newer-mtime` and `--older-than`
+///
+/// Either an age before `now`, a number with an `s`, `m`, `h`, `d` or `w`
+/// unit (`30d`), or a date: `YYYY-MM-DD` or `YYYY-MM-DD HH:MM[:SS]` in
+/// local time, RFC 3339, or `@SECONDS` since the epoch.
+pub fn parse_time_bound(text: &str, now: SystemTime) -> Option<SystemTime> {
+    let text = text.trim();
+    if let Some(seconds) = text.strip_prefix('@') {
+        return SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds.parse().ok()?));
+    }
+    if let Some(unit) = text.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        if let Ok(count) = text[..text.len() - 1].parse::<u64>() {
+            let seconds = match unit {
+                's' => 1,
+                'm' => 60,
+                'h' => 3_600,
+                'd' => 86_400,
+                'w' => 7 * 86_400,
+                _ => return None,
+            };
+            return now.checked_sub(Duration::from_secs(count.checked_mul(seconds)?));
+        }
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.into());
+    }
+
+    let naive = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .or_else(|| {
+            ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        })?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(SystemTime::from)
+}
+
+/// Short host label used to tell apart outputs named in the same instant
+///
+/// First DNS label of the hostname, restricted to `[A-Za-z0-9-]`.
+pub fn host_label() -> String {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+
+    let label: String = hostname
+        .trim()
+        .split('.')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+
+    if label.is_empty() {
+        "host".to_string()
+    } else {
+        label
     }
 }
 
@@ -20,21 +137,90 @@ mod tests {
 
     #[test]
     fn test_timestamp_none() {
-        let ts = generate_timestamp(TimestampOption::None);
+        let ts = generate_timestamp(&TimestampOption::None);
         assert_eq!(ts, "");
     }
 
     #[test]
     fn test_timestamp_date() {
-        let ts = generate_timestamp(TimestampOption::Date);
+        let ts = generate_timestamp(&TimestampOption::Date);
         assert_eq!(ts.len(), 8); // YYYYMMDD
         assert!(ts.chars().all(|c| c.is_ascii_digit()));
     }
 
     #[test]
     fn test_timestamp_datetime() {
-        let ts = generate_timestamp(TimestampOption::DateTime);
+        let ts = generate_timestamp(&TimestampOption::DateTime);
         assert_eq!(ts.len(), 15); // YYYYMMDD_HHMMSS
         assert!(ts.contains('_'));
     }
+
+    #[test]
+    fn test_format_local() {
+        let month = Local::now().format("%Y%m").to_string();
+        assert_eq!(
+            format_local("/var/log/logs-%Y%m.tar.xz").unwrap(),
+            format!("/var/log/logs-{}.tar.xz", month)
+        );
+        assert!(format_local("logs-%Q.tar").is_err());
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        let option = TimestampOption::Format {
+            pattern: "%Y-%m-%d_%H%M".to_string(),
+            utc: true,
+        };
+        let ts = generate_timestamp(&option);
+        assert_eq!(ts, Utc::now().format("%Y-%m-%d_%H%M").to_string());
+
+        let utc = TimestampOption::Date.in_utc();
+        assert_eq!(
+            utc,
+            TimestampOption::Format {
+                pattern: "%Y%m%d".to_string(),
+                utc: true
+            }
+        );
+        assert_eq!(
+            TimestampOption::Nanoseconds.in_utc(),
+            TimestampOption::Nanoseconds
+        );
+    }
+
+    #[test]
+    fn test_validate_timestamp_format() {
+        assert!(validate_timestamp_format("%Y-%m-%d_%H%M").is_ok());
+        assert!(validate_timestamp_format("week%V").is_ok());
+        assert!(validate_timestamp_format("%Q").is_err());
+        assert!(validate_timestamp_format("%Y%").is_err());
+        assert!(validate_timestamp_format("%D").is_err());
+        assert!(validate_timestamp_format("").is_err());
+    }
+
+    #[test]
+    fn test_parse_time_bound() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(10 * 86_400);
+        let day = |days: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86_400);
+        assert_eq!(parse_time_bound("3d", now), Some(day(7)));
+        assert_eq!(parse_time_bound("1w", now), Some(day(3)));
+        assert_eq!(parse_time_bound("@86400", now), Some(day(1)));
+        assert_eq!(parse_time_bound("1970-01-02T00:00:00Z", now), Some(day(1)));
+        let local = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            parse_time_bound("2024-01-01", now),
+            Some(SystemTime::from(local))
+        );
+        assert!(parse_time_bound("2024-01-01 12:30", now).is_some());
+        assert_eq!(parse_time_bound("30y", now), None);
+        assert_eq!(parse_time_bound("99999999999999999w", now), None);
+        assert_eq!(parse_time_bound("yesterday", now), None);
+    }
+
+    #[test]
+    fn test_host_label_is_filename_safe() {
+        let label = host_label();
+        assert!(!label.is_empty());
+        assert!(label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    }
 }
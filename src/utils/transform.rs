@@ -0,0 +1,248 @@
+//! Rewriting archive member names (`--transform`)
+//!
+//! Each expression is either a sed-style substitution,
+//! `s#^/var/www#site#`, or a prefix mapping, `/var/www=site`. Patterns use
+//! the `regex` crate's syntax (groups are `(...)`, as in `sed -E`), and
+//! replacements sed's `&` and `\1`. Expressions are applied in order to
+//! an input's path as given on the command line, and the files below a
+//! directory input follow its new name.
+
+use std::path::{Component, Path, PathBuf};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::core::error::{JcError, JcResult};
+
+/// One `--transform` expression
+#[derive(Debug, Clone)]
+enum Rule {
+    /// `s/REGEX/REPLACEMENT/[gi]`
+    Substitute {
+        regex: Regex,
+        replacement: String,
+        global: bool,
+    },
+    /// `OLD=NEW`, matching whole leading components
+    Prefix { from: PathBuf, to: PathBuf },
+}
+
+/// Ordered list of `--transform` expressions
+#[derive(Debug, Clone)]
+pub struct PathTransform {
+    rules: Vec<Rule>,
+}
+
+impl PathTransform {
+    /// Parse `expressions`, in the order they are applied
+    pub fn parse(expressions: &[String]) -> JcResult<Self> {
+        let rules = expressions
+            .iter()
+            .map(|expression| {
+                parse_rule(expression).map_err(|reason| {
+                    JcError::Usage(format!("Invalid --transform '{}': {}", expression, reason))
+                })
+            })
+            .collect::<JcResult<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Archive name for `path`: the rewritten path, without a leading `/`
+    /// or `./`
+    pub fn apply(&self, path: &Path) -> JcResult<PathBuf> {
+        let mut name = path.to_string_lossy().into_owned();
+        for rule in &self.rules {
+            name = match rule {
+                Rule::Substitute {
+                    regex,
+                    replacement,
+                    global: true,
+                } => regex.replace_all(&name, replacement.as_str()).into_owned(),
+                Rule::Substitute {
+                    regex, replacement, ..
+                } => regex.replace(&name, replacement.as_str()).into_owned(),
+                Rule::Prefix { from, to } => match Path::new(&name).strip_prefix(from) {
+                    Ok(rest) => to.join(rest).to_string_lossy().into_owned(),
+                    Err(_) => name,
+                },
+            };
+        }
+        archive_name(Path::new(&name))
+    }
+}
+
+/// `path` as a relative archive name: root and `.` components dropped
+///
+/// Names that would climb out of the extraction directory, or that are
+/// empty, are refused.
+pub fn archive_name(path: &Path) -> JcResult<PathBuf> {
+    let mut name = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => name.push(part),
+            Component::ParentDir => {
+                return Err(JcError::Other(format!(
+                    "Cannot store {} in an archive: it leads out of the current directory",
+                    path.display()
+                )))
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    if name.as_os_str().is_empty() {
+        return Err(JcError::Other(format!(
+            "Invalid archive name: '{}'",
+            path.display()
+        )));
+    }
+    Ok(name)
+}
+
+fn parse_rule(expression: &str) -> Result<Rule, String> {
+    let mut chars = expression.chars();
+    let delimiter = match (chars.next(), chars.next()) {
+        (Some('s'), Some(d)) if !d.is_alphanumeric() && d != '\\' => d,
+        _ => {
+            let (from, to) = expression
+                .split_once('=')
+                .ok_or("expected s/REGEX/REPLACEMENT/ or OLD=NEW")?;
+            if from.is_empty() {
+                return Err("the prefix to replace is empty".to_string());
+            }
+            return Ok(Rule::Prefix {
+                from: PathBuf::from(from),
+                to: PathBuf::from(to),
+            });
+        }
+    };
+
+    let parts = split_unescaped(chars.as_str(), delimiter);
+    let [pattern, replacement, flags] = parts.as_slice() else {
+        return Err(format!(
+            "expected s{0}REGEX{0}REPLACEMENT{0}[FLAGS]",
+            delimiter
+        ));
+    };
+    let mut global = false;
+    let mut ignore_case = false;
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'i' => ignore_case = true,
+            other => return Err(format!("unknown flag '{}' (use g or i)", other)),
+        }
+    }
+
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(Rule::Substitute {
+        regex,
+        replacement: sed_replacement(replacement),
+        global,
+    })
+}
+
+/// Split on `delimiter`, turning `\<delimiter>` into a literal delimiter
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        match c {
+            '\\' if chars.peek() == Some(&delimiter) => part.push(chars.next().unwrap()),
+            '\\' => {
+                part.push(c);
+                if let Some(next) = chars.next() {
+                    part.push(next);
+                }
+            }
+            c if c == delimiter => parts.push(String::new()),
+            c => part.push(c),
+        }
+    }
+    parts
+}
+
+/// Convert a sed replacement (`&`, `\1`) to the `regex` crate's syntax
+fn sed_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => result.push_str("${0}"),
+            '$' => result.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    result.push_str("${");
+                    result.push(digit);
+                    result.push('}');
+                }
+                Some('$') => result.push_str("$$"),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(expressions: &[&str], path: &str) -> PathBuf {
+        let expressions: Vec<String> = expressions.iter().map(|e| e.to_string()).collect();
+        PathTransform::parse(&expressions)
+            .unwrap()
+            .apply(Path::new(path))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(
+            transform(&["s#^/var/www#site#"], "/var/www/index.html"),
+            PathBuf::from("site/index.html")
+        );
+        assert_eq!(
+            transform(&[r"s/\(a\)/[&]/g"], "x(a)(a)"),
+            PathBuf::from("x[(a)][(a)]")
+        );
+        assert_eq!(
+            transform(&[r"s|^/srv/([a-z]*)/data|\1|"], "/srv/app/data/f"),
+            PathBuf::from("app/f")
+        );
+        assert_eq!(
+            transform(&["s/LOGS/logs/i", "s#^./##"], "./logs/a"),
+            PathBuf::from("logs/a")
+        );
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(
+            transform(&["/home/me/project=src"], "/home/me/project/main.c"),
+            PathBuf::from("src/main.c")
+        );
+        // Whole components only
+        assert_eq!(
+            transform(&["/home/me/pro=src"], "/home/me/project"),
+            PathBuf::from("home/me/project")
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let parse = |e: &str| PathTransform::parse(&[e.to_string()]);
+        assert!(parse("s#a#b").is_err());
+        assert!(parse("s#(#b#").is_err());
+        assert!(parse("s#a#b#x").is_err());
+        assert!(parse("nothing").is_err());
+        assert!(PathTransform::parse(&["s#^#../#".to_string()])
+            .unwrap()
+            .apply(Path::new("a"))
+            .is_err());
+    }
+}
@@ -1,14 +1,84 @@
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::time::SystemTime;
 
 use crate::core::error::{JcError, JcResult};
 use crate::core::types::InputFile;
-use crate::utils::logger::debug;
+use crate::utils::fs::canonical_path;
+use crate::utils::logger::{debug, info};
+
+/// Which regular files to work on, by size (`--min-size`, `--max-size`)
+/// and modification time (`--newer-mtime`, `--older-than`)
+///
+/// Directories, symlinks kept as links and special files always pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileFilter {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Only files modified at or after this time
+    pub newer_than: Option<SystemTime>,
+    /// Only files modified before this time
+    pub older_than: Option<SystemTime>,
+}
+
+impl FileFilter {
+    pub fn with_sizes(mut self, min: Option<u64>, max: Option<u64>) -> Self {
+        self.min_size = min;
+        self.max_size = max;
+        self
+    }
+
+    pub fn with_mtimes(mut self, newer: Option<SystemTime>, older: Option<SystemTime>) -> Self {
+        self.newer_than = newer;
+        self.older_than = older;
+        self
+    }
+
+    /// Whether any bound is set
+    pub fn is_active(&self) -> bool {
+        *self != FileFilter::default()
+    }
+
+    /// The option that leaves out a file of `size` modified at `mtime`,
+    /// if any; an unknown mtime passes the time bounds
+    pub fn rejection(&self, size: u64, mtime: Option<SystemTime>) -> Option<&'static str> {
+        if self.min_size.is_some_and(|min| size < min) {
+            return Some("--min-size");
+        }
+        if self.max_size.is_some_and(|max| size > max) {
+            return Some("--max-size");
+        }
+        let mtime = mtime?;
+        if self.newer_than.is_some_and(|newer| mtime < newer) {
+            return Some("--newer-mtime");
+        }
+        if self.older_than.is_some_and(|older| mtime >= older) {
+            return Some("--older-than");
+        }
+        None
+    }
+
+    /// Whether the entry described by `metadata` is left out
+    pub fn excludes(&self, metadata: &fs::Metadata) -> bool {
+        metadata.is_file()
+            && self
+                .rejection(metadata.len(), metadata.modified().ok())
+                .is_some()
+    }
+}
 
 /// Validate and process input files
-pub fn validate_input_files(paths: Vec<PathBuf>) -> JcResult<Vec<InputFile>> {
+///
+/// Symlinks are kept as given: `real_path` records the target, but
+/// duplicates are only merged by target when `dereference` is set, since
+/// otherwise each link is archived as a link of its own. Files `filter`
+/// leaves out are skipped.
+pub fn validate_input_files(
+    paths: Vec<PathBuf>,
+    dereference: bool,
+    filter: FileFilter,
+) -> JcResult<Vec<InputFile>> {
     if paths.is_empty() {
         return Err(JcError::NoInputFiles);
     }
@@ -17,26 +87,47 @@ pub fn validate_input_files(paths: Vec<PathBuf>) -> JcResult<Vec<InputFile>> {
     let mut seen_paths = HashSet::new();
 
     for path in paths {
-        // Check if file exists
-        let metadata = fs::metadata(&path).map_err(|_| JcError::FileNotFound(path.clone()))?;
+        // Check if file exists (a dangling link exists as a link)
+        let metadata =
+            fs::symlink_metadata(&path).map_err(|_| JcError::FileNotFound(path.clone()))?;
 
         // Resolve symbolic links
         let (real_path, was_symlink) = if metadata.file_type().is_symlink() {
             debug!("{} is a symbolic link, resolving", path.display());
-            let real = resolve_symlink(&path)?;
-            (real, true)
+            if dereference {
+                let real = resolve_symlink(&path)?;
+                if !real.exists() {
+                    return Err(JcError::FileNotFound(path.clone()));
+                }
+                (real, true)
+            } else {
+                // The link itself is archived, so an unresolvable one is fine
+                (
+                    resolve_symlink(&path).unwrap_or_else(|_| path.clone()),
+                    true,
+                )
+            }
         } else {
             (path.clone(), false)
         };
 
+        // Files are measured through links, whose own size says nothing
+        if let Some(target) = fs::metadata(&path).ok().filter(|target| target.is_file()) {
+            if let Some(option) = filter.rejection(target.len(), target.modified().ok()) {
+                info!("Skipping {}: left out by {}", path.display(), option);
+                continue;
+            }
+        }
+
         // Check for duplicates
-        if !seen_paths.insert(real_path.clone()) {
-            debug!("Skipping duplicate path: {}", real_path.display());
+        let key = if dereference { &real_path } else { &path };
+        if !seen_paths.insert(key.clone()) {
+            debug!("Skipping duplicate path: {}", key.display());
             continue;
         }
 
-        // Get basename
-        let basename = real_path
+        // Get basename; links are stored under their own name
+        let basename = path
             .file_name()
             .and_then(|n| n.to_str())
             .ok_or_else(|| JcError::Other("Invalid filename".to_string()))?
@@ -77,20 +168,7 @@ pub fn check_duplicate_basenames(files: &[InputFile]) -> Option<Vec<String>> {
 
 /// Resolve symbolic link to real path
 fn resolve_symlink(path: &Path) -> JcResult<PathBuf> {
-    let output = Command::new("readlink")
-        .arg("-f")
-        .arg(path)
-        .output()
-        .map_err(|_| JcError::SymlinkResolution(path.to_path_buf()))?;
-
-    if !output.status.success() {
-        return Err(JcError::SymlinkResolution(path.to_path_buf()));
-    }
-
-    let real_path = String::from_utf8_lossy(&output.stdout);
-    let real_path = real_path.trim();
-
-    Ok(PathBuf::from(real_path))
+    canonical_path(path).map_err(|_| JcError::SymlinkResolution(path.to_path_buf()))
 }
 
 /// Validate destination directory
@@ -0,0 +1,429 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::config::CompressionConfig;
+use crate::utils::{debug, warn};
+
+/// Extended attribute holding a file's POSIX access ACL
+const ACL_ACCESS_XATTR: &str = "system.posix_acl_access";
+
+/// Extended attribute holding a directory's POSIX default ACL
+const ACL_DEFAULT_XATTR: &str = "system.posix_acl_default";
+
+/// PAX key prefix for extended attributes (as written by GNU tar and star)
+const PAX_XATTR_PREFIX: &str = "SCHILY.xattr.";
+
+/// PAX keys for ACLs in their text form (as written by GNU tar and star)
+const PAX_ACL_ACCESS: &str = "SCHILY.acl.access";
+const PAX_ACL_DEFAULT: &str = "SCHILY.acl.default";
+
+/// Linux errno returned by filesystems without xattr support
+const EOPNOTSUPP: i32 = 95;
+
+/// Version field of the Linux ACL xattr encoding
+const ACL_XATTR_VERSION: u32 = 2;
+
+/// Qualifier stored for ACL entries that do not name a user or group
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// Which attribute families to store in and restore from tar archives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XattrSelection {
+    /// Extended attributes other than ACLs (`user.*`, `security.*`, ...)
+    pub xattrs: bool,
+
+    /// POSIX access and default ACLs
+    pub acls: bool,
+}
+
+impl XattrSelection {
+    pub fn from_config(config: &CompressionConfig) -> Self {
+        Self {
+            xattrs: config.preserve_xattrs,
+            acls: config.preserve_acls,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.xattrs && !self.acls
+    }
+
+    fn includes(&self, name: &OsStr) -> bool {
+        if is_acl_xattr(name) {
+            self.acls
+        } else {
+            self.xattrs
+        }
+    }
+}
+
+fn is_acl_xattr(name: &OsStr) -> bool {
+    name == ACL_ACCESS_XATTR || name == ACL_DEFAULT_XATTR
+}
+
+/// Read the selected attributes of `path` (not following symlinks)
+///
+/// Filesystems without xattr support simply yield nothing; other read
+/// errors are logged and the attribute is skipped.
+fn read_selected(path: &Path, selection: XattrSelection) -> Vec<(String, Vec<u8>)> {
+    let names = match list_xattrs(path) {
+        Ok(names) => names,
+        Err(e) => {
+            if e.raw_os_error() != Some(EOPNOTSUPP) && e.kind() != io::ErrorKind::Unsupported {
+                warn!("Cannot list attributes of {}: {}", path.display(), e);
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut attrs = Vec::new();
+    for name in names.into_iter().filter(|n| selection.includes(n)) {
+        let Some(key) = name.to_str() else {
+            warn!(
+                "Skipping non-UTF-8 attribute {:?} on {}",
+                name,
+                path.display()
+            );
+            continue;
+        };
+        match get_xattr(path, &name) {
+            Ok(Some(value)) => attrs.push((key.to_string(), value)),
+            Ok(None) => {}
+            Err(e) => warn!("Cannot read {} on {}: {}", key, path.display(), e),
+        }
+    }
+    attrs.sort();
+    // Listing order depends on the filesystem; keep archives stable
+    attrs.sort();
+    attrs
+}
+
+/// PAX records describing the selected attributes of `path`
+///
+/// ACLs are stored in text form under `SCHILY.acl.*` so that GNU tar
+/// `--acls` can restore them; everything else goes under `SCHILY.xattr.*`.
+pub fn pax_records(path: &Path, selection: XattrSelection) -> Vec<(String, Vec<u8>)> {
+    if selection.is_empty() {
+        return Vec::new();
+    }
+
+    read_selected(path, selection)
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let key = match name.as_str() {
+                ACL_ACCESS_XATTR => PAX_ACL_ACCESS,
+                ACL_DEFAULT_XATTR => PAX_ACL_DEFAULT,
+                _ => return Some((format!("{}{}", PAX_XATTR_PREFIX, name), value)),
+            };
+            match acl_to_text(&value) {
+                Some(text) => Some((key.to_string(), text.into_bytes())),
+                None => {
+                    warn!("Skipping malformed ACL on {}", path.display());
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Apply the selected attributes found in an entry's PAX records to `path`
+///
+/// Failures (unsupported filesystem, missing privileges for `security.*`
+/// or `trusted.*`) are reported as warnings, like GNU tar does.
+pub fn apply_pax_records<'a, I>(path: &Path, records: I, selection: XattrSelection)
+where
+    I: IntoIterator<Item = (&'a str, &'a [u8])>,
+{
+    for (key, value) in records {
+        let (name, value) = if let Some(name) = key.strip_prefix(PAX_XATTR_PREFIX) {
+            (name, value.to_vec())
+        } else {
+            let name = match key {
+                PAX_ACL_ACCESS => ACL_ACCESS_XATTR,
+                PAX_ACL_DEFAULT => ACL_DEFAULT_XATTR,
+                _ => continue,
+            };
+            let text = String::from_utf8_lossy(value);
+            match acl_from_text(&text) {
+                Some(bytes) => (name, bytes),
+                None => {
+                    warn!("Cannot restore ACL '{}' on {}", text, path.display());
+                    continue;
+                }
+            }
+        };
+
+        if !selection.includes(OsStr::new(name)) {
+            continue;
+        }
+
+        debug!("Setting {} on {}", name, path.display());
+        if let Err(e) = set_xattr(path, OsStr::new(name), &value) {
+            warn!("Cannot set {} on {}: {}", name, path.display(), e);
+        }
+    }
+}
+
+/// Split a PAX extended header into its `<len> <key>=<value>\n` records
+///
+/// Values may contain newlines (GNU tar's ACL text does), so records are
+/// delimited by their length prefix only. Parsing stops at the first
+/// malformed record.
+pub fn parse_pax_records(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let Some(space) = rest.iter().position(|b| *b == b' ') else {
+            break;
+        };
+        let Some(len) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|l| l.parse::<usize>().ok())
+            .filter(|&len| len > space + 1 && len <= rest.len())
+        else {
+            break;
+        };
+
+        let record = &rest[space + 1..len - 1];
+        let Some(equals) = record.iter().position(|b| *b == b'=') else {
+            break;
+        };
+        if let Ok(key) = std::str::from_utf8(&record[..equals]) {
+            records.push((key.to_string(), record[equals + 1..].to_vec()));
+        }
+        rest = &rest[len..];
+    }
+    records
+}
+
+/// Mirror the selected attributes of the `src` tree onto the `dst` tree
+///
+/// Used after copying extracted files out of the temporary directory,
+/// since a plain copy drops them.
+pub fn copy_attributes(src: &Path, dst: &Path, selection: XattrSelection) -> io::Result<()> {
+    if selection.is_empty() {
+        return Ok(());
+    }
+
+    for (name, value) in read_selected(src, selection) {
+        if let Err(e) = set_xattr(dst, OsStr::new(&name), &value) {
+            warn!("Cannot set {} on {}: {}", name, dst.display(), e);
+        }
+    }
+
+    // Never follow links: their targets may lie outside the tree
+    if fs::symlink_metadata(src)?.is_dir() {
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            if entry.file_type()?.is_symlink() {
+                continue;
+            }
+            let target = dst.join(entry.file_name());
+            if fs::symlink_metadata(&target).is_ok_and(|m| !m.file_type().is_symlink()) {
+                copy_attributes(&entry.path(), &target, selection)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn list_xattrs(path: &Path) -> io::Result<Vec<OsString>> {
+    Ok(xattr::list(path)?.collect())
+}
+
+#[cfg(unix)]
+fn get_xattr(path: &Path, name: &OsStr) -> io::Result<Option<Vec<u8>>> {
+    xattr::get(path, name)
+}
+
+#[cfg(unix)]
+fn set_xattr(path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+    xattr::set(path, name, value)
+}
+
+// Windows has no POSIX extended attributes: archives are still read and
+// written, their attribute records are just skipped
+
+#[cfg(not(unix))]
+fn list_xattrs(_path: &Path) -> io::Result<Vec<OsString>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(unix))]
+fn get_xattr(_path: &Path, _name: &OsStr) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+#[cfg(not(unix))]
+fn set_xattr(_path: &Path, _name: &OsStr, _value: &[u8]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Render a Linux ACL xattr as `user::rwx,user:1000:r-x,...`
+///
+/// Named entries use numeric ids so the text does not depend on the
+/// local passwd/group databases.
+fn acl_to_text(bytes: &[u8]) -> Option<String> {
+    let version = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?);
+    if version != ACL_XATTR_VERSION || !(bytes.len() - 4).is_multiple_of(8) {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    for chunk in bytes[4..].chunks_exact(8) {
+        let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+        let perm = u16::from_le_bytes([chunk[2], chunk[3]]);
+        let id = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+        let (tag_name, qualifier) = match tag {
+            ACL_USER_OBJ => ("user", String::new()),
+            ACL_USER => ("user", id.to_string()),
+            ACL_GROUP_OBJ => ("group", String::new()),
+            ACL_GROUP => ("group", id.to_string()),
+            ACL_MASK => ("mask", String::new()),
+            ACL_OTHER => ("other", String::new()),
+            _ => return None,
+        };
+        let perms: String = [(4, 'r'), (2, 'w'), (1, 'x')]
+            .iter()
+            .map(|&(bit, c)| if perm & bit != 0 { c } else { '-' })
+            .collect();
+        entries.push(format!("{}:{}:{}", tag_name, qualifier, perms));
+    }
+    Some(entries.join(","))
+}
+
+/// Parse the text form back into a Linux ACL xattr
+///
+/// Accepts the `tag:qualifier:perms[:id]` entries written by jcz, GNU tar
+/// and star. Named qualifiers without a trailing numeric id are looked up
+/// in `/etc/passwd` or `/etc/group`.
+fn acl_from_text(text: &str) -> Option<Vec<u8>> {
+    let mut entries: Vec<(u16, u32, u16)> = Vec::new();
+
+    for entry in text.split([',', '\n']).map(str::trim) {
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = entry.split(':').collect();
+        if fields.len() < 3 {
+            return None;
+        }
+        let (qualifier, perms) = (fields[1], fields[2]);
+
+        let id = if qualifier.is_empty() {
+            ACL_UNDEFINED_ID
+        } else if let Some(id) = fields.get(3).and_then(|id| id.parse().ok()) {
+            id
+        } else if let Ok(id) = qualifier.parse() {
+            id
+        } else if matches!(fields[0], "group" | "g") {
+            lookup_id("/etc/group", qualifier)?
+        } else {
+            lookup_id("/etc/passwd", qualifier)?
+        };
+
+        let tag = match (fields[0], qualifier.is_empty()) {
+            ("user" | "u", true) => ACL_USER_OBJ,
+            ("user" | "u", false) => ACL_USER,
+            ("group" | "g", true) => ACL_GROUP_OBJ,
+            ("group" | "g", false) => ACL_GROUP,
+            ("mask" | "m", _) => ACL_MASK,
+            ("other" | "o", _) => ACL_OTHER,
+            _ => return None,
+        };
+
+        let mut perm = 0;
+        for c in perms.chars() {
+            perm |= match c {
+                'r' => 4,
+                'w' => 2,
+                'x' => 1,
+                '-' => 0,
+                _ => return None,
+            };
+        }
+        entries.push((tag, id, perm));
+    }
+
+    // The kernel expects entries ordered by tag, then by id
+    entries.sort();
+
+    let mut bytes = ACL_XATTR_VERSION.to_le_bytes().to_vec();
+    for (tag, id, perm) in entries {
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&perm.to_le_bytes());
+        bytes.extend_from_slice(&id.to_le_bytes());
+    }
+    Some(bytes)
+}
+
+/// Numeric id of `name` in a passwd/group style database
+fn lookup_id(database: &str, name: &str) -> Option<u32> {
+    fs::read_to_string(database)
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl_text_round_trip() {
+        let text = "user::rw-,user:1000:r-x,group::r--,mask::r-x,other::---";
+        let bytes = acl_from_text(text).unwrap();
+        assert_eq!(bytes.len(), 4 + 5 * 8);
+        assert_eq!(acl_to_text(&bytes).unwrap(), text);
+    }
+
+    #[test]
+    fn test_acl_from_star_text_with_names() {
+        // GNU tar/star append the numeric id after a named qualifier
+        let bytes = acl_from_text("user::rwx,user:alice:r--:1001,group::r-x,other::---").unwrap();
+        assert_eq!(
+            acl_to_text(&bytes).unwrap(),
+            "user::rwx,user:1001:r--,group::r-x,other::---"
+        );
+        assert!(acl_from_text("user:no-such-user-jcz:r--").is_none());
+    }
+
+    #[test]
+    fn test_parse_pax_records_with_newlines_in_values() {
+        let data = b"30 mtime=1792040060.987332643\n54 SCHILY.acl.access=user::rw-\ngroup::r--\nother::---\n\n25 SCHILY.xattr.user.a=1\n";
+        let records = parse_pax_records(data);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1].0, "SCHILY.acl.access");
+        assert_eq!(records[1].1, b"user::rw-\ngroup::r--\nother::---\n");
+        assert_eq!(
+            records[2],
+            ("SCHILY.xattr.user.a".to_string(), b"1".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_selection_routes_acls_separately() {
+        let acls_only = XattrSelection {
+            xattrs: false,
+            acls: true,
+        };
+        assert!(acls_only.includes(OsStr::new(ACL_ACCESS_XATTR)));
+        assert!(!acls_only.includes(OsStr::new("user.comment")));
+        assert!(XattrSelection::default().is_empty());
+    }
+}
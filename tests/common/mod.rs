@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
 use std::fs;
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
@@ -26,6 +27,7 @@ pub fn jcz_command() -> Command {
 }
 
 /// Helper to verify a file exists
+#[allow(dead_code)]
 pub fn file_exists(path: &Path) -> bool {
     path.exists() && path.is_file()
 }
@@ -118,3 +120,33 @@ pub const TEST_DATA_MEDIUM: &[u8] = b"Lorem ipsum dolor sit amet, consectetur ad
     Sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.";
 #[allow(dead_code)]
 pub const TEST_DATA_BINARY: &[u8] = &[0u8, 1, 2, 3, 4, 5, 255, 254, 253, 252, 251, 250];
+
+/// Helper to generate an RSA key pair as private.pem and public.pem in dir
+#[allow(dead_code)]
+pub fn write_key_pair(dir: &Path) -> (PathBuf, PathBuf) {
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::rngs::OsRng, 2048).unwrap();
+    let public_key = rsa::RsaPublicKey::from(&private_key);
+    let private_path = dir.join("private.pem");
+    let public_path = dir.join("public.pem");
+    fs::write(
+        &private_path,
+        private_key.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes(),
+    )
+    .unwrap();
+    fs::write(
+        &public_path,
+        public_key.to_public_key_pem(LineEnding::LF).unwrap(),
+    )
+    .unwrap();
+    (private_path, public_path)
+}
+
+/// Helper to build a PATH with `bin` searched first, for fake tools
+#[allow(dead_code)]
+pub fn path_with(bin: &Path) -> String {
+    format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    )
+}
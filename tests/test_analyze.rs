@@ -0,0 +1,66 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Build `project/` with one file duplicated twice over
+fn create_project(base: &std::path::Path) -> std::path::PathBuf {
+    let project = base.join("project");
+    for dir in ["docs", "backup", "old"] {
+        fs::create_dir_all(project.join(dir)).unwrap();
+    }
+    create_test_files(
+        base,
+        &[
+            ("project/docs/readme.txt", TEST_DATA_MEDIUM),
+            ("project/backup/readme.txt", TEST_DATA_MEDIUM),
+            ("project/old/README.TXT", TEST_DATA_MEDIUM),
+            ("project/hello.md", TEST_DATA_SMALL),
+        ],
+    );
+    project
+}
+
+#[test]
+fn test_analyze_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = create_project(temp_dir.path());
+
+    jcz_command()
+        .arg("analyze")
+        .arg(&project)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("4 files"))
+        .stdout(predicate::str::contains("Duplicates: 1 groups"))
+        .stdout(predicate::str::contains("    backup/readme.txt"))
+        .stdout(predicate::str::contains("    old/README.TXT"))
+        .stdout(predicate::str::contains(".txt"))
+        .stdout(predicate::str::contains(".md"));
+}
+
+#[test]
+fn test_analyze_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = create_project(temp_dir.path());
+    jcz_command()
+        .args(["-c", "tgz"])
+        .arg(&project)
+        .assert()
+        .success();
+
+    // Member paths include the top-level directory
+    jcz_command()
+        .args(["analyze", "--top", "1"])
+        .arg(temp_dir.path().join("project.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Duplicates: 1 groups"))
+        .stdout(predicate::str::contains("    project/docs/readme.txt"))
+        .stdout(predicate::str::contains("project/hello.md").not());
+
+    let other = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+    jcz_command().arg("analyze").arg(&other).assert().failure();
+}
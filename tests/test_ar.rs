@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use std::process::Command;
+use tempfile::TempDir;
+
+#[test]
+fn test_ar_archive_single_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("ar")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let archive_file = temp_dir.path().join("test.txt.ar");
+    assert!(file_exists(&archive_file), "Archive file should exist");
+    assert!(file_exists(&test_file), "Original file should be preserved");
+}
+
+#[test]
+fn test_ar_rejects_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("dir");
+    create_test_dir_structure(&test_dir, &["a.txt"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("ar")
+        .arg(&test_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_ar_extract_single_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("ar")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    std::fs::remove_file(&test_file).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("test.txt.ar"))
+        .assert()
+        .success();
+
+    assert!(file_exists(&test_file), "Extracted file should exist");
+    assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM);
+}
+
+#[test]
+fn test_ar_extract_static_library_members() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_files(
+        temp_dir.path(),
+        &[("one.o", TEST_DATA_SMALL), ("two.o", TEST_DATA_BINARY)],
+    );
+
+    let status = Command::new("ar")
+        .current_dir(temp_dir.path())
+        .args(["rc", "libdemo.a", "one.o", "two.o"])
+        .status()
+        .expect("Failed to run ar");
+    assert!(status.success());
+
+    let out_dir = temp_dir.path().join("out");
+    std::fs::create_dir(&out_dir).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("libdemo.a"))
+        .arg("-C")
+        .arg(&out_dir)
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&out_dir.join("one.o")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&out_dir.join("two.o")), TEST_DATA_BINARY);
+}
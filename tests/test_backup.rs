@@ -0,0 +1,70 @@
+mod common;
+
+use common::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Test that an existing compressed output is renamed to NAME~ rather
+/// than overwritten
+#[test]
+fn test_backup_compressed_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "gzip"])
+        .arg(&input)
+        .assert()
+        .success();
+    let output = temp_dir.path().join("data.txt.gz");
+    let first = read_file(&output);
+
+    fs::write(&input, TEST_DATA_MEDIUM).unwrap();
+    jcz_command()
+        .args(["-c", "gzip", "--backup"])
+        .arg(&input)
+        .assert()
+        .success();
+    assert_eq!(read_file(&temp_dir.path().join("data.txt.gz~")), first);
+    assert!(verify_decompressed_content(&output, TEST_DATA_MEDIUM));
+
+    // A suffix of its own, from the environment
+    jcz_command()
+        .env("SIMPLE_BACKUP_SUFFIX", ".old")
+        .args(["-c", "gzip", "--backup=simple"])
+        .arg(&input)
+        .assert()
+        .success();
+    assert!(temp_dir.path().join("data.txt.gz.old").exists());
+}
+
+/// Test that extraction makes numbered backups of files and directories
+/// it would replace, without asking
+#[test]
+fn test_backup_numbered_on_extract() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = temp_dir.path().join("config");
+    fs::create_dir(&config).unwrap();
+    create_test_file(&config, "app.conf", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "tgz"])
+        .arg(&config)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("config.tar.gz");
+
+    fs::write(config.join("app.conf"), TEST_DATA_MEDIUM).unwrap();
+    for n in 1..=2 {
+        jcz_command()
+            .args(["-d", "--backup=numbered"])
+            .arg(&archive)
+            .write_stdin("")
+            .assert()
+            .success();
+        assert!(temp_dir.path().join(format!("config.~{}~", n)).is_dir());
+    }
+    assert_eq!(
+        read_file(&temp_dir.path().join("config.~1~/app.conf")),
+        TEST_DATA_MEDIUM
+    );
+    assert_eq!(read_file(&config.join("app.conf")), TEST_DATA_SMALL);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_benchmark_file_lists_every_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let sample = create_test_file(temp_dir.path(), "sample.txt", TEST_DATA_MEDIUM);
+
+    let output = jcz_command()
+        .arg("benchmark")
+        .arg(&sample)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("FORMAT LEVEL"), "Output: {}", stdout);
+    for command in ["gzip", "bzip2", "xz", "zip"] {
+        for level in [1, 9] {
+            let row = format!("{:<6} {:>5} ", command, level);
+            assert!(stdout.contains(&row), "Missing {:?} in: {}", row, stdout);
+        }
+    }
+
+    // Only the sample is left behind
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_benchmark_directory_uses_tar_compounds() {
+    let temp_dir = TempDir::new().unwrap();
+    let sample = temp_dir.path().join("project");
+    create_test_dir_structure(&sample, &["src/main.rs", "README"]);
+    fs::write(sample.join("notes.txt"), TEST_DATA_SMALL).unwrap();
+
+    jcz_command()
+        .arg("benchmark")
+        .arg(&sample)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("tgz"))
+        .stdout(predicates::str::contains("txz"))
+        .stdout(predicates::str::contains("zip").not());
+}
+
+#[test]
+fn test_benchmark_missing_sample_fails() {
+    jcz_command()
+        .arg("benchmark")
+        .arg("/nonexistent/sample")
+        .assert()
+        .failure();
+}
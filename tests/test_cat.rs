@@ -0,0 +1,81 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_cat_indexed_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = temp_dir.path().join("data");
+    fs::create_dir(&data).unwrap();
+    // Large enough to span several blocks, so later members are sought
+    let big: Vec<u8> = (0..3 << 20).map(|i: u32| (i % 251) as u8).collect();
+    fs::write(data.join("big.bin"), &big).unwrap();
+    create_test_file(&data, "notes.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .args(["-c", "tgz", "--indexed"])
+        .arg(&data)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("data.tar.gz");
+    assert!(file_exists(&temp_dir.path().join("data.tar.gz.jcz.idx")));
+
+    jcz_command()
+        .arg("cat")
+        .arg(&archive)
+        .arg("data/notes.txt")
+        .assert()
+        .success()
+        .stdout(predicate::eq(TEST_DATA_MEDIUM));
+
+    // Still an ordinary tgz
+    fs::remove_dir_all(&data).unwrap();
+    jcz_command().arg("-d").arg(&archive).assert().success();
+    assert_eq!(read_file(&data.join("big.bin")), big);
+}
+
+#[test]
+fn test_cat_streams_without_index() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "hello.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["-c", "txz"])
+        .arg(&input)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("hello.txt.tar.xz");
+    jcz_command()
+        .arg("cat")
+        .arg(&archive)
+        .arg("hello.txt")
+        .assert()
+        .success()
+        .stdout(predicate::eq(TEST_DATA_SMALL));
+
+    jcz_command()
+        .arg("cat")
+        .arg(&archive)
+        .arg("missing.txt")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no member missing.txt"));
+}
+
+#[test]
+fn test_indexed_requires_tgz() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "a.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["-c", "txz", "--indexed"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--indexed requires tgz"));
+}
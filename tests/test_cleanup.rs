@@ -0,0 +1,101 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// `jcz cleanup` with its own temp and runtime dirs, so that neither the
+/// system temp dir nor other runs' records are touched
+fn cleanup_command(root: &Path) -> assert_cmd::Command {
+    let tmp = root.join("tmp");
+    let runtime = root.join("runtime");
+    fs::create_dir_all(&tmp).unwrap();
+    fs::create_dir_all(&runtime).unwrap();
+    let mut cmd = jcz_command();
+    cmd.env("TMPDIR", &tmp).env("XDG_RUNTIME_DIR", &runtime);
+    cmd
+}
+
+#[test]
+fn test_cleanup_removes_old_scratch_by_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let work = temp_dir.path().join("work");
+    fs::create_dir_all(work.join(".jcz-tmp-a1b2c3/nested")).unwrap();
+    fs::create_dir(work.join("jczpkg_18f3a2")).unwrap();
+    create_test_file(&work, "notes.txt", TEST_DATA_SMALL);
+    fs::create_dir(temp_dir.path().join("tmp")).unwrap();
+    create_test_file(&temp_dir.path().join("tmp"), ".jcz-tmp-Xy12Zw", b"");
+    fs::create_dir(work.join("jcz-backup")).unwrap();
+
+    // Fresh ones may belong to a run still going
+    cleanup_command(temp_dir.path())
+        .args(["cleanup", "--dry-run"])
+        .arg(&work)
+        .assert()
+        .success()
+        .stdout(predicates::str::is_empty());
+
+    cleanup_command(temp_dir.path())
+        .args(["cleanup", "--dry-run", "--older-than", "0s"])
+        .arg(&work)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("would remove"))
+        .stdout(predicates::str::contains(".jcz-tmp-Xy12Zw"));
+    assert!(dir_exists(&work.join(".jcz-tmp-a1b2c3")));
+
+    cleanup_command(temp_dir.path())
+        .args(["cleanup", "--older-than", "0s"])
+        .arg(&work)
+        .assert()
+        .success();
+    assert!(!work.join(".jcz-tmp-a1b2c3").exists());
+    assert!(!work.join("jczpkg_18f3a2").exists());
+    assert!(!temp_dir.path().join("tmp/.jcz-tmp-Xy12Zw").exists());
+    assert!(file_exists(&work.join("notes.txt")));
+    assert!(dir_exists(&work.join("jcz-backup")));
+}
+
+#[test]
+fn test_dirs_recorded_by_crashed_run_removed_on_startup() {
+    let temp_dir = TempDir::new().unwrap();
+    let runtime = temp_dir.path().join("runtime");
+    let crashed = temp_dir.path().join(".jcz-tmp-crashed");
+    fs::create_dir_all(runtime.join("jcz")).unwrap();
+    fs::create_dir(&crashed).unwrap();
+    create_test_file(&crashed, "partial", TEST_DATA_MEDIUM);
+    let record = create_test_file(
+        &runtime.join("jcz"),
+        "4242.dirs",
+        format!("{}\n", crashed.canonicalize().unwrap().display()).as_bytes(),
+    );
+
+    // Any run cleans up after one that is gone, however recently it died
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+    cleanup_command(temp_dir.path())
+        .args(["-c", "gzip"])
+        .arg(&input)
+        .assert()
+        .success();
+    assert!(!crashed.exists());
+    assert!(!record.exists());
+    assert!(file_exists(&temp_dir.path().join("data.txt.gz")));
+}
+
+#[test]
+fn test_cleanup_rejects_bad_age_and_missing_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    cleanup_command(temp_dir.path())
+        .args(["cleanup", "--older-than", "soon"])
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid --older-than"));
+
+    cleanup_command(temp_dir.path())
+        .arg("cleanup")
+        .arg(temp_dir.path().join("missing"))
+        .assert()
+        .failure();
+}
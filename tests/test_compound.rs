@@ -1,6 +1,8 @@
 mod common;
 
 use common::*;
+use predicates::prelude::*;
+use std::path::Path;
 use tempfile::TempDir;
 
 // TGZ Tests (TAR + GZIP)
@@ -560,3 +562,268 @@ fn test_multiple_files_archive_can_be_extracted() {
     assert_eq!(read_file(&temp_dir.path().join("file2.txt")), b"Content 2");
     assert_eq!(read_file(&temp_dir.path().join("file3.txt")), b"Content 3");
 }
+
+#[test]
+fn test_collect_update_refreshes_newer_files() {
+    use std::time::{Duration, SystemTime};
+
+    let temp_dir = TempDir::new().unwrap();
+    let work = temp_dir.path().join("work");
+    std::fs::create_dir(&work).unwrap();
+    // Archived a minute ago, so a rewrite now is clearly newer
+    let backdate = |path: &std::path::Path| {
+        let when = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(when)
+            .unwrap();
+    };
+    let file1 = create_test_file(&work, "file1", b"one");
+    let file2 = create_test_file(&work, "file2", b"two");
+    backdate(&file1);
+    backdate(&file2);
+
+    let collect = |files: &[&std::path::Path]| {
+        jcz_command()
+            .args(["-c", "tgz", "-A", "backup", "-u", "-C"])
+            .arg(temp_dir.path())
+            .args(files)
+            .assert()
+            .success();
+    };
+    collect(&[&file1, &file2]);
+    let archive = temp_dir.path().join("backup.tar.gz");
+    let first = std::fs::read(&archive).unwrap();
+
+    // Nothing newer: the archive is left alone
+    collect(&[&file1, &file2]);
+    assert_eq!(std::fs::read(&archive).unwrap(), first);
+
+    std::fs::write(&file2, b"two, edited").unwrap();
+    let file3 = create_test_file(&work, "file3", b"three");
+    collect(&[&file2, &file3]);
+
+    let out = temp_dir.path().join("out");
+    std::fs::create_dir(&out).unwrap();
+    let status = std::process::Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&out)
+        .status()
+        .expect("Failed to run tar");
+    assert!(status.success());
+    assert_eq!(read_file(&out.join("file1")), b"one");
+    assert_eq!(read_file(&out.join("file2")), b"two, edited");
+    assert_eq!(read_file(&out.join("file3")), b"three");
+}
+
+#[test]
+fn test_collect_keep_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    for dir in ["a", "b"] {
+        let dir = temp_dir.path().join("src").join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        create_test_file(&dir, "main.c", format!("int {};", dir.display()).as_bytes());
+    }
+
+    // Same basename twice is only allowed with --keep-paths
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args([
+            "-c",
+            "tgz",
+            "-A",
+            "sources",
+            "src/a/main.c",
+            "./src/b/main.c",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Duplicate basenames"));
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-c", "tgz", "-A", "sources", "--keep-paths"])
+        .args(["src/a/main.c", "./src/b/main.c"])
+        .assert()
+        .success();
+
+    let out = temp_dir.path().join("out");
+    std::fs::create_dir(&out).unwrap();
+    let status = std::process::Command::new("tar")
+        .arg("-xzf")
+        .arg(temp_dir.path().join("sources.tar.gz"))
+        .arg("-C")
+        .arg(&out)
+        .status()
+        .expect("Failed to run tar");
+    assert!(status.success());
+    for dir in ["a", "b"] {
+        assert_eq!(
+            read_file(&out.join("src").join(dir).join("main.c")),
+            read_file(&temp_dir.path().join("src").join(dir).join("main.c"))
+        );
+    }
+
+    // An input inside another one would be stored twice
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-c", "tgz", "-a", "overlap", "--keep-paths", "src", "src/a"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Inputs overlap"));
+}
+
+// Single-extension Alias Tests (.tgz, .tbz2, .tbz, .txz)
+
+fn create_alias_archive(dir: &std::path::Path, name: &str, tar_flag: &str) -> std::path::PathBuf {
+    create_test_dir_structure(&dir.join("project"), &["src/main.rs", "README"]);
+
+    let status = std::process::Command::new("tar")
+        .current_dir(dir)
+        .args([tar_flag, name, "project"])
+        .status()
+        .expect("Failed to run tar");
+    assert!(status.success());
+
+    std::fs::remove_dir_all(dir.join("project")).unwrap();
+    dir.join(name)
+}
+
+#[test]
+fn test_alias_extensions_decompress() {
+    for (name, tar_flag) in [
+        ("project.tgz", "-czf"),
+        ("project.tbz2", "-cjf"),
+        ("project.tbz", "-cjf"),
+        ("project.txz", "-cJf"),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = create_alias_archive(temp_dir.path(), name, tar_flag);
+
+        jcz_command().arg("-d").arg(&archive).assert().success();
+
+        let extracted = temp_dir.path().join("project");
+        assert!(
+            dir_exists(&extracted),
+            "{} should extract to project/",
+            name
+        );
+        assert!(file_exists(&extracted.join("src/main.rs")));
+        assert!(
+            !temp_dir.path().join("project.tar").exists(),
+            "{} should not leave an intermediate .tar behind",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_alias_extension_identify_chain() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive = create_alias_archive(temp_dir.path(), "project.tgz", "-czf");
+
+    jcz_command()
+        .arg("identify")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("gzip -> tar"))
+        .stdout(predicates::str::contains("jcz -c tgz"));
+}
+
+#[test]
+fn test_tgz_warns_about_already_compressed_members() {
+    let temp_dir = TempDir::new().unwrap();
+    let videos = temp_dir.path().join("videos");
+    std::fs::create_dir(&videos).unwrap();
+    let mut clip = b"\0\0\0\x18ftypisom".to_vec();
+    clip.extend(vec![7u8; 20_000]);
+    create_test_file(&videos, "clip.mp4", &clip);
+    create_test_file(&videos, "notes.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg(&videos)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "already compressed (MP4/QuickTime media)",
+        ))
+        .stderr(predicates::str::contains("-l 1"));
+
+    // Nothing to suggest at the lowest level
+    std::fs::remove_file(temp_dir.path().join("videos.tar.gz")).unwrap();
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("-l")
+        .arg("1")
+        .arg(&videos)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("already compressed").not());
+}
+
+#[test]
+fn test_listed_incremental_chain() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = temp_dir.path().join("data");
+    create_test_dir_structure(&data, &["a.txt", "b.txt", "sub/c.txt"]);
+    let snapshot = temp_dir.path().join("data.snar");
+    let full = temp_dir.path().join("full");
+    let incremental = temp_dir.path().join("incremental");
+    std::fs::create_dir(&full).unwrap();
+    std::fs::create_dir(&incremental).unwrap();
+
+    let backup = |dest: &Path| {
+        jcz_command()
+            .args(["-c", "tgz", "--listed-incremental"])
+            .arg(&snapshot)
+            .arg("-C")
+            .arg(dest)
+            .arg(&data)
+            .assert()
+            .success();
+    };
+    backup(&full);
+    assert!(file_exists(&snapshot));
+
+    std::fs::remove_file(data.join("a.txt")).unwrap();
+    create_test_file(&data, "b.txt", b"changed and longer than before");
+    create_test_file(&data.join("sub"), "d.txt", TEST_DATA_SMALL);
+    backup(&incremental);
+
+    // Only the changes (and directories) go into the incremental
+    let listing = std::process::Command::new("tar")
+        .arg("-tzf")
+        .arg(incremental.join("data.tar.gz"))
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&listing.stdout);
+    assert!(listing.contains("data/b.txt"));
+    assert!(listing.contains("data/sub/d.txt"));
+    assert!(!listing.contains("c.txt"));
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .args(["-d", "--incremental", "-C"])
+        .arg(&restore)
+        .arg(full.join("data.tar.gz"))
+        .arg(incremental.join("data.tar.gz"))
+        .assert()
+        .success();
+
+    let restored = restore.join("data");
+    assert!(!file_exists(&restored.join("a.txt")));
+    assert_eq!(
+        read_file(&restored.join("b.txt")),
+        b"changed and longer than before"
+    );
+    assert!(file_exists(&restored.join("sub/c.txt")));
+    assert!(file_exists(&restored.join("sub/d.txt")));
+}
@@ -292,12 +292,75 @@ fn test_compress_with_c_flag_and_timestamp() {
     let entries: Vec<_> = fs::read_dir(&dest_dir)
         .unwrap()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "gz"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "gz"))
         .collect();
 
     assert_eq!(entries.len(), 1, "Should have exactly one .gz file");
 }
 
+/// Test compression with a custom timestamp pattern
+#[test]
+fn test_compress_with_timestamp_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    // Literal text only, so the name is predictable
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--timestamp-format")
+        .arg("nightly-%%1")
+        .arg("--utc")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    assert!(file_exists(&temp_dir.path().join("test.txt_nightly-%1.gz")));
+}
+
+/// Test output names from --name-template, including compound formats
+#[test]
+fn test_compress_with_name_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+    let dir = temp_dir.path().join("project");
+    fs::create_dir(&dir).unwrap();
+    create_test_file(&dir, "a.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("-l")
+        .arg("best")
+        .arg("--name-template")
+        .arg("{stem}-nightly-l{level}.{ext}")
+        .arg(&test_file)
+        .assert()
+        .success();
+    assert!(file_exists(&temp_dir.path().join("notes-nightly-l9.gz")));
+
+    // The compression stage of tgz appends its extension to the tar's name
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--name-template")
+        .arg("{name}-l{level}.{ext}")
+        .arg(&dir)
+        .assert()
+        .success();
+    assert!(file_exists(&temp_dir.path().join("project-l6.tar.gz")));
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--name-template")
+        .arg("{stem}-{user}.{ext}")
+        .arg(&test_file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unknown variable {user}"));
+}
+
 /// Test concurrent compression to different destinations
 #[test]
 fn test_concurrent_compress_with_different_destinations() {
@@ -357,3 +420,686 @@ fn test_compress_binary_data_with_c_flag() {
     // Verify original binary data is preserved
     assert_eq!(read_file(&test_file), TEST_DATA_BINARY);
 }
+
+/// Test password encryption with the password piped on stdin
+#[test]
+fn test_password_encryption_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("-e")
+        .arg(&test_file)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+
+    fs::remove_file(&test_file).unwrap();
+    let encrypted = temp_dir.path().join("test.txt.gz.jcze");
+    assert!(file_exists(&encrypted));
+
+    jcz_command()
+        .arg("-d")
+        .arg(&encrypted)
+        .write_stdin("battery staple\n")
+        .assert()
+        .code(4);
+    assert!(!file_exists(&test_file));
+
+    jcz_command()
+        .arg("-d")
+        .arg(&encrypted)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_SMALL);
+}
+
+/// Test that -d on an encrypted archive decrypts and unpacks in one step,
+/// leaving no intermediate decrypted archive behind
+#[test]
+fn test_decompress_encrypted_archive_in_one_step() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("project");
+    fs::create_dir(&source).unwrap();
+    create_test_file(&source, "notes.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("-e")
+        .arg(&source)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+    fs::remove_dir_all(&source).unwrap();
+
+    // An unrelated file with the intermediate's name is left alone
+    let intermediate = temp_dir.path().join("project.tar.gz");
+    fs::write(&intermediate, b"not an archive").unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--remove-encrypted")
+        .arg(temp_dir.path().join("project.tar.gz.jcze"))
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&source.join("notes.txt")), TEST_DATA_MEDIUM);
+    assert_eq!(read_file(&intermediate), b"not an archive");
+    assert!(!file_exists(&temp_dir.path().join("project.tar.gz.jcze")));
+    let leftovers: Vec<_> = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().starts_with(".jcz-tmp-"))
+        .collect();
+    assert!(leftovers.is_empty(), "scratch left behind: {:?}", leftovers);
+}
+
+/// Test encrypting and decrypting a file without any compression step
+#[test]
+fn test_encrypt_and_decrypt_subcommands() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "disk-image", TEST_DATA_BINARY);
+
+    jcz_command()
+        .arg("encrypt")
+        .arg(&test_file)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+
+    // The original is kept and the container holds it uncompressed
+    let encrypted = temp_dir.path().join("disk-image.jcze");
+    assert!(file_exists(&encrypted));
+    assert_eq!(read_file(&test_file), TEST_DATA_BINARY);
+    fs::remove_file(&test_file).unwrap();
+
+    jcz_command()
+        .arg("decrypt")
+        .arg("--remove-encrypted")
+        .arg(&encrypted)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_BINARY);
+    assert!(!file_exists(&encrypted));
+}
+
+/// Test that -d --no-decompress leaves the decrypted archive compressed
+#[test]
+fn test_decompress_no_decompress_stops_after_decryption() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("-e")
+        .arg(&test_file)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+    fs::remove_file(&test_file).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--no-decompress")
+        .arg(temp_dir.path().join("test.txt.gz.jcze"))
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+
+    let compressed = temp_dir.path().join("test.txt.gz");
+    assert!(verify_decompressed_content(&compressed, TEST_DATA_MEDIUM));
+    assert!(!file_exists(&test_file));
+
+    // A plain archive has nothing to decrypt
+    jcz_command()
+        .arg("-d")
+        .arg("--no-decompress")
+        .arg(&compressed)
+        .assert()
+        .failure();
+    assert!(!file_exists(&test_file));
+}
+
+/// Test that reencrypt moves RSA-encrypted files to a new key pair
+#[test]
+fn test_reencrypt_rotates_rsa_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let old_keys = temp_dir.path().join("old");
+    let new_keys = temp_dir.path().join("new");
+    fs::create_dir(&old_keys).unwrap();
+    fs::create_dir(&new_keys).unwrap();
+    let (old_private, old_public) = write_key_pair(&old_keys);
+    let (new_private, new_public) = write_key_pair(&new_keys);
+
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("a.txt", TEST_DATA_SMALL), ("b.txt", TEST_DATA_MEDIUM)],
+    );
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--encrypt-key")
+        .arg(&old_public)
+        .args(&files)
+        .assert()
+        .success();
+    let encrypted: Vec<_> = files
+        .iter()
+        .map(|f| f.with_extension("txt.gz.jcze"))
+        .collect();
+    for file in &files {
+        fs::remove_file(file).unwrap();
+    }
+
+    jcz_command()
+        .arg("reencrypt")
+        .arg("--decrypt-key")
+        .arg(&old_private)
+        .arg("--encrypt-key")
+        .arg(&new_public)
+        .args(&encrypted)
+        .assert()
+        .success();
+
+    // The old key no longer opens the files
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-key")
+        .arg(&old_private)
+        .arg(&encrypted[0])
+        .assert()
+        .failure();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-key")
+        .arg(&new_private)
+        .args(&encrypted)
+        .assert()
+        .success();
+    assert_eq!(read_file(&files[0]), TEST_DATA_SMALL);
+    assert_eq!(read_file(&files[1]), TEST_DATA_MEDIUM);
+}
+
+/// Test that reencrypt without keys changes the password
+#[test]
+fn test_reencrypt_changes_password() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg("-e")
+        .arg(&test_file)
+        .write_stdin("old secret\n")
+        .assert()
+        .success();
+    let encrypted = temp_dir.path().join("test.txt.xz.jcze");
+    fs::remove_file(&test_file).unwrap();
+
+    // Current password first, then the new one
+    jcz_command()
+        .arg("reencrypt")
+        .arg(&encrypted)
+        .write_stdin("old secret\nnew secret\n")
+        .assert()
+        .success();
+
+    jcz_command()
+        .arg("-d")
+        .arg(&encrypted)
+        .write_stdin("old secret\n")
+        .assert()
+        .failure();
+    assert!(!file_exists(&test_file));
+
+    jcz_command()
+        .arg("-d")
+        .arg(&encrypted)
+        .write_stdin("new secret\n")
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM);
+}
+
+/// Test that a split key needs its threshold of share files
+#[test]
+fn test_split_key_requires_threshold_shares() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--split-key")
+        .arg("3/5")
+        .arg(&test_file)
+        .assert()
+        .success();
+    fs::remove_file(&test_file).unwrap();
+
+    let encrypted = temp_dir.path().join("test.txt.gz.jcze");
+    assert!(file_exists(&encrypted));
+    let share = |i: u32| temp_dir.path().join(format!("test.txt.gz.jcze.share{}", i));
+    for i in 1..=5 {
+        assert!(file_exists(&share(i)));
+    }
+
+    // Two shares, or the same share three times, are not enough
+    jcz_command()
+        .arg("-d")
+        .arg("--share")
+        .arg(share(1))
+        .arg("--share")
+        .arg(share(4))
+        .arg(&encrypted)
+        .assert()
+        .failure();
+    jcz_command()
+        .arg("-d")
+        .arg("--share")
+        .arg(share(2))
+        .arg("--share")
+        .arg(share(2))
+        .arg("--share")
+        .arg(share(2))
+        .arg(&encrypted)
+        .assert()
+        .failure();
+    assert!(!file_exists(&test_file));
+
+    jcz_command()
+        .arg("-d")
+        .arg("--share")
+        .arg(share(5))
+        .arg("--share")
+        .arg(share(2))
+        .arg("--share")
+        .arg(share(3))
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM);
+}
+
+/// Test keyfile encryption with a keyfile from gen-keyfile
+#[test]
+fn test_keyfile_encryption() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+    let keyfile = temp_dir.path().join("secret.bin");
+    let other_keyfile = temp_dir.path().join("other.bin");
+
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&keyfile)
+        .assert()
+        .success();
+    assert_eq!(file_size(&keyfile), 32);
+    let key = read_file(&keyfile);
+
+    // An existing keyfile is never replaced
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&keyfile)
+        .assert()
+        .failure();
+    assert_eq!(read_file(&keyfile), key);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg("--encrypt-keyfile")
+        .arg(&keyfile)
+        .arg(&test_file)
+        .assert()
+        .success();
+    fs::remove_file(&test_file).unwrap();
+    let encrypted = temp_dir.path().join("test.txt.xz.jcze");
+
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&other_keyfile)
+        .assert()
+        .success();
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-keyfile")
+        .arg(&other_keyfile)
+        .arg(&encrypted)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("does not match"));
+    assert!(!file_exists(&test_file));
+
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-keyfile")
+        .arg(&keyfile)
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM);
+}
+
+/// Test that --pad-to gives inputs of different sizes the same encrypted size
+#[test]
+fn test_pad_to_hides_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let small = create_test_file(temp_dir.path(), "small.txt", TEST_DATA_SMALL);
+    let medium = create_test_file(temp_dir.path(), "medium.txt", TEST_DATA_MEDIUM);
+    let keyfile = temp_dir.path().join("secret.bin");
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&keyfile)
+        .assert()
+        .success();
+
+    jcz_command()
+        .args(["-c", "gzip", "--pad-to", "4K", "--encrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&small)
+        .arg(&medium)
+        .assert()
+        .success();
+    let small_encrypted = temp_dir.path().join("small.txt.gz.jcze");
+    let medium_encrypted = temp_dir.path().join("medium.txt.gz.jcze");
+    assert!(file_size(&small_encrypted) > 4096);
+    assert_eq!(file_size(&small_encrypted), file_size(&medium_encrypted));
+
+    jcz_command()
+        .arg("identify")
+        .arg(&small_encrypted)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("--pad-to 4096"));
+
+    fs::remove_file(&small).unwrap();
+    fs::remove_file(&medium).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-keyfile")
+        .arg(&keyfile)
+        .arg(&small_encrypted)
+        .arg(&medium_encrypted)
+        .assert()
+        .success();
+    assert_eq!(read_file(&small), TEST_DATA_SMALL);
+    assert_eq!(read_file(&medium), TEST_DATA_MEDIUM);
+}
+
+/// Test that files far larger than the I/O buffer are encrypted and
+/// decrypted whole, and that tampered data leaves no output behind
+#[test]
+fn test_encrypt_with_small_buffer() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut state = 1u32;
+    let data: Vec<u8> = (0..3 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 24) as u8
+        })
+        .collect();
+    let input = create_test_file(temp_dir.path(), "disk.img", &data);
+    let keyfile = temp_dir.path().join("secret.bin");
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&keyfile)
+        .assert()
+        .success();
+
+    jcz_command()
+        .args(["encrypt", "--buffer-size", "4K", "--encrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&input)
+        .assert()
+        .success();
+    let encrypted = temp_dir.path().join("disk.img.jcze");
+    fs::remove_file(&input).unwrap();
+
+    let mut tampered = read_file(&encrypted);
+    let middle = tampered.len() / 2;
+    tampered[middle] ^= 1;
+    let tampered_dir = temp_dir.path().join("tampered");
+    fs::create_dir(&tampered_dir).unwrap();
+    let tampered_file = create_test_file(&tampered_dir, "disk.img.jcze", &tampered);
+    jcz_command()
+        .args(["decrypt", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&tampered_file)
+        .assert()
+        .failure();
+    assert_eq!(fs::read_dir(&tampered_dir).unwrap().count(), 1);
+
+    jcz_command()
+        .args(["decrypt", "--buffer-size", "4K", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert!(read_file(&input) == data);
+}
+
+/// Test that data spanning several chunks round trips, with and without
+/// padding, and that dropping the last chunk is detected
+#[test]
+fn test_chunked_encryption() {
+    let temp_dir = TempDir::new().unwrap();
+    let data: Vec<u8> = (0..5 << 19).map(|i: u32| (i % 251) as u8).collect();
+    let keyfile = temp_dir.path().join("secret.bin");
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&keyfile)
+        .assert()
+        .success();
+
+    for (name, pad) in [("plain.bin", None), ("padded.bin", Some("4K"))] {
+        let input = create_test_file(temp_dir.path(), name, &data);
+        let mut encrypt = jcz_command();
+        encrypt
+            .arg("encrypt")
+            .arg("--encrypt-keyfile")
+            .arg(&keyfile);
+        if let Some(pad) = pad {
+            encrypt.args(["--pad-to", pad]);
+        }
+        encrypt.arg(&input).assert().success();
+        let encrypted = temp_dir.path().join(format!("{}.jcze", name));
+
+        jcz_command()
+            .arg("identify")
+            .arg(&encrypted)
+            .assert()
+            .success()
+            .stdout(predicates::str::contains("Container version: 3"))
+            .stdout(predicates::str::contains("Sealed in chunks of 1.0 MiB"));
+
+        fs::remove_file(&input).unwrap();
+        jcz_command()
+            .args(["decrypt", "--decrypt-keyfile"])
+            .arg(&keyfile)
+            .arg(&encrypted)
+            .assert()
+            .success();
+        assert!(read_file(&input) == data);
+    }
+
+    // Cut off after the first two of three chunks
+    let encrypted = temp_dir.path().join("plain.bin.jcze");
+    let sealed = read_file(&encrypted);
+    let truncated_dir = temp_dir.path().join("truncated");
+    fs::create_dir(&truncated_dir).unwrap();
+    let truncated = create_test_file(
+        &truncated_dir,
+        "plain.bin.jcze",
+        &sealed[..sealed.len() - (1 << 19) - 16],
+    );
+    jcz_command()
+        .args(["decrypt", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&truncated)
+        .assert()
+        .failure();
+    assert_eq!(fs::read_dir(&truncated_dir).unwrap().count(), 1);
+
+    // Small files are a single chunk
+    let small = create_test_file(temp_dir.path(), "small.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .arg("encrypt")
+        .arg("--encrypt-keyfile")
+        .arg(&keyfile)
+        .arg(&small)
+        .assert()
+        .success();
+    fs::remove_file(&small).unwrap();
+    jcz_command()
+        .args(["decrypt", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(temp_dir.path().join("small.txt.jcze"))
+        .assert()
+        .success();
+    assert_eq!(read_file(&small), TEST_DATA_SMALL);
+}
+
+/// Test X25519 encryption with keys from keygen
+#[test]
+fn test_x25519_encryption() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("project");
+    create_test_dir_structure(&test_dir, &["a.txt", "sub/b.txt"]);
+    let key = temp_dir.path().join("backup");
+    let other_key = temp_dir.path().join("other");
+
+    for name in [&key, &other_key] {
+        jcz_command()
+            .args(["keygen", "--type", "x25519"])
+            .arg(name)
+            .assert()
+            .success();
+    }
+    let private_key = temp_dir.path().join("backup.pem");
+    let public_key = temp_dir.path().join("backup.pub.pem");
+    assert!(fs::read_to_string(&public_key)
+        .unwrap()
+        .starts_with("-----BEGIN PUBLIC KEY-----"));
+
+    // Existing keys are never replaced
+    let before = read_file(&private_key);
+    jcz_command()
+        .args(["keygen", "--type", "x25519"])
+        .arg(&key)
+        .assert()
+        .failure();
+    assert_eq!(read_file(&private_key), before);
+
+    jcz_command()
+        .args(["-c", "tgz", "--encrypt-key"])
+        .arg(&public_key)
+        .arg(&test_dir)
+        .assert()
+        .success();
+    fs::remove_dir_all(&test_dir).unwrap();
+    let encrypted = temp_dir.path().join("project.tar.gz.jcze");
+
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-key")
+        .arg(temp_dir.path().join("other.pem"))
+        .arg(&encrypted)
+        .assert()
+        .failure();
+    assert!(!dir_exists(&test_dir));
+
+    jcz_command()
+        .arg("-d")
+        .arg("--decrypt-key")
+        .arg(&private_key)
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert!(file_exists(&test_dir.join("sub/b.txt")));
+}
+
+#[test]
+fn test_gpg_encryption() {
+    let temp_dir = TempDir::new().unwrap();
+    let gnupg_home = temp_dir.path().join("gnupg");
+    fs::create_dir(&gnupg_home).unwrap();
+    let gpg = || {
+        let mut cmd = std::process::Command::new("gpg");
+        cmd.env("GNUPGHOME", &gnupg_home).arg("--batch");
+        cmd
+    };
+    let status = gpg()
+        .args([
+            "--passphrase",
+            "",
+            "--quick-gen-key",
+            "jcz-test@example.com",
+        ])
+        .args(["default", "default", "never"])
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+
+    let test_dir = temp_dir.path().join("project");
+    create_test_dir_structure(&test_dir, &["a.txt", "sub/b.txt"]);
+    jcz_command()
+        .env("GNUPGHOME", &gnupg_home)
+        .args(["-c", "tgz", "--encrypt-gpg", "jcz-test@example.com"])
+        .arg(&test_dir)
+        .assert()
+        .success();
+    let encrypted = temp_dir.path().join("project.tar.gz.gpg");
+    assert!(file_exists(&encrypted));
+    assert!(!file_exists(&temp_dir.path().join("project.tar.gz")));
+
+    // Plain gpg opens it
+    let plain = temp_dir.path().join("plain.tar.gz");
+    let status = gpg()
+        .arg("--output")
+        .arg(&plain)
+        .arg("--decrypt")
+        .arg(&encrypted)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    assert_eq!(read_file(&plain)[..2], [0x1f, 0x8b]);
+
+    fs::remove_dir_all(&test_dir).unwrap();
+    jcz_command()
+        .env("GNUPGHOME", &gnupg_home)
+        .arg("-d")
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert_eq!(
+        read_file(&test_dir.join("sub/b.txt")),
+        b"Content of sub/b.txt"
+    );
+
+    // An unknown recipient fails without leaving output behind
+    fs::remove_file(&encrypted).unwrap();
+    jcz_command()
+        .env("GNUPGHOME", &gnupg_home)
+        .args(["-c", "tgz", "--encrypt-gpg", "nobody@example.com"])
+        .arg(&test_dir)
+        .assert()
+        .failure();
+    assert!(!file_exists(&encrypted));
+
+    let _ = std::process::Command::new("gpgconf")
+        .env("GNUPGHOME", &gnupg_home)
+        .args(["--kill", "gpg-agent"])
+        .status();
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::*;
+use std::process::Command;
+use tempfile::TempDir;
+
+#[test]
+fn test_cpio_archive_single_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("cpio")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let archive_file = temp_dir.path().join("test.txt.cpio");
+    assert!(file_exists(&archive_file), "Archive file should exist");
+    assert!(file_exists(&test_file), "Original file should be preserved");
+}
+
+#[test]
+fn test_cpio_extract_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("rootfs");
+    create_test_dir_structure(&test_dir, &["init", "etc/fstab", "bin/busybox"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("cpio")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    let archive_file = temp_dir.path().join("rootfs.cpio");
+    assert!(file_exists(&archive_file));
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(&archive_file)
+        .assert()
+        .success();
+
+    assert!(dir_exists(&test_dir), "Extracted directory should exist");
+    assert_eq!(
+        read_file(&test_dir.join("etc/fstab")),
+        b"Content of etc/fstab"
+    );
+    assert_eq!(
+        read_file(&test_dir.join("bin/busybox")),
+        b"Content of bin/busybox"
+    );
+}
+
+#[test]
+fn test_cpio_extract_gzipped_initramfs() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("initramfs");
+    create_test_dir_structure(&test_dir, &["init", "lib/modules.dep"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("cpio")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    // Compress the cpio image the way initramfs images are shipped
+    let status = Command::new("gzip")
+        .arg(temp_dir.path().join("initramfs.cpio"))
+        .status()
+        .expect("Failed to run gzip");
+    assert!(status.success());
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("initramfs.cpio.gz"))
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&test_dir.join("init")), b"Content of init");
+    assert_eq!(
+        read_file(&test_dir.join("lib/modules.dep")),
+        b"Content of lib/modules.dep"
+    );
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Flip the bits of the CRC32 in the trailer of the gzip at `path`
+fn corrupt_gzip_crc(path: &Path) {
+    let mut data = fs::read(path).unwrap();
+    let crc = data.len() - 8;
+    data[crc] ^= 0xff;
+    fs::write(path, data).unwrap();
+}
+
+/// Flip the bits of the CRC32 of `member` of the zip at `path`, in its
+/// local header and its central directory entry
+fn corrupt_zip_crc(path: &Path, member: &str) {
+    let mut data = fs::read(path).unwrap();
+    // Signature, offset of the CRC32 and of the name in each header
+    for (signature, crc, name) in [(b"PK\x03\x04", 14, 30), (b"PK\x01\x02", 16, 46)] {
+        let header = (0..data.len() - name)
+            .find(|&i| {
+                data[i..].starts_with(signature) && data[i + name..].starts_with(member.as_bytes())
+            })
+            .expect("member header");
+        data[header + crc] ^= 0xff;
+    }
+    fs::write(path, data).unwrap();
+}
+
+/// Test that gzip data failing its CRC32 is an integrity failure, with the
+/// tool and with the built-in decoder, and that --no-verify extracts it
+#[test]
+fn test_gzip_crc_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let no_tools = temp_dir.path().join("no-tools");
+    fs::create_dir(&no_tools).unwrap();
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+    jcz_command()
+        .args(["-c", "gzip"])
+        .arg(&input)
+        .assert()
+        .success();
+    fs::remove_file(&input).unwrap();
+    let compressed = temp_dir.path().join("data.txt.gz");
+    corrupt_gzip_crc(&compressed);
+
+    jcz_command()
+        .arg("-d")
+        .arg(&compressed)
+        .assert()
+        .code(5)
+        .stderr(predicates::str::contains("CRC32"));
+    jcz_command()
+        .env("PATH", &no_tools)
+        .arg("-d")
+        .arg(&compressed)
+        .assert()
+        .code(5)
+        .stderr(predicates::str::contains("gzip member 1"));
+    assert!(!input.exists());
+
+    jcz_command()
+        .args(["-d", "--no-verify"])
+        .arg(&compressed)
+        .assert()
+        .success();
+    assert_eq!(read_file(&input), TEST_DATA_MEDIUM);
+}
+
+/// Test that a zip member failing its CRC32 is named in an integrity
+/// failure, and kept with a warning under --no-verify
+#[test]
+fn test_zip_crc_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("site");
+    fs::create_dir(&dir).unwrap();
+    create_test_file(&dir, "index.html", TEST_DATA_SMALL);
+    create_test_file(&dir, "data.txt", TEST_DATA_MEDIUM);
+    jcz_command()
+        .args(["-c", "zip"])
+        .arg(&dir)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("site.zip");
+    fs::rename(&dir, temp_dir.path().join("original")).unwrap();
+    corrupt_zip_crc(&archive, "site/data.txt");
+
+    for threads in ["1", "4"] {
+        jcz_command()
+            .args(["-d", "-j", threads])
+            .arg(&archive)
+            .assert()
+            .code(5)
+            .stderr(predicates::str::contains(
+                "CRC32 mismatch in 1 member: site/data.txt",
+            ));
+        assert!(!dir.exists());
+    }
+
+    jcz_command()
+        .args(["-d", "--no-verify"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("kept as extracted"));
+    assert_eq!(read_file(&dir.join("index.html")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&dir.join("data.txt")), TEST_DATA_MEDIUM);
+}
@@ -214,7 +214,7 @@ fn test_concurrent_decompress_no_conflicts() {
     jcz_command()
         .arg("-c")
         .arg("tgz")
-        .args(&[&file1, &file2, &file3])
+        .args([&file1, &file2, &file3])
         .assert()
         .success();
 
@@ -465,3 +465,295 @@ fn test_decompress_preserves_binary_content() {
         fs::remove_file(&compressed_file).unwrap();
     }
 }
+
+/// Several processes extracting the same archive to the same place must not
+/// corrupt each other
+#[test]
+fn test_concurrent_extraction_of_same_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    let names: Vec<String> = (0..50)
+        .map(|i| format!("dir{}/file{}.bin", i % 5, i))
+        .collect();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    create_test_dir_structure(&project, &names);
+    let big: Vec<u8> = (0..512 * 1024u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(project.join("big.bin"), big).unwrap();
+    let expected = read_file(&project.join("big.bin"));
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg(&project)
+        .assert()
+        .success();
+    fs::remove_dir_all(&project).unwrap();
+
+    let archive = temp_dir.path().join("project.tar.gz");
+    let children: Vec<_> = (0..4)
+        .map(|_| {
+            std::process::Command::new(env!("CARGO_BIN_EXE_jcz"))
+                .arg("-d")
+                .arg("-f")
+                .arg(&archive)
+                .spawn()
+                .expect("Failed to spawn jcz")
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    assert_eq!(read_file(&project.join("big.bin")), expected);
+    assert_eq!(fs::read_dir(project.join("dir0")).unwrap().count(), 10);
+
+    // No staging leftovers next to the output
+    let leftovers: Vec<_> = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().contains(".jcz-tmp-"))
+        .collect();
+    assert!(leftovers.is_empty(), "Leftovers: {:?}", leftovers);
+}
+
+/// The lock only short-circuits concurrent runs; a later run extracts again
+#[test]
+fn test_sequential_extraction_runs_again() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&test_file)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("test.txt.gz");
+
+    jcz_command()
+        .arg("-d")
+        .arg("-f")
+        .arg(&archive)
+        .assert()
+        .success();
+    fs::write(&test_file, b"edited").unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("-f")
+        .arg(&archive)
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&test_file), TEST_DATA_SMALL);
+}
+
+/// Extensions are matched in any case, and `.tb2` is taken for `.tar.bz2`
+#[test]
+fn test_decompress_uppercase_and_alias_extensions() {
+    for (command, archive, renamed) in [
+        ("tgz", "data.tar.gz", "DATA.TGZ"),
+        ("tgz", "data.tar.gz", "data.TaR.gZ"),
+        ("tbz2", "data.tar.bz2", "data.tb2"),
+        ("txz", "data.tar.xz", "data.TXZ"),
+        ("zip", "data.zip", "DATA.ZIP"),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let data = temp_dir.path().join("data");
+        create_test_dir_structure(&data, &["small.txt"]);
+        create_test_file(&data, "medium.txt", TEST_DATA_MEDIUM);
+
+        jcz_command()
+            .args(["-c", command])
+            .arg(&data)
+            .assert()
+            .success();
+        fs::rename(temp_dir.path().join(archive), temp_dir.path().join(renamed)).unwrap();
+        fs::remove_dir_all(&data).unwrap();
+
+        jcz_command()
+            .arg("-d")
+            .arg(temp_dir.path().join(renamed))
+            .assert()
+            .success();
+        assert_eq!(
+            read_file(&data.join("medium.txt")),
+            TEST_DATA_MEDIUM,
+            "{}",
+            renamed
+        );
+        assert!(file_exists(&data.join("small.txt")), "{}", renamed);
+    }
+}
+
+#[test]
+fn test_decompress_uppercase_single_file() {
+    for (command, extension) in [("gzip", "GZ"), ("bzip2", "BZ2"), ("xz", "XZ")] {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+
+        jcz_command()
+            .args(["-c", command])
+            .arg(&file)
+            .assert()
+            .success();
+        let compressed = temp_dir.path().join(format!("notes.txt.{}", extension));
+        fs::rename(
+            temp_dir
+                .path()
+                .join(format!("notes.txt.{}", extension.to_lowercase())),
+            &compressed,
+        )
+        .unwrap();
+        fs::remove_file(&file).unwrap();
+
+        jcz_command().arg("-d").arg(&compressed).assert().success();
+        assert_eq!(read_file(&file), TEST_DATA_SMALL, "{}", command);
+    }
+}
+
+/// Inner files are only taken for another layer when their contents agree
+/// with their name
+#[test]
+fn test_decompress_stops_at_inner_file_misnamed_as_archive() {
+    for (name, data) in [
+        ("notes.zip", TEST_DATA_SMALL),
+        ("report.2024.backup.gz", TEST_DATA_MEDIUM),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let file = create_test_file(temp_dir.path(), name, data);
+
+        jcz_command()
+            .args(["-c", "xz"])
+            .arg(&file)
+            .assert()
+            .success();
+        fs::remove_file(&file).unwrap();
+
+        jcz_command()
+            .arg("-d")
+            .arg(temp_dir.path().join(format!("{}.xz", name)))
+            .assert()
+            .success();
+        assert_eq!(read_file(&file), data, "{}", name);
+    }
+}
+
+#[test]
+fn test_decompress_peels_nested_layers() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .args(["-c", "gzip"])
+        .arg(&file)
+        .assert()
+        .success();
+    let gz = temp_dir.path().join("data.txt.gz");
+    jcz_command()
+        .args(["-c", "bzip2"])
+        .arg(&gz)
+        .assert()
+        .success();
+    fs::remove_file(&file).unwrap();
+    fs::remove_file(&gz).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("data.txt.gz.bz2"))
+        .assert()
+        .success();
+    assert_eq!(read_file(&file), TEST_DATA_MEDIUM);
+}
+
+#[test]
+fn test_decompress_with_format_override() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // An xz file named .bin gives the name without .bin
+    let data = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+    jcz_command()
+        .args(["-c", "xz"])
+        .arg(&data)
+        .assert()
+        .success();
+    fs::rename(
+        temp_dir.path().join("data.txt.xz"),
+        temp_dir.path().join("data.bin"),
+    )
+    .unwrap();
+
+    // A gzip file without any extension gives NAME.out
+    let blob = create_test_file(temp_dir.path(), "blob.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "gzip", "--no-name"])
+        .arg(&blob)
+        .assert()
+        .success();
+    fs::rename(
+        temp_dir.path().join("blob.txt.gz"),
+        temp_dir.path().join("blob"),
+    )
+    .unwrap();
+
+    let out = temp_dir.path().join("out");
+    jcz_command()
+        .args(["-d", "--format", "xz", "-C"])
+        .arg(&out)
+        .arg(temp_dir.path().join("data.bin"))
+        .assert()
+        .success();
+    assert_eq!(read_file(&out.join("data")), TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .args(["-d", "--format", "gzip"])
+        .arg(temp_dir.path().join("blob"))
+        .assert()
+        .success();
+    assert_eq!(
+        read_file(&temp_dir.path().join("blob.out")),
+        TEST_DATA_SMALL
+    );
+    // Inputs are left alone, and no scratch space is left behind
+    assert!(file_exists(&temp_dir.path().join("data.bin")));
+    assert!(file_exists(&temp_dir.path().join("blob")));
+    let leftovers: Vec<_> = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with(".jcz-tmp"))
+        .collect();
+    assert!(leftovers.is_empty());
+}
+
+#[test]
+fn test_decompress_archive_with_format_override() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["small.txt"]);
+    create_test_file(&project, "medium.txt", TEST_DATA_MEDIUM);
+    jcz_command()
+        .args(["-c", "tgz"])
+        .arg(&project)
+        .assert()
+        .success();
+    fs::rename(
+        temp_dir.path().join("project.tar.gz"),
+        temp_dir.path().join("backup.img"),
+    )
+    .unwrap();
+    fs::remove_dir_all(&project).unwrap();
+
+    jcz_command()
+        .args(["-d", "--format", "tar.gz"])
+        .arg(temp_dir.path().join("backup.img"))
+        .assert()
+        .success();
+    assert_eq!(read_file(&project.join("medium.txt")), TEST_DATA_MEDIUM);
+
+    // Without the override, the name says nothing
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("backup.img"))
+        .assert()
+        .failure();
+}
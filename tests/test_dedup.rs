@@ -0,0 +1,72 @@
+mod common;
+
+use common::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Incompressible data, so only deduplication can shrink it
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_dedup_round_trip_stores_copies_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let images = temp_dir.path().join("images");
+    fs::create_dir(&images).unwrap();
+    let base = noise(2 << 20, 3);
+    let mut changed = base.clone();
+    changed[1 << 20] ^= 0xFF;
+    for name in ["vm1.img", "vm2.img", "vm3.img"] {
+        fs::write(images.join(name), &base).unwrap();
+    }
+    fs::write(images.join("vm4.img"), &changed).unwrap();
+    create_test_file(&images, "notes.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["-c", "dedup"])
+        .arg(&images)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("images.jczd");
+    let size = fs::metadata(&archive).unwrap().len();
+    assert!(size < (2 << 20) + (512 << 10), "archive is {} bytes", size);
+
+    fs::remove_dir_all(&images).unwrap();
+    jcz_command().arg("-d").arg(&archive).assert().success();
+    assert_eq!(read_file(&images.join("vm3.img")), base);
+    assert_eq!(read_file(&images.join("vm4.img")), changed);
+    assert_eq!(read_file(&images.join("notes.txt")), TEST_DATA_SMALL);
+}
+
+#[test]
+fn test_dedup_extract_to_move_to() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+    let out = temp_dir.path().join("out");
+    fs::create_dir(&out).unwrap();
+
+    jcz_command()
+        .args(["-c", "dedup"])
+        .arg(&input)
+        .assert()
+        .success();
+    jcz_command()
+        .arg("-d")
+        .arg("-C")
+        .arg(&out)
+        .arg(temp_dir.path().join("data.txt.jczd"))
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&out.join("data.txt")), TEST_DATA_MEDIUM);
+}
@@ -0,0 +1,97 @@
+// The fake curl is a shell script
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Install a fake `curl` in `bin` serving files from `site` by their last
+/// URL segment, without the query
+fn fake_curl(bin: &Path, site: &Path) {
+    fs::create_dir_all(bin).unwrap();
+    let path = bin.join("curl");
+    let script = format!(
+        "#!/bin/sh\nfor arg; do url=\"$arg\"; done\nurl=\"${{url%%\\?*}}\"\nexec cat '{}'/\"${{url##*/}}\"\n",
+        site.display()
+    );
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Publish `data` as `data.txt.gz` in `site`
+fn publish_gzip(temp_dir: &Path, site: &Path, data: &[u8]) {
+    fs::create_dir_all(site).unwrap();
+    let input = create_test_file(temp_dir, "data.txt", data);
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("-C")
+        .arg(site)
+        .arg(&input)
+        .assert()
+        .success();
+    fs::remove_file(&input).unwrap();
+}
+
+#[test]
+fn test_decompress_url_into_move_to() {
+    let temp_dir = TempDir::new().unwrap();
+    let site = temp_dir.path().join("site");
+    let bin = temp_dir.path().join("bin");
+    let out = temp_dir.path().join("out");
+    fs::create_dir(&out).unwrap();
+    publish_gzip(temp_dir.path(), &site, TEST_DATA_MEDIUM);
+    fake_curl(&bin, &site);
+
+    jcz_command()
+        .env("PATH", path_with(&bin))
+        .arg("-d")
+        .arg("-C")
+        .arg(&out)
+        .arg("https://example.com/releases/data.txt.gz?download=1")
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&out.join("data.txt")), TEST_DATA_MEDIUM);
+    // Only the extracted file is left behind
+    assert_eq!(fs::read_dir(&out).unwrap().count(), 1);
+}
+
+#[test]
+fn test_decompress_url_checksum_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let site = temp_dir.path().join("site");
+    let bin = temp_dir.path().join("bin");
+    publish_gzip(temp_dir.path(), &site, TEST_DATA_SMALL);
+    fake_curl(&bin, &site);
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .env("PATH", path_with(&bin))
+        .arg("-d")
+        .arg("--sha256")
+        .arg("0".repeat(64))
+        .arg("https://example.com/data.txt.gz")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Checksum mismatch"));
+
+    assert!(!file_exists(&temp_dir.path().join("data.txt")));
+    assert!(!file_exists(&temp_dir.path().join("data.txt.gz")));
+}
+
+#[test]
+fn test_url_input_requires_decompress() {
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("https://example.com/data.txt")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("can only be decompressed"));
+}
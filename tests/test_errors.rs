@@ -1,6 +1,7 @@
 mod common;
 
 use common::*;
+use std::fs;
 use tempfile::TempDir;
 
 // Invalid Option Tests
@@ -30,7 +31,9 @@ fn test_invalid_compression_level_zero() {
         .arg("0")
         .arg(&test_file)
         .assert()
-        .failure();
+        .code(1)
+        .stderr(predicates::str::contains("valid levels are 1 to 9"));
+    assert!(!temp_dir.path().join("test.txt.gz").exists());
 }
 
 #[test]
@@ -45,7 +48,9 @@ fn test_invalid_compression_level_too_high() {
         .arg("10")
         .arg(&test_file)
         .assert()
-        .failure();
+        .code(1)
+        .stderr(predicates::str::contains("valid levels are 1 to 9"));
+    assert!(!temp_dir.path().join("test.txt.gz").exists());
 }
 
 #[test]
@@ -78,6 +83,23 @@ fn test_invalid_timestamp_option_too_high() {
         .failure();
 }
 
+#[test]
+fn test_invalid_timestamp_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    // %D is month/day/year, which would put slashes in the file name
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--timestamp-format")
+        .arg("%D")
+        .arg(&test_file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("path separator"));
+}
+
 // Missing File Tests
 
 #[test]
@@ -116,6 +138,54 @@ fn test_multiple_files_with_one_missing() {
         .failure();
 }
 
+// Exit Code Tests
+
+#[test]
+fn test_usage_errors_exit_1() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("invalid")
+        .arg(&test_file)
+        .assert()
+        .code(1);
+    jcz_command()
+        .arg("--no-such-option")
+        .arg(&test_file)
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_batch_failures_exit_2_or_3() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "good.txt", TEST_DATA_SMALL);
+    let fake_gz = create_test_file(temp_dir.path(), "fake.gz", b"not a gzip file");
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&test_file)
+        .assert()
+        .success();
+    fs::remove_file(&test_file).unwrap();
+
+    // One of two inputs fails
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("good.txt.gz"))
+        .arg(&fake_gz)
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("(1 of 2)"));
+    assert!(file_exists(&test_file));
+
+    // Every input fails
+    jcz_command().arg("-d").arg(&fake_gz).assert().code(3);
+}
+
 // No Input Files Tests
 
 #[test]
@@ -360,3 +430,129 @@ fn test_decompress_file_with_wrong_extension() {
     // Try to decompress - jcz decompresses based on extension, so this should fail
     jcz_command().arg("-d").arg(&wrong_ext).assert().failure();
 }
+
+#[test]
+fn test_insufficient_space_fails_before_extracting() {
+    let temp_dir = TempDir::new().unwrap();
+    let members = temp_dir.path().join("members");
+    fs::create_dir(&members).unwrap();
+    for i in 0..256 {
+        fs::write(members.join(format!("{}.txt", i)), TEST_DATA_SMALL).unwrap();
+    }
+    let archive = temp_dir.path().join("huge.zip");
+    assert!(std::process::Command::new("zip")
+        .current_dir(&members)
+        .arg("-q")
+        .arg(&archive)
+        .args((0..256).map(|i| format!("{}.txt", i)))
+        .status()
+        .unwrap()
+        .success());
+
+    // Have every central directory entry claim almost 4 GiB: 1 TiB in all
+    let mut bytes = fs::read(&archive).unwrap();
+    let entries: Vec<usize> = bytes
+        .windows(4)
+        .enumerate()
+        .filter(|(_, w)| *w == b"PK\x01\x02")
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(entries.len(), 256);
+    for entry in entries {
+        bytes[entry + 24..entry + 28].copy_from_slice(&0xFFFF_FFFEu32.to_le_bytes());
+    }
+    fs::write(&archive, bytes).unwrap();
+
+    let dest = temp_dir.path().join("dest");
+    fs::create_dir(&dest).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("-C")
+        .arg(&dest)
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Not enough space"));
+    assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+}
+
+#[test]
+fn test_missing_tools() {
+    let temp_dir = TempDir::new().unwrap();
+    let empty_path = temp_dir.path().join("no-tools");
+    fs::create_dir(&empty_path).unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    // Every format falls back to a built-in implementation
+    for (command, extension) in [
+        ("gzip", "gz"),
+        ("bzip2", "bz2"),
+        ("xz", "xz"),
+        ("txz", "tar.xz"),
+        ("zip", "zip"),
+        ("ar", "ar"),
+    ] {
+        jcz_command()
+            .env("PATH", &empty_path)
+            .args(["-c", command])
+            .arg(&input)
+            .assert()
+            .success();
+        let compressed = temp_dir.path().join(format!("test.txt.{}", extension));
+        if matches!(command, "gzip" | "bzip2" | "xz") {
+            assert!(verify_decompressed_content(&compressed, TEST_DATA_MEDIUM));
+        }
+
+        fs::remove_file(&input).unwrap();
+        jcz_command()
+            .env("PATH", &empty_path)
+            .arg("-d")
+            .arg(&compressed)
+            .assert()
+            .success();
+        assert_eq!(read_file(&input), TEST_DATA_MEDIUM);
+        fs::remove_file(&compressed).unwrap();
+    }
+
+    // AES zips too, which otherwise need bsdtar
+    jcz_command()
+        .env("PATH", &empty_path)
+        .args(["-c", "zip", "--zip-encrypt"])
+        .arg(&input)
+        .write_stdin("hunter2\n")
+        .assert()
+        .success();
+    fs::remove_file(&input).unwrap();
+    jcz_command()
+        .env("PATH", &empty_path)
+        .args(["-d", "--zip-encrypt"])
+        .arg(temp_dir.path().join("test.txt.zip"))
+        .write_stdin("wrong\n")
+        .assert()
+        .code(4);
+    jcz_command()
+        .env("PATH", &empty_path)
+        .args(["-d", "--zip-encrypt"])
+        .arg(temp_dir.path().join("test.txt.zip"))
+        .write_stdin("hunter2\n")
+        .assert()
+        .success();
+    assert_eq!(read_file(&input), TEST_DATA_MEDIUM);
+}
+
+/// Test that --use-keyring explains the missing feature instead of prompting
+#[cfg(not(feature = "keyring"))]
+#[test]
+fn test_use_keyring_without_feature() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    let output = jcz_command()
+        .args(["-c", "gzip", "-e", "--use-keyring", "backups"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("`keyring` feature"));
+    assert!(!temp_dir.path().join("test.txt.gz").exists());
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_estimate_writes_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    let output = jcz_command()
+        .args(["estimate", "-c", "xz", "--full"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("data.txt: "), "Output: {}", stdout);
+    assert!(stdout.contains(" with xz"), "Output: {}", stdout);
+    assert!(!stdout.contains("sample"), "Output: {}", stdout);
+
+    // The estimate matches what compressing would write
+    let estimated: Vec<&str> = stdout.split(" -> ").collect();
+    jcz_command()
+        .args(["-c", "xz"])
+        .arg(&input)
+        .assert()
+        .success();
+    let actual = fs::metadata(temp_dir.path().join("data.txt.xz"))
+        .unwrap()
+        .len();
+    assert!(
+        estimated[1].starts_with(&format!("{} B", actual)),
+        "Estimate {} vs {} bytes",
+        estimated[1],
+        actual
+    );
+}
+
+#[test]
+fn test_estimate_samples_directories_as_tar() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("project");
+    create_test_dir_structure(&dir, &["src/main.rs", "README"]);
+    fs::write(dir.join("notes.txt"), TEST_DATA_SMALL.repeat(4000)).unwrap();
+
+    let output = jcz_command()
+        .args(["estimate", "-c", "gzip", "-l", "best", "--sample", "16K"])
+        .arg(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("with tgz"), "Output: {}", stdout);
+    assert!(stdout.contains("-> ~"), "Output: {}", stdout);
+    // About 16K, plus the tar headers the file sizes leave out
+    assert!(stdout.contains(" KiB sample"), "Output: {}", stdout);
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+    jcz_command()
+        .args(["estimate", "-c", "zip"])
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Cannot estimate zip"));
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// gzip stands in for an exotic tool, once as a filter and once with file
+/// arguments
+const REGISTRY: &str = r#"
+[compressor.gzf]
+compress = "gzip -c -{level}"
+decompress = "gzip -dc"
+
+[compressor.gzcopy]
+compress = "cp {in} {out}"
+decompress = "cp {in} {out}"
+extension = "copy.bin"
+"#;
+
+fn write_registry(dir: &Path) -> PathBuf {
+    create_test_file(dir, "compressors.toml", REGISTRY.as_bytes())
+}
+
+#[test]
+fn test_external_compressor_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let registry = write_registry(temp_dir.path());
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("--compressors")
+        .arg(&registry)
+        .arg("-c")
+        .arg("gzf")
+        .arg(&input)
+        .assert()
+        .success();
+
+    let compressed = temp_dir.path().join("test.txt.gzf");
+    assert!(file_exists(&compressed));
+    assert!(read_file(&compressed).starts_with(&[0x1F, 0x8B]));
+    fs::remove_file(&input).unwrap();
+
+    jcz_command()
+        .arg("--compressors")
+        .arg(&registry)
+        .arg("-d")
+        .arg(&compressed)
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&input), TEST_DATA_SMALL);
+}
+
+#[test]
+fn test_external_compressor_with_dotted_extension() {
+    let temp_dir = TempDir::new().unwrap();
+    let registry = write_registry(temp_dir.path());
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("--compressors")
+        .arg(&registry)
+        .args(["-c", "gzcopy"])
+        .arg(&input)
+        .assert()
+        .success();
+
+    let output = temp_dir.path().join("test.txt.copy.bin");
+    assert_eq!(read_file(&output), TEST_DATA_SMALL);
+    fs::remove_file(&input).unwrap();
+
+    jcz_command()
+        .arg("--compressors")
+        .arg(&registry)
+        .arg("-d")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(read_file(&input), TEST_DATA_SMALL);
+}
+
+#[test]
+fn test_external_compressor_from_default_registry() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path().join("config");
+    fs::create_dir_all(config_dir.join("jcz")).unwrap();
+    write_registry(&config_dir.join("jcz"));
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .args(["-c", "gzf"])
+        .arg(&input)
+        .assert()
+        .success();
+    assert!(file_exists(&temp_dir.path().join("test.txt.gzf")));
+}
+
+#[test]
+fn test_unregistered_command_is_a_usage_error() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("XDG_CONFIG_HOME", temp_dir.path())
+        .args(["-c", "gzf"])
+        .arg(&input)
+        .assert()
+        .code(1);
+
+    let registry = create_test_file(
+        temp_dir.path(),
+        "bad.toml",
+        b"[compressor.gzip]\ncompress = \"gzip -c\"\n",
+    );
+    jcz_command()
+        .arg("--compressors")
+        .arg(&registry)
+        .args(["-c", "gzip"])
+        .arg(&input)
+        .assert()
+        .failure();
+    assert!(input.exists());
+}
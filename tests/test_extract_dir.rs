@@ -0,0 +1,102 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Names in `dir`, sorted
+fn names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Test that an archive of loose files is extracted into a directory
+/// named after it rather than into the destination itself
+#[test]
+fn test_extract_dir_contains_loose_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("a.txt", TEST_DATA_SMALL), ("b.txt", TEST_DATA_MEDIUM)],
+    );
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-c", "tgz", "-A", "loose"])
+        .args(&files)
+        .assert()
+        .success();
+
+    let out = temp_dir.path().join("out");
+    fs::create_dir(&out).unwrap();
+    jcz_command()
+        .args(["-d", "--extract-dir", "-C"])
+        .arg(&out)
+        .arg(temp_dir.path().join("loose.tar.gz"))
+        .assert()
+        .success();
+    assert_eq!(names(&out), ["loose"]);
+    assert_eq!(read_file(&out.join("loose/a.txt")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&out.join("loose/b.txt")), TEST_DATA_MEDIUM);
+
+    // An existing directory is not replaced without confirmation
+    jcz_command()
+        .args(["-d", "--extract-dir", "-C"])
+        .arg(&out)
+        .arg(temp_dir.path().join("loose.tar.gz"))
+        .write_stdin("n\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+    assert_eq!(read_file(&out.join("loose/a.txt")), TEST_DATA_SMALL);
+}
+
+/// Test that an archive with a single top-level directory keeps it inside
+/// the new directory, which may be given a name, with or without --direct
+#[test]
+fn test_extract_dir_named() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    fs::create_dir(&project).unwrap();
+    create_test_file(&project, "main.c", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "zip"])
+        .arg(&project)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("project.zip");
+
+    for (flags, name) in [
+        (&["--extract-dir"][..], "project"),
+        (&["--mkdir=v2"][..], "v2"),
+        (&["--extract-dir=v3", "--direct"][..], "v3"),
+    ] {
+        let out = temp_dir.path().join(format!("out-{}", name));
+        jcz_command()
+            .arg("-d")
+            .args(flags)
+            .arg("-C")
+            .arg(&out)
+            .arg(&archive)
+            .assert()
+            .success();
+        assert_eq!(names(&out), [name]);
+        assert_eq!(
+            read_file(&out.join(name).join("project/main.c")),
+            TEST_DATA_SMALL
+        );
+    }
+
+    jcz_command()
+        .args(["-d", "--extract-dir=../up"])
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid extraction directory name",
+        ));
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::*;
+use std::fs::{self, File};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+#[test]
+fn test_size_filter_skips_inputs() {
+    let temp_dir = TempDir::new().unwrap();
+    let small = create_test_file(temp_dir.path(), "small.txt", TEST_DATA_SMALL);
+    let medium = create_test_file(temp_dir.path(), "medium.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .args(["-c", "gzip", "--min-size", "100"])
+        .arg(&small)
+        .arg(&medium)
+        .assert()
+        .success();
+    assert!(temp_dir.path().join("medium.txt.gz").exists());
+    assert!(!temp_dir.path().join("small.txt.gz").exists());
+
+    // Nothing left to do is not an error
+    jcz_command()
+        .args(["-c", "gzip", "--max-size", "10"])
+        .arg(&small)
+        .assert()
+        .success();
+    assert!(!temp_dir.path().join("small.txt.gz").exists());
+}
+
+#[test]
+fn test_size_filter_inside_directories() {
+    for command in ["tgz", "zip"] {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("project");
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("tiny.txt"), TEST_DATA_SMALL).unwrap();
+        fs::write(dir.join("docs/medium.txt"), TEST_DATA_MEDIUM).unwrap();
+        fs::write(dir.join("docs/huge.bin"), vec![7u8; 64 * 1024]).unwrap();
+
+        jcz_command()
+            .current_dir(temp_dir.path())
+            .args([
+                "-c",
+                command,
+                "--min-size",
+                "100",
+                "--max-size",
+                "32K",
+                "project",
+            ])
+            .assert()
+            .success();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let archive = if command == "tgz" {
+            "project.tar.gz"
+        } else {
+            "project.zip"
+        };
+        jcz_command()
+            .current_dir(temp_dir.path())
+            .args(["-d", archive])
+            .assert()
+            .success();
+        assert_eq!(
+            fs::read(dir.join("docs/medium.txt")).unwrap(),
+            TEST_DATA_MEDIUM,
+            "{}",
+            command
+        );
+        assert!(!dir.join("tiny.txt").exists(), "{}", command);
+        assert!(!dir.join("docs/huge.bin").exists(), "{}", command);
+    }
+}
+
+#[test]
+fn test_mtime_filters_in_collections() {
+    let temp_dir = TempDir::new().unwrap();
+    let old = create_test_file(temp_dir.path(), "old.log", TEST_DATA_MEDIUM);
+    let new = create_test_file(temp_dir.path(), "new.log", TEST_DATA_SMALL);
+    let year_ago = SystemTime::now() - Duration::from_secs(365 * 86_400);
+    File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(year_ago)
+        .unwrap();
+
+    // Only what is older than 30 days is archived
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-c", "tgz", "-A", "archive", "--older-than", "30d"])
+        .args(["old.log", "new.log"])
+        .assert()
+        .success();
+    fs::remove_file(&old).unwrap();
+    fs::remove_file(&new).unwrap();
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-d", "archive.tar.gz"])
+        .assert()
+        .success();
+    // -A stores files without a parent directory
+    assert_eq!(fs::read(&old).unwrap(), TEST_DATA_MEDIUM);
+    assert!(!new.exists());
+
+    let result = jcz_command()
+        .args(["-c", "gzip", "--newer-mtime", "next tuesday"])
+        .arg(temp_dir.path())
+        .assert()
+        .failure();
+    result.stderr(predicates::str::contains("Invalid --newer-mtime"));
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use tempfile::TempDir;
+
+#[test]
+fn test_fsync_compressed_outputs() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("one.txt", TEST_DATA_SMALL), ("two.txt", TEST_DATA_MEDIUM)],
+    );
+
+    jcz_command()
+        .args(["-c", "xz", "--fsync"])
+        .args(&files)
+        .assert()
+        .success();
+    assert!(verify_decompressed_content(
+        &temp_dir.path().join("one.txt.xz"),
+        TEST_DATA_SMALL
+    ));
+    assert!(verify_decompressed_content(
+        &temp_dir.path().join("two.txt.xz"),
+        TEST_DATA_MEDIUM
+    ));
+}
+
+#[test]
+fn test_encrypted_outputs_with_and_without_fsync() {
+    let temp_dir = TempDir::new().unwrap();
+    let (private_key, public_key) = write_key_pair(temp_dir.path());
+    let input = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_MEDIUM);
+
+    for fsync in ["--fsync", "--no-fsync"] {
+        jcz_command()
+            .args(["-c", "gzip", "-f", fsync, "--encrypt-key"])
+            .arg(&public_key)
+            .arg(&input)
+            .assert()
+            .success();
+        // Only the encrypted output is left
+        assert!(!temp_dir.path().join("notes.txt.gz").exists());
+    }
+
+    let encrypted = temp_dir.path().join("notes.txt.gz.jcze");
+    jcz_command()
+        .args(["-d", "-f", "--remove-encrypted", "--decrypt-key"])
+        .arg(&private_key)
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert!(!encrypted.exists());
+    assert_eq!(read_file(&input), TEST_DATA_MEDIUM);
+}
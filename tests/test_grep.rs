@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_grep_archive_members() {
+    let temp_dir = TempDir::new().unwrap();
+    let logs = temp_dir.path().join("logs");
+    fs::create_dir_all(logs.join("app")).unwrap();
+    fs::write(logs.join("app/server.log"), "start\nERROR disk full\nok\n").unwrap();
+    fs::write(logs.join("notes.txt"), "error in notes\n").unwrap();
+
+    jcz_command()
+        .args(["-c", "txz"])
+        .arg(&logs)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("logs.tar.xz");
+
+    jcz_command()
+        .args(["grep", "ERROR"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout("logs/app/server.log:ERROR disk full\n");
+
+    jcz_command()
+        .args(["grep", "-i", "-n", "error"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "logs/app/server.log:2:ERROR disk full\n",
+        ))
+        .stdout(predicate::str::contains(
+            "logs/notes.txt:1:error in notes\n",
+        ));
+
+    jcz_command()
+        .args(["grep", "-i", "--member", "*.log", "error"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout("logs/app/server.log:ERROR disk full\n");
+
+    // Nothing is extracted next to the archive
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn test_grep_single_file_and_several_archives() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("app.log");
+    fs::write(&log, "a.b\naxb\n").unwrap();
+    jcz_command()
+        .args(["-c", "gzip"])
+        .arg(&log)
+        .assert()
+        .success();
+    let gz = temp_dir.path().join("app.log.gz");
+
+    jcz_command()
+        .args(["grep", "-F", "a.b"])
+        .arg(&gz)
+        .assert()
+        .success()
+        .stdout("app.log:a.b\n");
+
+    jcz_command()
+        .args(["grep", "-F", "a.b"])
+        .arg(&gz)
+        .arg(&gz)
+        .assert()
+        .success()
+        .stdout(format!("{0}:app.log:a.b\n{0}:app.log:a.b\n", gz.display()));
+
+    // No match is not an error
+    jcz_command()
+        .args(["grep", "nothing"])
+        .arg(&gz)
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_grep_rejects_bad_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "plain.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["grep", "x"])
+        .arg(&file)
+        .assert()
+        .failure();
+
+    jcz_command()
+        .args(["grep", "("])
+        .arg(&file)
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Invalid pattern"));
+}
@@ -247,3 +247,130 @@ fn test_gzip_compress_preserves_original() {
         "Original file content should be unchanged"
     );
 }
+
+#[test]
+fn test_gzip_header_stores_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "report.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let header = read_file(&temp_dir.path().join("report.txt.gz"));
+    assert_ne!(header[3] & 0x08, 0, "FNAME flag should be set");
+    assert_eq!(&header[10..21], b"report.txt\0");
+}
+
+#[test]
+fn test_gzip_no_name_omits_header_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "secret-plans.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--no-name")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let header = read_file(&temp_dir.path().join("secret-plans.txt.gz"));
+    assert_eq!(header[3] & 0x08, 0, "FNAME flag should be clear");
+    assert_eq!(&header[4..8], &[0, 0, 0, 0], "MTIME should be zero");
+}
+
+#[test]
+fn test_gzip_renamed_archive_restores_stored_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let out_dir = temp_dir.path().join("out");
+    std::fs::create_dir(&out_dir).unwrap();
+    let renamed = out_dir.join("download.gz");
+    std::fs::rename(temp_dir.path().join("data.txt.gz"), &renamed).unwrap();
+
+    jcz_command().arg("-d").arg(&renamed).assert().success();
+
+    assert_eq!(read_file(&out_dir.join("data.txt")), TEST_DATA_MEDIUM);
+    assert!(!file_exists(&out_dir.join("download")));
+}
+
+#[test]
+fn test_gzip_decompress_no_name_keeps_outer_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let out_dir = temp_dir.path().join("out");
+    std::fs::create_dir(&out_dir).unwrap();
+    let renamed = out_dir.join("download.gz");
+    std::fs::rename(temp_dir.path().join("data.txt.gz"), &renamed).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--no-name")
+        .arg(&renamed)
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&out_dir.join("download")), TEST_DATA_SMALL);
+    assert!(!file_exists(&out_dir.join("data.txt")));
+}
+
+#[test]
+fn test_gzip_rsyncable() {
+    let temp_dir = TempDir::new().unwrap();
+    let data: Vec<u8> = (0..200_000u32)
+        .flat_map(|i| format!("line {} {}\n", i, i.wrapping_mul(2_654_435_761)).into_bytes())
+        .collect();
+    let test_file = create_test_file(temp_dir.path(), "data.txt", &data);
+    let compressed_file = temp_dir.path().join("data.txt.gz");
+
+    jcz_command()
+        .args(["-c", "gzip", "--no-name"])
+        .arg(&test_file)
+        .assert()
+        .success();
+    let plain = std::fs::read(&compressed_file).unwrap();
+    std::fs::remove_file(&compressed_file).unwrap();
+
+    jcz_command()
+        .args(["-c", "gzip", "--no-name", "--rsyncable"])
+        .arg(&test_file)
+        .assert()
+        .success();
+    // gzip restarts at content-defined points, so the stream differs
+    assert_ne!(std::fs::read(&compressed_file).unwrap(), plain);
+
+    std::fs::remove_file(&test_file).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg(&compressed_file)
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), data);
+
+    jcz_command()
+        .args(["-c", "xz", "--rsyncable"])
+        .arg(&test_file)
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("--rsyncable"));
+}
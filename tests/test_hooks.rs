@@ -0,0 +1,150 @@
+// Hooks run through sh
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Hook command appending its environment to `log`
+fn logging_hook(log: &std::path::Path) -> String {
+    format!(
+        "echo \"$JCZ_HOOK $JCZ_OPERATION ${{JCZ_INPUT##*/}} ${{JCZ_OUTPUT##*/}} $JCZ_STATUS $JCZ_TOTAL $JCZ_FAILED\" >> '{}'",
+        log.display()
+    )
+}
+
+#[test]
+fn test_hooks_run_around_batch_and_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let a = create_test_file(temp_dir.path(), "a.txt", TEST_DATA_SMALL);
+    let b = create_test_file(temp_dir.path(), "b.txt", TEST_DATA_SMALL);
+    let log = temp_dir.path().join("hooks.log");
+    let hook = logging_hook(&log);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--pre-batch-cmd")
+        .arg(&hook)
+        .arg("--pre-cmd")
+        .arg(&hook)
+        .arg("--post-cmd")
+        .arg(&hook)
+        .arg("--post-batch-cmd")
+        .arg(&hook)
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success();
+
+    let text = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "pre_batch compress    2",
+            "pre_file compress a.txt",
+            "pre_file compress b.txt",
+            "post_file compress a.txt a.txt.gz ok",
+            "post_file compress b.txt b.txt.gz ok",
+            "post_batch compress   ok 2 0",
+        ]
+    );
+}
+
+#[test]
+fn test_failing_pre_hook_skips_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let keep = create_test_file(temp_dir.path(), "keep.txt", TEST_DATA_SMALL);
+    let skip = create_test_file(temp_dir.path(), "skip.txt", TEST_DATA_SMALL);
+    let log = temp_dir.path().join("hooks.log");
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--pre-cmd")
+        .arg("test \"${JCZ_INPUT##*/}\" != skip.txt")
+        .arg("--post-batch-cmd")
+        .arg(logging_hook(&log))
+        .arg(&keep)
+        .arg(&skip)
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("pre_file hook failed"));
+
+    assert!(file_exists(&temp_dir.path().join("keep.txt.gz")));
+    assert!(!file_exists(&temp_dir.path().join("skip.txt.gz")));
+    assert_eq!(
+        fs::read_to_string(&log).unwrap().trim_end(),
+        "post_batch compress   failed 2 1"
+    );
+
+    // A failing batch hook stops the run before anything is done
+    fs::remove_file(temp_dir.path().join("keep.txt.gz")).unwrap();
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--pre-batch-cmd")
+        .arg("false")
+        .arg(&keep)
+        .assert()
+        .failure();
+    assert!(!file_exists(&temp_dir.path().join("keep.txt.gz")));
+}
+
+#[test]
+fn test_hooks_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+    let log = temp_dir.path().join("hooks.log");
+    let hooks = temp_dir.path().join("hooks.toml");
+    fs::write(
+        &hooks,
+        format!(
+            "[hooks]\npost_file = \"cp \\\"$JCZ_OUTPUT\\\" '{}'\"\n",
+            temp_dir.path().join("uploaded.gz").display()
+        ),
+    )
+    .unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--hooks")
+        .arg(&hooks)
+        .arg(&input)
+        .assert()
+        .success();
+    assert!(file_exists(&temp_dir.path().join("uploaded.gz")));
+
+    // Command-line hooks replace the file's
+    jcz_command()
+        .arg("-d")
+        .arg("-f")
+        .arg("--hooks")
+        .arg(&hooks)
+        .arg("--post-cmd")
+        .arg(logging_hook(&log))
+        .arg(temp_dir.path().join("uploaded.gz"))
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&log).unwrap().trim_end(),
+        // gzip restores the stored name
+        "post_file decompress uploaded.gz data.txt ok"
+    );
+
+    fs::write(&hooks, "[hooks]\nbefore = \"true\"\n").unwrap();
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--hooks")
+        .arg(&hooks)
+        .arg("-f")
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid hooks file"));
+}
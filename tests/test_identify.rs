@@ -0,0 +1,130 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+#[test]
+fn test_identify_gzip_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("-l")
+        .arg("9")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    jcz_command()
+        .arg("identify")
+        .arg(temp_dir.path().join("test.txt.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Format chain: gzip"))
+        .stdout(predicate::str::contains("Stored name: test.txt"))
+        .stdout(predicate::str::contains("jcz -c gzip -l 9"));
+}
+
+#[test]
+fn test_identify_compound_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("project");
+    create_test_dir_structure(&test_dir, &["a.txt", "sub/b.txt"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    jcz_command()
+        .arg("identify")
+        .arg(temp_dir.path().join("project.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Format chain: gzip -> tar"))
+        .stdout(predicate::str::contains("First entry: project/"))
+        .stdout(predicate::str::contains("jcz -c tgz"));
+}
+
+#[test]
+fn test_identify_compound_archive_without_tools() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("project");
+    create_test_dir_structure(&test_dir, &["a.txt", "sub/b.txt"]);
+    let no_tools = temp_dir.path().join("no-tools");
+    std::fs::create_dir(&no_tools).unwrap();
+
+    for (format, extension, chain) in [
+        ("tgz", "tar.gz", "gzip -> tar"),
+        ("tbz2", "tar.bz2", "bzip2 -> tar"),
+        ("txz", "tar.xz", "xz -> tar"),
+    ] {
+        jcz_command()
+            .arg("-c")
+            .arg(format)
+            .arg(&test_dir)
+            .assert()
+            .success();
+
+        jcz_command()
+            .env("PATH", &no_tools)
+            .arg("identify")
+            .arg(temp_dir.path().join(format!("project.{}", extension)))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("Format chain: {}", chain)))
+            .stdout(predicate::str::contains("First entry: project/"));
+    }
+}
+
+#[test]
+fn test_identify_renamed_file_uses_content() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let renamed = temp_dir.path().join("mystery.bin");
+    std::fs::rename(temp_dir.path().join("test.txt.xz"), &renamed).unwrap();
+
+    jcz_command()
+        .arg("identify")
+        .arg(&renamed)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Format chain: xz"))
+        .stdout(predicate::str::contains("Encrypted: no"));
+}
+
+#[test]
+fn test_identify_unrecognized_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "plain.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("identify")
+        .arg(&test_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Format chain: unknown"))
+        .stdout(predicate::str::contains("not a recognized jcz output"));
+}
+
+#[test]
+fn test_identify_nonexistent_file() {
+    jcz_command()
+        .arg("identify")
+        .arg("/nonexistent/file.gz")
+        .assert()
+        .failure();
+}
@@ -0,0 +1,102 @@
+// Ctrl-C is delivered as SIGINT to the whole process group
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Start jcz with `args` in `dir`, in a process group of its own, and wait
+/// until its tool is writing `output`
+fn start_jcz(args: &[&str], dir: &Path, output: &str) -> Child {
+    #[allow(deprecated)]
+    let child = Command::new(assert_cmd::cargo::cargo_bin("jcz"))
+        .current_dir(dir)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .unwrap();
+
+    let output = dir.join(output);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !output.exists() {
+        assert!(Instant::now() < deadline, "jcz did not start");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // The output is created just before the tool is started
+    std::thread::sleep(Duration::from_millis(300));
+    child
+}
+
+/// Send SIGINT to the process group of `child`, as a terminal does
+fn interrupt(child: &Child) {
+    let group = child.id() as libc::pid_t;
+    assert_eq!(unsafe { libc::kill(-group, libc::SIGINT) }, 0);
+}
+
+/// Names left in `dir`, sorted
+fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_interrupt_stops_batch_and_removes_partial_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let data: Vec<u8> = TEST_DATA_MEDIUM
+        .iter()
+        .cycle()
+        .take(256 * 1024)
+        .copied()
+        .collect();
+    create_test_files(
+        temp_dir.path(),
+        &[
+            ("a.txt", &data),
+            ("b.txt", &data),
+            ("c.txt", TEST_DATA_SMALL),
+        ],
+    );
+
+    // 256 KiB at 16 KiB/s keeps the first input in flight for seconds
+    let args = [
+        "-c",
+        "gzip",
+        "--threads",
+        "1",
+        "--limit-rate",
+        "16K",
+        "a.txt",
+        "b.txt",
+        "c.txt",
+    ];
+    let mut child = start_jcz(&args, temp_dir.path(), "a.txt.gz");
+    interrupt(&child);
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(entries(temp_dir.path()), ["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(read_file(&temp_dir.path().join("a.txt")), data);
+
+    // Nothing left behind is in the way of running it again
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-c", "gzip", "a.txt", "b.txt", "c.txt"])
+        .assert()
+        .success();
+    assert!(verify_decompressed_content(
+        &temp_dir.path().join("a.txt.gz"),
+        &data
+    ));
+}
@@ -0,0 +1,261 @@
+mod common;
+
+use common::*;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Archive `dir` with `command`, returning the archive path
+fn archive(dir: &Path, command: &str, extension: &str) -> std::path::PathBuf {
+    jcz_command()
+        .args(["-c", command])
+        .arg(dir)
+        .assert()
+        .success();
+    let mut name = dir.as_os_str().to_owned();
+    name.push(extension);
+    name.into()
+}
+
+#[test]
+fn test_merge_archives_and_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let part1 = temp_dir.path().join("part1");
+    let part2 = temp_dir.path().join("part2");
+    create_test_dir_structure(&part1, &["proj/a.txt", "proj/shared.txt"]);
+    create_test_dir_structure(&part2, &["proj/b.txt"]);
+    // Same content at the same path is not a collision
+    std::fs::copy(part1.join("proj/shared.txt"), part2.join("proj/shared.txt")).unwrap();
+    let extra = temp_dir.path().join("extra");
+    create_test_dir_structure(&extra, &["notes.txt"]);
+
+    let tgz = archive(&part1.join("proj"), "tgz", ".tar.gz");
+    let zip = archive(&part2.join("proj"), "zip", ".zip");
+    let output = temp_dir.path().join("all.tar.xz");
+
+    jcz_command()
+        .arg("merge")
+        .arg(&output)
+        .arg(&tgz)
+        .arg(&zip)
+        .arg(&extra)
+        .assert()
+        .success();
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(
+        read_file(&restore.join("proj/a.txt")),
+        b"Content of proj/a.txt"
+    );
+    assert_eq!(
+        read_file(&restore.join("proj/b.txt")),
+        b"Content of proj/b.txt"
+    );
+    assert!(file_exists(&restore.join("proj/shared.txt")));
+    assert_eq!(
+        read_file(&restore.join("extra/notes.txt")),
+        b"Content of notes.txt"
+    );
+}
+
+#[test]
+fn test_merge_collisions() {
+    let temp_dir = TempDir::new().unwrap();
+    let first = temp_dir.path().join("first");
+    let second = temp_dir.path().join("second");
+    create_test_dir_structure(&first, &["data/config.ini"]);
+    std::fs::create_dir_all(second.join("data")).unwrap();
+    create_test_file(&second.join("data"), "config.ini", TEST_DATA_SMALL);
+
+    let a = archive(&first.join("data"), "tar", ".tar");
+    let b = archive(&second.join("data"), "tgz", ".tar.gz");
+    let output = temp_dir.path().join("merged.tar");
+
+    jcz_command()
+        .arg("merge")
+        .arg(&output)
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("data/config.ini"));
+    assert!(!file_exists(&output));
+
+    jcz_command()
+        .args(["merge", "--on-collision", "last"])
+        .arg(&output)
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Path collision"));
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(read_file(&restore.join("data/config.ini")), TEST_DATA_SMALL);
+
+    // Unsupported output formats are refused up front
+    jcz_command()
+        .arg("merge")
+        .arg(temp_dir.path().join("out.tar.zst"))
+        .arg(&a)
+        .assert()
+        .failure();
+}
+
+/// Build `project/` with an executable, a symlink, an empty directory and
+/// old mtimes on a file and a directory
+#[cfg(unix)]
+fn create_metadata_project(base: &Path) -> std::path::PathBuf {
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let project = base.join("project");
+    fs::create_dir_all(project.join("bin")).unwrap();
+    fs::create_dir_all(project.join("empty")).unwrap();
+    let script = create_test_file(&project.join("bin"), "run.sh", TEST_DATA_SMALL);
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let notes = create_test_file(&project, "notes.txt", TEST_DATA_MEDIUM);
+    symlink("notes.txt", project.join("latest")).unwrap();
+
+    let old = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    for path in [&notes, &project.join("bin")] {
+        fs::File::open(path).unwrap().set_modified(old).unwrap();
+    }
+    project
+}
+
+/// Check what `create_metadata_project` made, as extracted into `restore`
+#[cfg(unix)]
+fn assert_metadata_kept(restore: &Path) {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let project = restore.join("project");
+    let mode = fs::metadata(project.join("bin/run.sh"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(
+        fs::read_link(project.join("latest")).unwrap(),
+        Path::new("notes.txt")
+    );
+    assert!(dir_exists(&project.join("empty")));
+    assert_eq!(read_file(&project.join("notes.txt")), TEST_DATA_MEDIUM);
+
+    let old = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    for path in [project.join("notes.txt"), project.join("bin")] {
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(modified, old, "mtime of {}", path.display());
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_merge_converts_tar_to_zip_keeping_metadata() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = create_metadata_project(temp_dir.path());
+    let tgz = archive(&project, "tgz", ".tar.gz");
+    let output = temp_dir.path().join("converted.zip");
+
+    jcz_command()
+        .arg("merge")
+        .arg(&output)
+        .arg(&tgz)
+        .assert()
+        .success();
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(&output)
+        .assert()
+        .success();
+    assert_metadata_kept(&restore);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_merge_converts_zip_to_tar_keeping_metadata() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = create_metadata_project(temp_dir.path());
+    jcz_command()
+        .args(["-c", "zip", "--comment", "build 7"])
+        .arg(&project)
+        .assert()
+        .success();
+    let output = temp_dir.path().join("converted.tar.gz");
+
+    // The comment has nowhere to go
+    jcz_command()
+        .arg("merge")
+        .arg(&output)
+        .arg(temp_dir.path().join("project.zip"))
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "archive comment cannot be stored in a tar",
+        ));
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(&output)
+        .assert()
+        .success();
+    assert_metadata_kept(&restore);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_merge_to_zip_warns_about_special_files_and_hard_links() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = temp_dir.path().join("data");
+    std::fs::create_dir(&data).unwrap();
+    let file = create_test_file(&data, "a.txt", TEST_DATA_SMALL);
+    std::fs::hard_link(&file, data.join("b.txt")).unwrap();
+    let status = std::process::Command::new("mkfifo")
+        .arg(data.join("pipe"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = temp_dir.path().join("data.zip");
+
+    jcz_command()
+        .arg("merge")
+        .arg(&output)
+        .arg(&data)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "data/pipe: special files (devices, FIFOs, sockets) cannot be stored in a zip",
+        ))
+        .stderr(predicates::str::contains(
+            "2 hard-linked files are stored in the zip as separate copies",
+        ));
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(read_file(&restore.join("data/b.txt")), TEST_DATA_SMALL);
+    assert!(!restore.join("data/pipe").exists());
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Compress `parts` one by one with `command` and write the outputs back
+/// to back to `joined.EXTENSION`, returning its path
+fn concatenated(dir: &Path, command: &str, extension: &str, parts: &[&[u8]]) -> std::path::PathBuf {
+    let mut data = Vec::new();
+    for part in parts {
+        let input = create_test_file(dir, "joined", part);
+        jcz_command()
+            .args(["-c", command])
+            .arg(&input)
+            .assert()
+            .success();
+        let compressed = dir.join(format!("joined.{}", extension));
+        data.extend(read_file(&compressed));
+        fs::remove_file(&compressed).unwrap();
+        fs::remove_file(&input).unwrap();
+    }
+    // Named like the parts, so gzip's stored name matches
+    let path = dir.join(format!("joined.{}", extension));
+    fs::write(&path, data).unwrap();
+    path
+}
+
+/// Test that every member or stream of a concatenated file is decompressed,
+/// by the tools and by the built-in decoders
+#[test]
+fn test_concatenated_members_and_streams() {
+    let temp_dir = TempDir::new().unwrap();
+    let no_tools = temp_dir.path().join("no-tools");
+    fs::create_dir(&no_tools).unwrap();
+    let parts: [&[u8]; 3] = [TEST_DATA_SMALL, TEST_DATA_MEDIUM, b"last part\n"];
+    let expected = parts.concat();
+
+    for (command, extension) in [("gzip", "gz"), ("bzip2", "bz2"), ("xz", "xz")] {
+        for builtin in [false, true] {
+            let joined = concatenated(temp_dir.path(), command, extension, &parts);
+            let mut cmd = jcz_command();
+            if builtin {
+                cmd.env("PATH", &no_tools);
+            }
+            cmd.args(["-d", "-j", "4"]).arg(&joined).assert().success();
+            let output = temp_dir.path().join("joined");
+            assert_eq!(
+                read_file(&output),
+                expected,
+                "{} builtin={}",
+                command,
+                builtin
+            );
+            fs::remove_file(&output).unwrap();
+            fs::remove_file(&joined).unwrap();
+        }
+    }
+}
+
+/// Test that many bzip2 streams, as pbzip2 writes them, decode in order
+/// with the built-in decoder
+#[test]
+fn test_many_bzip2_streams_builtin() {
+    let temp_dir = TempDir::new().unwrap();
+    let no_tools = temp_dir.path().join("no-tools");
+    fs::create_dir(&no_tools).unwrap();
+    let parts: Vec<Vec<u8>> = (0..12)
+        .map(|i| format!("stream {}\n", i).repeat(2000).into_bytes())
+        .collect();
+    let parts: Vec<&[u8]> = parts.iter().map(|part| &part[..]).collect();
+    let joined = concatenated(temp_dir.path(), "bzip2", "bz2", &parts);
+
+    jcz_command()
+        .env("PATH", &no_tools)
+        .args(["-d", "-j", "3"])
+        .arg(&joined)
+        .assert()
+        .success();
+    assert_eq!(read_file(&temp_dir.path().join("joined")), parts.concat());
+}
@@ -1,6 +1,7 @@
 mod common;
 
 use common::*;
+use predicates::prelude::*;
 use std::fs;
 use tempfile::TempDir;
 
@@ -53,7 +54,10 @@ fn test_timestamp_date() {
         })
         .collect();
 
-    assert!(entries.len() > 0, "Should have created a timestamped file");
+    assert!(
+        !entries.is_empty(),
+        "Should have created a timestamped file"
+    );
 }
 
 #[test]
@@ -80,7 +84,10 @@ fn test_timestamp_datetime() {
         })
         .collect();
 
-    assert!(entries.len() > 0, "Should have created a timestamped file");
+    assert!(
+        !entries.is_empty(),
+        "Should have created a timestamped file"
+    );
 }
 
 #[test]
@@ -107,7 +114,10 @@ fn test_timestamp_nanoseconds() {
         })
         .collect();
 
-    assert!(entries.len() > 0, "Should have created a timestamped file");
+    assert!(
+        !entries.is_empty(),
+        "Should have created a timestamped file"
+    );
 }
 
 #[test]
@@ -130,12 +140,79 @@ fn test_timestamp_default_is_none() {
     );
 }
 
+/// Names of the date-stamped outputs of `test.txt` in `dir`
+fn dated_outputs(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| n.starts_with("test.txt_") && n.ends_with(".gz"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_timestamp_collision_in_place_gets_new_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    for _ in 0..3 {
+        jcz_command()
+            .arg("-c")
+            .arg("gzip")
+            .arg("-t")
+            .arg("1")
+            .arg(&test_file)
+            .assert()
+            .success();
+    }
+
+    let outputs = dated_outputs(temp_dir.path());
+    assert_eq!(outputs.len(), 3, "Outputs: {:?}", outputs);
+    for output in &outputs {
+        assert!(verify_decompressed_content(
+            &temp_dir.path().join(output),
+            TEST_DATA_SMALL
+        ));
+    }
+}
+
+#[test]
+fn test_timestamp_collision_in_move_to_never_overwrites() {
+    let temp_dir = TempDir::new().unwrap();
+    let shared = temp_dir.path().join("shared");
+    fs::create_dir(&shared).unwrap();
+
+    // Two "hosts" with their own copy of the input, same second, same target
+    let mut inputs = Vec::new();
+    for host in ["a", "b"] {
+        let dir = temp_dir.path().join(host);
+        fs::create_dir(&dir).unwrap();
+        inputs.push(create_test_file(&dir, "test.txt", host.as_bytes()));
+    }
+
+    for input in &inputs {
+        jcz_command()
+            .arg("-c")
+            .arg("gzip")
+            .arg("-t")
+            .arg("1")
+            .arg("-C")
+            .arg(&shared)
+            .arg(input)
+            .assert()
+            .success();
+    }
+
+    let outputs = dated_outputs(&shared);
+    assert_eq!(outputs.len(), 2, "Outputs: {:?}", outputs);
+    assert!(verify_decompressed_content(&shared.join(&outputs[0]), b"a"));
+}
+
 // Move-to Directory Option Tests
-// NOTE: These tests are currently skipped due to cross-filesystem issues
-// The -C option uses rename() which fails across different filesystems
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_move_to_directory() {
     let temp_dir = TempDir::new().unwrap();
     let output_dir = temp_dir.path().join("output");
@@ -167,7 +244,6 @@ fn test_move_to_directory() {
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_move_to_directory_multiple_files() {
     let temp_dir = TempDir::new().unwrap();
     let output_dir = temp_dir.path().join("output");
@@ -195,7 +271,6 @@ fn test_move_to_directory_multiple_files() {
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_move_to_with_timestamp() {
     let temp_dir = TempDir::new().unwrap();
     let output_dir = temp_dir.path().join("output");
@@ -225,229 +300,385 @@ fn test_move_to_with_timestamp() {
         .collect();
 
     assert!(
-        entries.len() > 0,
+        !entries.is_empty(),
         "Timestamped file should be in output directory"
     );
 }
 
 // Collection Option Tests
-// NOTE: These tests are currently skipped due to cross-filesystem issues
-// The collection feature uses rename() which fails across different filesystems
 
-#[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
-fn test_collect_with_parent_directory() {
-    let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
+/// Create two files to collect in `dir`, returning their names
+fn create_collection_inputs(dir: &std::path::Path) -> [&'static str; 2] {
+    create_test_files(
+        dir,
         &[
             ("file1.txt", TEST_DATA_SMALL),
             ("file2.txt", TEST_DATA_MEDIUM),
         ],
     );
+    ["file1.txt", "file2.txt"]
+}
+
+/// Extract the collection `name` in `dir` into a fresh `out` directory there
+fn extract_collection(dir: &std::path::Path, name: &str) -> std::path::PathBuf {
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("-C")
+        .arg(&out)
+        .arg(dir.join(name))
+        .assert()
+        .success();
+    out
+}
 
-    let archive_name = temp_dir.path().join("archive.tar.gz");
+#[test]
+fn test_collect_with_parent_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tgz")
         .arg("-a")
-        .arg(&archive_name)
-        .args(&files)
+        .arg("archive")
+        .args(files)
         .assert()
         .success();
 
-    assert!(file_exists(&archive_name), "Archive should be created");
+    let out = extract_collection(temp_dir.path(), "archive.tar.gz");
+    assert_eq!(read_file(&out.join("archive/file1.txt")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&out.join("archive/file2.txt")), TEST_DATA_MEDIUM);
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_collect_flat_without_parent_directory() {
     let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = temp_dir.path().join("archive.tar.gz");
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tgz")
         .arg("-A")
-        .arg(&archive_name)
-        .args(&files)
+        .arg("archive")
+        .args(files)
         .assert()
         .success();
 
-    assert!(file_exists(&archive_name), "Flat archive should be created");
+    let out = extract_collection(temp_dir.path(), "archive.tar.gz");
+    assert_eq!(read_file(&out.join("file1.txt")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&out.join("file2.txt")), TEST_DATA_MEDIUM);
+    assert!(!out.join("archive").exists());
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_collect_preserves_originals() {
     let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = temp_dir.path().join("archive.tar.gz");
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tgz")
         .arg("-a")
-        .arg(&archive_name)
-        .args(&files)
+        .arg("archive")
+        .args(files)
         .assert()
         .success();
 
+    assert!(file_exists(&temp_dir.path().join("archive.tar.gz")));
     // Original files should still exist
-    assert!(file_exists(&files[0]), "Original files should be preserved");
-    assert!(file_exists(&files[1]), "Original files should be preserved");
+    for file in files {
+        assert!(
+            file_exists(&temp_dir.path().join(file)),
+            "Original files should be preserved"
+        );
+    }
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_collect_with_bzip2() {
     let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = temp_dir.path().join("archive.tar.bz2");
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tbz2")
         .arg("-a")
-        .arg(&archive_name)
-        .args(&files)
+        .arg("archive")
+        .args(files)
         .assert()
         .success();
 
-    assert!(file_exists(&archive_name), "TBZ2 archive should be created");
+    assert!(
+        file_exists(&temp_dir.path().join("archive.tar.bz2")),
+        "TBZ2 archive should be created"
+    );
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_collect_with_xz() {
     let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = temp_dir.path().join("archive.tar.xz");
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("txz")
         .arg("-a")
-        .arg(&archive_name)
-        .args(&files)
+        .arg("archive")
+        .args(files)
         .assert()
         .success();
 
-    assert!(file_exists(&archive_name), "TXZ archive should be created");
+    assert!(
+        file_exists(&temp_dir.path().join("archive.tar.xz")),
+        "TXZ archive should be created"
+    );
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_collect_with_compression_level() {
     let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = temp_dir.path().join("archive.tar.gz");
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tgz")
         .arg("-a")
-        .arg(&archive_name)
+        .arg("archive")
         .arg("-l")
         .arg("9")
-        .args(&files)
+        .args(files)
         .assert()
         .success();
 
     assert!(
-        file_exists(&archive_name),
+        file_exists(&temp_dir.path().join("archive.tar.gz")),
         "Archive with compression level should be created"
     );
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_collect_decompress() {
     let temp_dir = TempDir::new().unwrap();
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = temp_dir.path().join("archive.tar.gz");
+    let files = create_collection_inputs(temp_dir.path());
 
     // Create archive
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tgz")
         .arg("-a")
-        .arg(&archive_name)
-        .args(&files)
+        .arg("archive")
+        .args(files)
         .assert()
         .success();
 
     // Remove original files
-    for file in &files {
-        fs::remove_file(file).unwrap();
+    for file in files {
+        fs::remove_file(temp_dir.path().join(file)).unwrap();
     }
 
     // Decompress archive
     jcz_command()
         .arg("-d")
-        .arg(&archive_name)
+        .arg(temp_dir.path().join("archive.tar.gz"))
         .assert()
         .success();
 
-    // Files should be restored
-    assert!(
-        file_exists(&files[0]),
-        "Files should be extracted from archive"
+    // Files should be restored under the package directory
+    assert_eq!(
+        read_file(&temp_dir.path().join("archive/file1.txt")),
+        TEST_DATA_SMALL
     );
-    assert!(
-        file_exists(&files[1]),
-        "Files should be extracted from archive"
+    assert_eq!(
+        read_file(&temp_dir.path().join("archive/file2.txt")),
+        TEST_DATA_MEDIUM
+    );
+}
+
+// Group-by-directory Tests
+
+#[test]
+fn test_group_by_dir_one_archive_per_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_dir_structure(
+        temp_dir.path(),
+        &["proj1/a.txt", "proj1/b.txt", "proj2/c.txt"],
+    );
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("--group-by-dir")
+        .args(&files)
+        .assert()
+        .success();
+
+    let proj1 = temp_dir.path().join("proj1.tar.gz");
+    let proj2 = temp_dir.path().join("proj2.tar.gz");
+    assert!(file_exists(&proj1), "proj1 archive should be created");
+    assert!(file_exists(&proj2), "proj2 archive should be created");
+
+    // Extract proj1 elsewhere and check it holds only its own files
+    let out_dir = temp_dir.path().join("out");
+    fs::create_dir(&out_dir).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("-C")
+        .arg(&out_dir)
+        .arg(&proj1)
+        .assert()
+        .success();
+
+    assert!(file_exists(&out_dir.join("proj1/a.txt")));
+    assert!(file_exists(&out_dir.join("proj1/b.txt")));
+    assert!(!out_dir.join("proj1/c.txt").exists());
+}
+
+#[test]
+fn test_group_by_dir_with_move_to() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_dir_structure(temp_dir.path(), &["logs/x.log", "data/y.csv"]);
+    let out_dir = temp_dir.path().join("archives");
+    fs::create_dir(&out_dir).unwrap();
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("txz")
+        .arg("--group-by-dir")
+        .arg("-C")
+        .arg(&out_dir)
+        .args(&files)
+        .assert()
+        .success();
+
+    assert!(file_exists(&out_dir.join("logs.tar.xz")));
+    assert!(file_exists(&out_dir.join("data.tar.xz")));
+}
+
+#[test]
+fn test_group_by_dir_requires_compound_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_dir_structure(temp_dir.path(), &["proj1/a.txt"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--group-by-dir")
+        .args(&files)
+        .assert()
+        .failure();
+}
+
+// Sidecar Metadata Tests
+
+fn read_sidecar(path: &std::path::Path) -> serde_json::Value {
+    serde_json::from_slice(&read_file(path)).expect("Sidecar should be valid JSON")
+}
+
+#[test]
+fn test_sidecar_metadata_for_compound_archive() {
+    use sha2::{Digest, Sha256};
+
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("project");
+    create_test_dir_structure(&test_dir, &["a.txt", "sub/b.txt"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--sidecar-metadata")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("project.tar.gz");
+    let sidecar = read_sidecar(&temp_dir.path().join("project.tar.gz.jcz.json"));
+
+    assert_eq!(sidecar["archive"], "project.tar.gz");
+    assert_eq!(sidecar["format"], "tar.gz");
+    assert_eq!(sidecar["size"], file_size(&archive));
+    let digest: String = Sha256::digest(read_file(&archive))
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(sidecar["sha256"], digest);
+    assert!(sidecar["encryption"].is_null());
+
+    let paths: Vec<&str> = sidecar["manifest"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "project",
+            "project/a.txt",
+            "project/sub",
+            "project/sub/b.txt"
+        ]
     );
+
+    // The archive itself stays a standard .tar.gz
+    let status = std::process::Command::new("tar")
+        .arg("-tzf")
+        .arg(&archive)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn test_sidecar_metadata_follows_move_to() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+    let out_dir = temp_dir.path().join("out");
+    fs::create_dir(&out_dir).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--sidecar-metadata")
+        .arg("-C")
+        .arg(&out_dir)
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    let sidecar = read_sidecar(&out_dir.join("test.txt.gz.jcz.json"));
+    assert_eq!(sidecar["manifest"][0]["path"], "test.txt");
+    assert_eq!(sidecar["manifest"][0]["size"], TEST_DATA_SMALL.len());
+}
+
+#[test]
+fn test_no_sidecar_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    assert!(!temp_dir.path().join("test.txt.gz.jcz.json").exists());
 }
 
 // Combined Options Tests
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_combined_move_to_and_timestamp() {
     let temp_dir = TempDir::new().unwrap();
     let output_dir = temp_dir.path().join("output");
@@ -474,39 +705,838 @@ fn test_combined_move_to_and_timestamp() {
         .filter_map(|e| e.ok())
         .collect();
 
-    assert!(entries.len() > 0, "Combined options should work together");
+    assert!(!entries.is_empty(), "Combined options should work together");
 }
 
 #[test]
-#[ignore = "Skipped: cross-filesystem rename issue with tempfile"]
 fn test_combined_collect_move_to_and_level() {
     let temp_dir = TempDir::new().unwrap();
     let output_dir = temp_dir.path().join("output");
     fs::create_dir(&output_dir).unwrap();
-
-    let files = create_test_files(
-        temp_dir.path(),
-        &[
-            ("file1.txt", TEST_DATA_SMALL),
-            ("file2.txt", TEST_DATA_MEDIUM),
-        ],
-    );
-
-    let archive_name = output_dir.join("archive.tar.gz");
+    let files = create_collection_inputs(temp_dir.path());
 
     jcz_command()
+        .current_dir(temp_dir.path())
         .arg("-c")
         .arg("tgz")
         .arg("-a")
-        .arg(&archive_name)
+        .arg("archive")
+        .arg("-C")
+        .arg(&output_dir)
         .arg("-l")
         .arg("9")
-        .args(&files)
+        .args(files)
         .assert()
         .success();
 
     assert!(
-        file_exists(&archive_name),
+        file_exists(&output_dir.join("archive.tar.gz")),
         "Archive should be in output directory"
     );
+    assert!(!temp_dir.path().join("archive.tar.gz").exists());
+}
+
+// Durability Option Tests
+
+#[test]
+fn test_durability_modes_extract() {
+    for mode in ["full", "default", "lazy"] {
+        let temp_dir = TempDir::new().unwrap();
+        let test_dir = temp_dir.path().join("data");
+        create_test_dir_structure(&test_dir, &["a.txt", "nested/b.txt"]);
+
+        jcz_command()
+            .arg("-c")
+            .arg("tgz")
+            .arg(&test_dir)
+            .assert()
+            .success();
+
+        fs::remove_dir_all(&test_dir).unwrap();
+
+        jcz_command()
+            .arg("-d")
+            .arg("--durability")
+            .arg(mode)
+            .arg(temp_dir.path().join("data.tar.gz"))
+            .assert()
+            .success();
+
+        assert_eq!(
+            read_file(&test_dir.join("nested/b.txt")),
+            b"Content of nested/b.txt",
+            "Extraction with --durability {} should succeed",
+            mode
+        );
+    }
+}
+
+#[test]
+fn test_durability_invalid_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-d")
+        .arg("--durability")
+        .arg("sometimes")
+        .arg(&test_file)
+        .assert()
+        .failure();
+}
+
+// Reproducible Mode Tests
+
+/// Build `command` archives of the same tree in two places with different
+/// mtimes and argument order, returning both archives' bytes
+fn build_twice(command: &str, extension: &str, epoch: Option<&str>) -> (Vec<u8>, Vec<u8>) {
+    let temp_dir = TempDir::new().unwrap();
+    let mut outputs = Vec::new();
+
+    for (round, age) in [(0, 3600u64), (1, 7200)] {
+        let dir = temp_dir.path().join(format!("round{}", round));
+        let tree = dir.join("dist");
+        create_test_dir_structure(&tree, &["bin/app", "lib/a.so", "README"]);
+
+        let when = std::time::SystemTime::now() - std::time::Duration::from_secs(age);
+        for file in ["bin/app", "lib/a.so", "README"] {
+            fs::File::options()
+                .write(true)
+                .open(tree.join(file))
+                .unwrap()
+                .set_modified(when)
+                .unwrap();
+        }
+
+        let mut cmd = jcz_command();
+        if let Some(epoch) = epoch {
+            cmd.env("SOURCE_DATE_EPOCH", epoch);
+        }
+        cmd.arg("-c")
+            .arg(command)
+            .arg("--reproducible")
+            .arg(&tree)
+            .assert()
+            .success();
+
+        outputs.push(fs::read(dir.join(format!("dist.{}", extension))).unwrap());
+    }
+
+    let second = outputs.pop().unwrap();
+    (outputs.pop().unwrap(), second)
+}
+
+#[test]
+fn test_reproducible_tgz_is_bit_identical() {
+    let (first, second) = build_twice("tgz", "tar.gz", None);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_reproducible_cpio_is_bit_identical() {
+    let (first, second) = build_twice("cpio", "cpio", Some("1700000000"));
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_reproducible_clamps_to_source_date_epoch() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("SOURCE_DATE_EPOCH", "1700000000")
+        .arg("-c")
+        .arg("tar")
+        .arg("--reproducible")
+        .arg(&file)
+        .assert()
+        .success();
+
+    let output = std::process::Command::new("tar")
+        .arg("--numeric-owner")
+        .arg("--full-time")
+        .arg("-tvf")
+        .arg(temp_dir.path().join("notes.txt.tar"))
+        .env("TZ", "UTC")
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(listing.contains("0/0"), "Listing: {}", listing);
+    assert!(
+        listing.contains("2023-11-14 22:13:20"),
+        "Listing: {}",
+        listing
+    );
+}
+
+#[test]
+fn test_reproducible_rejects_bad_source_date_epoch() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("SOURCE_DATE_EPOCH", "yesterday")
+        .arg("-c")
+        .arg("tgz")
+        .arg("--reproducible")
+        .arg(&file)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_stats_reports_each_file_and_total() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("one.txt", TEST_DATA_SMALL), ("two.txt", TEST_DATA_MEDIUM)],
+    );
+
+    let output = jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--stats")
+        .args(&files)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "Output: {}", stdout);
+    assert!(lines[0].contains("one.txt.gz"), "Output: {}", stdout);
+    assert!(lines[0].contains(&format!("{} B ->", TEST_DATA_SMALL.len())));
+    assert!(lines[1].contains("two.txt.gz"), "Output: {}", stdout);
+    assert!(
+        lines[2].starts_with("Total: 2 files,"),
+        "Output: {}",
+        stdout
+    );
+    assert!(lines[2].contains("%) in "), "Output: {}", stdout);
+}
+
+#[test]
+fn test_stats_on_decompress_and_off_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicates::str::is_empty());
+    fs::remove_file(&file).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--stats")
+        .arg(temp_dir.path().join("data.txt.xz"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("data.txt.xz -> "))
+        .stdout(predicates::str::contains("Total: 1 files,"));
+}
+
+#[test]
+fn test_auto_picks_format_per_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = create_test_file(
+        temp_dir.path(),
+        "app.log",
+        "GET /index.html 200\n".repeat(500).as_bytes(),
+    );
+    let photo = create_test_file(
+        temp_dir.path(),
+        "photo.jpg",
+        &[&[0xFF, 0xD8, 0xFF, 0xE0][..], b"JFIF", TEST_DATA_SMALL].concat(),
+    );
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs", "README"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("auto")
+        .arg(&log)
+        .arg(&photo)
+        .arg(&project)
+        .assert()
+        .success();
+
+    assert!(temp_dir.path().join("app.log.xz").exists());
+    assert!(temp_dir.path().join("project.tar.xz").exists());
+    // Already compressed: left as it is
+    assert!(!temp_dir.path().join("photo.jpg.gz").exists());
+    assert!(!temp_dir.path().join("photo.jpg.xz").exists());
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 5);
+}
+
+#[test]
+fn test_auto_stores_compressed_file_when_encrypting() {
+    let temp_dir = TempDir::new().unwrap();
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::rngs::OsRng, 2048).unwrap();
+    let public_key = temp_dir.path().join("public.pem");
+    fs::write(
+        &public_key,
+        rsa::pkcs8::EncodePublicKey::to_public_key_pem(
+            &rsa::RsaPublicKey::from(&private_key),
+            rsa::pkcs8::LineEnding::LF,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let photo = create_test_file(
+        temp_dir.path(),
+        "photo.png",
+        &[&b"\x89PNG\r\n\x1a\n"[..], TEST_DATA_SMALL].concat(),
+    );
+
+    jcz_command()
+        .arg("-c")
+        .arg("auto")
+        .arg("--encrypt-key")
+        .arg(&public_key)
+        .arg(&photo)
+        .assert()
+        .success();
+
+    assert!(temp_dir.path().join("photo.png.tar.jcze").exists());
+}
+
+#[test]
+fn test_threads_limits_batch_and_round_trips() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[
+            ("one.txt", TEST_DATA_SMALL),
+            ("two.txt", TEST_DATA_MEDIUM),
+            ("three.txt", TEST_DATA_MEDIUM),
+        ],
+    );
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg("-j")
+        .arg("2")
+        .args(&files)
+        .assert()
+        .success();
+
+    let archives: Vec<_> = files
+        .iter()
+        .map(|f| {
+            temp_dir
+                .path()
+                .join(format!("{}.xz", f.file_name().unwrap().to_string_lossy()))
+        })
+        .collect();
+    for file in &files {
+        fs::remove_file(file).unwrap();
+    }
+
+    jcz_command()
+        .arg("-d")
+        .arg("--threads")
+        .arg("1")
+        .args(&archives)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(&files[0]).unwrap(), TEST_DATA_SMALL);
+    assert_eq!(fs::read(&files[2]).unwrap(), TEST_DATA_MEDIUM);
+}
+
+#[test]
+fn test_threads_zero_is_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-j")
+        .arg("0")
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--threads must be at least 1"));
+}
+
+#[test]
+fn test_limit_rate_paces_and_round_trips() {
+    let temp_dir = TempDir::new().unwrap();
+    let data: Vec<u8> = TEST_DATA_MEDIUM
+        .iter()
+        .cycle()
+        .take(96 * 1024)
+        .copied()
+        .collect();
+    let file = create_test_file(temp_dir.path(), "data.txt", &data);
+
+    // 96 KiB read at 64 KiB/s takes well over a second
+    let start = std::time::Instant::now();
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--limit-rate")
+        .arg("64K")
+        .arg(&file)
+        .assert()
+        .success();
+    assert!(start.elapsed() >= std::time::Duration::from_millis(1200));
+
+    fs::remove_file(&file).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("--limit-rate")
+        .arg("1M")
+        .arg(temp_dir.path().join("data.txt.gz"))
+        .assert()
+        .success();
+    assert_eq!(fs::read(&file).unwrap(), data);
+}
+
+#[test]
+fn test_limit_rate_compound_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs", "README"]);
+    fs::write(project.join("notes.txt"), TEST_DATA_SMALL).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("txz")
+        .arg("--limit-rate")
+        .arg("4M")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let extract = temp_dir.path().join("out");
+    fs::create_dir(&extract).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("--limit-rate")
+        .arg("4M")
+        .arg("-C")
+        .arg(&extract)
+        .arg(temp_dir.path().join("project.tar.xz"))
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read(extract.join("project/notes.txt")).unwrap(),
+        TEST_DATA_SMALL
+    );
+}
+
+#[test]
+fn test_nice_and_ionice() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("bzip2")
+        .arg("--nice")
+        .arg("--ionice")
+        .arg(&file)
+        .assert()
+        .success();
+    assert!(temp_dir.path().join("data.txt.bz2").exists());
+
+    jcz_command()
+        .arg("--limit-rate")
+        .arg("lots")
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid rate"));
+}
+
+#[test]
+fn test_tempdir_holds_scratch_space() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs", "README"]);
+    fs::write(project.join("notes.txt"), TEST_DATA_SMALL).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg(&project)
+        .assert()
+        .success();
+    fs::remove_dir_all(&project).unwrap();
+
+    let scratch = temp_dir.path().join("scratch");
+    fs::create_dir(&scratch).unwrap();
+    jcz_command()
+        .env("JCDBG", "debug")
+        .arg("-d")
+        .arg("--tempdir")
+        .arg(&scratch)
+        .arg(temp_dir.path().join("project.tar.gz"))
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(format!(
+            "Created temp directory: {}/.jcz-tmp-",
+            scratch.display()
+        )));
+
+    assert_eq!(
+        fs::read(project.join("notes.txt")).unwrap(),
+        TEST_DATA_SMALL
+    );
+    assert_eq!(fs::read_dir(&scratch).unwrap().count(), 0);
+
+    jcz_command()
+        .arg("-d")
+        .arg("--tempdir")
+        .arg(temp_dir.path().join("missing"))
+        .arg(temp_dir.path().join("project.tar.gz"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_scratch_space_defaults_to_destination() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg(&file)
+        .assert()
+        .success();
+    fs::remove_file(&file).unwrap();
+
+    let dest = temp_dir.path().join("dest");
+    jcz_command()
+        .env("JCDBG", "debug")
+        .env_remove("TMPDIR")
+        .arg("-d")
+        .arg("-C")
+        .arg(&dest)
+        .arg(temp_dir.path().join("data.txt.xz"))
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(format!(
+            "Created temp directory: {}/.jcz-tmp-",
+            dest.display()
+        )));
+
+    // Renamed into place; the hidden scratch dir is gone
+    assert_eq!(fs::read(dest.join("data.txt")).unwrap(), TEST_DATA_MEDIUM);
+    assert_eq!(fs::read_dir(&dest).unwrap().count(), 1);
+
+    // TMPDIR wins over the destination
+    let tmpdir = temp_dir.path().join("tmp");
+    fs::create_dir(&tmpdir).unwrap();
+    jcz_command()
+        .env("JCDBG", "debug")
+        .env("TMPDIR", &tmpdir)
+        .arg("-d")
+        .arg("-f")
+        .arg("-C")
+        .arg(&dest)
+        .arg(temp_dir.path().join("data.txt.xz"))
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(format!(
+            "Created temp directory: {}/",
+            tmpdir.display()
+        )));
+}
+
+#[test]
+fn test_fail_fast_cancels_remaining_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let bad = create_test_file(temp_dir.path(), "bad.gz", b"not a gzip file");
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("one.txt", TEST_DATA_SMALL), ("two.txt", TEST_DATA_MEDIUM)],
+    );
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .args(&files)
+        .assert()
+        .success();
+    for file in &files {
+        fs::remove_file(file).unwrap();
+    }
+    let archives: Vec<_> = files.iter().map(|f| f.with_extension("txt.gz")).collect();
+
+    // One thread runs the files in order, so the bad one stops the rest
+    jcz_command()
+        .arg("-d")
+        .arg("--fail-fast")
+        .arg("-j")
+        .arg("1")
+        .arg(&bad)
+        .args(&archives)
+        .assert()
+        .code(3)
+        .stderr(predicates::str::contains("(1 of 3, 2 cancelled)"));
+    assert!(files.iter().all(|f| !file_exists(f)));
+
+    // The default keeps going
+    jcz_command()
+        .arg("-d")
+        .arg("-j")
+        .arg("1")
+        .arg(&bad)
+        .args(&archives)
+        .assert()
+        .code(2);
+    assert_eq!(read_file(&files[1]), TEST_DATA_MEDIUM);
+}
+
+// Report Option Tests
+
+#[test]
+fn test_report_records_every_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+    let bad = create_test_file(temp_dir.path(), "bad.gz", b"not a gzip file");
+    let report_path = temp_dir.path().join("report.json");
+    let read_report = || -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap()
+    };
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--report")
+        .arg(&report_path)
+        .arg(&test_file)
+        .assert()
+        .success();
+    let report = read_report();
+    assert_eq!(report["operation"], "compress");
+    assert_eq!(report["succeeded"], 1);
+    let entry = &report["files"][0];
+    assert_eq!(entry["status"], "ok");
+    assert_eq!(
+        entry["output"],
+        temp_dir.path().join("test.txt.gz").to_str().unwrap()
+    );
+    assert_eq!(entry["original_size"], TEST_DATA_SMALL.len());
+    assert_eq!(entry["sha256"].as_str().unwrap().len(), 64);
+
+    fs::remove_file(&test_file).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("--report")
+        .arg(&report_path)
+        .arg(temp_dir.path().join("test.txt.gz"))
+        .arg(&bad)
+        .assert()
+        .code(2);
+    let report = read_report();
+    assert_eq!(report["operation"], "decompress");
+    assert_eq!(
+        (report["succeeded"].clone(), report["failed"].clone()),
+        (1.into(), 1.into())
+    );
+    assert_eq!(report["files"][0]["output"], test_file.to_str().unwrap());
+    assert_eq!(report["files"][1]["status"], "failed");
+    assert!(!report["files"][1]["error"].as_str().unwrap().is_empty());
+    assert!(report["files"][1]["output"].is_null());
+}
+
+#[test]
+fn test_report_and_stats_keep_input_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let text = "GET /index.html 200\n".repeat(500);
+    let first = create_test_file(temp_dir.path(), "first.log", text.as_bytes());
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs", "README"]);
+    let last = create_test_file(temp_dir.path(), "last.log", text.as_bytes());
+    let skipped = create_test_file(temp_dir.path(), "skipped.log", text.as_bytes());
+    let report_path = temp_dir.path().join("report.json");
+
+    // -c auto compresses the two logs as one batch and the directory as
+    // another; --pre-cmd fails skipped.log before anything starts
+    let output = jcz_command()
+        .args(["-c", "auto", "--stats", "--report"])
+        .arg(&report_path)
+        .arg("--pre-cmd")
+        .arg("test \"${JCZ_INPUT##*/}\" != skipped.log")
+        .arg(&first)
+        .arg(&project)
+        .arg(&last)
+        .arg(&skipped)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    let entries: Vec<(&str, &str)> = report["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["input"].as_str().unwrap(),
+                e["output"].as_str().unwrap_or(""),
+            )
+        })
+        .collect();
+    let path = |p: &std::path::Path| p.to_str().unwrap().to_string();
+    assert_eq!(
+        entries,
+        vec![
+            (
+                path(&first).as_str(),
+                path(&temp_dir.path().join("first.log.xz")).as_str()
+            ),
+            (
+                path(&project).as_str(),
+                path(&temp_dir.path().join("project.tar.xz")).as_str()
+            ),
+            (
+                path(&last).as_str(),
+                path(&temp_dir.path().join("last.log.xz")).as_str()
+            ),
+            (path(&skipped).as_str(), ""),
+        ]
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].contains("first.log -> "), "Output: {}", stdout);
+    assert!(lines[1].contains("project -> "), "Output: {}", stdout);
+    assert!(lines[2].contains("last.log -> "), "Output: {}", stdout);
+}
+
+/// Directory with a `pigz` that logs its arguments to `log` and runs gzip
+#[cfg(unix)]
+fn fake_pigz(dir: &std::path::Path, log: &std::path::Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let script = format!(
+        "#!/bin/sh\necho \"$@\" >> '{}'\nexec gzip \"$@\"\n",
+        log.display()
+    );
+    let pigz = create_test_file(&bin, "pigz", script.as_bytes());
+    fs::set_permissions(&pigz, fs::Permissions::from_mode(0o755)).unwrap();
+    bin
+}
+
+#[test]
+#[cfg(unix)]
+fn test_parallel_tool_preferred_when_installed() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("pigz.log");
+    let bin = fake_pigz(temp_dir.path(), &log);
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .env("PATH", &path)
+        .args(["-c", "gzip"])
+        .arg(&input)
+        .assert()
+        .success();
+    assert!(verify_decompressed_content(
+        &temp_dir.path().join("test.txt.gz"),
+        TEST_DATA_MEDIUM
+    ));
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 1);
+
+    // Opting out runs gzip itself
+    jcz_command()
+        .env("PATH", &path)
+        .args(["-c", "gzip", "-f", "--no-parallel-tools", "-t", "3"])
+        .arg(&input)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 1);
+}
+
+// Logging Option Tests
+
+#[test]
+fn test_quiet_and_log_level_override_jcdbg() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("JCDBG", "debug")
+        .arg("-q")
+        .arg("-c")
+        .arg("gzip")
+        .arg("-f")
+        .arg(&input)
+        .assert()
+        .success()
+        .stderr(predicates::str::is_empty());
+
+    jcz_command()
+        .env("JCDBG", "error")
+        .arg("--log-level")
+        .arg("debug")
+        .arg("-c")
+        .arg("gzip")
+        .arg("-f")
+        .arg(&input)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("DEBUG"));
+
+    jcz_command()
+        .arg("--log-level")
+        .arg("loud")
+        .arg(&input)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_log_file_receives_messages() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+    let log = temp_dir.path().join("jcz.log");
+
+    for _ in 0..2 {
+        jcz_command()
+            .arg("--log-file")
+            .arg(&log)
+            .arg("-c")
+            .arg("gzip")
+            .arg("-f")
+            .arg(&input)
+            .assert()
+            .success()
+            .stderr(predicates::str::is_empty());
+    }
+    // Appended, not truncated
+    let text = fs::read_to_string(&log).unwrap();
+    assert_eq!(text.matches("Compressed file").count(), 2, "Log: {}", text);
+}
+
+#[test]
+fn test_no_color_keeps_plain_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("--no-color")
+        .arg("-c")
+        .arg("gzip")
+        .arg("--stats")
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Total: 1 files"))
+        .stdout(predicates::str::contains("\x1b[").not());
 }
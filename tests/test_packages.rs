@@ -0,0 +1,189 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+fn run(dir: &Path, program: &str, args: &[&str]) {
+    let status = Command::new(program)
+        .current_dir(dir)
+        .args(args)
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {}: {}", program, e));
+    assert!(status.success(), "{} {:?} failed", program, args);
+}
+
+/// Assemble a .deb the way dpkg-deb lays it out: ar of debian-binary,
+/// control.tar.gz and data.tar.xz
+fn build_deb(dir: &Path, name: &str) -> PathBuf {
+    let root = dir.join("root");
+    create_test_dir_structure(&root, &["usr/bin/hello", "etc/hello.conf"]);
+    let control = dir.join("control");
+    fs::create_dir(&control).unwrap();
+    create_test_file(&control, "control", b"Package: hello\nVersion: 1.0\n");
+
+    create_test_file(dir, "debian-binary", b"2.0\n");
+    run(dir, "tar", &["czf", "control.tar.gz", "-C", "control", "."]);
+    run(dir, "tar", &["cJf", "data.tar.xz", "-C", "root", "."]);
+    run(
+        dir,
+        "ar",
+        &["rc", name, "debian-binary", "control.tar.gz", "data.tar.xz"],
+    );
+
+    dir.join(name)
+}
+
+/// Minimal header structure with no index entries
+fn rpm_header(data_len: u32) -> Vec<u8> {
+    let mut bytes = vec![0x8E, 0xAD, 0xE8, 0x01, 0, 0, 0, 0];
+    bytes.extend_from_slice(&0u32.to_be_bytes());
+    bytes.extend_from_slice(&data_len.to_be_bytes());
+    bytes.extend(vec![0u8; data_len as usize]);
+    bytes
+}
+
+/// Assemble an .rpm around a gzip-compressed cpio payload produced by jcz
+fn build_rpm(dir: &Path, name: &str) -> PathBuf {
+    let payload_dir = dir.join("usr");
+    create_test_dir_structure(&payload_dir, &["bin/hello", "share/doc/README"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("cpio")
+        .arg(&payload_dir)
+        .assert()
+        .success();
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(dir.join("usr.cpio"))
+        .assert()
+        .success();
+
+    let mut package = vec![0u8; 96];
+    package[..4].copy_from_slice(&[0xED, 0xAB, 0xEE, 0xDB]);
+    // Signature header is 16 + 5 bytes, padded to 24
+    package.extend(rpm_header(5));
+    package.extend([0u8; 3]);
+    package.extend(rpm_header(9));
+    package.extend(fs::read(dir.join("usr.cpio.gz")).unwrap());
+
+    let path = dir.join(name);
+    fs::write(&path, package).unwrap();
+    path
+}
+
+#[test]
+fn test_deb_extracts_data_and_control() {
+    let temp_dir = TempDir::new().unwrap();
+    let deb = build_deb(temp_dir.path(), "hello_1.0_all.deb");
+
+    jcz_command().arg("-d").arg(&deb).assert().success();
+
+    let out = temp_dir.path().join("hello_1.0_all");
+    assert!(dir_exists(&out), "Package directory should exist");
+    assert!(file_exists(&out.join("usr/bin/hello")));
+    assert!(file_exists(&out.join("etc/hello.conf")));
+    assert_eq!(
+        read_file(&out.join("DEBIAN/control")),
+        b"Package: hello\nVersion: 1.0\n"
+    );
+    assert!(file_exists(&deb), "Package should be preserved");
+}
+
+#[test]
+fn test_deb_extracts_without_tools() {
+    let temp_dir = TempDir::new().unwrap();
+    let deb = build_deb(temp_dir.path(), "hello_1.0_all.deb");
+    let empty_path = temp_dir.path().join("no-tools");
+    fs::create_dir(&empty_path).unwrap();
+
+    // ar, tar, gzip and xz all fall back to the built-in implementations
+    jcz_command()
+        .env("PATH", &empty_path)
+        .arg("-d")
+        .arg(&deb)
+        .assert()
+        .success();
+
+    let out = temp_dir.path().join("hello_1.0_all");
+    assert!(file_exists(&out.join("usr/bin/hello")));
+    assert_eq!(
+        read_file(&out.join("DEBIAN/control")),
+        b"Package: hello\nVersion: 1.0\n"
+    );
+}
+
+#[test]
+fn test_deb_extract_to_move_to() {
+    let temp_dir = TempDir::new().unwrap();
+    let deb = build_deb(temp_dir.path(), "hello_1.0_all.deb");
+    let out_dir = temp_dir.path().join("out");
+    fs::create_dir(&out_dir).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("-C")
+        .arg(&out_dir)
+        .arg(&deb)
+        .assert()
+        .success();
+
+    assert!(file_exists(&out_dir.join("hello_1.0_all/usr/bin/hello")));
+}
+
+#[test]
+fn test_rpm_extracts_payload() {
+    let temp_dir = TempDir::new().unwrap();
+    let rpm = build_rpm(temp_dir.path(), "hello-1.0-1.noarch.rpm");
+
+    jcz_command().arg("-d").arg(&rpm).assert().success();
+
+    let out = temp_dir.path().join("hello-1.0-1.noarch");
+    assert!(dir_exists(&out), "Package directory should exist");
+    assert!(file_exists(&out.join("usr/bin/hello")));
+    assert!(file_exists(&out.join("usr/share/doc/README")));
+}
+
+#[test]
+fn test_rpm_rejects_corrupt_package() {
+    let temp_dir = TempDir::new().unwrap();
+    let rpm = create_test_file(temp_dir.path(), "broken.rpm", TEST_DATA_SMALL);
+
+    jcz_command().arg("-d").arg(&rpm).assert().failure();
+}
+
+#[test]
+fn test_packages_cannot_be_created() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_SMALL);
+
+    for command in ["deb", "rpm"] {
+        jcz_command()
+            .arg("-c")
+            .arg(command)
+            .arg(&test_file)
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn test_identify_packages() {
+    let temp_dir = TempDir::new().unwrap();
+    let deb = build_deb(temp_dir.path(), "hello_1.0_all.deb");
+    let rpm = build_rpm(temp_dir.path(), "hello-1.0-1.noarch.rpm");
+
+    jcz_command()
+        .arg("identify")
+        .arg(&deb)
+        .arg(&rpm)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Format chain: deb"))
+        .stdout(predicate::str::contains("Format chain: rpm"));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Test that a large zip is extracted by several unzip runs at once, with
+/// every member intact
+#[test]
+fn test_zip_members_in_parallel() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = temp_dir.path().join("data");
+    let mut files = Vec::new();
+    for i in 0..8 {
+        let name = format!("sub{}/file{}.bin", i % 3, i);
+        let content: Vec<u8> = TEST_DATA_MEDIUM
+            .iter()
+            .cycle()
+            .take(256 * 1024)
+            .map(|b| b.wrapping_add(i as u8))
+            .collect();
+        fs::create_dir_all(data.join(&name).parent().unwrap()).unwrap();
+        fs::write(data.join(&name), &content).unwrap();
+        files.push((name, content));
+    }
+    create_test_file(&data, "small.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["-c", "zip"])
+        .arg(&data)
+        .assert()
+        .success();
+    fs::remove_dir_all(&data).unwrap();
+
+    jcz_command()
+        .env("JCDBG", "debug")
+        .args(["-d", "-j", "4"])
+        .arg(temp_dir.path().join("data.zip"))
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("parts on 4 threads"));
+    for (name, content) in files {
+        assert_eq!(read_file(&data.join(name)), content);
+    }
+    assert_eq!(read_file(&data.join("small.txt")), TEST_DATA_SMALL);
+}
+
+/// Test that a bzip2 file of several streams is decoded stream by stream
+/// when more than one thread may be used
+#[test]
+fn test_bzip2_streams_in_parallel() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut joined = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..4 {
+        let content = format!("stream {}\n", i).repeat(5000).into_bytes();
+        let input = create_test_file(temp_dir.path(), "joined", &content);
+        jcz_command()
+            .args(["-c", "bzip2"])
+            .arg(&input)
+            .assert()
+            .success();
+        let compressed = temp_dir.path().join("joined.bz2");
+        joined.extend(read_file(&compressed));
+        fs::remove_file(&compressed).unwrap();
+        fs::remove_file(&input).unwrap();
+        expected.extend(content);
+    }
+    let compressed = temp_dir.path().join("joined.bz2");
+    fs::write(&compressed, joined).unwrap();
+
+    // Plain bzip2 rather than pbzip2 or lbzip2, should they be installed
+    jcz_command()
+        .env("JCDBG", "debug")
+        .args(["-d", "-j", "2", "--no-parallel-tools"])
+        .arg(&compressed)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Decoding bzip2 streams on 2 threads",
+        ));
+    assert_eq!(read_file(&temp_dir.path().join("joined")), expected);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Compress `data` with --recovery, returning the archive path
+fn compress_with_recovery(dir: &std::path::Path, data: &[u8], percent: &str) -> std::path::PathBuf {
+    let input = dir.join("notes.txt");
+    fs::write(&input, data).unwrap();
+    jcz_command()
+        .args(["-c", "gzip", "--recovery", percent])
+        .arg(&input)
+        .assert()
+        .success();
+    let archive = dir.join("notes.txt.gz");
+    assert!(file_exists(&dir.join("notes.txt.gz.jczr")));
+    archive
+}
+
+#[test]
+fn test_repair_rebuilds_damaged_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = TEST_DATA_MEDIUM.repeat(4000);
+    let archive = compress_with_recovery(temp_dir.path(), &data, "10%");
+    let original = fs::read(&archive).unwrap();
+
+    jcz_command()
+        .arg("repair")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("intact"));
+
+    let mut damaged = original.clone();
+    damaged[20..400].fill(0);
+    fs::write(&archive, &damaged).unwrap();
+    jcz_command()
+        .arg("repair")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("damaged blocks rebuilt"));
+    assert_eq!(fs::read(&archive).unwrap(), original);
+
+    fs::remove_file(temp_dir.path().join("notes.txt")).unwrap();
+    jcz_command().arg("-d").arg(&archive).assert().success();
+    assert_eq!(read_file(&temp_dir.path().join("notes.txt")), data);
+}
+
+#[test]
+fn test_repair_without_recovery_data_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "hello.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "gzip"])
+        .arg(&input)
+        .assert()
+        .success();
+
+    jcz_command()
+        .arg("repair")
+        .arg(temp_dir.path().join("hello.txt.gz"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("hello.txt.gz.jczr"));
+}
+
+#[test]
+fn test_recovery_rejects_bad_percentage() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "hello.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "gzip", "--recovery", "0%"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --recovery"));
+}
@@ -0,0 +1,80 @@
+// The flaky transfer tool is a shell script
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Install an `aws` in `bin` that fails its first `failures` calls, then
+/// stores stdin in `bucket`
+fn flaky_aws(bin: &Path, bucket: &Path, failures: u32) {
+    fs::create_dir_all(bin).unwrap();
+    let calls = bin.join("calls");
+    let script = format!(
+        "#!/bin/sh\n\
+         n=$(cat '{calls}' 2>/dev/null || echo 0); echo $((n + 1)) > '{calls}'\n\
+         if [ \"$n\" -lt {failures} ]; then echo 'Connection reset by peer' >&2; exit 1; fi\n\
+         key=\"${{4#s3://backups/}}\"; cat > '{bucket}'/\"$key\"\n",
+        calls = calls.display(),
+        failures = failures,
+        bucket = bucket.display()
+    );
+    let path = bin.join("aws");
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn test_retries_flaky_upload() {
+    let temp_dir = TempDir::new().unwrap();
+    let bin = temp_dir.path().join("bin");
+    let bucket = temp_dir.path().join("bucket");
+    fs::create_dir(&bucket).unwrap();
+    flaky_aws(&bin, &bucket, 2);
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .env("PATH", path_with(&bin))
+        .args(["-c", "gzip", "-C", "s3://backups/"])
+        .args(["--retries", "3", "--retry-delay", "0"])
+        .arg(&input)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("retrying"));
+    assert!(file_exists(&bucket.join("data.txt.gz")));
+    assert_eq!(fs::read_to_string(bin.join("calls")).unwrap().trim(), "3");
+}
+
+#[test]
+fn test_gives_up_after_retries() {
+    let temp_dir = TempDir::new().unwrap();
+    let bin = temp_dir.path().join("bin");
+    let bucket = temp_dir.path().join("bucket");
+    fs::create_dir(&bucket).unwrap();
+    flaky_aws(&bin, &bucket, 5);
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("PATH", path_with(&bin))
+        .args(["-c", "gzip", "-C", "s3://backups/"])
+        .args(["--retries", "1", "--retry-delay", "0"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Connection reset"));
+    assert_eq!(fs::read_to_string(bin.join("calls")).unwrap().trim(), "2");
+}
+
+#[test]
+fn test_rejects_bad_retry_delay() {
+    jcz_command()
+        .args(["--retry-delay", "5000", "file.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --retry-delay"));
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Rotated logs next to the live `app.log`
+fn create_logs(dir: &Path) {
+    fs::create_dir_all(dir).unwrap();
+    create_test_files(
+        dir,
+        &[
+            ("app.log", TEST_DATA_SMALL),
+            ("app.log.1", TEST_DATA_MEDIUM),
+            ("app.log.2", b"older entries\n"),
+        ],
+    );
+}
+
+/// Unpack `archive` into `dir`/restore and return that directory
+fn restore(dir: &Path, archive: &Path) -> std::path::PathBuf {
+    let restore = dir.join("restore");
+    jcz_command()
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(archive)
+        .assert()
+        .success();
+    restore
+}
+
+#[test]
+fn test_rotate_archives_and_removes_logs() {
+    let temp_dir = TempDir::new().unwrap();
+    let logs = temp_dir.path().join("logs");
+    create_logs(&logs);
+    let into = temp_dir.path().join("archive/logs-%Y%m.tar.gz");
+
+    jcz_command()
+        .args(["rotate", "--pattern", "app.log.*", "--into"])
+        .arg(&into)
+        .arg(&logs)
+        .assert()
+        .success();
+
+    let month = chrono::Local::now().format("%Y%m").to_string();
+    let archive = temp_dir
+        .path()
+        .join(format!("archive/logs-{}.tar.gz", month));
+    assert!(file_exists(&archive));
+    assert!(file_exists(&logs.join("app.log")));
+    assert!(!file_exists(&logs.join("app.log.1")));
+    assert!(!file_exists(&logs.join("app.log.2")));
+
+    // A later rotation in the same month joins the same archive
+    create_test_file(&logs, "app.log.1", b"newer entries\n");
+    jcz_command()
+        .args(["rotate", "--pattern", "app.log.?", "--into"])
+        .arg(&into)
+        .arg(&logs)
+        .assert()
+        .failure();
+    assert!(file_exists(&logs.join("app.log.1")));
+
+    fs::rename(logs.join("app.log.1"), logs.join("app.log.3")).unwrap();
+    jcz_command()
+        .args(["rotate", "--pattern", "app.log.?", "--into"])
+        .arg(&into)
+        .arg(&logs)
+        .assert()
+        .success();
+    assert!(!file_exists(&logs.join("app.log.3")));
+
+    let restore = restore(temp_dir.path(), &archive);
+    assert_eq!(read_file(&restore.join("app.log.1")), TEST_DATA_MEDIUM);
+    assert_eq!(read_file(&restore.join("app.log.2")), b"older entries\n");
+    assert_eq!(read_file(&restore.join("app.log.3")), b"newer entries\n");
+    assert!(!file_exists(&restore.join("app.log")));
+}
+
+#[test]
+fn test_rotate_dry_run_and_keep() {
+    let temp_dir = TempDir::new().unwrap();
+    create_logs(temp_dir.path());
+    let archive = temp_dir.path().join("logs.zip");
+
+    jcz_command()
+        .args(["rotate", "--dry-run", "--pattern", "app.log.*", "--into"])
+        .arg(&archive)
+        .arg(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("would rotate"));
+    assert!(!file_exists(&archive));
+    assert!(file_exists(&temp_dir.path().join("app.log.1")));
+
+    jcz_command()
+        .args(["rotate", "--keep", "--pattern", "app.log.*", "--into"])
+        .arg(&archive)
+        .arg(temp_dir.path())
+        .assert()
+        .success();
+    assert!(file_exists(&archive));
+    assert!(file_exists(&temp_dir.path().join("app.log.1")));
+    assert!(file_exists(&temp_dir.path().join("app.log.2")));
+
+    // Nothing matching is not an error
+    jcz_command()
+        .args(["rotate", "--pattern", "*.gz", "--into"])
+        .arg(&archive)
+        .arg(temp_dir.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_rotate_into_registered_compressor() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path().join("config");
+    fs::create_dir_all(config_dir.join("jcz")).unwrap();
+    create_test_file(
+        &config_dir.join("jcz"),
+        "compressors.toml",
+        b"[compressor.gzf]\ncompress = \"gzip -c\"\ndecompress = \"gzip -dc\"\n",
+    );
+    let logs = temp_dir.path().join("logs");
+    create_logs(&logs);
+    let archive = temp_dir.path().join("logs.tar.gzf");
+
+    jcz_command()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .args(["rotate", "--pattern", "app.log.*", "--into"])
+        .arg(&archive)
+        .arg(&logs)
+        .assert()
+        .success();
+    assert!(read_file(&archive).starts_with(&[0x1F, 0x8B]));
+    assert!(!file_exists(&logs.join("app.log.1")));
+
+    let restore = temp_dir.path().join("restore");
+    jcz_command()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .args(["-d", "-C"])
+        .arg(&restore)
+        .arg(&archive)
+        .assert()
+        .success();
+    assert_eq!(read_file(&restore.join("app.log.1")), TEST_DATA_MEDIUM);
+
+    // Unregistered extensions are refused before anything is touched
+    jcz_command()
+        .env("XDG_CONFIG_HOME", temp_dir.path())
+        .args(["rotate", "--pattern", "app.log", "--into"])
+        .arg(temp_dir.path().join("logs.tar.zst"))
+        .arg(&logs)
+        .assert()
+        .failure();
+    assert!(file_exists(&logs.join("app.log")));
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// Byte offset of the header of member `name` in the tar at `path`
+fn header_offset(path: &std::path::Path, name: &str) -> usize {
+    let data = fs::read(path).unwrap();
+    data.windows(name.len())
+        .position(|w| w == name.as_bytes())
+        .unwrap()
+}
+
+#[test]
+fn test_salvage_skips_damaged_tar_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let data = temp_dir.path().join("data");
+    fs::create_dir(&data).unwrap();
+    create_test_file(&data, "a.txt", TEST_DATA_SMALL);
+    create_test_file(&data, "b.txt", TEST_DATA_MEDIUM);
+    create_test_file(&data, "c.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["-c", "tar"])
+        .arg(&data)
+        .assert()
+        .success();
+    fs::remove_dir_all(&data).unwrap();
+
+    // Break the checksum of b.txt's header
+    let archive = temp_dir.path().join("data.tar");
+    let mut bytes = fs::read(&archive).unwrap();
+    let offset = header_offset(&archive, "data/b.txt");
+    bytes[offset + 148..offset + 156].copy_from_slice(b"0000000\0");
+    fs::write(&archive, &bytes).unwrap();
+
+    jcz_command().arg("-d").arg(&archive).assert().failure();
+    let _ = fs::remove_dir_all(&data);
+
+    jcz_command()
+        .args(["-d", "--salvage"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Salvaged data.tar"));
+    assert_eq!(read_file(&data.join("a.txt")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&data.join("c.txt")), TEST_DATA_SMALL);
+    assert!(!file_exists(&data.join("b.txt")));
+}
+
+#[test]
+fn test_salvage_resumes_at_next_gzip_member() {
+    let temp_dir = TempDir::new().unwrap();
+    let first = create_test_file(temp_dir.path(), "first.txt", &TEST_DATA_MEDIUM.repeat(50));
+    let second = create_test_file(temp_dir.path(), "second.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "gzip"])
+        .arg(&first)
+        .arg(&second)
+        .assert()
+        .success();
+
+    // Two members back to back, the first one damaged
+    let mut first_gz = fs::read(temp_dir.path().join("first.txt.gz")).unwrap();
+    let middle = first_gz.len() / 2;
+    first_gz[middle..middle + 8].fill(0xFF);
+    first_gz.extend(fs::read(temp_dir.path().join("second.txt.gz")).unwrap());
+    let joined = temp_dir.path().join("joined.gz");
+    fs::write(&joined, &first_gz).unwrap();
+
+    jcz_command()
+        .args(["-d", "--salvage", "--no-name"])
+        .arg(&joined)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("resuming at the next member"));
+    assert_eq!(read_file(&temp_dir.path().join("joined")), TEST_DATA_SMALL);
+}
+
+#[test]
+fn test_salvage_requires_decompression() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "hello.txt", TEST_DATA_SMALL);
+    jcz_command()
+        .args(["-c", "gzip", "--salvage"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--salvage"));
+}
@@ -215,3 +215,606 @@ fn test_tar_with_compression_level_ignored() {
     let archive_file = temp_dir.path().join("test.txt.tar");
     assert!(file_exists(&archive_file));
 }
+
+#[test]
+fn test_tar_entries_are_sorted() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("project");
+    create_test_dir_structure(&test_dir, &["zeta.txt", "alpha.txt", "mid/beta.txt"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    let output = Command::new("tar")
+        .arg("-tf")
+        .arg(temp_dir.path().join("project.tar"))
+        .output()
+        .expect("Failed to list tar contents");
+    assert!(output.status.success());
+
+    let names: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.trim_end_matches('/').to_string())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "project",
+            "project/alpha.txt",
+            "project/mid",
+            "project/mid/beta.txt",
+            "project/zeta.txt"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_preserves_symlinks_and_permissions() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("tool");
+    create_test_dir_structure(&test_dir, &["run.sh"]);
+    std::fs::set_permissions(
+        test_dir.join("run.sh"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    symlink("run.sh", test_dir.join("start")).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    // Symlinks are stored as links, not dereferenced
+    let output = Command::new("tar")
+        .arg("-tvf")
+        .arg(temp_dir.path().join("tool.tar"))
+        .output()
+        .expect("Failed to list tar contents");
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        listing.contains("tool/start -> run.sh"),
+        "Listing: {}",
+        listing
+    );
+
+    std::fs::remove_dir_all(&test_dir).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("tool.tar"))
+        .assert()
+        .success();
+
+    let mode = std::fs::metadata(test_dir.join("run.sh"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+}
+
+/// Set an xattr, or report that the filesystem under the temp dir can't hold it
+#[cfg(unix)]
+fn try_set_xattr(path: &std::path::Path, name: &str, value: &[u8]) -> bool {
+    match xattr::set(path, name, value) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Skipping: cannot set {} ({})", name, e);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_xattrs_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("bin");
+    create_test_dir_structure(&test_dir, &["tool"]);
+    if !try_set_xattr(&test_dir.join("tool"), "user.origin", b"jcz-test") {
+        return;
+    }
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--xattrs")
+        .arg(&test_dir)
+        .assert()
+        .success();
+    std::fs::remove_dir_all(&test_dir).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--xattrs")
+        .arg(temp_dir.path().join("bin.tar.gz"))
+        .assert()
+        .success();
+
+    assert_eq!(
+        xattr::get(test_dir.join("tool"), "user.origin").unwrap(),
+        Some(b"jcz-test".to_vec())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_xattrs_not_stored_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+    if !try_set_xattr(&test_file, "user.origin", b"jcz-test") {
+        return;
+    }
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg(&test_file)
+        .assert()
+        .success();
+    std::fs::remove_file(&test_file).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--xattrs")
+        .arg(temp_dir.path().join("notes.txt.tar"))
+        .assert()
+        .success();
+
+    assert_eq!(xattr::get(&test_file, "user.origin").unwrap(), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_acls_round_trip() {
+    // user::rw-, user:1000:r--, group::r--, mask::r--, other::---
+    let mut acl = 2u32.to_le_bytes().to_vec();
+    for (tag, perm, id) in [
+        (0x01u16, 6u16, u32::MAX),
+        (0x02, 4, 1000),
+        (0x04, 4, u32::MAX),
+        (0x10, 4, u32::MAX),
+        (0x20, 0, u32::MAX),
+    ] {
+        acl.extend_from_slice(&tag.to_le_bytes());
+        acl.extend_from_slice(&perm.to_le_bytes());
+        acl.extend_from_slice(&id.to_le_bytes());
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "shared.txt", TEST_DATA_SMALL);
+    if !try_set_xattr(&test_file, "system.posix_acl_access", &acl) {
+        return;
+    }
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg("--acls")
+        .arg(&test_file)
+        .assert()
+        .success();
+    std::fs::remove_file(&test_file).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg("--acls")
+        .arg(temp_dir.path().join("shared.txt.tar"))
+        .assert()
+        .success();
+
+    assert_eq!(
+        xattr::get(&test_file, "system.posix_acl_access").unwrap(),
+        Some(acl)
+    );
+}
+
+/// List a (possibly compressed) tar archive with GNU tar
+#[cfg(unix)]
+fn tar_listing(archive: &std::path::Path) -> String {
+    let output = Command::new("tar")
+        .arg("-tvaf")
+        .arg(archive)
+        .output()
+        .expect("Failed to list tar contents");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_dereference_stores_link_targets() {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("tool");
+    create_test_dir_structure(&test_dir, &["run.sh"]);
+    symlink("run.sh", test_dir.join("start")).unwrap();
+    // A loop back to the root must not recurse forever
+    symlink(".", test_dir.join("again")).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--dereference")
+        .arg(&test_dir)
+        .assert()
+        .success();
+
+    let listing = tar_listing(&temp_dir.path().join("tool.tar.gz"));
+    assert!(!listing.contains("->"), "Listing: {}", listing);
+    assert!(
+        listing
+            .lines()
+            .any(|l| l.starts_with('-') && l.ends_with("tool/start")),
+        "Listing: {}",
+        listing
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_collection_keeps_symlinks_by_default() {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = TempDir::new().unwrap();
+    let test_dir = temp_dir.path().join("tool");
+    create_test_dir_structure(&test_dir, &["run.sh"]);
+    symlink("run.sh", test_dir.join("start")).unwrap();
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("-a")
+        .arg("pkg")
+        .arg("tool")
+        .assert()
+        .success();
+
+    let listing = tar_listing(&temp_dir.path().join("pkg.tar.gz"));
+    assert!(
+        listing.contains("pkg/tool/start -> run.sh"),
+        "Listing: {}",
+        listing
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_input_archived_as_link() {
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = TempDir::new().unwrap();
+    create_test_dir_structure(&temp_dir.path().join("release-1"), &["app.bin"]);
+    let current = temp_dir.path().join("current");
+    symlink("release-1", &current).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg(&current)
+        .assert()
+        .success();
+    let listing = tar_listing(&temp_dir.path().join("current.tar"));
+    assert!(
+        listing.contains("current -> release-1"),
+        "Listing: {}",
+        listing
+    );
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg("--dereference")
+        .arg("-f")
+        .arg(&current)
+        .assert()
+        .success();
+    let listing = tar_listing(&temp_dir.path().join("current.tar"));
+    assert!(listing.contains("current/app.bin"), "Listing: {}", listing);
+}
+
+#[test]
+fn test_tar_loose_entries_extract_next_to_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_files(
+        temp_dir.path(),
+        &[("a.txt", TEST_DATA_SMALL), ("b.txt", TEST_DATA_MEDIUM)],
+    );
+    assert!(Command::new("tar")
+        .current_dir(temp_dir.path())
+        .args(["-cf", "loose.tar", "a.txt", "b.txt"])
+        .status()
+        .unwrap()
+        .success());
+    std::fs::remove_file(temp_dir.path().join("a.txt")).unwrap();
+    std::fs::remove_file(temp_dir.path().join("b.txt")).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("loose.tar"))
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&temp_dir.path().join("a.txt")), TEST_DATA_SMALL);
+    assert_eq!(read_file(&temp_dir.path().join("b.txt")), TEST_DATA_MEDIUM);
+    // Nothing else: no leftover scratch directory
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+}
+
+#[test]
+fn test_tar_direct_extraction() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs", "README"]);
+    std::fs::write(project.join("notes.txt"), TEST_DATA_SMALL).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let dest = temp_dir.path().join("dest");
+    jcz_command()
+        .arg("-d")
+        .arg("--direct")
+        .arg("-C")
+        .arg(&dest)
+        .arg(temp_dir.path().join("project.tar"))
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("directly to"));
+
+    assert_eq!(read_file(&dest.join("project/notes.txt")), TEST_DATA_SMALL);
+    assert!(file_exists(&dest.join("project/src/main.rs")));
+    assert_eq!(std::fs::read_dir(&dest).unwrap().count(), 1);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_transform_rewrites_stored_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let www = temp_dir.path().join("var/www");
+    create_test_dir_structure(&www, &["index.html"]);
+    let nginx = temp_dir.path().join("etc/nginx");
+    create_test_dir_structure(&nginx, &["nginx.conf"]);
+
+    // A collection from two roots, one rewritten by regex, one by prefix
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("-A")
+        .arg("site")
+        .arg("--transform")
+        .arg("s#^.*/var/www#site#")
+        .arg("--transform")
+        .arg(format!("{}=config", temp_dir.path().join("etc").display()))
+        .arg(&www)
+        .arg(&nginx)
+        .assert()
+        .success();
+    let listing = tar_listing(&temp_dir.path().join("site.tar.gz"));
+    assert!(listing.contains(" site/index.html"), "Listing: {}", listing);
+    assert!(
+        listing.contains(" config/nginx/nginx.conf"),
+        "Listing: {}",
+        listing
+    );
+
+    // A single tar input is stored under its rewritten path too
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg("--transform")
+        .arg("s#^.*/www$#htdocs#")
+        .arg(&www)
+        .assert()
+        .success();
+    let listing = tar_listing(&temp_dir.path().join("var/www.tar"));
+    assert!(
+        listing.contains(" htdocs/index.html"),
+        "Listing: {}",
+        listing
+    );
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--transform")
+        .arg("s#a#b#")
+        .arg(&www)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--transform requires"));
+}
+
+#[test]
+fn test_verbose_lists_members() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs"]);
+
+    let output = jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tar")
+        .arg("-v")
+        .arg("project")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for member in ["project/", "project/src/", "project/src/main.rs"] {
+        assert!(
+            stdout.lines().any(|line| line == member),
+            "Missing {} in: {}",
+            member,
+            stdout
+        );
+    }
+
+    std::fs::remove_dir_all(&project).unwrap();
+    let output = jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-d")
+        .arg("-v")
+        .arg("project.tar")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.lines().any(|line| line == "project/src/main.rs"),
+        "Output: {}",
+        stdout
+    );
+    assert!(file_exists(&project.join("src/main.rs")));
+}
+
+#[test]
+fn test_verbose_lists_members_of_encrypted_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs"]);
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-c", "tgz", "-e", "project"])
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+
+    std::fs::remove_dir_all(&project).unwrap();
+    let output = jcz_command()
+        .current_dir(temp_dir.path())
+        .args(["-d", "-v", "project.tar.gz.jcze"])
+        .write_stdin("correct horse\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.lines().any(|line| line == "project/src/main.rs"),
+        "Output: {}",
+        stdout
+    );
+    assert!(file_exists(&project.join("src/main.rs")));
+}
+
+/// Create `name/` holding a node_modules chain whose paths run past the
+/// 100 bytes of a tar header's name field, with a 150-byte file name;
+/// returns the deepest directory
+fn create_deep_tree(base: &std::path::Path, name: &str) -> std::path::PathBuf {
+    let mut deep = base.join(name);
+    for i in 0..12 {
+        deep = deep
+            .join("node_modules")
+            .join(format!("package-with-a-long-name-{}", i));
+    }
+    std::fs::create_dir_all(&deep).unwrap();
+    create_test_file(&deep, &format!("{}.js", "f".repeat(150)), TEST_DATA_MEDIUM);
+    create_test_file(&deep, "index.js", TEST_DATA_SMALL);
+    deep
+}
+
+#[test]
+fn test_tar_long_paths_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let deep = create_deep_tree(temp_dir.path(), "app");
+    let relative = deep.strip_prefix(temp_dir.path()).unwrap().to_path_buf();
+    assert!(relative.as_os_str().len() > 255);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(deep.join("index.js"), temp_dir.path().join("app/main")).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg(temp_dir.path().join("app"))
+        .assert()
+        .success();
+    std::fs::remove_dir_all(temp_dir.path().join("app")).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("app.tar.gz"))
+        .assert()
+        .success();
+
+    let long_name = format!("{}.js", "f".repeat(150));
+    assert_eq!(read_file(&deep.join(&long_name)), TEST_DATA_MEDIUM);
+    assert_eq!(read_file(&deep.join("index.js")), TEST_DATA_SMALL);
+    #[cfg(unix)]
+    assert_eq!(
+        std::fs::read_link(temp_dir.path().join("app/main")).unwrap(),
+        deep.join("index.js")
+    );
+}
+
+#[test]
+fn test_tar_long_paths_readable_by_tar() {
+    let temp_dir = TempDir::new().unwrap();
+    let deep = create_deep_tree(temp_dir.path(), "app");
+
+    jcz_command()
+        .arg("-c")
+        .arg("tar")
+        .arg(temp_dir.path().join("app"))
+        .assert()
+        .success();
+
+    let output = Command::new("tar")
+        .arg("-tf")
+        .arg(temp_dir.path().join("app.tar"))
+        .output()
+        .expect("Failed to list tar contents");
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let long_name = deep
+        .join(format!("{}.js", "f".repeat(150)))
+        .strip_prefix(temp_dir.path())
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    assert!(
+        listing.lines().any(|line| line == long_name),
+        "Listing: {}",
+        listing
+    );
+}
+
+#[test]
+fn test_tar_extracts_pax_long_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let deep = create_deep_tree(temp_dir.path(), "app");
+
+    let status = Command::new("tar")
+        .args(["--format=pax", "-cf", "app.tar", "app"])
+        .current_dir(temp_dir.path())
+        .status();
+    if !status.map(|s| s.success()).unwrap_or(false) {
+        eprintln!("Skipping: tar cannot write PAX archives");
+        return;
+    }
+    std::fs::remove_dir_all(temp_dir.path().join("app")).unwrap();
+
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("app.tar"))
+        .assert()
+        .success();
+
+    let long_name = format!("{}.js", "f".repeat(150));
+    assert_eq!(read_file(&deep.join(&long_name)), TEST_DATA_MEDIUM);
+    assert_eq!(read_file(&deep.join("index.js")), TEST_DATA_SMALL);
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::*;
+use std::fs::{self, File, FileTimes};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+const RULES: &str = r#"
+[[rule]]
+pattern = "*.log"
+older_than_days = 30
+command = "gzip"
+"#;
+
+/// Backdate a file's access and modification times by `days`
+fn age_file(path: &Path, days: u64) -> SystemTime {
+    let when = SystemTime::now() - Duration::from_secs(days * 86_400);
+    let times = FileTimes::new().set_accessed(when).set_modified(when);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+    when
+}
+
+fn setup(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let data = temp_dir.path().join("data");
+    fs::create_dir_all(data.join("app")).unwrap();
+    let rules = create_test_file(temp_dir.path(), "tiering.toml", RULES.as_bytes());
+    (data, rules)
+}
+
+#[test]
+fn test_tier_compresses_old_matching_files_in_place() {
+    let temp_dir = TempDir::new().unwrap();
+    let (data, rules) = setup(&temp_dir);
+
+    let old_log = create_test_file(&data.join("app"), "old.log", TEST_DATA_MEDIUM);
+    let new_log = create_test_file(&data, "new.log", TEST_DATA_SMALL);
+    let old_txt = create_test_file(&data, "old.txt", TEST_DATA_SMALL);
+    age_file(&old_log, 90);
+    age_file(&old_txt, 90);
+
+    jcz_command()
+        .arg("tier")
+        .arg("--rules")
+        .arg(&rules)
+        .arg(&data)
+        .assert()
+        .success();
+
+    assert!(!old_log.exists(), "Old log should be replaced");
+    assert!(file_exists(&data.join("app/old.log.gz")));
+    assert!(file_exists(&new_log), "Recent file should be untouched");
+    assert!(
+        file_exists(&old_txt),
+        "Non-matching file should be untouched"
+    );
+    assert!(file_exists(&data.join(".jcz-tier-index")));
+}
+
+#[test]
+fn test_restore_file_by_original_path() {
+    let temp_dir = TempDir::new().unwrap();
+    let (data, rules) = setup(&temp_dir);
+
+    let old_log = create_test_file(&data.join("app"), "old.log", TEST_DATA_MEDIUM);
+    let when = age_file(&old_log, 90);
+
+    jcz_command()
+        .arg("tier")
+        .arg("--rules")
+        .arg(&rules)
+        .arg(&data)
+        .assert()
+        .success();
+
+    jcz_command()
+        .arg("restore-file")
+        .arg(&old_log)
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&old_log), TEST_DATA_MEDIUM);
+    assert!(!data.join("app/old.log.gz").exists());
+    assert!(
+        !data.join(".jcz-tier-index").exists(),
+        "Index should be removed once empty"
+    );
+
+    let modified = fs::metadata(&old_log).unwrap().modified().unwrap();
+    let drift = modified
+        .duration_since(when)
+        .unwrap_or_else(|e| e.duration());
+    assert!(drift < Duration::from_secs(1), "mtime should be restored");
+}
+
+#[test]
+fn test_tier_dry_run_changes_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let (data, rules) = setup(&temp_dir);
+
+    let old_log = create_test_file(&data, "old.log", TEST_DATA_SMALL);
+    age_file(&old_log, 90);
+
+    jcz_command()
+        .arg("tier")
+        .arg("--rules")
+        .arg(&rules)
+        .arg("--dry-run")
+        .arg(&data)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("would tier"));
+
+    assert!(file_exists(&old_log));
+    assert!(!data.join("old.log.gz").exists());
+    assert!(!data.join(".jcz-tier-index").exists());
+}
+
+#[test]
+fn test_tier_rejects_invalid_rules() {
+    let temp_dir = TempDir::new().unwrap();
+    let (data, _) = setup(&temp_dir);
+    let rules = create_test_file(
+        temp_dir.path(),
+        "bad.toml",
+        b"[[rule]]\nolder_than_days = 1\ncommand = \"tar\"\n",
+    );
+
+    jcz_command()
+        .arg("tier")
+        .arg("--rules")
+        .arg(&rules)
+        .arg(&data)
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains(
+            "Invalid compression command: tar",
+        ));
+}
+
+#[test]
+fn test_restore_file_not_tiered_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "plain.log", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("restore-file")
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "is not recorded in any tiering index",
+        ));
+}
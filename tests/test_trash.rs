@@ -0,0 +1,92 @@
+// The freedesktop.org trash, found through XDG_DATA_HOME
+#![cfg(all(unix, not(target_os = "macos")))]
+
+mod common;
+
+use common::*;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Encrypt `name` holding `content` with a fresh keyfile, returning the
+/// keyfile and the encrypted file
+fn encrypted_file(dir: &Path, name: &str, content: &[u8]) -> (PathBuf, PathBuf) {
+    let input = create_test_file(dir, name, content);
+    let keyfile = dir.join("secret.bin");
+    if !keyfile.exists() {
+        jcz_command()
+            .arg("gen-keyfile")
+            .arg(&keyfile)
+            .assert()
+            .success();
+    }
+    jcz_command()
+        .args(["-c", "gzip", "--encrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&input)
+        .assert()
+        .success();
+    std::fs::remove_file(&input).unwrap();
+    (keyfile, dir.join(format!("{}.gz.jcze", name)))
+}
+
+/// Test that --remove-encrypted --trash moves the encrypted file to the
+/// trash, where it can be restored from
+#[test]
+fn test_trash_removed_encrypted_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_home = temp_dir.path().join("data");
+    let (keyfile, encrypted) = encrypted_file(temp_dir.path(), "notes.txt", TEST_DATA_MEDIUM);
+    let ciphertext = read_file(&encrypted);
+
+    jcz_command()
+        .env("XDG_DATA_HOME", &data_home)
+        .args(["-d", "--remove-encrypted", "--trash", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert_eq!(
+        read_file(&temp_dir.path().join("notes.txt")),
+        TEST_DATA_MEDIUM
+    );
+    assert!(!encrypted.exists());
+    let trashed = data_home.join("Trash/files/notes.txt.gz.jcze");
+    assert_eq!(read_file(&trashed), ciphertext);
+    assert!(data_home
+        .join("Trash/info/notes.txt.gz.jcze.trashinfo")
+        .exists());
+}
+
+/// Test `jcz decrypt --trash`, and that --trash needs a removal to apply to
+#[test]
+fn test_trash_with_decrypt_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_home = temp_dir.path().join("data");
+    let (keyfile, encrypted) = encrypted_file(temp_dir.path(), "small.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .args(["-d", "--trash", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&encrypted)
+        .assert()
+        .failure();
+    assert!(encrypted.exists());
+
+    jcz_command()
+        .env("XDG_DATA_HOME", &data_home)
+        .args([
+            "decrypt",
+            "--remove-encrypted",
+            "--trash",
+            "--decrypt-keyfile",
+        ])
+        .arg(&keyfile)
+        .arg(&encrypted)
+        .assert()
+        .success();
+    assert!(verify_decompressed_content(
+        &temp_dir.path().join("small.txt.gz"),
+        TEST_DATA_SMALL
+    ));
+    assert!(data_home.join("Trash/files/small.txt.gz.jcze").exists());
+}
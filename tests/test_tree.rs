@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_tree_shows_members_and_sizes() {
+    let temp_dir = TempDir::new().unwrap();
+    let site = temp_dir.path().join("site");
+    fs::create_dir_all(site.join("assets")).unwrap();
+    fs::write(site.join("assets/app.js"), vec![b'x'; 2048]).unwrap();
+    fs::write(site.join("index.html"), vec![b'y'; 100]).unwrap();
+
+    for (command, extension) in [("tgz", "tar.gz"), ("zip", "zip")] {
+        jcz_command()
+            .args(["-c", command])
+            .arg(&site)
+            .assert()
+            .success();
+
+        jcz_command()
+            .arg("tree")
+            .arg(temp_dir.path().join(format!("site.{}", extension)))
+            .assert()
+            .success()
+            .stdout(
+                "site/ (2.1 KiB)\n\
+                 ├── assets/ (2.0 KiB)\n\
+                 │   └── app.js (2.0 KiB)\n\
+                 └── index.html (100 B)\n\
+                 \n\
+                 2 directories, 2 files, 2.1 KiB total\n",
+            );
+    }
+}
+
+#[test]
+fn test_tree_rejects_non_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "plain.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("tree")
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("plain.txt"));
+}
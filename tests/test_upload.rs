@@ -0,0 +1,136 @@
+// The fake transfer tools are shell scripts
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Install `script` as the program `name` in `bin`
+fn fake_tool(bin: &Path, name: &str, script: &str) {
+    fs::create_dir_all(bin).unwrap();
+    let path = bin.join(name);
+    fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn test_move_to_s3_uploads_without_local_copy() {
+    let temp_dir = TempDir::new().unwrap();
+    let bin = temp_dir.path().join("bin");
+    let bucket = temp_dir.path().join("bucket");
+    fs::create_dir(&bucket).unwrap();
+    // aws s3 cp - s3://BUCKET/KEY ...: store stdin under the key
+    fake_tool(
+        &bin,
+        "aws",
+        &format!(
+            "key=\"${{4#s3://backups/}}\"; mkdir -p \"$(dirname '{0}'/\"$key\")\"; cat > '{0}'/\"$key\"",
+            bucket.display()
+        ),
+    );
+    let input = create_test_file(temp_dir.path(), "data.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .env("PATH", path_with(&bin))
+        .arg("-c")
+        .arg("gzip")
+        .arg("-C")
+        .arg("s3://backups/nightly/")
+        .arg("--report")
+        .arg(temp_dir.path().join("report.json"))
+        .arg(&input)
+        .assert()
+        .success();
+
+    let uploaded = bucket.join("nightly/data.txt.gz");
+    assert!(file_exists(&uploaded));
+    assert!(!file_exists(&temp_dir.path().join("data.txt.gz")));
+    let report = fs::read_to_string(temp_dir.path().join("report.json")).unwrap();
+    assert!(
+        report.contains("s3://backups/nightly/data.txt.gz"),
+        "Report: {}",
+        report
+    );
+
+    // What was uploaded is the complete archive
+    fs::rename(&uploaded, temp_dir.path().join("data.txt.gz")).unwrap();
+    fs::remove_file(&input).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("data.txt.gz"))
+        .assert()
+        .success();
+    assert_eq!(read_file(&input), TEST_DATA_MEDIUM);
+}
+
+#[test]
+fn test_upload_keeps_local_copy_and_reports_failures() {
+    let temp_dir = TempDir::new().unwrap();
+    let bin = temp_dir.path().join("bin");
+    let log = temp_dir.path().join("curl.log");
+    // Record the arguments, fail for anything named bad*
+    fake_tool(
+        &bin,
+        "curl",
+        &format!(
+            "echo \"$@\" >> '{}'; case \"$6\" in */bad*) echo 'HTTP 403' >&2; exit 22;; esac",
+            log.display()
+        ),
+    );
+    let good = create_test_file(temp_dir.path(), "good.txt", TEST_DATA_SMALL);
+    let bad = create_test_file(temp_dir.path(), "bad.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .env("PATH", path_with(&bin))
+        .arg("-c")
+        .arg("gzip")
+        .arg("--upload")
+        .arg("https://store.example/up/")
+        .arg(&good)
+        .arg(&bad)
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains(
+            "Upload to https://store.example/up/bad.txt.gz failed: HTTP 403",
+        ));
+
+    assert!(file_exists(&temp_dir.path().join("good.txt.gz")));
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(
+        calls.contains(&format!(
+            "--upload-file {} https://store.example/up/good.txt.gz",
+            temp_dir.path().join("good.txt.gz").display()
+        )),
+        "Calls: {}",
+        calls
+    );
+}
+
+#[test]
+fn test_upload_rejected_when_decompressing() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_file(temp_dir.path(), "data.gz", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-d")
+        .arg("--upload")
+        .arg("s3://backups/")
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("applies to compression only"));
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("-C")
+        .arg("s3://")
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid upload URL"));
+}
@@ -0,0 +1,354 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Write a fresh 2048-bit key pair, returning (private, public) PEM paths
+fn verify(archive: &Path) -> assert_cmd::assert::Assert {
+    jcz_command().arg("verify").arg(archive).assert()
+}
+
+#[test]
+fn test_verify_sidecar_tagged_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs", "README"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--sidecar-metadata")
+        .arg(&project)
+        .assert()
+        .success();
+
+    verify(&temp_dir.path().join("project.tar.gz"))
+        .success()
+        .stdout(predicates::str::contains("OK"));
+}
+
+#[test]
+fn test_verify_detects_changed_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["a.txt", "b.txt"]);
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--sidecar-metadata")
+        .arg(&project)
+        .assert()
+        .success();
+
+    // Rebuild the archive from different contents, then fix up the outer
+    // hash so only the tree tag can catch it
+    fs::write(project.join("b.txt"), b"tampered").unwrap();
+    let archive = temp_dir.path().join("project.tar.gz");
+    let sidecar = temp_dir.path().join("project.tar.gz.jcz.json");
+    let original: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&sidecar).unwrap()).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("tgz")
+        .arg("--sidecar-metadata")
+        .arg(&project)
+        .assert()
+        .success();
+    let mut rebuilt: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&sidecar).unwrap()).unwrap();
+    rebuilt["tree_sha256"] = original["tree_sha256"].clone();
+    fs::write(&sidecar, rebuilt.to_string()).unwrap();
+
+    verify(&archive)
+        .code(5)
+        .stderr(predicates::str::contains("Integrity check failed"));
+}
+
+#[test]
+fn test_verify_rsa_encrypted_collection() {
+    let temp_dir = TempDir::new().unwrap();
+    let (private_key, public_key) = write_key_pair(temp_dir.path());
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("one.txt", TEST_DATA_SMALL), ("two.txt", TEST_DATA_MEDIUM)],
+    );
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("--encrypt-key")
+        .arg(&public_key)
+        .arg("-a")
+        .arg("bundle")
+        .args(&files)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("bundle.tar.gz.jcze");
+    jcz_command()
+        .arg("verify")
+        .arg("--decrypt-key")
+        .arg(&private_key)
+        .arg(&archive)
+        .assert()
+        .success();
+
+    // Verification works in scratch space only
+    assert!(!temp_dir.path().join("bundle.tar.gz").exists());
+    assert!(!temp_dir.path().join("bundle").exists());
+}
+
+#[test]
+fn test_verify_rsa_encrypted_single_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let (private_key, public_key) = write_key_pair(temp_dir.path());
+    let file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg("-t")
+        .arg("1")
+        .arg("--encrypt-key")
+        .arg(&public_key)
+        .arg(&file)
+        .assert()
+        .success();
+
+    let archive = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.to_string_lossy().ends_with(".xz.jcze"))
+        .expect("encrypted output");
+
+    jcz_command()
+        .arg("verify")
+        .arg("--decrypt-key")
+        .arg(&private_key)
+        .arg(&archive)
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_verify_without_tag() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "plain.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg(&file)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("plain.txt.gz");
+
+    // Passes on its structure and CRC32 alone, unless a tag is required
+    verify(&archive)
+        .success()
+        .stdout(predicates::str::contains("OK      layers decode"));
+    jcz_command()
+        .args(["verify", "--require-tag"])
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("no integrity tag"));
+}
+
+#[test]
+fn test_verify_batch_table() {
+    let temp_dir = TempDir::new().unwrap();
+    let keyfile = temp_dir.path().join("backup.key");
+    jcz_command()
+        .arg("gen-keyfile")
+        .arg(&keyfile)
+        .assert()
+        .success();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[
+            ("one.txt", TEST_DATA_SMALL),
+            ("two.txt", TEST_DATA_MEDIUM),
+            ("three.txt", TEST_DATA_MEDIUM),
+        ],
+    );
+    jcz_command()
+        .args(["-c", "gzip", "--encrypt-keyfile"])
+        .arg(&keyfile)
+        .arg(&files[0])
+        .assert()
+        .success();
+    jcz_command()
+        .args(["-c", "gzip"])
+        .args(&files[1..])
+        .assert()
+        .success();
+
+    // Damage the CRC32 in the trailer of one archive
+    let damaged = temp_dir.path().join("three.txt.gz");
+    let mut data = fs::read(&damaged).unwrap();
+    let crc = data.len() - 8;
+    data[crc] ^= 0xff;
+    fs::write(&damaged, data).unwrap();
+
+    let archives = [
+        temp_dir.path().join("one.txt.gz.jcze"),
+        temp_dir.path().join("two.txt.gz"),
+        damaged,
+    ];
+    let output = jcz_command()
+        .args(["verify", "-j", "2", "--decrypt-keyfile"])
+        .arg(&keyfile)
+        .args(&archives)
+        .assert()
+        .code(2)
+        .get_output()
+        .stdout
+        .clone();
+    let table = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", table);
+    assert!(lines[0].starts_with("ARCHIVE"));
+    // In the order given, whatever order they finished in
+    assert!(lines[1].contains("one.txt.gz.jcze") && lines[1].contains("OK"));
+    assert!(lines[1].contains("authenticated"));
+    assert!(lines[2].contains("two.txt.gz") && lines[2].contains("OK"));
+    assert!(lines[3].contains("three.txt.gz") && lines[3].contains("FAILED"));
+    assert!(lines[3].contains("CRC32"));
+}
+
+#[test]
+fn test_verify_embedded_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("one.txt", TEST_DATA_SMALL), ("two.txt", TEST_DATA_MEDIUM)],
+    );
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("--manifest")
+        .arg("-a")
+        .arg("bundle")
+        .args(&files)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("bundle.tar.gz");
+    let listing = std::process::Command::new("tar")
+        .arg("-tzf")
+        .arg(&archive)
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&listing.stdout).contains("bundle/MANIFEST.sha256"));
+
+    verify(&archive)
+        .success()
+        .stdout(predicates::str::contains("2 files match MANIFEST.sha256"));
+}
+
+#[test]
+fn test_verify_flat_collection_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(temp_dir.path(), &[("only.txt", TEST_DATA_SMALL)]);
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("--manifest")
+        .arg("-A")
+        .arg("flat")
+        .args(&files)
+        .assert()
+        .success();
+
+    verify(&temp_dir.path().join("flat.tar.gz"))
+        .success()
+        .stdout(predicates::str::contains("1 files match MANIFEST.sha256"));
+}
+
+#[test]
+fn test_verify_manifest_detects_changed_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let files = create_test_files(
+        temp_dir.path(),
+        &[("one.txt", TEST_DATA_SMALL), ("two.txt", TEST_DATA_MEDIUM)],
+    );
+
+    jcz_command()
+        .current_dir(temp_dir.path())
+        .arg("-c")
+        .arg("tgz")
+        .arg("--manifest")
+        .arg("-a")
+        .arg("bundle")
+        .args(&files)
+        .assert()
+        .success();
+
+    // Repack with one file altered but the original manifest kept
+    let unpacked = temp_dir.path().join("unpacked");
+    fs::create_dir(&unpacked).unwrap();
+    let archive = temp_dir.path().join("bundle.tar.gz");
+    let tar = |args: &[&std::ffi::OsStr]| {
+        assert!(std::process::Command::new("tar")
+            .args(args)
+            .status()
+            .unwrap()
+            .success());
+    };
+    tar(&[
+        "-xzf".as_ref(),
+        archive.as_os_str(),
+        "-C".as_ref(),
+        unpacked.as_os_str(),
+    ]);
+    fs::write(unpacked.join("bundle/two.txt"), b"bit rot").unwrap();
+    tar(&[
+        "-czf".as_ref(),
+        archive.as_os_str(),
+        "-C".as_ref(),
+        unpacked.as_os_str(),
+        "bundle".as_ref(),
+    ]);
+
+    verify(&archive)
+        .failure()
+        .stderr(predicates::str::contains("Manifest check failed"))
+        .stderr(predicates::str::contains("two.txt: size"));
+}
+
+#[test]
+fn test_verify_with_wrong_key_exits_4() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, public_key) = write_key_pair(temp_dir.path());
+    let other = temp_dir.path().join("other");
+    fs::create_dir(&other).unwrap();
+    let (wrong_key, _) = write_key_pair(&other);
+    let file = create_test_file(temp_dir.path(), "notes.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("gzip")
+        .arg("--encrypt-key")
+        .arg(&public_key)
+        .arg(&file)
+        .assert()
+        .success();
+
+    jcz_command()
+        .arg("verify")
+        .arg("--decrypt-key")
+        .arg(&wrong_key)
+        .arg(temp_dir.path().join("notes.txt.gz.jcze"))
+        .assert()
+        .code(4);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Start `jcz watch` with `args` and wait until it is watching
+fn start_watch(args: &[&str], dir: &Path) -> Child {
+    #[allow(deprecated)]
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin("jcz"))
+        .env("JCDBG", "info")
+        .arg("watch")
+        .args(args)
+        .arg(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let stderr = child.stderr.take().unwrap();
+    let mut lines = BufReader::new(stderr).lines();
+    let watching = lines.any(|line| line.is_ok_and(|l| l.contains("Watching")));
+    assert!(watching, "jcz watch did not start");
+    // Keep draining so the child never blocks on a full pipe
+    std::thread::spawn(move || lines.for_each(drop));
+    child
+}
+
+/// Wait up to 10 seconds for `path` to exist
+fn wait_for(path: &Path) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if path.exists() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+/// Wait up to 10 seconds for `path` to disappear
+fn wait_for_removal(path: &Path) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if !path.exists() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn test_watch_compresses_new_matching_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let watched = temp_dir.path().join("logs");
+    let archive = temp_dir.path().join("archive");
+    fs::create_dir(&watched).unwrap();
+    fs::create_dir(&archive).unwrap();
+
+    let mut child = start_watch(
+        &[
+            "--on-create",
+            "-c",
+            "gzip",
+            "--pattern",
+            "*.log",
+            "--settle",
+            "0",
+            "-C",
+            archive.to_str().unwrap(),
+            "--remove-source",
+        ],
+        &watched,
+    );
+
+    // Written elsewhere and moved in, as log rotation does
+    let staged = create_test_file(temp_dir.path(), "app.log", TEST_DATA_MEDIUM);
+    fs::rename(&staged, watched.join("app.log")).unwrap();
+    create_test_file(&watched, "notes.txt", TEST_DATA_SMALL);
+
+    let output = archive.join("app.log.gz");
+    let compressed = wait_for(&output);
+    let removed = wait_for_removal(&watched.join("app.log"));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(compressed);
+    assert!(removed);
+    assert!(verify_decompressed_content(&output, TEST_DATA_MEDIUM));
+    // Not matching the pattern
+    assert!(file_exists(&watched.join("notes.txt")));
+    assert!(!file_exists(&archive.join("notes.txt.gz")));
+}
+
+#[test]
+fn test_watch_requires_trigger_and_directory() {
+    let temp_dir = TempDir::new().unwrap();
+
+    jcz_command()
+        .arg("watch")
+        .arg(temp_dir.path())
+        .assert()
+        .failure();
+
+    jcz_command()
+        .args(["watch", "--on-create"])
+        .arg(temp_dir.path().join("missing"))
+        .assert()
+        .failure();
+
+    jcz_command()
+        .args(["watch", "--on-create", "-c", "nosuch"])
+        .arg(temp_dir.path())
+        .assert()
+        .failure();
+}
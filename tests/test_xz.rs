@@ -1,6 +1,7 @@
 mod common;
 
 use common::*;
+use std::fs;
 use tempfile::TempDir;
 
 #[test]
@@ -247,3 +248,109 @@ fn test_xz_compress_preserves_original() {
         "Original file content should be unchanged"
     );
 }
+
+#[test]
+fn test_xz_memory_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("xz")
+        .arg(&test_file)
+        .assert()
+        .success();
+    fs::remove_file(&test_file).unwrap();
+    let compressed_file = temp_dir.path().join("test.txt.xz");
+
+    // Far below what any xz decoder needs
+    jcz_command()
+        .arg("-d")
+        .arg("--memory-limit")
+        .arg("1K")
+        .arg(&compressed_file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("memory limit"));
+    assert!(!file_exists(&test_file), "Nothing should be decompressed");
+
+    jcz_command()
+        .arg("-d")
+        .arg("--memory-limit")
+        .arg("256M")
+        .arg(&compressed_file)
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM);
+}
+
+#[test]
+fn test_xz_level_presets_and_extreme() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+    let compressed_file = temp_dir.path().join("test.txt.xz");
+
+    for level in ["0", "9e", "fast", "beste"] {
+        jcz_command()
+            .args(["-c", "xz", "-f", "-l", level])
+            .arg(&test_file)
+            .assert()
+            .success();
+        fs::remove_file(&test_file).unwrap();
+        jcz_command()
+            .arg("-d")
+            .arg(&compressed_file)
+            .assert()
+            .success();
+        assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM, "level {}", level);
+    }
+
+    // Outside xz's range, and extreme for a format without it
+    jcz_command()
+        .args(["-c", "xz", "-l", "10"])
+        .arg(&test_file)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid compression level 10 for xz",
+        ));
+    jcz_command()
+        .args(["-c", "gzip", "-l", "9e"])
+        .arg(&test_file)
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("extreme"));
+    jcz_command()
+        .args(["-c", "xz", "-l", "max"])
+        .arg(&test_file)
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("Invalid level"));
+}
+
+#[test]
+fn test_xz_extreme_and_lzma2_options() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "test.txt", TEST_DATA_MEDIUM);
+    let compressed_file = temp_dir.path().join("test.txt.xz");
+
+    jcz_command()
+        .args(["-c", "xz", "--extreme", "--lzma2", "dict=1M,lc=4,lp=0,pb=0"])
+        .arg(&test_file)
+        .assert()
+        .success();
+    fs::remove_file(&test_file).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg(&compressed_file)
+        .assert()
+        .success();
+    assert_eq!(read_file(&test_file), TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .args(["-c", "xz", "--lzma2", "lc=4,lp=1"])
+        .arg(&test_file)
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("lc + lp"));
+}
@@ -0,0 +1,348 @@
+mod common;
+
+use common::*;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use tempfile::TempDir;
+
+#[test]
+fn test_zip_directory_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    create_test_dir_structure(&project, &["src/main.rs"]);
+    create_test_file(&project, "README", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg(&project)
+        .assert()
+        .success();
+
+    fs::remove_dir_all(&project).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("project.zip"))
+        .assert()
+        .success();
+
+    assert!(file_exists(&project.join("src/main.rs")));
+    assert_eq!(read_file(&project.join("README")), TEST_DATA_SMALL);
+}
+
+#[test]
+fn test_zip_stores_already_compressed_members() {
+    let temp_dir = TempDir::new().unwrap();
+    let album = temp_dir.path().join("album");
+    fs::create_dir(&album).unwrap();
+    // Compressible bytes behind a JPEG signature: only detection keeps
+    // zip from deflating them
+    let photo = [&[0xFF, 0xD8, 0xFF, 0xE0][..], &[0u8; 10_000]].concat();
+    create_test_file(&album, "photo.JPG", &photo);
+    create_test_file(&album, "notes.txt", &TEST_DATA_SMALL.repeat(100));
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg(&album)
+        .assert()
+        .success();
+
+    let listing = std::process::Command::new("unzip")
+        .arg("-v")
+        .arg(temp_dir.path().join("album.zip"))
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&listing.stdout);
+    let method = |name: &str| {
+        listing
+            .lines()
+            .find(|line| line.ends_with(name))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert_eq!(method("photo.JPG"), "Stored", "Listing: {}", listing);
+    assert!(
+        method("notes.txt").starts_with("Defl"),
+        "Listing: {}",
+        listing
+    );
+}
+
+#[test]
+fn test_zip_more_than_65535_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let dump = temp_dir.path().join("dump");
+    fs::create_dir(&dump).unwrap();
+    for i in 0..70_000 {
+        File::create(dump.join(format!("{:05}", i))).unwrap();
+    }
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg(&dump)
+        .assert()
+        .success();
+
+    fs::remove_dir_all(&dump).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("dump.zip"))
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_dir(&dump).unwrap().count(), 70_000);
+    assert!(file_exists(&dump.join("69999")));
+}
+
+#[test]
+#[ignore = "Skipped: writes over 4 GB; run with --ignored"]
+fn test_zip_member_larger_than_4gb() {
+    const SIZE: u64 = (4 << 30) + (64 << 20);
+
+    let temp_dir = TempDir::new().unwrap();
+    let dump = temp_dir.path().join("dump.bin");
+
+    // Sparse apart from a marker past the 4 GB boundary
+    let mut file = File::create(&dump).unwrap();
+    file.set_len(SIZE).unwrap();
+    file.seek(SeekFrom::Start(SIZE - 4)).unwrap();
+    file.write_all(b"tail").unwrap();
+    drop(file);
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg("-l")
+        .arg("1")
+        .arg(&dump)
+        .assert()
+        .success();
+
+    fs::remove_file(&dump).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg(temp_dir.path().join("dump.bin.zip"))
+        .assert()
+        .success();
+
+    assert_eq!(file_size(&dump), SIZE);
+    let mut file = File::open(&dump).unwrap();
+    let mut marker = [0u8; 4];
+    file.seek(SeekFrom::Start(SIZE - 4)).unwrap();
+    file.read_exact(&mut marker).unwrap();
+    assert_eq!(&marker, b"tail");
+}
+
+/// Whether `data` holds a WinZip AES extra field (AE-1 or AE-2) for 256-bit keys
+fn has_aes256_extra_field(data: &[u8]) -> bool {
+    data.windows(9).any(|w| {
+        w[..4] == [0x01, 0x99, 0x07, 0x00]
+            && matches!(w[4..6], [0x01, 0x00] | [0x02, 0x00])
+            && w[6..] == [b'A', b'E', 0x03]
+    })
+}
+
+/// Create an AES zip of a single file, feeding the password on stdin
+fn create_aes_zip(dir: &std::path::Path, password: &str) -> std::path::PathBuf {
+    let test_file = create_test_file(dir, "secret.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg("--zip-encrypt")
+        .arg(&test_file)
+        .write_stdin(format!("{}\n", password))
+        .assert()
+        .success();
+
+    fs::remove_file(&test_file).unwrap();
+    dir.join("secret.txt.zip")
+}
+
+#[test]
+fn test_zip_encrypt_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive = create_aes_zip(temp_dir.path(), "correct horse");
+
+    let data = read_file(&archive);
+    assert!(
+        has_aes256_extra_field(&data),
+        "Entry should carry a WinZip AES-256 extra field"
+    );
+    assert!(!data
+        .windows(TEST_DATA_SMALL.len())
+        .any(|w| w == TEST_DATA_SMALL));
+
+    jcz_command()
+        .arg("-d")
+        .arg("--zip-encrypt")
+        .arg(&archive)
+        .write_stdin("correct horse\n")
+        .assert()
+        .success();
+
+    assert_eq!(
+        read_file(&temp_dir.path().join("secret.txt")),
+        TEST_DATA_SMALL
+    );
+}
+
+#[test]
+fn test_zip_encrypt_wrong_password_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive = create_aes_zip(temp_dir.path(), "correct horse");
+
+    jcz_command()
+        .arg("-d")
+        .arg("--zip-encrypt")
+        .arg(&archive)
+        .write_stdin("battery staple\n")
+        .assert()
+        .code(4);
+
+    assert!(!file_exists(&temp_dir.path().join("secret.txt")));
+}
+
+#[test]
+fn test_aes_zip_needs_zip_encrypt() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive = create_aes_zip(temp_dir.path(), "correct horse");
+
+    jcz_command()
+        .arg("-d")
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("pass --zip-encrypt"));
+}
+
+#[test]
+fn test_zip_comment() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("release");
+    fs::create_dir(&project).unwrap();
+    create_test_file(&project, "README", TEST_DATA_SMALL);
+    create_test_file(&project, "CHANGES", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .args(["-c", "zip", "--comment", "nightly build 2024-05-01"])
+        .arg(&project)
+        .assert()
+        .success();
+
+    let archive = temp_dir.path().join("release.zip");
+    assert!(read_file(&archive).ends_with(b"nightly build 2024-05-01"));
+    jcz_command()
+        .arg("identify")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Comment: nightly build 2024-05-01",
+        ));
+    jcz_command()
+        .arg("tree")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Comment: nightly build 2024-05-01",
+        ));
+
+    fs::remove_dir_all(&project).unwrap();
+    jcz_command().arg("-d").arg(&archive).assert().success();
+    assert_eq!(read_file(&project.join("CHANGES")), TEST_DATA_MEDIUM);
+}
+
+#[test]
+fn test_zip_flags_utf8_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let test_file = create_test_file(temp_dir.path(), "日本語.txt", TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg(&test_file)
+        .assert()
+        .success();
+
+    // General purpose bit 11 of the first (only) local header
+    let archive = temp_dir.path().join("日本語.txt.zip");
+    let data = read_file(&archive);
+    assert_ne!(u16::from_le_bytes([data[6], data[7]]) & 0x0800, 0);
+
+    fs::remove_file(&test_file).unwrap();
+    jcz_command().arg("-d").arg(&archive).assert().success();
+    assert_eq!(read_file(&test_file), TEST_DATA_SMALL);
+}
+
+#[test]
+#[cfg(unix)] // Windows cannot create a file with a non-Unicode name
+fn test_zip_encoding_decodes_legacy_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let legacy = temp_dir.path().join("legacy");
+    fs::create_dir(&legacy).unwrap();
+
+    // "資料.txt" in Shift_JIS, as a Windows zip tool would store it
+    let sjis_name = OsStr::from_bytes(b"\x8e\x91\x97\xbf.txt");
+    fs::write(legacy.join(sjis_name), TEST_DATA_SMALL).unwrap();
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg(&legacy)
+        .assert()
+        .success();
+
+    fs::remove_dir_all(&legacy).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("--zip-encoding")
+        .arg("cp932")
+        .arg(temp_dir.path().join("legacy.zip"))
+        .assert()
+        .success();
+
+    assert_eq!(read_file(&legacy.join("資料.txt")), TEST_DATA_SMALL);
+}
+
+#[test]
+fn test_zip_direct_extraction_confirms_existing() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = create_test_file(temp_dir.path(), "report.txt", TEST_DATA_MEDIUM);
+
+    jcz_command()
+        .arg("-c")
+        .arg("zip")
+        .arg(&file)
+        .assert()
+        .success();
+    let archive = temp_dir.path().join("report.txt.zip");
+
+    // Without -f, the existing file is kept
+    std::fs::write(&file, TEST_DATA_SMALL).unwrap();
+    jcz_command()
+        .arg("-d")
+        .arg("--direct")
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("already exists"));
+    assert_eq!(read_file(&file), TEST_DATA_SMALL);
+
+    jcz_command()
+        .arg("-d")
+        .arg("--direct")
+        .arg("-f")
+        .arg(&archive)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("directly to"));
+    assert_eq!(read_file(&file), TEST_DATA_MEDIUM);
+}